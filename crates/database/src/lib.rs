@@ -2,7 +2,16 @@
 extern crate eserde;
 
 mod models;
+pub mod schema_compat;
+#[cfg(test)]
+mod testing;
 mod utils;
 
 /// Re-export the models module
 pub use models::*;
+/// Re-export the pool lifecycle for eager startup and clean shutdown
+pub use utils::connection::{
+    DatabaseConnectionError, close_db_connection, configure_db, migrate_db, open_db_connection,
+};
+/// Re-export the model error types
+pub use utils::error::{DatabaseError, ModelResult};
@@ -0,0 +1,32 @@
+use sqlx::query_scalar;
+
+use crate::db;
+use crate::models::UserModel;
+use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
+
+pub struct ApiKeyModel;
+
+impl ApiKeyModel {
+    /// Obtains the user owning the API key `secret`,
+    /// recording that the key was just used.
+    pub async fn authenticate(secret: &str) -> ModelResult<UserModel> {
+        let user_id = query_scalar!(
+            r#"
+                UPDATE api_keys
+                SET
+                    last_used_at = NOW()
+                WHERE
+                    secret = $1
+                RETURNING user_id
+            "#,
+            secret
+        )
+        .fetch_optional(db!())
+        .timed("api_key.authenticate")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("api key"))?;
+
+        UserModel::get(user_id).await
+    }
+}
@@ -0,0 +1,333 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{query, query_as, query_scalar};
+
+use crate::db;
+use crate::utils::connection::with_timeout;
+use crate::utils::error::ModelResult;
+use crate::utils::instrument::Timed;
+
+/// Checks scan every file, so they may run past the pool statement timeout.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Reference counts are kept by the `adjust_blob_refs` database function,
+/// triggered by every change to `objects`, this only inspects them.
+pub struct BlobModel;
+
+/// A blob whose recorded reference count doesn't match
+/// the amount of files actually pointing at it.
+pub struct BlobDrift {
+    pub hash_algorithm: String,
+    pub hash: String,
+    pub recorded: i64,
+    pub actual: i64,
+}
+
+#[derive(Serialize)]
+pub struct BlobStats {
+    total_blobs: i64,
+    referenced_bytes: i64,
+    last_check_drift: Option<i64>,
+    last_checked_at: Option<NaiveDateTime>,
+}
+
+impl BlobModel {
+    /// Recomputes every reference count from the files, reporting the
+    /// blobs that drifted and fixing them when `repair` is set.
+    ///
+    /// Writers are blocked meanwhile, so no count changes under the check.
+    pub async fn check_refs(repair: bool) -> ModelResult<Vec<BlobDrift>> {
+        let mut transaction = with_timeout(CHECK_TIMEOUT).await?;
+
+        query!("LOCK TABLE blobs IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *transaction)
+            .timed("blob.check_refs")
+            .await?;
+
+        let drift = query_as!(
+            BlobDrift,
+            r#"
+                WITH refs AS (
+                    SELECT
+                        hash_algorithm,
+                        hash
+                    FROM objects
+                    WHERE
+                        hash IS NOT NULL
+                    UNION ALL
+                    SELECT
+                        hash_algorithm,
+                        original_hash
+                    FROM objects
+                    WHERE
+                        original_hash IS NOT NULL
+                ),
+                actual AS (
+                    SELECT
+                        hash_algorithm,
+                        hash,
+                        COUNT(*) AS ref_count
+                    FROM refs
+                    GROUP BY hash_algorithm, hash
+                )
+                SELECT
+                    COALESCE(blobs.hash_algorithm, actual.hash_algorithm) AS "hash_algorithm!",
+                    COALESCE(blobs.hash, actual.hash) AS "hash!",
+                    COALESCE(blobs.ref_count, 0) AS "recorded!",
+                    COALESCE(actual.ref_count, 0) AS "actual!"
+                FROM blobs
+                FULL OUTER JOIN actual
+                    ON actual.hash_algorithm = blobs.hash_algorithm
+                    AND actual.hash = blobs.hash
+                WHERE
+                    COALESCE(blobs.ref_count, 0) <> COALESCE(actual.ref_count, 0)
+                ORDER BY 1, 2
+            "#
+        )
+        .fetch_all(&mut *transaction)
+        .timed("blob.check_refs")
+        .await?;
+
+        if repair && !drift.is_empty() {
+            query!(
+                r#"
+                    UPDATE blobs
+                    SET
+                        ref_count = 0
+                    WHERE
+                        NOT EXISTS (
+                            SELECT 1
+                            FROM objects
+                            WHERE
+                                objects.hash_algorithm = blobs.hash_algorithm
+                            AND
+                                (objects.hash = blobs.hash OR objects.original_hash = blobs.hash)
+                        )
+                "#
+            )
+            .execute(&mut *transaction)
+            .timed("blob.check_refs")
+            .await?;
+
+            query!(
+                r#"
+                    INSERT INTO blobs (
+                        hash_algorithm,
+                        hash,
+                        size,
+                        ref_count
+                    )
+                    SELECT
+                        hash_algorithm,
+                        hash,
+                        MAX(size),
+                        COUNT(*)
+                    FROM (
+                        SELECT
+                            hash_algorithm,
+                            hash,
+                            size
+                        FROM objects
+                        WHERE
+                            hash IS NOT NULL
+                        UNION ALL
+                        SELECT
+                            hash_algorithm,
+                            original_hash,
+                            original_size
+                        FROM objects
+                        WHERE
+                            original_hash IS NOT NULL
+                    ) refs
+                    GROUP BY hash_algorithm, hash
+                    ON CONFLICT (hash_algorithm, hash) DO UPDATE
+                    SET
+                        ref_count = EXCLUDED.ref_count
+                "#
+            )
+            .execute(&mut *transaction)
+            .timed("blob.check_refs")
+            .await?;
+        }
+
+        query!(
+            r#"
+                INSERT INTO blob_ref_checks (
+                    drifted,
+                    repaired
+                )
+                VALUES (
+                    $1,
+                    $2
+                )
+            "#,
+            drift.len() as i64,
+            repair
+        )
+        .execute(&mut *transaction)
+        .timed("blob.check_refs")
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(drift)
+    }
+
+    /// Totals over referenced blobs and the outcome of the last check.
+    pub async fn stats() -> ModelResult<BlobStats> {
+        let stats = query_as!(
+            BlobStats,
+            r#"
+                SELECT
+                    (SELECT COUNT(*) FROM blobs) AS "total_blobs!",
+                    (
+                        SELECT COALESCE(SUM(size), 0)::BIGINT
+                        FROM blobs
+                        WHERE
+                            ref_count > 0
+                    ) AS "referenced_bytes!",
+                    last_check.drifted AS "last_check_drift?",
+                    last_check.checked_at AS "last_checked_at?"
+                FROM (SELECT 1) AS totals
+                LEFT JOIN LATERAL (
+                    SELECT
+                        drifted,
+                        checked_at
+                    FROM blob_ref_checks
+                    ORDER BY checked_at DESC
+                    LIMIT 1
+                ) AS last_check
+                    ON TRUE
+            "#
+        )
+        .fetch_one(db!())
+        .timed("blob.stats")
+        .await?;
+
+        Ok(stats)
+    }
+
+    /// Of the given hashes, those still used by a file or counted as
+    /// referenced, anything else can be removed from the storage.
+    pub async fn referenced(algorithm: &str, hashes: &[String]) -> ModelResult<Vec<String>> {
+        let referenced = query_scalar!(
+            r#"
+                SELECT candidates.hash AS "hash!"
+                FROM UNNEST($2::TEXT[]) AS candidates (hash)
+                WHERE
+                    EXISTS (
+                        SELECT 1
+                        FROM objects
+                        WHERE
+                            objects.hash_algorithm = $1
+                        AND
+                            (objects.hash = candidates.hash OR objects.original_hash = candidates.hash)
+                    )
+                OR
+                    EXISTS (
+                        SELECT 1
+                        FROM blobs
+                        WHERE
+                            blobs.hash_algorithm = $1
+                        AND
+                            blobs.hash = candidates.hash
+                        AND
+                            blobs.ref_count > 0
+                    )
+            "#,
+            algorithm,
+            hashes
+        )
+        .fetch_all(db!())
+        .timed("blob.referenced")
+        .await?;
+
+        Ok(referenced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query_scalar;
+
+    use super::*;
+    use crate::testing::{create_bucket, run, unique_hash};
+    use crate::utils::connection::get_db_connection;
+    use crate::{FileCreation, FileModel};
+
+    async fn recorded(hash: &str) -> Option<i64> {
+        query_scalar::<_, i64>("SELECT ref_count FROM blobs WHERE hash = $1")
+            .bind(hash)
+            .fetch_optional(&get_db_connection().await.unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn create_file(bucket_id: i64, path: &str, hash: &str) -> FileModel {
+        FileModel::create_new(FileCreation {
+            bucket_id,
+            path: path.to_owned(),
+            size: 4,
+            hash: hash.to_owned(),
+            hash_algorithm: "sha256".into(),
+            created_at: None,
+            expires_at: None,
+            text: None,
+            name_key: path.as_bytes().to_vec(),
+            content_type: None,
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn counts_follow_the_files_pointing_at_a_blob() {
+        run(async {
+            let bucket = create_bucket().await;
+            let (shared, other) = (unique_hash(), unique_hash());
+
+            let first = create_file(bucket.id(), "first.txt", &shared).await;
+            create_file(bucket.id(), "second.txt", &shared).await;
+            assert_eq!(recorded(&shared).await, Some(2));
+
+            let first = first.replace_content(4, &other, None).await.unwrap().unwrap();
+            assert_eq!(recorded(&shared).await, Some(1));
+            assert_eq!(recorded(&other).await, Some(1));
+
+            first.delete().await.unwrap();
+            assert_eq!(recorded(&other).await, Some(0));
+
+            let drift = BlobModel::check_refs(false).await.unwrap();
+            assert!(!drift.iter().any(|blob| blob.hash == shared || blob.hash == other));
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn drifted_counts_are_reported_and_repaired() {
+        run(async {
+            let bucket = create_bucket().await;
+            let hash = unique_hash();
+            create_file(bucket.id(), "drifted.txt", &hash).await;
+
+            sqlx::query("UPDATE blobs SET ref_count = 5 WHERE hash = $1")
+                .bind(&hash)
+                .execute(&get_db_connection().await.unwrap())
+                .await
+                .unwrap();
+
+            let drift = BlobModel::check_refs(false).await.unwrap();
+            let blob = drift.iter().find(|blob| blob.hash == hash).unwrap();
+            assert_eq!((blob.recorded, blob.actual), (5, 1));
+            assert_eq!(recorded(&hash).await, Some(5));
+
+            BlobModel::check_refs(true).await.unwrap();
+            assert_eq!(recorded(&hash).await, Some(1));
+            let drift = BlobModel::check_refs(false).await.unwrap();
+            assert!(!drift.iter().any(|blob| blob.hash == hash));
+        })
+    }
+}
@@ -1 +1,334 @@
-pub struct BucketModel {}
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{FromRow, query_as};
+
+use crate::db;
+use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
+
+#[derive(FromRow)]
+pub struct BucketModel {
+    id: i64,
+    name: String,
+    owner_id: Option<i64>,
+    created_at: NaiveDateTime,
+    version: i64,
+    default_license: Option<String>,
+    visibility: String,
+}
+
+/// Who may read the feed of a bucket, its files stay
+/// reachable through their own links whatever it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Private,
+    /// Readable with a feed token minted by the owner.
+    Unlisted,
+    Public,
+}
+
+impl Visibility {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Private => "private",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Public => "public",
+        }
+    }
+
+    fn parse(visibility: &str) -> Self {
+        match visibility {
+            "public" => Visibility::Public,
+            "unlisted" => Visibility::Unlisted,
+            _ => Visibility::Private,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct BucketUpdate {
+    name: Option<String>,
+    /// An empty string clears it.
+    default_license: Option<String>,
+    visibility: Option<Visibility>,
+}
+
+impl BucketUpdate {
+    pub fn default_license(&self) -> Option<&str> {
+        self.default_license.as_deref()
+    }
+
+    pub fn with_default_license(self, default_license: String) -> Self {
+        BucketUpdate {
+            default_license: Some(default_license),
+            ..self
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BucketResult {
+    id: i64,
+    name: String,
+    owner_id: Option<i64>,
+    version_etag: String,
+    created_at: NaiveDateTime,
+    default_license: Option<String>,
+    visibility: Visibility,
+}
+
+impl BucketModel {
+    /// Obtains the bucket named `name` owned by `owner_id`,
+    /// creating it when it doesn't exist yet.
+    ///
+    /// Fails when the name is already taken by another user.
+    pub async fn get_or_create(name: &str, owner_id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                INSERT INTO buckets (
+                    name,
+                    owner_id
+                )
+                VALUES (
+                    $1,
+                    $2
+                )
+                ON CONFLICT (name) DO UPDATE
+                SET
+                    name = EXCLUDED.name
+                WHERE
+                    buckets.owner_id = $2
+                RETURNING *
+            "#,
+            name,
+            owner_id
+        )
+        .fetch_optional(db!())
+        .timed("bucket.get_or_create")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("bucket"))
+    }
+
+    /// Obtains the ownerless bucket anonymous uploads go to.
+    pub async fn anonymous() -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM buckets
+                WHERE
+                    name = 'anonymous'
+                AND
+                    owner_id IS NULL
+            "#
+        )
+        .fetch_optional(db!())
+        .timed("bucket.anonymous")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("bucket"))
+    }
+
+    pub async fn list_owned(owner_id: i64) -> ModelResult<Vec<Self>> {
+        let buckets = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM buckets
+                WHERE
+                    owner_id = $1
+                ORDER BY name
+            "#,
+            owner_id
+        )
+        .fetch_all(db!())
+        .timed("bucket.list_owned")
+        .await?;
+
+        Ok(buckets)
+    }
+
+    /// Buckets named under `name/` with the same owner, at any depth,
+    /// the way imported directories are named after their path.
+    pub async fn list_nested(name: &str, owner_id: Option<i64>) -> ModelResult<Vec<Self>> {
+        let buckets = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM buckets
+                WHERE
+                    starts_with(name, $1 || '/')
+                AND
+                    owner_id IS NOT DISTINCT FROM $2
+                ORDER BY name
+            "#,
+            name,
+            owner_id
+        )
+        .fetch_all(db!())
+        .timed("bucket.list_nested")
+        .await?;
+
+        Ok(buckets)
+    }
+
+    pub async fn list_by_ids(ids: &[i64]) -> ModelResult<Vec<Self>> {
+        let buckets = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM buckets
+                WHERE
+                    id = ANY($1)
+            "#,
+            ids
+        )
+        .fetch_all(db!())
+        .timed("bucket.list_by_ids")
+        .await?;
+
+        Ok(buckets)
+    }
+
+    /// Obtains a bucket by its name whoever owns it,
+    /// callers decide what of it may be shown.
+    pub async fn get_by_name(name: &str) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM buckets
+                WHERE
+                    name = $1
+            "#,
+            name
+        )
+        .fetch_optional(db!())
+        .timed("bucket.get_by_name")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("bucket"))
+    }
+
+    /// Obtains a bucket by name only if it belongs to `owner_id`.
+    pub async fn get_named_owned(name: &str, owner_id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM buckets
+                WHERE
+                    name = $1
+                AND
+                    owner_id = $2
+            "#,
+            name,
+            owner_id
+        )
+        .fetch_optional(db!())
+        .timed("bucket.get_named_owned")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("bucket"))
+    }
+
+    /// Obtains a bucket only if it belongs to `owner_id`.
+    pub async fn get_owned(id: i64, owner_id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM buckets
+                WHERE
+                    id = $1
+                AND
+                    owner_id = $2
+            "#,
+            id,
+            owner_id
+        )
+        .fetch_optional(db!())
+        .timed("bucket.get_owned")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("bucket"))
+    }
+
+    /// Applies `update` only while the row is still at `expected_version`,
+    /// when `None` is passed the last write wins.
+    ///
+    /// Returns `None` when the row moved to another version meanwhile.
+    pub async fn edit(
+        &self,
+        update: BucketUpdate,
+        expected_version: Option<i64>,
+    ) -> ModelResult<Option<Self>> {
+        let bucket = query_as!(
+            Self,
+            r#"
+                UPDATE buckets
+                SET
+                    name = COALESCE($1, name),
+                    default_license = NULLIF(COALESCE($4, default_license), ''),
+                    visibility = COALESCE($5, visibility),
+                    version = version + 1
+                WHERE
+                    id = $2
+                AND
+                    ($3::BIGINT IS NULL OR version = $3)
+                RETURNING *
+            "#,
+            update.name,
+            self.id,
+            expected_version,
+            update.default_license,
+            update.visibility.map(Visibility::as_str)
+        )
+        .fetch_optional(db!())
+        .timed("bucket.edit")
+        .await?;
+
+        Ok(bucket)
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+
+    /// `None` for the bucket holding anonymous uploads.
+    pub fn owner_id(&self) -> Option<i64> {
+        self.owner_id
+    }
+
+    /// The strong ETag clients send back in `If-Match`.
+    pub fn version_etag(&self) -> String {
+        format!("\"v{}\"", self.version)
+    }
+
+    /// The license of files created here without one of their own.
+    pub fn default_license(&self) -> Option<&str> {
+        self.default_license.as_deref()
+    }
+
+    pub fn visibility(&self) -> Visibility {
+        Visibility::parse(&self.visibility)
+    }
+
+    pub fn into_result(&self) -> BucketResult {
+        BucketResult {
+            id: self.id,
+            name: self.name.clone(),
+            owner_id: self.owner_id,
+            version_etag: self.version_etag(),
+            created_at: self.created_at,
+            default_license: self.default_license.clone(),
+            visibility: self.visibility(),
+        }
+    }
+}
@@ -0,0 +1,1905 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{FromRow, Postgres, Transaction, query, query_as, query_scalar};
+
+use crate::models::FilePermission;
+use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
+use crate::{db, db_read};
+
+#[derive(Clone, FromRow)]
+pub struct FileModel {
+    id: i64,
+    bucket_id: i64,
+    path: String,
+    size: i64,
+    created_at: NaiveDateTime,
+    last_modified_at: NaiveDateTime,
+    slug: String,
+    hash: Option<String>,
+    hash_algorithm: String,
+    version: i64,
+    expires_at: Option<NaiveDateTime>,
+    width: Option<i32>,
+    height: Option<i32>,
+    dominant_color: Option<String>,
+    text_encoding: Option<String>,
+    original_hash: Option<String>,
+    original_size: Option<i64>,
+    appendable: bool,
+    append_crc32: Option<i64>,
+    poster_state: Option<String>,
+    poster_at: Option<f64>,
+    link_rotated_at: Option<NaiveDateTime>,
+    indexable: Option<bool>,
+    name_key: Option<Vec<u8>>,
+    license: Option<String>,
+    attribution: Option<String>,
+    source_url: Option<String>,
+    content_type: Option<String>,
+    size_verified_at: Option<NaiveDateTime>,
+    mismatched_size: Option<i64>,
+}
+
+/// The row of a file locked while it's appended to, dropping
+/// it without recording the append rolls the lock back.
+pub struct AppendLock {
+    transaction: Transaction<'static, Postgres>,
+    file: FileModel,
+}
+
+/// A file along with what serving it needs from its bucket and owner.
+#[derive(Clone, FromRow)]
+pub struct ServedFile {
+    #[sqlx(flatten)]
+    pub file: FileModel,
+    /// Unset for anonymous uploads.
+    pub owner_id: Option<i64>,
+    /// Whether search engines may index the file, its own
+    /// setting wins over the preference of its owner.
+    #[sqlx(rename = "served_indexable")]
+    pub indexable: bool,
+}
+
+/// Totals over every file a user owns.
+#[derive(Clone, Copy, Serialize)]
+pub struct FileUsage {
+    pub files: i64,
+    pub bytes: i64,
+}
+
+/// A file listed in the sitemap.
+pub struct SitemapEntry {
+    pub slug: String,
+    pub last_modified_at: NaiveDateTime,
+}
+
+/// What is known about a file that decoded as an image.
+pub struct ImageMetadata {
+    pub width: i32,
+    pub height: i32,
+    /// A `#rrggbb` hex color.
+    pub dominant_color: String,
+}
+
+/// The reuse terms of a file, every part is optional.
+#[derive(Clone, Default, serde::Deserialize)]
+pub struct FileLicense {
+    /// An SPDX identifier, validated by the server.
+    pub license: Option<String>,
+    pub attribution: Option<String>,
+    pub source_url: Option<String>,
+}
+
+impl FileLicense {
+    pub fn is_empty(&self) -> bool {
+        self.license.is_none() && self.attribution.is_none() && self.source_url.is_none()
+    }
+}
+
+#[derive(Clone)]
+pub struct FileCreation {
+    pub bucket_id: i64,
+    pub path: String,
+    pub size: i64,
+    pub hash: String,
+    pub hash_algorithm: String,
+    /// Overrides the creation date, e.g. to keep the mtime of imported files.
+    pub created_at: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub text: Option<TextMetadata>,
+    /// The natural sort key of `path`.
+    pub name_key: Vec<u8>,
+    /// The type sniffed from the content.
+    pub content_type: Option<String>,
+}
+
+/// What is known about a file detected as text.
+#[derive(Clone)]
+pub struct TextMetadata {
+    /// The WHATWG name of the detected encoding, or `unknown`.
+    pub encoding: String,
+    /// Hash and size of the upload as sent, when the
+    /// stored copy was transcoded to UTF-8.
+    pub original: Option<(String, i64)>,
+}
+
+/// The keys file listings can be sorted by, deserializing
+/// only these keeps column names out of client input.
+#[derive(Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSort {
+    #[default]
+    Created,
+    /// The path of the file, compared case insensitively.
+    Name,
+    Size,
+}
+
+impl FileSort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileSort::Created => "created",
+            FileSort::Name => "name",
+            FileSort::Size => "size",
+        }
+    }
+}
+
+/// How names compare when sorting by name.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Collation {
+    /// Case and accents ignored, numbers compared by value.
+    #[default]
+    Natural,
+    /// The UTF-8 bytes of the path.
+    Binary,
+}
+
+impl Collation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Collation::Natural => "natural",
+            Collation::Binary => "binary",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// Where a page of a listing ended, the sort key of its last file
+/// along with its id, which breaks ties between equal keys.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileCursor {
+    Created(NaiveDateTime, i64),
+    Name(Vec<u8>, i64),
+    Size(i64, i64),
+}
+
+impl FileCursor {
+    /// Resumes a listing sorted by `sort` right after `file`.
+    pub async fn after(
+        file: &FileModel,
+        sort: FileSort,
+        collation: Collation,
+    ) -> ModelResult<Self> {
+        let key = match sort {
+            FileSort::Created => return Ok(FileCursor::Created(file.created_at, file.id)),
+            FileSort::Size => return Ok(FileCursor::Size(file.size, file.id)),
+            FileSort::Name => match (collation, &file.name_key) {
+                (Collation::Binary, _) => file.path.as_bytes().to_vec(),
+                (Collation::Natural, Some(name_key)) => name_key.clone(),
+                // Lowercased by the database, which may not fold case like Rust does.
+                (Collation::Natural, None) => {
+                    query_scalar!(
+                        r#"
+                        SELECT convert_to(LOWER(path), 'UTF8') AS "name_key!"
+                        FROM objects
+                        WHERE
+                            id = $1
+                    "#,
+                        file.id
+                    )
+                    .fetch_one(db!())
+                    .timed("file.cursor_after")
+                    .await?
+                },
+            },
+        };
+
+        Ok(FileCursor::Name(key, file.id))
+    }
+
+    pub fn sort(&self) -> FileSort {
+        match self {
+            FileCursor::Created(..) => FileSort::Created,
+            FileCursor::Name(..) => FileSort::Name,
+            FileCursor::Size(..) => FileSort::Size,
+        }
+    }
+
+    /// The key and id bound by the listing queries, only the key of the
+    /// cursor's sort is set.
+    fn bounds(
+        cursor: Option<&Self>,
+    ) -> (Option<NaiveDateTime>, Option<Vec<u8>>, Option<i64>, Option<i64>) {
+        match cursor {
+            None => (None, None, None, None),
+            Some(FileCursor::Created(created_at, id)) => (Some(*created_at), None, None, Some(*id)),
+            Some(FileCursor::Name(name_key, id)) => (None, Some(name_key.clone()), None, Some(*id)),
+            Some(FileCursor::Size(size, id)) => (None, None, Some(*size), Some(*id)),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct FileUpdate {
+    path: Option<String>,
+    /// Overrides the search engine preference of the owner.
+    indexable: Option<bool>,
+    /// Set by the server along with a new `path`.
+    #[serde(skip)]
+    name_key: Option<Vec<u8>>,
+    /// Empty strings clear the parts they're given for.
+    #[serde(flatten)]
+    license: FileLicense,
+}
+
+impl FileUpdate {
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn license(&self) -> &FileLicense {
+        &self.license
+    }
+
+    pub fn with_license(self, license: FileLicense) -> Self {
+        FileUpdate { license, ..self }
+    }
+
+    pub fn with_name_key(self, name_key: Vec<u8>) -> Self {
+        FileUpdate { name_key: Some(name_key), ..self }
+    }
+}
+
+/// A file id as shown to clients, numeric unless it's obfuscated.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum PublicFileId {
+    Numeric(i64),
+    Obfuscated(String),
+}
+
+#[derive(Serialize)]
+pub struct FileResult {
+    id: PublicFileId,
+    bucket_id: i64,
+    path: String,
+    size: i64,
+    slug: String,
+    hash: Option<String>,
+    hash_algorithm: String,
+    version_etag: String,
+    created_at: NaiveDateTime,
+    last_modified_at: NaiveDateTime,
+    expires_at: Option<NaiveDateTime>,
+    width: Option<i32>,
+    height: Option<i32>,
+    text_encoding: Option<String>,
+    /// Whether the stored copy was transcoded from `text_encoding` to UTF-8.
+    normalized: bool,
+    appendable: bool,
+    /// CRC-32 of the content appended so far, as 8 hex digits.
+    crc32: Option<String>,
+    /// Whether a video poster is `ready` or `failed` to extract.
+    poster_state: Option<String>,
+    license: Option<String>,
+    attribution: Option<String>,
+    source_url: Option<String>,
+    /// Where the content is served, relative unless a base was set.
+    url: String,
+    placeholder_url: String,
+    /// Where this version of the content is served for good,
+    /// only set when content addressed URLs are enabled.
+    immutable_url: Option<String>,
+}
+
+impl FileResult {
+    /// Shows `id` in place of the numeric id.
+    pub fn with_obfuscated_id(mut self, id: String) -> Self {
+        self.id = PublicFileId::Obfuscated(id);
+        self
+    }
+
+    /// Makes the content URL absolute under `base_url`.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.url = format!("{}{}", base_url.trim_end_matches('/'), self.url);
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Links the content at `url` for as long as it doesn't change.
+    pub fn with_immutable_url(mut self, url: String) -> Self {
+        self.immutable_url = Some(url);
+        self
+    }
+}
+
+impl FileModel {
+    pub async fn create_new(creation: FileCreation) -> ModelResult<Self> {
+        let (text_encoding, original) = match creation.text {
+            Some(text) => (Some(text.encoding), text.original),
+            None => (None, None),
+        };
+
+        let file = query_as!(
+            Self,
+            r#"
+                INSERT INTO objects (
+                    bucket_id,
+                    path,
+                    size,
+                    hash,
+                    hash_algorithm,
+                    created_at,
+                    last_modified_at,
+                    expires_at,
+                    text_encoding,
+                    original_hash,
+                    original_size,
+                    name_key,
+                    license,
+                    content_type
+                )
+                VALUES (
+                    $1,
+                    $2,
+                    $3,
+                    $4,
+                    $5,
+                    COALESCE($6::TIMESTAMP, NOW()),
+                    COALESCE($6::TIMESTAMP, NOW()),
+                    $7,
+                    $8,
+                    $9,
+                    $10,
+                    $11,
+                    (SELECT default_license FROM buckets WHERE id = $1),
+                    $12
+                )
+                RETURNING *
+            "#,
+            creation.bucket_id,
+            creation.path,
+            creation.size,
+            creation.hash,
+            creation.hash_algorithm,
+            creation.created_at,
+            creation.expires_at,
+            text_encoding,
+            original.as_ref().map(|(hash, _)| hash.clone()),
+            original.map(|(_, size)| size),
+            creation.name_key,
+            creation.content_type
+        )
+        .fetch_one(db!())
+        .timed("file.create_new")
+        .await?;
+
+        Ok(file)
+    }
+
+    /// Creates an empty file that grows with every append, its content
+    /// isn't addressed by a hash so `hash` stays unset.
+    pub async fn create_appendable(
+        bucket_id: i64,
+        path: &str,
+        name_key: &[u8],
+        hash_algorithm: &str,
+        expires_at: Option<NaiveDateTime>,
+    ) -> ModelResult<Self> {
+        let file = query_as!(
+            Self,
+            r#"
+                INSERT INTO objects (
+                    bucket_id,
+                    path,
+                    size,
+                    hash_algorithm,
+                    expires_at,
+                    appendable,
+                    append_crc32,
+                    name_key,
+                    license
+                )
+                VALUES (
+                    $1,
+                    $2,
+                    0,
+                    $3,
+                    $4,
+                    TRUE,
+                    0,
+                    $5,
+                    (SELECT default_license FROM buckets WHERE id = $1)
+                )
+                RETURNING *
+            "#,
+            bucket_id,
+            path,
+            hash_algorithm,
+            expires_at,
+            name_key
+        )
+        .fetch_one(db!())
+        .timed("file.create_appendable")
+        .await?;
+
+        Ok(file)
+    }
+
+    pub async fn get(id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    id = $1
+            "#,
+            id
+        )
+        .fetch_optional(db_read!())
+        .timed("file.get")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("file"))
+    }
+
+    /// Obtains a file by its public slug, this is what
+    /// shared links resolve to, so a rotated slug stops matching here.
+    pub async fn get_by_slug(slug: &str) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    slug = $1
+                AND
+                    (expires_at IS NULL OR expires_at > NOW())
+            "#,
+            slug
+        )
+        .fetch_optional(db_read!())
+        .timed("file.get_by_slug")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("file"))
+    }
+
+    /// Like `get_by_slug` along with what serving the file needs, read at
+    /// once so the file can be served without querying anything else.
+    pub async fn get_served_by_slug(slug: &str) -> ModelResult<ServedFile> {
+        // Not checked at compile time, the macros can't flatten the file.
+        query_as::<_, ServedFile>(
+            r#"
+                SELECT
+                    objects.*,
+                    buckets.owner_id,
+                    COALESCE(objects.indexable, users.indexable, TRUE) AS served_indexable
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                LEFT JOIN users
+                    ON users.id = buckets.owner_id
+                WHERE
+                    objects.slug = $1
+                AND
+                    (objects.expires_at IS NULL OR objects.expires_at > NOW())
+            "#,
+        )
+        .bind(slug)
+        .fetch_optional(db_read!())
+        .timed("file.get_served_by_slug")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("file"))
+    }
+
+    /// Whether `slug` was retired by a rotation that kept it revoked.
+    pub async fn slug_revoked(slug: &str) -> ModelResult<bool> {
+        let revoked = query_scalar!(
+            r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM revoked_slugs
+                    WHERE
+                        slug = $1
+                ) AS "revoked!"
+            "#,
+            slug
+        )
+        .fetch_one(db_read!())
+        .timed("file.slug_revoked")
+        .await?;
+
+        Ok(revoked)
+    }
+
+    /// Reads what serving the file needs from its bucket and owner.
+    pub async fn into_served(self) -> ModelResult<ServedFile> {
+        let served = query!(
+            r#"
+                SELECT
+                    buckets.owner_id,
+                    COALESCE(objects.indexable, users.indexable, TRUE) AS "indexable!"
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                LEFT JOIN users
+                    ON users.id = buckets.owner_id
+                WHERE
+                    objects.id = $1
+            "#,
+            self.id
+        )
+        .fetch_one(db!())
+        .timed("file.into_served")
+        .await?;
+
+        Ok(ServedFile {
+            file: self,
+            owner_id: served.owner_id,
+            indexable: served.indexable,
+        })
+    }
+
+    /// Counts and sums the files of `owner_id`, expired files
+    /// included since they take space until they're purged.
+    pub async fn usage_for_user(owner_id: i64) -> ModelResult<FileUsage> {
+        let usage = query_as!(
+            FileUsage,
+            r#"
+                SELECT
+                    COUNT(*) AS "files!",
+                    COALESCE(SUM(objects.size), 0)::BIGINT AS "bytes!"
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    buckets.owner_id = $1
+            "#,
+            owner_id
+        )
+        .fetch_one(db!())
+        .timed("file.usage_for_user")
+        .await?;
+
+        Ok(usage)
+    }
+
+    /// Counts the files listed in the sitemap, only those of public
+    /// buckets, slugs of the others are only known to who they're shared with.
+    pub async fn count_indexable() -> ModelResult<i64> {
+        let count = query_scalar!(
+            r#"
+                SELECT COUNT(*) AS "count!"
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                LEFT JOIN users
+                    ON users.id = buckets.owner_id
+                WHERE
+                    buckets.visibility = 'public'
+                AND
+                    objects.indexable IS NOT FALSE
+                AND
+                    COALESCE(objects.indexable, users.indexable, TRUE)
+                AND
+                    (objects.expires_at IS NULL OR objects.expires_at > NOW())
+            "#
+        )
+        .fetch_one(db!())
+        .timed("file.count_indexable")
+        .await?;
+
+        Ok(count)
+    }
+
+    /// A page of the files listed in the sitemap, in id order.
+    pub async fn list_indexable(offset: i64, limit: i64) -> ModelResult<Vec<SitemapEntry>> {
+        let entries = query_as!(
+            SitemapEntry,
+            r#"
+                SELECT
+                    objects.slug,
+                    objects.last_modified_at
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                LEFT JOIN users
+                    ON users.id = buckets.owner_id
+                WHERE
+                    buckets.visibility = 'public'
+                AND
+                    objects.indexable IS NOT FALSE
+                AND
+                    COALESCE(objects.indexable, users.indexable, TRUE)
+                AND
+                    (objects.expires_at IS NULL OR objects.expires_at > NOW())
+                ORDER BY objects.id
+                OFFSET $1
+                LIMIT $2
+            "#,
+            offset,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("file.list_indexable")
+        .await?;
+
+        Ok(entries)
+    }
+
+    /// Newest files across every owner, only those of `content_type`
+    /// when given, either exact or a `type/*` wildcard. Files stored
+    /// before their type was recorded and appendable ones don't match.
+    pub async fn list_recent(
+        content_type: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> ModelResult<Vec<Self>> {
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    $1::TEXT IS NULL
+                OR
+                    content_type = LOWER($1)
+                OR
+                    (
+                        RIGHT($1, 2) = '/*'
+                    AND
+                        STARTS_WITH(content_type, LOWER(LEFT($1, -1)))
+                    )
+                ORDER BY created_at DESC, id DESC
+                OFFSET $2
+                LIMIT $3
+            "#,
+            content_type,
+            offset,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("file.list_recent")
+        .await?;
+
+        Ok(files)
+    }
+
+    pub async fn list_in_bucket(bucket_id: i64) -> ModelResult<Vec<Self>> {
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    bucket_id = $1
+                ORDER BY path
+            "#,
+            bucket_id
+        )
+        .fetch_all(db!())
+        .timed("file.list_in_bucket")
+        .await?;
+
+        Ok(files)
+    }
+
+    /// The `limit` files last added to a bucket, newest first,
+    /// expired files are left out.
+    pub async fn list_newest_in_bucket(bucket_id: i64, limit: i64) -> ModelResult<Vec<Self>> {
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    bucket_id = $1
+                AND
+                    (expires_at IS NULL OR expires_at > NOW())
+                ORDER BY created_at DESC, id DESC
+                LIMIT $2
+            "#,
+            bucket_id,
+            limit
+        )
+        .fetch_all(db_read!())
+        .timed("file.list_newest_in_bucket")
+        .await?;
+
+        Ok(files)
+    }
+
+    /// Up to `limit` files in buckets owned by `owner_id` past `after`,
+    /// all of them without a limit, ties broken by id.
+    ///
+    /// Names compare by their key under the `natural` collation, rows
+    /// still without one fall back to their lowercased path.
+    pub async fn list_owned(
+        owner_id: i64,
+        sort: FileSort,
+        order: SortOrder,
+        collation: Collation,
+        license: Option<&str>,
+        after: Option<&FileCursor>,
+        limit: Option<i64>,
+    ) -> ModelResult<Vec<Self>> {
+        let (created_at, name_key, size, after_id) = FileCursor::bounds(after);
+
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT objects.*
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                CROSS JOIN LATERAL (
+                    SELECT CASE
+                        WHEN $4 = 'binary' THEN convert_to(objects.path, 'UTF8')
+                        ELSE COALESCE(objects.name_key, convert_to(LOWER(objects.path), 'UTF8'))
+                    END AS name_key
+                ) sort
+                WHERE
+                    buckets.owner_id = $1
+                AND
+                    ($5::TEXT IS NULL OR objects.license = $5)
+                AND
+                    (
+                        $9::BIGINT IS NULL
+                    OR
+                        (
+                            $2 = 'created'
+                        AND
+                            $3 = 'asc'
+                        AND
+                            (objects.created_at, objects.id) > ($6::TIMESTAMP, $9)
+                        )
+                    OR
+                        (
+                            $2 = 'created'
+                        AND
+                            $3 = 'desc'
+                        AND
+                            (objects.created_at < $6 OR (objects.created_at = $6 AND objects.id > $9))
+                        )
+                    OR
+                        (
+                            $2 = 'name'
+                        AND
+                            $3 = 'asc'
+                        AND
+                            (sort.name_key, objects.id) > ($7::BYTEA, $9)
+                        )
+                    OR
+                        (
+                            $2 = 'name'
+                        AND
+                            $3 = 'desc'
+                        AND
+                            (sort.name_key < $7 OR (sort.name_key = $7 AND objects.id > $9))
+                        )
+                    OR
+                        (
+                            $2 = 'size'
+                        AND
+                            $3 = 'asc'
+                        AND
+                            (objects.size, objects.id) > ($8::BIGINT, $9)
+                        )
+                    OR
+                        (
+                            $2 = 'size'
+                        AND
+                            $3 = 'desc'
+                        AND
+                            (objects.size < $8 OR (objects.size = $8 AND objects.id > $9))
+                        )
+                    )
+                ORDER BY
+                    CASE WHEN $2 = 'created' AND $3 = 'asc' THEN objects.created_at END ASC,
+                    CASE WHEN $2 = 'created' AND $3 = 'desc' THEN objects.created_at END DESC,
+                    CASE WHEN $2 = 'name' AND $3 = 'asc' THEN sort.name_key END ASC,
+                    CASE WHEN $2 = 'name' AND $3 = 'desc' THEN sort.name_key END DESC,
+                    CASE WHEN $2 = 'size' AND $3 = 'asc' THEN objects.size END ASC,
+                    CASE WHEN $2 = 'size' AND $3 = 'desc' THEN objects.size END DESC,
+                    objects.id
+                LIMIT $10
+            "#,
+            owner_id,
+            sort.as_str(),
+            order.as_str(),
+            collation.as_str(),
+            license,
+            created_at,
+            name_key,
+            size,
+            after_id,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("file.list_owned")
+        .await?;
+
+        Ok(files)
+    }
+
+    /// The `limit` largest files across every owner.
+    pub async fn largest(limit: i64) -> ModelResult<Vec<Self>> {
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                ORDER BY size DESC, id
+                LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("file.largest")
+        .await?;
+
+        Ok(files)
+    }
+
+    /// The `limit` largest files in buckets owned by `owner_id`.
+    pub async fn largest_owned(owner_id: i64, limit: i64) -> ModelResult<Vec<Self>> {
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT objects.*
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    buckets.owner_id = $1
+                ORDER BY objects.size DESC, objects.id
+                LIMIT $2
+            "#,
+            owner_id,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("file.largest_owned")
+        .await?;
+
+        Ok(files)
+    }
+
+    /// Files `owner_id` tagged with `tag`, paged like `list_owned`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn list_by_tag(
+        owner_id: i64,
+        tag: &str,
+        sort: FileSort,
+        order: SortOrder,
+        collation: Collation,
+        license: Option<&str>,
+        after: Option<&FileCursor>,
+        limit: Option<i64>,
+    ) -> ModelResult<Vec<Self>> {
+        let (created_at, name_key, size, after_id) = FileCursor::bounds(after);
+
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT objects.*
+                FROM objects
+                INNER JOIN file_tags
+                    ON file_tags.file_id = objects.id
+                CROSS JOIN LATERAL (
+                    SELECT CASE
+                        WHEN $5 = 'binary' THEN convert_to(objects.path, 'UTF8')
+                        ELSE COALESCE(objects.name_key, convert_to(LOWER(objects.path), 'UTF8'))
+                    END AS name_key
+                ) sort
+                WHERE
+                    file_tags.owner_id = $1
+                AND
+                    file_tags.tag = $2
+                AND
+                    ($6::TEXT IS NULL OR objects.license = $6)
+                AND
+                    (
+                        $10::BIGINT IS NULL
+                    OR
+                        (
+                            $3 = 'created'
+                        AND
+                            $4 = 'asc'
+                        AND
+                            (objects.created_at, objects.id) > ($7::TIMESTAMP, $10)
+                        )
+                    OR
+                        (
+                            $3 = 'created'
+                        AND
+                            $4 = 'desc'
+                        AND
+                            (objects.created_at < $7 OR (objects.created_at = $7 AND objects.id > $10))
+                        )
+                    OR
+                        (
+                            $3 = 'name'
+                        AND
+                            $4 = 'asc'
+                        AND
+                            (sort.name_key, objects.id) > ($8::BYTEA, $10)
+                        )
+                    OR
+                        (
+                            $3 = 'name'
+                        AND
+                            $4 = 'desc'
+                        AND
+                            (sort.name_key < $8 OR (sort.name_key = $8 AND objects.id > $10))
+                        )
+                    OR
+                        (
+                            $3 = 'size'
+                        AND
+                            $4 = 'asc'
+                        AND
+                            (objects.size, objects.id) > ($9::BIGINT, $10)
+                        )
+                    OR
+                        (
+                            $3 = 'size'
+                        AND
+                            $4 = 'desc'
+                        AND
+                            (objects.size < $9 OR (objects.size = $9 AND objects.id > $10))
+                        )
+                    )
+                ORDER BY
+                    CASE WHEN $3 = 'created' AND $4 = 'asc' THEN objects.created_at END ASC,
+                    CASE WHEN $3 = 'created' AND $4 = 'desc' THEN objects.created_at END DESC,
+                    CASE WHEN $3 = 'name' AND $4 = 'asc' THEN sort.name_key END ASC,
+                    CASE WHEN $3 = 'name' AND $4 = 'desc' THEN sort.name_key END DESC,
+                    CASE WHEN $3 = 'size' AND $4 = 'asc' THEN objects.size END ASC,
+                    CASE WHEN $3 = 'size' AND $4 = 'desc' THEN objects.size END DESC,
+                    objects.id
+                LIMIT $11
+            "#,
+            owner_id,
+            tag,
+            sort.as_str(),
+            order.as_str(),
+            collation.as_str(),
+            license,
+            created_at,
+            name_key,
+            size,
+            after_id,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("file.list_by_tag")
+        .await?;
+
+        Ok(files)
+    }
+
+    pub async fn get_at(bucket_id: i64, path: &str) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    bucket_id = $1
+                AND
+                    path = $2
+            "#,
+            bucket_id,
+            path
+        )
+        .fetch_optional(db_read!())
+        .timed("file.get_at")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("file"))
+    }
+
+    pub async fn exists_at(bucket_id: i64, path: &str) -> ModelResult<bool> {
+        let exists = query_scalar!(
+            r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM objects
+                    WHERE
+                        bucket_id = $1
+                    AND
+                        path = $2
+                ) AS "exists!"
+            "#,
+            bucket_id,
+            path
+        )
+        .fetch_one(db_read!())
+        .timed("file.exists_at")
+        .await?;
+
+        Ok(exists)
+    }
+
+    /// Obtains a file only if the bucket holding it
+    /// belongs to `owner_id`.
+    pub async fn get_owned(id: i64, owner_id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT objects.*
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    objects.id = $1
+                AND
+                    buckets.owner_id = $2
+            "#,
+            id,
+            owner_id
+        )
+        .fetch_optional(db!())
+        .timed("file.get_owned")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("file"))
+    }
+
+    /// Obtains a file owned by `user_id` or shared with them
+    /// with `permission`, `delete` grants also allow reading.
+    pub async fn get_accessible(
+        id: i64,
+        user_id: i64,
+        permission: FilePermission,
+    ) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT objects.*
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    objects.id = $1
+                AND
+                    (
+                        buckets.owner_id = $2
+                    OR
+                        EXISTS (
+                            SELECT 1
+                            FROM file_acls
+                            WHERE
+                                file_acls.file_id = objects.id
+                            AND
+                                file_acls.grantee_id = $2
+                            AND
+                                ($3 = 'read' OR file_acls.permission = $3)
+                        )
+                    )
+            "#,
+            id,
+            user_id,
+            permission.as_str()
+        )
+        .fetch_optional(db_read!())
+        .timed("file.get_accessible")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("file"))
+    }
+
+    /// Obtains a file with the same content, hashes are only
+    /// compared when they were computed with the same algorithm.
+    pub async fn find_by_hash(hash: &str, algorithm: &str) -> ModelResult<Option<Self>> {
+        let file = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    hash = $1
+                AND
+                    hash_algorithm = $2
+                LIMIT 1
+            "#,
+            hash,
+            algorithm
+        )
+        .fetch_optional(db_read!())
+        .timed("file.find_by_hash")
+        .await?;
+
+        Ok(file)
+    }
+
+    /// Obtains a file of `owner_id` by the hash of its content,
+    /// whichever algorithm computed it.
+    pub async fn get_by_content_hash(hash: &str, owner_id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT objects.*
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    objects.hash = $1
+                AND
+                    (objects.expires_at IS NULL OR objects.expires_at > NOW())
+                AND
+                    buckets.owner_id = $2
+                ORDER BY objects.id
+                LIMIT 1
+            "#,
+            hash,
+            owner_id
+        )
+        .fetch_optional(db_read!())
+        .timed("file.get_by_content_hash")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("file"))
+    }
+
+    /// Every live file with the content `hash`, of `owner_id` when given,
+    /// for the caller to tell apart by what only it knows of them.
+    pub async fn list_by_content_hash(hash: &str, owner_id: Option<i64>) -> ModelResult<Vec<Self>> {
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT objects.*
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    objects.hash = $1
+                AND
+                    (objects.expires_at IS NULL OR objects.expires_at > NOW())
+                AND
+                    ($2::BIGINT IS NULL OR buckets.owner_id = $2)
+                ORDER BY objects.id
+            "#,
+            hash,
+            owner_id
+        )
+        .fetch_all(db_read!())
+        .timed("file.list_by_content_hash")
+        .await?;
+
+        Ok(files)
+    }
+
+    /// Up to `limit` files in buckets owned by `owner_id` with an id past
+    /// `after_id`, by id, so every file can be read a page at a time.
+    pub async fn list_owned_after(
+        owner_id: i64,
+        after_id: i64,
+        limit: i64,
+    ) -> ModelResult<Vec<Self>> {
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT objects.*
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    buckets.owner_id = $1
+                AND
+                    objects.id > $2
+                ORDER BY objects.id
+                LIMIT $3
+            "#,
+            owner_id,
+            after_id,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("file.list_owned_after")
+        .await?;
+
+        Ok(files)
+    }
+
+    /// Applies `update` only while the row is still at `expected_version`
+    /// and wasn't modified after `unmodified_since`, when both are `None`
+    /// the last write wins.
+    ///
+    /// Returns `None` when the row changed meanwhile.
+    pub async fn edit(
+        &self,
+        update: FileUpdate,
+        expected_version: Option<i64>,
+        unmodified_since: Option<NaiveDateTime>,
+    ) -> ModelResult<Option<Self>> {
+        // HTTP dates have a one second resolution, so the stored
+        // timestamp is truncated to compare like with like.
+        let file = query_as!(
+            Self,
+            r#"
+                UPDATE objects
+                SET
+                    path = COALESCE($1, path),
+                    name_key = COALESCE($6, name_key),
+                    indexable = COALESCE($5, indexable),
+                    license = NULLIF(COALESCE($7, license), ''),
+                    attribution = NULLIF(COALESCE($8, attribution), ''),
+                    source_url = NULLIF(COALESCE($9, source_url), ''),
+                    last_modified_at = NOW(),
+                    version = version + 1
+                WHERE
+                    id = $2
+                AND
+                    ($3::BIGINT IS NULL OR version = $3)
+                AND
+                    ($4::TIMESTAMP IS NULL OR date_trunc('second', last_modified_at) <= $4)
+                RETURNING *
+            "#,
+            update.path,
+            self.id,
+            expected_version,
+            unmodified_since,
+            update.indexable,
+            update.name_key,
+            update.license.license,
+            update.license.attribution,
+            update.license.source_url
+        )
+        .fetch_optional(db!())
+        .timed("file.edit")
+        .await?;
+
+        Ok(file)
+    }
+
+    /// Records what was learned by decoding the file as an image.
+    pub async fn set_image(&self, image: ImageMetadata) -> ModelResult<Self> {
+        let file = query_as!(
+            Self,
+            r#"
+                UPDATE objects
+                SET
+                    width = $1,
+                    height = $2,
+                    dominant_color = $3
+                WHERE
+                    id = $4
+                RETURNING *
+            "#,
+            image.width,
+            image.height,
+            image.dominant_color,
+            self.id
+        )
+        .fetch_one(db!())
+        .timed("file.set_image")
+        .await?;
+
+        Ok(file)
+    }
+
+    /// Replaces the parts of the license given in `license`, those left
+    /// out keep what the file inherited from its bucket and empty ones clear it.
+    pub async fn set_license(&self, license: FileLicense) -> ModelResult<Self> {
+        let file = query_as!(
+            Self,
+            r#"
+                UPDATE objects
+                SET
+                    license = NULLIF(COALESCE($1, license), ''),
+                    attribution = NULLIF(COALESCE($2, attribution), ''),
+                    source_url = NULLIF(COALESCE($3, source_url), '')
+                WHERE
+                    id = $4
+                RETURNING *
+            "#,
+            license.license,
+            license.attribution,
+            license.source_url,
+            self.id
+        )
+        .fetch_one(db!())
+        .timed("file.set_license")
+        .await?;
+
+        Ok(file)
+    }
+
+    /// Records the outcome of a poster extraction,
+    /// `None` clears it so the generic icon is shown.
+    pub async fn set_poster(&self, state: Option<&str>, at: Option<f64>) -> ModelResult<Self> {
+        let file = query_as!(
+            Self,
+            r#"
+                UPDATE objects
+                SET
+                    poster_state = $1,
+                    poster_at = $2
+                WHERE
+                    id = $3
+                RETURNING *
+            "#,
+            state,
+            at,
+            self.id
+        )
+        .fetch_one(db!())
+        .timed("file.set_poster")
+        .await?;
+
+        Ok(file)
+    }
+
+    /// Up to `limit` files past `after_id` by id whose name key is
+    /// missing, or all of them when `all` is set, as id and path.
+    pub async fn list_name_keys(
+        after_id: i64,
+        all: bool,
+        limit: i64,
+    ) -> ModelResult<Vec<(i64, String)>> {
+        let files = query!(
+            r#"
+                SELECT id, path
+                FROM objects
+                WHERE
+                    id > $1
+                AND
+                    ($2 OR name_key IS NULL)
+                ORDER BY id
+                LIMIT $3
+            "#,
+            after_id,
+            all,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("file.list_name_keys")
+        .await?;
+
+        Ok(files.into_iter().map(|file| (file.id, file.path)).collect())
+    }
+
+    pub async fn set_name_key(id: i64, name_key: &[u8]) -> ModelResult<()> {
+        query!(
+            r#"
+                UPDATE objects
+                SET name_key = $1
+                WHERE
+                    id = $2
+            "#,
+            name_key,
+            id
+        )
+        .execute(db!())
+        .timed("file.set_name_key")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replaces the file slug with a freshly generated one, the id is left
+    /// untouched so anything keyed by it keeps resolving.
+    ///
+    /// The old slug is kept as revoked when `keep_revoked` is set and is
+    /// otherwise simply unknown, signed URLs issued before are revoked.
+    pub async fn rotate_slug(&self, keep_revoked: bool) -> ModelResult<Self> {
+        let mut transaction = db!().begin().await?;
+
+        if keep_revoked {
+            query!(
+                r#"
+                    INSERT INTO revoked_slugs (
+                        slug,
+                        object_id
+                    )
+                    VALUES (
+                        $1,
+                        $2
+                    )
+                "#,
+                self.slug,
+                self.id
+            )
+            .execute(&mut *transaction)
+            .timed("file.rotate_slug")
+            .await?;
+        }
+
+        let file = query_as!(
+            Self,
+            r#"
+                UPDATE objects
+                SET
+                    slug = translate(encode(gen_random_bytes(12), 'base64'), '+/', '-_'),
+                    link_rotated_at = NOW(),
+                    version = version + 1
+                WHERE
+                    id = $1
+                RETURNING *
+            "#,
+            self.id
+        )
+        .fetch_one(&mut *transaction)
+        .timed("file.rotate_slug")
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(file)
+    }
+
+    /// Removes the file, its blob is left to the reference counts.
+    pub async fn delete(&self) -> ModelResult<()> {
+        query!(
+            r#"
+                DELETE FROM objects
+                WHERE
+                    id = $1
+            "#,
+            self.id
+        )
+        .execute(db!())
+        .timed("file.delete")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Locks the row of the file until the returned lock is recorded
+    /// or dropped, appends from every node wait on it in turn.
+    pub async fn lock_for_append(id: i64) -> ModelResult<AppendLock> {
+        let mut transaction = db!().begin().await?;
+
+        let file = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    id = $1
+                FOR UPDATE
+            "#,
+            id
+        )
+        .fetch_optional(&mut *transaction)
+        .timed("file.lock_for_append")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("file"))?;
+
+        Ok(AppendLock { transaction, file })
+    }
+
+    /// Points the file at new content of `content_type`, only while no other
+    /// change was recorded meanwhile, what was derived from the old content
+    /// is cleared.
+    ///
+    /// Returns `None` when the file changed meanwhile.
+    pub async fn replace_content(
+        &self,
+        size: i64,
+        hash: &str,
+        content_type: Option<&str>,
+    ) -> ModelResult<Option<Self>> {
+        let file = query_as!(
+            Self,
+            r#"
+                UPDATE objects
+                SET
+                    size = $1,
+                    hash = $2,
+                    width = NULL,
+                    height = NULL,
+                    dominant_color = NULL,
+                    text_encoding = NULL,
+                    original_hash = NULL,
+                    original_size = NULL,
+                    poster_state = NULL,
+                    poster_at = NULL,
+                    content_type = $5,
+                    size_verified_at = NOW(),
+                    mismatched_size = NULL,
+                    last_modified_at = NOW(),
+                    version = version + 1
+                WHERE
+                    id = $3
+                AND
+                    version = $4
+                RETURNING *
+            "#,
+            size,
+            hash,
+            self.id,
+            self.version,
+            content_type
+        )
+        .fetch_optional(db!())
+        .timed("file.replace_content")
+        .await?;
+
+        Ok(file)
+    }
+
+    /// Records that the content was found with the recorded size,
+    /// unless the file changed since it was read.
+    pub async fn verify_size(&self) -> ModelResult<()> {
+        query!(
+            r#"
+                UPDATE objects
+                SET
+                    size_verified_at = NOW(),
+                    mismatched_size = NULL
+                WHERE
+                    id = $1
+                AND
+                    version = $2
+            "#,
+            self.id,
+            self.version
+        )
+        .execute(db!())
+        .timed("file.verify_size")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Flags the file for the consistency checks, its content was found with
+    /// `stored_size` bytes instead of the recorded size. Its size is checked
+    /// against storage again from then on.
+    ///
+    /// Returns whether the file was flagged, it isn't when it changed since
+    /// it was read.
+    pub async fn flag_size_mismatch(&self, stored_size: i64) -> ModelResult<bool> {
+        let flagged = query!(
+            r#"
+                UPDATE objects
+                SET
+                    size_verified_at = NULL,
+                    mismatched_size = $3
+                WHERE
+                    id = $1
+                AND
+                    version = $2
+            "#,
+            self.id,
+            self.version,
+            stored_size
+        )
+        .execute(db!())
+        .timed("file.flag_size_mismatch")
+        .await?
+        .rows_affected();
+
+        Ok(flagged > 0)
+    }
+
+    /// Files whose content was found with another size than recorded, the
+    /// most recently changed first.
+    pub async fn list_size_mismatches(offset: i64, limit: i64) -> ModelResult<Vec<Self>> {
+        let files = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM objects
+                WHERE
+                    mismatched_size IS NOT NULL
+                ORDER BY last_modified_at DESC, id DESC
+                OFFSET $1
+                LIMIT $2
+            "#,
+            offset,
+            limit
+        )
+        .fetch_all(db_read!())
+        .timed("file.list_size_mismatches")
+        .await?;
+
+        Ok(files)
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn bucket_id(&self) -> i64 {
+        self.bucket_id
+    }
+
+    pub fn slug(&self) -> &str {
+        &self.slug
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+
+    pub fn last_modified_at(&self) -> NaiveDateTime {
+        self.last_modified_at
+    }
+
+    pub fn expires_at(&self) -> Option<NaiveDateTime> {
+        self.expires_at
+    }
+
+    pub fn size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    pub fn hash_algorithm(&self) -> &str {
+        &self.hash_algorithm
+    }
+
+    /// The intrinsic size, only known for images.
+    pub fn dimensions(&self) -> Option<(i32, i32)> {
+        self.width.zip(self.height)
+    }
+
+    pub fn dominant_color(&self) -> Option<&str> {
+        self.dominant_color.as_deref()
+    }
+
+    /// The encoding detected at upload, for text files only.
+    pub fn text_encoding(&self) -> Option<&str> {
+        self.text_encoding.as_deref()
+    }
+
+    /// Whether the stored content was transcoded to UTF-8.
+    pub fn normalized(&self) -> bool {
+        self.original_hash.is_some()
+    }
+
+    /// The hash of the upload as sent, when it was transcoded.
+    pub fn original_hash(&self) -> Option<&str> {
+        self.original_hash.as_deref()
+    }
+
+    /// The size of the upload as sent, when it was transcoded.
+    pub fn original_size(&self) -> Option<i64> {
+        self.original_size
+    }
+
+    /// The natural sort key of the path, unset on rows not backfilled yet.
+    pub fn name_key(&self) -> Option<&[u8]> {
+        self.name_key.as_deref()
+    }
+
+    pub fn appendable(&self) -> bool {
+        self.appendable
+    }
+
+    /// CRC-32 of the content so far, only kept for appendable files.
+    pub fn append_crc32(&self) -> Option<u32> {
+        self.append_crc32.map(|crc32| crc32 as u32)
+    }
+
+    /// Whether search engines may index the file, unset to follow its owner.
+    pub fn indexable(&self) -> Option<bool> {
+        self.indexable
+    }
+
+    /// When the slug was last rotated, signed URLs issued before are revoked.
+    pub fn link_rotated_at(&self) -> Option<NaiveDateTime> {
+        self.link_rotated_at
+    }
+
+    pub fn poster_state(&self) -> Option<&str> {
+        self.poster_state.as_deref()
+    }
+
+    /// The SPDX identifier of the license, if any.
+    pub fn license(&self) -> Option<&str> {
+        self.license.as_deref()
+    }
+
+    pub fn attribution(&self) -> Option<&str> {
+        self.attribution.as_deref()
+    }
+
+    pub fn source_url(&self) -> Option<&str> {
+        self.source_url.as_deref()
+    }
+
+    /// The type sniffed from the content when it was stored.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Whether the recorded size is known to match the content, files stored
+    /// before sizes were checked and flagged ones are checked against storage.
+    pub fn size_verified(&self) -> bool {
+        self.size_verified_at.is_some()
+    }
+
+    /// The size the content was found with, when it didn't match the recorded one.
+    pub fn mismatched_size(&self) -> Option<i64> {
+        self.mismatched_size
+    }
+
+    pub fn license_terms(&self) -> FileLicense {
+        FileLicense {
+            license: self.license.clone(),
+            attribution: self.attribution.clone(),
+            source_url: self.source_url.clone(),
+        }
+    }
+
+    /// Seconds into the video the poster was taken at.
+    pub fn poster_at(&self) -> Option<f64> {
+        self.poster_at
+    }
+
+    /// Keyed on the version so it can be cached forever.
+    pub fn placeholder_url(&self) -> String {
+        format!("/f/{}/placeholder.svg?v={}", self.slug, self.version)
+    }
+
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// The strong ETag clients send back in `If-Match`.
+    pub fn version_etag(&self) -> String {
+        format!("\"v{}\"", self.version)
+    }
+
+    pub fn into_result(&self) -> FileResult {
+        FileResult {
+            id: PublicFileId::Numeric(self.id),
+            bucket_id: self.bucket_id,
+            path: self.path.clone(),
+            size: self.size,
+            slug: self.slug.clone(),
+            hash: self.hash.clone(),
+            hash_algorithm: self.hash_algorithm.clone(),
+            version_etag: self.version_etag(),
+            created_at: self.created_at,
+            last_modified_at: self.last_modified_at,
+            expires_at: self.expires_at,
+            width: self.width,
+            height: self.height,
+            text_encoding: self.text_encoding.clone(),
+            normalized: self.normalized(),
+            appendable: self.appendable,
+            crc32: self.append_crc32.map(|crc32| format!("{crc32:08x}")),
+            poster_state: self.poster_state.clone(),
+            license: self.license.clone(),
+            attribution: self.attribution.clone(),
+            source_url: self.source_url.clone(),
+            url: format!("/f/{}", self.slug),
+            placeholder_url: self.placeholder_url(),
+            immutable_url: None,
+        }
+    }
+}
+
+impl AppendLock {
+    /// The file as it is under the lock, every earlier append recorded.
+    pub fn file(&self) -> &FileModel {
+        &self.file
+    }
+
+    /// Records an append that grew the file to `size` and releases the lock.
+    pub async fn record(mut self, size: i64, crc32: u32) -> ModelResult<FileModel> {
+        let file = query_as!(
+            FileModel,
+            r#"
+                UPDATE objects
+                SET
+                    size = $1,
+                    append_crc32 = $2,
+                    size_verified_at = NOW(),
+                    mismatched_size = NULL,
+                    last_modified_at = NOW(),
+                    version = version + 1
+                WHERE
+                    id = $3
+                RETURNING *
+            "#,
+            size,
+            crc32 as i64,
+            self.file.id
+        )
+        .fetch_one(&mut *self.transaction)
+        .timed("file.record_append")
+        .await?;
+
+        self.transaction.commit().await?;
+
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{create_bucket, run, unique_hash};
+
+    async fn create_file(bucket_id: i64, path: &str, content_type: Option<&str>) -> FileModel {
+        FileModel::create_new(FileCreation {
+            bucket_id,
+            path: path.to_owned(),
+            size: 0,
+            hash: unique_hash(),
+            hash_algorithm: "sha256".into(),
+            created_at: None,
+            expires_at: None,
+            text: None,
+            name_key: path.as_bytes().to_vec(),
+            content_type: content_type.map(str::to_owned),
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn recent_files_are_filtered_on_their_stored_type() {
+        run(async {
+            // A type of its own keeps the files of other tests out.
+            let kind = format!("x{}", &unique_hash()[..16]);
+            let (png, text) = (format!("{kind}/png"), format!("{kind}/plain"));
+
+            let first = create_bucket().await;
+            let second = create_bucket().await;
+            // The extension says nothing about what was stored.
+            let renamed = create_file(first.id(), "photo.txt", Some(&png)).await;
+            let other = create_file(second.id(), "photo.png", Some(&png)).await;
+            let notes = create_file(first.id(), "notes.png", Some(&text)).await;
+            create_file(first.id(), "legacy.png", None).await;
+
+            let ids = |files: Vec<FileModel>| files.iter().map(FileModel::id).collect::<Vec<_>>();
+
+            let exact = FileModel::list_recent(Some(&png), 0, 10).await.unwrap();
+            assert_eq!(ids(exact), [other.id(), renamed.id()]);
+
+            let wildcard = FileModel::list_recent(Some(&format!("{kind}/*")), 0, 10).await.unwrap();
+            assert_eq!(ids(wildcard), [notes.id(), other.id(), renamed.id()]);
+
+            let page = FileModel::list_recent(Some(&format!("{kind}/*")), 1, 1).await.unwrap();
+            assert_eq!(ids(page), [other.id()]);
+        })
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{query, query_as};
+
+use crate::db;
+use crate::utils::error::ModelResult;
+use crate::utils::instrument::Timed;
+
+/// Grants on files to users other than their owner.
+pub struct FileAclModel;
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilePermission {
+    Read,
+    /// Implies `Read`.
+    Delete,
+}
+
+impl FilePermission {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FilePermission::Read => "read",
+            FilePermission::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct FileAclEntry {
+    pub grantee_id: i64,
+    pub username: String,
+    pub permission: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl FileAclModel {
+    /// Grants `permission` on a file to `grantee_id`,
+    /// replacing whatever the user was granted before.
+    pub async fn grant(
+        file_id: i64,
+        grantee_id: i64,
+        permission: FilePermission,
+    ) -> ModelResult<()> {
+        query!(
+            r#"
+                INSERT INTO file_acls (
+                    file_id,
+                    grantee_id,
+                    permission
+                )
+                VALUES (
+                    $1,
+                    $2,
+                    $3
+                )
+                ON CONFLICT (file_id, grantee_id) DO UPDATE
+                SET
+                    permission = EXCLUDED.permission
+            "#,
+            file_id,
+            grantee_id,
+            permission.as_str()
+        )
+        .execute(db!())
+        .timed("file_acl.grant")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether the user was granted anything.
+    pub async fn revoke(file_id: i64, grantee_id: i64) -> ModelResult<bool> {
+        let revoked = query!(
+            r#"
+                DELETE FROM file_acls
+                WHERE
+                    file_id = $1
+                AND
+                    grantee_id = $2
+            "#,
+            file_id,
+            grantee_id
+        )
+        .execute(db!())
+        .timed("file_acl.revoke")
+        .await?
+        .rows_affected();
+
+        Ok(revoked > 0)
+    }
+
+    pub async fn list_for_file(file_id: i64) -> ModelResult<Vec<FileAclEntry>> {
+        let entries = query_as!(
+            FileAclEntry,
+            r#"
+                SELECT
+                    file_acls.grantee_id,
+                    users.username,
+                    file_acls.permission,
+                    file_acls.created_at
+                FROM file_acls
+                INNER JOIN users
+                    ON users.id = file_acls.grantee_id
+                WHERE
+                    file_acls.file_id = $1
+                ORDER BY
+                    users.username
+            "#,
+            file_id
+        )
+        .fetch_all(db!())
+        .timed("file_acl.list_for_file")
+        .await?;
+
+        Ok(entries)
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{query, query_as};
+
+use crate::db;
+use crate::utils::error::ModelResult;
+use crate::utils::instrument::Timed;
+
+/// The post processing stages of files and where they stand.
+pub struct FileProcessingModel;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessingState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    /// The stage didn't apply to the file.
+    Skipped,
+}
+
+impl ProcessingState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProcessingState::Pending => "pending",
+            ProcessingState::Running => "running",
+            ProcessingState::Done => "done",
+            ProcessingState::Failed => "failed",
+            ProcessingState::Skipped => "skipped",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ProcessingStage {
+    pub stage: String,
+    pub state: String,
+    /// Why the stage failed, unset otherwise.
+    pub error: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Stage counts by state, compact enough for every file description.
+#[derive(Serialize)]
+pub struct ProcessingSummary {
+    /// No stage is pending or running anymore.
+    pub all_done: bool,
+    pub pending: i64,
+    pub running: i64,
+    pub done: i64,
+    pub failed: i64,
+    pub skipped: i64,
+}
+
+impl FileProcessingModel {
+    /// Moves a stage of a file to `state`, the error is cleared unless given.
+    pub async fn record(
+        file_id: i64,
+        stage: &str,
+        state: ProcessingState,
+        error: Option<&str>,
+    ) -> ModelResult<()> {
+        query!(
+            r#"
+                INSERT INTO file_processing (
+                    file_id,
+                    stage,
+                    state,
+                    error
+                )
+                VALUES (
+                    $1,
+                    $2,
+                    $3,
+                    $4
+                )
+                ON CONFLICT (file_id, stage) DO UPDATE
+                SET
+                    state = EXCLUDED.state,
+                    error = EXCLUDED.error,
+                    updated_at = NOW()
+            "#,
+            file_id,
+            stage,
+            state.as_str(),
+            error
+        )
+        .execute(db!())
+        .timed("file_processing.record")
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_for_file(file_id: i64) -> ModelResult<Vec<ProcessingStage>> {
+        let stages = query_as!(
+            ProcessingStage,
+            r#"
+                SELECT
+                    stage,
+                    state,
+                    error,
+                    updated_at
+                FROM file_processing
+                WHERE
+                    file_id = $1
+                ORDER BY stage
+            "#,
+            file_id
+        )
+        .fetch_all(db!())
+        .timed("file_processing.list_for_file")
+        .await?;
+
+        Ok(stages)
+    }
+
+    pub async fn summary(file_id: i64) -> ModelResult<ProcessingSummary> {
+        let summary = query_as!(
+            ProcessingSummary,
+            r#"
+                SELECT
+                    COUNT(*) FILTER (WHERE state IN ('pending', 'running')) = 0 AS "all_done!",
+                    COUNT(*) FILTER (WHERE state = 'pending') AS "pending!",
+                    COUNT(*) FILTER (WHERE state = 'running') AS "running!",
+                    COUNT(*) FILTER (WHERE state = 'done') AS "done!",
+                    COUNT(*) FILTER (WHERE state = 'failed') AS "failed!",
+                    COUNT(*) FILTER (WHERE state = 'skipped') AS "skipped!"
+                FROM file_processing
+                WHERE
+                    file_id = $1
+            "#,
+            file_id
+        )
+        .fetch_one(db!())
+        .timed("file_processing.summary")
+        .await?;
+
+        Ok(summary)
+    }
+}
@@ -0,0 +1,301 @@
+use serde::Serialize;
+use sqlx::{query, query_as, query_scalar};
+
+use crate::db;
+use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
+
+/// Tags attached to files, always scoped to the owner of the file.
+pub struct FileTagModel;
+
+#[derive(Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub files: i64,
+}
+
+impl FileTagModel {
+    /// Tags a file owned by `owner_id` with every tag in `tags`,
+    /// tags the file already has are left alone.
+    pub async fn add(file_id: i64, owner_id: i64, tags: &[String]) -> ModelResult<()> {
+        query!(
+            r#"
+                INSERT INTO file_tags (
+                    file_id,
+                    owner_id,
+                    tag
+                )
+                SELECT
+                    $1,
+                    $2,
+                    UNNEST($3::TEXT[])
+                ON CONFLICT (file_id, tag) DO NOTHING
+            "#,
+            file_id,
+            owner_id,
+            tags
+        )
+        .execute(db!())
+        .timed("file_tag.add")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether the file had the tag.
+    pub async fn remove(file_id: i64, tag: &str) -> ModelResult<bool> {
+        let removed = query!(
+            r#"
+                DELETE FROM file_tags
+                WHERE
+                    file_id = $1
+                AND
+                    tag = $2
+            "#,
+            file_id,
+            tag
+        )
+        .execute(db!())
+        .timed("file_tag.remove")
+        .await?
+        .rows_affected();
+
+        Ok(removed > 0)
+    }
+
+    pub async fn list_for_file(file_id: i64) -> ModelResult<Vec<String>> {
+        let tags = query_scalar!(
+            r#"
+                SELECT tag
+                FROM file_tags
+                WHERE
+                    file_id = $1
+                ORDER BY tag
+            "#,
+            file_id
+        )
+        .fetch_all(db!())
+        .timed("file_tag.list_for_file")
+        .await?;
+
+        Ok(tags)
+    }
+
+    /// Every tag of an owner along with how many files carry it.
+    pub async fn list_for_owner(owner_id: i64) -> ModelResult<Vec<TagCount>> {
+        let tags = query_as!(
+            TagCount,
+            r#"
+                SELECT
+                    tag,
+                    COUNT(*) AS "files!"
+                FROM file_tags
+                WHERE
+                    owner_id = $1
+                GROUP BY tag
+                ORDER BY tag
+            "#,
+            owner_id
+        )
+        .fetch_all(db!())
+        .timed("file_tag.list_for_owner")
+        .await?;
+
+        Ok(tags)
+    }
+
+    /// Of the given files owned by `owner_id`, those that would end up
+    /// with more than `limit` tags after adding `add` and removing `remove`.
+    pub async fn exceeding(
+        owner_id: i64,
+        file_ids: &[i64],
+        add: &[String],
+        remove: &[String],
+        limit: i64,
+    ) -> ModelResult<Vec<i64>> {
+        let files = query_scalar!(
+            r#"
+                SELECT objects.id
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    objects.id = ANY($1)
+                AND
+                    buckets.owner_id = $5
+                AND (
+                    SELECT COUNT(*)
+                    FROM (
+                        SELECT tag
+                        FROM file_tags
+                        WHERE
+                            file_id = objects.id
+                        UNION
+                        SELECT UNNEST($2::TEXT[])
+                    ) AS tags (tag)
+                    WHERE
+                        tag <> ALL($3::TEXT[])
+                ) > $4
+                ORDER BY objects.id
+            "#,
+            file_ids,
+            add,
+            remove,
+            limit,
+            owner_id
+        )
+        .fetch_all(db!())
+        .timed("file_tag.exceeding")
+        .await?;
+
+        Ok(files)
+    }
+
+    /// Moves every file of the owner tagged `from` over to `to`, files
+    /// already carrying `to` keep a single one, returns the files moved.
+    pub async fn rename(owner_id: i64, from: &str, to: &str) -> ModelResult<u64> {
+        let mut transaction = db!().begin().await?;
+
+        query!(
+            r#"
+                INSERT INTO file_tags (
+                    file_id,
+                    owner_id,
+                    tag
+                )
+                SELECT
+                    file_id,
+                    owner_id,
+                    $3
+                FROM file_tags
+                WHERE
+                    owner_id = $1
+                AND
+                    tag = $2
+                ON CONFLICT (file_id, tag) DO NOTHING
+            "#,
+            owner_id,
+            from,
+            to
+        )
+        .execute(&mut *transaction)
+        .timed("file_tag.rename")
+        .await?;
+
+        let moved = query!(
+            r#"
+                DELETE FROM file_tags
+                WHERE
+                    owner_id = $1
+                AND
+                    tag = $2
+            "#,
+            owner_id,
+            from
+        )
+        .execute(&mut *transaction)
+        .timed("file_tag.rename")
+        .await?
+        .rows_affected();
+
+        transaction.commit().await?;
+
+        Ok(moved)
+    }
+
+    /// Removes a tag from every file of the owner, returns how many had it.
+    pub async fn delete_for_owner(owner_id: i64, tag: &str) -> ModelResult<u64> {
+        let removed = query!(
+            r#"
+                DELETE FROM file_tags
+                WHERE
+                    owner_id = $1
+                AND
+                    tag = $2
+            "#,
+            owner_id,
+            tag
+        )
+        .execute(db!())
+        .timed("file_tag.delete_for_owner")
+        .await?
+        .rows_affected();
+
+        Ok(removed)
+    }
+
+    /// Adds and removes tags on several files of the owner at once, either
+    /// every file is updated or none is, fails when a file isn't owned.
+    pub async fn bulk(
+        owner_id: i64,
+        file_ids: &[i64],
+        add: &[String],
+        remove: &[String],
+    ) -> ModelResult<()> {
+        let mut transaction = db!().begin().await?;
+
+        let owned = query_scalar!(
+            r#"
+                SELECT COUNT(*) AS "count!"
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    objects.id = ANY($1)
+                AND
+                    buckets.owner_id = $2
+            "#,
+            file_ids,
+            owner_id
+        )
+        .fetch_one(&mut *transaction)
+        .timed("file_tag.bulk")
+        .await?;
+
+        if owned as usize != file_ids.len() {
+            return Err(DatabaseError::ModelNotFound("file"));
+        }
+
+        query!(
+            r#"
+                DELETE FROM file_tags
+                WHERE
+                    file_id = ANY($1)
+                AND
+                    tag = ANY($2)
+            "#,
+            file_ids,
+            remove
+        )
+        .execute(&mut *transaction)
+        .timed("file_tag.bulk")
+        .await?;
+
+        query!(
+            r#"
+                INSERT INTO file_tags (
+                    file_id,
+                    owner_id,
+                    tag
+                )
+                SELECT
+                    files.id,
+                    $2,
+                    tags.tag
+                FROM UNNEST($1::BIGINT[]) AS files (id)
+                CROSS JOIN UNNEST($3::TEXT[]) AS tags (tag)
+                ON CONFLICT (file_id, tag) DO NOTHING
+            "#,
+            file_ids,
+            owner_id,
+            add
+        )
+        .execute(&mut *transaction)
+        .timed("file_tag.bulk")
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
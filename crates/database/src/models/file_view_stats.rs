@@ -0,0 +1,98 @@
+use chrono::NaiveDate;
+use sqlx::{FromRow, query, query_as};
+
+use crate::utils::error::ModelResult;
+use crate::utils::instrument::Timed;
+use crate::{db, db_read};
+
+/// Daily views of files along with a sketch of who viewed them.
+pub struct FileViewStatsModel;
+
+#[derive(FromRow)]
+pub struct FileViewDay {
+    pub day: NaiveDate,
+    pub views: i64,
+    /// HyperLogLog registers, empty when viewers weren't counted.
+    pub viewers: Vec<u8>,
+}
+
+impl FileViewStatsModel {
+    /// Adds `views` to the day of a file, `viewers` is merged into its sketch
+    /// by keeping the largest of every register, which is their union.
+    ///
+    /// Views of files deleted meanwhile are dropped.
+    pub async fn add(file_id: i64, day: NaiveDate, views: i64, viewers: &[u8]) -> ModelResult<()> {
+        query!(
+            r#"
+                INSERT INTO file_view_stats (
+                    file_id,
+                    day,
+                    views,
+                    viewers
+                )
+                SELECT
+                    $1,
+                    $2,
+                    $3,
+                    $4
+                WHERE
+                    EXISTS (SELECT 1 FROM objects WHERE id = $1)
+                ON CONFLICT (file_id, day) DO UPDATE
+                SET
+                    views = file_view_stats.views + EXCLUDED.views,
+                    viewers = CASE
+                        WHEN length(EXCLUDED.viewers) = 0 THEN file_view_stats.viewers
+                        WHEN length(file_view_stats.viewers) <> length(EXCLUDED.viewers)
+                            THEN EXCLUDED.viewers
+                        ELSE (
+                            SELECT decode(string_agg(lpad(to_hex(GREATEST(
+                                get_byte(file_view_stats.viewers, register),
+                                get_byte(EXCLUDED.viewers, register)
+                            )), 2, '0'), '' ORDER BY register), 'hex')
+                            FROM generate_series(0, length(EXCLUDED.viewers) - 1) AS register
+                        )
+                    END
+            "#,
+            file_id,
+            day,
+            views,
+            viewers
+        )
+        .execute(db!())
+        .timed("file_view_stats.add")
+        .await?;
+
+        Ok(())
+    }
+
+    /// The days of a file from `from` to `to` included, that had views.
+    pub async fn list_range(
+        file_id: i64,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> ModelResult<Vec<FileViewDay>> {
+        let days = query_as!(
+            FileViewDay,
+            r#"
+                SELECT
+                    day,
+                    views,
+                    viewers
+                FROM file_view_stats
+                WHERE
+                    file_id = $1
+                AND
+                    day BETWEEN $2 AND $3
+                ORDER BY day
+            "#,
+            file_id,
+            from,
+            to
+        )
+        .fetch_all(db_read!())
+        .timed("file_view_stats.list_range")
+        .await?;
+
+        Ok(days)
+    }
+}
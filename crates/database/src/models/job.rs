@@ -0,0 +1,229 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{query, query_as};
+
+use super::HEARTBEAT_TIMEOUT;
+use crate::db;
+use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
+
+pub const JOB_SUCCEEDED: &str = "succeeded";
+pub const JOB_FAILED: &str = "failed";
+pub const JOB_CANCELLED: &str = "cancelled";
+
+/// A tracked admin operation along with its progress and outcome.
+#[derive(Serialize)]
+pub struct JobModel {
+    id: i64,
+    kind: String,
+    parameters: Value,
+    state: String,
+    progress_done: i64,
+    progress_total: Option<i64>,
+    cancel_requested: bool,
+    result: Option<Value>,
+    error: Option<String>,
+    started_at: NaiveDateTime,
+    finished_at: Option<NaiveDateTime>,
+    owner_instance: Option<String>,
+}
+
+impl JobModel {
+    /// Records a job run by `owner_instance`, the instance id it heartbeats with.
+    pub async fn start(kind: &str, parameters: Value, owner_instance: &str) -> ModelResult<Self> {
+        let job = query_as!(
+            Self,
+            r#"
+                INSERT INTO jobs (
+                    kind,
+                    parameters,
+                    owner_instance
+                )
+                VALUES (
+                    $1,
+                    $2,
+                    $3
+                )
+                RETURNING *
+            "#,
+            kind,
+            parameters,
+            owner_instance
+        )
+        .fetch_one(db!())
+        .timed("job.start")
+        .await?;
+
+        Ok(job)
+    }
+
+    pub async fn get(id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM jobs
+                WHERE
+                    id = $1
+            "#,
+            id
+        )
+        .fetch_optional(db!())
+        .timed("job.get")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("job"))
+    }
+
+    /// Newest jobs first, only those of `kind` and in `state` when given.
+    pub async fn list(
+        kind: Option<&str>,
+        state: Option<&str>,
+        limit: i64,
+    ) -> ModelResult<Vec<Self>> {
+        let jobs = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM jobs
+                WHERE
+                    ($1::TEXT IS NULL OR kind = $1)
+                AND
+                    ($2::TEXT IS NULL OR state = $2)
+                ORDER BY started_at DESC, id DESC
+                LIMIT $3
+            "#,
+            kind,
+            state,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("job.list")
+        .await?;
+
+        Ok(jobs)
+    }
+
+    /// Records how far a running job got, returns whether it was asked to stop.
+    pub async fn set_progress(id: i64, done: i64, total: Option<i64>) -> ModelResult<bool> {
+        let cancel_requested = query!(
+            r#"
+                UPDATE jobs
+                SET
+                    progress_done = $2,
+                    progress_total = COALESCE($3, progress_total)
+                WHERE
+                    id = $1
+                RETURNING cancel_requested
+            "#,
+            id,
+            done,
+            total
+        )
+        .fetch_optional(db!())
+        .timed("job.set_progress")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("job"))?
+        .cancel_requested;
+
+        Ok(cancel_requested)
+    }
+
+    /// Moves a running job to its final state.
+    pub async fn finish(
+        id: i64,
+        state: &str,
+        result: Option<Value>,
+        error: Option<String>,
+    ) -> ModelResult<()> {
+        query!(
+            r#"
+                UPDATE jobs
+                SET
+                    state = $2,
+                    result = $3,
+                    error = $4,
+                    finished_at = NOW()
+                WHERE
+                    id = $1
+                AND
+                    state = 'running'
+            "#,
+            id,
+            state,
+            result,
+            error
+        )
+        .execute(db!())
+        .timed("job.finish")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Asks a running job to stop, fails when it isn't running anymore.
+    pub async fn request_cancel(id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                UPDATE jobs
+                SET
+                    cancel_requested = TRUE
+                WHERE
+                    id = $1
+                AND
+                    state = 'running'
+                RETURNING *
+            "#,
+            id
+        )
+        .fetch_optional(db!())
+        .timed("job.request_cancel")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("running job"))
+    }
+
+    /// Marks the running jobs of instances that stopped heartbeating as
+    /// interrupted, jobs only run on their instance so none of them survived.
+    ///
+    /// Jobs younger than `HEARTBEAT_TIMEOUT` are spared, their instance may
+    /// not have reported yet, those without an owner predate owners.
+    pub async fn mark_interrupted() -> ModelResult<Vec<Self>> {
+        let jobs = query_as!(
+            Self,
+            r#"
+                UPDATE jobs
+                SET
+                    state = 'interrupted',
+                    finished_at = NOW()
+                WHERE
+                    state = 'running'
+                AND
+                    started_at < NOW() - MAKE_INTERVAL(secs => $1)
+                AND NOT EXISTS (
+                    SELECT 1
+                    FROM instance_heartbeats
+                    WHERE
+                        instance_heartbeats.instance_id = jobs.owner_instance
+                    AND
+                        instance_heartbeats.seen_at > NOW() - MAKE_INTERVAL(secs => $1)
+                )
+                RETURNING *
+            "#,
+            HEARTBEAT_TIMEOUT.as_secs_f64()
+        )
+        .fetch_all(db!())
+        .timed("job.mark_interrupted")
+        .await?;
+
+        Ok(jobs)
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+}
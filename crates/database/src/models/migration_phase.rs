@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{query, query_as};
+
+use crate::db;
+use crate::schema_compat::Phase;
+use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
+
+/// Instances that haven't reported for this long are considered gone.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Schema changes rolled out in phases, see `schema_compat`.
+pub struct MigrationPhaseModel;
+
+/// The schema version each running instance was built with.
+pub struct InstanceHeartbeatModel;
+
+#[derive(Serialize)]
+pub struct MigrationPhase {
+    pub name: String,
+    pub phase: String,
+    pub min_version: i64,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct InstanceHeartbeat {
+    pub instance_id: String,
+    pub schema_version: i64,
+    pub seen_at: NaiveDateTime,
+}
+
+impl MigrationPhaseModel {
+    pub async fn list() -> ModelResult<Vec<MigrationPhase>> {
+        let phases = query_as!(
+            MigrationPhase,
+            r#"
+                SELECT *
+                FROM migration_phases
+                ORDER BY name
+            "#
+        )
+        .fetch_all(db!())
+        .timed("migration_phase.list")
+        .await?;
+
+        Ok(phases)
+    }
+
+    /// Moves the change `name` to the next phase, refused when it isn't the
+    /// next one or while a running instance can't handle both shapes yet.
+    pub async fn advance(name: &str, to: Phase) -> ModelResult<MigrationPhase> {
+        let current = Self::list()
+            .await?
+            .into_iter()
+            .find(|phase| phase.name == name)
+            .ok_or(DatabaseError::ModelNotFound("migration phase"))?;
+
+        let next = match Phase::parse(&current.phase) {
+            Some(Phase::Expand) => Phase::Migrate,
+            Some(Phase::Migrate) => Phase::Contract,
+            _ => return Err(DatabaseError::PhaseRefused("it's already contracted".to_owned())),
+        };
+
+        if to != next {
+            return Err(DatabaseError::PhaseRefused(format!(
+                "{name} is in the {} phase, only {} comes next",
+                current.phase,
+                next.as_str()
+            )));
+        }
+
+        let outdated = InstanceHeartbeatModel::list_live()
+            .await?
+            .into_iter()
+            .filter(|instance| instance.schema_version < current.min_version)
+            .map(|instance| {
+                format!("{} (schema {})", instance.instance_id, instance.schema_version)
+            })
+            .collect::<Vec<_>>();
+
+        if !outdated.is_empty() {
+            return Err(DatabaseError::PhaseRefused(format!(
+                "instances built before schema {} still run: {}",
+                current.min_version,
+                outdated.join(", ")
+            )));
+        }
+
+        let phase = query_as!(
+            MigrationPhase,
+            r#"
+                UPDATE migration_phases
+                SET
+                    phase = $2,
+                    updated_at = NOW()
+                WHERE
+                    name = $1
+                RETURNING *
+            "#,
+            name,
+            to.as_str()
+        )
+        .fetch_one(db!())
+        .timed("migration_phase.advance")
+        .await?;
+
+        Ok(phase)
+    }
+}
+
+impl InstanceHeartbeatModel {
+    /// Records that `instance_id` runs with `schema_version`,
+    /// forgetting instances that stopped reporting a day ago.
+    pub async fn beat(instance_id: &str, schema_version: i64) -> ModelResult<()> {
+        query!(
+            r#"
+                INSERT INTO instance_heartbeats (
+                    instance_id,
+                    schema_version
+                )
+                VALUES (
+                    $1,
+                    $2
+                )
+                ON CONFLICT (instance_id) DO UPDATE
+                SET
+                    schema_version = EXCLUDED.schema_version,
+                    seen_at = NOW()
+            "#,
+            instance_id,
+            schema_version
+        )
+        .execute(db!())
+        .timed("instance_heartbeat.beat")
+        .await?;
+
+        query!("DELETE FROM instance_heartbeats WHERE seen_at < NOW() - INTERVAL '1 day'")
+            .execute(db!())
+            .timed("instance_heartbeat.beat")
+            .await?;
+
+        Ok(())
+    }
+
+    /// Instances that reported within `HEARTBEAT_TIMEOUT`.
+    pub async fn list_live() -> ModelResult<Vec<InstanceHeartbeat>> {
+        let heartbeats = query_as!(
+            InstanceHeartbeat,
+            r#"
+                SELECT *
+                FROM instance_heartbeats
+                WHERE
+                    seen_at > NOW() - MAKE_INTERVAL(secs => $1)
+                ORDER BY instance_id
+            "#,
+            HEARTBEAT_TIMEOUT.as_secs_f64()
+        )
+        .fetch_all(db!())
+        .timed("instance_heartbeat.list_live")
+        .await?;
+
+        Ok(heartbeats)
+    }
+}
@@ -1,5 +1,35 @@
+mod api_key;
+mod blob;
 mod bucket;
+mod file;
+mod file_acl;
+mod file_processing;
+mod file_tag;
+mod file_view_stats;
+mod job;
+mod migration_phase;
+mod multipart_upload;
+mod signed_url;
+mod storage;
+mod tenant_domain;
+mod traffic_stats;
 mod user;
+mod user_webhook;
 
+pub use api_key::*;
+pub use blob::*;
 pub use bucket::*;
+pub use file::*;
+pub use file_acl::*;
+pub use file_processing::*;
+pub use file_tag::*;
+pub use file_view_stats::*;
+pub use job::*;
+pub use migration_phase::*;
+pub use multipart_upload::*;
+pub use signed_url::*;
+pub use storage::*;
+pub use tenant_domain::*;
+pub use traffic_stats::*;
 pub use user::*;
+pub use user_webhook::*;
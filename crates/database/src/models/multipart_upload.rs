@@ -0,0 +1,200 @@
+use chrono::NaiveDateTime;
+use sqlx::{FromRow, query, query_as};
+
+use crate::db;
+use crate::utils::error::ModelResult;
+use crate::utils::instrument::Timed;
+
+/// A multipart upload started against the storage backend,
+/// the row only lives until the upload is completed or aborted.
+#[derive(FromRow)]
+pub struct MultipartUploadModel {
+    id: i64,
+    blob_key: String,
+    upload_id: String,
+    started_at: NaiveDateTime,
+    abort_attempts: i32,
+    last_abort_error: Option<String>,
+}
+
+#[derive(FromRow)]
+pub struct MultipartPart {
+    pub part_number: i32,
+    pub etag: String,
+}
+
+impl MultipartUploadModel {
+    pub async fn create(blob_key: &str, upload_id: &str) -> ModelResult<Self> {
+        let upload = query_as!(
+            Self,
+            r#"
+                INSERT INTO multipart_uploads (
+                    blob_key,
+                    upload_id
+                )
+                VALUES (
+                    $1,
+                    $2
+                )
+                RETURNING *
+            "#,
+            blob_key,
+            upload_id
+        )
+        .fetch_one(db!())
+        .timed("multipart_upload.create")
+        .await?;
+
+        Ok(upload)
+    }
+
+    /// The most recent upload for `blob_key` started after `since`,
+    /// older ones are left for reconciliation to abort.
+    pub async fn get_resumable(blob_key: &str, since: NaiveDateTime) -> ModelResult<Option<Self>> {
+        let upload = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM multipart_uploads
+                WHERE
+                    blob_key = $1
+                AND
+                    started_at > $2
+                ORDER BY started_at DESC
+                LIMIT 1
+            "#,
+            blob_key,
+            since
+        )
+        .fetch_optional(db!())
+        .timed("multipart_upload.get_resumable")
+        .await?;
+
+        Ok(upload)
+    }
+
+    pub async fn list() -> ModelResult<Vec<Self>> {
+        let uploads = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM multipart_uploads
+                ORDER BY started_at
+            "#
+        )
+        .fetch_all(db!())
+        .timed("multipart_upload.list")
+        .await?;
+
+        Ok(uploads)
+    }
+
+    /// Checkpoints a part the backend acknowledged, so a resumed
+    /// upload doesn't send it again.
+    pub async fn record_part(&self, part_number: i32, etag: &str) -> ModelResult<()> {
+        query!(
+            r#"
+                INSERT INTO multipart_upload_parts (
+                    multipart_upload_id,
+                    part_number,
+                    etag
+                )
+                VALUES (
+                    $1,
+                    $2,
+                    $3
+                )
+                ON CONFLICT (multipart_upload_id, part_number) DO UPDATE
+                SET
+                    etag = EXCLUDED.etag
+            "#,
+            self.id,
+            part_number,
+            etag
+        )
+        .execute(db!())
+        .timed("multipart_upload.record_part")
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn parts(&self) -> ModelResult<Vec<MultipartPart>> {
+        let parts = query_as!(
+            MultipartPart,
+            r#"
+                SELECT
+                    part_number,
+                    etag
+                FROM multipart_upload_parts
+                WHERE
+                    multipart_upload_id = $1
+                ORDER BY part_number
+            "#,
+            self.id
+        )
+        .fetch_all(db!())
+        .timed("multipart_upload.parts")
+        .await?;
+
+        Ok(parts)
+    }
+
+    pub async fn record_abort_failure(&self, error: &str) -> ModelResult<()> {
+        query!(
+            r#"
+                UPDATE multipart_uploads
+                SET
+                    abort_attempts = abort_attempts + 1,
+                    last_abort_error = $1
+                WHERE
+                    id = $2
+            "#,
+            error,
+            self.id
+        )
+        .execute(db!())
+        .timed("multipart_upload.record_abort_failure")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets the upload once it was completed or aborted.
+    pub async fn finish(&self) -> ModelResult<()> {
+        query!(
+            r#"
+                DELETE FROM multipart_uploads
+                WHERE
+                    id = $1
+            "#,
+            self.id
+        )
+        .execute(db!())
+        .timed("multipart_upload.finish")
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn blob_key(&self) -> &str {
+        &self.blob_key
+    }
+
+    pub fn upload_id(&self) -> &str {
+        &self.upload_id
+    }
+
+    pub fn started_at(&self) -> NaiveDateTime {
+        self.started_at
+    }
+
+    /// Aborts that failed so far, reconciliation retries until one succeeds.
+    pub fn abort_attempts(&self) -> i32 {
+        self.abort_attempts
+    }
+
+    pub fn last_abort_error(&self) -> Option<&str> {
+        self.last_abort_error.as_deref()
+    }
+}
@@ -0,0 +1,39 @@
+use sqlx::query_scalar;
+
+use crate::db;
+use crate::utils::error::ModelResult;
+use crate::utils::instrument::Timed;
+
+pub struct SignedUrlUseModel;
+
+impl SignedUrlUseModel {
+    /// Atomically counts a use of the signed URL identified by `jti`,
+    /// returns `false` without counting when `max_uses` was already reached.
+    pub async fn consume(jti: &str, max_uses: i32) -> ModelResult<bool> {
+        let uses = query_scalar!(
+            r#"
+                INSERT INTO signed_url_uses (
+                    jti,
+                    uses
+                )
+                VALUES (
+                    $1,
+                    1
+                )
+                ON CONFLICT (jti) DO UPDATE
+                SET
+                    uses = signed_url_uses.uses + 1
+                WHERE
+                    signed_url_uses.uses < $2
+                RETURNING uses
+            "#,
+            jti,
+            max_uses
+        )
+        .fetch_optional(db!())
+        .timed("signed_url.consume")
+        .await?;
+
+        Ok(uses.is_some_and(|uses| uses <= max_uses))
+    }
+}
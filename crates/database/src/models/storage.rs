@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{query, query_as};
+
+use crate::db;
+use crate::utils::connection::with_timeout;
+use crate::utils::error::ModelResult;
+use crate::utils::instrument::Timed;
+
+/// Refreshing the summary aggregates every file, past the pool statement timeout.
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Storage usage reports, aggregated by the database so
+/// no file row is loaded to compute them.
+pub struct StorageModel;
+
+#[derive(Serialize)]
+pub struct UsageTotals {
+    files: i64,
+    bytes: i64,
+}
+
+/// Usage of the files sharing a category or a size bucket.
+#[derive(Serialize)]
+pub struct UsageGroup {
+    key: String,
+    files: i64,
+    bytes: i64,
+}
+
+#[derive(Serialize)]
+pub struct BucketUsage {
+    bucket_id: i64,
+    name: String,
+    files: i64,
+    bytes: i64,
+}
+
+/// The usage of a single user, expired files still take space
+/// until they're purged so they're reported on their own.
+#[derive(Serialize)]
+pub struct StorageBreakdown {
+    live: UsageTotals,
+    expired: UsageTotals,
+    by_category: Vec<UsageGroup>,
+    by_bucket: Vec<BucketUsage>,
+    by_size: Vec<UsageGroup>,
+}
+
+/// A row of the admin wide summary, as of its last refresh.
+#[derive(Serialize)]
+pub struct UserStorage {
+    user_id: i64,
+    username: String,
+    files: i64,
+    bytes: i64,
+    expired_bytes: i64,
+    refreshed_at: NaiveDateTime,
+}
+
+impl StorageModel {
+    /// Computes the usage of `owner_id` live, it's bounded by their files.
+    pub async fn breakdown(owner_id: i64) -> ModelResult<StorageBreakdown> {
+        let totals = query!(
+            r#"
+                SELECT
+                    COUNT(*) FILTER (
+                        WHERE objects.expires_at IS NULL OR objects.expires_at > NOW()
+                    ) AS "live_files!",
+                    COALESCE(SUM(objects.size) FILTER (
+                        WHERE objects.expires_at IS NULL OR objects.expires_at > NOW()
+                    ), 0)::BIGINT AS "live_bytes!",
+                    COUNT(*) FILTER (WHERE objects.expires_at <= NOW()) AS "expired_files!",
+                    COALESCE(SUM(objects.size) FILTER (
+                        WHERE objects.expires_at <= NOW()
+                    ), 0)::BIGINT AS "expired_bytes!"
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    buckets.owner_id = $1
+            "#,
+            owner_id
+        )
+        .fetch_one(db!())
+        .timed("storage.breakdown")
+        .await?;
+
+        let by_category = query_as!(
+            UsageGroup,
+            r#"
+                SELECT
+                    object_category(objects.path) AS "key!",
+                    COUNT(*) AS "files!",
+                    SUM(objects.size)::BIGINT AS "bytes!"
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    buckets.owner_id = $1
+                AND
+                    (objects.expires_at IS NULL OR objects.expires_at > NOW())
+                GROUP BY 1
+                ORDER BY 3 DESC
+            "#,
+            owner_id
+        )
+        .fetch_all(db!())
+        .timed("storage.breakdown")
+        .await?;
+
+        let by_bucket = query_as!(
+            BucketUsage,
+            r#"
+                SELECT
+                    buckets.id AS bucket_id,
+                    buckets.name,
+                    COUNT(objects.id) AS "files!",
+                    COALESCE(SUM(objects.size), 0)::BIGINT AS "bytes!"
+                FROM buckets
+                LEFT JOIN objects
+                    ON objects.bucket_id = buckets.id
+                    AND (objects.expires_at IS NULL OR objects.expires_at > NOW())
+                WHERE
+                    buckets.owner_id = $1
+                GROUP BY buckets.id, buckets.name
+                ORDER BY 4 DESC
+            "#,
+            owner_id
+        )
+        .fetch_all(db!())
+        .timed("storage.breakdown")
+        .await?;
+
+        let by_size = query_as!(
+            UsageGroup,
+            r#"
+                SELECT
+                    object_size_bucket(objects.size) AS "key!",
+                    COUNT(*) AS "files!",
+                    SUM(objects.size)::BIGINT AS "bytes!"
+                FROM objects
+                INNER JOIN buckets
+                    ON buckets.id = objects.bucket_id
+                WHERE
+                    buckets.owner_id = $1
+                AND
+                    (objects.expires_at IS NULL OR objects.expires_at > NOW())
+                GROUP BY 1
+                ORDER BY MIN(objects.size)
+            "#,
+            owner_id
+        )
+        .fetch_all(db!())
+        .timed("storage.breakdown")
+        .await?;
+
+        Ok(StorageBreakdown {
+            live: UsageTotals {
+                files: totals.live_files,
+                bytes: totals.live_bytes,
+            },
+            expired: UsageTotals {
+                files: totals.expired_files,
+                bytes: totals.expired_bytes,
+            },
+            by_category,
+            by_bucket,
+            by_size,
+        })
+    }
+
+    /// Reads the per user summary, largest users first unless `ascending`.
+    pub async fn user_totals(ascending: bool, limit: i64) -> ModelResult<Vec<UserStorage>> {
+        let users = query_as!(
+            UserStorage,
+            r#"
+                SELECT
+                    user_id AS "user_id!",
+                    username AS "username!",
+                    files AS "files!",
+                    bytes AS "bytes!",
+                    expired_bytes AS "expired_bytes!",
+                    refreshed_at::TIMESTAMP AS "refreshed_at!"
+                FROM user_storage_summary
+                ORDER BY
+                    CASE WHEN $1 THEN bytes END ASC,
+                    CASE WHEN NOT $1 THEN bytes END DESC,
+                    user_id
+                LIMIT $2
+            "#,
+            ascending,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("storage.user_totals")
+        .await?;
+
+        Ok(users)
+    }
+
+    /// Recomputes the per user summary without blocking its readers.
+    pub async fn refresh_summary() -> ModelResult<()> {
+        let mut transaction = with_timeout(REFRESH_TIMEOUT).await?;
+
+        query!("REFRESH MATERIALIZED VIEW CONCURRENTLY user_storage_summary")
+            .execute(&mut *transaction)
+            .timed("storage.refresh_summary")
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
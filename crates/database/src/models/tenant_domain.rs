@@ -0,0 +1,250 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{FromRow, query, query_as};
+
+use crate::db;
+use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
+
+#[derive(FromRow)]
+pub struct TenantDomainModel {
+    id: i64,
+    owner_id: i64,
+    hostname: String,
+    verification_token: String,
+    verified_at: Option<NaiveDateTime>,
+    public_base_url: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct TenantDomainResult {
+    id: i64,
+    hostname: String,
+    verification_token: String,
+    verified_at: Option<NaiveDateTime>,
+    public_base_url: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+impl TenantDomainModel {
+    /// Claims `hostname` for `owner_id`, it's unverified until
+    /// the TXT challenge passes so claiming it serves nothing yet.
+    ///
+    /// Several users may claim the same hostname, the first claim to pass
+    /// wins. Claiming it again keeps the token and updates the base URL.
+    pub async fn create(
+        owner_id: i64,
+        hostname: &str,
+        public_base_url: Option<&str>,
+    ) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                INSERT INTO tenant_domains (
+                    owner_id,
+                    hostname,
+                    public_base_url
+                )
+                SELECT
+                    $1,
+                    $2,
+                    $3
+                WHERE
+                    NOT EXISTS (
+                        SELECT 1
+                        FROM tenant_domains
+                        WHERE
+                            hostname = $2
+                        AND
+                            verified_at IS NOT NULL
+                        AND
+                            owner_id <> $1
+                    )
+                ON CONFLICT (owner_id, hostname) DO UPDATE
+                SET
+                    public_base_url = EXCLUDED.public_base_url
+                RETURNING *
+            "#,
+            owner_id,
+            hostname,
+            public_base_url
+        )
+        .fetch_optional(db!())
+        .timed("tenant_domain.create")
+        .await?
+        .ok_or(DatabaseError::HostnameTaken)
+    }
+
+    /// Obtains the claim of `owner_id` to `hostname`, verified or not.
+    pub async fn get_claim(hostname: &str, owner_id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM tenant_domains
+                WHERE
+                    hostname = $1
+                AND
+                    owner_id = $2
+            "#,
+            hostname,
+            owner_id
+        )
+        .fetch_optional(db!())
+        .timed("tenant_domain.get_claim")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("domain"))
+    }
+
+    /// Obtains the verified domain for a `Host` header value.
+    pub async fn get_verified(hostname: &str) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM tenant_domains
+                WHERE
+                    hostname = $1
+                AND
+                    verified_at IS NOT NULL
+            "#,
+            hostname
+        )
+        .fetch_optional(db!())
+        .timed("tenant_domain.get_verified")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("domain"))
+    }
+
+    pub async fn list_owned(owner_id: i64) -> ModelResult<Vec<Self>> {
+        let domains = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM tenant_domains
+                WHERE
+                    owner_id = $1
+                ORDER BY hostname
+            "#,
+            owner_id
+        )
+        .fetch_all(db!())
+        .timed("tenant_domain.list_owned")
+        .await?;
+
+        Ok(domains)
+    }
+
+    pub async fn list_unverified() -> ModelResult<Vec<Self>> {
+        let domains = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM tenant_domains
+                WHERE
+                    verified_at IS NULL
+            "#
+        )
+        .fetch_all(db!())
+        .timed("tenant_domain.list_unverified")
+        .await?;
+
+        Ok(domains)
+    }
+
+    /// Marks the claim as verified, either because its challenge passed
+    /// or as an admin override. The first claim of a hostname to be
+    /// verified wins, the pending claims of others are dropped.
+    ///
+    /// Fails with `HostnameTaken` when another claim won already,
+    /// this one is dropped then.
+    pub async fn verify(&self) -> ModelResult<Self> {
+        let verified = query_as!(
+            Self,
+            r#"
+                UPDATE tenant_domains
+                SET
+                    verified_at = COALESCE(verified_at, NOW())
+                WHERE
+                    id = $1
+                RETURNING *
+            "#,
+            self.id
+        )
+        .fetch_optional(db!())
+        .timed("tenant_domain.verify")
+        .await
+        .map_err(DatabaseError::from);
+
+        // Verified hostnames are unique, so of two claims passing at once
+        // the second waits on the first and fails once it's committed.
+        let domain = match verified {
+            Err(DatabaseError::HostnameTaken) => {
+                self.delete().await?;
+                return Err(DatabaseError::HostnameTaken);
+            },
+            verified => verified?.ok_or(DatabaseError::ModelNotFound("domain"))?,
+        };
+
+        query!(
+            r#"
+                DELETE FROM tenant_domains
+                WHERE
+                    hostname = $1
+                AND
+                    verified_at IS NULL
+            "#,
+            domain.hostname
+        )
+        .execute(db!())
+        .timed("tenant_domain.drop_pending")
+        .await?;
+
+        Ok(domain)
+    }
+
+    async fn delete(&self) -> ModelResult<()> {
+        query!(
+            r#"
+                DELETE FROM tenant_domains
+                WHERE
+                    id = $1
+            "#,
+            self.id
+        )
+        .execute(db!())
+        .timed("tenant_domain.delete")
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn owner_id(&self) -> i64 {
+        self.owner_id
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    pub fn verification_token(&self) -> &str {
+        &self.verification_token
+    }
+
+    /// The base generated links use, `https://<hostname>` unless overridden.
+    pub fn public_base_url(&self) -> String {
+        self.public_base_url.clone().unwrap_or_else(|| format!("https://{}", self.hostname))
+    }
+
+    pub fn into_result(&self) -> TenantDomainResult {
+        TenantDomainResult {
+            id: self.id,
+            hostname: self.hostname.clone(),
+            verification_token: self.verification_token.clone(),
+            verified_at: self.verified_at,
+            public_base_url: self.public_base_url.clone(),
+            created_at: self.created_at,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{FromRow, query, query_as};
+
+use crate::utils::error::ModelResult;
+use crate::utils::instrument::Timed;
+use crate::{db, db_read};
+
+/// Daily bytes exchanged with every client on every route.
+pub struct TrafficStatsModel;
+
+/// What a client exchanged on a route, over a day or until it's written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrafficTotals {
+    pub requests: i64,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    /// Bytes of requests that were rejected or cut short, both ways.
+    pub wasted_bytes: i64,
+    pub aborted_uploads: i64,
+}
+
+impl TrafficTotals {
+    pub fn add(&mut self, other: &TrafficTotals) {
+        self.requests += other.requests;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+        self.wasted_bytes += other.wasted_bytes;
+        self.aborted_uploads += other.aborted_uploads;
+    }
+}
+
+#[derive(FromRow, Serialize)]
+pub struct TrafficEntry {
+    pub client: String,
+    pub route: String,
+    pub requests: i64,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub wasted_bytes: i64,
+    pub aborted_uploads: i64,
+}
+
+impl TrafficStatsModel {
+    /// Adds `totals` to what `client` exchanged on `route` during `day`.
+    pub async fn add(
+        day: NaiveDate,
+        client: &str,
+        route: &str,
+        totals: &TrafficTotals,
+    ) -> ModelResult<()> {
+        query!(
+            r#"
+                INSERT INTO traffic_stats (
+                    day,
+                    client,
+                    route,
+                    requests,
+                    bytes_in,
+                    bytes_out,
+                    wasted_bytes,
+                    aborted_uploads
+                )
+                VALUES (
+                    $1,
+                    $2,
+                    $3,
+                    $4,
+                    $5,
+                    $6,
+                    $7,
+                    $8
+                )
+                ON CONFLICT (day, client, route) DO UPDATE
+                SET
+                    requests = traffic_stats.requests + EXCLUDED.requests,
+                    bytes_in = traffic_stats.bytes_in + EXCLUDED.bytes_in,
+                    bytes_out = traffic_stats.bytes_out + EXCLUDED.bytes_out,
+                    wasted_bytes = traffic_stats.wasted_bytes + EXCLUDED.wasted_bytes,
+                    aborted_uploads = traffic_stats.aborted_uploads + EXCLUDED.aborted_uploads
+            "#,
+            day,
+            client,
+            route,
+            totals.requests,
+            totals.bytes_in,
+            totals.bytes_out,
+            totals.wasted_bytes,
+            totals.aborted_uploads
+        )
+        .execute(db!())
+        .timed("traffic_stats.add")
+        .await?;
+
+        Ok(())
+    }
+
+    /// The traffic of `day`, the most wasted first.
+    pub async fn list_day(day: NaiveDate, limit: i64) -> ModelResult<Vec<TrafficEntry>> {
+        let entries = query_as!(
+            TrafficEntry,
+            r#"
+                SELECT
+                    client,
+                    route,
+                    requests,
+                    bytes_in,
+                    bytes_out,
+                    wasted_bytes,
+                    aborted_uploads
+                FROM traffic_stats
+                WHERE
+                    day = $1
+                ORDER BY wasted_bytes DESC, bytes_in + bytes_out DESC
+                LIMIT $2
+            "#,
+            day,
+            limit
+        )
+        .fetch_all(db_read!())
+        .timed("traffic_stats.list_day")
+        .await?;
+
+        Ok(entries)
+    }
+}
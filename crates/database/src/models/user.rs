@@ -1,9 +1,10 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::Serialize;
-use sqlx::{FromRow, query_as};
+use sqlx::{FromRow, query_as, query_scalar};
 
 use crate::db;
 use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
 
 #[derive(FromRow)]
 pub struct UserModel {
@@ -12,6 +13,26 @@ pub struct UserModel {
     email: String,
     password: String,
     created_at: NaiveDateTime,
+    deleted_at: Option<NaiveDateTime>,
+    indexable: bool,
+    role: String,
+    is_active: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -27,12 +48,24 @@ pub struct UserUpdate {
     email: Option<String>,
     old_password: Option<String>,
     new_password: Option<String>,
+    /// Whether search engines may index files that don't say otherwise.
+    indexable: Option<bool>,
+}
+
+/// User totals broken down by their soft delete state.
+#[derive(Serialize)]
+pub struct UserCounts {
+    total: i64,
+    active: i64,
+    deleted: i64,
 }
 
 #[derive(Serialize)]
 pub struct UserResult {
     id: i64,
     username: String,
+    role: Role,
+    is_active: bool,
     created_at: NaiveDateTime,
 }
 
@@ -58,6 +91,7 @@ impl UserModel {
             creation.password
         )
         .fetch_one(db!())
+        .timed("user.create_new")
         .await?;
 
         Ok(user)
@@ -75,10 +109,89 @@ impl UserModel {
             id
         )
         .fetch_one(db!())
+        .timed("user.get")
         .await?;
         Ok(user)
     }
 
+    pub async fn get_by_username(username: &str) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM users
+                WHERE
+                    username = $1
+            "#,
+            username
+        )
+        .fetch_optional(db!())
+        .timed("user.get_by_username")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("user"))
+    }
+
+    /// Users whose username or email contains `term`, case insensitively,
+    /// registered between `after` and `before` inclusive, oldest first.
+    pub async fn search(
+        term: Option<&str>,
+        after: Option<NaiveDate>,
+        before: Option<NaiveDate>,
+        offset: i64,
+        limit: i64,
+    ) -> ModelResult<Vec<Self>> {
+        let users = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM users
+                WHERE
+                    (
+                        $1::TEXT IS NULL
+                    OR
+                        STRPOS(LOWER(username), LOWER($1)) > 0
+                    OR
+                        STRPOS(LOWER(email), LOWER($1)) > 0
+                    )
+                AND
+                    ($2::DATE IS NULL OR created_at >= $2)
+                AND
+                    ($3::DATE IS NULL OR created_at < $3 + 1)
+                ORDER BY created_at, id
+                OFFSET $4
+                LIMIT $5
+            "#,
+            term,
+            after,
+            before,
+            offset,
+            limit
+        )
+        .fetch_all(db!())
+        .timed("user.search")
+        .await?;
+
+        Ok(users)
+    }
+
+    pub async fn count() -> ModelResult<UserCounts> {
+        let counts = query_as!(
+            UserCounts,
+            r#"
+                SELECT
+                    COUNT(*) AS "total!",
+                    COUNT(*) FILTER (WHERE deleted_at IS NULL) AS "active!",
+                    COUNT(*) FILTER (WHERE deleted_at IS NOT NULL) AS "deleted!"
+                FROM users
+            "#
+        )
+        .fetch_one(db!())
+        .timed("user.count")
+        .await?;
+
+        Ok(counts)
+    }
+
     pub async fn edit(&self, update: UserUpdate) -> ModelResult<Self> {
         query_as!(
             Self,
@@ -87,6 +200,7 @@ impl UserModel {
                 SET
                     username = COALESCE($1, username),
                     email = COALESCE($2, email),
+                    indexable = COALESCE($6, indexable),
                     password = CASE
                         WHEN $3::TEXT IS NOT NULL
                             AND (password IS NULL OR crypt($4::TEXT, password) = password)
@@ -103,18 +217,267 @@ impl UserModel {
             update.email,
             update.old_password,
             update.new_password,
-            self.id
+            self.id,
+            update.indexable
         )
         .fetch_optional(db!())
+        .timed("user.edit")
         .await?
         .ok_or(DatabaseError::ModelNotFound("user"))
     }
 
+    /// Changes the role of the user, refusing to demote the last admin
+    /// so there's always someone left to manage the others.
+    pub async fn set_role(&self, role: Role) -> ModelResult<Self> {
+        let mut transaction = db!().begin().await?;
+
+        // Locks every admin so concurrent demotions can't both pass the check.
+        let admins = query_scalar!(
+            r#"
+                SELECT id
+                FROM users
+                WHERE
+                    role = 'admin'
+                AND
+                    deleted_at IS NULL
+                FOR UPDATE
+            "#
+        )
+        .fetch_all(&mut *transaction)
+        .timed("user.set_role")
+        .await?;
+
+        if role != Role::Admin && admins == [self.id] {
+            return Err(DatabaseError::LastAdmin);
+        }
+
+        let user = query_as!(
+            Self,
+            r#"
+                UPDATE users
+                SET
+                    role = $2
+                WHERE
+                    id = $1
+                RETURNING *
+            "#,
+            self.id,
+            role.as_str()
+        )
+        .fetch_optional(&mut *transaction)
+        .timed("user.set_role")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("user"))?;
+
+        transaction.commit().await?;
+
+        Ok(user)
+    }
+
+    /// Disables or enables the user, disabled users can't authenticate
+    /// but keep their data and their files stay served.
+    pub async fn set_active(&self, active: bool) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                UPDATE users
+                SET
+                    is_active = $2
+                WHERE
+                    id = $1
+                RETURNING *
+            "#,
+            self.id,
+            active
+        )
+        .fetch_optional(db!())
+        .timed("user.set_active")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("user"))
+    }
+
+    /// Whether the user `id` may still authenticate, read live
+    /// since sessions only hold a snapshot of the user.
+    pub async fn is_active_by_id(id: i64) -> ModelResult<bool> {
+        let active = query_scalar!(
+            r#"
+                SELECT is_active
+                FROM users
+                WHERE
+                    id = $1
+            "#,
+            id
+        )
+        .fetch_optional(db!())
+        .timed("user.is_active_by_id")
+        .await?;
+
+        Ok(active.unwrap_or(false))
+    }
+
+    /// The role of the user `id`, read live so a demotion
+    /// applies to sessions issued before it.
+    pub async fn role_by_id(id: i64) -> ModelResult<Role> {
+        let role = query_scalar!(
+            r#"
+                SELECT role
+                FROM users
+                WHERE
+                    id = $1
+            "#,
+            id
+        )
+        .fetch_optional(db!())
+        .timed("user.role_by_id")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("user"))?;
+
+        Ok(if role == Role::Admin.as_str() { Role::Admin } else { Role::User })
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    pub fn deleted_at(&self) -> Option<NaiveDateTime> {
+        self.deleted_at
+    }
+
+    pub fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.is_active
+    }
+
+    /// Whether search engines may index the files of the user
+    /// that don't set it themselves.
+    pub fn indexable(&self) -> bool {
+        self.indexable
+    }
+
+    pub fn role(&self) -> Role {
+        if self.role == Role::Admin.as_str() { Role::Admin } else { Role::User }
+    }
+
     pub fn into_result(&self) -> UserResult {
         UserResult {
             id: self.id.clone(),
             username: self.username.clone(),
+            role: self.role(),
+            is_active: self.is_active,
             created_at: self.created_at,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::query;
+
+    use super::*;
+    use crate::testing::{run, unique_hash};
+    use crate::utils::connection::get_db_connection;
+
+    /// Inserts a user directly, soft deleted when `deleted` is set.
+    async fn insert_user(deleted: bool) {
+        let name = format!("count-{}", &unique_hash()[..32]);
+
+        query(
+            r#"
+                INSERT INTO users (username, email, password, deleted_at)
+                VALUES ($1, $1, '', CASE WHEN $2 THEN NOW() END)
+            "#,
+        )
+        .bind(name)
+        .bind(deleted)
+        .execute(&get_db_connection().await.unwrap())
+        .await
+        .unwrap();
+    }
+
+    /// Inserts a user registered at `created_at`.
+    async fn insert_registered(username: &str, email: &str, created_at: &str) {
+        query(
+            r#"
+                INSERT INTO users (username, email, password, created_at)
+                VALUES ($1, $2, '', $3::TIMESTAMP)
+            "#,
+        )
+        .bind(username)
+        .bind(email)
+        .bind(created_at)
+        .execute(&get_db_connection().await.unwrap())
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn counts_split_users_by_their_soft_delete_state() {
+        run(async {
+            let before = UserModel::count().await.unwrap();
+
+            for deleted in [false, false, false, true, true] {
+                insert_user(deleted).await;
+            }
+
+            let after = UserModel::count().await.unwrap();
+
+            assert_eq!(after.total - before.total, 5);
+            assert_eq!(after.active - before.active, 3);
+            assert_eq!(after.deleted - before.deleted, 2);
+            assert_eq!(after.active + after.deleted, after.total);
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn searches_match_substrings_of_usernames_and_emails_in_any_case() {
+        run(async {
+            let tag = &unique_hash()[..32];
+            let alice = format!("Alice-{}", tag.to_uppercase());
+            let (bob, carol) = (format!("bob-{tag}"), format!("carol-{tag}"));
+
+            insert_registered(&alice, &format!("alice-{tag}@example.com"), "2001-03-01 00:00:00")
+                .await;
+            insert_registered(&bob, &format!("bob-{tag}@example.com"), "2001-03-02 23:59:59").await;
+            insert_registered(&carol, &format!("carol@{tag}.example.com"), "2001-03-03 00:00:00")
+                .await;
+
+            let date = |day| NaiveDate::from_ymd_opt(2001, 3, day);
+            let search = async |term: &str, after, before, offset, limit| {
+                let users = UserModel::search(Some(term), after, before, offset, limit);
+                let users = users.await.unwrap();
+                users.iter().map(|user| user.username().to_owned()).collect::<Vec<_>>()
+            };
+
+            // Oldest first, wherever the term is found.
+            assert_eq!(search(tag, None, None, 0, 100).await, [&*alice, &*bob, &*carol]);
+            assert_eq!(search(&format!("ALICE-{tag}"), None, None, 0, 100).await, [&*alice]);
+            assert_eq!(search(&format!("@{tag}"), None, None, 0, 100).await, [&*carol]);
+            assert_eq!(search(&tag[8..20], None, None, 0, 100).await, [&*alice, &*bob, &*carol]);
+            assert!(search(&format!("dave-{tag}"), None, None, 0, 100).await.is_empty());
+
+            // Both ends are whole days and inclusive.
+            assert_eq!(search(tag, date(2), date(2), 0, 100).await, [&*bob]);
+            assert_eq!(search(tag, date(2), None, 0, 100).await, [&*bob, &*carol]);
+            assert_eq!(search(tag, None, date(1), 0, 100).await, [&*alice]);
+            assert!(search(tag, date(4), None, 0, 100).await.is_empty());
+            assert!(search(tag, date(3), date(2), 0, 100).await.is_empty());
+
+            assert_eq!(search(tag, None, None, 1, 1).await, [&*bob]);
+            assert_eq!(search(tag, None, None, 2, 100).await, [&*carol]);
+            assert!(search(tag, None, None, 3, 100).await.is_empty());
+        })
+    }
+}
@@ -0,0 +1,459 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{FromRow, query, query_as};
+
+use crate::utils::error::{DatabaseError, ModelResult};
+use crate::utils::instrument::Timed;
+use crate::{db, db_read};
+
+/// Attempts kept per webhook, older ones are dropped as new ones come.
+pub const DELIVERIES_KEPT: i64 = 20;
+
+#[derive(FromRow)]
+pub struct UserWebhookModel {
+    id: i64,
+    owner_id: i64,
+    url: String,
+    secret: Option<String>,
+    events: Vec<String>,
+    bucket_id: Option<i64>,
+    tag: Option<String>,
+    format: String,
+    enabled: bool,
+    consecutive_failures: i32,
+    disabled_reason: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+/// How deliveries are shaped, `discord` posts a message
+/// Discord's incoming webhooks accept as is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    #[default]
+    Json,
+    Discord,
+}
+
+impl WebhookFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookFormat::Json => "json",
+            WebhookFormat::Discord => "discord",
+        }
+    }
+
+    fn parse(format: &str) -> Self {
+        match format {
+            "discord" => WebhookFormat::Discord,
+            _ => WebhookFormat::Json,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct UserWebhookCreation {
+    pub url: String,
+    /// Signs deliveries when set.
+    pub secret: Option<String>,
+    pub events: Vec<String>,
+    /// Only fires for files of this bucket.
+    pub bucket_id: Option<i64>,
+    /// Only fires for files carrying this tag.
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UserWebhookUpdate {
+    pub url: Option<String>,
+    /// An empty string clears it.
+    pub secret: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub format: Option<WebhookFormat>,
+    /// Enabling a webhook forgets the failures that disabled it.
+    pub enabled: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct UserWebhookResult {
+    id: i64,
+    url: String,
+    has_secret: bool,
+    events: Vec<String>,
+    bucket_id: Option<i64>,
+    tag: Option<String>,
+    format: String,
+    enabled: bool,
+    consecutive_failures: i32,
+    disabled_reason: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct UserWebhookDelivery {
+    pub id: i64,
+    pub event: String,
+    pub attempt: i32,
+    /// `None` when no response came back.
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl UserWebhookModel {
+    /// Creates a webhook for `owner_id` unless they already have `max`.
+    pub async fn create(
+        owner_id: i64,
+        creation: &UserWebhookCreation,
+        max: i64,
+    ) -> ModelResult<Option<Self>> {
+        let webhook = query_as!(
+            Self,
+            r#"
+                INSERT INTO user_webhooks (
+                    owner_id,
+                    url,
+                    secret,
+                    events,
+                    bucket_id,
+                    tag,
+                    format
+                )
+                SELECT
+                    $1,
+                    $2,
+                    $3,
+                    $4,
+                    $5,
+                    $6,
+                    $7
+                WHERE
+                    (SELECT COUNT(*) FROM user_webhooks WHERE owner_id = $1) < $8
+                RETURNING *
+            "#,
+            owner_id,
+            creation.url,
+            creation.secret,
+            &creation.events,
+            creation.bucket_id,
+            creation.tag,
+            creation.format.as_str(),
+            max
+        )
+        .fetch_optional(db!())
+        .timed("user_webhook.create")
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn get(id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM user_webhooks
+                WHERE
+                    id = $1
+            "#,
+            id
+        )
+        .fetch_optional(db!())
+        .timed("user_webhook.get")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("webhook"))
+    }
+
+    pub async fn get_owned(id: i64, owner_id: i64) -> ModelResult<Self> {
+        query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM user_webhooks
+                WHERE
+                    id = $1
+                AND
+                    owner_id = $2
+            "#,
+            id,
+            owner_id
+        )
+        .fetch_optional(db!())
+        .timed("user_webhook.get_owned")
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("webhook"))
+    }
+
+    pub async fn list_owned(owner_id: i64) -> ModelResult<Vec<Self>> {
+        let webhooks = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM user_webhooks
+                WHERE
+                    owner_id = $1
+                ORDER BY id
+            "#,
+            owner_id
+        )
+        .fetch_all(db_read!())
+        .timed("user_webhook.list_owned")
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    /// The enabled webhooks of the owner of `bucket_id` subscribed to
+    /// `event`, their bucket and tag scopes are left to the caller.
+    pub async fn list_subscribed(bucket_id: i64, event: &str) -> ModelResult<Vec<Self>> {
+        let webhooks = query_as!(
+            Self,
+            r#"
+                SELECT *
+                FROM user_webhooks
+                WHERE
+                    owner_id = (SELECT owner_id FROM buckets WHERE id = $1)
+                AND
+                    enabled
+                AND
+                    $2 = ANY(events)
+            "#,
+            bucket_id,
+            event
+        )
+        .fetch_all(db!())
+        .timed("user_webhook.list_subscribed")
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    pub async fn edit(&self, update: &UserWebhookUpdate) -> ModelResult<Self> {
+        let webhook = query_as!(
+            Self,
+            r#"
+                UPDATE user_webhooks
+                SET
+                    url = COALESCE($2, url),
+                    secret = NULLIF(COALESCE($3, secret), ''),
+                    events = COALESCE($4, events),
+                    format = COALESCE($5, format),
+                    enabled = COALESCE($6, enabled),
+                    consecutive_failures =
+                        CASE WHEN $6 THEN 0 ELSE consecutive_failures END,
+                    disabled_reason = CASE WHEN $6 THEN NULL ELSE disabled_reason END
+                WHERE
+                    id = $1
+                RETURNING *
+            "#,
+            self.id,
+            update.url,
+            update.secret,
+            update.events.as_deref(),
+            update.format.map(WebhookFormat::as_str),
+            update.enabled
+        )
+        .fetch_one(db!())
+        .timed("user_webhook.edit")
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn delete(&self) -> ModelResult<()> {
+        query!(
+            r#"
+                DELETE FROM user_webhooks
+                WHERE
+                    id = $1
+            "#,
+            self.id
+        )
+        .execute(db!())
+        .timed("user_webhook.delete")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records an attempt, dropping those past the `DELIVERIES_KEPT` latest.
+    pub async fn record_delivery(
+        id: i64,
+        event: &str,
+        attempt: i32,
+        status_code: Option<i32>,
+        error: Option<&str>,
+    ) -> ModelResult<()> {
+        query!(
+            r#"
+                WITH inserted AS (
+                    INSERT INTO user_webhook_deliveries (
+                        webhook_id,
+                        event,
+                        attempt,
+                        status_code,
+                        error
+                    )
+                    VALUES (
+                        $1,
+                        $2,
+                        $3,
+                        $4,
+                        $5
+                    )
+                )
+                DELETE FROM user_webhook_deliveries
+                WHERE id IN (
+                    SELECT id
+                    FROM user_webhook_deliveries
+                    WHERE
+                        webhook_id = $1
+                    ORDER BY id DESC
+                    OFFSET $6::BIGINT - 1
+                )
+            "#,
+            id,
+            event,
+            attempt,
+            status_code,
+            error,
+            DELIVERIES_KEPT
+        )
+        .execute(db!())
+        .timed("user_webhook.record_delivery")
+        .await?;
+
+        Ok(())
+    }
+
+    /// The latest attempts, newest first.
+    pub async fn list_deliveries(id: i64) -> ModelResult<Vec<UserWebhookDelivery>> {
+        let deliveries = query_as!(
+            UserWebhookDelivery,
+            r#"
+                SELECT
+                    id,
+                    event,
+                    attempt,
+                    status_code,
+                    error,
+                    created_at
+                FROM user_webhook_deliveries
+                WHERE
+                    webhook_id = $1
+                ORDER BY id DESC
+                LIMIT $2
+            "#,
+            id,
+            DELIVERIES_KEPT
+        )
+        .fetch_all(db_read!())
+        .timed("user_webhook.list_deliveries")
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    /// A delivery went through, so past failures no longer count.
+    pub async fn delivered(id: i64) -> ModelResult<()> {
+        query!(
+            r#"
+                UPDATE user_webhooks
+                SET
+                    consecutive_failures = 0
+                WHERE
+                    id = $1
+            "#,
+            id
+        )
+        .execute(db!())
+        .timed("user_webhook.delivered")
+        .await?;
+
+        Ok(())
+    }
+
+    /// Counts a delivery that failed every attempt, the webhook is disabled
+    /// with `reason` once `disable_after` failed in a row, `0` never does.
+    pub async fn failed(id: i64, disable_after: i32, reason: &str) -> ModelResult<Self> {
+        let webhook = query_as!(
+            Self,
+            r#"
+                UPDATE user_webhooks
+                SET
+                    consecutive_failures = consecutive_failures + 1,
+                    enabled = enabled
+                        AND ($2 = 0 OR consecutive_failures + 1 < $2),
+                    disabled_reason = CASE
+                        WHEN enabled AND $2 > 0 AND consecutive_failures + 1 >= $2 THEN $3
+                        ELSE disabled_reason
+                    END
+                WHERE
+                    id = $1
+                RETURNING *
+            "#,
+            id,
+            disable_after,
+            reason
+        )
+        .fetch_one(db!())
+        .timed("user_webhook.failed")
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn owner_id(&self) -> i64 {
+        self.owner_id
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn secret(&self) -> Option<&str> {
+        self.secret.as_deref()
+    }
+
+    pub fn bucket_id(&self) -> Option<i64> {
+        self.bucket_id
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    pub fn format(&self) -> WebhookFormat {
+        WebhookFormat::parse(&self.format)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn disabled_reason(&self) -> Option<&str> {
+        self.disabled_reason.as_deref()
+    }
+
+    pub fn into_result(&self) -> UserWebhookResult {
+        UserWebhookResult {
+            id: self.id,
+            url: self.url.clone(),
+            has_secret: self.secret.is_some(),
+            events: self.events.clone(),
+            bucket_id: self.bucket_id,
+            tag: self.tag.clone(),
+            format: self.format.clone(),
+            enabled: self.enabled,
+            consecutive_failures: self.consecutive_failures,
+            disabled_reason: self.disabled_reason.clone(),
+            created_at: self.created_at,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Expand and contract migrations, so a column can be renamed or split
+//! without a maintenance window.
+//!
+//! A change goes through three phases, advanced with `cdnctl migration-phase`:
+//!
+//! - `expand`: a migration adds the new shape next to the old one and
+//!   registers the change in `migration_phases`. Writes fill both shapes,
+//!   reads still use the old one since rows written by older binaries
+//!   lack the new shape. The new shape is backfilled meanwhile.
+//! - `migrate`: reads use the new shape, writes still fill both so a
+//!   rollback to the expand phase loses nothing.
+//! - `contract`: only the new shape is used, a later migration drops the
+//!   old one once every binary was built past the contract.
+//!
+//! Queries pick their variant with `compat_read!` and `compat_write!`.
+//! A change that isn't registered is treated as contracted.
+
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use serde::Serialize;
+
+use crate::models::MigrationPhaseModel;
+use crate::utils::error::ModelResult;
+
+/// The phases of changes in flight, as of the last `load_phases`.
+static PHASES: RwLock<Option<HashMap<String, Phase>>> = RwLock::new(None);
+
+static SCHEMA_VERSION: OnceLock<i64> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Expand,
+    Migrate,
+    Contract,
+}
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Expand => "expand",
+            Phase::Migrate => "migrate",
+            Phase::Contract => "contract",
+        }
+    }
+
+    pub fn parse(phase: &str) -> Option<Self> {
+        match phase {
+            "expand" => Some(Phase::Expand),
+            "migrate" => Some(Phase::Migrate),
+            "contract" => Some(Phase::Contract),
+            _ => None,
+        }
+    }
+}
+
+/// The phase of the change `name`, `Contract` when it isn't registered.
+pub fn phase(name: &str) -> Phase {
+    PHASES
+        .read()
+        .unwrap_or_else(|error| error.into_inner())
+        .as_ref()
+        .and_then(|phases| phases.get(name).copied())
+        .unwrap_or(Phase::Contract)
+}
+
+/// Whether reads of the change `name` still go to the old shape.
+pub fn reads_old(name: &str) -> bool {
+    phase(name) == Phase::Expand
+}
+
+/// Whether writes of the change `name` must still fill the old shape.
+pub fn writes_old(name: &str) -> bool {
+    phase(name) < Phase::Contract
+}
+
+/// Picks the read query of a change, `old` during the expand phase.
+#[macro_export]
+macro_rules! compat_read {
+    ($name:literal, old => $old:expr, new => $new:expr $(,)?) => {
+        if $crate::schema_compat::reads_old($name) { $old } else { $new }
+    };
+}
+
+/// Picks the write query of a change, `both` filling the old and the new
+/// shape in a single statement until the contract phase.
+#[macro_export]
+macro_rules! compat_write {
+    ($name:literal, both => $both:expr, new => $new:expr $(,)?) => {
+        if $crate::schema_compat::writes_old($name) { $both } else { $new }
+    };
+}
+
+/// The newest migration this binary was built with.
+pub fn schema_version() -> Option<i64> {
+    SCHEMA_VERSION.get().copied()
+}
+
+pub(crate) fn set_schema_version(version: i64) {
+    let _ = SCHEMA_VERSION.set(version);
+}
+
+/// Reads the phase of every change in flight, called at startup
+/// and along with every heartbeat so advances are picked up.
+pub async fn load_phases() -> ModelResult<()> {
+    let phases = MigrationPhaseModel::list()
+        .await?
+        .into_iter()
+        .filter_map(|phase| Some((phase.name, Phase::parse(&phase.phase)?)))
+        .collect();
+
+    *PHASES.write().unwrap_or_else(|error| error.into_inner()) = Some(phases);
+
+    Ok(())
+}
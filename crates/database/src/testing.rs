@@ -0,0 +1,58 @@
+//! Fixtures for the tests that need a database, ignored by default
+//! and run with `cargo test -- --ignored` once `DATABASE_URL` points
+//! to a migrated database.
+//!
+//! They run through `run` rather than `#[tokio::test]`, the pool `db!()`
+//! hands out is shared by every test and its connections only work as
+//! long as the runtime they were opened on.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sqlx::query_scalar;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::BucketModel;
+use crate::utils::connection::get_db_connection;
+
+/// Runs a database test on the runtime every one of them shares, one at
+/// a time since some replace the pool the others would be querying.
+pub fn run<F: Future>(test: F) -> F::Output {
+    static RUNTIME: LazyLock<Runtime> =
+        LazyLock::new(|| Builder::new_multi_thread().enable_all().build().unwrap());
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    let _serial = SERIAL.lock().unwrap_or_else(|error| error.into_inner());
+
+    RUNTIME.block_on(test)
+}
+
+/// Hex digits no other test run produces, 32 of them.
+fn unique() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+
+    format!("{now:016x}{:016x}", COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// A digest shaped like a SHA-256 one, unique to the caller.
+pub fn unique_hash() -> String {
+    unique().repeat(2)
+}
+
+/// A bucket of a user of its own, the user is inserted
+/// directly since tests only need someone owning files.
+pub async fn create_bucket() -> BucketModel {
+    let name = format!("test-{}", unique());
+    let owner = query_scalar::<_, i64>(
+        "INSERT INTO users (username, email, password) VALUES ($1, $1, '') RETURNING id",
+    )
+    .bind(&name)
+    .fetch_one(&get_db_connection().await.unwrap())
+    .await
+    .unwrap();
+
+    BucketModel::get_or_create(&name, owner).await.unwrap()
+}
@@ -1,21 +1,54 @@
+use std::env::var;
 use std::path::Path;
-use std::sync::OnceLock;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
 
+use log::{LevelFilter, warn};
 use sqlx::migrate::{MigrateError, Migrator};
-use sqlx::postgres::PgPoolOptions;
-use sqlx::{Error as SqlxError, Pool, Postgres};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Connection, Error as SqlxError, Executor, Pool, Postgres, Transaction};
 use thiserror::Error as ThisError;
+use tokio::runtime::Handle;
+
+use super::error::ModelResult;
+use crate::schema_compat::set_schema_version;
 
 /// This macro obtains a connection to the database,
 /// beware it must be in an async (sugar syntaxed) context.
 #[macro_export]
 macro_rules! db {
     () => {
-        $crate::utils::connection::get_db_connection().await?
+        &$crate::utils::connection::get_db_connection().await?
     };
 }
 
-static CONNECTION: OnceLock<Pool<Postgres>> = OnceLock::new();
+/// Like `db!()` but for the read pool, meant for the lookups serving does.
+#[macro_export]
+macro_rules! db_read {
+    () => {
+        &$crate::utils::connection::get_read_connection().await?
+    };
+}
+
+/// The pool currently in use, `None` until the first query or after
+/// it was reset because its connections went stale.
+///
+/// Pools are handles to shared state, so `db!()` hands out clones
+/// and a replaced pool is closed once its last query is done.
+static CONNECTION: RwLock<Option<Pool<Postgres>>> = RwLock::new(None);
+
+/// The read pool, handled like `CONNECTION`, holding `Some(None)`
+/// once it was opened to share the main pool.
+static READ_CONNECTION: RwLock<Option<Option<Pool<Postgres>>>> = RwLock::new(None);
+
+/// Set while a reset checks the pools, so a burst of
+/// failing queries only checks them once.
+static PROBING: AtomicBool = AtomicBool::new(false);
+
+/// How the pools are sized and what they log, read once.
+static SETTINGS: OnceLock<PoolSettings> = OnceLock::new();
 
 #[derive(ThisError, Debug)]
 pub enum DatabaseConnectionError {
@@ -24,26 +57,348 @@ pub enum DatabaseConnectionError {
 
     #[error("{0:#}")]
     MigrateError(#[from] MigrateError),
+
+    #[error("{0} must be {1}")]
+    InvalidSetting(&'static str, &'static str),
+}
+
+/// Settings of the pools, given to `configure_db` or read from the environment.
+#[derive(Debug)]
+pub(crate) struct PoolSettings {
+    /// Connections of the main pool, `DB_POOL_SIZE` or 5.
+    size: u32,
+    /// Connections of the read pool, `DB_READ_POOL_SIZE` or 3.
+    read_size: u32,
+    /// How long a statement may run before Postgres cancels it,
+    /// `STATEMENT_TIMEOUT_MS` or 10 seconds.
+    statement_timeout: Duration,
+    /// Level every statement is logged at, `SQLX_LOG_LEVEL`.
+    log_level: LevelFilter,
+    /// Level slow statements are logged at, `SQLX_SLOW_LOG_LEVEL`.
+    slow_log_level: LevelFilter,
+    /// Queries taking longer are slow, `SLOW_QUERY_MS` or 500ms.
+    pub(crate) slow_query: Duration,
+}
+
+impl PoolSettings {
+    /// Parses the settings `lookup` finds by their variable name.
+    fn parse(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, DatabaseConnectionError> {
+        let connections = "a number of connections";
+        let milliseconds = "a number of milliseconds";
+        let level = "a log level or off";
+
+        Ok(PoolSettings {
+            size: setting(&lookup, "DB_POOL_SIZE", connections)?.unwrap_or(5),
+            read_size: setting(&lookup, "DB_READ_POOL_SIZE", connections)?.unwrap_or(3),
+            statement_timeout: Duration::from_millis(
+                setting(&lookup, "STATEMENT_TIMEOUT_MS", milliseconds)?.unwrap_or(10_000),
+            ),
+            log_level: setting(&lookup, "SQLX_LOG_LEVEL", level)?.unwrap_or(LevelFilter::Off),
+            slow_log_level: setting(&lookup, "SQLX_SLOW_LOG_LEVEL", level)?
+                .unwrap_or(LevelFilter::Off),
+            slow_query: Duration::from_millis(
+                setting(&lookup, "SLOW_QUERY_MS", milliseconds)?.unwrap_or(500),
+            ),
+        })
+    }
+}
+
+/// The setting `name` parsed, `None` when unset.
+fn setting<T: FromStr>(
+    lookup: &impl Fn(&str) -> Option<String>,
+    name: &'static str,
+    expected: &'static str,
+) -> Result<Option<T>, DatabaseConnectionError> {
+    lookup(name)
+        .map(|value| {
+            value.parse().map_err(|_| DatabaseConnectionError::InvalidSetting(name, expected))
+        })
+        .transpose()
+}
+
+/// The pool settings, from the environment unless `configure_db` was called
+/// first, a malformed one fails every connection with `InvalidSetting`.
+pub(crate) fn settings() -> Result<&'static PoolSettings, DatabaseConnectionError> {
+    if let Some(settings) = SETTINGS.get() {
+        return Ok(settings);
+    }
+
+    let settings = PoolSettings::parse(|name| var(name).ok())?;
+
+    Ok(SETTINGS.get_or_init(|| settings))
+}
+
+/// Reads the pool settings through `lookup`, such as the server's
+/// configuration, so a malformed one fails startup instead of the
+/// first query. Without it they're read from the environment.
+pub fn configure_db(
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<(), DatabaseConnectionError> {
+    let settings = PoolSettings::parse(lookup)?;
+
+    // Pools already opened keep the settings they were opened with.
+    let _ = SETTINGS.set(settings);
+
+    Ok(())
 }
 
-/// This obtains a database connection from the `CONNECTION` oncelock
+/// This obtains a database connection from the `CONNECTION` lock
 /// or creates a new connection and stores it there.
 ///
 /// This is not to be used directly, prefer `db!()` instead.
-pub async fn get_db_connection<'r>() -> Result<&'r Pool<Postgres>, DatabaseConnectionError> {
-    if let Some(connection) = CONNECTION.get() {
-        return Ok(connection);
+pub async fn get_db_connection() -> Result<Pool<Postgres>, DatabaseConnectionError> {
+    if let Some(connection) = &*CONNECTION.read().unwrap_or_else(|error| error.into_inner()) {
+        return Ok(connection.clone());
     }
 
-    let pool = PgPoolOptions::new() //
-        .max_connections(5)
-        .connect(env!("DATABASE_URL"))
-        .await?;
+    let pool = pool_options(settings()?.size)?.connect_with(connect_options()?).await?;
+
+    let mut connection = CONNECTION.write().unwrap_or_else(|error| error.into_inner());
+
+    // Another request may have rebuilt the pool while this one was connecting.
+    Ok(connection.get_or_insert(pool).clone())
+}
+
+/// The pool for serving lookups, kept apart from the main one so a burst
+/// of downloads can't take the connections uploads and logins need.
+///
+/// Sized by `DB_READ_POOL_SIZE`, `0` shares the main pool instead.
+///
+/// This is not to be used directly, prefer `db_read!()` instead.
+pub async fn get_read_connection() -> Result<Pool<Postgres>, DatabaseConnectionError> {
+    let connection = READ_CONNECTION.read().unwrap_or_else(|error| error.into_inner()).clone();
+
+    match connection {
+        Some(Some(connection)) => return Ok(connection),
+        Some(None) => return get_db_connection().await,
+        None => {},
+    }
+
+    let size = settings()?.read_size;
+    let main = get_db_connection().await?;
+
+    let pool = match size {
+        0 => None,
+        size => Some(pool_options(size)?.connect_with(connect_options()?).await?),
+    };
+
+    let mut connection = READ_CONNECTION.write().unwrap_or_else(|error| error.into_inner());
+
+    Ok(connection.get_or_insert(pool).clone().unwrap_or(main))
+}
+
+/// Options shared by both pools, each connection gets the statement timeout.
+fn pool_options(size: u32) -> Result<PgPoolOptions, DatabaseConnectionError> {
+    let timeout = settings()?.statement_timeout.as_millis();
+
+    // A runaway query would otherwise hold its connection and starve the pool.
+    Ok(PgPoolOptions::new() //
+        .max_connections(size)
+        .after_connect(move |connection, _| {
+            Box::pin(async move {
+                connection.execute(&*format!("SET statement_timeout = {timeout}")).await?;
+                Ok(())
+            })
+        }))
+}
+
+/// The connection options with sqlx's own statement logging, which goes
+/// through the crate logger under the `sqlx::query` target.
+///
+/// Every statement is logged at `SQLX_LOG_LEVEL` and those slower than
+/// `SLOW_QUERY_MS` at `SQLX_SLOW_LOG_LEVEL`, both off by default since
+/// they include the SQL and model queries are already timed without it.
+fn connect_options() -> Result<PgConnectOptions, DatabaseConnectionError> {
+    let settings = settings()?;
+
+    Ok(PgConnectOptions::from_str(env!("DATABASE_URL"))?
+        .log_statements(settings.log_level)
+        .log_slow_statements(settings.slow_log_level, settings.slow_query))
+}
+
+/// Begins a transaction whose statements may run for `timeout` rather
+/// than the pool default, for maintenance that's known to be slow.
+pub async fn with_timeout(timeout: Duration) -> ModelResult<Transaction<'static, Postgres>> {
+    let mut transaction = get_db_connection().await?.begin().await?;
 
-    Migrator::new(Path::new(env!("DATABASE_MIGRATIONS"))) //
-        .await?
-        .run(&pool)
-        .await?;
+    transaction.execute(&*format!("SET LOCAL statement_timeout = {}", timeout.as_millis())).await?;
 
-    Ok(CONNECTION.get_or_init(|| pool))
+    Ok(transaction)
+}
+
+/// Connects eagerly instead of on the first query,
+/// so an unreachable database is noticed at startup.
+pub async fn open_db_connection() -> Result<(), DatabaseConnectionError> {
+    get_db_connection().await.map(|_| ())
+}
+
+/// Migrates the schema to the newest migration this binary was built
+/// with, only run by the server at startup while writes are refused.
+pub async fn migrate_db() -> Result<(), DatabaseConnectionError> {
+    let migrator = Migrator::new(Path::new(env!("DATABASE_MIGRATIONS"))).await?;
+    migrator.run(&get_db_connection().await?).await?;
+
+    set_schema_version(migrator.iter().map(|migration| migration.version).max().unwrap_or(0));
+
+    Ok(())
+}
+
+/// Closes the pools, waiting for checked out connections to be returned,
+/// a later query connects again.
+pub async fn close_db_connection() {
+    let read = READ_CONNECTION.write().unwrap_or_else(|error| error.into_inner()).take();
+    let main = CONNECTION.write().unwrap_or_else(|error| error.into_inner()).take();
+
+    for pool in [read.flatten(), main].into_iter().flatten() {
+        pool.close().await;
+    }
+}
+
+/// Checks the pools in the background and replaces those whose
+/// connections were lost, called when a query failed that way.
+///
+/// The pool a query ran on isn't known here, so each one is pinged
+/// and only one that fails is closed, the next query connecting again.
+pub fn reset_db_connection() {
+    let Ok(runtime) = Handle::try_current() else {
+        return;
+    };
+
+    if PROBING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    runtime.spawn(async {
+        let main = CONNECTION.read().unwrap_or_else(|error| error.into_inner()).clone();
+        let read = READ_CONNECTION.read().unwrap_or_else(|error| error.into_inner()).clone();
+
+        if let Some(pool) = main
+            && is_lost(&pool).await
+        {
+            {
+                let mut connection = CONNECTION.write().unwrap_or_else(|error| error.into_inner());
+
+                if connection.as_ref().is_some_and(|current| same_pool(current, &pool)) {
+                    connection.take();
+                    warn!("Database connection lost, reconnecting on the next query");
+                }
+            }
+
+            pool.close().await;
+        }
+
+        if let Some(Some(pool)) = read
+            && is_lost(&pool).await
+        {
+            {
+                let mut connection =
+                    READ_CONNECTION.write().unwrap_or_else(|error| error.into_inner());
+
+                if let Some(Some(current)) = &*connection
+                    && same_pool(current, &pool)
+                {
+                    connection.take();
+                    warn!("Database read connection lost, reconnecting on the next query");
+                }
+            }
+
+            pool.close().await;
+        }
+
+        PROBING.store(false, Ordering::Release);
+    });
+}
+
+/// Whether a connection of `pool` can't reach the database anymore,
+/// a pool that's merely busy isn't counted as lost.
+async fn is_lost(pool: &Pool<Postgres>) -> bool {
+    match pool.acquire().await {
+        Ok(mut connection) => connection.ping().await.is_err(),
+        Err(error) => is_connection_error(&error),
+    }
+}
+
+/// Whether two handles are to the same pool, each pool
+/// is opened with its own copy of the connect options.
+fn same_pool(left: &Pool<Postgres>, right: &Pool<Postgres>) -> bool {
+    Arc::ptr_eq(&left.connect_options(), &right.connect_options())
+}
+
+/// Whether `error` means the connection itself is unusable, rather
+/// than the query being wrong, such as when Postgres restarted.
+pub fn is_connection_error(error: &SqlxError) -> bool {
+    match error {
+        SqlxError::Io(_)
+        | SqlxError::Tls(_)
+        | SqlxError::Protocol(_)
+        | SqlxError::WorkerCrashed => true,
+        // Class 08 are connection exceptions, 57P01 to 57P03 a server shutting down.
+        SqlxError::Database(error) => error.code().is_some_and(|code| {
+            code.starts_with("08") || matches!(&*code, "57P01" | "57P02" | "57P03")
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use sqlx::query;
+    use tokio::time::sleep;
+
+    use super::*;
+    use crate::DatabaseError;
+    use crate::testing::run;
+
+    #[test]
+    fn malformed_settings_are_an_error_rather_than_a_panic() {
+        let settings = HashMap::from([("DB_POOL_SIZE", "five")]);
+        let error = PoolSettings::parse(|name| settings.get(name).map(|value| value.to_string()))
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "DB_POOL_SIZE must be a number of connections");
+    }
+
+    #[test]
+    fn unset_settings_are_defaulted() {
+        let settings = PoolSettings::parse(|_| None).unwrap();
+
+        assert_eq!((settings.size, settings.read_size), (5, 3));
+        assert_eq!(settings.statement_timeout, Duration::from_secs(10));
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn a_lost_pool_is_replaced_and_queries_recover() {
+        run(async {
+            // Connecting to a socket that doesn't exist fails like a database
+            // that went away, without the retries a refused connection gets.
+            let lost = pool_options(1)
+                .unwrap()
+                .acquire_timeout(Duration::from_secs(2))
+                .connect_lazy_with(connect_options().unwrap().socket("/nonexistent"));
+            *CONNECTION.write().unwrap() = Some(lost.clone());
+
+            let error =
+                query("SELECT 1").execute(&get_db_connection().await.unwrap()).await.unwrap_err();
+            assert!(is_connection_error(&error));
+
+            // As every model query does with its error, which starts the probe.
+            let _ = DatabaseError::from(error);
+
+            for _ in 0..50 {
+                if lost.is_closed() {
+                    break;
+                }
+
+                sleep(Duration::from_millis(100)).await;
+            }
+
+            assert!(lost.is_closed());
+            let pool = get_db_connection().await.unwrap();
+            assert!(!same_pool(&pool, &lost));
+            query("SELECT 1").execute(&pool).await.unwrap();
+        })
+    }
 }
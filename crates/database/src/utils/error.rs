@@ -2,14 +2,14 @@ use actix_error_proc::ActixError;
 use sqlx::Error as SqlxError;
 use thiserror::Error as ThisError;
 
-use super::connection::DatabaseConnectionError;
+use super::connection::{DatabaseConnectionError, is_connection_error, reset_db_connection};
 
 pub type ModelResult<T> = Result<T, DatabaseError>;
 
 #[derive(ThisError, Debug, ActixError)]
 pub enum DatabaseError {
     #[error("{0:#}")]
-    DatabaseQuery(#[from] SqlxError),
+    DatabaseQuery(SqlxError),
 
     #[error("{0:#}")]
     DatabaseConnectionError(#[from] DatabaseConnectionError),
@@ -17,4 +17,64 @@ pub enum DatabaseError {
     #[http_status(NotFound)]
     #[error("No {0} found with that query.")]
     ModelNotFound(&'static str),
+
+    #[http_status(Conflict)]
+    #[error("The last admin can't be demoted.")]
+    LastAdmin,
+
+    /// A file already exists at the path in its bucket.
+    #[http_status(Conflict)]
+    #[error("A file already exists at that path.")]
+    PathTaken(SqlxError),
+
+    /// The hostname was verified for another user.
+    #[http_status(Conflict)]
+    #[error("The hostname was already verified by another user.")]
+    HostnameTaken,
+
+    /// Advancing a migration phase would break binaries still running.
+    #[http_status(Conflict)]
+    #[error("The migration phase can't be advanced, {0}.")]
+    PhaseRefused(String),
+
+    /// The statement ran past its `statement_timeout` and was canceled.
+    #[http_status(GatewayTimeout)]
+    #[error("The query took too long and was canceled.")]
+    QueryTimeout(SqlxError),
+
+    /// A blob lost more references than it had, meaning
+    /// the counts drifted and should be checked.
+    #[error("A blob reference count would become negative.")]
+    BlobRefUnderflow(SqlxError),
+}
+
+/// Every failed query goes through here, so a lost
+/// connection is noticed by whichever query hits it first.
+impl From<SqlxError> for DatabaseError {
+    fn from(error: SqlxError) -> Self {
+        if is_connection_error(&error) {
+            reset_db_connection();
+        }
+
+        let constraint = error.as_database_error().and_then(|error| error.constraint());
+
+        match constraint {
+            Some("blobs_ref_count_non_negative") => return DatabaseError::BlobRefUnderflow(error),
+            Some("objects_bucket_id_path_key") => return DatabaseError::PathTaken(error),
+            Some("tenant_domains_verified_hostname") => return DatabaseError::HostnameTaken,
+            _ => {},
+        }
+
+        // 57014 is query_canceled, raised when the statement timeout runs out.
+        let timeout = error
+            .as_database_error()
+            .and_then(|error| error.code())
+            .is_some_and(|code| code == "57014");
+
+        if timeout {
+            return DatabaseError::QueryTimeout(error);
+        }
+
+        DatabaseError::DatabaseQuery(error)
+    }
 }
@@ -0,0 +1,48 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use super::connection::settings;
+
+/// Queries taking longer than `SLOW_QUERY_MS` are logged, 500ms by default,
+/// which is also used while the settings are malformed since queries fail then.
+pub(crate) fn slow_query() -> Duration {
+    settings().map_or(Duration::from_millis(500), |settings| settings.slow_query)
+}
+
+/// Times model queries, slow ones are logged under a static label
+/// such as `file.get_owned`, never with their SQL or bound values.
+pub trait Timed: Future + Sized {
+    fn timed(self, label: &'static str) -> TimedQuery<Self> {
+        TimedQuery {
+            query: Box::pin(self),
+            label,
+            started: None,
+        }
+    }
+}
+
+impl<F: Future> Timed for F {}
+
+pub struct TimedQuery<F> {
+    query: Pin<Box<F>>,
+    label: &'static str,
+    started: Option<Instant>,
+}
+
+impl<F: Future> Future for TimedQuery<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let output = self.query.as_mut().poll(context);
+
+        if output.is_ready() && started.elapsed() > slow_query() {
+            warn!("Slow query {} took {}ms", self.label, started.elapsed().as_millis());
+        }
+
+        output
+    }
+}
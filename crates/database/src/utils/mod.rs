@@ -1,2 +1,3 @@
 pub mod connection;
 pub mod error;
+pub mod instrument;
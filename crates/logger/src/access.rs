@@ -0,0 +1,215 @@
+//! Access log sink kept apart from the application logs.
+//!
+//! Records are written one per line either in the Combined Log Format,
+//! extended with the request id, user id and duration, or as JSON objects.
+//!
+//! # Usage
+//! ```no_run
+//! use logger::access::{AccessFormat, AccessLog, AccessTarget};
+//!
+//! AccessLog::init(AccessTarget::parse("file:/var/log/cdn/access.log"), AccessFormat::Combined)
+//!     .unwrap();
+//! ```
+
+use std::fmt::Write as FmtWrite;
+use std::fs::{File, OpenOptions, rename};
+use std::io::{Result, Write, stdout};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, Local};
+
+static ACCESS_LOG: OnceLock<AccessLog> = OnceLock::new();
+
+/// Size after which the access log file is rotated.
+const ROTATE_AFTER: u64 = 64 * 1024 * 1024;
+
+/// Where access records are written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessTarget {
+    Stdout,
+    /// A file rotated into `<path>.1` once it grows past 64MiB.
+    File(PathBuf),
+}
+
+impl AccessTarget {
+    /// Parses `stdout` or `file:<path>`, anything else is treated as a path.
+    pub fn parse(target: &str) -> Self {
+        match target {
+            "stdout" | "" => AccessTarget::Stdout,
+            target => AccessTarget::File(target.strip_prefix("file:").unwrap_or(target).into()),
+        }
+    }
+}
+
+/// How every access record is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessFormat {
+    Combined,
+    Json,
+}
+
+impl AccessFormat {
+    pub fn parse(format: &str) -> Self {
+        match format.to_ascii_lowercase().as_str() {
+            "json" => AccessFormat::Json,
+            _ => AccessFormat::Combined,
+        }
+    }
+}
+
+/// A single served request.
+pub struct AccessRecord {
+    pub remote_addr: String,
+    pub user_id: Option<i64>,
+    pub time: DateTime<Local>,
+    pub method: String,
+    pub path: String,
+    pub protocol: String,
+    pub status: u16,
+    pub bytes_sent: u64,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: String,
+    pub duration_ms: u128,
+}
+
+impl AccessRecord {
+    /// Combined Log Format followed by the request id, user id and duration.
+    pub fn to_combined(&self) -> String {
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {} {}ms",
+            self.remote_addr,
+            self.user_id.map(|id| id.to_string()).unwrap_or_else(|| "-".into()),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            self.path,
+            self.protocol,
+            self.status,
+            self.bytes_sent,
+            self.referer.as_deref().unwrap_or("-").replace('"', "\\\""),
+            self.user_agent.as_deref().unwrap_or("-").replace('"', "\\\""),
+            self.request_id,
+            self.duration_ms
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+
+        let _ = write!(json, "\"remote_addr\":{},", json_string(&self.remote_addr));
+        let _ = match self.user_id {
+            Some(id) => write!(json, "\"user_id\":{id},"),
+            None => write!(json, "\"user_id\":null,"),
+        };
+        let _ = write!(json, "\"time\":{},", json_string(&self.time.to_rfc3339()));
+        let _ = write!(json, "\"method\":{},", json_string(&self.method));
+        let _ = write!(json, "\"path\":{},", json_string(&self.path));
+        let _ = write!(json, "\"protocol\":{},", json_string(&self.protocol));
+        let _ = write!(json, "\"status\":{},", self.status);
+        let _ = write!(json, "\"bytes_sent\":{},", self.bytes_sent);
+        let _ = write!(json, "\"referer\":{},", json_optional(self.referer.as_deref()));
+        let _ = write!(json, "\"user_agent\":{},", json_optional(self.user_agent.as_deref()));
+        let _ = write!(json, "\"request_id\":{},", json_string(&self.request_id));
+        let _ = write!(json, "\"duration_ms\":{}", self.duration_ms);
+
+        json.push('}');
+        json
+    }
+}
+
+/// The process wide access log sink.
+pub struct AccessLog {
+    format: AccessFormat,
+    writer: Mutex<AccessWriter>,
+}
+
+enum AccessWriter {
+    Stdout,
+    File { path: PathBuf, file: File, written: u64 },
+}
+
+impl AccessLog {
+    /// Opens the sink, only the first call has any effect.
+    pub fn init(target: AccessTarget, format: AccessFormat) -> Result<()> {
+        if ACCESS_LOG.get().is_some() {
+            return Ok(());
+        }
+
+        let writer = match target {
+            AccessTarget::Stdout => AccessWriter::Stdout,
+            AccessTarget::File(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(&path)?;
+                let written = file.metadata()?.len();
+                AccessWriter::File { path, file, written }
+            },
+        };
+
+        let _ = ACCESS_LOG.set(AccessLog { format, writer: Mutex::new(writer) });
+        Ok(())
+    }
+
+    /// Writes a record to the sink, records are dropped
+    /// when it was never initialized.
+    pub fn log(record: &AccessRecord) {
+        let Some(access_log) = ACCESS_LOG.get() else {
+            return;
+        };
+
+        let line = match access_log.format {
+            AccessFormat::Combined => record.to_combined(),
+            AccessFormat::Json => record.to_json(),
+        };
+
+        let mut writer = access_log.writer.lock().unwrap_or_else(|error| error.into_inner());
+        let _ = writer.write_line(&line);
+    }
+}
+
+impl AccessWriter {
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        match self {
+            AccessWriter::Stdout => writeln!(stdout().lock(), "{line}"),
+            AccessWriter::File { path, file, written } => {
+                if *written >= ROTATE_AFTER {
+                    let mut rotated = path.clone().into_os_string();
+                    rotated.push(".1");
+
+                    rename(&*path, rotated)?;
+                    *file = OpenOptions::new().create(true).append(true).open(&*path)?;
+                    *written = 0;
+                }
+
+                writeln!(file, "{line}")?;
+                *written += line.len() as u64 + 1;
+                Ok(())
+            },
+        }
+    }
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+
+    for char in value.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            char if char.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", char as u32);
+            },
+            char => escaped.push(char),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+fn json_optional(value: Option<&str>) -> String {
+    value.map(json_string).unwrap_or_else(|| "null".into())
+}
@@ -35,6 +35,8 @@
 //! - Requires terminal with ANSI escape code support
 //! - Uses CSI (Control Sequence Introducer) sequences
 //! - Reset code (`\x1b[0m`) automatically applied after each styled value
+//! - Chained colors join the same style stack, and resets inside a styled
+//!   value re-apply the outer styles, so nesting never ends a style early
 
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
@@ -48,7 +50,7 @@ use std::fmt::{Display, Formatter, Result as FmtResult};
 /// | Variant     | Escape Sequence          | Description               |
 /// |-------------|--------------------------|---------------------------|
 /// | `Reset`     | `\x1b[0m`                | Reset all styles          |
-/// | `Red`       | `\x1b[38;2;235;111;146m` | RGB(235, 111, 146) text   |
+/// | `Red`       | `\x1b[38;2;255;85;85m`   | RGB(255, 85, 85) text     |
 /// | `Bold`      | `\x1b[1m`                | Increased intensity/bold  |
 /// | `Underline` | `\x1b[4m`                | Underlined text           |
 ///
@@ -61,28 +63,28 @@ pub enum AnsiCode {
     /// ANSI: `\x1b[0m`
     Reset,
 
-    /// Red text (RGB: 235, 111, 146)
-    /// ANSI: `\x1b[38;2;235;111;146m`
+    /// Red text (RGB: 255, 85, 85)
+    /// ANSI: `\x1b[38;2;255;85;85m`
     Red,
 
-    /// Green text (RGB: 49, 116, 143)
-    /// ANSI: `\x1b[38;2;49;116;143m`
+    /// Green text (RGB: 80, 250, 123)
+    /// ANSI: `\x1b[38;2;80;250;123m`
     Green,
 
-    /// Yellow text (RGB: 246, 193, 119)
-    /// ANSI: `\x1b[38;2;246;193;119m`
+    /// Yellow text (RGB: 241, 250, 140)
+    /// ANSI: `\x1b[38;2;241;250;140m`
     Yellow,
 
-    /// Purple text (RGB: 196, 167, 231)
-    /// ANSI: `\x1b[38;2;196;167;231m`
+    /// Purple text (RGB: 189, 147, 249)
+    /// ANSI: `\x1b[38;2;189;147;249m`
     Purple,
 
-    /// Cyan text (RGB: 156, 207, 216)
-    /// ANSI: `\x1b[38;2;156;207;216m`
+    /// Cyan text (RGB: 139, 233, 253)
+    /// ANSI: `\x1b[38;2;139;233;253m`
     Cyan,
 
-    /// Gray text (RGB: 144, 140, 170)
-    /// ANSI: `\x1b[38;2;144;140;170m`
+    /// Gray text (RGB: 136, 136, 136)
+    /// ANSI: `\x1b[38;2;136;136;136m`
     Gray,
 
     /// Bold/bright text style
@@ -119,6 +121,27 @@ impl Display for AnsiCode {
     }
 }
 
+/// Generates color methods on `StyledText` pushing onto its style stack
+///
+/// # Macro Expansion Example
+/// ```ignore
+/// stack_method!(red, AnsiCode::Red);
+/// expands to:
+/// pub fn red(mut self) -> Self {
+///     self.styles.push(AnsiCode::Red);
+///     self
+/// }
+/// ```
+macro_rules! stack_method {
+    ($method:ident, $code:expr) => {
+        #[inline]
+        pub fn $method(mut self) -> Self {
+            self.styles.push($code);
+            self
+        }
+    };
+}
+
 /// Composable text styling container
 ///
 /// # Type Parameters
@@ -129,6 +152,7 @@ impl Display for AnsiCode {
 /// - Collects styling codes in insertion order
 /// - Applies all styles when formatted
 /// - Automatically resets styles after value
+/// - Colors chained onto it join its stack instead of wrapping it
 pub struct StyledText<T: Display> {
     /// Original displayable value being styled
     value: T,
@@ -150,7 +174,7 @@ impl<T: Display> StyledText<T> {
     /// - Sets `reset` to `AnsiCode::Reset`
     ///
     /// # Example
-    /// ```
+    /// ```ignore
     /// use logger::colors::{StyledText, AnsiCode};
     /// let styled = StyledText::new("Hello", AnsiCode::Red);
     /// ```
@@ -171,7 +195,7 @@ impl<T: Display> StyledText<T> {
     ///
     /// # Example
     /// ```
-    /// use crate::helpers::misc::colors::{Colorize, AnsiCode};
+    /// use logger::colors::Colorize;
     /// let text = "Alert".red().bold();
     /// ```
     pub fn bold(mut self) -> Self {
@@ -187,13 +211,31 @@ impl<T: Display> StyledText<T> {
     ///
     /// # Example
     /// ```
-    /// use crate::helpers::misc::colors::{Colorize, AnsiCode};
-    /// let text = "URL".blue().underline();
+    /// use logger::colors::Colorize;
+    /// let text = "URL".cyan().underline();
     /// ```
     pub fn underline(mut self) -> Self {
         self.styles.push(AnsiCode::Underline);
         self
     }
+
+    // Inherent methods win over `Colorize`, so a chained color is
+    // pushed here rather than wrapping this text in another one.
+    stack_method!(red, AnsiCode::Red);
+    stack_method!(green, AnsiCode::Green);
+    stack_method!(yellow, AnsiCode::Yellow);
+    stack_method!(purple, AnsiCode::Purple);
+    stack_method!(cyan, AnsiCode::Cyan);
+    stack_method!(gray, AnsiCode::Gray);
+
+    /// Writes every style of the stack in insertion order
+    fn write_styles(&self, f: &mut Formatter<'_>) -> FmtResult {
+        for style in &self.styles {
+            write!(f, "{}", style)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl<T: Display> Display for StyledText<T> {
@@ -201,25 +243,61 @@ impl<T: Display> Display for StyledText<T> {
     ///
     /// # Formatting Steps
     /// 1. Applies all stored ANSI codes in insertion order
-    /// 2. Writes original value
+    /// 2. Writes original value, re-applying the styles after any reset
+    ///    it contains and dropping a reset it ends with
     /// 3. Applies reset code
     ///
     /// # Error Handling
     /// Propagates any formatting errors from underlying writes
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        // Apply all accumulated styles
-        for style in &self.styles {
-            write!(f, "{}", style)?;
-        }
+        let reset = self.reset.to_string();
+        let value = self.value.to_string();
+        let value = value.strip_suffix(&reset).unwrap_or(&value);
+
+        self.write_styles(f)?;
 
-        // Write original value
-        write!(f, "{}", self.value)?;
+        // Already styled values would otherwise end the outer styles early
+        for (index, part) in value.split(&reset).enumerate() {
+            if index > 0 {
+                write!(f, "{}", self.reset)?;
+                self.write_styles(f)?;
+            }
+
+            write!(f, "{}", part)?;
+        }
 
-        // Reset styling
         write!(f, "{}", self.reset)
     }
 }
 
+/// Width of `text` as shown on a terminal, ignoring ANSI escape sequences
+///
+/// # Measuring Process
+/// - Skips every CSI sequence, from `\x1b[` up to its final byte
+/// - Counts the remaining characters, one column each
+///
+/// # Example
+/// ```
+/// use logger::colors::{Colorize, visible_width};
+/// assert_eq!(visible_width(&"INFO".green().to_string()), 4);
+/// ```
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        if char == '\x1b' && chars.peek() == Some(&'[') {
+            // Parameters and intermediates precede the final byte in `@`..=`~`
+            chars.by_ref().skip(1).find(|char| ('@'..='~').contains(char));
+            continue;
+        }
+
+        width += 1;
+    }
+
+    width
+}
+
 /// Generates color styling methods for the Colorize trait
 ///
 /// # Macro Expansion Example
@@ -263,3 +341,79 @@ pub trait Colorize: Sized + Display {
 
 /// Applies color styling methods to all Display implementers
 impl<T: Display> Colorize for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ansi_codes_display_their_escape_sequences() {
+        let cases = [
+            (AnsiCode::Reset, "\x1b[0m"),
+            (AnsiCode::Red, "\x1b[38;2;255;85;85m"),
+            (AnsiCode::Green, "\x1b[38;2;80;250;123m"),
+            (AnsiCode::Yellow, "\x1b[38;2;241;250;140m"),
+            (AnsiCode::Purple, "\x1b[38;2;189;147;249m"),
+            (AnsiCode::Cyan, "\x1b[38;2;139;233;253m"),
+            (AnsiCode::Gray, "\x1b[38;2;136;136;136m"),
+            (AnsiCode::Bold, "\x1b[1m"),
+            (AnsiCode::Underline, "\x1b[4m"),
+        ];
+
+        for (code, expected) in cases {
+            assert_eq!(code.to_string(), expected, "{code:?}");
+        }
+    }
+
+    #[test]
+    fn styled_text_applies_codes_in_order() {
+        assert_eq!(
+            "Warning".yellow().bold().underline().to_string(),
+            "\x1b[38;2;241;250;140m\x1b[1m\x1b[4mWarning\x1b[0m"
+        );
+        assert_eq!(
+            42.5.cyan().underline().bold().to_string(),
+            "\x1b[38;2;139;233;253m\x1b[4m\x1b[1m42.5\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn styled_text_resets_once() {
+        let text = "Error".red().bold().to_string();
+
+        assert_eq!(text.matches("\x1b[0m").count(), 1);
+        assert!(text.ends_with("Error\x1b[0m"));
+    }
+
+    #[test]
+    fn chained_colors_join_one_stack() {
+        assert_eq!(
+            "Error".red().bold().gray().to_string(),
+            "\x1b[38;2;255;85;85m\x1b[1m\x1b[38;2;136;136;136mError\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn nested_styled_text_resets_once() {
+        let text = "Error".red().to_string().cyan().bold().to_string();
+
+        assert_eq!(text, "\x1b[38;2;139;233;253m\x1b[1m\x1b[38;2;255;85;85mError\x1b[0m");
+        assert_eq!(text.matches("\x1b[0m").count(), 1);
+    }
+
+    #[test]
+    fn inner_resets_restore_outer_styles() {
+        let inner = format!("{} failed", "upload".red());
+
+        assert_eq!(
+            inner.yellow().to_string(),
+            "\x1b[38;2;241;250;140m\x1b[38;2;255;85;85mupload\x1b[0m\x1b[38;2;241;250;140m failed\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn visible_width_ignores_escape_sequences() {
+        assert_eq!(visible_width(&"INFO".green().bold().to_string()), 4);
+        assert_eq!(visible_width("plain"), 5);
+    }
+}
@@ -1,12 +1,40 @@
 use std::fmt::format;
 use std::io::{Result, Write};
+use std::sync::OnceLock;
 
+use chrono_tz::Tz;
 use flexi_logger::DeferredNow;
 use log::Record;
 
-use crate::colors::Colorize;
+use crate::access::json_string;
+use crate::colors::{Colorize, visible_width};
 
+pub mod access;
 pub mod colors;
+pub mod outputs;
+
+/// Columns the level label is padded to, so messages line up.
+const LEVEL_WIDTH: usize = 5;
+
+/// Layout of the timestamp leading every line.
+const TIME_FORMAT: &str = "%H:%M:%S %Y-%m-%d";
+
+/// Zone timestamps are rendered in, the local time of the host when unset.
+static TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Renders every later timestamp in `timezone` rather than the local
+/// time of the host, only the first zone set is kept.
+pub fn set_timezone(timezone: Tz) {
+    let _ = TIMEZONE.set(timezone);
+}
+
+/// Formats the time of the record in the configured zone.
+fn timestamp(now: &mut DeferredNow, format: &str) -> String {
+    match TIMEZONE.get() {
+        Some(timezone) => now.now().with_timezone(timezone).format(format).to_string(),
+        None => now.format(format).to_string(),
+    }
+}
 
 /// Formats a log record and writes it to the provided writer.
 ///
@@ -43,18 +71,50 @@ pub fn format_log(w: &mut dyn Write, now: &mut DeferredNow, record: &Record) ->
         log::Level::Info => "INFO".green(),
         log::Level::Debug => "DEBUG".purple(),
         log::Level::Trace => "TRACE".cyan(),
-    };
+    }
+    .to_string();
+
+    // The escape codes take bytes but no columns, so the
+    // padding is computed from the visible width of the label
+    let padding = LEVEL_WIDTH.saturating_sub(visible_width(&level));
 
     // Write the formatted log message to the writer
     write!(
         w,
         // Format: [HH:MM:SS YYYY-MM-DD LEVEL] > message
-        "[{} {}] \u{203A} {}",
-        // Current local time formatted as HH:MM:SS YYYY-MM-DD and colored gray
-        now.format("%H:%M:%S %Y-%m-%d").to_string().gray(),
+        "[{} {}{:padding$}] \u{203A} {}",
+        // Current time formatted as HH:MM:SS YYYY-MM-DD and colored gray
+        timestamp(now, TIME_FORMAT).gray(),
         // Colored log level
         level,
+        // Padding up to the widest level label
+        "",
         // Log message formatted and colored gray
         format(*record.args()).gray()
     )
 }
+
+/// Formats a log record like `format_log` but without colors,
+/// meant for outputs that aren't a terminal such as files.
+pub fn format_log_plain(w: &mut dyn Write, now: &mut DeferredNow, record: &Record) -> Result<()> {
+    write!(
+        w,
+        "[{} {:<LEVEL_WIDTH$}] \u{203A} {}",
+        timestamp(now, TIME_FORMAT),
+        record.level(),
+        record.args()
+    )
+}
+
+/// Formats a log record as a single line JSON object,
+/// meant for outputs read by log collectors.
+pub fn format_log_json(w: &mut dyn Write, now: &mut DeferredNow, record: &Record) -> Result<()> {
+    write!(
+        w,
+        "{{\"time\":{},\"level\":{},\"target\":{},\"message\":{}}}",
+        json_string(&timestamp(now, "%+")),
+        json_string(record.level().as_str()),
+        json_string(record.target()),
+        json_string(&format(*record.args()))
+    )
+}
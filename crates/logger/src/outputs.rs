@@ -0,0 +1,247 @@
+//! Application log outputs, stdout is always written to and a
+//! rotating file and syslog can be added on top of it.
+//!
+//! # Usage
+//! ```no_run
+//! use logger::outputs::{FileFormat, LogOutputs};
+//!
+//! let _handle = LogOutputs {
+//!     file: Some("/var/log/cdn/server.log".into()),
+//!     file_format: FileFormat::Json,
+//!     syslog: true,
+//!     timezone: Some("Europe/Paris".into()),
+//! }
+//! .start("info")
+//! .unwrap();
+//! ```
+
+use std::path::PathBuf;
+
+use chrono_tz::Tz;
+use flexi_logger::writers::{SyslogConnection, SyslogFacility, SyslogLineHeader, SyslogWriter};
+use flexi_logger::{
+    Cleanup, Criterion, Duplicate, FileSpec, FlexiLoggerError, FormatFunction, LogSpecification,
+    Logger, LoggerHandle, Naming,
+};
+use log::warn;
+
+use crate::{format_log, format_log_json, format_log_plain, set_timezone};
+
+/// Size after which the log file is rotated.
+const ROTATE_AFTER: u64 = 64 * 1024 * 1024;
+
+/// Rotated log files kept around.
+const KEEP_ROTATED: usize = 7;
+
+/// How records are laid out in the log file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Plain,
+    Json,
+}
+
+impl FileFormat {
+    pub fn parse(format: &str) -> Self {
+        match format.to_ascii_lowercase().as_str() {
+            "json" => FileFormat::Json,
+            _ => FileFormat::Plain,
+        }
+    }
+
+    fn function(self) -> FormatFunction {
+        match self {
+            FileFormat::Plain => format_log_plain,
+            FileFormat::Json => format_log_json,
+        }
+    }
+}
+
+/// Every output records are written to at the same time.
+pub struct LogOutputs {
+    /// A file rotated once it grows past 64MiB.
+    pub file: Option<PathBuf>,
+    pub file_format: FileFormat,
+    /// Sends plain records to the local syslog daemon.
+    pub syslog: bool,
+    /// IANA zone timestamps are rendered in, the local time of the host when
+    /// unset or unknown, containers often run in UTC unlike their operators.
+    pub timezone: Option<String>,
+}
+
+impl LogOutputs {
+    /// Starts logging records matching the `spec` log specification,
+    /// the returned handle must be kept alive for as long as logging.
+    pub fn start(&self, spec: &str) -> Result<LoggerHandle, FlexiLoggerError> {
+        let handle = self.logger(spec)?.start()?;
+
+        if let Some(name) = self.unknown_timezone() {
+            warn!("Logging in local time, {name} isn't a known IANA time zone");
+        }
+
+        Ok(handle)
+    }
+
+    /// Like `start`, but a misconfigured `spec` or output falls back
+    /// to plain stderr logging at `info` instead of failing.
+    ///
+    /// Returns `None` only when not even stderr logging could start.
+    pub fn start_or_fallback(&self, spec: &str) -> Option<LoggerHandle> {
+        let error = match self.start(spec) {
+            Ok(handle) => return Some(handle),
+            Err(error) => error,
+        };
+
+        let handle = Logger::with(LogSpecification::info())
+            .format(format_log_plain)
+            .log_to_stderr()
+            .start()
+            .ok();
+
+        warn!("Logging to stderr only, the configured logging failed to start: {error}");
+        handle
+    }
+
+    /// The logger writing to every configured output, not started yet.
+    fn logger(&self, spec: &str) -> Result<Logger, FlexiLoggerError> {
+        if let Some(timezone) = self.timezone.as_deref().and_then(|name| name.parse().ok()) {
+            set_timezone(timezone);
+        }
+
+        let logger = Logger::try_with_str(spec)?.format_for_stdout(format_log);
+
+        // flexi_logger has a single primary target, stdout is
+        // duplicated onto it whenever a file or syslog is configured.
+        Ok(match (&self.file, self.syslog) {
+            (None, false) => logger.log_to_stdout(),
+            (Some(file), false) => self.file_logger(logger).log_to_file(FileSpec::try_from(file)?),
+            (None, true) => logger
+                .format_for_writer(format_log_plain)
+                .log_to_writer(syslog_writer()?)
+                .duplicate_to_stdout(Duplicate::All),
+            (Some(file), true) => self
+                .file_logger(logger)
+                .format_for_writer(format_log_plain)
+                .log_to_file_and_writer(FileSpec::try_from(file)?, syslog_writer()?),
+        })
+    }
+
+    fn unknown_timezone(&self) -> Option<&str> {
+        self.timezone.as_deref().filter(|name| name.parse::<Tz>().is_err())
+    }
+
+    fn file_logger(&self, logger: Logger) -> Logger {
+        logger
+            .format_for_files(self.file_format.function())
+            .rotate(
+                Criterion::Size(ROTATE_AFTER),
+                Naming::Numbers,
+                Cleanup::KeepLogFiles(KEEP_ROTATED),
+            )
+            .append()
+            .duplicate_to_stdout(Duplicate::All)
+    }
+}
+
+fn syslog_writer() -> Result<Box<SyslogWriter>, FlexiLoggerError> {
+    let connection = SyslogConnection::try_datagram("/dev/log")?;
+
+    Ok(SyslogWriter::builder(connection, SyslogLineHeader::Rfc3164, SyslogFacility::LocalUse0)
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs::{read_dir, read_to_string, remove_dir_all};
+    use std::process::id;
+
+    use log::{Level, LevelFilter, Record, max_level};
+
+    use super::*;
+
+    /// Logs one record of each level through the built logger
+    /// writing to a file in its own directory, returns the file.
+    fn log_to_file(name: &str, file_format: FileFormat, spec: &str) -> String {
+        let dir = temp_dir().join(format!("logger-{name}-{}", id()));
+        let outputs = LogOutputs {
+            file: Some(dir.join("server.log")),
+            file_format,
+            syslog: false,
+            timezone: Some("Europe/Paris".into()),
+        };
+
+        let (logger, _handle) = outputs.logger(spec).unwrap().build().unwrap();
+
+        for level in [Level::Debug, Level::Info, Level::Warn] {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target("cdn::outputs")
+                    .args(format_args!("a \"{level}\" record"))
+                    .build(),
+            );
+        }
+        logger.flush();
+
+        let mut files = read_dir(&dir).unwrap().map(|entry| entry.unwrap().path());
+        let written = read_to_string(files.next().unwrap()).unwrap();
+
+        assert!(files.next().is_none());
+        remove_dir_all(dir).unwrap();
+
+        written
+    }
+
+    #[test]
+    fn files_receive_json_records_past_the_spec() {
+        let written = log_to_file("json", FileFormat::Json, "info");
+        let lines = written.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"time\":\""));
+        assert!(lines[0].ends_with(
+            "\"level\":\"INFO\",\"target\":\"cdn::outputs\",\"message\":\"a \\\"INFO\\\" record\"}"
+        ));
+        assert!(lines[1].contains("\"level\":\"WARN\""));
+    }
+
+    #[test]
+    fn files_receive_plain_records_without_colors() {
+        let written = log_to_file("plain", FileFormat::Plain, "debug");
+
+        assert_eq!(written.lines().count(), 3);
+        assert!(!written.contains('\x1b'));
+        assert!(written.lines().all(|line| line.starts_with('[') && line.ends_with("\" record")));
+    }
+
+    #[test]
+    fn unknown_timezones_are_reported() {
+        let outputs = |timezone: &str| LogOutputs {
+            file: None,
+            file_format: FileFormat::Plain,
+            syslog: false,
+            timezone: Some(timezone.into()),
+        };
+
+        assert_eq!(outputs("Mars/Olympus").unknown_timezone(), Some("Mars/Olympus"));
+        assert_eq!(outputs("Europe/Paris").unknown_timezone(), None);
+    }
+
+    // The only test starting the global logger, the others build theirs.
+    #[test]
+    fn malformed_specs_fall_back_to_stderr_at_info() {
+        let outputs = LogOutputs {
+            file: None,
+            file_format: FileFormat::Plain,
+            syslog: false,
+            timezone: None,
+        };
+
+        assert!(outputs.start("trace,cdn=loud").is_err());
+
+        let handle = outputs.start_or_fallback("trace,cdn=loud");
+
+        assert!(handle.is_some());
+        assert_eq!(max_level(), LevelFilter::Info);
+    }
+}
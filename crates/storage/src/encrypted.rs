@@ -0,0 +1,606 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::{Storage, StorageError, StorageResult};
+
+/// Leads every encrypted blob, blobs without it were stored in plaintext
+/// before encryption was enabled and are read as they are.
+const MAGIC: &[u8; 8] = b"CDNENC01";
+
+/// Bytes of the head `looks_sealed` needs to tell.
+pub const SEALED_HEAD_LENGTH: usize = MAGIC.len();
+
+/// Whether content starting with `head` would be read as an encrypted
+/// blob, plaintext written in place must never start like one.
+pub fn looks_sealed(head: &[u8]) -> bool {
+    head.starts_with(MAGIC)
+}
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+/// Random part of the frame nonces, followed by the
+/// frame counter and a flag set on the last frame.
+const PREFIX_LENGTH: usize = 7;
+
+/// Magic, key id, key nonce, wrapped data key, frame size and nonce prefix.
+const HEADER_LENGTH: usize =
+    MAGIC.len() + 4 + NONCE_LENGTH + KEY_LENGTH + TAG_LENGTH + 4 + PREFIX_LENGTH;
+
+/// Plaintext bytes per frame, a range is read from the frame holding its
+/// start so at most this much is decrypted and thrown away.
+const FRAME_SIZE: usize = 64 * 1024;
+
+/// The key data keys are wrapped with, identified by the
+/// start of its SHA-256 so blobs tell which key they need.
+#[derive(Clone)]
+pub struct MasterKey {
+    id: u32,
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    pub fn new(key: [u8; KEY_LENGTH]) -> Self {
+        let digest = Sha256::digest(key);
+        let id = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+
+        Self {
+            id,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        }
+    }
+
+    /// Reads a key given as 64 hex digits.
+    pub fn from_hex(hex: &str) -> StorageResult<Self> {
+        let hex = hex.trim().as_bytes();
+
+        if hex.len() != KEY_LENGTH * 2 {
+            return Err(StorageError::Backend("A master key must be 64 hex digits".into()));
+        }
+
+        let mut key = [0; KEY_LENGTH];
+
+        for (byte, pair) in key.iter_mut().zip(hex.chunks(2)) {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+
+            *byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| StorageError::Backend("A master key must be 64 hex digits".into()))?;
+        }
+
+        Ok(Self::new(key))
+    }
+
+    /// Reads a key file holding either the 32 raw bytes or 64 hex digits.
+    pub async fn from_file(path: impl AsRef<Path>) -> StorageResult<Self> {
+        let content = fs::read(path).await?;
+
+        match <[u8; KEY_LENGTH]>::try_from(content.as_slice()) {
+            Ok(key) => Ok(Self::new(key)),
+            Err(_) => Self::from_hex(&String::from_utf8_lossy(&content)),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// The fields leading an encrypted blob.
+struct Header {
+    key_id: u32,
+    key_nonce: [u8; NONCE_LENGTH],
+    wrapped_key: [u8; KEY_LENGTH + TAG_LENGTH],
+    frame_size: usize,
+    prefix: [u8; PREFIX_LENGTH],
+}
+
+impl Header {
+    fn parse(stored: &[u8]) -> Option<Self> {
+        let header = stored.get(..HEADER_LENGTH)?.strip_prefix(MAGIC)?;
+        let (key_id, header) = header.split_first_chunk::<4>()?;
+        let (key_nonce, header) = header.split_first_chunk()?;
+        let (wrapped_key, header) = header.split_first_chunk()?;
+        let (frame_size, header) = header.split_first_chunk::<4>()?;
+        let (prefix, _) = header.split_first_chunk()?;
+
+        if *frame_size == [0; 4] {
+            return None;
+        }
+
+        Some(Self {
+            key_id: u32::from_be_bytes(*key_id),
+            key_nonce: *key_nonce,
+            wrapped_key: *wrapped_key,
+            frame_size: u32::from_be_bytes(*frame_size) as usize,
+            prefix: *prefix,
+        })
+    }
+
+    fn write(&self, stored: &mut Vec<u8>) {
+        stored.extend_from_slice(MAGIC);
+        stored.extend_from_slice(&self.key_id.to_be_bytes());
+        stored.extend_from_slice(&self.key_nonce);
+        stored.extend_from_slice(&self.wrapped_key);
+        stored.extend_from_slice(&(self.frame_size as u32).to_be_bytes());
+        stored.extend_from_slice(&self.prefix);
+    }
+
+    /// Frames the blob was cut in, an empty blob still has one.
+    fn frames(&self, stored_length: usize) -> usize {
+        (stored_length - HEADER_LENGTH).div_ceil(self.frame_size + TAG_LENGTH).max(1)
+    }
+
+    fn plaintext_size(&self, stored_length: usize) -> usize {
+        let frames = self.frames(stored_length);
+        (stored_length - HEADER_LENGTH).saturating_sub(frames * TAG_LENGTH)
+    }
+}
+
+/// Encrypts blobs with AES-256-GCM before they reach the wrapped backend.
+///
+/// Every blob gets its own data key, wrapped by the master key and stored
+/// in a header along with the key id, the content follows in fixed size
+/// frames sealed on their own so ranges don't need the whole blob.
+///
+/// Callers only ever see plaintext, blobs stored before encryption
+/// was enabled are told apart by the header magic and read as is.
+/// Without a current key blobs are written in plaintext, those already
+/// sealed stay readable with the previous keys.
+#[derive(Clone)]
+pub struct Encrypted<S> {
+    inner: S,
+    key: Option<MasterKey>,
+    /// Keys blobs written before a rotation may still be wrapped with.
+    previous: Vec<MasterKey>,
+}
+
+impl<S: Storage + Sync> Encrypted<S> {
+    pub fn new(inner: S, key: MasterKey, previous: Vec<MasterKey>) -> Self {
+        Self { inner, key: Some(key), previous }
+    }
+
+    /// Writes plaintext but still reads the blobs sealed with `previous`.
+    pub fn disabled(inner: S, previous: Vec<MasterKey>) -> Self {
+        Self { inner, key: None, previous }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Wraps the data key of a blob with the current master key, the
+    /// content is copied as it is so nothing is encrypted again.
+    ///
+    /// Returns whether the blob was rewritten, plaintext blobs and
+    /// blobs already on the current key are left alone.
+    pub async fn rewrap(&self, key: &str) -> StorageResult<bool> {
+        let Some(current) = &self.key else {
+            return Ok(false);
+        };
+
+        let mut stored = self.inner.get(key).await?;
+
+        let Some(mut header) = Header::parse(&stored) else {
+            return Ok(false);
+        };
+
+        if header.key_id == current.id {
+            return Ok(false);
+        }
+
+        let data_key = self
+            .master_key(header.key_id)?
+            .cipher
+            .decrypt(Nonce::from_slice(&header.key_nonce), header.wrapped_key.as_slice())
+            .map_err(|_| StorageError::Decryption(key.to_owned()))?;
+
+        let (key_nonce, wrapped_key) = wrap(current, &data_key)?;
+        header.key_id = current.id;
+        header.key_nonce = key_nonce;
+        header.wrapped_key = wrapped_key;
+
+        let mut rewrapped = Vec::with_capacity(HEADER_LENGTH);
+        header.write(&mut rewrapped);
+        stored.splice(..HEADER_LENGTH, rewrapped);
+
+        self.inner.put(key, &stored).await?;
+
+        Ok(true)
+    }
+
+    /// Seals `data` with the current key, or leaves it as is without one.
+    fn encrypt<'a>(&self, data: &'a [u8]) -> StorageResult<Cow<'a, [u8]>> {
+        let Some(current) = &self.key else {
+            return Ok(Cow::Borrowed(data));
+        };
+
+        let mut data_key = [0; KEY_LENGTH];
+        let mut prefix = [0; PREFIX_LENGTH];
+        rand::rng().fill_bytes(&mut data_key);
+        rand::rng().fill_bytes(&mut prefix);
+
+        let (key_nonce, wrapped_key) = wrap(current, &data_key)?;
+
+        let header = Header {
+            key_id: current.id,
+            key_nonce,
+            wrapped_key,
+            frame_size: FRAME_SIZE,
+            prefix,
+        };
+
+        let frames = data.len().div_ceil(FRAME_SIZE).max(1);
+        let mut stored = Vec::with_capacity(HEADER_LENGTH + data.len() + frames * TAG_LENGTH);
+        header.write(&mut stored);
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key));
+
+        for index in 0..frames {
+            let frame = data.get(index * FRAME_SIZE..).unwrap_or_default();
+            let frame = &frame[..frame.len().min(FRAME_SIZE)];
+            let nonce = frame_nonce(&prefix, index, index + 1 == frames);
+
+            let sealed = cipher
+                .encrypt(Nonce::from_slice(&nonce), frame)
+                .map_err(|_| StorageError::Backend("A frame couldn't be encrypted".into()))?;
+
+            stored.extend_from_slice(&sealed);
+        }
+
+        Ok(Cow::Owned(stored))
+    }
+
+    fn decrypt(&self, key: &str, stored: Vec<u8>) -> StorageResult<Vec<u8>> {
+        let Some(header) = Header::parse(&stored) else {
+            return Ok(stored);
+        };
+
+        let cipher = self.data_key(key, &header)?;
+        let frames = header.frames(stored.len());
+        let mut plaintext = Vec::with_capacity(plaintext_size(&stored) as usize);
+
+        for index in 0..frames {
+            plaintext.extend(decrypt_frame(key, &cipher, &header, &stored, index)?);
+        }
+
+        Ok(plaintext)
+    }
+
+    /// Unwraps the data key of a blob, failing when its master key isn't
+    /// loaded or doesn't open it, which is what a wrong key looks like.
+    fn data_key(&self, key: &str, header: &Header) -> StorageResult<Aes256Gcm> {
+        let data_key = self
+            .master_key(header.key_id)?
+            .cipher
+            .decrypt(Nonce::from_slice(&header.key_nonce), header.wrapped_key.as_slice())
+            .map_err(|_| StorageError::Decryption(key.to_owned()))?;
+
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key)))
+    }
+
+    fn master_key(&self, id: u32) -> StorageResult<&MasterKey> {
+        self.key
+            .iter()
+            .chain(&self.previous)
+            .find(|key| key.id == id)
+            .ok_or(StorageError::UnknownKey(id))
+    }
+}
+
+/// Size of the plaintext of a stored blob, plaintext blobs report their own.
+pub fn plaintext_size(stored: &[u8]) -> u64 {
+    match Header::parse(stored) {
+        Some(header) => header.plaintext_size(stored.len()) as u64,
+        None => stored.len() as u64,
+    }
+}
+
+/// Wraps a data key with `master`, under a nonce of its own.
+fn wrap(
+    master: &MasterKey,
+    data_key: &[u8],
+) -> StorageResult<([u8; NONCE_LENGTH], [u8; KEY_LENGTH + TAG_LENGTH])> {
+    let mut nonce = [0; NONCE_LENGTH];
+    rand::rng().fill_bytes(&mut nonce);
+
+    let wrapped = master
+        .cipher
+        .encrypt(Nonce::from_slice(&nonce), data_key)
+        .ok()
+        .and_then(|wrapped| <[u8; KEY_LENGTH + TAG_LENGTH]>::try_from(wrapped).ok())
+        .ok_or_else(|| StorageError::Backend("A data key couldn't be wrapped".into()))?;
+
+    Ok((nonce, wrapped))
+}
+
+/// Opens frame `index` of a blob, the flag in the nonce of the
+/// last one makes a truncated blob fail instead of reading short.
+fn decrypt_frame(
+    key: &str,
+    cipher: &Aes256Gcm,
+    header: &Header,
+    stored: &[u8],
+    index: usize,
+) -> StorageResult<Vec<u8>> {
+    let frames = header.frames(stored.len());
+    let sealed_size = header.frame_size + TAG_LENGTH;
+    let start = HEADER_LENGTH + index * sealed_size;
+    let end = (start + sealed_size).min(stored.len());
+
+    let sealed = stored.get(start..end).ok_or_else(|| StorageError::Decryption(key.to_owned()))?;
+
+    open_frame(key, cipher, header, sealed, index, index + 1 == frames)
+}
+
+/// Opens the sealed frame `index`, `last` as the blob it was read from tells.
+fn open_frame(
+    key: &str,
+    cipher: &Aes256Gcm,
+    header: &Header,
+    sealed: &[u8],
+    index: usize,
+    last: bool,
+) -> StorageResult<Vec<u8>> {
+    let nonce = frame_nonce(&header.prefix, index, last);
+
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), sealed)
+        .map_err(|_| StorageError::Decryption(key.to_owned()))
+}
+
+fn frame_nonce(prefix: &[u8; PREFIX_LENGTH], index: usize, last: bool) -> [u8; NONCE_LENGTH] {
+    let mut nonce = [0; NONCE_LENGTH];
+    nonce[..PREFIX_LENGTH].copy_from_slice(prefix);
+    nonce[PREFIX_LENGTH..NONCE_LENGTH - 1].copy_from_slice(&(index as u32).to_be_bytes());
+    nonce[NONCE_LENGTH - 1] = u8::from(last);
+    nonce
+}
+
+impl<S: Storage + Sync> Storage for Encrypted<S> {
+    async fn get(&self, key: &str) -> StorageResult<Vec<u8>> {
+        let stored = self.inner.get(key).await?;
+        self.decrypt(key, stored)
+    }
+
+    /// Only the header and the frames the range overlaps are read
+    /// and decrypted, the range is cut at the end of the plaintext.
+    async fn get_range(&self, key: &str, range: Range<u64>) -> StorageResult<Vec<u8>> {
+        let stored_size = self.inner.size(key).await? as usize;
+        let head = self.inner.get_range(key, 0..HEADER_LENGTH as u64).await?;
+
+        let Some(header) = Header::parse(&head) else {
+            return self.inner.get_range(key, range).await;
+        };
+
+        let size = header.plaintext_size(stored_size);
+        let (start, end) = ((range.start as usize).min(size), (range.end as usize).min(size));
+
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let cipher = self.data_key(key, &header)?;
+        let frames = header.frames(stored_size);
+        let first = start / header.frame_size;
+        let last = (end - 1) / header.frame_size;
+
+        let sealed_size = header.frame_size + TAG_LENGTH;
+        let offset = HEADER_LENGTH + first * sealed_size;
+        let sealed = self
+            .inner
+            .get_range(key, offset as u64..(HEADER_LENGTH + (last + 1) * sealed_size) as u64)
+            .await?;
+
+        let mut plaintext = Vec::with_capacity(end - start);
+
+        for (index, frame) in (first..=last).zip(sealed.chunks(sealed_size)) {
+            plaintext.extend(open_frame(key, &cipher, &header, frame, index, index + 1 == frames)?);
+        }
+
+        let skipped = first * header.frame_size;
+
+        plaintext
+            .get(start - skipped..end - skipped)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| StorageError::Decryption(key.to_owned()))
+    }
+
+    async fn size(&self, key: &str) -> StorageResult<u64> {
+        let stored_size = self.inner.size(key).await?;
+        let head = self.inner.get_range(key, 0..HEADER_LENGTH as u64).await?;
+
+        Ok(match Header::parse(&head) {
+            Some(header) => header.plaintext_size(stored_size as usize) as u64,
+            None => stored_size,
+        })
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) -> StorageResult<()> {
+        self.inner.put(key, &self.encrypt(data)?).await
+    }
+
+    async fn put_if_absent(&self, key: &str, data: &[u8]) -> StorageResult<bool> {
+        if self.inner.exists(key).await? {
+            return Ok(false);
+        }
+
+        self.inner.put_if_absent(key, &self.encrypt(data)?).await
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn exists(&self, key: &str) -> StorageResult<bool> {
+        self.inner.exists(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Blobs kept in memory, so what reaches the backend can be altered.
+    #[derive(Default)]
+    struct Memory {
+        blobs: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl Memory {
+        fn stored(&self, key: &str) -> Vec<u8> {
+            self.blobs.lock().unwrap()[key].clone()
+        }
+
+        fn replace(&self, key: &str, data: Vec<u8>) {
+            self.blobs.lock().unwrap().insert(key.to_owned(), data);
+        }
+    }
+
+    impl Storage for &Memory {
+        async fn get(&self, key: &str) -> StorageResult<Vec<u8>> {
+            self.blobs
+                .lock()
+                .unwrap()
+                .get(key)
+                .cloned()
+                .ok_or_else(|| StorageError::NotFound(key.to_owned()))
+        }
+
+        async fn get_range(&self, key: &str, range: Range<u64>) -> StorageResult<Vec<u8>> {
+            let blob = self.get(key).await?;
+            let end = (range.end as usize).min(blob.len());
+            let start = (range.start as usize).min(end);
+            Ok(blob[start..end].to_vec())
+        }
+
+        async fn size(&self, key: &str) -> StorageResult<u64> {
+            Ok(self.get(key).await?.len() as u64)
+        }
+
+        async fn put(&self, key: &str, data: &[u8]) -> StorageResult<()> {
+            self.replace(key, data.to_vec());
+            Ok(())
+        }
+
+        async fn put_if_absent(&self, key: &str, data: &[u8]) -> StorageResult<bool> {
+            let mut blobs = self.blobs.lock().unwrap();
+            let absent = !blobs.contains_key(key);
+
+            if absent {
+                blobs.insert(key.to_owned(), data.to_vec());
+            }
+
+            Ok(absent)
+        }
+
+        async fn delete(&self, key: &str) -> StorageResult<()> {
+            self.blobs.lock().unwrap().remove(key);
+            Ok(())
+        }
+
+        async fn exists(&self, key: &str) -> StorageResult<bool> {
+            Ok(self.blobs.lock().unwrap().contains_key(key))
+        }
+    }
+
+    /// Spans three frames, the last one partial.
+    fn content() -> Vec<u8> {
+        (0..FRAME_SIZE * 2 + 1000).map(|index| (index % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn blobs_round_trip() {
+        let memory = Memory::default();
+        let storage = Encrypted::new(&memory, MasterKey::new([1; KEY_LENGTH]), Vec::new());
+        let content = content();
+
+        storage.put("blob", &content).await.unwrap();
+
+        let stored = memory.stored("blob");
+        assert!(stored.starts_with(MAGIC));
+        assert!(!stored.windows(64).any(|window| window == &content[..64]));
+
+        assert_eq!(storage.get("blob").await.unwrap(), content);
+        assert_eq!(storage.size("blob").await.unwrap(), content.len() as u64);
+
+        // Across the boundary of the first two frames.
+        let range = FRAME_SIZE as u64 - 10..FRAME_SIZE as u64 + 10;
+        let expected = &content[range.start as usize..range.end as usize];
+        assert_eq!(storage.get_range("blob", range).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn tampered_ciphertext_is_rejected() {
+        let memory = Memory::default();
+        let storage = Encrypted::new(&memory, MasterKey::new([1; KEY_LENGTH]), Vec::new());
+        storage.put("blob", &content()).await.unwrap();
+
+        let mut stored = memory.stored("blob");
+        stored[HEADER_LENGTH + 5] ^= 1;
+        memory.replace("blob", stored);
+
+        assert!(matches!(storage.get("blob").await, Err(StorageError::Decryption(_))));
+        assert!(matches!(storage.get_range("blob", 0..10).await, Err(StorageError::Decryption(_))));
+    }
+
+    #[tokio::test]
+    async fn truncated_blobs_are_rejected() {
+        let memory = Memory::default();
+        let storage = Encrypted::new(&memory, MasterKey::new([1; KEY_LENGTH]), Vec::new());
+        storage.put("blob", &content()).await.unwrap();
+
+        // Drops the last frame, the one before isn't sealed as the last.
+        let mut stored = memory.stored("blob");
+        stored.truncate(HEADER_LENGTH + 2 * (FRAME_SIZE + TAG_LENGTH));
+        memory.replace("blob", stored);
+
+        assert!(matches!(storage.get("blob").await, Err(StorageError::Decryption(_))));
+    }
+
+    #[tokio::test]
+    async fn blobs_need_the_key_they_were_sealed_with() {
+        let memory = Memory::default();
+        let writer = Encrypted::new(&memory, MasterKey::new([1; KEY_LENGTH]), Vec::new());
+        writer.put("blob", b"secret").await.unwrap();
+
+        let reader = Encrypted::new(&memory, MasterKey::new([2; KEY_LENGTH]), Vec::new());
+        assert!(matches!(reader.get("blob").await, Err(StorageError::UnknownKey(_))));
+
+        let rotated = Encrypted::new(
+            &memory,
+            MasterKey::new([2; KEY_LENGTH]),
+            vec![MasterKey::new([1; KEY_LENGTH])],
+        );
+        assert_eq!(rotated.get("blob").await.unwrap(), b"secret");
+
+        assert!(rotated.rewrap("blob").await.unwrap());
+        assert!(!rotated.rewrap("blob").await.unwrap());
+        assert_eq!(reader.get("blob").await.unwrap(), b"secret");
+    }
+
+    #[tokio::test]
+    async fn disabled_encryption_still_reads_sealed_blobs() {
+        let memory = Memory::default();
+        let writer = Encrypted::new(&memory, MasterKey::new([1; KEY_LENGTH]), Vec::new());
+        writer.put("sealed", b"secret").await.unwrap();
+
+        let storage = Encrypted::disabled(&memory, vec![MasterKey::new([1; KEY_LENGTH])]);
+        storage.put("plain", b"public").await.unwrap();
+
+        assert_eq!(memory.stored("plain"), b"public");
+        assert_eq!(storage.get("plain").await.unwrap(), b"public");
+        assert_eq!(storage.get("sealed").await.unwrap(), b"secret");
+        assert!(!storage.rewrap("sealed").await.unwrap());
+    }
+}
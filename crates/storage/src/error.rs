@@ -0,0 +1,75 @@
+use std::io::{Error as IoError, ErrorKind};
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{HttpResponse, ResponseError};
+use thiserror::Error as ThisError;
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+#[derive(ThisError, Debug)]
+pub enum StorageError {
+    #[error("{0:#}")]
+    Io(#[from] IoError),
+
+    #[error("No blob found with key {0}")]
+    NotFound(String),
+
+    #[error("The storage backend is throttling requests")]
+    Throttled,
+
+    #[error("The storage backend did not answer in time")]
+    Timeout,
+
+    #[error("The storage backend is unavailable")]
+    CircuitOpen(Duration),
+
+    #[error("{0}")]
+    Backend(String),
+
+    #[error("The blob {0} couldn't be decrypted, it was altered or the key is wrong")]
+    Decryption(String),
+
+    #[error("No master key with id {0:08x} is loaded")]
+    UnknownKey(u32),
+}
+
+impl StorageError {
+    /// Whether repeating the same operation may succeed.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            StorageError::Throttled | StorageError::Timeout => true,
+            StorageError::Io(error) => matches!(
+                error.kind(),
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::Interrupted
+                    | ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl ResponseError for StorageError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            StorageError::NotFound(_) => StatusCode::NOT_FOUND,
+            StorageError::Throttled | StorageError::Timeout | StorageError::CircuitOpen(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            },
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+
+        if let StorageError::CircuitOpen(retry_after) = self {
+            response.insert_header((RETRY_AFTER, retry_after.as_secs().max(1)));
+        }
+
+        response.body(self.to_string())
+    }
+}
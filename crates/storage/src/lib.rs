@@ -0,0 +1,47 @@
+use std::future::Future;
+use std::ops::Range;
+
+mod encrypted;
+mod error;
+mod local;
+mod multipart;
+mod resilient;
+
+pub use encrypted::*;
+pub use error::*;
+pub use local::*;
+pub use multipart::*;
+pub use resilient::*;
+
+/// A blob storage backend addressed by string keys.
+///
+/// Every operation but `put` is safe to repeat, `put_if_absent`
+/// is the conditional variant used when a write has to be retried.
+pub trait Storage {
+    fn get(&self, key: &str) -> impl Future<Output = StorageResult<Vec<u8>>> + Send;
+
+    /// Reads `range` of a blob without the rest of it,
+    /// the range is cut at the end of the blob.
+    fn get_range(
+        &self,
+        key: &str,
+        range: Range<u64>,
+    ) -> impl Future<Output = StorageResult<Vec<u8>>> + Send;
+
+    /// The size of a blob, as `get` would read it.
+    fn size(&self, key: &str) -> impl Future<Output = StorageResult<u64>> + Send;
+
+    fn put(&self, key: &str, data: &[u8]) -> impl Future<Output = StorageResult<()>> + Send;
+
+    /// Writes the blob only if nothing is stored under `key` yet,
+    /// returns whether the write happened.
+    fn put_if_absent(
+        &self,
+        key: &str,
+        data: &[u8],
+    ) -> impl Future<Output = StorageResult<bool>> + Send;
+
+    fn delete(&self, key: &str) -> impl Future<Output = StorageResult<()>> + Send;
+
+    fn exists(&self, key: &str) -> impl Future<Output = StorageResult<bool>> + Send;
+}
@@ -0,0 +1,343 @@
+use std::io::{ErrorKind, Result as IoResult, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use log::warn;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::{Storage, StorageError, StorageResult};
+
+/// Suffix marking blobs still being written, these
+/// never live at a final path so readers can't see partial blobs.
+const TEMP_SUFFIX: &str = ".partial";
+
+/// A blob found while listing the storage.
+pub struct StoredBlob {
+    pub key: String,
+    pub size: u64,
+    pub modified_at: SystemTime,
+}
+
+/// Stores blobs as files under a root directory.
+///
+/// Writes go to a temporary file next to the final path and are
+/// renamed into place, which is atomic within the same filesystem.
+#[derive(Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Opens the storage, removing temporary files left behind
+    /// by writes interrupted by a crash.
+    pub async fn new(root: impl Into<PathBuf>) -> StorageResult<Self> {
+        let storage = Self { root: root.into() };
+
+        fs::create_dir_all(&storage.root).await?;
+        storage.cleanup_temp_files().await?;
+
+        Ok(storage)
+    }
+
+    /// Removes every leftover temporary file under the root.
+    pub async fn cleanup_temp_files(&self) -> StorageResult<usize> {
+        let mut removed = 0;
+        let mut pending = vec![self.root.clone()];
+
+        while let Some(directory) = pending.pop() {
+            let mut entries = fs::read_dir(&directory).await?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                } else if is_temp_file(&path) {
+                    warn!("Removing interrupted blob write {}", path.display());
+                    fs::remove_file(&path).await?;
+                    removed += 1;
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Lists every finished blob under `prefix`, which is empty when nothing
+    /// was ever stored there, writes still in progress are left out.
+    pub async fn list(&self, prefix: &str) -> StorageResult<Vec<StoredBlob>> {
+        let mut blobs = Vec::new();
+        let mut pending = vec![self.path(prefix)?];
+
+        while let Some(directory) = pending.pop() {
+            let mut entries = match fs::read_dir(&directory).await {
+                Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                entries => entries?,
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let metadata = entry.metadata().await?;
+
+                if metadata.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                if is_temp_file(&path) {
+                    continue;
+                }
+
+                let Some(key) = path.strip_prefix(&self.root).ok().and_then(Path::to_str) else {
+                    continue;
+                };
+
+                blobs.push(StoredBlob {
+                    key: key.replace(std::path::MAIN_SEPARATOR, "/"),
+                    size: metadata.len(),
+                    modified_at: metadata.modified()?,
+                });
+            }
+        }
+
+        Ok(blobs)
+    }
+
+    /// When the blob under `key` was last written, or last found
+    /// already stored by a write that would have created it.
+    pub async fn modified_at(&self, key: &str) -> StorageResult<SystemTime> {
+        match fs::metadata(self.path(key)?).await {
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(key.to_owned()))
+            },
+            result => Ok(result?.modified()?),
+        }
+    }
+
+    /// Appends `data` to the blob under `key` recorded as holding `offset`
+    /// bytes, creating it when missing. The blob is opened with `O_APPEND`
+    /// so the content already stored is never read again.
+    ///
+    /// Bytes past `offset` were left by an append that was never recorded,
+    /// they're cut first. Callers hold a lock every node honors, appends
+    /// to the same key would interleave otherwise.
+    pub async fn append_at(&self, key: &str, offset: u64, data: &[u8]) -> StorageResult<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = OpenOptions::new().append(true).create(true).open(&path).await?;
+        let stored = file.metadata().await?.len();
+
+        if stored < offset {
+            return Err(StorageError::Backend(format!(
+                "Blob {key} holds {stored} bytes but {offset} were appended"
+            )));
+        }
+
+        if stored > offset {
+            file.set_len(offset).await?;
+        }
+
+        file.write_all(data).await?;
+        file.sync_data().await?;
+
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> StorageResult<PathBuf> {
+        let relative = Path::new(key);
+
+        let valid = !key.is_empty()
+            && !key.ends_with(TEMP_SUFFIX)
+            && relative.components().all(|component| matches!(component, Component::Normal(_)));
+
+        if !valid {
+            return Err(StorageError::Backend(format!("Invalid blob key {key}")));
+        }
+
+        Ok(self.root.join(relative))
+    }
+
+    /// Writes `data` to a temporary file next to `path`,
+    /// flushed to disk before it's returned.
+    async fn write_temp(&self, path: &Path, data: &[u8]) -> StorageResult<PathBuf> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let temp = temp_path(path);
+
+        if let Err(error) = write_synced(&temp, data).await {
+            let _ = fs::remove_file(&temp).await;
+            return Err(error.into());
+        }
+
+        Ok(temp)
+    }
+}
+
+/// A unique temporary path next to `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".{:016x}{TEMP_SUFFIX}", rand::random::<u64>()));
+    PathBuf::from(temp)
+}
+
+async fn write_synced(path: &Path, data: &[u8]) -> IoResult<()> {
+    let mut file = File::create(path).await?;
+    file.write_all(data).await?;
+    file.sync_all().await
+}
+
+/// Marks an existing blob as just written, so one deduplicated
+/// by an upload starts its orphan grace period over.
+async fn touch(path: &Path) -> IoResult<()> {
+    let file = OpenOptions::new().write(true).open(path).await?.into_std().await;
+    file.set_modified(SystemTime::now())
+}
+
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.ends_with(TEMP_SUFFIX))
+}
+
+impl Storage for LocalStorage {
+    async fn get(&self, key: &str) -> StorageResult<Vec<u8>> {
+        match fs::read(self.path(key)?).await {
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(key.to_owned()))
+            },
+            result => Ok(result?),
+        }
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> StorageResult<Vec<u8>> {
+        let mut file = match File::open(self.path(key)?).await {
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Err(StorageError::NotFound(key.to_owned()));
+            },
+            file => file?,
+        };
+
+        let size = file.metadata().await?.len();
+        let (start, end) = (range.start.min(size), range.end.min(size));
+
+        let mut data = vec![0; end.saturating_sub(start) as usize];
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(&mut data).await?;
+
+        Ok(data)
+    }
+
+    async fn size(&self, key: &str) -> StorageResult<u64> {
+        match fs::metadata(self.path(key)?).await {
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(key.to_owned()))
+            },
+            result => Ok(result?.len()),
+        }
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) -> StorageResult<()> {
+        let path = self.path(key)?;
+        let temp = self.write_temp(&path, data).await?;
+
+        if let Err(error) = fs::rename(&temp, &path).await {
+            let _ = fs::remove_file(&temp).await;
+            return Err(error.into());
+        }
+
+        Ok(())
+    }
+
+    async fn put_if_absent(&self, key: &str, data: &[u8]) -> StorageResult<bool> {
+        let path = self.path(key)?;
+
+        match touch(&path).await {
+            Ok(()) => return Ok(false),
+            Err(error) if error.kind() == ErrorKind::NotFound => {},
+            Err(error) => return Err(error.into()),
+        }
+
+        let temp = self.write_temp(&path, data).await?;
+
+        // Unlike `rename`, a hard link never replaces an existing file,
+        // so concurrent writers can't clobber each other.
+        let linked = fs::hard_link(&temp, &path).await;
+        let _ = fs::remove_file(&temp).await;
+
+        match linked {
+            Ok(()) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                touch(&path).await?;
+                Ok(false)
+            },
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> StorageResult<bool> {
+        Ok(fs::try_exists(self.path(key)?).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn storage() -> LocalStorage {
+        let root = std::env::temp_dir().join(format!("local-{:016x}", rand::random::<u64>()));
+        LocalStorage::new(root).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn interrupted_writes_are_never_read_and_removed_on_open() {
+        let storage = storage().await;
+        storage.put("ab/cd/done", b"done").await.unwrap();
+
+        // What a crash in the middle of `put` leaves behind.
+        let interrupted = temp_path(&storage.path("ab/cd/interrupted").unwrap());
+        fs::write(&interrupted, b"half of it").await.unwrap();
+
+        assert!(!storage.exists("ab/cd/interrupted").await.unwrap());
+        assert!(matches!(storage.get("ab/cd/interrupted").await, Err(StorageError::NotFound(_))));
+
+        let listed = storage.list("ab").await.unwrap();
+        assert_eq!(listed.iter().map(|blob| blob.key.as_str()).collect::<Vec<_>>(), ["ab/cd/done"]);
+
+        let reopened = LocalStorage::new(&storage.root).await.unwrap();
+        assert!(!fs::try_exists(&interrupted).await.unwrap());
+        assert_eq!(reopened.get("ab/cd/done").await.unwrap(), b"done");
+
+        fs::remove_dir_all(&storage.root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn appends_cut_what_was_never_recorded() {
+        let storage = storage().await;
+
+        storage.append_at("ab/cd/log", 0, b"hello").await.unwrap();
+        storage.append_at("ab/cd/log", 5, b" world").await.unwrap();
+        assert_eq!(storage.get("ab/cd/log").await.unwrap(), b"hello world");
+
+        // Bytes past the recorded size were never acknowledged, they're replaced.
+        storage.append_at("ab/cd/log", 5, b"!").await.unwrap();
+        assert_eq!(storage.get("ab/cd/log").await.unwrap(), b"hello!");
+
+        assert!(storage.append_at("ab/cd/log", 100, b"gap").await.is_err());
+        assert_eq!(storage.get("ab/cd/log").await.unwrap(), b"hello!");
+
+        fs::remove_dir_all(&storage.root).await.unwrap();
+    }
+}
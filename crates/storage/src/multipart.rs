@@ -0,0 +1,54 @@
+use std::future::Future;
+
+use crate::StorageResult;
+
+/// A multipart upload the backend still holds parts for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipartUpload {
+    pub key: String,
+    pub upload_id: String,
+}
+
+/// A part accepted by the backend, the tag is what
+/// completing the upload has to reference it by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedPart {
+    pub part_number: i32,
+    pub etag: String,
+}
+
+/// Backends that assemble large blobs from separately uploaded parts,
+/// as S3 does, parts of unfinished uploads are stored and billed
+/// until the upload is either completed or aborted.
+pub trait MultipartStorage {
+    /// Starts an upload for `key`, returning the id its parts are sent under.
+    fn create_multipart(&self, key: &str) -> impl Future<Output = StorageResult<String>> + Send;
+
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        data: &[u8],
+    ) -> impl Future<Output = StorageResult<CompletedPart>> + Send;
+
+    /// Assembles the blob from `parts`, which must be sorted by part number.
+    fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> impl Future<Output = StorageResult<()>> + Send;
+
+    /// Drops every part of the upload, aborting an unknown upload is not an error.
+    fn abort_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> impl Future<Output = StorageResult<()>> + Send;
+
+    /// Every upload the backend considers in progress.
+    fn list_multipart_uploads(
+        &self,
+    ) -> impl Future<Output = StorageResult<Vec<MultipartUpload>>> + Send;
+}
@@ -0,0 +1,553 @@
+use std::future::Future;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use tokio::task_local;
+use tokio::time::{sleep, timeout};
+
+use crate::{Storage, StorageError, StorageResult};
+
+task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Runs `future` with every storage operation it makes, retries
+/// included, bounded by `deadline` as well as the retry budget.
+pub async fn with_deadline<F: Future>(deadline: Instant, future: F) -> F::Output {
+    DEADLINE.scope(deadline, future).await
+}
+
+/// How failed operations are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts per operation, including the first one.
+    pub max_attempts: u32,
+    /// Backoff before the second attempt, doubled on every retry.
+    pub base_delay: Duration,
+    /// Upper bound for a single backoff.
+    pub max_delay: Duration,
+    /// Time an operation may take counting every attempt and backoff.
+    pub total_budget: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            total_budget: Duration::from_secs(10),
+        }
+    }
+}
+
+/// When a circuit breaker opens and how it recovers.
+#[derive(Debug, Clone)]
+pub struct BreakerPolicy {
+    /// Calls observed before the failure rate is evaluated.
+    pub window: u32,
+    /// Failure rate, between 0 and 1, that opens the breaker.
+    pub failure_rate: f32,
+    /// Time the breaker stays open before probing the backend.
+    pub open_for: Duration,
+    /// Probe calls let through while half open.
+    pub probes: u32,
+}
+
+impl Default for BreakerPolicy {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            failure_rate: 0.5,
+            open_for: Duration::from_secs(30),
+            probes: 3,
+        }
+    }
+}
+
+/// The operations tracked by their own circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Get,
+    Put,
+    Delete,
+    Exists,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 4] =
+        [Operation::Get, Operation::Put, Operation::Delete, Operation::Exists];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open(Instant),
+    HalfOpen,
+}
+
+/// What happened to the calls of an operation since startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationMetrics {
+    pub state: BreakerState,
+    /// Calls made to the backend, retries included.
+    pub attempts: u64,
+    pub retries: u64,
+    /// Attempts failing with a retriable error.
+    pub failures: u64,
+    /// Calls refused without reaching the backend while the breaker was open.
+    pub rejected: u64,
+    /// Times the breaker opened.
+    pub opened: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    attempts: AtomicU64,
+    retries: AtomicU64,
+    failures: AtomicU64,
+    rejected: AtomicU64,
+    opened: AtomicU64,
+}
+
+struct Breaker {
+    state: BreakerState,
+    calls: u32,
+    failures: u32,
+    probes: u32,
+}
+
+impl Breaker {
+    const fn new() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            calls: 0,
+            failures: 0,
+            probes: 0,
+        }
+    }
+}
+
+/// Wraps a backend with retries for transient errors
+/// and a circuit breaker per operation type.
+///
+/// Only idempotent operations are retried, a failed `put` is
+/// retried through `put_if_absent` so it never writes twice.
+/// Clones share their breakers and counters.
+#[derive(Clone)]
+pub struct Resilient<S> {
+    inner: S,
+    retry: RetryPolicy,
+    breaker: BreakerPolicy,
+    breakers: Arc<[Mutex<Breaker>; 4]>,
+    counters: Arc<[Counters; 4]>,
+}
+
+impl<S: Storage + Sync> Resilient<S> {
+    pub fn new(inner: S, retry: RetryPolicy, breaker: BreakerPolicy) -> Self {
+        Self {
+            inner,
+            retry,
+            breaker,
+            breakers: Arc::new([const { Mutex::new(Breaker::new()) }; 4]),
+            counters: Default::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// The current state of the breaker for `operation`.
+    pub fn breaker_state(&self, operation: Operation) -> BreakerState {
+        self.breaker(operation).state
+    }
+
+    pub fn metrics(&self, operation: Operation) -> OperationMetrics {
+        let counters = &self.counters[operation as usize];
+
+        OperationMetrics {
+            state: self.breaker_state(operation),
+            attempts: counters.attempts.load(Ordering::Relaxed),
+            retries: counters.retries.load(Ordering::Relaxed),
+            failures: counters.failures.load(Ordering::Relaxed),
+            rejected: counters.rejected.load(Ordering::Relaxed),
+            opened: counters.opened.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Runs `call` until it succeeds, fails with a permanent error
+    /// or the attempts or `deadline` run out.
+    pub async fn run<T, F, Fut>(
+        &self,
+        operation: Operation,
+        deadline: Instant,
+        mut call: F,
+    ) -> StorageResult<T>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = StorageResult<T>>,
+    {
+        let deadline = deadline.min(Instant::now() + self.retry.total_budget);
+        let counters = &self.counters[operation as usize];
+        let mut attempt = 0;
+
+        loop {
+            if let Err(error) = self.admit(operation) {
+                counters.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(error);
+            }
+
+            counters.attempts.fetch_add(1, Ordering::Relaxed);
+            counters.retries.fetch_add(u64::from(attempt > 0), Ordering::Relaxed);
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let result = match timeout(remaining, call(attempt)).await {
+                Ok(result) => result,
+                Err(_) => Err(StorageError::Timeout),
+            };
+
+            // Permanent errors such as a missing blob say nothing
+            // about the backend health, so they don't count as failures.
+            let failed = result.as_ref().is_err_and(StorageError::is_retriable);
+            counters.failures.fetch_add(u64::from(failed), Ordering::Relaxed);
+            self.record(operation, !failed);
+
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            attempt += 1;
+
+            let backoff = self.backoff(attempt);
+            let exhausted = attempt >= self.retry.max_attempts
+                || Instant::now() + backoff >= deadline
+                || !error.is_retriable();
+
+            if exhausted {
+                return Err(error);
+            }
+
+            sleep(backoff).await;
+        }
+    }
+
+    /// Exponential backoff with full jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .retry
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.retry.max_delay);
+
+        ceiling.mul_f64(rand::random::<f64>())
+    }
+
+    fn breaker(&self, operation: Operation) -> MutexGuard<'_, Breaker> {
+        self.breakers[operation as usize].lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn admit(&self, operation: Operation) -> StorageResult<()> {
+        let mut breaker = self.breaker(operation);
+
+        match breaker.state {
+            BreakerState::Closed => Ok(()),
+            BreakerState::Open(until) => {
+                let now = Instant::now();
+
+                if now < until {
+                    return Err(StorageError::CircuitOpen(until - now));
+                }
+
+                info!("Storage breaker for {operation:?} is half open, probing the backend");
+                breaker.state = BreakerState::HalfOpen;
+                breaker.probes = 1;
+                Ok(())
+            },
+            BreakerState::HalfOpen if breaker.probes < self.breaker.probes => {
+                breaker.probes += 1;
+                Ok(())
+            },
+            BreakerState::HalfOpen => Err(StorageError::CircuitOpen(self.breaker.open_for)),
+        }
+    }
+
+    fn record(&self, operation: Operation, success: bool) {
+        let mut breaker = self.breaker(operation);
+
+        match breaker.state {
+            BreakerState::HalfOpen if success => {
+                info!("Storage breaker for {operation:?} closed, the backend recovered");
+                *breaker = Breaker::new();
+            },
+            BreakerState::HalfOpen => {
+                warn!("Storage breaker for {operation:?} reopened, a probe failed");
+                breaker.state = BreakerState::Open(Instant::now() + self.breaker.open_for);
+                self.counters[operation as usize].opened.fetch_add(1, Ordering::Relaxed);
+            },
+            BreakerState::Closed => {
+                breaker.calls += 1;
+                breaker.failures += u32::from(!success);
+
+                if breaker.calls < self.breaker.window {
+                    return;
+                }
+
+                let rate = breaker.failures as f32 / breaker.calls as f32;
+                breaker.calls = 0;
+                breaker.failures = 0;
+
+                if rate >= self.breaker.failure_rate {
+                    warn!("Storage breaker for {operation:?} opened, {:.0}% failed", rate * 100.0);
+                    breaker.state = BreakerState::Open(Instant::now() + self.breaker.open_for);
+                    self.counters[operation as usize].opened.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+            BreakerState::Open(_) => {},
+        }
+    }
+
+    /// The deadline of the caller when it set one with `with_deadline`,
+    /// `run` cuts it to the retry budget either way.
+    fn deadline(&self) -> Instant {
+        DEADLINE
+            .try_with(|deadline| *deadline)
+            .unwrap_or_else(|_| Instant::now() + self.retry.total_budget)
+    }
+}
+
+impl<S: Storage + Sync> Storage for Resilient<S> {
+    async fn get(&self, key: &str) -> StorageResult<Vec<u8>> {
+        self.run(Operation::Get, self.deadline(), |_| self.inner.get(key)).await
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> StorageResult<Vec<u8>> {
+        self.run(Operation::Get, self.deadline(), |_| self.inner.get_range(key, range.clone()))
+            .await
+    }
+
+    async fn size(&self, key: &str) -> StorageResult<u64> {
+        self.run(Operation::Exists, self.deadline(), |_| self.inner.size(key)).await
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) -> StorageResult<()> {
+        self.run(Operation::Put, self.deadline(), |attempt| async move {
+            if attempt == 0 {
+                self.inner.put(key, data).await
+            } else {
+                self.inner.put_if_absent(key, data).await.map(|_| ())
+            }
+        })
+        .await
+    }
+
+    async fn put_if_absent(&self, key: &str, data: &[u8]) -> StorageResult<bool> {
+        self.run(Operation::Put, self.deadline(), |_| self.inner.put_if_absent(key, data)).await
+    }
+
+    async fn delete(&self, key: &str) -> StorageResult<()> {
+        self.run(Operation::Delete, self.deadline(), |_| self.inner.delete(key)).await
+    }
+
+    async fn exists(&self, key: &str) -> StorageResult<bool> {
+        self.run(Operation::Exists, self.deadline(), |_| self.inner.exists(key)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+
+    /// A backend whose reads fail with `Throttled` until `failures` runs out.
+    #[derive(Default)]
+    struct Flaky {
+        failures: AtomicU32,
+        calls: AtomicU32,
+    }
+
+    impl Flaky {
+        fn failing(failures: u32) -> Self {
+            Self {
+                failures: AtomicU32::new(failures),
+                calls: AtomicU32::new(0),
+            }
+        }
+
+        fn calls(&self) -> u32 {
+            self.calls.load(Ordering::Relaxed)
+        }
+
+        fn read(&self) -> StorageResult<Vec<u8>> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+
+            let failing = self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| left.checked_sub(1))
+                .is_ok();
+
+            if failing { Err(StorageError::Throttled) } else { Ok(b"blob".to_vec()) }
+        }
+    }
+
+    impl Storage for &Flaky {
+        async fn get(&self, _: &str) -> StorageResult<Vec<u8>> {
+            self.read()
+        }
+
+        async fn get_range(&self, _: &str, _: Range<u64>) -> StorageResult<Vec<u8>> {
+            self.read()
+        }
+
+        async fn size(&self, _: &str) -> StorageResult<u64> {
+            self.read().map(|blob| blob.len() as u64)
+        }
+
+        async fn put(&self, _: &str, _: &[u8]) -> StorageResult<()> {
+            self.read().map(|_| ())
+        }
+
+        async fn put_if_absent(&self, _: &str, _: &[u8]) -> StorageResult<bool> {
+            self.read().map(|_| true)
+        }
+
+        async fn delete(&self, _: &str) -> StorageResult<()> {
+            self.read().map(|_| ())
+        }
+
+        async fn exists(&self, _: &str) -> StorageResult<bool> {
+            self.read().map(|_| true)
+        }
+    }
+
+    fn retry(max_attempts: u32, total_budget: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            total_budget,
+        }
+    }
+
+    fn breaker(open_for: Duration) -> BreakerPolicy {
+        BreakerPolicy {
+            window: 4,
+            failure_rate: 0.5,
+            open_for,
+            probes: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let backend = Flaky::failing(2);
+        let storage = Resilient::new(
+            &backend,
+            retry(4, Duration::from_secs(5)),
+            breaker(Duration::from_secs(30)),
+        );
+
+        assert_eq!(storage.get("key").await.unwrap(), b"blob");
+        assert_eq!(backend.calls(), 3);
+
+        let metrics = storage.metrics(Operation::Get);
+        assert_eq!((metrics.attempts, metrics.retries, metrics.failures), (3, 2, 2));
+        assert_eq!(metrics.state, BreakerState::Closed);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_when_the_budget_runs_out() {
+        let backend = Flaky::failing(u32::MAX);
+        let storage = Resilient::new(
+            &backend,
+            RetryPolicy {
+                base_delay: Duration::from_millis(20),
+                ..retry(1000, Duration::from_millis(50))
+            },
+            // A breaker that never trips, so only the budget ends the retries.
+            BreakerPolicy {
+                window: u32::MAX,
+                ..BreakerPolicy::default()
+            },
+        );
+
+        let started = Instant::now();
+        assert!(matches!(storage.exists("key").await, Err(StorageError::Throttled)));
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert!(backend.calls() < 1000);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_caller_deadline() {
+        let backend = Flaky::failing(u32::MAX);
+        let storage = Resilient::new(
+            &backend,
+            RetryPolicy {
+                base_delay: Duration::from_millis(20),
+                ..retry(1000, Duration::from_secs(30))
+            },
+            // A breaker that never trips, so only the budget ends the retries.
+            BreakerPolicy {
+                window: u32::MAX,
+                ..BreakerPolicy::default()
+            },
+        );
+
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(50);
+        let result = with_deadline(deadline, storage.exists("key")).await;
+
+        assert!(matches!(result, Err(StorageError::Throttled)));
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn sustained_failures_open_the_breaker() {
+        let backend = Flaky::failing(u32::MAX);
+        let storage = Resilient::new(
+            &backend,
+            retry(1, Duration::from_secs(5)),
+            breaker(Duration::from_secs(30)),
+        );
+
+        for _ in 0..4 {
+            assert!(storage.get("key").await.is_err());
+        }
+
+        assert!(matches!(storage.breaker_state(Operation::Get), BreakerState::Open(_)));
+        assert!(matches!(storage.get("key").await, Err(StorageError::CircuitOpen(_))));
+        assert_eq!(backend.calls(), 4);
+
+        // Breakers are per operation, writes still reach the backend.
+        assert_eq!(storage.breaker_state(Operation::Put), BreakerState::Closed);
+
+        let metrics = storage.metrics(Operation::Get);
+        assert_eq!((metrics.opened, metrics.rejected), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn half_open_probes_close_the_breaker_once_the_backend_recovers() {
+        let backend = Flaky::failing(4);
+        let storage = Resilient::new(
+            &backend,
+            retry(1, Duration::from_secs(5)),
+            breaker(Duration::from_millis(20)),
+        );
+
+        for _ in 0..4 {
+            assert!(storage.get("key").await.is_err());
+        }
+
+        assert!(matches!(storage.breaker_state(Operation::Get), BreakerState::Open(_)));
+
+        sleep(Duration::from_millis(30)).await;
+
+        assert_eq!(storage.get("key").await.unwrap(), b"blob");
+        assert_eq!(storage.breaker_state(Operation::Get), BreakerState::Closed);
+    }
+}
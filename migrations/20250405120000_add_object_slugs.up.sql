@@ -0,0 +1,5 @@
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+ALTER TABLE objects
+    ADD COLUMN IF NOT EXISTS slug TEXT NOT NULL UNIQUE
+    DEFAULT translate(encode(gen_random_bytes(12), 'base64'), '+/', '-_');
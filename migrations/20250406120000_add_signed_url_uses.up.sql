@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS signed_url_uses (
+    jti TEXT PRIMARY KEY,
+    uses INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
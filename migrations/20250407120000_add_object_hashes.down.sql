@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS objects_hash_idx;
+
+ALTER TABLE objects
+    DROP COLUMN IF EXISTS hash,
+    DROP COLUMN IF EXISTS hash_algorithm;
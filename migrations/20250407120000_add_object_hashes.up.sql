@@ -0,0 +1,5 @@
+ALTER TABLE objects
+    ADD COLUMN IF NOT EXISTS hash TEXT,
+    ADD COLUMN IF NOT EXISTS hash_algorithm TEXT NOT NULL DEFAULT 'sha256';
+
+CREATE INDEX IF NOT EXISTS objects_hash_idx ON objects (hash_algorithm, hash);
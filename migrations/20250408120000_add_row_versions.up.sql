@@ -0,0 +1,2 @@
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE buckets ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
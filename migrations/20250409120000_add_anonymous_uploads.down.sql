@@ -0,0 +1,6 @@
+ALTER TABLE objects DROP COLUMN IF EXISTS expires_at;
+
+DELETE FROM objects WHERE bucket_id IN (SELECT id FROM buckets WHERE owner_id IS NULL);
+DELETE FROM buckets WHERE owner_id IS NULL;
+
+ALTER TABLE buckets ALTER COLUMN owner_id SET NOT NULL;
@@ -0,0 +1,6 @@
+ALTER TABLE buckets ALTER COLUMN owner_id DROP NOT NULL;
+
+-- Anonymous uploads are stored in this ownerless bucket.
+INSERT INTO buckets (name) VALUES ('anonymous') ON CONFLICT (name) DO NOTHING;
+
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS expires_at TIMESTAMP;
@@ -0,0 +1,3 @@
+ALTER TABLE objects DROP COLUMN IF EXISTS dominant_color;
+ALTER TABLE objects DROP COLUMN IF EXISTS height;
+ALTER TABLE objects DROP COLUMN IF EXISTS width;
@@ -0,0 +1,4 @@
+-- Computed once at upload so placeholders never decode the image.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS width INTEGER;
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS height INTEGER;
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS dominant_color TEXT;
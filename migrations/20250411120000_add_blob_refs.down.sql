@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS objects_blob_refs ON objects;
+DROP FUNCTION IF EXISTS objects_adjust_blob_refs();
+DROP FUNCTION IF EXISTS adjust_blob_refs(TEXT, TEXT, BIGINT, BIGINT);
+DROP TABLE IF EXISTS blob_ref_checks;
+DROP TABLE IF EXISTS blobs;
@@ -0,0 +1,65 @@
+-- One row per stored blob, counting the files pointing at it.
+CREATE TABLE IF NOT EXISTS blobs (
+    hash_algorithm TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    ref_count BIGINT NOT NULL DEFAULT 0,
+
+    PRIMARY KEY (hash_algorithm, hash),
+    CONSTRAINT blobs_ref_count_non_negative CHECK (ref_count >= 0)
+);
+
+-- The outcome of the last consistency check, kept for metrics.
+CREATE TABLE IF NOT EXISTS blob_ref_checks (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS identity,
+    checked_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    drifted BIGINT NOT NULL,
+    repaired BOOLEAN NOT NULL
+);
+
+-- The only place reference counts change, so every path
+-- adjusts them the same way inside the transaction that owns it.
+CREATE OR REPLACE FUNCTION adjust_blob_refs(
+    algorithm TEXT,
+    digest TEXT,
+    blob_size BIGINT,
+    delta BIGINT
+) RETURNS VOID AS $$
+BEGIN
+    IF digest IS NULL THEN
+        RETURN;
+    END IF;
+
+    INSERT INTO blobs (hash_algorithm, hash, size, ref_count)
+    VALUES (algorithm, digest, blob_size, delta)
+    ON CONFLICT (hash_algorithm, hash) DO UPDATE
+    SET ref_count = blobs.ref_count + delta;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION objects_adjust_blob_refs() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        PERFORM adjust_blob_refs(OLD.hash_algorithm, OLD.hash, OLD.size, -1);
+    END IF;
+
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        PERFORM adjust_blob_refs(NEW.hash_algorithm, NEW.hash, NEW.size, 1);
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS objects_blob_refs ON objects;
+
+CREATE TRIGGER objects_blob_refs
+AFTER INSERT OR DELETE OR UPDATE OF hash, hash_algorithm ON objects
+FOR EACH ROW EXECUTE FUNCTION objects_adjust_blob_refs();
+
+INSERT INTO blobs (hash_algorithm, hash, size, ref_count)
+SELECT hash_algorithm, hash, MAX(size), COUNT(*)
+FROM objects
+WHERE hash IS NOT NULL
+GROUP BY hash_algorithm, hash
+ON CONFLICT (hash_algorithm, hash) DO NOTHING;
@@ -0,0 +1,11 @@
+-- Hostnames a user serves their files from, usable
+-- once the DNS TXT challenge passed or an admin verified it.
+CREATE TABLE IF NOT EXISTS tenant_domains (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS identity,
+    owner_id BIGINT NOT NULL REFERENCES users (ID),
+    hostname TEXT NOT NULL UNIQUE,
+    verification_token TEXT NOT NULL DEFAULT encode(gen_random_bytes(16), 'hex'),
+    verified_at TIMESTAMP,
+    public_base_url TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+-- Deleted users are kept around, marked by when they were deleted.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP;
@@ -0,0 +1,23 @@
+CREATE OR REPLACE FUNCTION objects_adjust_blob_refs() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        PERFORM adjust_blob_refs(OLD.hash_algorithm, OLD.hash, OLD.size, -1);
+    END IF;
+
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        PERFORM adjust_blob_refs(NEW.hash_algorithm, NEW.hash, NEW.size, 1);
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS objects_blob_refs ON objects;
+
+CREATE TRIGGER objects_blob_refs
+AFTER INSERT OR DELETE OR UPDATE OF hash, hash_algorithm ON objects
+FOR EACH ROW EXECUTE FUNCTION objects_adjust_blob_refs();
+
+ALTER TABLE objects DROP COLUMN IF EXISTS original_size;
+ALTER TABLE objects DROP COLUMN IF EXISTS original_hash;
+ALTER TABLE objects DROP COLUMN IF EXISTS text_encoding;
@@ -0,0 +1,28 @@
+-- Detected for text uploads, 'unknown' when detection wasn't confident.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS text_encoding TEXT;
+-- The untouched upload when the stored copy was transcoded to UTF-8.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS original_hash TEXT;
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS original_size BIGINT;
+
+-- Originals are blobs like any other, so they're counted too.
+CREATE OR REPLACE FUNCTION objects_adjust_blob_refs() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        PERFORM adjust_blob_refs(OLD.hash_algorithm, OLD.hash, OLD.size, -1);
+        PERFORM adjust_blob_refs(OLD.hash_algorithm, OLD.original_hash, OLD.original_size, -1);
+    END IF;
+
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        PERFORM adjust_blob_refs(NEW.hash_algorithm, NEW.hash, NEW.size, 1);
+        PERFORM adjust_blob_refs(NEW.hash_algorithm, NEW.original_hash, NEW.original_size, 1);
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS objects_blob_refs ON objects;
+
+CREATE TRIGGER objects_blob_refs
+AFTER INSERT OR DELETE OR UPDATE OF hash, hash_algorithm, original_hash ON objects
+FOR EACH ROW EXECUTE FUNCTION objects_adjust_blob_refs();
@@ -0,0 +1,12 @@
+-- Tags are scoped to the owner of the file, so the
+-- same tag never mixes files of different users.
+CREATE TABLE IF NOT EXISTS file_tags (
+    file_id BIGINT NOT NULL REFERENCES objects (ID) ON DELETE CASCADE,
+    owner_id BIGINT NOT NULL REFERENCES users (ID),
+    tag TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (file_id, tag)
+);
+
+CREATE INDEX IF NOT EXISTS file_tags_owner_tag ON file_tags (owner_id, tag);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS multipart_upload_parts;
+DROP TABLE IF EXISTS multipart_uploads;
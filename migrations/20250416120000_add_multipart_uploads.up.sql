@@ -0,0 +1,19 @@
+-- Multipart uploads started against the storage backend, kept
+-- so uploads orphaned by a restart can be resumed or aborted.
+CREATE TABLE IF NOT EXISTS multipart_uploads (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS identity,
+    blob_key TEXT NOT NULL,
+    upload_id TEXT NOT NULL UNIQUE,
+    started_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    abort_attempts INT NOT NULL DEFAULT 0,
+    last_abort_error TEXT
+);
+
+-- Checkpointed as the backend acknowledges each part.
+CREATE TABLE IF NOT EXISTS multipart_upload_parts (
+    multipart_upload_id BIGINT NOT NULL REFERENCES multipart_uploads (ID) ON DELETE CASCADE,
+    part_number INT NOT NULL,
+    etag TEXT NOT NULL,
+
+    PRIMARY KEY (multipart_upload_id, part_number)
+);
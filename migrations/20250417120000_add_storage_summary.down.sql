@@ -0,0 +1,4 @@
+DROP MATERIALIZED VIEW IF EXISTS user_storage_summary;
+DROP INDEX IF EXISTS objects_bucket_size;
+DROP FUNCTION IF EXISTS object_size_bucket(BIGINT);
+DROP FUNCTION IF EXISTS object_category(TEXT);
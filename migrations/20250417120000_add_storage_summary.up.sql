@@ -0,0 +1,58 @@
+-- Broad kind of a file from its extension, shared by every storage report.
+CREATE OR REPLACE FUNCTION object_category(path TEXT) RETURNS TEXT AS $$
+    SELECT CASE lower(substring(path FROM '\.([^./]+)$'))
+        WHEN 'png' THEN 'image' WHEN 'jpg' THEN 'image' WHEN 'jpeg' THEN 'image'
+        WHEN 'gif' THEN 'image' WHEN 'webp' THEN 'image' WHEN 'avif' THEN 'image'
+        WHEN 'bmp' THEN 'image' WHEN 'svg' THEN 'image' WHEN 'tiff' THEN 'image'
+        WHEN 'mp4' THEN 'video' WHEN 'webm' THEN 'video' WHEN 'mkv' THEN 'video'
+        WHEN 'mov' THEN 'video' WHEN 'avi' THEN 'video'
+        WHEN 'mp3' THEN 'audio' WHEN 'ogg' THEN 'audio' WHEN 'wav' THEN 'audio'
+        WHEN 'flac' THEN 'audio' WHEN 'aac' THEN 'audio' WHEN 'm4a' THEN 'audio'
+        WHEN 'pdf' THEN 'document' WHEN 'txt' THEN 'document' WHEN 'md' THEN 'document'
+        WHEN 'doc' THEN 'document' WHEN 'docx' THEN 'document' WHEN 'odt' THEN 'document'
+        WHEN 'srt' THEN 'document' WHEN 'log' THEN 'document'
+        WHEN 'zip' THEN 'archive' WHEN 'tar' THEN 'archive' WHEN 'gz' THEN 'archive'
+        WHEN 'xz' THEN 'archive' WHEN '7z' THEN 'archive' WHEN 'rar' THEN 'archive'
+        ELSE 'other'
+    END
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Size bucket labels, every boundary belongs to the bucket above it.
+CREATE OR REPLACE FUNCTION object_size_bucket(size BIGINT) RETURNS TEXT AS $$
+    SELECT CASE
+        WHEN size < 1048576 THEN '<1MB'
+        WHEN size < 10485760 THEN '1-10MB'
+        WHEN size < 104857600 THEN '10-100MB'
+        ELSE '>100MB'
+    END
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Largest files of an owner are read through their buckets.
+CREATE INDEX IF NOT EXISTS objects_bucket_size ON objects (bucket_id, size DESC);
+
+-- Per user totals across every user, too expensive to compute
+-- live so it's refreshed in the background.
+CREATE MATERIALIZED VIEW IF NOT EXISTS user_storage_summary AS
+SELECT
+    users.id AS user_id,
+    users.username,
+    COUNT(objects.id) FILTER (
+        WHERE objects.expires_at IS NULL OR objects.expires_at > NOW()
+    ) AS files,
+    COALESCE(SUM(objects.size) FILTER (
+        WHERE objects.expires_at IS NULL OR objects.expires_at > NOW()
+    ), 0)::BIGINT AS bytes,
+    COALESCE(SUM(objects.size) FILTER (
+        WHERE objects.expires_at <= NOW()
+    ), 0)::BIGINT AS expired_bytes,
+    NOW() AS refreshed_at
+FROM users
+LEFT JOIN buckets
+    ON buckets.owner_id = users.id
+LEFT JOIN objects
+    ON objects.bucket_id = buckets.id
+GROUP BY users.id, users.username;
+
+-- Required to refresh the view without blocking readers.
+CREATE UNIQUE INDEX IF NOT EXISTS user_storage_summary_user_id ON user_storage_summary (user_id);
+CREATE INDEX IF NOT EXISTS user_storage_summary_bytes ON user_storage_summary (bytes DESC);
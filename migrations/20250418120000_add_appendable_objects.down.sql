@@ -0,0 +1,2 @@
+ALTER TABLE objects DROP COLUMN IF EXISTS append_crc32;
+ALTER TABLE objects DROP COLUMN IF EXISTS appendable;
@@ -0,0 +1,5 @@
+-- Appendable files grow in place, so they're stored under their id
+-- rather than a content hash, which stays NULL.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS appendable BOOLEAN NOT NULL DEFAULT FALSE;
+-- CRC-32 of the content so far, resumed from on every append.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS append_crc32 BIGINT;
@@ -0,0 +1,2 @@
+ALTER TABLE objects DROP COLUMN IF EXISTS poster_at;
+ALTER TABLE objects DROP COLUMN IF EXISTS poster_state;
@@ -0,0 +1,6 @@
+-- Whether a poster frame was extracted from a video, NULL when
+-- none was attempted or the owner removed it.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS poster_state TEXT
+    CHECK (poster_state IN ('ready', 'failed'));
+-- Seconds into the video the poster was taken at.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS poster_at DOUBLE PRECISION;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS revoked_slugs;
+ALTER TABLE objects DROP COLUMN IF EXISTS link_rotated_at;
@@ -0,0 +1,10 @@
+-- When the slug was last replaced, signed URLs issued
+-- before then no longer grant access.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS link_rotated_at TIMESTAMP;
+
+-- Slugs retired by a rotation, answered as revoked rather than missing.
+CREATE TABLE IF NOT EXISTS revoked_slugs (
+    slug TEXT PRIMARY KEY,
+    object_id BIGINT NOT NULL REFERENCES objects (id) ON DELETE CASCADE,
+    revoked_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
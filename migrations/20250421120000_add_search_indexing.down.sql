@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS objects_indexable;
+ALTER TABLE objects DROP COLUMN IF EXISTS indexable;
+ALTER TABLE users DROP COLUMN IF EXISTS indexable;
@@ -0,0 +1,8 @@
+-- Whether search engines may index the files of a user,
+-- unless a file overrides it.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS indexable BOOLEAN NOT NULL DEFAULT TRUE;
+-- NULL follows the preference of the owner.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS indexable BOOLEAN;
+
+-- Walked in order by the sitemap, skipping files opted out themselves.
+CREATE INDEX IF NOT EXISTS objects_indexable ON objects (id) WHERE indexable IS NOT FALSE;
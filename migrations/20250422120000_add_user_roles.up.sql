@@ -0,0 +1,3 @@
+-- Admins listed in ADMIN_USERNAMES stay admins whatever their role.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'user'
+    CHECK (role IN ('user', 'admin'));
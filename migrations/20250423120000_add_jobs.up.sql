@@ -0,0 +1,19 @@
+-- Long running admin operations, so their progress outlives both
+-- the page that started them and restarts of the server.
+CREATE TABLE IF NOT EXISTS jobs (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS identity,
+    kind TEXT NOT NULL,
+    parameters JSONB NOT NULL DEFAULT '{}',
+    state TEXT NOT NULL DEFAULT 'running'
+        CHECK (state IN ('running', 'succeeded', 'failed', 'cancelled', 'interrupted')),
+    progress_done BIGINT NOT NULL DEFAULT 0,
+    progress_total BIGINT,
+    -- Polled by the job, which stops at its next progress update.
+    cancel_requested BOOLEAN NOT NULL DEFAULT FALSE,
+    result JSONB,
+    error TEXT,
+    started_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS jobs_started_at ON jobs (started_at DESC);
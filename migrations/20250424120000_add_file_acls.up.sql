@@ -0,0 +1,12 @@
+-- Users other than the owner a file is shared with,
+-- `delete` lets them remove the file and implies `read`.
+CREATE TABLE IF NOT EXISTS file_acls (
+    file_id BIGINT NOT NULL REFERENCES objects (ID) ON DELETE CASCADE,
+    grantee_id BIGINT NOT NULL REFERENCES users (ID) ON DELETE CASCADE,
+    permission TEXT NOT NULL CHECK (permission IN ('read', 'delete')),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (file_id, grantee_id)
+);
+
+CREATE INDEX IF NOT EXISTS file_acls_grantee ON file_acls (grantee_id);
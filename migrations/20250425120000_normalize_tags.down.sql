@@ -0,0 +1,3 @@
+-- Normalized tags can't be told apart from the
+-- ones they were merged with, nothing is undone.
+SELECT 1;
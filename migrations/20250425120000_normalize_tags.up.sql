@@ -0,0 +1,33 @@
+-- Re-normalizes stored tags to version 1 of `normalize_tag`, NFKC
+-- and case folded, merging the tags of a file that now collide.
+CREATE FUNCTION pg_temp.normalize_tag_v1(tag TEXT) RETURNS TEXT AS $$
+    SELECT BTRIM(REGEXP_REPLACE(
+        NORMALIZE(
+            REPLACE(REPLACE(REPLACE(REPLACE(
+                LOWER(NORMALIZE(REPLACE(tag, 'İ', 'i'), NFKC)),
+            'ß', 'ss'), 'ẞ', 'ss'), 'ς', 'σ'), 'ſ', 's'),
+            NFKC
+        ),
+        '\s+', ' ', 'g'
+    ))
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- The oldest of the colliding tags of a file is kept.
+DELETE FROM file_tags
+WHERE ctid IN (
+    SELECT ctid
+    FROM (
+        SELECT
+            ctid,
+            ROW_NUMBER() OVER (
+                PARTITION BY file_id, pg_temp.normalize_tag_v1(tag)
+                ORDER BY created_at, tag
+            ) AS position
+        FROM file_tags
+    ) AS ranked
+    WHERE position > 1
+);
+
+UPDATE file_tags
+SET tag = pg_temp.normalize_tag_v1(tag)
+WHERE tag <> pg_temp.normalize_tag_v1(tag);
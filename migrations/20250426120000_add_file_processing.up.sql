@@ -0,0 +1,11 @@
+-- The state of every post processing stage run on a file,
+-- so clients learn when a file is fully processed.
+CREATE TABLE IF NOT EXISTS file_processing (
+    file_id BIGINT NOT NULL REFERENCES objects (ID) ON DELETE CASCADE,
+    stage TEXT NOT NULL,
+    state TEXT NOT NULL CHECK (state IN ('pending', 'running', 'done', 'failed', 'skipped')),
+    error TEXT,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (file_id, stage)
+);
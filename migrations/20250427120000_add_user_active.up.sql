@@ -0,0 +1,3 @@
+-- Disabled users keep their data and their files stay served,
+-- they just can't authenticate anymore.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_active BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS instance_heartbeats;
+DROP TABLE IF EXISTS migration_phases;
@@ -0,0 +1,19 @@
+-- Expand and contract migrations in flight, their phase tells running
+-- binaries which query variants to use, see `schema_compat`.
+--
+-- The expand migration of a change registers it here.
+CREATE TABLE IF NOT EXISTS migration_phases (
+    name TEXT PRIMARY KEY,
+    phase TEXT NOT NULL CHECK (phase IN ('expand', 'migrate', 'contract')),
+    -- The schema version a binary needs to know both shapes of the data.
+    min_version BIGINT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Running servers and the schema version they were built for,
+-- so a phase isn't advanced while older binaries still run.
+CREATE TABLE IF NOT EXISTS instance_heartbeats (
+    instance_id TEXT PRIMARY KEY,
+    schema_version BIGINT NOT NULL,
+    seen_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
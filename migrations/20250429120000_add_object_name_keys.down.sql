@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS objects_name_key;
+
+ALTER TABLE objects DROP COLUMN IF EXISTS name_key;
@@ -0,0 +1,9 @@
+-- Natural sort keys of paths, computed by the server so listings sort
+-- "file2" before "file10" and "Ärger" next to "Arger" whatever the
+-- collations Postgres was built with. Bytes compare in the intended order.
+--
+-- Existing rows are filled by `cdnctl refresh-name-keys`, until then
+-- they sort by their lowercased path.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS name_key BYTEA;
+
+CREATE INDEX IF NOT EXISTS objects_name_key ON objects (name_key);
@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS objects_license;
+
+ALTER TABLE buckets DROP COLUMN IF EXISTS default_license;
+
+ALTER TABLE objects DROP COLUMN IF EXISTS source_url;
+ALTER TABLE objects DROP COLUMN IF EXISTS attribution;
+ALTER TABLE objects DROP COLUMN IF EXISTS license;
@@ -0,0 +1,10 @@
+-- Reuse terms of a file, `license` is an SPDX identifier from the
+-- list the server accepts, the attribution and source are free form.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS license TEXT;
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS attribution TEXT;
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS source_url TEXT;
+
+-- Applied to files created in the bucket without a license of their own.
+ALTER TABLE buckets ADD COLUMN IF NOT EXISTS default_license TEXT;
+
+CREATE INDEX IF NOT EXISTS objects_license ON objects (license) WHERE license IS NOT NULL;
@@ -0,0 +1,3 @@
+-- The instance running a job, see `instance_heartbeats`, its jobs are
+-- only given up on once it stopped reporting.
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS owner_instance TEXT;
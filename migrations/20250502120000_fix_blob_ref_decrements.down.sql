@@ -0,0 +1,17 @@
+CREATE OR REPLACE FUNCTION adjust_blob_refs(
+    algorithm TEXT,
+    digest TEXT,
+    blob_size BIGINT,
+    delta BIGINT
+) RETURNS VOID AS $$
+BEGIN
+    IF digest IS NULL THEN
+        RETURN;
+    END IF;
+
+    INSERT INTO blobs (hash_algorithm, hash, size, ref_count)
+    VALUES (algorithm, digest, blob_size, delta)
+    ON CONFLICT (hash_algorithm, hash) DO UPDATE
+    SET ref_count = blobs.ref_count + delta;
+END;
+$$ LANGUAGE plpgsql;
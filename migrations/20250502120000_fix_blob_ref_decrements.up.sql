@@ -0,0 +1,28 @@
+-- The proposed row of an upsert is checked before the conflict is, so
+-- decrementing through one always broke `blobs_ref_count_non_negative`.
+-- A decrement of a blob that isn't counted is left for the drift check.
+CREATE OR REPLACE FUNCTION adjust_blob_refs(
+    algorithm TEXT,
+    digest TEXT,
+    blob_size BIGINT,
+    delta BIGINT
+) RETURNS VOID AS $$
+BEGIN
+    IF digest IS NULL THEN
+        RETURN;
+    END IF;
+
+    IF delta < 0 THEN
+        UPDATE blobs
+        SET ref_count = ref_count + delta
+        WHERE hash_algorithm = algorithm AND hash = digest;
+
+        RETURN;
+    END IF;
+
+    INSERT INTO blobs (hash_algorithm, hash, size, ref_count)
+    VALUES (algorithm, digest, blob_size, delta)
+    ON CONFLICT (hash_algorithm, hash) DO UPDATE
+    SET ref_count = blobs.ref_count + delta;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,3 @@
+-- The type sniffed from the content at upload, files uploaded
+-- before it was stored and appendable ones have none.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS content_type TEXT;
@@ -0,0 +1,3 @@
+-- Who may read the feed of a bucket, `unlisted` ones need a token minted by the owner.
+ALTER TABLE buckets ADD COLUMN IF NOT EXISTS visibility TEXT NOT NULL DEFAULT 'private'
+    CHECK (visibility IN ('private', 'unlisted', 'public'));
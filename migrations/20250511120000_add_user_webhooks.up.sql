@@ -0,0 +1,34 @@
+-- Outgoing webhooks of end users, fired for the events of their own files
+-- and narrowed to a bucket or a tag when those are set.
+CREATE TABLE IF NOT EXISTS user_webhooks (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS identity,
+    owner_id BIGINT NOT NULL REFERENCES users (ID) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret TEXT,
+    events TEXT[] NOT NULL,
+    bucket_id BIGINT REFERENCES buckets (ID) ON DELETE CASCADE,
+    tag TEXT,
+    format TEXT NOT NULL DEFAULT 'json' CHECK (format IN ('json', 'discord')),
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- Deliveries that failed every attempt in a row, past the
+    -- limit the webhook is disabled with the reason kept aside.
+    consecutive_failures INT NOT NULL DEFAULT 0,
+    disabled_reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS user_webhooks_owner ON user_webhooks (owner_id);
+
+-- Every attempt at delivering an event, only the latest are kept.
+CREATE TABLE IF NOT EXISTS user_webhook_deliveries (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS identity,
+    webhook_id BIGINT NOT NULL REFERENCES user_webhooks (ID) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    attempt INT NOT NULL,
+    status_code INT,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS user_webhook_deliveries_webhook
+    ON user_webhook_deliveries (webhook_id, id DESC);
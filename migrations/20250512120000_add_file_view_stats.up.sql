@@ -0,0 +1,10 @@
+-- Views of a file per day, `viewers` is a HyperLogLog sketch of the salted
+-- viewer hashes of that day, empty when unique viewers aren't counted.
+CREATE TABLE IF NOT EXISTS file_view_stats (
+    file_id BIGINT NOT NULL REFERENCES objects (ID) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    views BIGINT NOT NULL DEFAULT 0,
+    viewers BYTEA NOT NULL DEFAULT '',
+
+    PRIMARY KEY (file_id, day)
+);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS objects_mismatched;
+ALTER TABLE objects DROP COLUMN IF EXISTS mismatched_size;
+ALTER TABLE objects DROP COLUMN IF EXISTS size_verified_at;
@@ -0,0 +1,8 @@
+-- When the recorded size was last found to match the stored content. Files
+-- stored from now on record the size of what was written, those stored
+-- before have none and are checked against storage until one matches.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS size_verified_at TIMESTAMP;
+ALTER TABLE objects ALTER COLUMN size_verified_at SET DEFAULT NOW();
+-- The size the content was found with when it didn't match the recorded one.
+ALTER TABLE objects ADD COLUMN IF NOT EXISTS mismatched_size BIGINT;
+CREATE INDEX IF NOT EXISTS objects_mismatched ON objects (id) WHERE mismatched_size IS NOT NULL;
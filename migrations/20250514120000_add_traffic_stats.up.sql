@@ -0,0 +1,17 @@
+-- Bytes exchanged per day with every client on every route, as they crossed
+-- the connection. `wasted_bytes` are those of requests that were rejected
+-- or cut short. Clients are `user:{id}` or `ip:{address}`.
+CREATE TABLE IF NOT EXISTS traffic_stats (
+    day DATE NOT NULL,
+    client TEXT NOT NULL,
+    route TEXT NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    bytes_in BIGINT NOT NULL DEFAULT 0,
+    bytes_out BIGINT NOT NULL DEFAULT 0,
+    wasted_bytes BIGINT NOT NULL DEFAULT 0,
+    aborted_uploads BIGINT NOT NULL DEFAULT 0,
+
+    PRIMARY KEY (day, client, route)
+);
+
+CREATE INDEX IF NOT EXISTS traffic_stats_wasted ON traffic_stats (day, wasted_bytes DESC);
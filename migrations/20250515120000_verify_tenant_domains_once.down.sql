@@ -0,0 +1,12 @@
+DROP INDEX IF EXISTS tenant_domains_verified_hostname;
+ALTER TABLE tenant_domains DROP CONSTRAINT IF EXISTS tenant_domains_owner_id_hostname_key;
+-- Only the verified claim of a hostname, or else its first one, is kept.
+DELETE FROM tenant_domains claim
+USING tenant_domains other
+WHERE
+    claim.hostname = other.hostname
+AND
+    claim.verified_at IS NULL
+AND
+    (other.verified_at IS NOT NULL OR other.id < claim.id);
+ALTER TABLE tenant_domains ADD CONSTRAINT tenant_domains_hostname_key UNIQUE (hostname);
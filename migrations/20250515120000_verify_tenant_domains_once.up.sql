@@ -0,0 +1,6 @@
+-- A hostname may be claimed by several users until one of them passes the
+-- challenge, only verified hostnames are unique so nobody can squat on a
+-- hostname by claiming it first.
+ALTER TABLE tenant_domains DROP CONSTRAINT IF EXISTS tenant_domains_hostname_key;
+ALTER TABLE tenant_domains ADD CONSTRAINT tenant_domains_owner_id_hostname_key UNIQUE (owner_id, hostname);
+CREATE UNIQUE INDEX IF NOT EXISTS tenant_domains_verified_hostname ON tenant_domains (hostname) WHERE verified_at IS NOT NULL;
@@ -0,0 +1,305 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use database::schema_compat::Phase;
+use database::{
+    BlobModel, FileModel, InstanceHeartbeatModel, MigrationPhaseModel, TenantDomainModel,
+    UserModel, configure_db,
+};
+use server::cli::export::{ExportOptions, export};
+use server::cli::import::{ImportOptions, TransferMode, import};
+use server::config::setting;
+use server::utils::app_storage::open_storage;
+use server::utils::normalize::natural_sort_key;
+
+/// Administration commands for the CDN.
+/// Files whose name key is computed between two round trips.
+const NAME_KEY_BATCH: i64 = 1000;
+
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Imports a directory tree, directories become buckets.
+    Import {
+        #[arg(long)]
+        path: PathBuf,
+
+        /// Username owning the imported files.
+        #[arg(long)]
+        owner: String,
+
+        #[arg(long, value_enum, default_value = "copy")]
+        mode: TransferMode,
+
+        /// Keeps the file modification time as the creation date.
+        #[arg(long)]
+        preserve_times: bool,
+
+        /// Records per path outcomes so the import can be resumed.
+        #[arg(long, default_value = "import.journal")]
+        journal: PathBuf,
+
+        #[arg(long, default_value_t = 4)]
+        workers: usize,
+
+        /// Files bigger than this many bytes are reported as failed,
+        /// defaults to `MAX_UPLOAD_SIZE` as files are read whole.
+        #[arg(long)]
+        max_size: Option<u64>,
+    },
+    /// Writes a public bucket and the buckets nested under it as a static
+    /// mirror, files unchanged since the previous export are left as they are.
+    ExportStatic {
+        #[arg(long)]
+        bucket: String,
+
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Makes the links of the listings absolute under this URL.
+        #[arg(long)]
+        base_url: Option<String>,
+    },
+    /// Recomputes blob reference counts from the files and reports drift.
+    CheckRefs {
+        /// Overwrites drifted counts with the recomputed ones.
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Verifies the claim of a user to a custom domain without waiting
+    /// for its DNS challenge, the claims of others are dropped.
+    VerifyDomain {
+        #[arg(long)]
+        hostname: String,
+        /// Username of the user whose claim is verified.
+        #[arg(long)]
+        owner: String,
+    },
+    /// Lists expand and contract migrations and the running instances,
+    /// or moves one to its next phase.
+    MigrationPhase {
+        name: Option<String>,
+
+        /// One of migrate or contract, the phase following the current one.
+        #[arg(long, requires = "name")]
+        to: Option<String>,
+    },
+    /// Computes the natural sort keys of files still without one.
+    RefreshNameKeys {
+        /// Recomputes every key, needed after `NORMALIZATION_VERSION` changed.
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[actix_web::main]
+async fn main() -> ExitCode {
+    let command = Cli::parse().command;
+
+    // The pools are sized like the server's, `CONFIG_FILE` included.
+    if let Err(error) = configure_db(setting) {
+        eprintln!("{error}");
+        return ExitCode::FAILURE;
+    }
+
+    match command {
+        Command::Import {
+            path,
+            owner,
+            mode,
+            preserve_times,
+            journal,
+            workers,
+            max_size,
+        } => {
+            let options = ImportOptions {
+                root: path,
+                owner,
+                mode,
+                preserve_times,
+                journal,
+                workers,
+                max_size,
+            };
+
+            let report = match import(options).await {
+                Ok(report) => report,
+                Err(error) => {
+                    eprintln!("Import failed: {error}");
+                    return ExitCode::FAILURE;
+                },
+            };
+
+            println!("Imported: {}", report.imported);
+            println!("Already in the journal: {}", report.skipped_journal);
+            println!("Skipped duplicates: {}", report.skipped_duplicate);
+
+            for path in &report.skipped_symlink {
+                println!("Skipped symlink: {}", path.display());
+            }
+
+            for path in &report.skipped_empty {
+                println!("Skipped empty file: {}", path.display());
+            }
+
+            for (path, reason) in &report.failed {
+                println!("Failed: {} ({reason})", path.display());
+            }
+
+            if report.failed.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+        },
+        Command::ExportStatic { bucket, out, base_url } => {
+            let storage = match open_storage().await {
+                Ok(storage) => storage,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return ExitCode::FAILURE;
+                },
+            };
+
+            let options = ExportOptions { bucket, out, base_url };
+
+            let report = match export(&storage, &options, None).await {
+                Ok(report) => report,
+                Err(error) => {
+                    eprintln!("Export failed: {error}");
+                    return ExitCode::FAILURE;
+                },
+            };
+
+            println!("Written: {}", report.written);
+            println!("Unchanged: {}", report.unchanged);
+            println!("Removed: {}", report.removed);
+
+            for excluded in &report.excluded {
+                println!("Excluded: {} ({})", excluded.path, excluded.reason);
+            }
+
+            ExitCode::SUCCESS
+        },
+        Command::CheckRefs { repair } => {
+            let drift = match BlobModel::check_refs(repair).await {
+                Ok(drift) => drift,
+                Err(error) => {
+                    eprintln!("Check failed: {error}");
+                    return ExitCode::FAILURE;
+                },
+            };
+
+            for blob in &drift {
+                println!(
+                    "Drifted: {}/{} recorded {} actual {}",
+                    blob.hash_algorithm, blob.hash, blob.recorded, blob.actual
+                );
+            }
+
+            println!("Drifted blobs: {}{}", drift.len(), if repair { " (repaired)" } else { "" });
+
+            if drift.is_empty() || repair { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+        },
+        Command::VerifyDomain { hostname, owner } => {
+            let verified = async {
+                let owner = UserModel::get_by_username(&owner).await?;
+                let hostname = hostname.to_ascii_lowercase();
+
+                TenantDomainModel::get_claim(&hostname, owner.id()).await?.verify().await
+            };
+
+            match verified.await {
+                Ok(domain) => {
+                    println!("Verified {}", domain.hostname());
+                    ExitCode::SUCCESS
+                },
+                Err(error) => {
+                    eprintln!("Verification failed: {error}");
+                    ExitCode::FAILURE
+                },
+            }
+        },
+        Command::MigrationPhase { name: Some(name), to: Some(to) } => {
+            let Some(phase) = Phase::parse(&to) else {
+                eprintln!("Unknown phase {to}");
+                return ExitCode::FAILURE;
+            };
+
+            match MigrationPhaseModel::advance(&name, phase).await {
+                Ok(phase) => {
+                    println!("{} is now in the {} phase", phase.name, phase.phase);
+                    ExitCode::SUCCESS
+                },
+                Err(error) => {
+                    eprintln!("Advance refused: {error}");
+                    ExitCode::FAILURE
+                },
+            }
+        },
+        Command::MigrationPhase { name, .. } => {
+            let (phases, instances) = match futures::try_join!(
+                MigrationPhaseModel::list(),
+                InstanceHeartbeatModel::list_live()
+            ) {
+                Ok(listed) => listed,
+                Err(error) => {
+                    eprintln!("Listing failed: {error}");
+                    return ExitCode::FAILURE;
+                },
+            };
+
+            for phase in
+                phases.iter().filter(|phase| name.as_ref().is_none_or(|name| *name == phase.name))
+            {
+                println!(
+                    "{} {} (needs schema {}, since {})",
+                    phase.name, phase.phase, phase.min_version, phase.updated_at
+                );
+            }
+
+            for instance in &instances {
+                println!(
+                    "Instance {} schema {} seen {}",
+                    instance.instance_id, instance.schema_version, instance.seen_at
+                );
+            }
+
+            ExitCode::SUCCESS
+        },
+        Command::RefreshNameKeys { all } => {
+            let mut after_id = 0;
+            let mut refreshed = 0;
+
+            loop {
+                let files = match FileModel::list_name_keys(after_id, all, NAME_KEY_BATCH).await {
+                    Ok(files) => files,
+                    Err(error) => {
+                        eprintln!("Refresh failed: {error}");
+                        return ExitCode::FAILURE;
+                    },
+                };
+
+                let Some((last_id, _)) = files.last() else {
+                    break;
+                };
+                after_id = *last_id;
+
+                for (id, path) in &files {
+                    if let Err(error) = FileModel::set_name_key(*id, &natural_sort_key(path)).await
+                    {
+                        eprintln!("Refresh failed at file {id}: {error}");
+                        return ExitCode::FAILURE;
+                    }
+                }
+
+                refreshed += files.len();
+            }
+
+            println!("Refreshed name keys: {refreshed}");
+            ExitCode::SUCCESS
+        },
+    }
+}
@@ -1,24 +1,97 @@
-use actix_web::web::get;
-use actix_web::{App, HttpResponse, HttpServer};
-use flexi_logger::Logger;
-use logger::format_log;
+use std::time::Duration;
+
+use actix_http::HttpService;
+use actix_service::{fn_service, map_config};
+use actix_web::dev::{AppConfig, Server};
+use actix_web::middleware::from_fn;
+use actix_web::web::{Data, PayloadConfig, get};
+use actix_web::{App, HttpResponse};
+use logger::outputs::{FileFormat, LogOutputs};
+use server::config::Config;
+use server::lifecycle::Lifecycle;
+use server::lifecycle::subsystems::{
+    AbuseDetectionSubsystem, AccessLogSubsystem, DatabaseSubsystem, DomainVerificationSubsystem,
+    HeartbeatSubsystem, JobsSubsystem, StatsSubsystem, StorageSubsystem, StorageSummarySubsystem,
+};
+use server::middleware::access_log::access_log;
+use server::middleware::byte_accounting::byte_accounting;
+use server::middleware::compress::compress;
+use server::middleware::csrf::csrf;
+use server::middleware::expect::expect_continue;
+use server::middleware::forwarded::forwarded;
+use server::middleware::header_limits::header_limits;
+use server::middleware::read_only::read_only;
+use server::middleware::readiness::readiness;
+use server::middleware::security_headers::security_headers;
+use server::middleware::storage_deadline::storage_deadline;
+use server::middleware::tenant::tenant;
+use server::utils::app_storage::AppStorage;
 use server::{AppError, routes};
 
+/// Time every subsystem gets to shut down before it's abandoned.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[actix_web::main]
 async fn main() -> Result<(), AppError> {
-    Logger::try_with_str("info")? //
-        .format(format_log)
-        .log_to_stdout()
-        .start()?;
+    let config = Config::get();
+
+    let _logger = LogOutputs {
+        file: (!config.log_file.is_empty()).then(|| config.log_file.clone().into()),
+        file_format: FileFormat::parse(&config.log_file_format),
+        syslog: config.log_syslog,
+        timezone: (!config.log_tz.is_empty()).then(|| config.log_tz.clone()),
+    }
+    .start_or_fallback(&config.log_spec);
+
+    let mut lifecycle = Lifecycle::default()
+        .register(AccessLogSubsystem)
+        .register(DatabaseSubsystem)
+        .register(StorageSubsystem)
+        .register(DomainVerificationSubsystem)
+        .register(StorageSummarySubsystem)
+        .register(JobsSubsystem)
+        .register(HeartbeatSubsystem)
+        .register(StatsSubsystem)
+        .register(AbuseDetectionSubsystem);
 
-    HttpServer::new(|| {
-        App::new() //
+    lifecycle.start(SHUTDOWN_TIMEOUT).await?;
+
+    let storage = lifecycle
+        .context
+        .get::<Data<AppStorage>>()
+        .cloned()
+        .expect("The storage subsystem provides its data");
+
+    // Built from the HTTP service rather than `HttpServer`, which
+    // always answers `Expect: 100-continue` before the request is routed.
+    let served = match Server::build().bind("cdn", ("0.0.0.0", 8080), move || {
+        let app = App::new() //
+            .app_data(storage.clone())
+            .app_data(PayloadConfig::new(config.upload_memory_threshold))
+            .wrap(from_fn(storage_deadline))
+            .wrap(from_fn(compress))
+            .wrap(from_fn(csrf))
+            .wrap(from_fn(read_only))
+            .wrap(from_fn(readiness))
+            .wrap(from_fn(tenant))
+            .wrap(from_fn(header_limits))
+            .wrap(from_fn(security_headers))
+            .wrap(from_fn(access_log))
+            .wrap(from_fn(byte_accounting))
+            .wrap(from_fn(forwarded))
             .route("/", get().to(HttpResponse::Ok))
-            .configure(routes::routes)
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await?;
+            .configure(routes::routes);
+
+        HttpService::build()
+            .expect(fn_service(expect_continue))
+            .finish(map_config(app, |_| AppConfig::default()))
+            .tcp()
+    }) {
+        Ok(server) => server.run().await,
+        Err(error) => Err(error),
+    };
+
+    lifecycle.shutdown(SHUTDOWN_TIMEOUT).await;
 
-    Ok(())
+    Ok(served?)
 }
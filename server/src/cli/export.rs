@@ -0,0 +1,870 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+use database::{BucketModel, FileModel, Visibility};
+use log::warn;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use storage::{Storage, StorageError};
+use tokio::fs::{
+    File, OpenOptions, create_dir, create_dir_all, read, remove_dir, remove_file, rename,
+    symlink_metadata,
+};
+use tokio::io::AsyncWriteExt;
+
+use crate::AppError;
+use crate::tasks::jobs::JobProgress;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::dav::{encode, escape};
+use crate::utils::hashing::hex;
+use crate::utils::serving::file_blob_key;
+
+const INDEX: &str = "index.html";
+const CHECKSUMS: &str = "SHA256SUMS";
+const MANIFEST: &str = "manifest.json";
+
+/// Files exported between two progress updates of the job.
+const PROGRESS_BATCH: usize = 100;
+
+/// Bytes of a blob read from storage at once while it's copied.
+const COPY_CHUNK: u64 = 256 * 1024;
+
+/// Bytes kept of a name, well under what filesystems accept.
+const MAX_NAME_LENGTH: usize = 200;
+
+pub struct ExportOptions {
+    /// Name of the public bucket exported, along with
+    /// the buckets of its owner nested under it.
+    pub bucket: String,
+    pub out: PathBuf,
+    /// Listings link to files under it when set, relatively otherwise.
+    pub base_url: Option<String>,
+}
+
+#[derive(Default, Serialize)]
+pub struct ExportReport {
+    pub written: usize,
+    pub unchanged: usize,
+    /// Files of the previous export gone from the buckets.
+    pub removed: usize,
+    pub excluded: Vec<Excluded>,
+}
+
+/// A file or bucket left out of the mirror.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Excluded {
+    /// The bucket name, followed by the file path for files.
+    pub path: String,
+    pub reason: String,
+}
+
+/// Everything an export wrote, read back by the next one to
+/// skip the files that didn't change since.
+#[derive(Default, Deserialize, Serialize)]
+struct Manifest {
+    bucket: String,
+    base_url: Option<String>,
+    files: Vec<ManifestEntry>,
+    /// Every directory holding a listing, the root being empty.
+    directories: Vec<String>,
+    excluded: Vec<Excluded>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct ManifestEntry {
+    id: i64,
+    /// The version of the file, any change to it bumps it.
+    version: i64,
+    /// Relative to the root of the export with `/` separators.
+    path: String,
+    size: u64,
+    sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+/// A file with where it goes in the mirror.
+struct Planned {
+    path: String,
+    /// The bucket name followed by the file path, for the warnings.
+    source: String,
+    file: FileModel,
+}
+
+/// Writes a public bucket and the buckets nested under it to `out` as a
+/// static mirror: the files under sanitized names, an `index.html` listing
+/// in every directory, `SHA256SUMS` and `manifest.json` at the root.
+///
+/// Files the previous manifest lists at the same version and size are
+/// left as they are, those gone since are removed. Everything is written
+/// in a stable order so exporting again only changes what did.
+pub async fn export(
+    storage: &AppStorage,
+    options: &ExportOptions,
+    progress: Option<&JobProgress>,
+) -> Result<ExportReport, AppError> {
+    let base_url = options.base_url.as_deref().map(base_url).transpose()?;
+    let root = BucketModel::get_by_name(&options.bucket).await?;
+
+    if root.visibility() != Visibility::Public {
+        return Err(AppError::BucketNotPublic);
+    }
+
+    create_dir_all(&options.out).await?;
+
+    let mut buckets = vec![root];
+    buckets.extend(BucketModel::list_nested(&options.bucket, buckets[0].owner_id()).await?);
+
+    let mut report = ExportReport::default();
+    let mut found = Vec::new();
+
+    for bucket in &buckets {
+        if bucket.visibility() != Visibility::Public {
+            report.excluded.push(Excluded {
+                path: bucket.name().to_owned(),
+                reason: format!("the bucket is {}", bucket.visibility().as_str()),
+            });
+            continue;
+        }
+
+        // Relative to the exported bucket, whose own files are at the root.
+        let directory = bucket.name()[options.bucket.len()..]
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+
+        for file in FileModel::list_in_bucket(bucket.id()).await? {
+            if file.expires_at().is_some() {
+                report.excluded.push(Excluded {
+                    path: format!("{}/{}", bucket.name(), file.path()),
+                    reason: "the file expires, its mirror would outlive it".into(),
+                });
+                continue;
+            }
+
+            let mut components = directory.clone();
+            components.extend(file.path().split('/').filter(|c| !c.is_empty()).map(str::to_owned));
+            let name = components.pop().unwrap_or_default();
+
+            let source = format!("{}/{}", bucket.name(), file.path());
+            found.push((components, name, source, file));
+        }
+    }
+
+    // Names are given in this order, so the same buckets always get the same tree.
+    found.sort_by(|a, b| (&a.0, &a.1, a.3.id()).cmp(&(&b.0, &b.1, b.3.id())));
+
+    let mut namer = Namer::new();
+    let mut planned = found
+        .into_iter()
+        .map(|(directory, name, source, file)| Planned {
+            path: namer.file(&directory, &name, file.id()),
+            source,
+            file,
+        })
+        .collect::<Vec<_>>();
+    planned.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let previous = read_manifest(&options.out).await;
+    let kept = planned.iter().map(|planned| planned.path.as_str()).collect::<HashSet<_>>();
+
+    // Removed first, a file gone may be where a directory now goes.
+    for entry in &previous.files {
+        if !kept.contains(entry.path.as_str()) && remove_exported(&options.out, &entry.path).await?
+        {
+            report.removed += 1;
+        }
+    }
+
+    let previous_files =
+        previous.files.iter().map(|entry| (entry.id, entry)).collect::<HashMap<_, _>>();
+    let mut entries = Vec::with_capacity(planned.len());
+
+    for (done, Planned { path, source, file }) in planned.iter().enumerate() {
+        if let Some(progress) = progress
+            && done % PROGRESS_BATCH == 0
+        {
+            progress.update(done as i64, Some(planned.len() as i64)).await?;
+        }
+
+        let url = base_url.as_ref().map(|base| link(base, path));
+        let target = options.out.join(path);
+
+        if let Some(entry) = previous_files.get(&file.id())
+            && entry.version == file.version()
+            && entry.path == *path
+            && symlink_metadata(&target)
+                .await
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() == entry.size)
+        {
+            entries.push(ManifestEntry { url, ..(*entry).clone() });
+            report.unchanged += 1;
+            continue;
+        }
+
+        // Copied aside first, a file left out must not create its directory.
+        let temporary = options.out.join(format!(".{}.partial", file.id()));
+
+        let (size, sha256) = match copy_content(storage, file, &temporary).await? {
+            Ok(copied) => copied,
+            Err(reason) => {
+                remove_exported(&options.out, path).await?;
+                report.excluded.push(Excluded {
+                    path: source.clone(),
+                    reason: reason.into(),
+                });
+                continue;
+            },
+        };
+
+        create_directories(&options.out, parent(path)).await?;
+        replace(&temporary, &target).await?;
+
+        entries.push(ManifestEntry {
+            id: file.id(),
+            version: file.version(),
+            path: path.clone(),
+            size,
+            sha256,
+            url,
+        });
+        report.written += 1;
+    }
+
+    let directories = write_listings(&options.bucket, &options.out, &entries, &base_url).await?;
+
+    for directory in previous.directories.iter().rev() {
+        if !directories.contains(directory) {
+            remove_listing(&options.out, directory).await?;
+        }
+    }
+
+    let checksums = entries
+        .iter()
+        .map(|entry| format!("{}  {}\n", entry.sha256, entry.path))
+        .collect::<String>();
+    write_if_changed(&options.out.join(CHECKSUMS), checksums.as_bytes()).await?;
+
+    for excluded in &report.excluded {
+        warn!("Left {} out of the export: {}", excluded.path, excluded.reason);
+    }
+
+    let manifest = Manifest {
+        bucket: options.bucket.clone(),
+        base_url,
+        files: entries,
+        directories: directories.into_iter().collect(),
+        excluded: report.excluded.clone(),
+    };
+
+    let mut manifest = serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::other)?;
+    manifest.push(b'\n');
+    write_if_changed(&options.out.join(MANIFEST), &manifest).await?;
+
+    if let Some(progress) = progress {
+        progress.update(planned.len() as i64, Some(planned.len() as i64)).await?;
+    }
+
+    Ok(report)
+}
+
+/// Copies the content of `file` to `temporary` a chunk at a time, returns its
+/// size and SHA-256 or why it's left out, nothing is left behind then.
+async fn copy_content(
+    storage: &AppStorage,
+    file: &FileModel,
+    temporary: &Path,
+) -> Result<Result<(u64, String), &'static str>, AppError> {
+    let Ok(key) = file_blob_key(file) else {
+        return Ok(Err("it has no content yet"));
+    };
+
+    let size = match storage.size(&key).await {
+        Err(StorageError::NotFound(_)) => {
+            return Ok(Err("its content is missing from the storage"));
+        },
+        size => size?,
+    };
+
+    let mut copy = create_temporary(temporary).await?;
+
+    let sha256 = match copy_blob(storage, &key, size, &mut copy).await {
+        Ok(sha256) => sha256,
+        Err(error) => {
+            remove_file(temporary).await?;
+            return Err(error);
+        },
+    };
+
+    // Checked against the hash recorded on upload, a copy of damaged
+    // content would pass its own checksum.
+    if file.hash_algorithm() == "sha256" && !file.appendable() && file.hash() != Some(&sha256) {
+        remove_file(temporary).await?;
+        return Ok(Err("its content doesn't match its checksum"));
+    }
+
+    Ok(Ok((size, sha256)))
+}
+
+/// Writes the `size` bytes of the blob at `key` to `copy`
+/// hashing them along the way, returns their SHA-256.
+async fn copy_blob(
+    storage: &AppStorage,
+    key: &str,
+    size: u64,
+    copy: &mut File,
+) -> Result<String, AppError> {
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+
+    while offset < size {
+        let chunk = storage.get_range(key, offset..(offset + COPY_CHUNK).min(size)).await?;
+
+        // The blob shrank since its size was read.
+        if chunk.is_empty() {
+            return Err(IoError::from(ErrorKind::UnexpectedEof).into());
+        }
+
+        hasher.update(&chunk);
+        copy.write_all(&chunk).await?;
+        offset += chunk.len() as u64;
+    }
+
+    copy.sync_all().await?;
+
+    Ok(hex(&hasher.finalize()))
+}
+
+/// The base URL without its trailing slash, only http and https are linkable.
+fn base_url(url: &str) -> Result<String, AppError> {
+    let parsed = Url::parse(url).map_err(|_| AppError::InvalidBaseUrl)?;
+
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(AppError::InvalidBaseUrl);
+    }
+
+    Ok(url.trim_end_matches('/').to_owned())
+}
+
+fn link(base: &str, path: &str) -> String {
+    let path = path.split('/').map(encode).collect::<Vec<_>>().join("/");
+
+    format!("{base}/{path}")
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// Gives every entry of the mirror a safe name unique within its
+/// directory whatever the case, the first one claiming a name keeps it.
+struct Namer {
+    /// Directories by the components they were named after.
+    directories: HashMap<Vec<String>, String>,
+    /// Names taken in every directory, lowercased.
+    taken: HashSet<(String, String)>,
+}
+
+impl Namer {
+    fn new() -> Self {
+        let mut namer = Namer {
+            directories: HashMap::new(),
+            taken: HashSet::new(),
+        };
+
+        for name in [INDEX, CHECKSUMS, MANIFEST] {
+            namer.claim("", name);
+        }
+
+        namer
+    }
+
+    /// The path of the directory named after `components`.
+    fn directory(&mut self, components: &[String]) -> String {
+        let Some((last, parents)) = components.split_last() else {
+            return String::new();
+        };
+
+        if let Some(path) = self.directories.get(components) {
+            return path.clone();
+        }
+
+        let parent = self.directory(parents);
+        let base = sanitize(last);
+        let name = (1..)
+            .map(|n| if n == 1 { base.clone() } else { format!("{base}~{n}") })
+            .find(|name| self.claim(&parent, name))
+            .unwrap_or(base);
+
+        let path = if parent.is_empty() { name } else { format!("{parent}/{name}") };
+        self.claim(&path, INDEX);
+        self.directories.insert(components.to_vec(), path.clone());
+
+        path
+    }
+
+    /// The path of file `id` named `name` in the directory named after
+    /// `directory`, suffixed with its id when the name is taken.
+    fn file(&mut self, directory: &[String], name: &str, id: i64) -> String {
+        let parent = self.directory(directory);
+        let base = sanitize(name);
+        let name = (1..)
+            .map(|n| match n {
+                1 => base.clone(),
+                2 => suffixed(&base, &id.to_string()),
+                n => suffixed(&base, &format!("{id}~{n}")),
+            })
+            .find(|name| self.claim(&parent, name))
+            .unwrap_or(base);
+
+        if parent.is_empty() { name } else { format!("{parent}/{name}") }
+    }
+
+    fn claim(&mut self, directory: &str, name: &str) -> bool {
+        self.taken.insert((directory.to_owned(), name.to_lowercase()))
+    }
+}
+
+/// A name any filesystem accepts: no separators, control or reserved
+/// characters, never hidden, `.` or `..`, and not too long.
+pub fn sanitize(name: &str) -> String {
+    let mut name = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_control() || r#"/\:*?"<>|"#.contains(c) { '_' } else { c })
+        .collect::<String>();
+
+    if name.starts_with('.') {
+        name.replace_range(..1, "_");
+    }
+
+    if name.len() > MAX_NAME_LENGTH {
+        let end = (0..=MAX_NAME_LENGTH).rev().find(|&end| name.is_char_boundary(end)).unwrap_or(0);
+        name.truncate(end);
+    }
+
+    // Windows drops these, two names would end up the same.
+    let name = name.trim_end_matches(['.', ' ']);
+
+    if name.is_empty() { "_".to_owned() } else { name.to_owned() }
+}
+
+/// `name` with `~suffix` before its extension.
+fn suffixed(name: &str, suffix: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem}~{suffix}.{extension}"),
+        _ => format!("{name}~{suffix}"),
+    }
+}
+
+/// The manifest of the previous export, an empty one when there's none
+/// or it can't be read, everything is written again then.
+async fn read_manifest(out: &Path) -> Manifest {
+    let content = match read(out.join(MANIFEST)).await {
+        Ok(content) => content,
+        Err(_) => return Manifest::default(),
+    };
+
+    serde_json::from_slice(&content).unwrap_or_else(|error| {
+        warn!("Couldn't read the manifest of the previous export, exporting everything: {error}");
+        Manifest::default()
+    })
+}
+
+/// Creates every directory of `relative` under `out`, refusing to go
+/// through symlinks so nothing gets written outside of it.
+async fn create_directories(out: &Path, relative: &str) -> Result<(), AppError> {
+    let mut path = out.to_owned();
+
+    for component in relative.split('/').filter(|component| !component.is_empty()) {
+        path.push(component);
+
+        match symlink_metadata(&path).await {
+            Ok(metadata) if metadata.is_dir() => {},
+            Ok(_) => return Err(AppError::ExportPathUnsafe(path.display().to_string())),
+            Err(error) if error.kind() == ErrorKind::NotFound => create_dir(&path).await?,
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes `content` to a temporary file renamed over `path`, a symlink
+/// there is replaced rather than followed and readers never see half
+/// of a file.
+async fn write_file(path: &Path, content: &[u8]) -> Result<(), AppError> {
+    // Sanitized names never start with a dot, so this is no one's.
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{name}.partial"));
+
+    let mut file = create_temporary(&temporary).await?;
+    file.write_all(content).await?;
+    file.sync_all().await?;
+
+    replace(&temporary, path).await
+}
+
+/// Creates `temporary` afresh, whatever an interrupted export left there.
+async fn create_temporary(temporary: &Path) -> Result<File, AppError> {
+    match remove_file(temporary).await {
+        Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+        _ => {},
+    }
+
+    Ok(OpenOptions::new().write(true).create_new(true).open(temporary).await?)
+}
+
+/// Renames `temporary` over `path`, unless a directory is in the way.
+async fn replace(temporary: &Path, path: &Path) -> Result<(), AppError> {
+    if symlink_metadata(path).await.is_ok_and(|metadata| metadata.is_dir()) {
+        remove_file(temporary).await?;
+        return Err(AppError::ExportPathUnsafe(path.display().to_string()));
+    }
+
+    rename(temporary, path).await?;
+
+    Ok(())
+}
+
+/// Writes `content` unless `path` already holds it, so the files an
+/// export didn't change keep their modification time.
+async fn write_if_changed(path: &Path, content: &[u8]) -> Result<(), AppError> {
+    let regular = symlink_metadata(path).await.is_ok_and(|metadata| metadata.is_file());
+
+    if regular && read(path).await.is_ok_and(|current| current == content) {
+        return Ok(());
+    }
+
+    write_file(path, content).await
+}
+
+/// `relative` under `out` when it's a plain path whose every directory
+/// is a real one, the way `create_directories` makes them. The previous
+/// manifest is only read back, a `..` or a symlinked directory in it
+/// must not reach outside of the mirror.
+async fn exported_path(out: &Path, relative: &str) -> Result<Option<PathBuf>, AppError> {
+    let mut path = out.to_owned();
+    let mut components = Path::new(relative).components().peekable();
+
+    while let Some(component) = components.next() {
+        let Component::Normal(name) = component else {
+            warn!("Ignored {relative:?} of the previous export, it leaves the mirror");
+            return Ok(None);
+        };
+
+        path.push(name);
+
+        if components.peek().is_none() {
+            break;
+        }
+
+        match symlink_metadata(&path).await {
+            Ok(metadata) if metadata.is_dir() => {},
+            Ok(_) => {
+                warn!(
+                    "Ignored {relative:?} of the previous export, {} isn't a directory",
+                    path.display()
+                );
+                return Ok(None);
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(Some(path))
+}
+
+/// Removes a file the previous export wrote, returns whether it was there.
+async fn remove_exported(out: &Path, relative: &str) -> Result<bool, AppError> {
+    let Some(path) = exported_path(out, relative).await? else {
+        return Ok(false);
+    };
+
+    match symlink_metadata(&path).await {
+        Ok(metadata) if !metadata.is_dir() => {
+            remove_file(&path).await?;
+            Ok(true)
+        },
+        _ => Ok(false),
+    }
+}
+
+/// Removes the listing of a directory no longer exported, and the
+/// directory itself when nothing else is left in it.
+async fn remove_listing(out: &Path, directory: &str) -> Result<(), AppError> {
+    let listing = format!("{directory}/{INDEX}");
+    remove_exported(out, listing.trim_start_matches('/')).await?;
+
+    if !directory.is_empty()
+        && let Some(path) = exported_path(out, directory).await?
+        && symlink_metadata(&path).await.is_ok_and(|metadata| metadata.is_dir())
+    {
+        let _ = remove_dir(&path).await;
+    }
+
+    Ok(())
+}
+
+/// Writes the listing of every directory holding files, returns them.
+async fn write_listings(
+    bucket: &str,
+    out: &Path,
+    entries: &[ManifestEntry],
+    base_url: &Option<String>,
+) -> Result<BTreeSet<String>, AppError> {
+    let mut listings = BTreeMap::<String, Listing>::new();
+    listings.entry(String::new()).or_default();
+
+    for entry in entries {
+        let mut directory = parent(&entry.path);
+        listings.entry(directory.to_owned()).or_default().files.push(entry);
+
+        // Every ancestor lists the directory below it.
+        while !directory.is_empty() {
+            let above = parent(directory);
+            let name = directory.rsplit('/').next().unwrap_or(directory).to_owned();
+            listings.entry(above.to_owned()).or_default().directories.insert(name);
+            directory = above;
+        }
+    }
+
+    for (directory, listing) in &listings {
+        let title =
+            if directory.is_empty() { bucket.to_owned() } else { format!("{bucket}/{directory}") };
+        let html = listing.render(&title, directory, base_url.as_deref());
+
+        create_directories(out, directory).await?;
+        write_if_changed(&out.join(directory).join(INDEX), html.as_bytes()).await?;
+    }
+
+    Ok(listings.into_keys().collect())
+}
+
+#[derive(Default)]
+struct Listing<'a> {
+    directories: BTreeSet<String>,
+    files: Vec<&'a ManifestEntry>,
+}
+
+impl Listing<'_> {
+    /// A plain page linking to the directories and files of `directory`,
+    /// relatively unless `base_url` is set.
+    fn render(&self, title: &str, directory: &str, base_url: Option<&str>) -> String {
+        let href = |path: &str| match base_url {
+            Some(base) => link(base, path),
+            None => path.rsplit('/').next().map(encode).unwrap_or_default(),
+        };
+        let child = |name: &str| {
+            if directory.is_empty() { name.to_owned() } else { format!("{directory}/{name}") }
+        };
+
+        let mut items = String::new();
+
+        if !directory.is_empty() {
+            let up = match base_url {
+                Some(_) if parent(directory).is_empty() => href(INDEX),
+                Some(_) => href(&format!("{}/{INDEX}", parent(directory))),
+                None => format!("../{INDEX}"),
+            };
+            let _ = write!(items, "<li><a href=\"{}\">../</a></li>", escape(&up));
+        }
+
+        for name in &self.directories {
+            let target = match base_url {
+                Some(_) => href(&format!("{}/{INDEX}", child(name))),
+                None => format!("{}/{INDEX}", encode(name)),
+            };
+            let _ = write!(items, "<li><a href=\"{}\">{}/</a></li>", escape(&target), escape(name));
+        }
+
+        for entry in &self.files {
+            let name = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+            let _ = write!(
+                items,
+                "<li><a href=\"{}\">{}</a> {} bytes</li>",
+                escape(&href(&entry.path)),
+                escape(name),
+                entry.size
+            );
+        }
+
+        format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><title>{0}</title>\
+             <style>body{{font-family:sans-serif;margin:2em}}</style></head>\
+             <body><h1>{0}</h1><ul>{items}</ul></body></html>\n",
+            escape(title),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+    use std::os::unix::fs::symlink;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{
+        create_bucket, create_user, run, set_visibility, unique_name, upload_file,
+    };
+    use crate::utils::app_storage::open_storage;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("export-{:016x}", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn names_are_sanitized_and_kept_apart() {
+        assert_eq!(sanitize("a/b:c?"), "a_b_c_");
+        assert_eq!(sanitize(".hidden"), "_hidden");
+        assert_eq!(sanitize(".."), "_");
+        assert_eq!(sanitize(" name. "), "name");
+        assert_eq!(sanitize(""), "_");
+
+        let mut namer = Namer::new();
+        let year = ["2024".to_owned()];
+
+        assert_eq!(namer.file(&[], "index.html", 7), "index~7.html");
+        assert_eq!(namer.file(&[], "Photo.jpg", 1), "Photo.jpg");
+        assert_eq!(namer.file(&[], "photo.jpg", 2), "photo~2.jpg");
+        assert_eq!(namer.file(&[], "2024", 3), "2024");
+        assert_eq!(namer.file(&year, "index.html", 4), "2024~2/index~4.html");
+        assert_eq!(namer.file(&year, "a.txt", 5), "2024~2/a.txt");
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn exporting_twice_copies_nothing() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let owner_id = create_user(&unique_name("export")).await.id();
+            let name = unique_name("mirror");
+
+            let bucket = |name: String| async move {
+                BucketModel::get_or_create(&name, owner_id).await.unwrap()
+            };
+            let root = set_visibility(bucket(name.clone()).await, "public").await;
+            let nested = set_visibility(bucket(format!("{name}/nested")).await, "public").await;
+            let private = bucket(format!("{name}/private")).await;
+
+            upload_file(&storage, &root, "top.txt", b"top").await;
+            upload_file(&storage, &root, "Nested", b"in the way").await;
+            upload_file(&storage, &nested, "deep.txt", b"nested").await;
+            upload_file(&storage, &private, "secret.txt", b"secret").await;
+
+            let out = temp_dir();
+            let options = ExportOptions {
+                bucket: name.clone(),
+                out: out.clone(),
+                base_url: None,
+            };
+
+            let first = export(&storage, &options, None).await.unwrap();
+            assert_eq!((first.written, first.unchanged), (3, 0));
+            assert_eq!(first.excluded.len(), 1);
+            assert_eq!(first.excluded[0].path, private.name());
+
+            let second = export(&storage, &options, None).await.unwrap();
+            assert_eq!((second.written, second.unchanged, second.removed), (0, 3, 0));
+
+            let checksums = read_to_string(out.join(CHECKSUMS)).unwrap();
+            let paths = checksums
+                .lines()
+                .map(|line| {
+                    let (sha256, path) = line.split_once("  ").unwrap();
+                    assert_eq!(
+                        hex(&Sha256::digest(std::fs::read(out.join(path)).unwrap())),
+                        sha256
+                    );
+                    path
+                })
+                .collect::<Vec<_>>();
+
+            // The file named like the nested bucket came first, it keeps the name.
+            assert_eq!(paths, ["Nested", "nested~2/deep.txt", "top.txt"]);
+            assert!(!out.join("private").exists());
+
+            let listing = read_to_string(out.join(INDEX)).unwrap();
+            assert!(listing.contains("href=\"nested~2/index.html\""));
+            assert!(listing.contains("href=\"top.txt\""));
+            assert!(out.join("nested~2").join(INDEX).is_file());
+
+            // A symlink planted in the mirror is replaced, not written through.
+            let outside = temp_dir();
+            create_dir_all(&outside).unwrap();
+            write(outside.join("target"), b"untouched").unwrap();
+            remove_file(out.join("top.txt")).await.unwrap();
+            symlink(outside.join("target"), out.join("top.txt")).unwrap();
+
+            let third = export(&storage, &options, None).await.unwrap();
+            assert_eq!((third.written, third.unchanged), (1, 2));
+            assert_eq!(read_to_string(outside.join("target")).unwrap(), "untouched");
+            assert_eq!(read_to_string(out.join("top.txt")).unwrap(), "top");
+
+            remove_dir_all(out).unwrap();
+            remove_dir_all(outside).unwrap();
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn tampered_manifests_remove_nothing_outside_the_mirror() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let owner = create_user(&unique_name("export")).await;
+            let root = set_visibility(create_bucket(&owner).await, "public").await;
+
+            // Spans several chunks, it's copied without being held in memory.
+            let large = (0..COPY_CHUNK * 2 + 7).map(|byte| byte as u8).collect::<Vec<_>>();
+            upload_file(&storage, &root, "large.bin", &large).await;
+
+            let parent = temp_dir();
+            let out = parent.join("mirror");
+            let outside = parent.join("outside");
+            create_dir_all(&outside).unwrap();
+            write(parent.join("victim"), b"untouched").unwrap();
+            write(outside.join("victim"), b"untouched").unwrap();
+
+            let options = ExportOptions {
+                bucket: root.name().to_owned(),
+                out: out.clone(),
+                base_url: None,
+            };
+
+            let first = export(&storage, &options, None).await.unwrap();
+            assert_eq!(first.written, 1);
+            assert_eq!(std::fs::read(out.join("large.bin")).unwrap(), large);
+
+            symlink(&outside, out.join("link")).unwrap();
+
+            let mut manifest: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(out.join(MANIFEST)).unwrap()).unwrap();
+            let entry = manifest["files"][0].clone();
+
+            for path in ["../victim", "link/victim", "/tmp/victim"] {
+                let mut planted = entry.clone();
+                planted["id"] = json!(-1);
+                planted["path"] = json!(path);
+                manifest["files"].as_array_mut().unwrap().push(planted);
+            }
+
+            manifest["directories"] = json!(["", "..", "link"]);
+            write(out.join(MANIFEST), serde_json::to_vec(&manifest).unwrap()).unwrap();
+
+            let second = export(&storage, &options, None).await.unwrap();
+            assert_eq!((second.unchanged, second.removed), (1, 0));
+            assert_eq!(read_to_string(parent.join("victim")).unwrap(), "untouched");
+            assert_eq!(read_to_string(outside.join("victim")).unwrap(), "untouched");
+            assert!(outside.is_dir());
+
+            remove_dir_all(parent).unwrap();
+        })
+    }
+}
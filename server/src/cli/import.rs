@@ -0,0 +1,354 @@
+use std::collections::HashSet;
+use std::fs::{Metadata, read_dir, symlink_metadata};
+use std::path::{Path, PathBuf};
+
+use actix_web::web::Bytes;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use database::{BucketModel, DatabaseError, FileModel, UserModel};
+use futures::{StreamExt, stream};
+use tokio::fs::{File, OpenOptions, read, read_to_string, remove_file};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::utils::app_storage::{AppStorage, open_storage};
+use crate::utils::upload::{check_upload, import_upload};
+
+/// How files are transferred into the storage, their content
+/// is always copied as it goes through the upload pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TransferMode {
+    Copy,
+    /// Deletes the source once it was imported.
+    Move,
+}
+
+pub struct ImportOptions {
+    pub root: PathBuf,
+    pub owner: String,
+    pub mode: TransferMode,
+    pub preserve_times: bool,
+    pub journal: PathBuf,
+    pub workers: usize,
+    /// Files are read whole like uploads, so this
+    /// defaults to `MAX_UPLOAD_SIZE`.
+    pub max_size: Option<u64>,
+}
+
+/// What happened to a single path.
+pub enum Outcome {
+    Imported,
+    SkippedJournal,
+    SkippedDuplicate,
+    SkippedSymlink,
+    SkippedEmpty,
+    Failed(String),
+}
+
+#[derive(Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub skipped_journal: usize,
+    pub skipped_duplicate: usize,
+    pub skipped_symlink: Vec<PathBuf>,
+    pub skipped_empty: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl ImportReport {
+    fn add(&mut self, path: PathBuf, outcome: Outcome) {
+        match outcome {
+            Outcome::Imported => self.imported += 1,
+            Outcome::SkippedJournal => self.skipped_journal += 1,
+            Outcome::SkippedDuplicate => self.skipped_duplicate += 1,
+            Outcome::SkippedSymlink => self.skipped_symlink.push(path),
+            Outcome::SkippedEmpty => self.skipped_empty.push(path),
+            Outcome::Failed(reason) => self.failed.push((path, reason)),
+        }
+    }
+}
+
+struct Importer {
+    options: ImportOptions,
+    owner: UserModel,
+    storage: AppStorage,
+    done: HashSet<PathBuf>,
+    journal: Mutex<File>,
+}
+
+/// Imports a directory tree, every directory becomes a bucket
+/// and every regular file a file in it. Files go through the same
+/// checks and post processors as uploads.
+///
+/// Outcomes are appended to the journal as they happen, paths
+/// already recorded there are skipped so interrupted runs can resume.
+pub async fn import(options: ImportOptions) -> Result<ImportReport, Box<dyn std::error::Error>> {
+    let owner = UserModel::get_by_username(&options.owner).await?;
+    let storage = open_storage().await?;
+
+    let done = match read_to_string(&options.journal).await {
+        Ok(journal) => journal
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .filter(|(status, _)| !status.starts_with("failed"))
+            .map(|(_, path)| PathBuf::from(path))
+            .collect(),
+        Err(_) => HashSet::new(),
+    };
+
+    let journal = OpenOptions::new().create(true).append(true).open(&options.journal).await?;
+
+    let mut files = Vec::new();
+    let mut unreadable = Vec::new();
+    walk(&options.root, &mut files, &mut unreadable);
+
+    let workers = options.workers.max(1);
+    let importer = Importer {
+        options,
+        owner,
+        storage,
+        done,
+        journal: Mutex::new(journal),
+    };
+
+    let importer = &importer;
+    let mut report = ImportReport::default();
+
+    for (path, reason) in unreadable {
+        let outcome = Outcome::Failed(reason);
+        importer.record(&path, &outcome).await;
+        report.add(path, outcome);
+    }
+
+    let outcomes = stream::iter(files)
+        .map(|(path, metadata)| async move {
+            let outcome = importer.import_one(&path, &metadata).await;
+            importer.record(&path, &outcome).await;
+            (path, outcome)
+        })
+        .buffer_unordered(workers)
+        .collect::<Vec<_>>()
+        .await;
+
+    for (path, outcome) in outcomes {
+        report.add(path, outcome);
+    }
+
+    Ok(report)
+}
+
+/// Collects every entry under `directory` without following symlinks,
+/// paths that can't be read are added to `unreadable` and skipped.
+fn walk(
+    directory: &Path,
+    files: &mut Vec<(PathBuf, Metadata)>,
+    unreadable: &mut Vec<(PathBuf, String)>,
+) {
+    let entries = match read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) => return unreadable.push((directory.to_owned(), error.to_string())),
+    };
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(error) => {
+                unreadable.push((directory.to_owned(), error.to_string()));
+                continue;
+            },
+        };
+
+        match symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => walk(&path, files, unreadable),
+            Ok(metadata) => files.push((path, metadata)),
+            Err(error) => unreadable.push((path, error.to_string())),
+        }
+    }
+}
+
+impl Importer {
+    async fn import_one(&self, path: &Path, metadata: &Metadata) -> Outcome {
+        if self.done.contains(path) {
+            return Outcome::SkippedJournal;
+        }
+
+        if metadata.is_symlink() {
+            return Outcome::SkippedSymlink;
+        }
+
+        if metadata.len() == 0 {
+            return Outcome::SkippedEmpty;
+        }
+
+        let max_size = self.options.max_size.unwrap_or(Config::get().max_upload_size as u64);
+
+        if metadata.len() > max_size {
+            return Outcome::Failed("exceeds the maximum size".into());
+        }
+
+        let Some((bucket, name)) = self.names(path) else {
+            return Outcome::Failed("name is not valid UTF-8 or has control characters".into());
+        };
+
+        match self.ingest(path, metadata, &bucket, &name).await {
+            Ok(outcome) => outcome,
+            Err(error) => Outcome::Failed(error.to_string()),
+        }
+    }
+
+    async fn ingest(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        bucket: &str,
+        name: &str,
+    ) -> Result<Outcome, Box<dyn std::error::Error>> {
+        // Bucket names are unique across users.
+        let bucket = match BucketModel::get_or_create(bucket, self.owner.id()).await {
+            Err(DatabaseError::ModelNotFound(_)) => {
+                return Ok(Outcome::Failed(format!("bucket {bucket} belongs to another user")));
+            },
+            result => result?,
+        };
+
+        // Spares reading files imported before, a file created meanwhile
+        // is still caught when its row is inserted.
+        if FileModel::exists_at(bucket.id(), name).await? {
+            return Ok(Outcome::SkippedDuplicate);
+        }
+
+        let body = Bytes::from(read(path).await?);
+        check_upload(&body).await?;
+
+        let created_at = self.options.preserve_times.then(|| modified_at(metadata)).flatten();
+        let file = import_upload(&self.storage, bucket.id(), name.to_owned(), created_at, body);
+
+        if file.await?.is_none() {
+            return Ok(Outcome::SkippedDuplicate);
+        }
+
+        if self.options.mode == TransferMode::Move {
+            remove_file(path).await?;
+        }
+
+        Ok(Outcome::Imported)
+    }
+
+    /// The bucket and file names for `path`, directories are named
+    /// after their path relative to the root with `/` separators.
+    fn names(&self, path: &Path) -> Option<(String, String)> {
+        let relative = path.strip_prefix(&self.options.root).ok()?;
+        let name = sanitize(relative.file_name()?.to_str()?)?;
+
+        let directory = match relative.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            Some(parent) => parent
+                .components()
+                .map(|component| sanitize(component.as_os_str().to_str()?))
+                .collect::<Option<Vec<_>>>()?
+                .join("/"),
+            None => sanitize(self.options.root.file_name()?.to_str()?)?,
+        };
+
+        Some((directory, name))
+    }
+
+    async fn record(&self, path: &Path, outcome: &Outcome) {
+        let status = match outcome {
+            Outcome::Imported => "imported".into(),
+            Outcome::SkippedJournal => return,
+            Outcome::SkippedDuplicate => "skipped-duplicate".into(),
+            Outcome::SkippedSymlink => "skipped-symlink".into(),
+            Outcome::SkippedEmpty => "skipped-empty".into(),
+            Outcome::Failed(reason) => format!("failed: {reason}"),
+        };
+
+        let line = format!("{status}\t{}\n", path.display());
+        let _ = self.journal.lock().await.write_all(line.as_bytes()).await;
+    }
+}
+
+fn sanitize(name: &str) -> Option<String> {
+    let name = name.trim();
+    let valid = !name.is_empty() && !name.chars().any(char::is_control);
+
+    valid.then(|| name.to_owned())
+}
+
+fn modified_at(metadata: &Metadata) -> Option<NaiveDateTime> {
+    let modified = metadata.modified().ok()?;
+    Some(DateTime::<Utc>::from(modified).naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use super::*;
+    use crate::testing::{create_user, run};
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("import-{:016x}", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn unreadable_directories_are_reported_and_skipped() {
+        let root = temp_dir();
+        create_dir_all(root.join("nested")).unwrap();
+        write(root.join("nested/file.txt"), b"content").unwrap();
+
+        let (mut files, mut unreadable) = (Vec::new(), Vec::new());
+        walk(&root, &mut files, &mut unreadable);
+        walk(&root.join("missing"), &mut files, &mut unreadable);
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, root.join("nested/file.txt"));
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0].0, root.join("missing"));
+
+        remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn importing_twice_changes_nothing() {
+        run(async {
+            let base = temp_dir();
+            let suffix = base.file_name().unwrap().to_str().unwrap().to_owned();
+            let (root_name, nested_name) = (format!("{suffix}-root"), format!("{suffix}-nested"));
+            let root = base.join(&root_name);
+
+            create_dir_all(root.join(&nested_name)).unwrap();
+            write(root.join("top.txt"), b"top").unwrap();
+            write(root.join(&nested_name).join("nested.txt"), b"nested").unwrap();
+
+            let owner = create_user(&suffix).await;
+
+            let options = |journal: &str| ImportOptions {
+                root: root.clone(),
+                owner: suffix.clone(),
+                mode: TransferMode::Copy,
+                preserve_times: false,
+                journal: base.join(journal),
+                workers: 2,
+                max_size: None,
+            };
+
+            let first = import(options("first.journal")).await.unwrap();
+            assert_eq!(first.imported, 2);
+            assert!(first.failed.is_empty());
+
+            // A journal of its own, so only the files themselves tell it's done.
+            let second = import(options("second.journal")).await.unwrap();
+            assert_eq!(second.imported, 0);
+            assert_eq!(second.skipped_duplicate, 2);
+            assert!(second.failed.is_empty());
+
+            for name in [&root_name, &nested_name] {
+                let bucket = BucketModel::get_named_owned(name, owner.id()).await.unwrap();
+                assert_eq!(FileModel::list_in_bucket(bucket.id()).await.unwrap().len(), 1);
+            }
+
+            remove_dir_all(base).unwrap();
+        })
+    }
+}
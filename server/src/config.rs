@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+use std::env::{self, VarError};
+use std::fs;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+use ipnet::IpNet;
+
+use crate::utils::hashing::HashAlgorithm;
+use crate::utils::purge::PurgeProvider;
+use crate::utils::upload::UploadCollision;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Settings read from `CONFIG_FILE`, keyed like the environment variables.
+static FILE: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Runtime configuration read from the environment,
+/// a `.env` file is loaded first when present.
+///
+/// Settings missing from the environment are read from `CONFIG_FILE`
+/// when set, a TOML or JSON file of the same keys, so the environment wins.
+///
+/// The pool settings `DB_POOL_SIZE`, `DB_READ_POOL_SIZE`, `STATEMENT_TIMEOUT_MS`,
+/// `SQLX_LOG_LEVEL`, `SQLX_SLOW_LOG_LEVEL` and `SLOW_QUERY_MS` aren't fields,
+/// the database crate parses them from the same sources through [`setting`].
+pub struct Config {
+    /// Secret used to sign and verify shared URLs.
+    pub signing_secret: String,
+    /// Longest lifetime of a form upload policy, in seconds.
+    pub policy_max_ttl: i64,
+    /// Longest lifetime of a signed URL, in seconds.
+    pub sign_max_ttl: i64,
+    /// Origins form uploads may redirect to after succeeding, as `https://host[:port]`.
+    pub policy_redirect_origins: Vec<String>,
+
+    /// Cookie holding the session, requests carrying it must send a CSRF token.
+    pub session_cookie_name: String,
+    /// Rejects cookie authenticated mutations without a valid CSRF
+    /// token, when off they're only logged.
+    pub csrf_enforce: bool,
+
+    /// Answers internal errors with their full detail, set with `ERROR_DETAIL=full`
+    /// for development, by default clients only get a generic message.
+    pub error_detail_full: bool,
+
+    /// Usernames allowed to use the admin routes.
+    pub admin_usernames: Vec<String>,
+
+    /// Base of generated links when the request didn't come through a custom
+    /// domain, when empty links use the scheme and host the client asked for.
+    pub public_base_url: String,
+    /// Networks of the reverse proxies whose forwarded headers are honored.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Answers 404 for hosts that aren't a verified custom domain
+    /// instead of serving them as the default host.
+    pub reject_unknown_hosts: bool,
+    /// Seconds between DNS checks of pending custom domains.
+    pub domain_verification_interval: u64,
+    /// Seconds between refreshes of the per user storage summary.
+    pub storage_summary_interval: u64,
+
+    /// Value for `X-Frame-Options`, empty to omit the header.
+    pub frame_options: String,
+    /// Value for `Content-Security-Policy`, empty to omit the header.
+    pub content_security_policy: String,
+    /// Value for `Referrer-Policy`, empty to omit the header.
+    pub referrer_policy: String,
+    /// Value for `Strict-Transport-Security`, only sent over TLS.
+    pub strict_transport_security: String,
+    /// Lets search engines index files whose owner didn't opt out,
+    /// otherwise every file is served with `X-Robots-Tag: noindex`.
+    pub seo_allow_indexing: bool,
+    /// Served as `/robots.txt`, empty to generate it from `SEO_ALLOW_INDEXING`.
+    pub robots_txt: String,
+
+    /// flexi_logger specification, such as `info` or `warn,server=debug`.
+    pub log_spec: String,
+    /// Rotating file application logs are also written to, empty to disable.
+    pub log_file: String,
+    /// Either `plain` or `json`.
+    pub log_file_format: String,
+    /// Also sends application logs to the local syslog daemon.
+    pub log_syslog: bool,
+    /// IANA zone log timestamps are rendered in, such as `Europe/Paris`,
+    /// empty for the local time of the host.
+    pub log_tz: String,
+
+    /// Either `stdout` or `file:<path>`.
+    pub access_log_target: String,
+    /// Either `combined` or `json`.
+    pub access_log_format: String,
+    /// Paths left out of the access log, a trailing `*` matches a prefix.
+    pub access_log_exclude: Vec<String>,
+    /// Successful requests to these paths are only logged 1 in N times, set
+    /// as `pattern=N` pairs, errors are always logged.
+    pub access_log_sample: Vec<(String, u32)>,
+
+    /// Responses smaller than this many bytes are sent uncompressed.
+    pub compress_min_bytes: usize,
+    /// From 0 to 9.
+    pub compress_gzip_level: u32,
+    /// From 0 to 11.
+    pub compress_brotli_level: u32,
+
+    /// Digest used for deduplication and ETags, `sha256` by default.
+    pub hash_algorithm: HashAlgorithm,
+
+    /// Rejects mutations without `If-Match` instead of letting the last write win.
+    pub require_if_match: bool,
+
+    /// Serves files at `/f/<hash>-<tag>/<filename>`, the tag ties
+    /// the URL to one file so a hash alone fetches nothing.
+    pub content_addressed_urls: bool,
+    /// Tags served content with `Surrogate-Key` and `Cache-Tag` headers,
+    /// so caches in front of the origin can purge a file, a bucket or an owner.
+    pub surrogate_keys: bool,
+    /// The cache purged when files change, `none`, `cloudflare` or `fastly`.
+    pub purge_backend: PurgeProvider,
+    /// The Cloudflare zone or the Fastly service purged.
+    pub purge_zone: String,
+    /// Token of the purge API, required along with a backend.
+    pub purge_api_token: String,
+    /// Key file ids are obfuscated with in URLs and API results,
+    /// numeric ids are used when it's empty.
+    pub file_id_salt: String,
+
+    /// Transcodes text uploads to UTF-8 keeping the original aside,
+    /// uploads can still opt in or out with `?normalize=`.
+    pub normalize_text_encoding: bool,
+    /// What an upload named like an existing file in its bucket does,
+    /// `rename`, `overwrite` or `reject`, the latter by default.
+    pub upload_collision: UploadCollision,
+
+    /// Seconds missing slugs are remembered for and their 404s
+    /// cached by upstream caches, `0` disables both.
+    pub not_found_cache_ttl: u64,
+    /// Files kept in the slug lookup cache, `0` disables it.
+    pub file_cache_capacity: usize,
+    /// Seconds a cached file is served from memory, bounding how stale it gets
+    /// when changed by something that doesn't invalidate it, like a purge.
+    pub file_cache_ttl: u64,
+    /// Blob sizes kept from storage stats, `0` disables the cache.
+    pub stat_cache_capacity: usize,
+    /// Seconds a blob size is kept, bounding how stale it gets when
+    /// the blob changes behind the server's back.
+    pub stat_cache_ttl: u64,
+
+    /// Most header lines a request may carry, repeated headers count once per line.
+    pub max_header_count: usize,
+    /// Most bytes the names and values of the headers of a request may take.
+    pub max_header_bytes: usize,
+
+    /// Serves HTML inline as `text/html`, only honored on `SANDBOX_BASE_URL`.
+    pub allow_html_inline: bool,
+    /// A separate origin file content is served from, empty when there's none.
+    pub sandbox_base_url: String,
+
+    /// Rapid successive failures after which a background task is given up on.
+    pub task_max_failures: u32,
+    /// Longest delay in seconds between restarts of a failing background task.
+    pub task_backoff_max: u64,
+
+    /// Post processors run on every upload, in order, among
+    /// `dimensions`, `thumbnail`, `poster`, `av-scan` and `precompress`.
+    pub post_processors: Vec<String>,
+    /// Command uploads are piped to by the `av-scan` post processor.
+    pub av_scan_command: String,
+    /// The ffmpeg binary the `poster` post processor runs.
+    pub ffmpeg_path: String,
+    /// The ffprobe binary used to find the duration of videos.
+    pub ffprobe_path: String,
+    /// Scans uploads with clamd before storing them.
+    pub av_scan: bool,
+    /// Address of clamd, either `host:port` or the path of a unix socket.
+    pub clamd_address: String,
+    /// Accepts uploads unscanned when clamd fails instead of rejecting them.
+    pub av_scan_fail_open: bool,
+
+    /// Directory blobs are stored in.
+    pub storage_path: String,
+    /// Attempts per storage operation, the first one included.
+    pub storage_max_attempts: u32,
+    /// Milliseconds a storage operation may take, retries included.
+    pub storage_retry_budget_ms: u64,
+    /// Milliseconds every storage operation of a request may take together.
+    pub storage_request_budget_ms: u64,
+    /// File holding the key new blobs are encrypted with,
+    /// blobs are stored in plaintext when unset. Appendable
+    /// files are written in place and always stay in plaintext.
+    pub master_key_file: Option<String>,
+    /// Files holding the keys blobs were encrypted with before a rotation.
+    pub previous_master_key_files: Vec<String>,
+    /// Directory the static exports started by admins are written under,
+    /// they're refused when unset.
+    pub export_path: Option<String>,
+    /// Seconds after which unfinished multipart uploads are aborted
+    /// instead of resumed, their parts are billed until then.
+    pub multipart_upload_ttl: i64,
+
+    /// Size in bytes of the largest upload.
+    pub max_upload_size: usize,
+    /// Size in bytes up to which upload bodies are held in memory while
+    /// they're received, larger ones are spooled to `upload_spool_path`.
+    /// Also the largest body any other request may send.
+    pub upload_memory_threshold: usize,
+    /// Directory upload bodies past `upload_memory_threshold` are
+    /// spooled to, the temporary directory of the system by default.
+    pub upload_spool_path: String,
+
+    /// Seconds an unreferenced blob is kept before it may be purged,
+    /// long enough for any upload still writing it to finish.
+    pub orphan_grace_period: u64,
+
+    /// Accepts uploads without authentication into the anonymous bucket.
+    pub allow_anonymous_upload: bool,
+    /// Seconds anonymous uploads are kept for, `0` keeps them forever.
+    pub anonymous_upload_ttl: i64,
+    /// Longest side in pixels of images accepted at upload,
+    /// checked from their header before anything decodes them.
+    pub max_image_dimension: u32,
+    /// Size in bytes appendable files can't grow past.
+    pub max_appendable_size: u64,
+    /// Size in bytes past which a delta is applied as a tracked
+    /// job, answered right away with the job to poll.
+    pub delta_job_threshold: u64,
+    /// Uploads a minute each user, or address when anonymous,
+    /// is allowed on average, `0` disables the limit.
+    pub upload_rate_limit: u32,
+    /// Uploads allowed back to back before the average applies.
+    pub upload_rate_burst: u32,
+    /// Seconds between checks of the traffic of every client for abuse.
+    pub abuse_check_interval: u64,
+    /// Share in percent of its bytes a client may waste on rejected or
+    /// cut short requests between two checks, `0` doesn't check it.
+    pub abuse_wasted_percent: u32,
+    /// Bytes a client has to waste between two checks before its share counts.
+    pub abuse_min_wasted_bytes: u64,
+    /// Uploads a client may abort between two checks, `0` doesn't check it.
+    pub abuse_aborted_uploads: i64,
+    /// Uploads a minute a client found abusive is held to, one at a time,
+    /// `0` only logs the client.
+    pub abuse_rate_limit: u32,
+    /// Seconds a client found abusive is held to `ABUSE_RATE_LIMIT`.
+    pub abuse_cooldown: u64,
+    /// Uploads each user, or address when anonymous, may run at
+    /// once, `0` disables the limit.
+    pub upload_concurrency: u32,
+
+    /// Size in bytes of the largest paste, taken apart from
+    /// `MAX_UPLOAD_SIZE` since pastes are small.
+    pub paste_max_size: usize,
+    /// Seconds pastes are kept for unless they ask otherwise, `0` keeps them.
+    pub paste_ttl: i64,
+    /// Pastes a minute each user, or address when anonymous, is
+    /// allowed on average apart from uploads, `0` disables the limit.
+    pub paste_rate_limit: u32,
+    pub paste_rate_burst: u32,
+
+    /// Most files a single zip download may hold.
+    pub max_zip_files: usize,
+    /// Total size in bytes of the files a single zip download may hold.
+    pub max_zip_size: u64,
+
+    /// Files listed in the Atom and RSS feeds of a bucket, newest first.
+    pub feed_size: i64,
+
+    /// Webhook deliveries a minute each user is allowed on average,
+    /// the excess is dropped and recorded, `0` disables the limit.
+    pub webhook_rate_limit: u32,
+    /// Deliveries of a user webhook failing in a row before
+    /// it's disabled, `0` keeps failing webhooks enabled.
+    pub webhook_disable_after: i32,
+
+    /// Estimates the unique viewers of files from salted hashes of their
+    /// address and user agent, views are still counted when disabled.
+    pub unique_viewers: bool,
+    /// Seconds between writes of the views and traffic buffered in memory.
+    pub stats_flush_interval: u64,
+
+    /// Refuses every mutating request, for nodes that only serve content.
+    pub read_only: bool,
+}
+
+impl Config {
+    /// Obtains the configuration, reading it from the
+    /// environment the first time it's accessed.
+    pub fn get() -> &'static Config {
+        CONFIG.get_or_init(Config::from_env)
+    }
+
+    fn from_env() -> Self {
+        dotenvy::dotenv().ok();
+
+        let error_detail = var_or("ERROR_DETAIL", "safe");
+
+        assert!(
+            matches!(error_detail.as_str(), "full" | "safe"),
+            "ERROR_DETAIL must be either full or safe"
+        );
+
+        let purge_backend = PurgeProvider::parse(&var_or("PURGE_BACKEND", "none"))
+            .expect("PURGE_BACKEND must be one of none, cloudflare or fastly");
+        let purge_zone = var_or("PURGE_ZONE", "");
+        let purge_api_token = var_or("PURGE_API_TOKEN", "");
+
+        assert!(
+            purge_backend == PurgeProvider::None
+                || !(purge_zone.is_empty() || purge_api_token.is_empty()),
+            "PURGE_ZONE and PURGE_API_TOKEN must be set along with PURGE_BACKEND"
+        );
+
+        Self {
+            signing_secret: var("SIGNING_SECRET").expect("SIGNING_SECRET must be set"),
+            policy_max_ttl: var_or("POLICY_MAX_TTL", "3600")
+                .parse()
+                .expect("POLICY_MAX_TTL must be a number of seconds"),
+            sign_max_ttl: var_or("SIGN_MAX_TTL", "604800")
+                .parse()
+                .expect("SIGN_MAX_TTL must be a number of seconds"),
+            policy_redirect_origins: var_list("POLICY_REDIRECT_ORIGINS"),
+            session_cookie_name: var_or("SESSION_COOKIE_NAME", "id"),
+            csrf_enforce: var_bool("CSRF_ENFORCE", true),
+            error_detail_full: error_detail == "full",
+            admin_usernames: var_list("ADMIN_USERNAMES"),
+            public_base_url: var_or("PUBLIC_BASE_URL", ""),
+            trusted_proxies: var_list("TRUSTED_PROXIES")
+                .iter()
+                .map(|proxy| {
+                    proxy
+                        .parse()
+                        .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                        .expect("TRUSTED_PROXIES must be a list of addresses or CIDR networks")
+                })
+                .collect(),
+            reject_unknown_hosts: var_bool("REJECT_UNKNOWN_HOSTS", false),
+            domain_verification_interval: var_or("DOMAIN_VERIFICATION_INTERVAL", "300")
+                .parse()
+                .expect("DOMAIN_VERIFICATION_INTERVAL must be a number of seconds"),
+            storage_summary_interval: var_or("STORAGE_SUMMARY_INTERVAL", "900")
+                .parse()
+                .expect("STORAGE_SUMMARY_INTERVAL must be a number of seconds"),
+            frame_options: var_or("FRAME_OPTIONS", "DENY"),
+            content_security_policy: var_or(
+                "CONTENT_SECURITY_POLICY",
+                "default-src 'none'; img-src 'self'; media-src 'self'; style-src 'unsafe-inline'",
+            ),
+            referrer_policy: var_or("REFERRER_POLICY", "no-referrer"),
+            strict_transport_security: var_or(
+                "STRICT_TRANSPORT_SECURITY",
+                "max-age=63072000; includeSubDomains",
+            ),
+            seo_allow_indexing: var_bool("SEO_ALLOW_INDEXING", false),
+            robots_txt: var_or("ROBOTS_TXT", ""),
+            log_spec: var_or("LOG_SPEC", "info"),
+            log_file: var_or("LOG_FILE", ""),
+            log_file_format: var_or("LOG_FILE_FORMAT", "plain"),
+            log_syslog: var_bool("LOG_SYSLOG", false),
+            log_tz: var_or("LOG_TZ", ""),
+            access_log_target: var_or("ACCESS_LOG_TARGET", "stdout"),
+            access_log_format: var_or("ACCESS_LOG_FORMAT", "combined"),
+            access_log_exclude: var_list("ACCESS_LOG_EXCLUDE"),
+            access_log_sample: var_list("ACCESS_LOG_SAMPLE")
+                .iter()
+                .map(|sample| {
+                    sample
+                        .rsplit_once('=')
+                        .and_then(|(pattern, rate)| {
+                            Some((pattern.trim().to_owned(), rate.trim().parse().ok()?))
+                        })
+                        .filter(|(_, rate)| *rate > 0)
+                        .expect("ACCESS_LOG_SAMPLE must be a list of pattern=N pairs")
+                })
+                .collect(),
+            compress_min_bytes: var_or("COMPRESS_MIN_BYTES", "1024")
+                .parse()
+                .expect("COMPRESS_MIN_BYTES must be a number of bytes"),
+            compress_gzip_level: var_or("COMPRESS_GZIP_LEVEL", "6")
+                .parse()
+                .ok()
+                .filter(|level| *level <= 9)
+                .expect("COMPRESS_GZIP_LEVEL must be a number from 0 to 9"),
+            compress_brotli_level: var_or("COMPRESS_BROTLI_LEVEL", "4")
+                .parse()
+                .ok()
+                .filter(|level| *level <= 11)
+                .expect("COMPRESS_BROTLI_LEVEL must be a number from 0 to 11"),
+            hash_algorithm: HashAlgorithm::parse(&var_or("HASH_ALGORITHM", "sha256"))
+                .expect("HASH_ALGORITHM must be one of sha256, blake3 or md5"),
+            require_if_match: var_bool("REQUIRE_IF_MATCH", false),
+            content_addressed_urls: var_bool("CONTENT_ADDRESSED_URLS", false),
+            surrogate_keys: var_bool("SURROGATE_KEYS", false),
+            purge_backend,
+            purge_zone,
+            purge_api_token,
+            file_id_salt: var_or("FILE_ID_SALT", ""),
+            normalize_text_encoding: var_bool("NORMALIZE_TEXT_ENCODING", false),
+            upload_collision: UploadCollision::parse(&var_or("UPLOAD_COLLISION", "reject"))
+                .expect("UPLOAD_COLLISION must be one of rename, overwrite or reject"),
+            not_found_cache_ttl: var_or("NOT_FOUND_CACHE_TTL", "10")
+                .parse()
+                .expect("NOT_FOUND_CACHE_TTL must be a number of seconds"),
+            file_cache_capacity: var_or("FILE_CACHE_CAPACITY", "1024")
+                .parse()
+                .expect("FILE_CACHE_CAPACITY must be a number of files"),
+            file_cache_ttl: var_or("FILE_CACHE_TTL", "5")
+                .parse()
+                .expect("FILE_CACHE_TTL must be a number of seconds"),
+            stat_cache_capacity: var_or("STAT_CACHE_CAPACITY", "4096")
+                .parse()
+                .expect("STAT_CACHE_CAPACITY must be a number of blobs"),
+            stat_cache_ttl: var_or("STAT_CACHE_TTL", "30")
+                .parse()
+                .expect("STAT_CACHE_TTL must be a number of seconds"),
+            max_header_count: var_or("MAX_HEADER_COUNT", "64")
+                .parse()
+                .expect("MAX_HEADER_COUNT must be a number of headers"),
+            max_header_bytes: var_or("MAX_HEADER_BYTES", "16384")
+                .parse()
+                .expect("MAX_HEADER_BYTES must be a number of bytes"),
+            allow_html_inline: var_bool("ALLOW_HTML_INLINE", false),
+            sandbox_base_url: var_or("SANDBOX_BASE_URL", ""),
+            task_max_failures: var_or("TASK_MAX_FAILURES", "5")
+                .parse()
+                .expect("TASK_MAX_FAILURES must be a number"),
+            task_backoff_max: var_or("TASK_BACKOFF_MAX", "300")
+                .parse()
+                .expect("TASK_BACKOFF_MAX must be a number of seconds"),
+            post_processors: match var("POST_PROCESSORS") {
+                Ok(_) => var_list("POST_PROCESSORS"),
+                Err(_) => vec!["dimensions".into(), "thumbnail".into(), "poster".into()],
+            },
+            av_scan_command: var_or("AV_SCAN_COMMAND", ""),
+            ffmpeg_path: var_or("FFMPEG_PATH", "ffmpeg"),
+            ffprobe_path: var_or("FFPROBE_PATH", "ffprobe"),
+            av_scan: var_bool("AV_SCAN", false),
+            clamd_address: var_or("CLAMD_ADDRESS", "127.0.0.1:3310"),
+            av_scan_fail_open: var_bool("AV_SCAN_FAIL_OPEN", false),
+            storage_path: var_or("STORAGE_PATH", "./storage"),
+            storage_max_attempts: var_or("STORAGE_MAX_ATTEMPTS", "4")
+                .parse()
+                .expect("STORAGE_MAX_ATTEMPTS must be a number"),
+            storage_retry_budget_ms: var_or("STORAGE_RETRY_BUDGET_MS", "10000")
+                .parse()
+                .expect("STORAGE_RETRY_BUDGET_MS must be a number of milliseconds"),
+            storage_request_budget_ms: var_or("STORAGE_REQUEST_BUDGET_MS", "30000")
+                .parse()
+                .expect("STORAGE_REQUEST_BUDGET_MS must be a number of milliseconds"),
+            master_key_file: var("MASTER_KEY_FILE").ok(),
+            previous_master_key_files: var_list("PREVIOUS_MASTER_KEY_FILES"),
+            export_path: var("EXPORT_PATH").ok(),
+            multipart_upload_ttl: var_or("MULTIPART_UPLOAD_TTL", "86400")
+                .parse()
+                .expect("MULTIPART_UPLOAD_TTL must be a number of seconds"),
+            max_upload_size: var_or("MAX_UPLOAD_SIZE", "104857600")
+                .parse()
+                .expect("MAX_UPLOAD_SIZE must be a number of bytes"),
+            upload_memory_threshold: var_or("UPLOAD_MEMORY_THRESHOLD", "262144")
+                .parse()
+                .expect("UPLOAD_MEMORY_THRESHOLD must be a number of bytes"),
+            upload_spool_path: var("UPLOAD_SPOOL_PATH")
+                .unwrap_or_else(|_| env::temp_dir().to_string_lossy().into_owned()),
+            orphan_grace_period: var_or("ORPHAN_GRACE_PERIOD", "86400")
+                .parse()
+                .expect("ORPHAN_GRACE_PERIOD must be a number of seconds"),
+            allow_anonymous_upload: var_bool("ALLOW_ANONYMOUS_UPLOAD", false),
+            anonymous_upload_ttl: var_or("ANONYMOUS_UPLOAD_TTL", "86400")
+                .parse()
+                .expect("ANONYMOUS_UPLOAD_TTL must be a number of seconds"),
+            max_image_dimension: var_or("MAX_IMAGE_DIMENSION", "16384")
+                .parse()
+                .expect("MAX_IMAGE_DIMENSION must be a number of pixels"),
+            max_appendable_size: var_or("MAX_APPENDABLE_SIZE", "1073741824")
+                .parse()
+                .expect("MAX_APPENDABLE_SIZE must be a number of bytes"),
+            delta_job_threshold: var_or("DELTA_JOB_THRESHOLD", "268435456")
+                .parse()
+                .expect("DELTA_JOB_THRESHOLD must be a number of bytes"),
+            upload_rate_limit: var_or("UPLOAD_RATE_LIMIT", "0")
+                .parse()
+                .expect("UPLOAD_RATE_LIMIT must be a number of uploads"),
+            upload_rate_burst: var_or("UPLOAD_RATE_BURST", "10")
+                .parse()
+                .expect("UPLOAD_RATE_BURST must be a number of uploads"),
+            abuse_check_interval: var_or("ABUSE_CHECK_INTERVAL", "60")
+                .parse()
+                .expect("ABUSE_CHECK_INTERVAL must be a number of seconds"),
+            abuse_wasted_percent: var_or("ABUSE_WASTED_PERCENT", "50")
+                .parse()
+                .expect("ABUSE_WASTED_PERCENT must be a percentage"),
+            abuse_min_wasted_bytes: var_or("ABUSE_MIN_WASTED_BYTES", "67108864")
+                .parse()
+                .expect("ABUSE_MIN_WASTED_BYTES must be a number of bytes"),
+            abuse_aborted_uploads: var_or("ABUSE_ABORTED_UPLOADS", "10")
+                .parse()
+                .expect("ABUSE_ABORTED_UPLOADS must be a number of uploads"),
+            abuse_rate_limit: var_or("ABUSE_RATE_LIMIT", "2")
+                .parse()
+                .expect("ABUSE_RATE_LIMIT must be a number of uploads"),
+            abuse_cooldown: var_or("ABUSE_COOLDOWN", "900")
+                .parse()
+                .expect("ABUSE_COOLDOWN must be a number of seconds"),
+            upload_concurrency: var_or("UPLOAD_CONCURRENCY", "5")
+                .parse()
+                .expect("UPLOAD_CONCURRENCY must be a number of uploads"),
+            paste_max_size: var_or("PASTE_MAX_SIZE", "10485760")
+                .parse()
+                .expect("PASTE_MAX_SIZE must be a number of bytes"),
+            paste_ttl: var_or("PASTE_TTL", "604800")
+                .parse()
+                .expect("PASTE_TTL must be a number of seconds"),
+            paste_rate_limit: var_or("PASTE_RATE_LIMIT", "0")
+                .parse()
+                .expect("PASTE_RATE_LIMIT must be a number of pastes"),
+            paste_rate_burst: var_or("PASTE_RATE_BURST", "30")
+                .parse()
+                .expect("PASTE_RATE_BURST must be a number of pastes"),
+            max_zip_files: var_or("MAX_ZIP_FILES", "100")
+                .parse()
+                .expect("MAX_ZIP_FILES must be a number of files"),
+            max_zip_size: var_or("MAX_ZIP_SIZE", "1073741824")
+                .parse()
+                .expect("MAX_ZIP_SIZE must be a number of bytes"),
+            feed_size: var_or("FEED_SIZE", "50")
+                .parse()
+                .expect("FEED_SIZE must be a number of files"),
+            webhook_rate_limit: var_or("WEBHOOK_RATE_LIMIT", "30")
+                .parse()
+                .expect("WEBHOOK_RATE_LIMIT must be a number of deliveries"),
+            webhook_disable_after: var_or("WEBHOOK_DISABLE_AFTER", "5")
+                .parse()
+                .expect("WEBHOOK_DISABLE_AFTER must be a number of deliveries"),
+            unique_viewers: var_bool("UNIQUE_VIEWERS", true),
+            stats_flush_interval: var_or("STATS_FLUSH_INTERVAL", "60")
+                .parse()
+                .expect("STATS_FLUSH_INTERVAL must be a number of seconds"),
+            read_only: var_bool("READ_ONLY", false),
+        }
+    }
+}
+
+/// The raw value of a setting, for those parsed outside of `Config`.
+pub fn setting(key: &str) -> Option<String> {
+    var(key).ok()
+}
+
+/// Reads a setting from the environment, then from `CONFIG_FILE`.
+fn var(key: &str) -> Result<String, VarError> {
+    env::var(key).or_else(|error| FILE.get_or_init(load_file).get(key).cloned().ok_or(error))
+}
+
+/// Loads `CONFIG_FILE`, empty when it's unset.
+fn load_file() -> HashMap<String, String> {
+    env::var("CONFIG_FILE").map(|path| parse_file(&path)).unwrap_or_default()
+}
+
+/// Parses the file at `path` as JSON when its extension says so and as TOML otherwise,
+/// keys may be lowercase and lists are arrays, invalid files abort startup.
+fn parse_file(path: &str) -> HashMap<String, String> {
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("CONFIG_FILE {path} can't be read: {error}"));
+
+    let table: HashMap<String, serde_json::Value> = if path.ends_with(".json") {
+        serde_json::from_str(&content).unwrap_or_else(|error| {
+            let line = content.lines().nth(error.line().saturating_sub(1)).unwrap_or_default();
+            panic!("CONFIG_FILE {path} is invalid: {error}\n{line}")
+        })
+    } else {
+        // TOML errors already quote the offending line.
+        toml::from_str(&content)
+            .unwrap_or_else(|error| panic!("CONFIG_FILE {path} is invalid: {error}"))
+    };
+
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                serde_json::Value::Array(items) => items
+                    .iter()
+                    .map(|item| item.as_str().map_or_else(|| item.to_string(), From::from))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                serde_json::Value::Object(_) | serde_json::Value::Null => {
+                    panic!("CONFIG_FILE {path} sets {key} to a table or null, settings are flat")
+                },
+                value => value.to_string(),
+            };
+
+            (key.to_ascii_uppercase(), value)
+        })
+        .collect()
+}
+
+/// Reads an environment variable falling back to `default` when unset.
+fn var_or(key: &str, default: &str) -> String {
+    var(key).unwrap_or_else(|_| default.to_owned())
+}
+
+/// Reads a boolean environment variable, accepting `true`/`1`/`yes`.
+fn var_bool(key: &str, default: bool) -> bool {
+    var(key)
+        .map(|value| matches!(value.to_ascii_lowercase().as_str(), "true" | "1" | "yes"))
+        .unwrap_or(default)
+}
+
+/// Reads a comma separated environment variable, empty when unset.
+fn var_list(key: &str) -> Vec<String> {
+    var(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(From::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+
+    /// Writes `content` to a file named `name` that's unique to the test.
+    fn write(name: &str, content: &str) -> String {
+        let path = temp_dir().join(format!("{}-{name}", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn toml_keys_are_uppercased_and_arrays_joined() {
+        let path = write(
+            "config.toml",
+            "signing_secret = \"secret\"\nDB_POOL_SIZE = 12\nadmin_usernames = [\"ana\", \"bo\"]\n",
+        );
+        let file = parse_file(&path);
+
+        assert_eq!(file["SIGNING_SECRET"], "secret");
+        assert_eq!(file["DB_POOL_SIZE"], "12");
+        assert_eq!(file["ADMIN_USERNAMES"], "ana,bo");
+    }
+
+    #[test]
+    fn json_is_read_by_its_extension() {
+        let path =
+            write("config.json", r#"{"read_only": true, "trusted_proxies": ["10.0.0.0/8"]}"#);
+        let file = parse_file(&path);
+
+        assert_eq!(file["READ_ONLY"], "true");
+        assert_eq!(file["TRUSTED_PROXIES"], "10.0.0.0/8");
+    }
+
+    #[test]
+    #[should_panic(expected = "settings are flat")]
+    fn tables_are_refused() {
+        parse_file(&write("nested.toml", "[storage]\npath = \"/srv\"\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "is invalid")]
+    fn invalid_files_abort() {
+        parse_file(&write("invalid.json", "{\"read_only\": }"));
+    }
+}
@@ -0,0 +1,372 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use actix_web::http::StatusCode;
+use database::DatabaseError;
+use serde::Serialize;
+use storage::StorageError;
+
+use crate::AppError;
+
+/// Declares `ErrorCode` along with `ErrorCode::ALL`, so
+/// the list can't miss or repeat a variant.
+macro_rules! error_codes {
+    ($(#[$meta:meta])* pub enum $name:ident { $($variant:ident,)* }) => {
+        $(#[$meta])*
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl $name {
+            /// Every code in declaration order.
+            pub const ALL: [$name; [$(stringify!($variant)),*].len()] = [$($name::$variant),*];
+        }
+    };
+}
+
+error_codes! {
+    /// Machine readable error codes, every error a route can
+    /// answer with maps to exactly one of these.
+    ///
+    /// The mappings below match exhaustively on every error variant,
+    /// so adding an error without registering its code fails to compile.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ErrorCode {
+        NotFound,
+        GoneExpired,
+        GoneRevoked,
+        ForbiddenPrivate,
+        ForbiddenScope,
+        CsrfInvalid,
+        Unauthorized,
+        AccountDisabled,
+        RateLimited,
+        InvalidRequest,
+        PreconditionRequired,
+        PreconditionFailed,
+        LastAdmin,
+        NameTaken,
+        HostnameTaken,
+        RangeNotSatisfiable,
+        PayloadTooLarge,
+        HeadersTooLarge,
+        UploadRejected,
+        DeltaMismatch,
+        MalwareDetected,
+        ImageTooLarge,
+        ReadOnly,
+        SignatureInvalid,
+        SignatureExpired,
+        SignatureWrongIp,
+        SignatureWrongClient,
+        SignatureUsesExhausted,
+        SignatureRevoked,
+        PolicyInvalid,
+        PolicyExpired,
+        PolicyViolation,
+        MalformedMultipart,
+        StorageUnavailable,
+        NotReady,
+        QueryTimeout,
+        NotImplemented,
+        Internal,
+    }
+}
+
+/// A registry entry as served by `GET /meta/error-codes`.
+#[derive(Serialize)]
+pub struct ErrorCodeEntry {
+    code: &'static str,
+    status: u16,
+    description: &'static str,
+}
+
+impl ErrorCode {
+    /// Private resources probed without authentication are reported
+    /// as missing, so their existence isn't leaked.
+    pub fn private_probe(authenticated: bool) -> Self {
+        if authenticated { ErrorCode::ForbiddenPrivate } else { ErrorCode::NotFound }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::GoneExpired => "GONE_EXPIRED",
+            ErrorCode::GoneRevoked => "GONE_REVOKED",
+            ErrorCode::ForbiddenPrivate => "FORBIDDEN_PRIVATE",
+            ErrorCode::ForbiddenScope => "FORBIDDEN_SCOPE",
+            ErrorCode::CsrfInvalid => "CSRF_INVALID",
+            ErrorCode::Unauthorized => "UNAUTHORIZED",
+            ErrorCode::AccountDisabled => "ACCOUNT_DISABLED",
+            ErrorCode::RateLimited => "RATE_LIMITED",
+            ErrorCode::InvalidRequest => "INVALID_REQUEST",
+            ErrorCode::PreconditionRequired => "PRECONDITION_REQUIRED",
+            ErrorCode::PreconditionFailed => "PRECONDITION_FAILED",
+            ErrorCode::LastAdmin => "LAST_ADMIN",
+            ErrorCode::NameTaken => "NAME_TAKEN",
+            ErrorCode::HostnameTaken => "HOSTNAME_TAKEN",
+            ErrorCode::RangeNotSatisfiable => "RANGE_NOT_SATISFIABLE",
+            ErrorCode::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            ErrorCode::HeadersTooLarge => "HEADERS_TOO_LARGE",
+            ErrorCode::UploadRejected => "UPLOAD_REJECTED",
+            ErrorCode::DeltaMismatch => "DELTA_MISMATCH",
+            ErrorCode::MalwareDetected => "MALWARE_DETECTED",
+            ErrorCode::ImageTooLarge => "IMAGE_TOO_LARGE",
+            ErrorCode::ReadOnly => "READ_ONLY",
+            ErrorCode::SignatureInvalid => "SIGNATURE_INVALID",
+            ErrorCode::SignatureExpired => "SIGNATURE_EXPIRED",
+            ErrorCode::SignatureWrongIp => "SIGNATURE_WRONG_IP",
+            ErrorCode::SignatureWrongClient => "SIGNATURE_WRONG_CLIENT",
+            ErrorCode::SignatureUsesExhausted => "SIGNATURE_USES_EXHAUSTED",
+            ErrorCode::SignatureRevoked => "SIGNATURE_REVOKED",
+            ErrorCode::PolicyInvalid => "POLICY_INVALID",
+            ErrorCode::PolicyExpired => "POLICY_EXPIRED",
+            ErrorCode::PolicyViolation => "POLICY_VIOLATION",
+            ErrorCode::MalformedMultipart => "MALFORMED_MULTIPART",
+            ErrorCode::StorageUnavailable => "STORAGE_UNAVAILABLE",
+            ErrorCode::NotReady => "NOT_READY",
+            ErrorCode::QueryTimeout => "QUERY_TIMEOUT",
+            ErrorCode::NotImplemented => "NOT_IMPLEMENTED",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::GoneExpired | ErrorCode::GoneRevoked => StatusCode::GONE,
+            ErrorCode::ForbiddenPrivate
+            | ErrorCode::ForbiddenScope
+            | ErrorCode::CsrfInvalid
+            | ErrorCode::AccountDisabled
+            | ErrorCode::SignatureInvalid
+            | ErrorCode::SignatureExpired
+            | ErrorCode::SignatureWrongIp
+            | ErrorCode::SignatureWrongClient
+            | ErrorCode::SignatureUsesExhausted
+            | ErrorCode::SignatureRevoked
+            | ErrorCode::PolicyInvalid
+            | ErrorCode::PolicyExpired => StatusCode::FORBIDDEN,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::InvalidRequest
+            | ErrorCode::PolicyViolation
+            | ErrorCode::MalformedMultipart => StatusCode::BAD_REQUEST,
+            ErrorCode::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            ErrorCode::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            ErrorCode::LastAdmin | ErrorCode::NameTaken | ErrorCode::HostnameTaken => {
+                StatusCode::CONFLICT
+            },
+            ErrorCode::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::HeadersTooLarge => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ErrorCode::UploadRejected
+            | ErrorCode::DeltaMismatch
+            | ErrorCode::MalwareDetected
+            | ErrorCode::ImageTooLarge => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::ReadOnly => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::StorageUnavailable | ErrorCode::NotReady => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::QueryTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::NotImplemented => StatusCode::NOT_IMPLEMENTED,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "The resource never existed or can't be disclosed",
+            ErrorCode::GoneExpired => "The resource existed but its lifetime ran out",
+            ErrorCode::GoneRevoked => "The link was replaced by its owner",
+            ErrorCode::ForbiddenPrivate => "The resource is private to its owner",
+            ErrorCode::ForbiddenScope => "The credentials lack the scope for this operation",
+            ErrorCode::CsrfInvalid => "The CSRF token is missing or doesn't match the session",
+            ErrorCode::Unauthorized => "Authentication is required",
+            ErrorCode::AccountDisabled => "The account was disabled by an admin",
+            ErrorCode::RateLimited => "Too many uploads, retry after the time given",
+            ErrorCode::InvalidRequest => "The request is malformed",
+            ErrorCode::PreconditionRequired => "The mutation requires an If-Match header",
+            ErrorCode::PreconditionFailed => "The resource changed since it was read",
+            ErrorCode::LastAdmin => "The last admin can't be demoted",
+            ErrorCode::NameTaken => "A file already exists under that name in the bucket",
+            ErrorCode::HostnameTaken => "The hostname was verified by another user",
+            ErrorCode::RangeNotSatisfiable => "The requested range is outside of the resource",
+            ErrorCode::PayloadTooLarge => "The resource would grow past its size limit",
+            ErrorCode::HeadersTooLarge => "The request headers are too many or too long",
+            ErrorCode::UploadRejected => "The upload was refused by a post processor",
+            ErrorCode::DeltaMismatch => {
+                "The patched content doesn't match its checksum, upload the whole file"
+            },
+            ErrorCode::MalwareDetected => "The upload was found to contain malware",
+            ErrorCode::ImageTooLarge => "The image is wider or taller than allowed",
+            ErrorCode::ReadOnly => "The node only serves content and refuses writes",
+            ErrorCode::SignatureInvalid => "The signed URL is malformed or was tampered with",
+            ErrorCode::SignatureExpired => "The signed URL has expired",
+            ErrorCode::SignatureWrongIp => "The signed URL is bound to another network",
+            ErrorCode::SignatureWrongClient => "The signed URL is bound to another client",
+            ErrorCode::SignatureUsesExhausted => "The signed URL has no uses left",
+            ErrorCode::SignatureRevoked => "The signed URL predates a link rotation",
+            ErrorCode::PolicyInvalid => "The upload policy is malformed or was tampered with",
+            ErrorCode::PolicyExpired => "The upload policy has expired",
+            ErrorCode::PolicyViolation => "The upload doesn't meet the conditions of its policy",
+            ErrorCode::MalformedMultipart => "The multipart form body couldn't be parsed",
+            ErrorCode::StorageUnavailable => "The storage backend is temporarily unavailable",
+            ErrorCode::NotReady => "The node is still starting up and refuses writes",
+            ErrorCode::QueryTimeout => "The database took too long to answer",
+            ErrorCode::NotImplemented => "The server doesn't support this option",
+            ErrorCode::Internal => "An unexpected error happened",
+        }
+    }
+
+    pub fn entry(&self) -> ErrorCodeEntry {
+        ErrorCodeEntry {
+            code: self.as_str(),
+            status: self.status().as_u16(),
+            description: self.description(),
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&AppError> for ErrorCode {
+    fn from(error: &AppError) -> Self {
+        match error {
+            AppError::Io(_)
+            | AppError::LoggerError(_)
+            | AppError::Lifecycle(_)
+            | AppError::JobCancelled
+            | AppError::WebhookDeliveryFailed(_)
+            | AppError::PurgeFailed(_)
+            | AppError::ExportPathUnsafe(_) => ErrorCode::Internal,
+            AppError::Database(error) => error.into(),
+            AppError::Storage(error) => error.into(),
+            AppError::FileNotFound(_) => ErrorCode::NotFound,
+            AppError::LinkRevoked => ErrorCode::GoneRevoked,
+            AppError::FileExpired => ErrorCode::GoneExpired,
+            AppError::Private { authenticated, .. } => ErrorCode::private_probe(*authenticated),
+            AppError::AuthorizationError | AppError::BasicAuthRequired => ErrorCode::Unauthorized,
+            AppError::AccountDisabled => ErrorCode::AccountDisabled,
+            AppError::AdminRequired => ErrorCode::ForbiddenScope,
+            AppError::RateLimited(_) => ErrorCode::RateLimited,
+            AppError::PreconditionRequired => ErrorCode::PreconditionRequired,
+            AppError::InvalidIfMatch
+            | AppError::InvalidHeader(_)
+            | AppError::InvalidHostname
+            | AppError::InvalidTag
+            | AppError::TooManyTags(_)
+            | AppError::InvalidLicense(_)
+            | AppError::InvalidAttribution
+            | AppError::InvalidSourceUrl
+            | AppError::InvalidMaxUses
+            | AppError::NotVideo
+            | AppError::InvalidTimestamp
+            | AppError::InvalidCursor
+            | AppError::ContentTypeMismatch(..)
+            | AppError::NotAppendable
+            | AppError::AppendLooksSealed
+            | AppError::InvalidDelta(_)
+            | AppError::InvalidWebhookUrl
+            | AppError::WebhookTargetForbidden(_)
+            | AppError::InvalidWebhookEvent(_)
+            | AppError::TooManyWebhooks(_)
+            | AppError::BucketNotPublic
+            | AppError::InvalidBaseUrl
+            | AppError::InvalidSurrogateKey(_) => ErrorCode::InvalidRequest,
+            AppError::HeadersTooLarge(..) => ErrorCode::HeadersTooLarge,
+            AppError::AppendTooLarge(_)
+            | AppError::UploadTooLarge(_)
+            | AppError::ArchiveTooLarge(..)
+            | AppError::DeltaTooLarge(_) => ErrorCode::PayloadTooLarge,
+            AppError::DeltaConflict => ErrorCode::PreconditionFailed,
+            AppError::DeltaMismatch => ErrorCode::DeltaMismatch,
+            AppError::UploadRejected(_) => ErrorCode::UploadRejected,
+            AppError::MalwareDetected(_) => ErrorCode::MalwareDetected,
+            AppError::ImageTooLarge(_) => ErrorCode::ImageTooLarge,
+            AppError::RangeNotSatisfiable(_) => ErrorCode::RangeNotSatisfiable,
+            AppError::ReadOnly => ErrorCode::ReadOnly,
+            AppError::NotReady => ErrorCode::NotReady,
+            AppError::UnsupportedFormat | AppError::ExportDisabled | AppError::PurgeDisabled => {
+                ErrorCode::NotImplemented
+            },
+            AppError::CsrfTokenInvalid => ErrorCode::CsrfInvalid,
+            AppError::SignatureInvalid => ErrorCode::SignatureInvalid,
+            AppError::SignatureExpired => ErrorCode::SignatureExpired,
+            AppError::SignatureWrongIp => ErrorCode::SignatureWrongIp,
+            AppError::SignatureWrongClient => ErrorCode::SignatureWrongClient,
+            AppError::SignatureUsesExhausted => ErrorCode::SignatureUsesExhausted,
+            AppError::SignatureRevoked => ErrorCode::SignatureRevoked,
+            AppError::PolicyInvalid => ErrorCode::PolicyInvalid,
+            AppError::PolicyExpired => ErrorCode::PolicyExpired,
+            AppError::PolicyViolation(_) => ErrorCode::PolicyViolation,
+            AppError::RedirectNotAllowed => ErrorCode::InvalidRequest,
+            AppError::MalformedMultipart(_) => ErrorCode::MalformedMultipart,
+        }
+    }
+}
+
+impl From<&DatabaseError> for ErrorCode {
+    fn from(error: &DatabaseError) -> Self {
+        match error {
+            DatabaseError::DatabaseQuery(sqlx::Error::RowNotFound) => ErrorCode::NotFound,
+            DatabaseError::DatabaseQuery(_)
+            | DatabaseError::DatabaseConnectionError(_)
+            | DatabaseError::BlobRefUnderflow(_) => ErrorCode::Internal,
+            DatabaseError::ModelNotFound(_) => ErrorCode::NotFound,
+            DatabaseError::QueryTimeout(_) => ErrorCode::QueryTimeout,
+            DatabaseError::LastAdmin => ErrorCode::LastAdmin,
+            DatabaseError::PathTaken(_) => ErrorCode::NameTaken,
+            DatabaseError::HostnameTaken => ErrorCode::HostnameTaken,
+            DatabaseError::PhaseRefused(_) => ErrorCode::PreconditionFailed,
+        }
+    }
+}
+
+impl From<&StorageError> for ErrorCode {
+    fn from(error: &StorageError) -> Self {
+        match error {
+            StorageError::NotFound(_) => ErrorCode::NotFound,
+            StorageError::Throttled | StorageError::Timeout | StorageError::CircuitOpen(_) => {
+                ErrorCode::StorageUnavailable
+            },
+            StorageError::Io(_)
+            | StorageError::Backend(_)
+            | StorageError::Decryption(_)
+            | StorageError::UnknownKey(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn codes_are_unique() {
+        let codes = ErrorCode::ALL.iter().map(ErrorCode::as_str).collect::<HashSet<_>>();
+
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+    }
+
+    #[test]
+    fn codes_spell_their_variant() {
+        for code in ErrorCode::ALL {
+            let mut spelled = String::new();
+
+            for character in format!("{code:?}").chars() {
+                if character.is_ascii_uppercase() && !spelled.is_empty() {
+                    spelled.push('_');
+                }
+
+                spelled.push(character.to_ascii_uppercase());
+            }
+
+            assert_eq!(code.as_str(), spelled);
+            assert_eq!(code.to_string(), spelled);
+        }
+    }
+}
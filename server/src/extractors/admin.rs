@@ -0,0 +1,63 @@
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use database::{Role, UserModel};
+use futures::future::LocalBoxFuture;
+
+use crate::AppError;
+use crate::config::Config;
+
+/// A user with the admin role or listed in `ADMIN_USERNAMES`.
+pub struct AdminUser(pub UserModel);
+
+impl FromRequest for AdminUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = UserModel::from_request(req, payload);
+
+        Box::pin(async move { require_admin(user.await?).await })
+    }
+}
+
+/// Checks the role of `user` in the database rather than the copy the
+/// session holds, which keeps the role it had at login.
+pub async fn require_admin(user: UserModel) -> Result<AdminUser, AppError> {
+    let admin = Config::get().admin_usernames.iter().any(|admin| admin == user.username())
+        || UserModel::role_by_id(user.id()).await? == Role::Admin;
+
+    if !admin {
+        return Err(AppError::AdminRequired);
+    }
+
+    Ok(AdminUser(user))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::ResponseError;
+    use actix_web::http::StatusCode;
+
+    use super::*;
+    use crate::testing::{create_user, run, unique_name};
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn demoted_admins_are_refused_with_their_old_session() {
+        run(async {
+            let user = create_user(&unique_name("admin")).await;
+            let session = user.set_role(Role::Admin).await.unwrap();
+            // Someone else stays admin, the last one can't be demoted.
+            create_user(&unique_name("admin")).await.set_role(Role::Admin).await.unwrap();
+
+            assert!(require_admin(UserModel::get(session.id()).await.unwrap()).await.is_ok());
+
+            session.set_role(Role::User).await.unwrap();
+
+            // The session still says admin, the database doesn't.
+            assert!(session.role() == Role::Admin);
+            let error = require_admin(session).await.err().unwrap();
+            assert_eq!(error.status_code(), StatusCode::FORBIDDEN);
+        })
+    }
+}
@@ -1,25 +1,97 @@
-use std::future::{Ready, ready};
-
 use actix_identity::Identity;
 use actix_web::dev::Payload;
-use actix_web::{Error, FromRequest, HttpRequest};
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
 use database::UserModel;
+use futures::future::LocalBoxFuture;
 
 use crate::AppError;
 
+/// The user of a route anonymous callers may use too, `None` without a session.
+///
+/// Unlike `Option<UserModel>`, which makes any failure anonymous, a disabled
+/// account or a failed lookup is answered with its error.
+pub struct OptionalUser(pub Option<UserModel>);
+
+impl OptionalUser {
+    /// Only a missing or unreadable session is anonymous.
+    fn from_result(user: Result<UserModel, AppError>) -> Result<Self, AppError> {
+        match user {
+            Ok(user) => Ok(OptionalUser(Some(user))),
+            Err(AppError::AuthorizationError) => Ok(OptionalUser(None)),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl FromRequest for OptionalUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = UserModel::from_request(req, payload);
+
+        Box::pin(async move { OptionalUser::from_result(user.await) })
+    }
+}
+
+/// Stored in the request extensions once a user was
+/// authenticated, so middlewares can tell who made the request.
+pub struct AuthenticatedUser(pub i64);
+
 impl FromRequest for UserModel {
-    type Error = Error;
-    type Future = Ready<Result<UserModel, AppError>>;
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<UserModel, AppError>>;
 
     fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
-        if let Ok(identify) = Identity::from_request(req, payload).into_inner() {
-            if let Ok(user_json) = identify.id() {
-                if let Ok(user) = eserde::json::from_str(&user_json) {
-                    return ready(Ok(UserModel::from(user)));
-                }
+        let user = Identity::from_request(req, payload)
+            .into_inner()
+            .ok()
+            .and_then(|identify| identify.id().ok())
+            .and_then(|user_json| eserde::json::from_str::<UserModel>(&user_json).ok());
+
+        let Some(user) = user else {
+            return Box::pin(async { Err(AppError::AuthorizationError) });
+        };
+
+        req.extensions_mut().insert(AuthenticatedUser(user.id()));
+
+        Box::pin(async move {
+            // The session was issued before the user may have been disabled.
+            if !UserModel::is_active_by_id(user.id()).await? {
+                return Err(AppError::AccountDisabled);
             }
-        }
 
-        ready(Err(AppError::AuthorizationError.into()))
+            Ok(user)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::ResponseError;
+    use actix_web::http::StatusCode;
+    use database::DatabaseError;
+
+    use super::*;
+
+    #[test]
+    fn requests_without_a_session_are_anonymous() {
+        let user = OptionalUser::from_result(Err(AppError::AuthorizationError)).unwrap();
+
+        assert!(user.0.is_none());
+    }
+
+    #[test]
+    fn disabled_accounts_are_refused_rather_than_anonymous() {
+        let error = OptionalUser::from_result(Err(AppError::AccountDisabled)).err().unwrap();
+
+        assert_eq!(error.status_code(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn failed_lookups_are_not_anonymous() {
+        let error = AppError::Database(DatabaseError::ModelNotFound("user"));
+
+        assert!(OptionalUser::from_result(Err(error)).is_err());
     }
 }
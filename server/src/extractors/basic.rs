@@ -0,0 +1,49 @@
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use database::{ApiKeyModel, UserModel};
+use futures::future::LocalBoxFuture;
+
+use crate::AppError;
+use crate::extractors::auth::AuthenticatedUser;
+use crate::utils::headers::single_header_value;
+
+/// A user authenticated with HTTP Basic auth, where the password
+/// is one of their API keys and never the account password.
+///
+/// Meant for clients that can't do cookie flows, such as WebDAV.
+pub struct BasicAuthUser(pub UserModel);
+
+impl FromRequest for BasicAuthUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        // A malformed or repeated header is treated as missing credentials.
+        let secret = single_header_value(req, AUTHORIZATION)
+            .ok()
+            .flatten()
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|credentials| STANDARD.decode(credentials.trim()).ok())
+            .and_then(|credentials| String::from_utf8(credentials).ok())
+            .and_then(|credentials| Some(credentials.split_once(':')?.1.to_owned()));
+
+        let req = req.clone();
+
+        Box::pin(async move {
+            let secret = secret.ok_or(AppError::BasicAuthRequired)?;
+            let user = ApiKeyModel::authenticate(&secret)
+                .await
+                .map_err(|_| AppError::BasicAuthRequired)?;
+
+            if !user.is_active() {
+                return Err(AppError::AccountDisabled);
+            }
+
+            req.extensions_mut().insert(AuthenticatedUser(user.id()));
+            Ok(BasicAuthUser(user))
+        })
+    }
+}
@@ -0,0 +1,64 @@
+use std::future::{Ready, ready};
+use std::ops::Deref;
+
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use database::DatabaseError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::AppError;
+use crate::utils::file_id::{decode, enabled, encode};
+
+/// The `{id}` of a file route, decoded from its obfuscated form
+/// when `FILE_ID_SALT` is set, ids that don't decode aren't found.
+#[derive(Clone, Copy)]
+pub struct FileId(pub i64);
+
+impl Deref for FileId {
+    type Target = i64;
+
+    fn deref(&self) -> &i64 {
+        &self.0
+    }
+}
+
+impl FromRequest for FileId {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req.match_info().get("id").and_then(decode).map(FileId);
+
+        ready(id.ok_or_else(|| DatabaseError::ModelNotFound("file").into()))
+    }
+}
+
+impl Serialize for FileId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if enabled() {
+            serializer.serialize_str(&encode(self.0))
+        } else {
+            serializer.serialize_i64(self.0)
+        }
+    }
+}
+
+/// Ids in request bodies, numbers when they aren't obfuscated and strings otherwise.
+impl<'de> Deserialize<'de> for FileId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Numeric(i64),
+            Encoded(String),
+        }
+
+        let decoded = match Raw::deserialize(deserializer)? {
+            Raw::Numeric(id) if !enabled() => decode(&id.to_string()),
+            Raw::Encoded(encoded) if enabled() => decode(&encoded),
+            _ => None,
+        };
+
+        decoded.map(FileId).ok_or_else(|| serde::de::Error::custom("unknown file id"))
+    }
+}
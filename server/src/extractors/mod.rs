@@ -1 +1,4 @@
+pub mod admin;
 pub mod auth;
+pub mod basic;
+pub mod file_id;
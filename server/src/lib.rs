@@ -1,13 +1,38 @@
 use std::io::Error as IoError;
 
-use actix_error_proc::ActixError;
+use actix_multipart::MultipartError;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ALLOW, CACHE_CONTROL, CONTENT_RANGE, RETRY_AFTER, WWW_AUTHENTICATE};
+use actix_web::{HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use database::DatabaseError;
 use flexi_logger::FlexiLoggerError;
+use serde::Serialize;
+use storage::StorageError;
 use thiserror::Error as ThisError;
 
+use crate::config::Config;
+use crate::error_code::ErrorCode;
+use crate::lifecycle::LifecycleError;
+use crate::utils::headers::HeaderError;
+use crate::utils::license::LICENSES;
+use crate::utils::upload_limit::{RateLimit, ceil_secs};
+use crate::utils::webhooks::WebhookEvent;
+
+pub mod cli;
+pub mod config;
+pub mod error_code;
 pub mod extractors;
+pub mod lifecycle;
+pub mod middleware;
+pub mod processing;
 pub mod routes;
+pub mod tasks;
+#[cfg(test)]
+mod testing;
+pub mod utils;
 
-#[derive(Debug, ThisError, ActixError)]
+#[derive(Debug, ThisError)]
 pub enum AppError {
     #[error("{0:#}")]
     Io(#[from] IoError),
@@ -15,7 +40,390 @@ pub enum AppError {
     #[error("0:#")]
     LoggerError(#[from] FlexiLoggerError),
 
-    #[http_status(Unauthorized)]
+    #[error("{0:#}")]
+    Database(#[from] DatabaseError),
+
+    #[error("{0:#}")]
+    Lifecycle(#[from] LifecycleError),
+
+    #[error("{0:#}")]
+    Storage(#[from] StorageError),
+
+    #[error("No file found with that slug")]
+    FileNotFound(u64),
+
+    #[error("This link was revoked by its owner")]
+    LinkRevoked,
+
+    #[error("This file has expired")]
+    FileExpired,
+
+    /// Worded like `ModelNotFound` for anonymous callers, who are told it's missing.
+    #[error("{}", private_message(.resource, *.authenticated))]
+    Private {
+        resource: &'static str,
+        authenticated: bool,
+    },
+
     #[error("You are not authorized to access this resource")]
     AuthorizationError,
+
+    #[error("This account was disabled")]
+    AccountDisabled,
+
+    #[error("Only admins may access this resource")]
+    AdminRequired,
+
+    #[error("Too many uploads, try again in {} seconds", ceil_secs(.0.retry_after))]
+    RateLimited(RateLimit),
+
+    #[error("Authenticate with an API key as the Basic auth password")]
+    BasicAuthRequired,
+
+    #[error("This resource can only be modified with an If-Match header")]
+    PreconditionRequired,
+
+    #[error("The If-Match header must hold a version ETag")]
+    InvalidIfMatch,
+
+    #[error("{0}")]
+    InvalidHeader(#[from] HeaderError),
+
+    #[error("The request has {0} header lines taking {1} bytes, past the limits")]
+    HeadersTooLarge(usize, usize),
+
+    #[error("The hostname is not a valid domain name")]
+    InvalidHostname,
+
+    #[error("Tags must be 1 to 64 characters long without control characters")]
+    InvalidTag,
+
+    #[error("Files can't have more than {0} tags")]
+    TooManyTags(usize),
+
+    #[error("Unknown license {}, accepted ones are {}", .0, LICENSES.join(", "))]
+    InvalidLicense(String),
+
+    #[error("Attributions must be at most 1024 characters without control characters")]
+    InvalidAttribution,
+
+    #[error("The source URL must be an absolute http or https URL")]
+    InvalidSourceUrl,
+
+    #[error("Only the JSON format is supported")]
+    UnsupportedFormat,
+
+    #[error("The job was cancelled")]
+    JobCancelled,
+
+    #[error("This node is read only")]
+    ReadOnly,
+
+    #[error("This node isn't ready yet")]
+    NotReady,
+
+    #[error("Posters can only be extracted from videos")]
+    NotVideo,
+
+    #[error("The timestamp must be a number of seconds such as 12.5s")]
+    InvalidTimestamp,
+
+    #[error("The cursor is malformed or was issued for another sort")]
+    InvalidCursor,
+
+    #[error("Only files uploaded as appendable can be appended to")]
+    NotAppendable,
+
+    #[error("Appendable files can't grow past {0} bytes")]
+    AppendTooLarge(u64),
+
+    #[error("Uploads can't be larger than {0} bytes")]
+    UploadTooLarge(usize),
+
+    #[error("The content was declared as {0} but looks like {1}")]
+    ContentTypeMismatch(String, &'static str),
+
+    #[error("The delta is malformed, {0}")]
+    InvalidDelta(&'static str),
+
+    #[error("The patched content doesn't match the checksum, upload the whole file instead")]
+    DeltaMismatch,
+
+    #[error("Patched files can't grow past {0} bytes")]
+    DeltaTooLarge(u64),
+
+    #[error("The file changed while the delta was applied, fetch a new signature")]
+    DeltaConflict,
+
+    #[error("Zip downloads are limited to {0} files and {1} bytes")]
+    ArchiveTooLarge(usize, u64),
+
+    #[error("Appendable files can't start like an encrypted blob")]
+    AppendLooksSealed,
+
+    #[error("The upload was rejected by the {0} post processor")]
+    UploadRejected(&'static str),
+
+    #[error("The upload was rejected, it matched {0}")]
+    MalwareDetected(String),
+
+    #[error("Images can't be wider or taller than {0} pixels")]
+    ImageTooLarge(u32),
+
+    #[error("The requested range is outside of the {0} bytes stored")]
+    RangeNotSatisfiable(u64),
+
+    #[error("Mutations authenticated by the session need a valid X-CSRF-Token header")]
+    CsrfTokenInvalid,
+
+    #[error("The signed URL is malformed or was tampered with")]
+    SignatureInvalid,
+
+    #[error("The signed URL has expired")]
+    SignatureExpired,
+
+    #[error("The signed URL is bound to another network")]
+    SignatureWrongIp,
+
+    #[error("The signed URL is bound to another client")]
+    SignatureWrongClient,
+
+    #[error("The signed URL has no uses left")]
+    SignatureUsesExhausted,
+
+    #[error("A signed URL must allow at least one use")]
+    InvalidMaxUses,
+
+    #[error("The signed URL was revoked when its file link was rotated")]
+    SignatureRevoked,
+
+    #[error("The upload policy is malformed or was tampered with")]
+    PolicyInvalid,
+
+    #[error("The upload policy has expired")]
+    PolicyExpired,
+
+    #[error("The upload doesn't meet its policy: {0}")]
+    PolicyViolation(String),
+
+    #[error("The redirect target isn't an allowed origin")]
+    RedirectNotAllowed,
+
+    #[error("The multipart body is malformed: {0}")]
+    MalformedMultipart(String),
+
+    #[error("Webhook URLs must be absolute http or https URLs of a resolvable host")]
+    InvalidWebhookUrl,
+
+    #[error("Webhooks can't target {0}, only public addresses are allowed")]
+    WebhookTargetForbidden(String),
+
+    #[error(
+        "Unknown webhook event {:?}, accepted ones are {}",
+        .0,
+        WebhookEvent::ALL.map(WebhookEvent::as_str).join(", ")
+    )]
+    InvalidWebhookEvent(String),
+
+    #[error("Users can't have more than {0} webhooks")]
+    TooManyWebhooks(i64),
+
+    #[error("The webhook delivery failed: {0}")]
+    WebhookDeliveryFailed(String),
+
+    #[error("Purging the cache failed: {0}")]
+    PurgeFailed(String),
+
+    #[error("Purging is disabled, PURGE_BACKEND isn't set")]
+    PurgeDisabled,
+
+    #[error("Invalid surrogate key {0:?}, keys are letters, digits, dashes and underscores")]
+    InvalidSurrogateKey(String),
+
+    #[error("Only public buckets can be exported")]
+    BucketNotPublic,
+
+    #[error("The base URL must be an absolute http or https URL")]
+    InvalidBaseUrl,
+
+    #[error("Static exports are disabled, EXPORT_PATH isn't set")]
+    ExportDisabled,
+
+    #[error("{0} is a symlink or in the way of the export")]
+    ExportPathUnsafe(String),
+}
+
+/// Multipart parse failures are the client's, so they are answered with
+/// `MALFORMED_MULTIPART` rather than actix's unstructured 400.
+impl From<MultipartError> for AppError {
+    fn from(error: MultipartError) -> Self {
+        AppError::MalformedMultipart(error.to_string())
+    }
+}
+
+impl AppError {
+    /// The message answered to clients, the detail is still
+    /// logged along with the request id by the access log.
+    ///
+    /// `ERROR_DETAIL` is only looked up for errors exposing internals.
+    pub fn public_message(&self) -> String {
+        self.message(self.exposes_internals() && Config::get().error_detail_full)
+    }
+
+    /// The message of the error, internals are replaced with
+    /// the description of its code unless `full` is set.
+    fn message(&self, full: bool) -> String {
+        if self.exposes_internals() && !full {
+            ErrorCode::from(self).description().to_owned()
+        } else {
+            self.to_string()
+        }
+    }
+
+    /// Whether the message may reveal internals such as SQL, paths
+    /// or blob keys, these are only answered with `ERROR_DETAIL=full`.
+    pub fn exposes_internals(&self) -> bool {
+        ErrorCode::from(self) == ErrorCode::Internal
+            || matches!(
+                self,
+                AppError::Database(DatabaseError::DatabaseQuery(_))
+                    | AppError::Storage(StorageError::NotFound(_))
+            )
+    }
+}
+
+/// The body every error is answered with.
+#[derive(Serialize)]
+struct ErrorEnvelope<'a> {
+    code: &'static str,
+    message: &'a str,
+    #[serde(flatten)]
+    throttle: Option<Throttle>,
+}
+
+/// When to retry, added to errors that only need the client to wait,
+/// the limiter state is only known when a budget was exhausted.
+#[derive(Serialize)]
+struct Throttle {
+    retry_after_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reset_at: Option<DateTime<Utc>>,
+}
+
+impl Throttle {
+    fn retry_after(retry_after: u128) -> Self {
+        Throttle {
+            retry_after_ms: retry_after,
+            limit: None,
+            remaining: None,
+            reset_at: None,
+        }
+    }
+}
+
+/// Private resources are only admitted to exist to authenticated callers.
+fn private_message(resource: &'static str, authenticated: bool) -> String {
+    if authenticated {
+        format!("This {resource} is private")
+    } else {
+        DatabaseError::ModelNotFound(resource).to_string()
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        ErrorCode::from(self).status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let code = ErrorCode::from(self);
+        let mut response = HttpResponse::build(code.status());
+        let mut throttle = None;
+
+        match self {
+            AppError::Storage(StorageError::CircuitOpen(retry_after)) => {
+                response.insert_header((RETRY_AFTER, retry_after.as_secs().max(1)));
+                throttle = Some(Throttle::retry_after(retry_after.as_millis()));
+            },
+            AppError::FileNotFound(ttl) if *ttl > 0 => {
+                response.insert_header((CACHE_CONTROL, format!("public, max-age={ttl}")));
+            },
+            AppError::BasicAuthRequired => {
+                response.insert_header((WWW_AUTHENTICATE, "Basic realm=\"cdn\""));
+            },
+            AppError::RateLimited(limit) => {
+                response.insert_header((RETRY_AFTER, ceil_secs(limit.retry_after).max(1)));
+                limit.insert_headers(&mut response);
+                throttle = Some(Throttle {
+                    retry_after_ms: limit.retry_after.as_millis(),
+                    limit: Some(limit.limit),
+                    remaining: Some(limit.remaining),
+                    reset_at: Some(limit.reset_at()),
+                });
+            },
+            AppError::ReadOnly => {
+                response.insert_header((ALLOW, "GET, HEAD, OPTIONS"));
+            },
+            AppError::NotReady => {
+                response.insert_header((RETRY_AFTER, 1));
+                throttle = Some(Throttle::retry_after(1000));
+            },
+            AppError::RangeNotSatisfiable(size) => {
+                response.insert_header((CONTENT_RANGE, format!("bytes */{size}")));
+            },
+            _ => {},
+        }
+
+        response.json(ErrorEnvelope {
+            code: code.as_str(),
+            message: &self.public_message(),
+            throttle,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Error as SqlxError;
+
+    use super::*;
+
+    #[test]
+    fn queries_only_show_in_full_detail() {
+        let sql = "syntax error at or near \"FROM\" in SELECT password FROM users";
+        let error =
+            AppError::Database(DatabaseError::DatabaseQuery(SqlxError::Protocol(sql.into())));
+
+        assert!(error.exposes_internals());
+        assert!(!error.message(false).contains("SELECT"));
+        assert_eq!(error.message(false), ErrorCode::Internal.description());
+        assert!(error.message(true).contains(sql));
+    }
+
+    #[test]
+    fn client_errors_are_answered_as_they_are() {
+        let error = AppError::InvalidTag;
+
+        assert!(!error.exposes_internals());
+        assert_eq!(error.message(false), error.to_string());
+    }
+
+    #[test]
+    fn anonymous_private_probes_look_like_missing_resources() {
+        let missing = AppError::from(DatabaseError::ModelNotFound("bucket"));
+        let anonymous = AppError::Private { resource: "bucket", authenticated: false };
+
+        assert_eq!(anonymous.status_code(), missing.status_code());
+        assert_eq!(ErrorCode::from(&anonymous), ErrorCode::NotFound);
+        assert_eq!(anonymous.message(false), missing.message(false));
+
+        let authenticated = AppError::Private { resource: "bucket", authenticated: true };
+
+        assert_eq!(ErrorCode::from(&authenticated), ErrorCode::ForbiddenPrivate);
+        assert_eq!(authenticated.status_code(), StatusCode::FORBIDDEN);
+    }
 }
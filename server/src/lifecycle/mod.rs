@@ -0,0 +1,380 @@
+//! Ordered startup and shutdown of the long lived parts of the server.
+//!
+//! Subsystems declare what they depend on by name, they're started
+//! in dependency order and shut down in the reverse one.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use actix_web::rt::time::timeout;
+use futures::future::LocalBoxFuture;
+use log::{info, warn};
+use thiserror::Error as ThisError;
+
+pub mod subsystems;
+
+/// Set once the schema is migrated, until then only safe requests are served.
+static READY: AtomicBool = AtomicBool::new(false);
+
+pub fn is_ready() -> bool {
+    READY.load(Ordering::Acquire)
+}
+
+/// Flips the readiness gate, cleared again on shutdown so draining nodes stop taking writes.
+pub fn set_ready(ready: bool) {
+    READY.store(ready, Ordering::Release);
+}
+
+/// Whatever a subsystem needs back to shut itself down.
+pub type Handle = Box<dyn Any>;
+
+pub type StartResult = Result<Handle, Box<dyn Error>>;
+
+/// A part of the server with its own startup and shutdown.
+pub trait Subsystem {
+    fn name(&self) -> &'static str;
+
+    /// Names of the subsystems that must be running before this one starts.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Optional subsystems failing to start only log a warning.
+    fn required(&self) -> bool {
+        true
+    }
+
+    fn start_timeout(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+
+    fn start<'a>(&'a self, context: &'a mut Context) -> LocalBoxFuture<'a, StartResult>;
+
+    /// Stops the subsystem, it's abandoned once `deadline` passes.
+    fn shutdown(&self, handle: Handle, deadline: Instant) -> LocalBoxFuture<'_, ()>;
+}
+
+/// Values subsystems provide to the ones started after them
+/// and to the server, keyed by their type.
+#[derive(Default)]
+pub struct Context {
+    values: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Context {
+    pub fn provide<T: 'static>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref())
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum LifecycleError {
+    #[error("{subsystem} depends on {dependency} which isn't registered")]
+    UnknownDependency {
+        subsystem: &'static str,
+        dependency: &'static str,
+    },
+
+    #[error("{0} is part of a dependency cycle")]
+    DependencyCycle(&'static str),
+
+    #[error("{subsystem} failed to start: {reason}")]
+    StartFailed { subsystem: &'static str, reason: String },
+}
+
+#[derive(Default)]
+pub struct Lifecycle {
+    subsystems: Vec<Box<dyn Subsystem>>,
+    /// Indexes into `subsystems` in the order they were started.
+    running: Vec<(usize, Handle)>,
+    pub context: Context,
+}
+
+impl Lifecycle {
+    pub fn register(mut self, subsystem: impl Subsystem + 'static) -> Self {
+        self.subsystems.push(Box::new(subsystem));
+        self
+    }
+
+    /// Starts every subsystem in dependency order and logs how each went.
+    ///
+    /// When a required one fails, the ones already running are
+    /// shut down in reverse order before the error is returned.
+    pub async fn start(&mut self, shutdown_timeout: Duration) -> Result<(), LifecycleError> {
+        let order = self.start_order()?;
+
+        for index in order {
+            let subsystem = &self.subsystems[index];
+            let started = Instant::now();
+
+            let missing = subsystem.dependencies().iter().find(|dependency| {
+                !self
+                    .running
+                    .iter()
+                    .any(|(running, _)| self.subsystems[*running].name() == **dependency)
+            });
+
+            let result = match missing {
+                Some(dependency) => Err(format!("{dependency} isn't running")),
+                None => {
+                    match timeout(subsystem.start_timeout(), subsystem.start(&mut self.context))
+                        .await
+                    {
+                        Ok(Ok(handle)) => Ok(handle),
+                        Ok(Err(error)) => Err(error.to_string()),
+                        Err(_) => Err(format!("timed out after {:?}", subsystem.start_timeout())),
+                    }
+                },
+            };
+
+            let elapsed = started.elapsed();
+
+            match result {
+                Ok(handle) => {
+                    info!("Started {} in {elapsed:?}", subsystem.name());
+                    self.running.push((index, handle));
+                },
+                Err(reason) if !subsystem.required() => {
+                    warn!("Running without {} after {elapsed:?}: {reason}", subsystem.name());
+                },
+                Err(reason) => {
+                    let subsystem = subsystem.name();
+                    self.shutdown(shutdown_timeout).await;
+                    return Err(LifecycleError::StartFailed { subsystem, reason });
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shuts every running subsystem down in reverse start order,
+    /// each one gets `timeout` before it's abandoned.
+    pub async fn shutdown(&mut self, timeout_per_subsystem: Duration) {
+        while let Some((index, handle)) = self.running.pop() {
+            let subsystem = &self.subsystems[index];
+            let deadline = Instant::now() + timeout_per_subsystem;
+
+            match timeout(timeout_per_subsystem, subsystem.shutdown(handle, deadline)).await {
+                Ok(()) => info!("Stopped {}", subsystem.name()),
+                Err(_) => warn!("Abandoned {} after {timeout_per_subsystem:?}", subsystem.name()),
+            }
+        }
+    }
+
+    /// Orders the subsystems so every one comes after its dependencies,
+    /// registration order is kept between independent ones.
+    fn start_order(&self) -> Result<Vec<usize>, LifecycleError> {
+        let mut order = Vec::with_capacity(self.subsystems.len());
+        let mut visiting = Vec::new();
+
+        for index in 0..self.subsystems.len() {
+            self.visit(index, &mut visiting, &mut order)?;
+        }
+
+        Ok(order)
+    }
+
+    /// Depth first visit pushing dependencies before `index`,
+    /// `visiting` holds the current path to detect cycles.
+    fn visit(
+        &self,
+        index: usize,
+        visiting: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), LifecycleError> {
+        let subsystem = &self.subsystems[index];
+
+        if order.contains(&index) {
+            return Ok(());
+        }
+
+        if visiting.contains(&index) {
+            return Err(LifecycleError::DependencyCycle(subsystem.name()));
+        }
+
+        visiting.push(index);
+
+        for &dependency in subsystem.dependencies() {
+            let dependency_index =
+                self.subsystems.iter().position(|other| other.name() == dependency).ok_or(
+                    LifecycleError::UnknownDependency { subsystem: subsystem.name(), dependency },
+                )?;
+
+            self.visit(dependency_index, visiting, order)?;
+        }
+
+        visiting.pop();
+        order.push(index);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use actix_web::rt::time::sleep;
+
+    use super::*;
+
+    type Events = Rc<RefCell<Vec<String>>>;
+
+    struct Fake {
+        name: &'static str,
+        dependencies: &'static [&'static str],
+        required: bool,
+        fails: bool,
+        shutdown_takes: Duration,
+        events: Events,
+    }
+
+    impl Fake {
+        fn new(name: &'static str, dependencies: &'static [&'static str], events: &Events) -> Self {
+            Fake {
+                name,
+                dependencies,
+                required: true,
+                fails: false,
+                shutdown_takes: Duration::ZERO,
+                events: events.clone(),
+            }
+        }
+    }
+
+    impl Subsystem for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn dependencies(&self) -> &'static [&'static str] {
+            self.dependencies
+        }
+
+        fn required(&self) -> bool {
+            self.required
+        }
+
+        fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+            Box::pin(async {
+                if self.fails {
+                    return Err("refused".into());
+                }
+
+                self.events.borrow_mut().push(format!("start {}", self.name));
+                Ok(Box::new(()) as Handle)
+            })
+        }
+
+        fn shutdown(&self, _: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+            Box::pin(async {
+                sleep(self.shutdown_takes).await;
+                self.events.borrow_mut().push(format!("stop {}", self.name));
+            })
+        }
+    }
+
+    #[actix_web::test]
+    async fn subsystems_start_after_their_dependencies_and_stop_in_reverse() {
+        let events = Events::default();
+        let mut lifecycle = Lifecycle::default()
+            .register(Fake::new("server", &["storage", "database"], &events))
+            .register(Fake::new("storage", &["database"], &events))
+            .register(Fake::new("database", &[], &events));
+
+        lifecycle.start(Duration::from_secs(1)).await.unwrap();
+        lifecycle.shutdown(Duration::from_secs(1)).await;
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                "start database",
+                "start storage",
+                "start server",
+                "stop server",
+                "stop storage",
+                "stop database",
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn required_failures_stop_what_already_started() {
+        let events = Events::default();
+        let mut lifecycle = Lifecycle::default()
+            .register(Fake::new("database", &[], &events))
+            .register(Fake {
+                fails: true,
+                ..Fake::new("storage", &["database"], &events)
+            })
+            .register(Fake::new("server", &["storage"], &events));
+
+        let error = lifecycle.start(Duration::from_secs(1)).await.unwrap_err();
+
+        assert!(matches!(error, LifecycleError::StartFailed { subsystem: "storage", .. }));
+        assert_eq!(*events.borrow(), ["start database", "stop database"]);
+    }
+
+    #[actix_web::test]
+    async fn optional_failures_only_skip_their_dependents() {
+        let events = Events::default();
+        let mut lifecycle = Lifecycle::default()
+            .register(Fake::new("database", &[], &events))
+            .register(Fake {
+                required: false,
+                fails: true,
+                ..Fake::new("cache", &[], &events)
+            })
+            .register(Fake {
+                required: false,
+                ..Fake::new("warmer", &["cache"], &events)
+            });
+
+        lifecycle.start(Duration::from_secs(1)).await.unwrap();
+
+        assert_eq!(*events.borrow(), ["start database"]);
+    }
+
+    #[actix_web::test]
+    async fn slow_shutdowns_are_abandoned_at_the_deadline() {
+        let events = Events::default();
+        let mut lifecycle =
+            Lifecycle::default().register(Fake::new("database", &[], &events)).register(Fake {
+                shutdown_takes: Duration::from_secs(60),
+                ..Fake::new("uploads", &["database"], &events)
+            });
+
+        lifecycle.start(Duration::from_secs(1)).await.unwrap();
+
+        let started = Instant::now();
+        lifecycle.shutdown(Duration::from_millis(50)).await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(*events.borrow(), ["start database", "start uploads", "stop database"]);
+    }
+
+    #[test]
+    fn broken_dependency_graphs_are_refused() {
+        let events = Events::default();
+
+        let cycle = Lifecycle::default()
+            .register(Fake::new("a", &["b"], &events))
+            .register(Fake::new("b", &["a"], &events));
+        assert!(matches!(cycle.start_order(), Err(LifecycleError::DependencyCycle(_))));
+
+        let unknown = Lifecycle::default().register(Fake::new("a", &["missing"], &events));
+        assert!(matches!(
+            unknown.start_order(),
+            Err(LifecycleError::UnknownDependency { subsystem: "a", dependency: "missing" })
+        ));
+    }
+}
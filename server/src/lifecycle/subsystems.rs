@@ -0,0 +1,300 @@
+use std::time::Instant;
+
+use actix_web::rt::spawn;
+use actix_web::rt::task::JoinHandle;
+use actix_web::web::Data;
+use database::schema_compat::load_phases;
+use database::{close_db_connection, configure_db, migrate_db};
+use futures::future::LocalBoxFuture;
+use log::error;
+use logger::access::{AccessFormat, AccessLog, AccessTarget};
+
+use super::{Context, Handle, StartResult, Subsystem, set_ready};
+use crate::config::{Config, setting};
+use crate::tasks::abuse::run_abuse_detection;
+use crate::tasks::domain_verification::run_domain_verification;
+use crate::tasks::heartbeat::run_heartbeat;
+use crate::tasks::jobs::interrupt_abandoned_jobs;
+use crate::tasks::stats::{flush_stats, run_stats_flush};
+use crate::tasks::storage_summary::run_storage_summary_refresh;
+use crate::tasks::supervisor::supervised_task;
+use crate::utils::app_storage::open_storage;
+
+/// Sink for the access log middleware.
+pub struct AccessLogSubsystem;
+
+impl Subsystem for AccessLogSubsystem {
+    fn name(&self) -> &'static str {
+        "access-log"
+    }
+
+    fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async {
+            let config = Config::get();
+
+            AccessLog::init(
+                AccessTarget::parse(&config.access_log_target),
+                AccessFormat::parse(&config.access_log_format),
+            )?;
+
+            Ok(Box::new(()) as Handle)
+        })
+    }
+
+    fn shutdown(&self, _: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// The connection pool, connected and migrated at startup
+/// instead of on the first request.
+///
+/// Migrations run in the background so the server binds meanwhile,
+/// the readiness gate holds writes back until they're done.
+pub struct DatabaseSubsystem;
+
+impl Subsystem for DatabaseSubsystem {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async {
+            // A malformed pool setting is a configuration error, not one to retry.
+            configure_db(setting)?;
+
+            let task = spawn(async {
+                match migrate().await {
+                    Ok(()) => set_ready(true),
+                    Err(error) => {
+                        error!("Couldn't migrate the database, writes stay refused: {error}")
+                    },
+                }
+            });
+
+            Ok(Box::new(task) as Handle)
+        })
+    }
+
+    fn shutdown(&self, handle: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        set_ready(false);
+
+        if let Ok(task) = handle.downcast::<JoinHandle<()>>() {
+            task.abort();
+        }
+
+        Box::pin(close_db_connection())
+    }
+}
+
+/// Migrates the schema, then loads the phases queries
+/// pick their variant from, before any write is served.
+async fn migrate() -> Result<(), Box<dyn std::error::Error>> {
+    migrate_db().await?;
+    load_phases().await?;
+
+    Ok(())
+}
+
+/// The blob storage, provided to the server as `Data<AppStorage>`.
+pub struct StorageSubsystem;
+
+impl Subsystem for StorageSubsystem {
+    fn name(&self) -> &'static str {
+        "storage"
+    }
+
+    fn start<'a>(&'a self, context: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async {
+            let storage = open_storage().await?;
+            context.provide(Data::new(storage));
+
+            Ok(Box::new(()) as Handle)
+        })
+    }
+
+    fn shutdown(&self, _: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// Background checks of pending custom domains, supervised
+/// so a crash restarts them and the server keeps running without them.
+pub struct DomainVerificationSubsystem;
+
+impl Subsystem for DomainVerificationSubsystem {
+    fn name(&self) -> &'static str {
+        "domain-verification"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["database"]
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async {
+            Ok(Box::new(supervised_task("domain-verification", run_domain_verification)) as Handle)
+        })
+    }
+
+    fn shutdown(&self, handle: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        if let Ok(task) = handle.downcast::<JoinHandle<()>>() {
+            task.abort();
+        }
+
+        Box::pin(async {})
+    }
+}
+
+/// Background refreshes of the admin storage summary, only
+/// the admin report goes stale while they're not running.
+pub struct StorageSummarySubsystem;
+
+impl Subsystem for StorageSummarySubsystem {
+    fn name(&self) -> &'static str {
+        "storage-summary"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["database"]
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async {
+            Ok(Box::new(supervised_task("storage-summary", run_storage_summary_refresh)) as Handle)
+        })
+    }
+
+    fn shutdown(&self, handle: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        if let Ok(task) = handle.downcast::<JoinHandle<()>>() {
+            task.abort();
+        }
+
+        Box::pin(async {})
+    }
+}
+
+/// Periodic writes of the views and traffic buffered in memory,
+/// what's left is written on shutdown so a restart loses none.
+pub struct StatsSubsystem;
+
+impl Subsystem for StatsSubsystem {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["database"]
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async { Ok(Box::new(supervised_task("stats", run_stats_flush)) as Handle) })
+    }
+
+    fn shutdown(&self, handle: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        if let Ok(task) = handle.downcast::<JoinHandle<()>>() {
+            task.abort();
+        }
+
+        Box::pin(flush_stats())
+    }
+}
+
+/// Watches the traffic of every client for abuse, those found
+/// are held to a stricter upload budget for a while.
+pub struct AbuseDetectionSubsystem;
+
+impl Subsystem for AbuseDetectionSubsystem {
+    fn name(&self) -> &'static str {
+        "abuse-detection"
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async {
+            Ok(Box::new(supervised_task("abuse-detection", run_abuse_detection)) as Handle)
+        })
+    }
+
+    fn shutdown(&self, handle: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        if let Ok(task) = handle.downcast::<JoinHandle<()>>() {
+            task.abort();
+        }
+
+        Box::pin(async {})
+    }
+}
+
+/// Reports this instance's schema version and reloads migration phases,
+/// phases can't advance past what a silent instance supports meanwhile.
+pub struct HeartbeatSubsystem;
+
+impl Subsystem for HeartbeatSubsystem {
+    fn name(&self) -> &'static str {
+        "heartbeat"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["database"]
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async { Ok(Box::new(supervised_task("heartbeat", run_heartbeat)) as Handle) })
+    }
+
+    fn shutdown(&self, handle: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        if let Ok(task) = handle.downcast::<JoinHandle<()>>() {
+            task.abort();
+        }
+
+        Box::pin(async {})
+    }
+}
+
+/// Marks the jobs left running by instances that stopped as interrupted,
+/// the heartbeat keeps checking for them after startup.
+pub struct JobsSubsystem;
+
+impl Subsystem for JobsSubsystem {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["database"]
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn start<'a>(&'a self, _: &'a mut Context) -> LocalBoxFuture<'a, StartResult> {
+        Box::pin(async {
+            interrupt_abandoned_jobs().await?;
+            Ok(Box::new(()) as Handle)
+        })
+    }
+
+    fn shutdown(&self, _: Handle, _: Instant) -> LocalBoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
@@ -0,0 +1,237 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, REFERER, USER_AGENT};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpMessage};
+use chrono::Local;
+use log::error;
+use logger::access::{AccessLog, AccessRecord};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::extractors::auth::AuthenticatedUser;
+use crate::middleware::forwarded::ClientInfo;
+use crate::utils::headers::first_header_value;
+
+const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Writes an access record for every request not excluded by
+/// `ACCESS_LOG_EXCLUDE`, once its body finished or was dropped.
+///
+/// Successful requests matching `ACCESS_LOG_SAMPLE` are only recorded
+/// at its rate, so busy paths don't drown the log.
+pub async fn access_log(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let config = Config::get();
+
+    record_access(&config.access_log_exclude, &config.access_log_sample, req, next).await
+}
+
+async fn record_access(
+    exclude: &[String],
+    sample: &[(String, u32)],
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let started = Instant::now();
+    let path = req.path().to_owned();
+
+    let header = |name| first_header_value(&req, name).map(String::from);
+
+    let request_id =
+        header(X_REQUEST_ID).unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+
+    if exclude.iter().any(|pattern| matches_pattern(pattern, &path)) {
+        let res = next.call(req).await?;
+        log_error_detail(&res, &request_id);
+
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let mut record = AccessRecord {
+        remote_addr: ClientInfo::get(req.request())
+            .ip
+            .map(|ip| ip.to_string())
+            .unwrap_or("-".into()),
+        user_id: None,
+        time: Local::now(),
+        method: req.method().to_string(),
+        path: req.uri().to_string(),
+        protocol: format!("{:?}", req.version()),
+        status: 0,
+        bytes_sent: 0,
+        referer: header(REFERER),
+        user_agent: header(USER_AGENT),
+        request_id: request_id.clone(),
+        duration_ms: 0,
+    };
+
+    let mut res = next.call(req).await?;
+    log_error_detail(&res, &request_id);
+
+    record.status = res.status().as_u16();
+    record.user_id = res.request().extensions().get::<AuthenticatedUser>().map(|user| user.0);
+
+    if let Ok(request_id) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(X_REQUEST_ID, request_id);
+    }
+
+    let failed = res.status().is_client_error() || res.status().is_server_error();
+
+    if !failed && !sampled(sample, &path) {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    Ok(res
+        .map_body(|_, body| CountingBody { inner: body.boxed(), record, started })
+        .map_into_boxed_body())
+}
+
+/// Logs the detail of errors clients may only get a generic message for,
+/// the request id is what ties a client report to the log line.
+fn log_error_detail<B>(res: &ServiceResponse<B>, request_id: &str) {
+    let error = res.response().error().and_then(|error| error.as_error::<AppError>());
+
+    if let Some(error) = error.filter(|error| error.exposes_internals()) {
+        error!("Request {request_id} failed: {error}");
+    }
+}
+
+/// Whether a successful request to `path` is recorded, the
+/// first matching rate of `sample` applies.
+fn sampled(sample: &[(String, u32)], path: &str) -> bool {
+    match sample.iter().find(|(pattern, _)| matches_pattern(pattern, path)) {
+        Some((_, rate)) => rand::random_ratio(1, *rate),
+        None => true,
+    }
+}
+
+/// Supports a trailing `*` to match every path under a prefix.
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => path.starts_with(prefix),
+        None => path == pattern,
+    }
+}
+
+/// Counts the bytes actually handed to the connection, the
+/// record is written on drop so early disconnects are logged too.
+struct CountingBody {
+    inner: BoxBody,
+    record: AccessRecord,
+    started: Instant,
+}
+
+impl MessageBody for CountingBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.inner.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.record.bytes_sent += chunk.len() as u64;
+        }
+
+        poll
+    }
+}
+
+impl Drop for CountingBody {
+    fn drop(&mut self) {
+        self.record.duration_ms = self.started.elapsed().as_millis();
+        AccessLog::log(&self.record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs::read_to_string;
+
+    use actix_web::App;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, call_service, init_service, read_body};
+    use actix_web::web::get;
+    use logger::access::{AccessFormat, AccessTarget};
+
+    use super::*;
+
+    /// The status, bytes and request id of a Combined Log Format line.
+    fn parse(line: &str) -> (u16, u64, &str) {
+        let (_, rest) = line.split_once("] \"").expect("no timestamp");
+        let (_, rest) = rest.split_once("\" ").expect("no request line");
+        let mut fields = rest.split(' ');
+
+        let status = fields.next().unwrap().parse().unwrap();
+        let bytes = fields.next().unwrap().parse().unwrap();
+        let request_id = rest.rsplit(' ').nth(1).unwrap();
+
+        (status, bytes, request_id)
+    }
+
+    /// Stands in for `access_log` with nothing excluded or sampled.
+    async fn log_everything(
+        req: ServiceRequest,
+        next: Next<impl MessageBody + 'static>,
+    ) -> Result<ServiceResponse<BoxBody>, Error> {
+        record_access(&[], &[], req, next).await
+    }
+
+    #[actix_web::test]
+    async fn requests_are_written_as_combined_log_lines() {
+        let path = temp_dir().join(format!("access-{:016x}.log", rand::random::<u64>()));
+        AccessLog::init(AccessTarget::File(path.clone()), AccessFormat::Combined).unwrap();
+
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(log_everything))
+                .route("/hello", get().to(|| async { "hello" }))
+                .route("/large", get().to(|| async { "x".repeat(4096) })),
+        )
+        .await;
+        let request = |path: &str, id: &str| {
+            TestRequest::get().uri(path).insert_header((X_REQUEST_ID, id)).to_request()
+        };
+
+        let response = call_service(&app, request("/hello", "served")).await;
+        assert_eq!(response.headers().get(X_REQUEST_ID).unwrap(), "served");
+        read_body(response).await;
+
+        let response = call_service(&app, request("/missing", "missing")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        read_body(response).await;
+
+        // The client went away before reading anything.
+        drop(call_service(&app, request("/large", "dropped")).await);
+
+        let log = read_to_string(&path).unwrap();
+        let lines = log.lines().map(parse).collect::<Vec<_>>();
+
+        assert_eq!(lines, [(200, 5, "served"), (404, 0, "missing"), (200, 0, "dropped")]);
+        assert!(log.lines().all(|line| line.contains("\"GET /") && line.ends_with("ms")));
+    }
+
+    #[test]
+    fn patterns_match_exact_paths_or_prefixes() {
+        assert!(matches_pattern("/health", "/health"));
+        assert!(!matches_pattern("/health", "/healthz"));
+        assert!(matches_pattern("/health*", "/healthz"));
+        assert!(matches_pattern("/meta/*", "/meta/ready"));
+        assert!(!matches_pattern("/meta/*", "/f/meta"));
+    }
+}
@@ -0,0 +1,227 @@
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpMessage};
+use futures::Stream;
+
+use crate::extractors::auth::AuthenticatedUser;
+use crate::middleware::forwarded::ClientInfo;
+use crate::utils::traffic::{Traffic, Transfer};
+use crate::utils::upload_limit::upload_key;
+
+/// Route of requests that didn't match any.
+const UNMATCHED: &str = "-";
+
+/// What the request body did, shared by its counting stream and the response.
+#[derive(Default)]
+struct Received {
+    bytes: Cell<u64>,
+    aborted: Cell<bool>,
+}
+
+/// Counts the bytes of every request and response as they cross the
+/// connection, attributed to the user or address and the route pattern.
+///
+/// Requests rejected with a 4xx, uploads that broke off and responses
+/// the client left before the end of waste every byte they exchanged,
+/// the abuse detection watches for clients wasting too much.
+pub async fn byte_accounting(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let received = Rc::new(Received::default());
+
+    // Most requests have no body, those are left as they are.
+    match req.take_payload() {
+        Payload::None => {},
+        payload => req.set_payload(Payload::from(Box::pin(CountingPayload {
+            inner: payload,
+            received: received.clone(),
+        }) as Pin<Box<dyn Stream<Item = _>>>)),
+    }
+
+    let head = req.method() == Method::HEAD;
+    let res = next.call(req).await?;
+
+    let request = res.request();
+    let user_id = request.extensions().get::<AuthenticatedUser>().map(|user| user.0);
+    let client = upload_key(user_id, ClientInfo::get(request).ip);
+    let route = request.match_pattern().unwrap_or_else(|| UNMATCHED.to_owned());
+    let rejected = res.status().is_client_error();
+
+    Ok(res
+        .map_body(|_, body| {
+            let size = body.size();
+
+            AccountedBody {
+                inner: body.boxed(),
+                // Nothing is sent for these, so they're never polled.
+                finished: head || matches!(size, BodySize::None | BodySize::Sized(0)),
+                size,
+                sent: 0,
+                received,
+                client,
+                route,
+                rejected,
+            }
+        })
+        .map_into_boxed_body())
+}
+
+/// Counts the bytes of a request body as the handler reads them.
+struct CountingPayload {
+    inner: Payload,
+    received: Rc<Received>,
+}
+
+impl Stream for CountingPayload {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => {
+                self.received.bytes.set(self.received.bytes.get() + chunk.len() as u64);
+            },
+            Poll::Ready(Some(Err(_))) => self.received.aborted.set(true),
+            _ => {},
+        }
+
+        poll
+    }
+}
+
+/// Counts the bytes of a response handed to the connection, the request
+/// is recorded on drop so responses the client left are counted too.
+struct AccountedBody {
+    inner: BoxBody,
+    size: BodySize,
+    sent: u64,
+    finished: bool,
+    received: Rc<Received>,
+    client: String,
+    route: String,
+    rejected: bool,
+}
+
+impl MessageBody for AccountedBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.size
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => self.sent += chunk.len() as u64,
+            Poll::Ready(None) => self.finished = true,
+            _ => {},
+        }
+
+        poll
+    }
+}
+
+impl Drop for AccountedBody {
+    fn drop(&mut self) {
+        let whole = matches!(self.size, BodySize::Sized(size) if self.sent >= size);
+
+        let transfer = Transfer {
+            received: self.received.bytes.get(),
+            sent: self.sent,
+            rejected: self.rejected,
+            aborted_upload: self.received.aborted.get(),
+            disconnected: !self.finished && !whole,
+        };
+
+        Traffic::get().record(&self.client, &self.route, &transfer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, call_service, init_service, read_body};
+    use actix_web::web::{head, post};
+    use actix_web::{App, HttpResponse};
+    use futures::stream;
+
+    use super::*;
+
+    fn peer() -> (SocketAddr, String) {
+        let ip = IpAddr::V4(Ipv4Addr::from(rand::random::<u32>()));
+
+        (SocketAddr::new(ip, 4000), upload_key(None, Some(ip)))
+    }
+
+    #[actix_web::test]
+    async fn partial_uploads_are_counted_as_wasted() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(byte_accounting))
+                .route("/upload/{name}", post().to(|body: Bytes| async move { body }))
+                .route("/upload/{name}", head().to(HttpResponse::Ok)),
+        )
+        .await;
+        let windows = || Traffic::get().take_windows();
+
+        let (addr, client) = peer();
+        let chunks =
+            stream::iter([Ok(Bytes::from(vec![0; 1000])), Err(PayloadError::Incomplete(None))]);
+        let (req, _) = TestRequest::post()
+            .uri("/upload/partial")
+            .peer_addr(addr)
+            .to_request()
+            .replace_payload(Payload::from(Box::pin(chunks) as Pin<Box<dyn Stream<Item = _>>>));
+
+        let response = call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let sent = read_body(response).await.len() as i64;
+
+        let totals = windows().remove(&client).unwrap();
+        assert_eq!((totals.bytes_in, totals.bytes_out), (1000, sent));
+        assert_eq!(totals.wasted_bytes, 1000 + sent);
+        assert_eq!(totals.aborted_uploads, 1);
+
+        let (addr, client) = peer();
+        let req =
+            TestRequest::post().uri("/upload/whole").peer_addr(addr).set_payload(vec![1; 500]);
+        let response = call_service(&app, req.to_request()).await;
+        assert_eq!(read_body(response).await.len(), 500);
+
+        let totals = windows().remove(&client).unwrap();
+        assert_eq!((totals.bytes_in, totals.bytes_out, totals.wasted_bytes), (500, 500, 0));
+
+        // A response dropped halfway, like when the client leaves.
+        let (addr, client) = peer();
+        let req = TestRequest::post().uri("/upload/left").peer_addr(addr).set_payload(vec![1; 500]);
+        drop(call_service(&app, req.to_request()).await);
+
+        let totals = windows().remove(&client).unwrap();
+        assert_eq!((totals.bytes_in, totals.bytes_out, totals.wasted_bytes), (500, 0, 500));
+
+        let (addr, client) = peer();
+        let req = TestRequest::default().method(Method::HEAD).uri("/upload/head").peer_addr(addr);
+        drop(call_service(&app, req.to_request()).await);
+
+        let totals = windows().remove(&client).unwrap();
+        assert_eq!((totals.requests, totals.wasted_bytes), (1, 0));
+    }
+}
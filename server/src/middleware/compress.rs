@@ -0,0 +1,81 @@
+use actix_web::Error;
+use actix_web::body::{self, BodySize, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{
+    ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, HeaderValue, VARY,
+};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use log::debug;
+
+use crate::config::Config;
+use crate::utils::compression::{NoCompression, is_compressible, negotiate};
+use crate::utils::headers::joined_header_values;
+
+/// Compresses responses the client accepts compressed, as long as their
+/// type compresses well and they're at least `COMPRESS_MIN_BYTES` long.
+///
+/// Streamed bodies, responses already encoded and those marked
+/// with `NoCompression` are sent as they are.
+pub async fn compress(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody, Bytes>>, Error> {
+    let encoding =
+        joined_header_values(&req, ACCEPT_ENCODING).ok().flatten().as_deref().and_then(negotiate);
+
+    let mut res = next.call(req).await?;
+
+    let compressible = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(is_compressible);
+
+    // Precompressed files already vary on it.
+    let varies = res
+        .headers()
+        .get_all(VARY)
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.to_ascii_lowercase().contains("accept-encoding"));
+
+    if compressible && !varies {
+        res.headers_mut().append(VARY, HeaderValue::from_static("accept-encoding"));
+    }
+
+    let size = match res.response().body().size() {
+        BodySize::Sized(size) => size as usize,
+        _ => 0,
+    };
+
+    let skip = !compressible
+        || size < Config::get().compress_min_bytes
+        || res.headers().contains_key(CONTENT_ENCODING)
+        || res.response().extensions().contains::<NoCompression>();
+
+    let Some(encoding) = encoding.filter(|_| !skip) else {
+        return Ok(res.map_into_left_body());
+    };
+
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+
+    let data =
+        body::to_bytes(body).await.map_err(|error| ErrorInternalServerError(error.into()))?;
+    let compressed = encoding.compress(&data)?;
+
+    debug!(
+        "Compressed {} with {} from {} to {} bytes",
+        req.path(),
+        encoding.as_str(),
+        data.len(),
+        compressed.len()
+    );
+
+    let headers = res.headers_mut();
+    headers.remove(CONTENT_LENGTH);
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+
+    Ok(ServiceResponse::new(req, res.set_body(Bytes::from(compressed))).map_into_right_body())
+}
@@ -0,0 +1,56 @@
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use log::warn;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::csrf::{X_CSRF_TOKEN, verify_csrf_token};
+use crate::utils::headers::single_header_value;
+
+const FORM_UPLOAD_PATH: &str = "/file/upload/form";
+
+/// Requires a valid `X-CSRF-Token` on mutating requests authenticated
+/// by the session cookie, requests carrying credentials in
+/// `Authorization` can't be forged by another site and are exempt.
+///
+/// Form uploads are authenticated by their signed policy rather than the
+/// session and are exempt too.
+///
+/// With `CSRF_ENFORCE` off would be rejections are only logged.
+pub async fn csrf(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let config = Config::get();
+
+    let safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || req.method().as_str() == "PROPFIND";
+
+    let session = req.cookie(&config.session_cookie_name);
+
+    let exempt =
+        safe || req.headers().contains_key(AUTHORIZATION) || req.path() == FORM_UPLOAD_PATH;
+
+    let Some(session) = session.filter(|_| !exempt) else {
+        return next.call(req).await;
+    };
+
+    let valid = single_header_value(&req, X_CSRF_TOKEN)
+        .ok()
+        .flatten()
+        .is_some_and(|token| verify_csrf_token(session.value(), token));
+
+    if !valid {
+        if config.csrf_enforce {
+            return Err(AppError::CsrfTokenInvalid.into());
+        }
+
+        warn!("Would have rejected {} {} without a valid CSRF token", req.method(), req.path());
+    }
+
+    next.call(req).await
+}
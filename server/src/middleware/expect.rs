@@ -0,0 +1,88 @@
+use actix_http::Request;
+use actix_web::http::Method;
+use actix_web::http::header::{AUTHORIZATION, CONTENT_LENGTH, COOKIE};
+use actix_web::{Error, HttpMessage};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::lifecycle::is_ready;
+
+/// Decides whether to answer `Expect: 100-continue` with the go ahead,
+/// uploads that would be refused get their final status instead so
+/// the client never sends the body.
+///
+/// Only the headers are known here, the upload route checks everything again.
+pub async fn expect_continue(req: Request) -> Result<Request, Error> {
+    let Some(anonymous_allowed) = upload_route(req.method(), req.path()) else {
+        return Ok(req);
+    };
+
+    let config = Config::get();
+
+    if config.read_only {
+        return Err(AppError::ReadOnly.into());
+    }
+
+    if !is_ready() {
+        return Err(AppError::NotReady.into());
+    }
+
+    let declared = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+
+    let limit = match req.path() {
+        "/file/paste" => config.paste_max_size,
+        _ => config.max_upload_size,
+    };
+
+    if declared.is_some_and(|length| length > limit) {
+        return Err(AppError::UploadTooLarge(limit).into());
+    }
+
+    let session = req
+        .headers()
+        .get_all(COOKIE)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .any(|cookie| {
+            cookie
+                .trim()
+                .split_once('=')
+                .is_some_and(|(name, _)| name == config.session_cookie_name)
+        });
+
+    let anonymous = !session && !req.headers().contains_key(AUTHORIZATION);
+
+    if anonymous && !(anonymous_allowed && config.allow_anonymous_upload) {
+        return Err(AppError::AuthorizationError.into());
+    }
+
+    Ok(req)
+}
+
+/// Whether the request sends a file body, and if so
+/// whether it may come without credentials.
+fn upload_route(method: &Method, path: &str) -> Option<bool> {
+    if method == Method::PUT {
+        return path.strip_prefix("/f/").filter(|name| !name.contains('/')).map(|_| false);
+    }
+
+    if method != Method::POST {
+        return None;
+    }
+
+    if path == "/file/upload" || path == "/file/paste" {
+        return Some(true);
+    }
+
+    let rest = path.strip_prefix("/file/")?;
+
+    if rest.ends_with("/append") || rest.ends_with("/delta") {
+        return Some(false);
+    }
+
+    None
+}
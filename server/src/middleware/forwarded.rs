@@ -0,0 +1,298 @@
+use std::net::IpAddr;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{FORWARDED, HOST, HeaderName};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpRequest};
+use ipnet::IpNet;
+
+use crate::config::Config;
+use crate::utils::headers::joined_header_values;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// Who a request came from and how they addressed us,
+/// as seen before any trusted reverse proxy.
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub scheme: String,
+    pub host: String,
+}
+
+impl ClientInfo {
+    /// The client info resolved by the `forwarded` middleware, or the
+    /// connection itself when the request didn't go through it.
+    pub fn get(req: &HttpRequest) -> ClientInfo {
+        if let Some(info) = req.extensions().get::<ClientInfo>() {
+            return info.clone();
+        }
+
+        direct(req)
+    }
+}
+
+/// Resolves the `ClientInfo` of every request, forwarded headers are only
+/// honored when the peer is one of `TRUSTED_PROXIES` so clients can't spoof them.
+pub async fn forwarded(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    resolve_client(&Config::get().trusted_proxies, req, next).await
+}
+
+async fn resolve_client<B: MessageBody>(
+    proxies: &[IpNet],
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let direct = direct(req.request());
+
+    let info = match direct.ip {
+        Some(peer) if is_trusted(proxies, peer) => from_proxy(req.request(), proxies, direct),
+        _ => direct,
+    };
+
+    req.extensions_mut().insert(info);
+
+    next.call(req).await
+}
+
+/// What the connection itself tells, without looking at forwarded headers.
+fn direct(req: &HttpRequest) -> ClientInfo {
+    let scheme = if req.app_config().secure() { "https" } else { "http" };
+
+    ClientInfo {
+        ip: req.peer_addr().map(|addr| addr.ip()),
+        scheme: scheme.to_owned(),
+        host: header(req, HOST).unwrap_or_else(|| req.app_config().host().to_owned()),
+    }
+}
+
+/// Reads `Forwarded` when present and the `X-Forwarded-*` headers otherwise.
+fn from_proxy(req: &HttpRequest, proxies: &[IpNet], direct: ClientInfo) -> ClientInfo {
+    let (chain, scheme, host) = match header(req, FORWARDED) {
+        Some(forwarded) => parse_forwarded(&forwarded),
+        None => (
+            header(req, X_FORWARDED_FOR)
+                .map(|value| value.split(',').map(|hop| hop.trim().to_owned()).collect())
+                .unwrap_or_default(),
+            header(req, X_FORWARDED_PROTO).and_then(|value| last_value(&value)),
+            header(req, X_FORWARDED_HOST).and_then(|value| last_value(&value)),
+        ),
+    };
+
+    ClientInfo {
+        ip: client_ip(&chain, proxies).or(direct.ip),
+        scheme: scheme
+            .filter(|scheme| matches!(scheme.as_str(), "http" | "https"))
+            .unwrap_or(direct.scheme),
+        host: host.unwrap_or(direct.host),
+    }
+}
+
+/// Walks the chain from the nearest hop, the first address that isn't a
+/// trusted proxy is the client, every address to its left could be forged.
+///
+/// When every hop is trusted the farthest one is the client.
+fn client_ip(chain: &[String], proxies: &[IpNet]) -> Option<IpAddr> {
+    let hops = chain.iter().filter_map(|hop| parse_ip(hop)).collect::<Vec<_>>();
+
+    hops.iter().rev().find(|hop| !is_trusted(proxies, **hop)).or(hops.first()).copied()
+}
+
+/// The `for` chain and the `proto` and `host` of the nearest hop.
+fn parse_forwarded(value: &str) -> (Vec<String>, Option<String>, Option<String>) {
+    let mut chain = Vec::new();
+    let (mut scheme, mut host) = (None, None);
+
+    for element in value.split(',') {
+        for pair in element.split(';') {
+            let Some((key, value)) = pair.trim().split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim().trim_matches('"').to_owned();
+
+            match key.trim().to_ascii_lowercase().as_str() {
+                "for" => chain.push(value),
+                "proto" => scheme = Some(value.to_ascii_lowercase()),
+                "host" => host = Some(value),
+                _ => {},
+            }
+        }
+    }
+
+    (chain, scheme, host)
+}
+
+/// Addresses may carry a port, IPv6 ones are then bracketed.
+fn parse_ip(hop: &str) -> Option<IpAddr> {
+    if let Ok(ip) = hop.parse() {
+        return Some(ip);
+    }
+
+    if let Some(rest) = hop.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+
+    hop.rsplit_once(':')?.0.parse().ok()
+}
+
+fn is_trusted(proxies: &[IpNet], ip: IpAddr) -> bool {
+    proxies.iter().any(|proxy| proxy.contains(&ip))
+}
+
+/// Forwarded headers may be split over several lines, they're joined in order
+/// and ignored when malformed so the connection itself is trusted instead.
+fn header(req: &HttpRequest, name: HeaderName) -> Option<String> {
+    joined_header_values(req, name).ok().flatten()
+}
+
+/// Proxies append their value, so the last one comes from the nearest hop.
+fn last_value(value: &str) -> Option<String> {
+    value.rsplit(',').next().map(str::trim).filter(|value| !value.is_empty()).map(From::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, call_and_read_body, call_and_read_body_json, init_service};
+    use actix_web::{App, web};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::routes::routes;
+    use crate::testing::{create_bucket, create_file, create_user, run, unique_name};
+
+    fn proxies() -> Vec<IpNet> {
+        vec!["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()]
+    }
+
+    /// Stands in for the `forwarded` middleware with `TRUSTED_PROXIES` set to `proxies`.
+    async fn behind_proxies(
+        req: ServiceRequest,
+        next: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<impl MessageBody>, Error> {
+        resolve_client(&proxies(), req, next).await
+    }
+
+    /// A request from `peer` claiming to be forwarded for another client over TLS.
+    fn spoofable(peer: &str) -> TestRequest {
+        TestRequest::get()
+            .peer_addr(peer.parse::<SocketAddr>().unwrap())
+            .insert_header((HOST, "cdn.test"))
+            .insert_header((X_FORWARDED_FOR, "198.51.100.7"))
+            .insert_header((X_FORWARDED_PROTO, "https"))
+            .insert_header((X_FORWARDED_HOST, "files.test"))
+    }
+
+    fn chain(hops: &[&str]) -> Vec<String> {
+        hops.iter().map(|hop| hop.to_string()).collect()
+    }
+
+    #[test]
+    fn the_client_is_the_nearest_untrusted_hop() {
+        let hops = chain(&["198.51.100.7", "203.0.113.9", "10.0.0.2", "10.0.0.1"]);
+
+        assert_eq!(client_ip(&hops, &proxies()), "203.0.113.9".parse().ok());
+    }
+
+    #[test]
+    fn the_farthest_hop_is_the_client_when_all_are_trusted() {
+        let hops = chain(&["10.0.0.3", "[fd00::1]:443", "10.0.0.1"]);
+
+        assert_eq!(client_ip(&hops, &proxies()), "10.0.0.3".parse().ok());
+        assert_eq!(client_ip(&chain(&["unknown", "_hidden"]), &proxies()), None);
+    }
+
+    #[test]
+    fn hops_may_carry_ports() {
+        assert_eq!(parse_ip("192.0.2.1:8080"), "192.0.2.1".parse().ok());
+        assert_eq!(parse_ip("[2001:db8::1]:8080"), "2001:db8::1".parse().ok());
+        assert_eq!(parse_ip("2001:db8::1"), "2001:db8::1".parse().ok());
+        assert_eq!(parse_ip("unknown"), None);
+    }
+
+    #[test]
+    fn forwarded_keeps_the_nearest_proto_and_host() {
+        let (chain, scheme, host) = parse_forwarded(
+            "for=192.0.2.60;proto=http;host=spoofed, For=\"[2001:db8::1]:4711\";Proto=HTTPS;host=cdn.test",
+        );
+
+        assert_eq!(chain, ["192.0.2.60", "[2001:db8::1]:4711"]);
+        assert_eq!(scheme.as_deref(), Some("https"));
+        assert_eq!(host.as_deref(), Some("cdn.test"));
+    }
+
+    #[test]
+    fn x_forwarded_headers_are_read_without_forwarded() {
+        let req = TestRequest::default()
+            .insert_header((X_FORWARDED_FOR, "203.0.113.9, 10.0.0.2"))
+            .insert_header((X_FORWARDED_PROTO, "ftp, https"))
+            .insert_header((X_FORWARDED_HOST, "cdn.test"))
+            .to_http_request();
+
+        let direct = ClientInfo {
+            ip: "10.0.0.1".parse().ok(),
+            scheme: "http".to_owned(),
+            host: "internal".to_owned(),
+        };
+
+        let info = from_proxy(&req, &proxies(), direct);
+
+        assert_eq!(info.ip, "203.0.113.9".parse().ok());
+        assert_eq!(info.scheme, "https");
+        assert_eq!(info.host, "cdn.test");
+    }
+
+    #[actix_web::test]
+    async fn forwarded_headers_from_untrusted_peers_are_ignored() {
+        let app = init_service(App::new().wrap(from_fn(behind_proxies)).default_service(web::to(
+            |req: HttpRequest| async move {
+                let info = ClientInfo::get(&req);
+                format!("{} {}://{}", info.ip.unwrap(), info.scheme, info.host)
+            },
+        )))
+        .await;
+        let resolve = async |peer: &str| {
+            let body = call_and_read_body(&app, spoofable(peer).to_request()).await;
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        assert_eq!(resolve("203.0.113.9:4711").await, "203.0.113.9 http://cdn.test");
+        assert_eq!(resolve("10.0.0.1:4711").await, "198.51.100.7 https://files.test");
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn share_links_follow_the_scheme_the_proxy_forwarded() {
+        run(async {
+            let app =
+                init_service(App::new().wrap(from_fn(behind_proxies)).configure(routes)).await;
+            let bucket = create_bucket(&create_user(&unique_name("proxied")).await).await;
+            let file = create_file(&bucket, "shared.txt").await;
+
+            let link = async |peer: &str| {
+                let req = spoofable(peer).uri(&format!("/file/s/{}", file.slug()));
+                let result: Value = call_and_read_body_json(&app, req.to_request()).await;
+                result["url"].as_str().unwrap().to_owned()
+            };
+
+            assert_eq!(
+                link("10.0.0.1:4711").await,
+                format!("https://files.test/f/{}", file.slug())
+            );
+            // A client can't make its links look secure.
+            assert_eq!(
+                link("203.0.113.9:4711").await,
+                format!("http://cdn.test/f/{}", file.slug())
+            );
+        })
+    }
+}
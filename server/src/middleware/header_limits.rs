@@ -0,0 +1,30 @@
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+
+use crate::AppError;
+use crate::config::Config;
+
+/// Refuses requests with more than `MAX_HEADER_COUNT` header lines or
+/// `MAX_HEADER_BYTES` of them, answered with the error envelope.
+///
+/// The HTTP codec enforces its own larger limits before this runs,
+/// those are answered by actix without a body.
+pub async fn header_limits(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let config = Config::get();
+    let headers = req.headers();
+
+    let count = headers.len();
+    let bytes =
+        headers.iter().map(|(name, value)| name.as_str().len() + value.len()).sum::<usize>();
+
+    if count > config.max_header_count || bytes > config.max_header_bytes {
+        return Err(AppError::HeadersTooLarge(count, bytes).into());
+    }
+
+    next.call(req).await
+}
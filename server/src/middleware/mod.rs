@@ -0,0 +1,12 @@
+pub mod access_log;
+pub mod byte_accounting;
+pub mod compress;
+pub mod csrf;
+pub mod expect;
+pub mod forwarded;
+pub mod header_limits;
+pub mod read_only;
+pub mod readiness;
+pub mod security_headers;
+pub mod storage_deadline;
+pub mod tenant;
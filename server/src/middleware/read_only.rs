@@ -0,0 +1,75 @@
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+
+use crate::AppError;
+use crate::config::Config;
+
+/// Refuses every request that could write when `READ_ONLY` is set,
+/// so a node acting as a read replica only ever serves content.
+pub async fn read_only(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    refuse_writes(Config::get().read_only, req, next).await
+}
+
+async fn refuse_writes(
+    read_only: bool,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if read_only && !is_safe(req.method()) {
+        return Err(AppError::ReadOnly.into());
+    }
+
+    next.call(req).await
+}
+
+/// Methods that never write, WebDAV's `PROPFIND` included.
+pub(crate) fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) || method.as_str() == "PROPFIND"
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::http::header::ALLOW;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, init_service};
+    use actix_web::web::{get, post};
+    use actix_web::{App, HttpResponse};
+
+    use super::*;
+
+    async fn refuse_all_writes(
+        req: ServiceRequest,
+        next: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<impl MessageBody>, Error> {
+        refuse_writes(true, req, next).await
+    }
+
+    #[actix_web::test]
+    async fn writes_are_refused_and_reads_served() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(refuse_all_writes))
+                .route("/file", get().to(HttpResponse::Ok))
+                .route("/file", post().to(HttpResponse::Created)),
+        )
+        .await;
+
+        let Err(error) = app.call(TestRequest::post().uri("/file").to_request()).await else {
+            panic!("Writes are refused on a read only node");
+        };
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers().get(ALLOW).unwrap(), "GET, HEAD, OPTIONS");
+
+        let response = app.call(TestRequest::get().uri("/file").to_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,64 @@
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+
+use super::read_only::is_safe;
+use crate::AppError;
+use crate::lifecycle::is_ready;
+
+/// Refuses writes until the schema is migrated,
+/// so nothing is written against a half migrated database.
+pub async fn readiness(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if !is_ready() && !is_safe(req.method()) {
+        return Err(AppError::NotReady.into());
+    }
+
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::{Service, ServiceResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, init_service, try_call_service};
+    use actix_web::web::{get, post};
+    use actix_web::{App, HttpResponse};
+
+    use super::*;
+    use crate::lifecycle::set_ready;
+
+    /// The status the server would answer with, errors
+    /// of the middleware included.
+    async fn status<S, B>(app: &S, req: TestRequest) -> StatusCode
+    where
+        S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+    {
+        match try_call_service(app, req.to_request()).await {
+            Ok(response) => response.status(),
+            Err(error) => error.as_response_error().status_code(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn writes_wait_for_readiness() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(readiness))
+                .route("/", get().to(HttpResponse::Ok))
+                .route("/", post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        set_ready(false);
+        assert_eq!(status(&app, TestRequest::get()).await, StatusCode::OK);
+        assert_eq!(status(&app, TestRequest::post()).await, StatusCode::SERVICE_UNAVAILABLE);
+
+        set_ready(true);
+        assert_eq!(status(&app, TestRequest::post()).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,179 @@
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    CONTENT_SECURITY_POLICY, HeaderMap, HeaderName, HeaderValue, REFERRER_POLICY,
+    STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use actix_web::middleware::Next;
+
+use crate::config::Config;
+use crate::middleware::forwarded::ClientInfo;
+
+/// Set in the extensions of a response meant to be framed by other
+/// sites, which then goes without the default `X-Frame-Options`.
+pub struct AllowFraming;
+
+/// Adds the baseline security headers to every response,
+/// `Strict-Transport-Security` is only sent when the client came over TLS.
+///
+/// Headers already set by a route are left untouched.
+pub async fn security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let config = Config::get();
+    let values = HeaderValues {
+        frame_options: &config.frame_options,
+        content_security_policy: &config.content_security_policy,
+        referrer_policy: &config.referrer_policy,
+        strict_transport_security: &config.strict_transport_security,
+    };
+
+    add_security_headers(&values, req, next).await
+}
+
+/// The configured values of the security headers, empty ones aren't sent.
+struct HeaderValues<'a> {
+    frame_options: &'a str,
+    content_security_policy: &'a str,
+    referrer_policy: &'a str,
+    strict_transport_security: &'a str,
+}
+
+async fn add_security_headers<B: MessageBody>(
+    values: &HeaderValues<'_>,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let secure = ClientInfo::get(req.request()).scheme == "https";
+
+    let mut res = next.call(req).await?;
+    let framed = res.response().extensions().contains::<AllowFraming>();
+    let headers = res.headers_mut();
+
+    insert_default(headers, X_CONTENT_TYPE_OPTIONS, "nosniff");
+
+    if !framed {
+        insert_default(headers, X_FRAME_OPTIONS, values.frame_options);
+    }
+
+    insert_default(headers, CONTENT_SECURITY_POLICY, values.content_security_policy);
+    insert_default(headers, REFERRER_POLICY, values.referrer_policy);
+
+    if secure {
+        insert_default(headers, STRICT_TRANSPORT_SECURITY, values.strict_transport_security);
+    }
+
+    Ok(res)
+}
+
+fn insert_default(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if value.is_empty() || headers.contains_key(&name) {
+        return;
+    }
+
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::Service;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, init_service};
+    use actix_web::web::get;
+    use actix_web::{App, HttpMessage, HttpResponse};
+
+    use super::*;
+
+    /// Stands in for `security_headers` with the default configuration.
+    async fn default_headers(
+        req: ServiceRequest,
+        next: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<impl MessageBody>, Error> {
+        let values = HeaderValues {
+            frame_options: "DENY",
+            content_security_policy: "default-src 'none'",
+            referrer_policy: "no-referrer",
+            strict_transport_security: "max-age=63072000; includeSubDomains",
+        };
+
+        add_security_headers(&values, req, next).await
+    }
+
+    /// Stands in for the `forwarded` middleware resolving a TLS client.
+    async fn over_tls(
+        req: ServiceRequest,
+        next: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<impl MessageBody>, Error> {
+        req.extensions_mut().insert(ClientInfo {
+            ip: None,
+            scheme: "https".to_owned(),
+            host: "cdn.test".to_owned(),
+        });
+
+        next.call(req).await
+    }
+
+    async fn framed() -> HttpResponse {
+        let mut response = HttpResponse::Ok().finish();
+        response.extensions_mut().insert(AllowFraming);
+
+        response
+    }
+
+    #[actix_web::test]
+    async fn plain_http_gets_every_header_but_hsts() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(default_headers))
+                .route("/", get().to(HttpResponse::Ok))
+                .route("/framed", get().to(framed)),
+        )
+        .await;
+
+        let response = app.call(TestRequest::get().uri("/").to_request()).await.unwrap();
+        let headers = response.headers();
+        assert_eq!(headers.get(X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert_eq!(headers.get(X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(headers.get(REFERRER_POLICY).unwrap(), "no-referrer");
+        assert!(headers.contains_key(CONTENT_SECURITY_POLICY));
+        assert!(!headers.contains_key(STRICT_TRANSPORT_SECURITY));
+
+        let response = app.call(TestRequest::get().uri("/framed").to_request()).await.unwrap();
+        assert!(!response.headers().contains_key(X_FRAME_OPTIONS));
+    }
+
+    #[actix_web::test]
+    async fn hsts_is_only_sent_over_tls() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(default_headers))
+                .wrap(from_fn(over_tls))
+                .route("/", get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let response = app.call(TestRequest::get().uri("/").to_request()).await.unwrap();
+        assert_eq!(
+            response.headers().get(STRICT_TRANSPORT_SECURITY).unwrap(),
+            "max-age=63072000; includeSubDomains"
+        );
+    }
+
+    #[actix_web::test]
+    async fn headers_set_by_the_route_are_kept() {
+        let app = init_service(App::new().wrap(from_fn(default_headers)).route(
+            "/",
+            get().to(|| async {
+                HttpResponse::Ok().insert_header((X_FRAME_OPTIONS, "SAMEORIGIN")).finish()
+            }),
+        ))
+        .await;
+
+        let response = app.call(TestRequest::get().uri("/").to_request()).await.unwrap();
+        assert_eq!(response.headers().get(X_FRAME_OPTIONS).unwrap(), "SAMEORIGIN");
+    }
+}
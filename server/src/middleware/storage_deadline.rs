@@ -0,0 +1,20 @@
+use std::time::{Duration, Instant};
+
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use storage::with_deadline;
+
+use crate::config::Config;
+
+/// Bounds the storage retries of a request by `STORAGE_REQUEST_BUDGET_MS`,
+/// so a flaky backend is given up on before the client does.
+pub async fn storage_deadline(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let budget = Duration::from_millis(Config::get().storage_request_budget_ms);
+
+    with_deadline(Instant::now() + budget, next.call(req)).await
+}
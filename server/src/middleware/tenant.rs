@@ -0,0 +1,155 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+use database::{DatabaseError, TenantDomainModel};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::middleware::forwarded::ClientInfo;
+
+/// Stored in the request extensions when the request came
+/// through a verified custom domain.
+#[derive(Clone)]
+pub struct Tenant {
+    pub owner_id: i64,
+    pub base_url: String,
+}
+
+/// Resolves the tenant from the `Host` header, the host of
+/// `PUBLIC_BASE_URL` is never looked up.
+///
+/// Unknown hosts are served as the default one unless `REJECT_UNKNOWN_HOSTS` is set.
+pub async fn tenant(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let config = Config::get();
+    let host = hostname(&ClientInfo::get(req.request()).host);
+
+    if host != hostname(public_host(&config.public_base_url)) {
+        match TenantDomainModel::get_verified(&host).await {
+            Ok(domain) => {
+                req.extensions_mut().insert(Tenant {
+                    owner_id: domain.owner_id(),
+                    base_url: domain.public_base_url(),
+                });
+            },
+            Err(DatabaseError::ModelNotFound(_)) if !config.reject_unknown_hosts => {},
+            Err(error) => return Err(AppError::from(error).into()),
+        }
+    }
+
+    next.call(req).await
+}
+
+/// The lowercased host without its port.
+pub fn hostname(host: &str) -> String {
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|char| char.is_ascii_digit()) => host,
+        _ => host,
+    };
+
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+pub fn public_host(base_url: &str) -> &str {
+    let host = base_url.split_once("://").map_or(base_url, |(_, rest)| rest);
+    host.split('/').next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::HOST;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, call_and_read_body, call_and_read_body_json, init_service};
+    use actix_web::{App, HttpRequest, web};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::routes::routes;
+    use crate::testing::{create_bucket, create_file, create_user, run, unique_name};
+
+    #[test]
+    fn hosts_are_compared_without_port_case_or_trailing_dot() {
+        assert_eq!(hostname("Files.Example.com.:8443"), "files.example.com");
+        assert_eq!(hostname("[::1]:80"), "[::1]");
+        assert_eq!(hostname("[::1]"), "[::1]");
+        assert_eq!(public_host("https://cdn.example.com:8443/base"), "cdn.example.com:8443");
+        assert_eq!(public_host("cdn.example.com"), "cdn.example.com");
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn only_verified_domains_resolve_to_their_owner() {
+        run(async {
+            let owner = create_user(&unique_name("tenant")).await;
+            let verified = format!("{}.example.com", unique_name("verified"));
+            let pending = format!("{}.example.com", unique_name("pending"));
+
+            let domain = TenantDomainModel::create(owner.id(), &verified, None).await.unwrap();
+            domain.verify().await.unwrap();
+            TenantDomainModel::create(owner.id(), &pending, None).await.unwrap();
+
+            let app = init_service(App::new().wrap(from_fn(tenant)).default_service(web::to(
+                |req: HttpRequest| async move {
+                    match req.extensions().get::<Tenant>() {
+                        Some(tenant) => format!("{} {}", tenant.owner_id, tenant.base_url),
+                        None => "default".to_owned(),
+                    }
+                },
+            )))
+            .await;
+            let resolve = async |host: &str| {
+                let req = TestRequest::get().insert_header((HOST, host)).to_request();
+                String::from_utf8(call_and_read_body(&app, req).await.to_vec()).unwrap()
+            };
+
+            assert_eq!(
+                resolve(&format!("{}:443", verified.to_uppercase())).await,
+                format!("{} https://{verified}", owner.id())
+            );
+            assert_eq!(resolve(&pending).await, "default");
+            assert_eq!(resolve("unknown.example.com").await, "default");
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn share_links_use_the_domain_the_request_came_through() {
+        run(async {
+            let app = init_service(App::new().wrap(from_fn(tenant)).configure(routes)).await;
+            let mut tenants = Vec::new();
+
+            for base_url in [None, Some("https://cdn.example.org/files")] {
+                let owner = create_user(&unique_name("tenant")).await;
+                let hostname = format!("{}.example.com", unique_name("files"));
+                let domain =
+                    TenantDomainModel::create(owner.id(), &hostname, base_url).await.unwrap();
+                domain.verify().await.unwrap();
+
+                let file = create_file(&create_bucket(&owner).await, "shared.txt").await;
+                tenants.push((hostname, file));
+            }
+
+            let link = async |host: &str, slug: &str| {
+                let req =
+                    TestRequest::get().uri(&format!("/file/s/{slug}")).insert_header((HOST, host));
+                let result: Value = call_and_read_body_json(&app, req.to_request()).await;
+                result["url"].as_str().unwrap().to_owned()
+            };
+
+            let (first, first_file) = &tenants[0];
+            let (second, second_file) = &tenants[1];
+
+            assert_eq!(
+                link(first, first_file.slug()).await,
+                format!("https://{first}/f/{}", first_file.slug())
+            );
+            assert_eq!(
+                link(second, second_file.slug()).await,
+                format!("https://cdn.example.org/files/f/{}", second_file.slug())
+            );
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::Error as IoError;
+use std::process::Stdio;
+
+use database::FileModel;
+use futures::future::LocalBoxFuture;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use super::PostProcessor;
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::app_storage::AppStorage;
+
+/// Pipes every upload to `AV_SCAN_COMMAND`, which follows the
+/// `clamdscan -` convention of exiting with 1 when it found something.
+///
+/// Uploads that can't be scanned are rejected too.
+pub struct AvScan;
+
+impl PostProcessor for AvScan {
+    fn name(&self) -> &'static str {
+        "av-scan"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    fn process<'a>(
+        &'a self,
+        file: FileModel,
+        data: &'a [u8],
+        _: &'a AppStorage,
+    ) -> LocalBoxFuture<'a, Result<FileModel, AppError>> {
+        Box::pin(async move {
+            let command = &Config::get().av_scan_command;
+            let mut arguments = command.split_whitespace();
+
+            let program = arguments
+                .next()
+                .ok_or_else(|| IoError::other("AV_SCAN_COMMAND must be set to scan uploads"))?;
+
+            let mut child = Command::new(program)
+                .args(arguments)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .kill_on_drop(true)
+                .spawn()?;
+
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(data).await?;
+            }
+
+            match child.wait().await?.code() {
+                Some(0) => Ok(file),
+                Some(1) => Err(AppError::UploadRejected("av-scan")),
+                _ => Err(IoError::other("AV_SCAN_COMMAND failed").into()),
+            }
+        })
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::Error as IoError;
+
+use clamav_client::tokio::{Socket, Tcp, scan_buffer};
+use log::warn;
+
+use crate::AppError;
+use crate::config::Config;
+
+/// Scans an upload with clamd before anything is stored, enabled by
+/// `AV_SCAN`, infected uploads are rejected and never persisted.
+///
+/// When clamd can't be reached the upload is rejected too,
+/// unless `AV_SCAN_FAIL_OPEN` is set.
+pub async fn scan(data: &[u8]) -> Result<(), AppError> {
+    let config = Config::get();
+
+    if !config.av_scan {
+        return Ok(());
+    }
+
+    match scan_with_clamd(data, &config.clamd_address).await {
+        Ok(None) => Ok(()),
+        Ok(Some(signature)) => Err(AppError::MalwareDetected(signature)),
+        Err(error) if config.av_scan_fail_open => {
+            warn!("Accepting an unscanned upload, clamd failed: {error}");
+            Ok(())
+        },
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// The signature clamd matched, `None` for clean data, addresses
+/// starting with `/` are unix sockets and anything else `host:port`.
+async fn scan_with_clamd(data: &[u8], address: &str) -> Result<Option<String>, IoError> {
+    let response = if address.starts_with('/') {
+        scan_buffer(data, Socket { socket_path: address }, None).await?
+    } else {
+        scan_buffer(data, Tcp { host_address: address }, None).await?
+    };
+
+    let response = std::str::from_utf8(&response).map_err(IoError::other)?;
+    let response = response.trim_end_matches(['\0', '\n']);
+
+    if response.ends_with("OK") {
+        return Ok(None);
+    }
+
+    match response.strip_suffix(" FOUND") {
+        Some(found) => Ok(Some(found.trim_start_matches("stream:").trim().to_owned())),
+        None => Err(IoError::other(format!("Unexpected clamd response {response}"))),
+    }
+}
@@ -0,0 +1,34 @@
+use actix_web::web::block;
+use database::FileModel;
+use futures::future::LocalBoxFuture;
+
+use super::PostProcessor;
+use crate::AppError;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::placeholder::image_metadata;
+
+/// Records the size and dominant color of images, used by placeholders.
+pub struct Dimensions;
+
+impl PostProcessor for Dimensions {
+    fn name(&self) -> &'static str {
+        "dimensions"
+    }
+
+    fn process<'a>(
+        &'a self,
+        file: FileModel,
+        data: &'a [u8],
+        _: &'a AppStorage,
+    ) -> LocalBoxFuture<'a, Result<FileModel, AppError>> {
+        Box::pin(async move {
+            // Decoding is CPU bound, so it's kept off the async workers.
+            let data = data.to_vec();
+
+            match block(move || image_metadata(&data)).await.ok().flatten() {
+                Some(image) => Ok(file.set_image(image).await?),
+                None => Ok(file),
+            }
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::OnceLock;
+
+use database::FileModel;
+use futures::future::LocalBoxFuture;
+use log::warn;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::processing::tracker::ProcessingTracker;
+use crate::utils::app_storage::AppStorage;
+
+pub mod av_scan;
+pub mod clamav;
+pub mod dimensions;
+pub mod poster;
+pub mod precompress;
+pub mod thumbnail;
+pub mod tracker;
+
+static PIPELINE: OnceLock<Pipeline> = OnceLock::new();
+
+/// A step run on every upload once its file was stored.
+pub trait PostProcessor: Send + Sync {
+    /// Used in `POST_PROCESSORS` and logs.
+    fn name(&self) -> &'static str;
+
+    /// Whether a failure rejects the upload, otherwise it's only logged.
+    fn required(&self) -> bool {
+        false
+    }
+
+    /// Returns the file with whatever the processor recorded on it.
+    fn process<'a>(
+        &'a self,
+        file: FileModel,
+        data: &'a [u8],
+        storage: &'a AppStorage,
+    ) -> LocalBoxFuture<'a, Result<FileModel, AppError>>;
+}
+
+/// The post processors enabled by `POST_PROCESSORS`, run in that order.
+#[derive(Default)]
+pub struct Pipeline {
+    processors: Vec<Box<dyn PostProcessor>>,
+}
+
+impl Pipeline {
+    pub fn get() -> &'static Pipeline {
+        PIPELINE.get_or_init(|| {
+            let mut pipeline = Pipeline::default();
+
+            for name in &Config::get().post_processors {
+                pipeline = match name.as_str() {
+                    "dimensions" => pipeline.register(dimensions::Dimensions),
+                    "thumbnail" => pipeline.register(thumbnail::Thumbnail),
+                    "poster" => pipeline.register(poster::Poster),
+                    "av-scan" => pipeline.register(av_scan::AvScan),
+                    "precompress" => pipeline.register(precompress::Precompress),
+                    name => panic!("POST_PROCESSORS has an unknown processor {name}"),
+                };
+            }
+
+            pipeline
+        })
+    }
+
+    pub fn register(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// Runs every processor on a freshly uploaded file, when a required one
+    /// fails the file is removed and its error returned.
+    ///
+    /// Each processor is tracked as a processing stage named after it.
+    pub async fn run(
+        &self,
+        mut file: FileModel,
+        data: &[u8],
+        storage: &AppStorage,
+    ) -> Result<FileModel, AppError> {
+        let tracker = ProcessingTracker::new(file.id());
+
+        for processor in &self.processors {
+            tracker.pending(processor.name()).await;
+        }
+
+        for processor in &self.processors {
+            // The file is moved into the processor, so it's reloaded when one fails.
+            let id = file.id();
+            tracker.running(processor.name()).await;
+
+            match processor.process(file, data, storage).await {
+                Ok(processed) => {
+                    tracker.done(processor.name()).await;
+                    file = processed;
+                },
+                Err(error) if processor.required() => {
+                    FileModel::get(id).await?.delete().await?;
+                    return Err(error);
+                },
+                Err(error) => {
+                    warn!("Post processor {} failed on file {id}: {error}", processor.name());
+                    tracker.failed(processor.name(), &error.to_string()).await;
+                    file = FileModel::get(id).await?;
+                },
+            }
+        }
+
+        Ok(file)
+    }
+}
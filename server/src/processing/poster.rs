@@ -0,0 +1,161 @@
+use std::io::{Cursor, Error as IoError, ErrorKind};
+use std::path::Path;
+use std::process::Stdio;
+
+use actix_web::web::block;
+use database::FileModel;
+use futures::future::LocalBoxFuture;
+use image::ImageFormat;
+use log::warn;
+use storage::Storage;
+use tokio::fs;
+use tokio::process::Command;
+
+use super::PostProcessor;
+use super::thumbnail::THUMBNAIL_SIZE;
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::app_storage::AppStorage;
+
+pub const POSTER_READY: &str = "ready";
+pub const POSTER_FAILED: &str = "failed";
+
+/// Fraction of the duration posters are taken at unless a timestamp is given.
+const DEFAULT_POSITION: f64 = 0.1;
+
+/// Posters are keyed on the file rather than its content,
+/// as owners pick the timestamp of their own copy.
+pub fn poster_key(file: &FileModel) -> String {
+    format!("posters/{}.jpg", file.id())
+}
+
+/// The poster scaled down to `THUMBNAIL_SIZE`.
+pub fn poster_thumbnail_key(file: &FileModel) -> String {
+    format!("posters/{}-thumbnail.jpg", file.id())
+}
+
+/// Extracts a poster frame from videos with ffmpeg, so listings have
+/// something to show, it's also served as their thumbnail.
+pub struct Poster;
+
+impl PostProcessor for Poster {
+    fn name(&self) -> &'static str {
+        "poster"
+    }
+
+    fn process<'a>(
+        &'a self,
+        file: FileModel,
+        data: &'a [u8],
+        storage: &'a AppStorage,
+    ) -> LocalBoxFuture<'a, Result<FileModel, AppError>> {
+        Box::pin(async move {
+            if !infer::is_video(data) {
+                return Ok(file);
+            }
+
+            extract_poster(file, data, storage, None).await
+        })
+    }
+}
+
+/// Extracts the poster of a video at `at` seconds, or 10% into it.
+///
+/// A video ffmpeg can't take a frame from is recorded as failed rather
+/// than retried, only a missing ffmpeg is reported as an error.
+pub async fn extract_poster(
+    file: FileModel,
+    data: &[u8],
+    storage: &AppStorage,
+    at: Option<f64>,
+) -> Result<FileModel, AppError> {
+    match render(file.id(), data, at).await {
+        Ok((at, poster, thumbnail)) => {
+            storage.put(&poster_key(&file), &poster).await?;
+            storage.put(&poster_thumbnail_key(&file), &thumbnail).await?;
+
+            Ok(file.set_poster(Some(POSTER_READY), Some(at)).await?)
+        },
+        Err(error) if error.kind() == ErrorKind::NotFound => Err(error.into()),
+        Err(error) => {
+            warn!("No poster could be extracted from file {}: {error}", file.id());
+            Ok(file.set_poster(Some(POSTER_FAILED), at).await?)
+        },
+    }
+}
+
+/// The timestamp, poster and thumbnail of a video, ffmpeg needs to seek
+/// so the video is written to a temporary file rather than piped.
+async fn render(id: i64, data: &[u8], at: Option<f64>) -> Result<(f64, Vec<u8>, Vec<u8>), IoError> {
+    let input = std::env::temp_dir().join(format!("poster-{id}-{:016x}", rand::random::<u64>()));
+
+    fs::write(&input, data).await?;
+    let rendered = render_file(&input, at).await;
+    let _ = fs::remove_file(&input).await;
+
+    rendered
+}
+
+async fn render_file(input: &Path, at: Option<f64>) -> Result<(f64, Vec<u8>, Vec<u8>), IoError> {
+    let at = match at {
+        Some(at) => at,
+        None => duration(input).await? * DEFAULT_POSITION,
+    };
+
+    let output = Command::new(&Config::get().ffmpeg_path)
+        .args(["-v", "error", "-ss", &format!("{at:.3}"), "-i"])
+        .arg(input)
+        .args(["-frames:v", "1", "-f", "image2", "-c:v", "mjpeg", "pipe:1"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(IoError::other(format!("ffmpeg took no frame at {at:.3}s")));
+    }
+
+    let poster = output.stdout;
+
+    // Decoding is CPU bound, so it's kept off the async workers.
+    let thumbnail = {
+        let poster = poster.clone();
+
+        block(move || {
+            let image = image::load_from_memory(&poster).map_err(IoError::other)?;
+            let mut jpeg = Vec::new();
+
+            image
+                .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+                .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+                .map_err(IoError::other)?;
+
+            Ok::<_, IoError>(jpeg)
+        })
+        .await
+        .map_err(IoError::other)??
+    };
+
+    Ok((at, poster, thumbnail))
+}
+
+/// The duration of a video in seconds, videos without one have no poster.
+async fn duration(input: &Path) -> Result<f64, IoError> {
+    let output = Command::new(&Config::get().ffprobe_path)
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0"])
+        .arg(input)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    let duration = String::from_utf8_lossy(&output.stdout).trim().parse::<f64>().unwrap_or(0.0);
+
+    if !output.status.success() || !duration.is_finite() || duration <= 0.0 {
+        return Err(IoError::other("the video has no duration"));
+    }
+
+    Ok(duration)
+}
@@ -0,0 +1,70 @@
+use std::io::Error as IoError;
+
+use actix_web::web::block;
+use database::FileModel;
+use futures::future::LocalBoxFuture;
+use storage::Storage;
+
+use super::PostProcessor;
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::compression::{Encoding, is_compressible};
+use crate::utils::inline::sniff;
+use crate::utils::serving::file_blob_key;
+
+/// The storage key of the `encoding` variant of the blob at `key`,
+/// shared by every file with the same content.
+pub fn precompressed_key(key: &str, encoding: Encoding) -> String {
+    format!("precompressed/{key}.{}", encoding.extension())
+}
+
+/// Whether variants are stored, they're never looked up otherwise.
+pub fn enabled() -> bool {
+    Config::get().post_processors.iter().any(|name| name == "precompress")
+}
+
+/// Stores brotli and gzip variants of compressible uploads, served to
+/// clients accepting them instead of compressing on every download.
+///
+/// Variants that aren't smaller than the content aren't kept.
+pub struct Precompress;
+
+impl PostProcessor for Precompress {
+    fn name(&self) -> &'static str {
+        "precompress"
+    }
+
+    fn process<'a>(
+        &'a self,
+        file: FileModel,
+        data: &'a [u8],
+        storage: &'a AppStorage,
+    ) -> LocalBoxFuture<'a, Result<FileModel, AppError>> {
+        Box::pin(async move {
+            // Appended content changes under the same key.
+            let skip = file.appendable()
+                || data.len() < Config::get().compress_min_bytes
+                || !is_compressible(sniff(data, file.path()));
+
+            if skip {
+                return Ok(file);
+            }
+
+            let key = file_blob_key(&file)?;
+
+            for encoding in [Encoding::Brotli, Encoding::Gzip] {
+                // Compressing is CPU bound, so it's kept off the async workers.
+                let content = data.to_vec();
+                let variant =
+                    block(move || encoding.compress(&content)).await.map_err(IoError::other)??;
+
+                if variant.len() < data.len() {
+                    storage.put_if_absent(&precompressed_key(&key, encoding), &variant).await?;
+                }
+            }
+
+            Ok(file)
+        })
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::sync::{Mutex, OnceLock};
+
+use actix_web::rt::spawn;
+use actix_web::web::{Data, block};
+use database::FileModel;
+use futures::future::LocalBoxFuture;
+use image::ImageFormat;
+use log::warn;
+use storage::Storage;
+
+use super::PostProcessor;
+use crate::AppError;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::serving::file_blob_key;
+
+/// Longest side of a thumbnail, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 320;
+
+/// The storage key of the thumbnail of a file, shared by
+/// every file with the same content.
+pub fn thumbnail_key(file: &FileModel) -> Result<String, AppError> {
+    Ok(format!("thumbnails/{}.png", file_blob_key(file)?))
+}
+
+/// Seconds caches may keep serving a thumbnail while they fetch a newer one.
+pub const STALE_WHILE_REVALIDATE: u64 = 86400;
+
+/// Files whose thumbnail is being rendered in the background.
+static REGENERATING: OnceLock<Mutex<HashSet<i64>>> = OnceLock::new();
+
+/// The latest thumbnail rendered for a file, kept by file instead of
+/// content so it can still be served once the content changed.
+pub fn latest_thumbnail_key(file: &FileModel) -> String {
+    format!("thumbnails/files/{}.png", file.id())
+}
+
+async fn render(data: Vec<u8>) -> Option<Vec<u8>> {
+    block(move || {
+        let image = image::load_from_memory(&data).ok()?;
+        let mut png = Vec::new();
+
+        image
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .ok()?;
+
+        Some(png)
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+async fn store(file: &FileModel, data: Vec<u8>, storage: &AppStorage) -> Result<(), AppError> {
+    if let Some(thumbnail) = render(data).await {
+        storage.put_if_absent(&thumbnail_key(file)?, &thumbnail).await?;
+        storage.put(&latest_thumbnail_key(file), &thumbnail).await?;
+    }
+
+    Ok(())
+}
+
+/// Renders the thumbnail of the current content in the background,
+/// at most once at a time per file.
+pub fn regenerate(file: FileModel, storage: Data<AppStorage>) {
+    let regenerating = REGENERATING.get_or_init(Default::default);
+
+    if !regenerating.lock().unwrap_or_else(|error| error.into_inner()).insert(file.id()) {
+        return;
+    }
+
+    spawn(async move {
+        let result: Result<(), AppError> = async {
+            let data = storage.get(&file_blob_key(&file)?).await?;
+            store(&file, data, &storage).await
+        }
+        .await;
+
+        if let Err(error) = result {
+            warn!("Couldn't regenerate the thumbnail of file {}: {error}", file.id());
+        }
+
+        regenerating.lock().unwrap_or_else(|error| error.into_inner()).remove(&file.id());
+    });
+}
+
+/// Stores a PNG thumbnail of images, served at `/f/<slug>/thumbnail.png`.
+pub struct Thumbnail;
+
+impl PostProcessor for Thumbnail {
+    fn name(&self) -> &'static str {
+        "thumbnail"
+    }
+
+    fn process<'a>(
+        &'a self,
+        file: FileModel,
+        data: &'a [u8],
+        storage: &'a AppStorage,
+    ) -> LocalBoxFuture<'a, Result<FileModel, AppError>> {
+        Box::pin(async move {
+            store(&file, data.to_vec(), storage).await?;
+
+            Ok(file)
+        })
+    }
+}
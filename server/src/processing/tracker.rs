@@ -0,0 +1,44 @@
+use database::{FileProcessingModel, ProcessingState};
+use log::warn;
+
+/// Records the stages run on a file, a stage only has to be given a
+/// name and reported through this to show up in `/file/{id}/processing`.
+///
+/// Recording is best effort, a failure to write the state is logged
+/// rather than failing the stage itself.
+#[derive(Clone, Copy)]
+pub struct ProcessingTracker {
+    file_id: i64,
+}
+
+impl ProcessingTracker {
+    pub fn new(file_id: i64) -> Self {
+        ProcessingTracker { file_id }
+    }
+
+    async fn record(&self, stage: &str, state: ProcessingState, error: Option<&str>) {
+        if let Err(error) = FileProcessingModel::record(self.file_id, stage, state, error).await {
+            warn!("Couldn't record stage {stage} of file {}: {error}", self.file_id);
+        }
+    }
+
+    pub async fn pending(&self, stage: &str) {
+        self.record(stage, ProcessingState::Pending, None).await;
+    }
+
+    pub async fn running(&self, stage: &str) {
+        self.record(stage, ProcessingState::Running, None).await;
+    }
+
+    pub async fn done(&self, stage: &str) {
+        self.record(stage, ProcessingState::Done, None).await;
+    }
+
+    pub async fn failed(&self, stage: &str, error: &str) {
+        self.record(stage, ProcessingState::Failed, Some(error)).await;
+    }
+
+    pub async fn skipped(&self, stage: &str) {
+        self.record(stage, ProcessingState::Skipped, None).await;
+    }
+}
@@ -0,0 +1,220 @@
+use std::path::PathBuf;
+
+use actix_web::web::{Data, Json, Path, Query};
+use actix_web::{HttpResponse, Responder, post};
+use database::{BlobModel, BucketModel, DatabaseError, JobModel, Visibility};
+use serde_json::json;
+
+use crate::AppError;
+use crate::cli::export::{ExportOptions, export, sanitize};
+use crate::config::Config;
+use crate::extractors::admin::AdminUser;
+use crate::extractors::file_id::FileId;
+use crate::tasks::jobs::spawn_tracked;
+use crate::tasks::orphans::purge_orphaned_blobs;
+use crate::tasks::supervisor::Supervisor;
+use crate::utils::app_storage::{AppStorage, local};
+use crate::utils::purge::{Backend, enqueue, file_key, valid_key};
+
+macros_utils::routes! {
+    route route_restart_task,
+    route route_check_refs,
+    route route_purge_orphans,
+    route route_export_static,
+    route route_purge,
+    route route_cancel_job,
+}
+
+/// Restarts a supervised task without waiting for its backoff,
+/// this is the only way back for a task that was given up on.
+#[post("/tasks/{name}/restart")]
+pub async fn route_restart_task(
+    _: AdminUser,
+    name: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    if !Supervisor::get().restart(&name) {
+        return Err(DatabaseError::ModelNotFound("task").into());
+    }
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[derive(serde::Deserialize)]
+pub struct CheckRefsQuery {
+    #[serde(default)]
+    repair: bool,
+}
+
+/// Recomputes blob reference counts as a tracked job, the
+/// same check `cdnctl check-refs` runs.
+#[post("/jobs/check-refs")]
+pub async fn route_check_refs(
+    _: AdminUser,
+    query: Query<CheckRefsQuery>,
+) -> Result<impl Responder, AppError> {
+    let repair = query.repair;
+
+    let job =
+        spawn_tracked("check-refs", json!({ "repair": repair }), move |progress| async move {
+            // The check is a single statement, it can only be cancelled before it starts.
+            progress.update(0, Some(1)).await?;
+            let drift = BlobModel::check_refs(repair).await?;
+            progress.update(1, Some(1)).await?;
+
+            Ok(json!({ "drifted": drift.len(), "repaired": repair }))
+        })
+        .await?;
+
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PurgeOrphansQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Removes blobs no file references anymore as a tracked
+/// job, its result tells how many bytes were reclaimed.
+#[post("/jobs/purge-orphans")]
+pub async fn route_purge_orphans(
+    _: AdminUser,
+    storage: Data<AppStorage>,
+    query: Query<PurgeOrphansQuery>,
+) -> Result<impl Responder, AppError> {
+    let dry_run = query.dry_run;
+    let parameters = json!({ "dry_run": dry_run });
+
+    let job = spawn_tracked("purge-orphans", parameters, move |_| async move {
+        Ok(json!(purge_orphaned_blobs(local(&storage), dry_run).await?))
+    })
+    .await?;
+
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportStaticQuery {
+    bucket: String,
+    base_url: Option<String>,
+}
+
+/// Exports a public bucket as a static mirror as a tracked job, the same
+/// export `cdnctl export-static` runs. Every bucket gets a directory of
+/// its own under `EXPORT_PATH`, named after it and its id, exporting it
+/// again updates it.
+#[post("/jobs/export-static")]
+pub async fn route_export_static(
+    _: AdminUser,
+    storage: Data<AppStorage>,
+    query: Query<ExportStaticQuery>,
+) -> Result<impl Responder, AppError> {
+    let root = Config::get().export_path.as_deref().ok_or(AppError::ExportDisabled)?;
+    let ExportStaticQuery { bucket, base_url } = query.into_inner();
+
+    // Refused right away rather than failing the job.
+    let found = BucketModel::get_by_name(&bucket).await?;
+
+    if found.visibility() != Visibility::Public {
+        return Err(AppError::BucketNotPublic);
+    }
+
+    let parameters = json!({ "bucket": bucket, "base_url": base_url });
+
+    // The id tells apart names that sanitize the same.
+    let directory = format!("{}~{}", sanitize(found.name()), found.id());
+    let options = ExportOptions {
+        out: PathBuf::from(root).join(directory),
+        bucket,
+        base_url,
+    };
+
+    let job = spawn_tracked("export-static", parameters, move |progress| async move {
+        Ok(json!(export(&storage, &options, Some(&progress)).await?))
+    })
+    .await?;
+
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PurgeRequest {
+    #[serde(default)]
+    file_ids: Vec<FileId>,
+    /// Surrogate keys as served, such as `bucket-7` or `owner-3`.
+    #[serde(default)]
+    keys: Vec<String>,
+}
+
+impl PurgeRequest {
+    /// The keys of the files followed by the raw ones, each once.
+    fn surrogate_keys(&self) -> Result<Vec<String>, AppError> {
+        if let Some(key) = self.keys.iter().find(|key| !valid_key(key)) {
+            return Err(AppError::InvalidSurrogateKey(key.clone()));
+        }
+
+        let mut keys = Vec::new();
+
+        for key in self.file_ids.iter().map(|id| file_key(**id)).chain(self.keys.iter().cloned()) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        if keys.is_empty() {
+            return Err(AppError::InvalidSurrogateKey(String::new()));
+        }
+
+        Ok(keys)
+    }
+}
+
+/// Purges files and surrogate keys from the cache in front of the origin
+/// as a tracked job, for what changed without the server knowing.
+#[post("/purge")]
+pub async fn route_purge(
+    _: AdminUser,
+    request: Json<PurgeRequest>,
+) -> Result<impl Responder, AppError> {
+    let backend = Backend::get();
+
+    if !backend.enabled() {
+        return Err(AppError::PurgeDisabled);
+    }
+
+    let job = enqueue(backend, request.surrogate_keys()?).await?;
+
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+/// Asks a running job to stop, it does so at its next progress update.
+#[post("/jobs/{id}/cancel")]
+pub async fn route_cancel_job(_: AdminUser, id: Path<i64>) -> Result<impl Responder, AppError> {
+    Ok(Json(JobModel::request_cancel(*id).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(file_ids: &[i64], keys: &[&str]) -> PurgeRequest {
+        PurgeRequest {
+            file_ids: file_ids.iter().copied().map(FileId).collect(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn purges_name_files_by_their_key() {
+        let keys = request(&[42], &["bucket-7", "owner-3"]).surrogate_keys();
+        assert_eq!(keys.unwrap(), ["file-42", "bucket-7", "owner-3"]);
+
+        let keys = request(&[42], &["bucket-7", "file-42"]).surrogate_keys();
+        assert_eq!(keys.unwrap(), ["file-42", "bucket-7"]);
+
+        for keys in [&[][..], &["bucket-7", "a b"], &[""]] {
+            let error = request(&[], keys).surrogate_keys().unwrap_err();
+            assert!(matches!(error, AppError::InvalidSurrogateKey(_)), "{keys:?}");
+        }
+    }
+}
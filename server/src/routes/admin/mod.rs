@@ -0,0 +1,11 @@
+mod create;
+mod read;
+mod update;
+
+macros_utils::routes! {
+    load create,
+    load read,
+    load update,
+
+    on "/admin"
+}
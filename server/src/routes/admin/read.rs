@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+
+use actix_web::http::header::CONTENT_SECURITY_POLICY;
+use actix_web::web::{Data, Json, Path, Query};
+use actix_web::{HttpRequest, HttpResponse, Responder, get};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use database::{
+    BlobModel, BucketModel, FileModel, FileResult, FileUsage, JobModel, StorageModel,
+    TrafficStatsModel, UserCounts, UserModel, UserResult,
+};
+use futures::join;
+use serde::Serialize;
+use storage::{BreakerState, Operation};
+
+use crate::AppError;
+use crate::extractors::admin::AdminUser;
+use crate::tasks::supervisor::Supervisor;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::dashboard::{page, panel};
+use crate::utils::links::file_result;
+use crate::utils::serving::size_mismatches;
+use crate::utils::upload_limit::upload_key;
+use crate::utils::upload_slots::UploadSlots;
+
+macros_utils::routes! {
+    route route_stats,
+    route route_tasks,
+    route route_storage,
+    route route_storage_breakers,
+    route route_recent_files,
+    route route_size_mismatches,
+    route route_traffic,
+    route route_jobs,
+    route route_job,
+    route route_dashboard,
+    route route_users,
+    route route_user,
+}
+
+/// Seconds between reloads of the dashboard.
+const DASHBOARD_REFRESH: u64 = 30;
+
+#[derive(Serialize)]
+struct AdminStats {
+    users: UserCounts,
+    /// Files this instance found with another size than recorded since startup.
+    size_mismatches: u64,
+}
+
+/// Totals for the admin overview.
+#[get("/stats")]
+pub async fn route_stats(_: AdminUser) -> Result<impl Responder, AppError> {
+    Ok(Json(AdminStats {
+        users: UserModel::count().await?,
+        size_mismatches: size_mismatches(),
+    }))
+}
+
+/// Lists the supervised background tasks with their status and restarts.
+#[get("/tasks")]
+pub async fn route_tasks(_: AdminUser) -> impl Responder {
+    Json(Supervisor::get().tasks())
+}
+
+#[derive(serde::Deserialize)]
+pub struct StorageQuery {
+    /// Smallest users first when `asc`, largest first otherwise.
+    order: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Per user storage totals, as of the last background refresh
+/// of the summary every `STORAGE_SUMMARY_INTERVAL` seconds.
+#[get("/storage")]
+pub async fn route_storage(
+    _: AdminUser,
+    query: Query<StorageQuery>,
+) -> Result<impl Responder, AppError> {
+    let ascending = query.order.as_deref() == Some("asc");
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    Ok(Json(StorageModel::user_totals(ascending, limit).await?))
+}
+
+#[derive(Serialize)]
+struct BreakerEntry {
+    operation: &'static str,
+    state: &'static str,
+    attempts: u64,
+    retries: u64,
+    failures: u64,
+    rejected: u64,
+    opened: u64,
+}
+
+/// The circuit breaker of every storage operation with its counters
+/// since startup, to tell a flaky backend from a dead one.
+#[get("/storage/breakers")]
+pub async fn route_storage_breakers(_: AdminUser, storage: Data<AppStorage>) -> impl Responder {
+    let entries = Operation::ALL.map(|operation| {
+        let metrics = storage.metrics(operation);
+
+        BreakerEntry {
+            operation: match operation {
+                Operation::Get => "get",
+                Operation::Put => "put",
+                Operation::Delete => "delete",
+                Operation::Exists => "exists",
+            },
+            state: match metrics.state {
+                BreakerState::Closed => "closed",
+                BreakerState::Open(_) => "open",
+                BreakerState::HalfOpen => "half_open",
+            },
+            attempts: metrics.attempts,
+            retries: metrics.retries,
+            failures: metrics.failures,
+            rejected: metrics.rejected,
+            opened: metrics.opened,
+        }
+    });
+
+    Json(entries)
+}
+
+#[derive(serde::Deserialize)]
+pub struct RecentQuery {
+    /// Such as `image/png` or `image/*`, matched against the
+    /// type sniffed from the content at upload.
+    mime: Option<String>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct RecentFile {
+    /// Unset for anonymous uploads.
+    owner_id: Option<i64>,
+    #[serde(flatten)]
+    file: FileResult,
+}
+
+/// Newest uploads across every user, for moderation.
+#[get("/files/recent")]
+pub async fn route_recent_files(
+    req: HttpRequest,
+    _: AdminUser,
+    query: Query<RecentQuery>,
+) -> Result<impl Responder, AppError> {
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let files = FileModel::list_recent(query.mime.as_deref(), offset, limit).await?;
+
+    let mut bucket_ids = files.iter().map(FileModel::bucket_id).collect::<Vec<_>>();
+    bucket_ids.sort_unstable();
+    bucket_ids.dedup();
+
+    let owners = BucketModel::list_by_ids(&bucket_ids)
+        .await?
+        .into_iter()
+        .map(|bucket| (bucket.id(), bucket.owner_id()))
+        .collect::<HashMap<_, _>>();
+
+    Ok(Json(
+        files
+            .iter()
+            .map(|file| RecentFile {
+                owner_id: owners.get(&file.bucket_id()).copied().flatten(),
+                file: file_result(&req, file),
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct MismatchesQuery {
+    offset: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct SizeMismatch {
+    /// The size the content was found with, `size` is the recorded one.
+    stored_size: Option<i64>,
+    #[serde(flatten)]
+    file: FileResult,
+}
+
+/// Files whose content was found with another size than recorded when
+/// serving them, the most recently changed first. They're served with
+/// what's stored until their size matches again.
+#[get("/files/size-mismatches")]
+pub async fn route_size_mismatches(
+    req: HttpRequest,
+    _: AdminUser,
+    query: Query<MismatchesQuery>,
+) -> Result<impl Responder, AppError> {
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    let files = FileModel::list_size_mismatches(offset, limit).await?;
+
+    Ok(Json(
+        files
+            .iter()
+            .map(|file| SizeMismatch {
+                stored_size: file.mismatched_size(),
+                file: file_result(&req, file),
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TrafficQuery {
+    day: Option<NaiveDate>,
+    limit: Option<i64>,
+}
+
+/// Bytes every client exchanged on every route during a day, today by
+/// default, those wasting the most first.
+#[get("/traffic")]
+pub async fn route_traffic(
+    _: AdminUser,
+    query: Query<TrafficQuery>,
+) -> Result<impl Responder, AppError> {
+    let day = query.day.unwrap_or_else(|| Utc::now().date_naive());
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    Ok(Json(TrafficStatsModel::list_day(day, limit).await?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct JobsQuery {
+    kind: Option<String>,
+    state: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Tracked admin jobs, newest first.
+#[get("/jobs")]
+pub async fn route_jobs(_: AdminUser, query: Query<JobsQuery>) -> Result<impl Responder, AppError> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    Ok(Json(JobModel::list(query.kind.as_deref(), query.state.as_deref(), limit).await?))
+}
+
+/// A tracked admin job with its progress and outcome.
+#[get("/jobs/{id}")]
+pub async fn route_job(_: AdminUser, id: Path<i64>) -> Result<impl Responder, AppError> {
+    Ok(Json(JobModel::get(*id).await?))
+}
+
+/// An overview for operators, every panel loads on its own so a
+/// slow or failing source only blanks its own panel.
+#[get("/dashboard")]
+pub async fn route_dashboard(req: HttpRequest, _: AdminUser) -> HttpResponse {
+    let (users, blobs, storage, tasks, jobs, uploads, largest) = join!(
+        panel("Users", async { Ok(UserModel::count().await?) }),
+        panel("Blobs", async { Ok(BlobModel::stats().await?) }),
+        panel("Largest users", async { Ok(StorageModel::user_totals(false, 10).await?) }),
+        panel("Background tasks", async { Ok(Supervisor::get().tasks()) }),
+        panel("Running jobs", async { Ok(JobModel::list(None, Some("running"), 20).await?) }),
+        panel("Uploads in flight", async { Ok(UploadSlots::get().all_in_flight()) }),
+        panel("Largest files", async {
+            let files = FileModel::largest(10).await?;
+            Ok(files.iter().map(|file| file_result(&req, file)).collect::<Vec<_>>())
+        }),
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((CONTENT_SECURITY_POLICY, "default-src 'none'; style-src 'unsafe-inline'"))
+        .body(page(&[users, blobs, storage, tasks, jobs, uploads, largest], DASHBOARD_REFRESH))
+}
+
+#[derive(serde::Deserialize)]
+pub struct UsersQuery {
+    /// Matched anywhere in the username or the email.
+    q: Option<String>,
+    registered_after: Option<NaiveDate>,
+    registered_before: Option<NaiveDate>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+}
+
+impl UsersQuery {
+    /// The search term, a blank one matches every user.
+    fn term(&self) -> Option<&str> {
+        self.q.as_deref().map(str::trim).filter(|term| !term.is_empty())
+    }
+
+    /// The offset and limit of the page, kept within bounds.
+    fn page(&self) -> (i64, i64) {
+        (self.offset.unwrap_or(0).max(0), self.limit.unwrap_or(100).clamp(1, 1000))
+    }
+}
+
+#[derive(Serialize)]
+pub struct AdminUserEntry {
+    #[serde(flatten)]
+    user: UserResult,
+    email: String,
+    deleted_at: Option<NaiveDateTime>,
+}
+
+impl AdminUserEntry {
+    fn new(user: &UserModel) -> Self {
+        AdminUserEntry {
+            user: user.into_result(),
+            email: user.email().to_owned(),
+            deleted_at: user.deleted_at(),
+        }
+    }
+}
+
+/// Searches users by username or email and registration date, oldest first.
+#[get("/users")]
+pub async fn route_users(
+    _: AdminUser,
+    query: Query<UsersQuery>,
+) -> Result<impl Responder, AppError> {
+    let (offset, limit) = query.page();
+
+    let users = UserModel::search(
+        query.term(),
+        query.registered_after,
+        query.registered_before,
+        offset,
+        limit,
+    )
+    .await?;
+
+    Ok(Json(users.iter().map(AdminUserEntry::new).collect::<Vec<_>>()))
+}
+
+#[derive(Serialize)]
+pub struct AdminUserDetail {
+    #[serde(flatten)]
+    user: AdminUserEntry,
+    usage: FileUsage,
+    /// Uploads the user is running right now on this instance.
+    uploads_in_flight: u32,
+}
+
+/// A single user along with the storage they use.
+#[get("/users/{id}")]
+pub async fn route_user(_: AdminUser, id: Path<i64>) -> Result<impl Responder, AppError> {
+    let user = UserModel::get(*id).await?;
+
+    Ok(Json(AdminUserDetail {
+        user: AdminUserEntry::new(&user),
+        usage: FileModel::usage_for_user(user.id()).await?,
+        uploads_in_flight: UploadSlots::get().in_flight(&upload_key(Some(user.id()), None)),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users_query(query: &str) -> UsersQuery {
+        Query::<UsersQuery>::from_query(query).unwrap().into_inner()
+    }
+
+    #[test]
+    fn user_searches_are_paged_within_bounds() {
+        let query = users_query("");
+        assert_eq!(query.term(), None);
+        assert_eq!(query.page(), (0, 100));
+
+        let query = users_query("q=%20%20&offset=-5&limit=0");
+        assert_eq!(query.term(), None);
+        assert_eq!(query.page(), (0, 1));
+
+        let query = users_query("q=%20Alice%20&offset=20&limit=5000&registered_after=2024-02-29");
+        assert_eq!(query.term(), Some("Alice"));
+        assert_eq!(query.page(), (20, 1000));
+        assert_eq!(query.registered_after, NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(query.registered_before, None);
+    }
+}
@@ -0,0 +1,59 @@
+use actix_web::web::{Json, Path};
+use actix_web::{Responder, patch};
+use database::{Role, UserModel};
+use log::info;
+
+use crate::AppError;
+use crate::extractors::admin::AdminUser;
+
+macros_utils::routes! {
+    route route_set_role,
+    route route_set_active,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleRequest {
+    role: Role,
+}
+
+/// Promotes or demotes a user, the last admin can't be demoted.
+#[patch("/user/{id}/role")]
+pub async fn route_set_role(
+    AdminUser(admin): AdminUser,
+    id: Path<i64>,
+    request: Json<RoleRequest>,
+) -> Result<impl Responder, AppError> {
+    let user = UserModel::get(*id).await?;
+    let updated = user.set_role(request.role).await?;
+
+    info!(
+        "Admin {} changed the role of user {} from {} to {}",
+        admin.id(),
+        user.id(),
+        user.role().as_str(),
+        updated.role().as_str()
+    );
+
+    Ok(Json(updated.into_result()))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ActiveRequest {
+    active: bool,
+}
+
+/// Disables or enables a user, disabled users can't authenticate
+/// but their files stay served.
+#[patch("/user/{id}/active")]
+pub async fn route_set_active(
+    AdminUser(admin): AdminUser,
+    id: Path<i64>,
+    request: Json<ActiveRequest>,
+) -> Result<impl Responder, AppError> {
+    let user = UserModel::get(*id).await?.set_active(request.active).await?;
+    let action = if user.is_active() { "enabled" } else { "disabled" };
+
+    info!("Admin {} {action} user {}", admin.id(), user.id());
+
+    Ok(Json(user.into_result()))
+}
@@ -2,3 +2,9 @@ mod create;
 mod delete;
 mod read;
 mod update;
+
+macros_utils::routes! {
+    load read,
+
+    on "/auth"
+}
@@ -1 +1,34 @@
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::{HttpRequest, HttpResponse, get};
+use serde::Serialize;
 
+use crate::AppError;
+use crate::config::Config;
+use crate::middleware::forwarded::ClientInfo;
+use crate::utils::csrf::{CSRF_COOKIE, csrf_token};
+
+macros_utils::routes! {
+    route route_csrf,
+}
+
+#[derive(Serialize)]
+struct CsrfResult {
+    token: String,
+}
+
+/// The CSRF token of the current session, also set as a readable cookie
+/// so the UI can echo it in `X-CSRF-Token` on mutating requests.
+#[get("/csrf")]
+pub async fn route_csrf(req: HttpRequest) -> Result<HttpResponse, AppError> {
+    let session =
+        req.cookie(&Config::get().session_cookie_name).ok_or(AppError::AuthorizationError)?;
+    let token = csrf_token(session.value());
+
+    let cookie = Cookie::build(CSRF_COOKIE, token.clone())
+        .path("/")
+        .same_site(SameSite::Strict)
+        .secure(ClientInfo::get(&req).scheme == "https")
+        .finish();
+
+    Ok(HttpResponse::Ok().cookie(cookie).json(CsrfResult { token }))
+}
@@ -0,0 +1,45 @@
+use actix_web::web::{Json, Path};
+use actix_web::{HttpRequest, Responder, post};
+use database::{BucketModel, UserModel};
+use serde::Serialize;
+
+use crate::AppError;
+use crate::utils::dav::encode;
+use crate::utils::feed::{FeedFormat, feed_token};
+use crate::utils::links::public_url;
+
+macros_utils::routes! {
+    route route_feed_token,
+}
+
+#[derive(Serialize)]
+pub struct FeedToken {
+    pub token: String,
+    pub atom_url: String,
+    pub rss_url: String,
+}
+
+/// Mints the token the feeds of an owned bucket are read with while
+/// it's unlisted, public feeds don't need it and private ones ignore it.
+#[post("/{id}/feed-token")]
+pub async fn route_feed_token(
+    req: HttpRequest,
+    user: UserModel,
+    id: Path<i64>,
+) -> Result<impl Responder, AppError> {
+    let bucket = BucketModel::get_owned(*id, user.id()).await?;
+    let token = feed_token(bucket.id());
+
+    let url = |format: FeedFormat| {
+        public_url(
+            &req,
+            &format!("/c/{}/feed.{}?token={token}", encode(bucket.name()), format.extension()),
+        )
+    };
+
+    Ok(Json(FeedToken {
+        atom_url: url(FeedFormat::Atom),
+        rss_url: url(FeedFormat::Rss),
+        token,
+    }))
+}
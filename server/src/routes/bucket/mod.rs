@@ -0,0 +1,9 @@
+mod create;
+mod update;
+
+macros_utils::routes! {
+    load create,
+    load update,
+
+    on "/bucket"
+}
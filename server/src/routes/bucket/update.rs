@@ -0,0 +1,44 @@
+use actix_web::web::{Json, Path};
+use actix_web::{HttpRequest, HttpResponse, patch};
+use database::{BucketModel, BucketUpdate, UserModel};
+
+use crate::AppError;
+use crate::utils::concurrency::expected_version;
+use crate::utils::license::canonical_license;
+use crate::utils::purge::{bucket_key, purge};
+
+macros_utils::routes! {
+    route route_edit,
+}
+
+/// Renames an owned bucket or sets the license its new files get, when
+/// `If-Match` is stale the current state is returned with a 412 so the client can merge.
+#[patch("/{id}")]
+pub async fn route_edit(
+    req: HttpRequest,
+    user: UserModel,
+    id: Path<i64>,
+    update: Json<BucketUpdate>,
+) -> Result<HttpResponse, AppError> {
+    let expected = expected_version(&req)?;
+    let bucket = BucketModel::get_owned(*id, user.id()).await?;
+
+    let mut update = update.into_inner();
+
+    if let Some(license) = update.default_license().filter(|license| !license.is_empty()) {
+        let license = canonical_license(license)?;
+        update = update.with_default_license(license.to_owned());
+    }
+
+    Ok(match bucket.edit(update, expected).await? {
+        Some(edited) => {
+            if edited.visibility() != bucket.visibility() {
+                purge(vec![bucket_key(bucket.id())]);
+            }
+
+            HttpResponse::Ok().json(edited.into_result())
+        },
+        None => HttpResponse::PreconditionFailed()
+            .json(BucketModel::get_owned(*id, user.id()).await?.into_result()),
+    })
+}
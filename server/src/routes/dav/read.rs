@@ -0,0 +1,242 @@
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ALLOW, HeaderName};
+use actix_web::web::{Data, Path};
+use actix_web::{HttpRequest, HttpResponse, route};
+use database::{BucketModel, FileModel};
+
+use crate::AppError;
+use crate::extractors::basic::BasicAuthUser;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::dav::{DavEntry, multistatus};
+use crate::utils::headers::single_header_value;
+use crate::utils::serving::serve_file;
+
+macros_utils::routes! {
+    route route_options,
+    route route_propfind,
+    route route_get,
+    route route_read_only,
+}
+
+const DAV: HeaderName = HeaderName::from_static("dav");
+const DEPTH: HeaderName = HeaderName::from_static("depth");
+
+const ALLOWED: &str = "OPTIONS, PROPFIND, GET, HEAD";
+
+/// Advertises class 1 compliance, answered without
+/// authentication since clients probe it before sending credentials.
+#[route("/{path:.*}", method = "OPTIONS")]
+pub async fn route_options() -> HttpResponse {
+    HttpResponse::Ok().insert_header((DAV, "1")).insert_header((ALLOW, ALLOWED)).finish()
+}
+
+/// Lists the buckets of the user at the root and the files
+/// of a bucket below it, `Depth: infinity` is treated as `1`.
+#[route("/{path:.*}", method = "PROPFIND")]
+pub async fn route_propfind(
+    req: HttpRequest,
+    user: BasicAuthUser,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let user = user.0;
+    let children = single_header_value(&req, DEPTH)?.is_none_or(|depth| depth.trim() != "0");
+
+    let entries = match split(&path) {
+        (None, _) => {
+            let mut entries = vec![DavEntry {
+                segments: Vec::new(),
+                collection: true,
+                length: 0,
+                last_modified: user.created_at(),
+                etag: None,
+            }];
+
+            if children {
+                entries.extend(BucketModel::list_owned(user.id()).await?.iter().map(bucket_entry));
+            }
+
+            entries
+        },
+        (Some(bucket), None) => {
+            let bucket = BucketModel::get_named_owned(bucket, user.id()).await?;
+            let mut entries = vec![bucket_entry(&bucket)];
+
+            if children {
+                let files = FileModel::list_in_bucket(bucket.id()).await?;
+                entries.extend(files.iter().map(|file| file_entry(&bucket, file)));
+            }
+
+            entries
+        },
+        (Some(bucket), Some(name)) => {
+            let bucket = BucketModel::get_named_owned(bucket, user.id()).await?;
+            let file = FileModel::get_at(bucket.id(), name).await?;
+
+            vec![file_entry(&bucket, &file)]
+        },
+    };
+
+    Ok(HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(multistatus(&entries)))
+}
+
+/// Serves the content of a file, `HEAD` bodies are stripped by actix.
+#[route("/{path:.*}", method = "GET", method = "HEAD")]
+pub async fn route_get(
+    req: HttpRequest,
+    user: BasicAuthUser,
+    storage: Data<AppStorage>,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let (Some(bucket), Some(name)) = split(&path) else {
+        return Ok(read_only());
+    };
+
+    let bucket = BucketModel::get_named_owned(bucket, user.0.id()).await?;
+    let file = FileModel::get_at(bucket.id(), name).await?;
+
+    serve_file(&req, &storage, &file.into_served().await?).await
+}
+
+/// The facade is read only, every mutating method is refused.
+#[route(
+    "/{path:.*}",
+    method = "PUT",
+    method = "DELETE",
+    method = "MKCOL",
+    method = "COPY",
+    method = "MOVE",
+    method = "PROPPATCH",
+    method = "LOCK",
+    method = "UNLOCK"
+)]
+pub async fn route_read_only() -> HttpResponse {
+    read_only()
+}
+
+fn read_only() -> HttpResponse {
+    HttpResponse::MethodNotAllowed().insert_header((ALLOW, ALLOWED)).finish()
+}
+
+/// Splits a path into its bucket and file name.
+fn split(path: &str) -> (Option<&str>, Option<&str>) {
+    let path = path.trim_matches('/');
+
+    match path.split_once('/') {
+        _ if path.is_empty() => (None, None),
+        Some((bucket, name)) => (Some(bucket), Some(name)),
+        None => (Some(path), None),
+    }
+}
+
+fn bucket_entry(bucket: &BucketModel) -> DavEntry {
+    DavEntry {
+        segments: vec![bucket.name().to_owned()],
+        collection: true,
+        length: 0,
+        last_modified: bucket.created_at(),
+        etag: None,
+    }
+}
+
+fn file_entry(bucket: &BucketModel, file: &FileModel) -> DavEntry {
+    DavEntry {
+        segments: vec![bucket.name().to_owned(), file.path().to_owned()],
+        collection: false,
+        length: file.size() as u64,
+        last_modified: file.last_modified_at(),
+        etag: file.hash().map(str::to_owned),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::App;
+    use actix_web::http::Method;
+    use actix_web::http::header::{AUTHORIZATION, RANGE, WWW_AUTHENTICATE};
+    use actix_web::test::{TestRequest, call_service, init_service, read_body};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    use super::*;
+    use crate::routes::routes;
+    use crate::testing::{
+        create_api_key, create_bucket, create_user, run, unique_name, upload_file,
+    };
+    use crate::utils::app_storage::open_storage;
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn seeded_trees_are_listed_and_downloaded_with_an_api_key() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let user = create_user(&unique_name("dav")).await;
+            let bucket = create_bucket(&user).await;
+            upload_file(&storage, &bucket, "holiday 2024.txt", b"hello dav").await;
+            upload_file(&storage, &bucket, "notes.md", b"# notes").await;
+
+            let app = init_service(App::new().app_data(Data::new(storage)).configure(routes)).await;
+            let auth = |password: &str| {
+                format!("Basic {}", STANDARD.encode(format!("{}:{password}", user.username())))
+            };
+            let key = auth(&create_api_key(&user).await);
+            let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+
+            let request = |method: Method, uri: &str| {
+                TestRequest::default()
+                    .method(method)
+                    .uri(uri)
+                    .insert_header((AUTHORIZATION, key.as_str()))
+            };
+
+            let response = call_service(
+                &app,
+                request(propfind.clone(), "/dav/").insert_header((DEPTH, "1")).to_request(),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+            let root = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+            assert!(root.contains("<D:href>/dav/</D:href>"));
+            assert!(root.contains(&format!("<D:href>/dav/{}/</D:href>", bucket.name())));
+
+            let listing = format!("/dav/{}/", bucket.name());
+            let response =
+                call_service(&app, request(propfind.clone(), &listing).to_request()).await;
+            let listing = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+            assert_eq!(listing.matches("<D:response>").count(), 3);
+            assert!(
+                listing.contains(&format!(
+                    "<D:href>/dav/{}/holiday%202024.txt</D:href>",
+                    bucket.name()
+                ))
+            );
+            assert!(listing.contains("<D:getcontentlength>9</D:getcontentlength>"));
+
+            let file = format!("/dav/{}/holiday%202024.txt", bucket.name());
+            let response = call_service(
+                &app,
+                request(Method::GET, &file).insert_header((RANGE, "bytes=0-4")).to_request(),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+            assert_eq!(read_body(response).await, "hello");
+
+            let response = call_service(&app, request(Method::PUT, &file).to_request()).await;
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+            // The account password is never accepted, nor are missing credentials.
+            for authorization in [Some(auth("")), None] {
+                let mut request = TestRequest::default().method(propfind.clone()).uri("/dav/");
+
+                if let Some(authorization) = authorization {
+                    request = request.insert_header((AUTHORIZATION, authorization));
+                }
+
+                let response = call_service(&app, request.to_request()).await;
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+                assert!(response.headers().contains_key(WWW_AUTHENTICATE));
+            }
+        })
+    }
+}
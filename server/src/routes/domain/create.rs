@@ -0,0 +1,46 @@
+use actix_web::web::Json;
+use actix_web::{Responder, post};
+use database::{TenantDomainModel, UserModel};
+
+use crate::AppError;
+
+macros_utils::routes! {
+    route route_claim,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DomainClaim {
+    hostname: String,
+    /// Overrides `https://<hostname>` as the base of generated links.
+    public_base_url: Option<String>,
+}
+
+/// Claims a custom domain, it's served once a TXT record at
+/// `_cdn-challenge.<hostname>` holds the returned verification token.
+///
+/// Other users may claim it too until one of them is verified,
+/// hostnames verified for someone else are refused.
+#[post("")]
+pub async fn route_claim(
+    user: UserModel,
+    claim: Json<DomainClaim>,
+) -> Result<impl Responder, AppError> {
+    let hostname = claim.hostname.trim().trim_end_matches('.').to_ascii_lowercase();
+
+    let valid = hostname.len() <= 253
+        && hostname.contains('.')
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label.chars().all(|char| char.is_ascii_alphanumeric() || char == '-')
+        });
+
+    if !valid {
+        return Err(AppError::InvalidHostname);
+    }
+
+    let domain =
+        TenantDomainModel::create(user.id(), &hostname, claim.public_base_url.as_deref()).await?;
+
+    Ok(Json(domain.into_result()))
+}
@@ -0,0 +1,17 @@
+use actix_web::web::Json;
+use actix_web::{Responder, get};
+use database::{TenantDomainModel, UserModel};
+
+use crate::AppError;
+
+macros_utils::routes! {
+    route route_list,
+}
+
+/// Lists the custom domains of the caller with their verification state.
+#[get("")]
+pub async fn route_list(user: UserModel) -> Result<impl Responder, AppError> {
+    let domains = TenantDomainModel::list_owned(user.id()).await?;
+
+    Ok(Json(domains.iter().map(TenantDomainModel::into_result).collect::<Vec<_>>()))
+}
@@ -0,0 +1,120 @@
+use actix_web::http::header::CONTENT_SECURITY_POLICY;
+use actix_web::web::{Json, Path, Query};
+use actix_web::{HttpRequest, HttpResponse, Responder, get};
+use database::FileLicense;
+
+use crate::AppError;
+use crate::middleware::security_headers::AllowFraming;
+use crate::processing::poster::POSTER_READY;
+use crate::utils::dav::escape;
+use crate::utils::license::{license_url, video_json_ld};
+use crate::utils::links::content_base_url;
+use crate::utils::not_found::file_by_slug;
+use crate::utils::oembed::{describe, file_from_url};
+use crate::utils::serving::check_tenant;
+
+macros_utils::routes! {
+    route route_oembed,
+    route route_embed,
+}
+
+#[derive(serde::Deserialize)]
+pub struct OEmbedQuery {
+    url: String,
+    format: Option<String>,
+    maxwidth: Option<u32>,
+    maxheight: Option<u32>,
+}
+
+/// The oEmbed provider endpoint, so sites supporting it can
+/// embed files from their link alone, only JSON is answered.
+#[get("/oembed")]
+pub async fn route_oembed(
+    req: HttpRequest,
+    query: Query<OEmbedQuery>,
+) -> Result<impl Responder, AppError> {
+    if query.format.as_deref().is_some_and(|format| format != "json") {
+        return Err(AppError::UnsupportedFormat);
+    }
+
+    let file = file_from_url(&req, &query.url).await?;
+
+    Ok(Json(describe(&req, &file, query.maxwidth, query.maxheight)))
+}
+
+/// A bare player for videos embedded through oEmbed, it may
+/// be framed anywhere but can't load anything but the video.
+///
+/// Licensed videos get a credit line and their terms as schema.org markup.
+#[get("/embed/{slug}")]
+pub async fn route_embed(req: HttpRequest, slug: Path<String>) -> Result<HttpResponse, AppError> {
+    let served = file_by_slug(&slug).await?;
+    check_tenant(&req, &served)?;
+    let file = served.file;
+
+    let base_url = content_base_url(&req);
+    let content_url = format!("{}/f/{}", base_url.trim_end_matches('/'), file.slug());
+
+    let poster = if file.poster_state() == Some(POSTER_READY) {
+        format!(" poster=\"{}\"", escape(&format!("{content_url}/poster.jpg")))
+    } else {
+        String::new()
+    };
+
+    let name = file.path().rsplit('/').next().unwrap_or_default();
+    let terms = file.license_terms();
+
+    let json_ld = video_json_ld(name, &content_url, &terms)
+        .map(|json_ld| format!("<script type=\"application/ld+json\">{json_ld}</script>"))
+        .unwrap_or_default();
+
+    let html = format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>{}</title>{json_ld}\
+         <style>html,body{{margin:0;height:100%;background:#000}}\
+         video{{width:100%;height:100%}}\
+         small{{position:absolute;top:0;left:0;padding:2px 6px;background:#0008;\
+         color:#fff;font:12px sans-serif}}small a{{color:inherit}}</style></head>\
+         <body><video src=\"{}\"{poster} controls playsinline></video>{}</body></html>",
+        escape(name),
+        escape(&content_url),
+        credit_line(&terms),
+    );
+
+    let origin = escape(base_url.trim_end_matches('/'));
+    let policy = format!(
+        "default-src 'none'; media-src {origin}; img-src {origin}; style-src 'unsafe-inline'; \
+         frame-ancestors *"
+    );
+
+    let mut response = HttpResponse::Ok();
+    response.extensions_mut().insert(AllowFraming);
+
+    Ok(response
+        .content_type("text/html; charset=utf-8")
+        .insert_header((CONTENT_SECURITY_POLICY, policy))
+        .body(html))
+}
+
+/// The visible credit of a licensed video, empty when it has no terms.
+fn credit_line(terms: &FileLicense) -> String {
+    let mut parts = Vec::new();
+
+    if let Some(attribution) = &terms.attribution {
+        parts.push(escape(attribution));
+    }
+
+    if let Some(license) = &terms.license {
+        parts.push(match license_url(license) {
+            Some(url) => {
+                format!("<a href=\"{}\" target=\"_blank\">{}</a>", escape(&url), escape(license))
+            },
+            None => escape(license),
+        });
+    }
+
+    if let Some(source_url) = &terms.source_url {
+        parts.push(format!("<a href=\"{}\" target=\"_blank\">Source</a>", escape(source_url)));
+    }
+
+    if parts.is_empty() { String::new() } else { format!("<small>{}</small>", parts.join(" · ")) }
+}
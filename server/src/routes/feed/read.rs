@@ -0,0 +1,250 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use actix_web::http::header::{
+    CACHE_CONTROL, ETag, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::web::{Path, Query};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, get};
+use database::{BucketModel, DatabaseError, FileModel, Visibility};
+use sha2::{Digest, Sha256};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::extractors::auth::OptionalUser;
+use crate::middleware::tenant::Tenant;
+use crate::utils::dav::encode;
+use crate::utils::feed::{Feed, FeedEntry, FeedFormat, verify_feed_token};
+use crate::utils::hashing::hex;
+use crate::utils::links::{content_base_url, immutable_path, public_url};
+
+macros_utils::routes! {
+    route route_feed,
+}
+
+/// Feed readers poll, they're told to wait at least this long.
+const FEED_MAX_AGE: u32 = 300;
+
+#[derive(serde::Deserialize)]
+pub struct FeedQuery {
+    token: Option<String>,
+}
+
+/// The newest files of a bucket as an Atom or RSS feed, public buckets
+/// are readable by anyone, unlisted ones with the token minted by their
+/// owner and private ones by no one, they're reported as missing to
+/// anonymous callers.
+#[get("/{name}/feed.{format}")]
+pub async fn route_feed(
+    req: HttpRequest,
+    OptionalUser(user): OptionalUser,
+    path: Path<(String, String)>,
+    query: Query<FeedQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (name, extension) = path.into_inner();
+    let format =
+        FeedFormat::from_extension(&extension).ok_or(DatabaseError::ModelNotFound("feed"))?;
+    let bucket = readable_bucket(&req, &name, query.token.as_deref(), user.is_some()).await?;
+
+    let mut self_path = format!("/c/{}/feed.{}", encode(bucket.name()), format.extension());
+
+    if bucket.visibility() == Visibility::Unlisted
+        && let Some(token) = &query.token
+    {
+        self_path += &format!("?token={}", encode(token));
+    }
+
+    let files = FileModel::list_newest_in_bucket(bucket.id(), Config::get().feed_size).await?;
+    let feed = Feed {
+        title: bucket.name().to_owned(),
+        self_url: public_url(&req, &self_path),
+        updated: files.iter().map(FileModel::last_modified_at).max().unwrap_or(bucket.created_at()),
+        entries: files.iter().map(|file| feed_entry(&req, file)).collect(),
+    };
+
+    // Derived from the document, so any change to an entry changes it.
+    let body = feed.render(format);
+    let etag = EntityTag::new_strong(hex(&Sha256::digest(&body)[..16]));
+    // HTTP dates have no fractions, so dates sent back compare equal.
+    let updated = feed.updated.and_utc().timestamp().max(0) as u64;
+    let last_modified = HttpDate::from(UNIX_EPOCH + Duration::from_secs(updated));
+
+    let cache_control = match bucket.visibility() {
+        Visibility::Public => format!("public, max-age={FEED_MAX_AGE}"),
+        _ => format!("private, max-age={FEED_MAX_AGE}"),
+    };
+
+    let fresh = not_modified(&req, &etag, last_modified);
+    let mut response = if fresh { HttpResponse::NotModified() } else { HttpResponse::Ok() };
+
+    response
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified))
+        .insert_header((CACHE_CONTROL, cache_control));
+
+    if fresh {
+        return Ok(response.finish());
+    }
+
+    Ok(response.content_type(format.content_type()).body(body))
+}
+
+/// The bucket named `name` when its feed may be read with `token`,
+/// on a custom domain only the buckets of its tenant exist.
+async fn readable_bucket(
+    req: &HttpRequest,
+    name: &str,
+    token: Option<&str>,
+    authenticated: bool,
+) -> Result<BucketModel, AppError> {
+    let bucket = BucketModel::get_by_name(name).await?;
+    let tenant = req.extensions().get::<Tenant>().map(|tenant| tenant.owner_id);
+
+    if tenant.is_some_and(|owner_id| bucket.owner_id() != Some(owner_id)) {
+        return Err(DatabaseError::ModelNotFound("bucket").into());
+    }
+
+    let readable = match bucket.visibility() {
+        Visibility::Public => true,
+        Visibility::Unlisted => token.is_some_and(|token| verify_feed_token(bucket.id(), token)),
+        Visibility::Private => false,
+    };
+
+    if !readable {
+        return Err(AppError::Private { resource: "bucket", authenticated });
+    }
+
+    Ok(bucket)
+}
+
+/// Whether the client already has this version of the feed, `If-None-Match`
+/// takes precedence over `If-Modified-Since` as HTTP mandates.
+fn not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: HttpDate) -> bool {
+    if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+
+    req.get_header::<IfModifiedSince>().is_some_and(|IfModifiedSince(since)| since >= last_modified)
+}
+
+/// Entries link to the file on the primary origin and enclose its content,
+/// under its immutable URL when there's one since it's cached for good.
+fn feed_entry(req: &HttpRequest, file: &FileModel) -> FeedEntry {
+    let content_base_url = content_base_url(req);
+    let content_path = immutable_path(file).unwrap_or_else(|| format!("/f/{}", file.slug()));
+
+    let content_type = file.content_type().map(str::to_owned).unwrap_or_else(|| {
+        mime_guess::from_path(file.path()).first_or_octet_stream().essence_str().to_owned()
+    });
+
+    FeedEntry {
+        title: file.path().rsplit('/').next().unwrap_or_default().to_owned(),
+        link: public_url(req, &format!("/f/{}", file.slug())),
+        enclosure_url: format!("{}{content_path}", content_base_url.trim_end_matches('/')),
+        content_type,
+        length: file.size() as u64,
+        published: file.created_at(),
+        updated: file.last_modified_at(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::App;
+    use actix_web::http::StatusCode;
+    use actix_web::http::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+    use actix_web::test::{TestRequest, call_service, init_service, read_body};
+
+    use super::*;
+    use crate::routes::routes;
+    use crate::testing::{
+        create_bucket, create_file, create_user, run, set_visibility, unique_name,
+    };
+    use crate::utils::feed::feed_token;
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn public_feeds_list_the_newest_files_and_answer_304s() {
+        run(async {
+            let owner = create_user(&unique_name("feed")).await;
+            let bucket = set_visibility(create_bucket(&owner).await, "public").await;
+            create_file(&bucket, "old.png").await;
+            create_file(&bucket, "new & shiny.mp4").await;
+
+            let app = init_service(App::new().configure(routes)).await;
+            let uri = format!("/c/{}/feed.atom", bucket.name());
+
+            let response = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "public, max-age=300");
+            let etag = response.headers().get(ETAG).unwrap().clone();
+            let last_modified = response.headers().get(LAST_MODIFIED).unwrap().clone();
+
+            let atom = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+            let (first, second) = (atom.find("new &amp; shiny.mp4"), atom.find("old.png"));
+            assert!(first.unwrap() < second.unwrap(), "newest first");
+            assert!(atom.contains("type=\"video/mp4\" length=\"0\""));
+
+            for (header, value) in
+                [(IF_NONE_MATCH, etag.clone()), (IF_MODIFIED_SINCE, last_modified)]
+            {
+                let request = TestRequest::get().uri(&uri).insert_header((header, value));
+                let response = call_service(&app, request.to_request()).await;
+
+                assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+                assert_eq!(response.headers().get(ETAG), Some(&etag));
+                assert!(read_body(response).await.is_empty());
+            }
+
+            let request = TestRequest::get().uri(&uri).insert_header((IF_NONE_MATCH, "\"stale\""));
+            assert_eq!(call_service(&app, request.to_request()).await.status(), StatusCode::OK);
+
+            let rss = format!("/c/{}/feed.rss", bucket.name());
+            let response = call_service(&app, TestRequest::get().uri(&rss).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_ne!(response.headers().get(ETAG), Some(&etag));
+
+            let json = format!("/c/{}/feed.json", bucket.name());
+            let response = call_service(&app, TestRequest::get().uri(&json).to_request()).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn unlisted_feeds_need_their_token_and_private_ones_are_missing() {
+        run(async {
+            let owner = create_user(&unique_name("feed")).await;
+            let bucket = create_bucket(&owner).await;
+            create_file(&bucket, "clip.webm").await;
+
+            let app = init_service(App::new().configure(routes)).await;
+            let status = async |uri: String| {
+                call_service(&app, TestRequest::get().uri(&uri).to_request()).await.status()
+            };
+            let token = feed_token(bucket.id());
+            let feed = format!("/c/{}/feed.atom", bucket.name());
+
+            // Buckets are private until their owner says otherwise.
+            assert_eq!(status(feed.clone()).await, StatusCode::NOT_FOUND);
+            assert_eq!(status(format!("{feed}?token={token}")).await, StatusCode::NOT_FOUND);
+
+            let bucket = set_visibility(bucket, "unlisted").await;
+            assert_eq!(status(feed.clone()).await, StatusCode::NOT_FOUND);
+            assert_eq!(
+                status(format!("{feed}?token={}", feed_token(bucket.id() + 1))).await,
+                StatusCode::NOT_FOUND
+            );
+
+            let request = TestRequest::get().uri(&format!("{feed}?token={token}"));
+            let response = call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "private, max-age=300");
+            let atom = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+            assert!(atom.contains(&format!("feed.atom?token={token}\"/>")));
+            assert!(atom.contains("clip.webm"));
+        })
+    }
+}
@@ -0,0 +1,976 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use actix_multipart::{Field, Multipart};
+use actix_web::http::header::{
+    ACCEPT, CONTENT_SECURITY_POLICY, ContentDisposition, DispositionParam, DispositionType,
+    LOCATION,
+};
+use actix_web::web::{Bytes, Data, Json, Payload, Query};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError, post};
+use chrono::{TimeDelta, Utc};
+use database::{
+    BucketModel, DatabaseError, FileAclModel, FileLicense, FileModel, FilePermission, FileResult,
+    FileTagModel, UserModel,
+};
+use futures::TryStreamExt;
+use log::info;
+use serde::Serialize;
+use serde_json::json;
+use storage::Storage;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::error_code::ErrorCode;
+use crate::extractors::auth::OptionalUser;
+use crate::extractors::file_id::FileId;
+use crate::middleware::forwarded::ClientInfo;
+use crate::processing::poster::extract_poster;
+use crate::processing::tracker::ProcessingTracker;
+use crate::tasks::jobs::spawn_tracked;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::append::append;
+use crate::utils::archive::zip_stream;
+use crate::utils::dav::{encode, escape};
+use crate::utils::delta::{block_size, patch};
+use crate::utils::encoding::is_text_like;
+use crate::utils::file_cache::FileCache;
+use crate::utils::file_id;
+use crate::utils::headers::joined_header_values;
+use crate::utils::license::check_license;
+use crate::utils::links::{file_result, file_result_at, public_url};
+use crate::utils::normalize::natural_sort_key;
+use crate::utils::not_found::NotFoundCache;
+use crate::utils::post_policy::{PolicyFields, PostPolicy, check_redirect};
+use crate::utils::serving::file_blob_key;
+use crate::utils::signing::SignedUrl;
+use crate::utils::tags::{MAX_TAGS_PER_FILE, normalize_tags};
+use crate::utils::upload::{check_upload, read_body, read_upload, store_upload};
+use crate::utils::upload_limit::{UploadLimiter, upload_key};
+use crate::utils::upload_slots::{UploadSlots, preferred_wait};
+use crate::utils::webhooks::{WebhookEvent, dispatch};
+
+macros_utils::routes! {
+    route route_upload,
+    route route_upload_policy,
+    route route_upload_form,
+    route route_paste,
+    route route_download_zip,
+    route route_preflight,
+    route route_sign,
+    route route_add_tags,
+    route route_bulk_tag,
+    route route_append,
+    route route_apply_delta,
+    route route_grant_access,
+    route route_extract_poster,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UploadQuery {
+    /// Defaults to a bucket named after the user.
+    bucket: Option<i64>,
+    name: String,
+    /// Overrides `NORMALIZE_TEXT_ENCODING` for this upload.
+    normalize: Option<bool>,
+    /// Creates a file that can later be appended to, stored as sent.
+    #[serde(default)]
+    appendable: bool,
+    /// Overrides the default license of the bucket, as an SPDX identifier.
+    license: Option<String>,
+    attribution: Option<String>,
+    source_url: Option<String>,
+}
+
+/// Stores the request body as a new file.
+///
+/// Without authentication the upload is only accepted when
+/// `ALLOW_ANONYMOUS_UPLOAD` is set, it then goes to the anonymous bucket
+/// and expires after `ANONYMOUS_UPLOAD_TTL`.
+///
+/// Uploads are scanned for malware first when `AV_SCAN` is set.
+///
+/// Text uploads get their encoding detected, when normalizing the
+/// stored copy is transcoded to UTF-8 and the original kept aside.
+///
+/// A name already taken in the bucket is handled per `UPLOAD_COLLISION`,
+/// anonymous uploads never overwrite.
+///
+/// Each uploader gets `UPLOAD_RATE_LIMIT` uploads a minute, answers
+/// carry the `RateLimit-*` headers so clients can pace themselves.
+///
+/// At most `UPLOAD_CONCURRENCY` uploads of an uploader run at once, the
+/// excess is rejected unless `Prefer: wait=<seconds>` asks to wait for a slot.
+///
+/// Files take the default license of their bucket unless `license`,
+/// `attribution` or `source_url` are given.
+#[post("/upload")]
+pub async fn route_upload(
+    req: HttpRequest,
+    OptionalUser(user): OptionalUser,
+    storage: Data<AppStorage>,
+    query: Query<UploadQuery>,
+    mut payload: Payload,
+) -> Result<impl Responder, AppError> {
+    let config = Config::get();
+
+    let bucket = upload_bucket(user.as_ref(), query.bucket).await?;
+    let expires_at = (user.is_none() && config.anonymous_upload_ttl > 0)
+        .then(|| Utc::now().naive_utc() + TimeDelta::seconds(config.anonymous_upload_ttl));
+
+    let license = check_license(FileLicense {
+        license: query.license.clone(),
+        attribution: query.attribution.clone(),
+        source_url: query.source_url.clone(),
+    })?;
+
+    let limit_key = upload_key(user.as_ref().map(UserModel::id), ClientInfo::get(&req).ip);
+    let _slot = UploadSlots::get().acquire(&limit_key, preferred_wait(&req)).await?;
+    let limit = UploadLimiter::get().acquire(&limit_key)?;
+
+    let mut response = HttpResponse::Ok();
+
+    if let Some(limit) = limit {
+        limit.insert_headers(&mut response);
+    }
+
+    let body = read_body(&mut payload, config.max_upload_size).await?;
+    check_upload(&body).await?;
+
+    // Only the owner can append, so anonymous files could never grow.
+    if query.appendable {
+        if user.is_none() {
+            return Err(AppError::AuthorizationError);
+        }
+
+        let algorithm = config.hash_algorithm;
+        let file = FileModel::create_appendable(
+            bucket.id(),
+            &query.name,
+            &natural_sort_key(&query.name),
+            algorithm.name(),
+            expires_at,
+        )
+        .await?;
+        let file = append(&storage, file.id(), &body).await?;
+        let file = apply_license(file, license).await?;
+        NotFoundCache::get().forget(file.slug());
+        dispatch(WebhookEvent::FileUploaded, &file, file_result(&req, &file));
+
+        return Ok(response.json(file_result(&req, &file)));
+    }
+
+    let normalize = query.normalize.unwrap_or(config.normalize_text_encoding);
+    let owned = user.is_some();
+    let name = query.into_inner().name;
+    let file =
+        store_upload(&storage, bucket.id(), name, expires_at, normalize, owned, body).await?;
+    let file = apply_license(file, license).await?;
+    dispatch(WebhookEvent::FileUploaded, &file, file_result(&req, &file));
+
+    Ok(response.json(file_result(&req, &file)))
+}
+
+/// The bucket an upload goes to, `bucket` of the user or the one named after
+/// them, anonymous uploads go to the anonymous bucket if they're allowed at all.
+async fn upload_bucket(
+    user: Option<&UserModel>,
+    bucket: Option<i64>,
+) -> Result<BucketModel, AppError> {
+    let bucket = match user {
+        Some(user) => match bucket {
+            Some(bucket) => BucketModel::get_owned(bucket, user.id()).await?,
+            None => BucketModel::get_or_create(user.username(), user.id()).await?,
+        },
+        None if Config::get().allow_anonymous_upload => BucketModel::anonymous().await?,
+        None => return Err(AppError::AuthorizationError),
+    };
+
+    Ok(bucket)
+}
+
+/// Sets the license an upload was sent with, those sent without
+/// keep the default of their bucket.
+async fn apply_license(file: FileModel, license: FileLicense) -> Result<FileModel, AppError> {
+    if license.is_empty() {
+        return Ok(file);
+    }
+
+    let file = file.set_license(license).await?;
+    FileCache::get().forget(file.id());
+
+    Ok(file)
+}
+
+/// Longest text field accepted in an upload form, a policy is the longest.
+const MAX_FORM_FIELD_LENGTH: usize = 8 * 1024;
+
+#[derive(serde::Deserialize)]
+pub struct PolicyRequest {
+    /// Defaults to a bucket named after the user.
+    bucket: Option<i64>,
+    /// Seconds the policy is valid for, at most `POLICY_MAX_TTL`.
+    expires_in: Option<i64>,
+    #[serde(default)]
+    min_size: u64,
+    /// Defaults to, and is capped by, `MAX_UPLOAD_SIZE`.
+    max_size: Option<u64>,
+    #[serde(default)]
+    key_prefix: String,
+    content_type_prefix: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PolicyResult {
+    url: String,
+    expires_at: i64,
+    fields: PolicyFields,
+}
+
+/// Issues a policy for uploading with a plain HTML form, its fields
+/// go in hidden inputs of a form posting to `url`.
+#[post("/upload/policy")]
+pub async fn route_upload_policy(
+    req: HttpRequest,
+    user: UserModel,
+    request: Json<PolicyRequest>,
+) -> Result<impl Responder, AppError> {
+    let config = Config::get();
+    let request = request.into_inner();
+
+    let bucket = match request.bucket {
+        Some(bucket) => BucketModel::get_owned(bucket, user.id()).await?,
+        None => BucketModel::get_or_create(user.username(), user.id()).await?,
+    };
+
+    let ttl = request.expires_in.unwrap_or(config.policy_max_ttl).clamp(1, config.policy_max_ttl);
+    let limit = config.max_upload_size as u64;
+
+    let policy = PostPolicy {
+        user_id: user.id(),
+        bucket_id: bucket.id(),
+        expires_at: Utc::now().timestamp() + ttl,
+        min_size: request.min_size,
+        max_size: request.max_size.unwrap_or(limit).min(limit),
+        key_prefix: request.key_prefix,
+        content_type_prefix: request.content_type_prefix,
+    };
+
+    Ok(Json(PolicyResult {
+        url: public_url(&req, "/file/upload/form"),
+        expires_at: policy.expires_at,
+        fields: policy.sign(&config.signing_secret),
+    }))
+}
+
+/// Stores a file posted by an HTML form carrying the fields issued by
+/// `POST /file/upload/policy`, the file part must come last.
+///
+/// `key` names the file, `${filename}` in it is replaced by the name of
+/// the file sent. Succeeds with a redirect to `success_action_redirect`
+/// when given, browsers are answered failures as an HTML page.
+#[post("/upload/form")]
+pub async fn route_upload_form(
+    req: HttpRequest,
+    storage: Data<AppStorage>,
+    form: Multipart,
+) -> Result<HttpResponse, AppError> {
+    match upload_form(&req, &storage, form).await {
+        Err(error) if accepts_html(&req) => Ok(form_error_page(&error)),
+        result => result,
+    }
+}
+
+/// Reads a whole part, `None` once it grows past `limit` bytes.
+async fn read_field(field: &mut Field, limit: usize) -> Result<Option<Vec<u8>>, AppError> {
+    let mut data = Vec::new();
+
+    while let Some(chunk) = field.try_next().await? {
+        if data.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(Some(data))
+}
+
+async fn upload_form(
+    req: &HttpRequest,
+    storage: &AppStorage,
+    mut form: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let config = Config::get();
+    let mut fields = HashMap::new();
+
+    while let Some(mut field) = form.try_next().await? {
+        let name = field.name().unwrap_or_default().to_owned();
+
+        if name != "file" {
+            let value = read_field(&mut field, MAX_FORM_FIELD_LENGTH)
+                .await?
+                .and_then(|value| String::from_utf8(value).ok())
+                .ok_or_else(|| {
+                    AppError::MalformedMultipart(format!(
+                        "the {name} field is too long or not text"
+                    ))
+                })?;
+
+            fields.insert(name, value);
+            continue;
+        }
+
+        let field_value = |name: &str| fields.get(name).map(String::as_str).unwrap_or_default();
+        let policy = PostPolicy::verify(
+            field_value("policy"),
+            field_value("signature"),
+            &config.signing_secret,
+        )?;
+        let redirect = fields.get("success_action_redirect");
+
+        if let Some(redirect) = redirect {
+            check_redirect(redirect)?;
+        }
+
+        let filename = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .unwrap_or_default();
+        let key = field_value("key").replace("${filename}", filename);
+
+        policy.check_name(&key)?;
+        policy.check_content_type(field.content_type().map(|mime| mime.essence_str()))?;
+
+        if !UserModel::is_active_by_id(policy.user_id).await? {
+            return Err(AppError::AccountDisabled);
+        }
+
+        let bucket = BucketModel::get_owned(policy.bucket_id, policy.user_id).await?;
+
+        let limit_key = upload_key(Some(policy.user_id), ClientInfo::get(req).ip);
+        let _slot = UploadSlots::get().acquire(&limit_key, preferred_wait(req)).await?;
+        let limit = UploadLimiter::get().acquire(&limit_key)?;
+
+        let max_size = policy.max_size.min(config.max_upload_size as u64) as usize;
+        let data = read_upload((&mut field).map_err(AppError::from), max_size).await?;
+
+        // Parts cut short for growing too large are never in range.
+        policy.check_size(data.as_ref().map_or(u64::MAX, |data| data.size() as u64))?;
+
+        let body = match data {
+            Some(data) => data.into_bytes().await?,
+            None => Bytes::new(),
+        };
+        check_upload(&body).await?;
+
+        let file = store_upload(
+            storage,
+            bucket.id(),
+            key.clone(),
+            None,
+            config.normalize_text_encoding,
+            true,
+            body,
+        )
+        .await?;
+        dispatch(WebhookEvent::FileUploaded, &file, file_result(req, &file));
+
+        let mut response = match redirect {
+            Some(redirect) => {
+                let separator = if redirect.contains('?') { '&' } else { '?' };
+                let location = format!(
+                    "{redirect}{separator}id={}&key={}",
+                    file_id::encode(file.id()),
+                    encode(&key)
+                );
+
+                let mut response = HttpResponse::SeeOther();
+                response.insert_header((LOCATION, location));
+                response
+            },
+            None => HttpResponse::Ok(),
+        };
+
+        if let Some(limit) = limit {
+            limit.insert_headers(&mut response);
+        }
+
+        return Ok(match redirect {
+            Some(_) => response.finish(),
+            None => response.json(file_result(req, &file)),
+        });
+    }
+
+    Err(AppError::MalformedMultipart("the form has no file part".into()))
+}
+
+fn accepts_html(req: &HttpRequest) -> bool {
+    joined_header_values(req, ACCEPT)
+        .ok()
+        .flatten()
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Failures answered to a browser posting the form directly.
+fn form_error_page(error: &AppError) -> HttpResponse {
+    HttpResponse::build(error.status_code())
+        .content_type("text/html; charset=utf-8")
+        .insert_header((CONTENT_SECURITY_POLICY, "default-src 'none'"))
+        .body(format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><title>Upload failed</title>\
+             </head><body><h1>Upload failed</h1><p>{}</p>\
+             <p>Go back to try again.</p></body></html>",
+            escape(&error.public_message())
+        ))
+}
+
+/// What a paste is declared as when it comes without a `Content-Type`.
+const OCTET_STREAM: &str = "application/octet-stream";
+
+#[derive(serde::Deserialize)]
+pub struct PasteQuery {
+    /// Defaults to a bucket named after the user.
+    bucket: Option<i64>,
+    /// Defaults to a random name with an extension fitting the content.
+    name: Option<String>,
+    /// Seconds the paste is kept for, `PASTE_TTL` by default, `0` keeps it.
+    expires_in: Option<i64>,
+}
+
+/// Stores a raw body such as a screenshot or copied text, typed by its
+/// `Content-Type` and capped at `PASTE_MAX_SIZE`, for clients that can't
+/// be bothered with multipart framing.
+///
+/// The declared type must match the sniffed one. Pastes expire after
+/// `PASTE_TTL` and are rate limited by `PASTE_RATE_LIMIT` apart from uploads.
+#[post("/paste")]
+pub async fn route_paste(
+    req: HttpRequest,
+    OptionalUser(user): OptionalUser,
+    storage: Data<AppStorage>,
+    query: Query<PasteQuery>,
+    mut payload: Payload,
+) -> Result<impl Responder, AppError> {
+    let config = Config::get();
+    let query = query.into_inner();
+
+    let bucket = upload_bucket(user.as_ref(), query.bucket).await?;
+
+    let mut ttl = query.expires_in.unwrap_or(config.paste_ttl).max(0);
+
+    // Anonymous pastes are held to the anonymous upload lifetime.
+    if user.is_none() && config.anonymous_upload_ttl > 0 {
+        ttl = if ttl == 0 {
+            config.anonymous_upload_ttl
+        } else {
+            ttl.min(config.anonymous_upload_ttl)
+        };
+    }
+
+    let expires_at = (ttl > 0).then(|| Utc::now().naive_utc() + TimeDelta::seconds(ttl));
+
+    let limit_key = upload_key(user.as_ref().map(UserModel::id), ClientInfo::get(&req).ip);
+    let _slot = UploadSlots::get().acquire(&limit_key, preferred_wait(&req)).await?;
+    let limit = UploadLimiter::get().acquire_at(
+        &format!("paste:{limit_key}"),
+        config.paste_rate_limit,
+        config.paste_rate_burst,
+    )?;
+
+    let body = read_body(&mut payload, config.paste_max_size).await?;
+    let declared = req.mime_type().ok().flatten();
+    let extension =
+        paste_extension(declared.as_ref().map_or(OCTET_STREAM, |mime| mime.essence_str()), &body)?;
+
+    check_upload(&body).await?;
+
+    let name =
+        query.name.unwrap_or_else(|| format!("paste-{:08x}.{extension}", rand::random::<u32>()));
+    let normalize = config.normalize_text_encoding;
+    let owned = user.is_some();
+    let file =
+        store_upload(&storage, bucket.id(), name, expires_at, normalize, owned, body).await?;
+    dispatch(WebhookEvent::FileUploaded, &file, file_result(&req, &file));
+
+    let mut response = HttpResponse::Created();
+
+    if let Some(limit) = limit {
+        limit.insert_headers(&mut response);
+    }
+
+    Ok(response.json(file_result(&req, &file)))
+}
+
+/// Refuses a paste whose content isn't what it was declared as,
+/// returning the extension a generated name gets.
+fn paste_extension(declared: &str, body: &[u8]) -> Result<&'static str, AppError> {
+    match infer::get(body) {
+        Some(kind) if declared == OCTET_STREAM || declared == kind.mime_type() => {
+            Ok(kind.extension())
+        },
+        Some(kind) => Err(AppError::ContentTypeMismatch(declared.to_owned(), kind.mime_type())),
+        None if declared.starts_with("text/") && is_text_like(body) => Ok("txt"),
+        None if declared == OCTET_STREAM => Ok("bin"),
+        None => Err(AppError::ContentTypeMismatch(declared.to_owned(), "unknown content")),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreflightRequest {
+    size: u64,
+    bucket: Option<i64>,
+    /// Digest of the content in `HASH_ALGORITHM`, as hex.
+    checksum: Option<String>,
+    #[serde(default)]
+    appendable: bool,
+}
+
+#[derive(Serialize)]
+struct PreflightViolation {
+    code: &'static str,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct PreflightResult {
+    ok: bool,
+    violations: Vec<PreflightViolation>,
+    /// An owned file with the same content, the upload can be skipped.
+    existing: Option<FileResult>,
+}
+
+/// Runs the checks an upload would fail without sending its body, so
+/// large uploads aren't rejected at the end, every violation is listed.
+///
+/// The answer is advisory, nothing is reserved and the upload checks again.
+#[post("/upload/preflight")]
+pub async fn route_preflight(
+    req: HttpRequest,
+    OptionalUser(user): OptionalUser,
+    request: Json<PreflightRequest>,
+) -> Result<impl Responder, AppError> {
+    let config = Config::get();
+    let mut violations = Vec::new();
+
+    match (&user, request.bucket) {
+        (Some(user), Some(bucket)) => match BucketModel::get_owned(bucket, user.id()).await {
+            Err(DatabaseError::ModelNotFound(name)) => {
+                violations.push(DatabaseError::ModelNotFound(name).into())
+            },
+            Err(error) => return Err(error.into()),
+            Ok(_) => {},
+        },
+        (Some(_), None) => {},
+        (None, _) if config.allow_anonymous_upload && !request.appendable => {},
+        (None, _) => violations.push(AppError::AuthorizationError),
+    }
+
+    if request.appendable && request.size > config.max_appendable_size {
+        violations.push(AppError::AppendTooLarge(config.max_appendable_size));
+    }
+
+    // Anonymous callers aren't told about files they don't own.
+    let existing = match (&user, &request.checksum) {
+        (Some(user), Some(checksum)) if !request.appendable => {
+            match FileModel::get_by_content_hash(&checksum.to_ascii_lowercase(), user.id()).await {
+                Ok(file) => Some(file_result(&req, &file)),
+                Err(DatabaseError::ModelNotFound(_)) => None,
+                Err(error) => return Err(error.into()),
+            }
+        },
+        _ => None,
+    };
+
+    Ok(Json(PreflightResult {
+        ok: violations.is_empty(),
+        violations: violations
+            .into_iter()
+            .map(|error: AppError| PreflightViolation {
+                code: ErrorCode::from(&error).as_str(),
+                message: error.to_string(),
+            })
+            .collect(),
+        existing,
+    }))
+}
+
+/// A file referenced by id or by slug.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum FileRef {
+    Id(FileId),
+    Slug(String),
+}
+
+#[derive(serde::Deserialize)]
+pub struct ZipRequest {
+    files: Vec<FileRef>,
+}
+
+/// Downloads owned files as a single zip archive built while it's
+/// sent, capped by `MAX_ZIP_FILES` and `MAX_ZIP_SIZE`.
+#[post("/download-zip")]
+pub async fn route_download_zip(
+    user: UserModel,
+    storage: Data<AppStorage>,
+    request: Json<ZipRequest>,
+) -> Result<HttpResponse, AppError> {
+    let config = Config::get();
+    let too_large = AppError::ArchiveTooLarge(config.max_zip_files, config.max_zip_size);
+
+    if request.files.len() > config.max_zip_files {
+        return Err(too_large);
+    }
+
+    let mut files = Vec::with_capacity(request.files.len());
+
+    for file in &request.files {
+        let id = match file {
+            FileRef::Id(id) => **id,
+            FileRef::Slug(slug) => FileModel::get_by_slug(slug).await?.id(),
+        };
+
+        files.push(FileModel::get_owned(id, user.id()).await?);
+    }
+
+    if files.iter().map(|file| file.size() as u64).sum::<u64>() > config.max_zip_size {
+        return Err(too_large);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("files.zip".into())],
+        })
+        .streaming(zip_stream(storage, files)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SignRequest {
+    /// Seconds the URL is valid for, at most `SIGN_MAX_TTL`.
+    expires_in: i64,
+    bind_ip: Option<IpAddr>,
+    max_uses: Option<i32>,
+    bind_ua: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SignResult {
+    url: String,
+    expires_at: i64,
+}
+
+/// Mints a signed URL for an owned file, optionally bound
+/// to a network, a client or a maximum amount of uses.
+#[post("/{id}/sign")]
+pub async fn route_sign(
+    req: HttpRequest,
+    user: UserModel,
+    id: FileId,
+    request: Json<SignRequest>,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let request = request.into_inner();
+    let config = Config::get();
+
+    if request.max_uses.is_some_and(|max_uses| max_uses < 1) {
+        return Err(AppError::InvalidMaxUses);
+    }
+
+    let ttl = request.expires_in.clamp(1, config.sign_max_ttl);
+    let mut signed = SignedUrl::new(file.id(), Utc::now().timestamp() + ttl);
+
+    if let Some(ip) = request.bind_ip {
+        signed = signed.bind_ip(ip);
+    }
+
+    if let Some(max_uses) = request.max_uses {
+        signed = signed.bind_max_uses(max_uses);
+    }
+
+    if let Some(user_agent) = &request.bind_ua {
+        signed = signed.bind_user_agent(user_agent);
+    }
+
+    Ok(Json(SignResult {
+        url: public_url(&req, &format!("/file/signed/{}", signed.sign(&config.signing_secret))),
+        expires_at: signed.expires_at,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TagsRequest {
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct FileTags {
+    pub tags: Vec<String>,
+}
+
+/// Adds tags to an owned file, answering with every tag it now has.
+#[post("/{id}/tags")]
+pub async fn route_add_tags(
+    req: HttpRequest,
+    user: UserModel,
+    id: FileId,
+    request: Json<TagsRequest>,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let tags = normalize_tags(&request.tags)?;
+
+    if !FileTagModel::exceeding(user.id(), &[file.id()], &tags, &[], MAX_TAGS_PER_FILE as i64)
+        .await?
+        .is_empty()
+    {
+        return Err(AppError::TooManyTags(MAX_TAGS_PER_FILE));
+    }
+
+    FileTagModel::add(file.id(), user.id(), &tags).await?;
+    dispatch(WebhookEvent::FileTagged, &file, file_result(&req, &file));
+
+    Ok(Json(FileTags {
+        tags: FileTagModel::list_for_file(file.id()).await?,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct BulkTagRequest {
+    files: Vec<FileId>,
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BulkTagResult {
+    pub updated: Vec<FileId>,
+    /// Files left untouched since they would carry too many tags.
+    pub over_limit: Vec<FileId>,
+}
+
+/// Adds and removes tags on several owned files in a single transaction,
+/// files that would go over `MAX_TAGS_PER_FILE` are reported and skipped.
+#[post("/bulk-tag")]
+pub async fn route_bulk_tag(
+    user: UserModel,
+    request: Json<BulkTagRequest>,
+) -> Result<impl Responder, AppError> {
+    let add = normalize_tags(&request.add)?;
+    let remove = normalize_tags(&request.remove)?;
+
+    let mut files = request.files.iter().map(|id| **id).collect::<Vec<_>>();
+    files.sort_unstable();
+    files.dedup();
+
+    let over_limit =
+        FileTagModel::exceeding(user.id(), &files, &add, &remove, MAX_TAGS_PER_FILE as i64).await?;
+    let updated = files.into_iter().filter(|id| !over_limit.contains(id)).collect::<Vec<_>>();
+
+    FileTagModel::bulk(user.id(), &updated, &add, &remove).await?;
+
+    info!(
+        "User {} bulk tagged {} files, +{:?} -{:?}, {} over the limit",
+        user.id(),
+        updated.len(),
+        add,
+        remove,
+        over_limit.len()
+    );
+
+    Ok(Json(BulkTagResult {
+        updated: updated.into_iter().map(FileId).collect(),
+        over_limit: over_limit.into_iter().map(FileId).collect(),
+    }))
+}
+
+/// Appends the request body to an owned appendable file,
+/// readers following it can fetch `Range: bytes=<size>-`.
+///
+/// Appendable files aren't encrypted at rest, even with a master key set.
+#[post("/{id}/append")]
+pub async fn route_append(
+    req: HttpRequest,
+    user: UserModel,
+    storage: Data<AppStorage>,
+    id: FileId,
+    body: Bytes,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let file = append(&storage, file.id(), &body).await?;
+
+    Ok(Json(file_result(&req, &file)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeltaQuery {
+    /// The block size of the signature the delta was computed against.
+    block_size: Option<usize>,
+    /// Digest of the patched content in the algorithm of the file, as hex.
+    checksum: String,
+}
+
+/// Patches an owned file with copy and literal instructions computed
+/// against its signature, so only the changed parts are sent.
+///
+/// Files past `DELTA_JOB_THRESHOLD` are patched as a tracked job, the
+/// answer is then a 202 with the job to poll.
+#[post("/{id}/delta")]
+pub async fn route_apply_delta(
+    req: HttpRequest,
+    user: UserModel,
+    storage: Data<AppStorage>,
+    id: FileId,
+    query: Query<DeltaQuery>,
+    body: Bytes,
+) -> Result<HttpResponse, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let block_size = block_size(file.size() as u64, query.block_size);
+    let DeltaQuery { checksum, .. } = query.into_inner();
+
+    if file.size() as u64 <= Config::get().delta_job_threshold {
+        let file = patch(&storage, file, block_size, &checksum, body.to_vec(), None).await?;
+
+        return Ok(HttpResponse::Ok().json(file_result(&req, &file)));
+    }
+
+    let parameters = json!({ "file": file.id(), "block_size": block_size });
+
+    let job = spawn_tracked("delta", parameters, move |progress| async move {
+        let delta = body.to_vec();
+        let file = patch(&storage, file, block_size, &checksum, delta, Some(&progress)).await?;
+
+        Ok(json!(file_result_at(&file, "")))
+    })
+    .await?;
+
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+#[derive(serde::Deserialize)]
+pub struct GrantRequest {
+    username: String,
+    permission: FilePermission,
+}
+
+/// Shares an owned file with another user, replacing what they were
+/// granted before, answering with everyone the file is shared with.
+#[post("/{id}/acl")]
+pub async fn route_grant_access(
+    user: UserModel,
+    id: FileId,
+    request: Json<GrantRequest>,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let grantee = UserModel::get_by_username(&request.username).await?;
+
+    FileAclModel::grant(file.id(), grantee.id(), request.permission).await?;
+    info!(
+        "User {} granted {} on file {} to user {}",
+        user.id(),
+        request.permission.as_str(),
+        file.id(),
+        grantee.id()
+    );
+
+    Ok(Json(FileAclModel::list_for_file(file.id()).await?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PosterQuery {
+    /// Seconds into the video, such as `12.5s`.
+    at: Option<String>,
+}
+
+/// Extracts the poster of an owned video again, at the requested
+/// timestamp or 10% into it, replacing the current one.
+#[post("/{id}/poster")]
+pub async fn route_extract_poster(
+    req: HttpRequest,
+    user: UserModel,
+    storage: Data<AppStorage>,
+    id: FileId,
+    query: Query<PosterQuery>,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let at = query.at.as_deref().map(parse_timestamp).transpose()?;
+
+    let data = storage.get(&file_blob_key(&file)?).await?;
+
+    if !infer::is_video(&data) {
+        return Err(AppError::NotVideo);
+    }
+
+    let tracker = ProcessingTracker::new(file.id());
+    tracker.running("poster").await;
+
+    let file = match extract_poster(file, &data, &storage, at).await {
+        Ok(file) => {
+            tracker.done("poster").await;
+            file
+        },
+        Err(error) => {
+            tracker.failed("poster", &error.to_string()).await;
+            return Err(error);
+        },
+    };
+    FileCache::get().forget(file.id());
+
+    Ok(Json(file_result(&req, &file)))
+}
+
+fn parse_timestamp(value: &str) -> Result<f64, AppError> {
+    value
+        .strip_suffix('s')
+        .unwrap_or(value)
+        .parse::<f64>()
+        .ok()
+        .filter(|at| at.is_finite() && *at >= 0.0)
+        .ok_or(AppError::InvalidTimestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::to_bytes;
+    use actix_web::error::PayloadError;
+    use actix_web::http::StatusCode;
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::test::TestRequest;
+    use futures::stream;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn broken_forms_are_answered_with_the_structured_error() {
+        let req = TestRequest::default()
+            .insert_header((CONTENT_TYPE, "multipart/form-data; boundary=abc"))
+            .to_http_request();
+        let body = Bytes::from_static(b"--abc\r\nContent-Disposition: form-data\r\n\r\ntruncated");
+        let mut form = Multipart::new(req.headers(), stream::iter([Ok::<_, PayloadError>(body)]));
+
+        let error = match form.try_next().await {
+            Ok(Some(mut field)) => read_field(&mut field, MAX_FORM_FIELD_LENGTH).await.unwrap_err(),
+            Ok(None) => panic!("a broken form yielded no error"),
+            Err(error) => AppError::from(error),
+        };
+
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "MALFORMED_MULTIPART");
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs SIGNING_SECRET"]
+    async fn anonymous_uploads_are_unauthorized_unless_allowed() {
+        // `ALLOW_ANONYMOUS_UPLOAD` is off by default.
+        let error = upload_bucket(None, None).await.err().unwrap();
+
+        assert_eq!(error.status_code(), StatusCode::UNAUTHORIZED);
+    }
+}
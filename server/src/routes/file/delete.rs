@@ -0,0 +1,173 @@
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete};
+use database::{DatabaseError, FileAclModel, FileModel, FilePermission, FileTagModel, UserModel};
+use log::info;
+use storage::Storage;
+
+use super::create::FileTags;
+use crate::AppError;
+use crate::extractors::file_id::FileId;
+use crate::processing::poster::{poster_key, poster_thumbnail_key};
+use crate::utils::app_storage::AppStorage;
+use crate::utils::file_cache::FileCache;
+use crate::utils::links::file_result;
+use crate::utils::purge::{file_key, purge};
+use crate::utils::stat_cache::StatCache;
+use crate::utils::tags::normalize_tag;
+
+macros_utils::routes! {
+    route route_remove_tag,
+    route route_remove_poster,
+    route route_revoke_access,
+    route route_delete_file,
+}
+
+/// Removes a tag from an owned file, answering with the tags it has left.
+#[delete("/{id}/tags/{tag}")]
+pub async fn route_remove_tag(
+    user: UserModel,
+    id: FileId,
+    path: Path<(String, String)>,
+) -> Result<impl Responder, AppError> {
+    let (_, tag) = path.into_inner();
+    let file = FileModel::get_owned(*id, user.id()).await?;
+
+    if !FileTagModel::remove(file.id(), &normalize_tag(&tag)?).await? {
+        return Err(DatabaseError::ModelNotFound("tag").into());
+    }
+
+    Ok(Json(FileTags {
+        tags: FileTagModel::list_for_file(file.id()).await?,
+    }))
+}
+
+/// Removes the poster of an owned video, so it's shown with the generic icon.
+#[delete("/{id}/poster")]
+pub async fn route_remove_poster(
+    req: HttpRequest,
+    user: UserModel,
+    storage: Data<AppStorage>,
+    id: FileId,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+
+    if file.poster_state().is_none() {
+        return Err(DatabaseError::ModelNotFound("poster").into());
+    }
+
+    storage.delete(&poster_key(&file)).await?;
+    storage.delete(&poster_thumbnail_key(&file)).await?;
+
+    let file = file.set_poster(None, None).await?;
+    FileCache::get().forget(file.id());
+
+    Ok(Json(file_result(&req, &file)))
+}
+
+/// Stops sharing an owned file with a user, answering with
+/// everyone the file is still shared with.
+#[delete("/{id}/acl/{username}")]
+pub async fn route_revoke_access(
+    user: UserModel,
+    id: FileId,
+    path: Path<(String, String)>,
+) -> Result<impl Responder, AppError> {
+    let (_, username) = path.into_inner();
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let grantee = UserModel::get_by_username(&username).await?;
+
+    if !FileAclModel::revoke(file.id(), grantee.id()).await? {
+        return Err(DatabaseError::ModelNotFound("grant").into());
+    }
+
+    Ok(Json(FileAclModel::list_for_file(file.id()).await?))
+}
+
+/// Deletes a file owned by the user or shared with them with
+/// the `delete` permission, its blob is left to the reference counts.
+#[delete("/{id}")]
+pub async fn route_delete_file(user: UserModel, id: FileId) -> Result<HttpResponse, AppError> {
+    let file = FileModel::get_accessible(*id, user.id(), FilePermission::Delete).await?;
+
+    delete_file(&file).await?;
+    info!("User {} deleted file {}", user.id(), file.id());
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Deletes `file` and drops its cached copy, so it stops serving right away.
+async fn delete_file(file: &FileModel) -> Result<(), AppError> {
+    file.delete().await?;
+    StatCache::get().forget_file(file);
+    FileCache::get().forget(file.id());
+    purge(vec![file_key(file.id())]);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+    use crate::testing::{create_bucket, create_user, get, run, unique_name, upload_file};
+    use crate::utils::app_storage::open_storage;
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn deleted_files_stop_serving_right_away() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("delete")).await).await;
+            let file = upload_file(&storage, &bucket, "gone.txt", b"gone").await;
+            let path = format!("/f/{}", file.slug());
+
+            // Served once first, so the file is cached.
+            assert_eq!(get(&storage, &path).await.status(), StatusCode::OK);
+
+            delete_file(&file).await.unwrap();
+
+            assert_eq!(get(&storage, &path).await.status(), StatusCode::NOT_FOUND);
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn only_delete_grants_let_others_delete_and_revoking_takes_them_back() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let owner = create_user(&unique_name("owner")).await;
+            let reader = create_user(&unique_name("reader")).await;
+            let deleter = create_user(&unique_name("deleter")).await;
+            let bucket = create_bucket(&owner).await;
+            let file = upload_file(&storage, &bucket, "shared.txt", b"shared").await;
+
+            FileAclModel::grant(file.id(), reader.id(), FilePermission::Read).await.unwrap();
+            FileAclModel::grant(file.id(), deleter.id(), FilePermission::Delete).await.unwrap();
+
+            // What `DELETE /file/{id}` looks the file up with.
+            let deletable = |user: &UserModel| {
+                FileModel::get_accessible(file.id(), user.id(), FilePermission::Delete)
+            };
+
+            let refused = deletable(&reader).await;
+            assert!(matches!(refused, Err(DatabaseError::ModelNotFound(_))));
+            assert!(deletable(&owner).await.is_ok());
+
+            // Revoked, the delete grant no longer lets them in.
+            assert!(FileAclModel::revoke(file.id(), deleter.id()).await.unwrap());
+            assert!(deletable(&deleter).await.is_err());
+            assert!(!FileAclModel::revoke(file.id(), deleter.id()).await.unwrap());
+
+            // Granting again replaces the read grant.
+            FileAclModel::grant(file.id(), reader.id(), FilePermission::Delete).await.unwrap();
+            let granted = deletable(&reader).await.unwrap();
+
+            delete_file(&granted).await.unwrap();
+            assert!(FileModel::get(file.id()).await.is_err());
+
+            let path = format!("/f/{}", file.slug());
+            assert_eq!(get(&storage, &path).await.status(), StatusCode::NOT_FOUND);
+        })
+    }
+}
@@ -0,0 +1,617 @@
+use actix_web::http::header::{CACHE_CONTROL, HeaderName, HeaderValue, USER_AGENT};
+use actix_web::web::{Data, Json, Path, Query};
+use actix_web::{HttpRequest, HttpResponse, Responder, get};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use database::{
+    Collation, FileAclModel, FileCursor, FileModel, FilePermission, FileProcessingModel,
+    FileResult, FileSort, ProcessingSummary, SignedUrlUseModel, SortOrder, UserModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::extractors::file_id::FileId;
+use crate::middleware::forwarded::ClientInfo;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::delta::{block_size, cached_signature};
+use crate::utils::headers::first_header_value;
+use crate::utils::license::canonical_license;
+use crate::utils::links::file_result;
+use crate::utils::not_found::file_by_slug;
+use crate::utils::serving::{check_tenant, original_blob_key, serve_blob, serve_file};
+use crate::utils::signing::SignedUrl;
+use crate::utils::tags::normalize_tag;
+use crate::utils::view_stats::viewer_stats;
+
+macros_utils::routes! {
+    route route_get_by_slug,
+    route route_get_signed,
+    route route_get_original,
+    route route_list_mine,
+    route route_get_signature,
+    route route_list_acl,
+    route route_get_processing,
+    route route_get_stats,
+    // Registered last, `/{id}` would also match the other single segment paths.
+    route route_get_file,
+}
+
+#[get("/s/{slug}")]
+pub async fn route_get_by_slug(
+    req: HttpRequest,
+    slug: Path<String>,
+) -> Result<impl Responder, AppError> {
+    let served = file_by_slug(&slug).await?;
+
+    Ok(Json(file_result(&req, &served.file)))
+}
+
+/// Serves the content of the file a signed URL was minted for, only the
+/// bindings encoded in it are checked so unbound URLs never touch the use
+/// counter. The file itself is never described, its slug would be a link
+/// free of every binding.
+///
+/// A use is only counted once every other check passed.
+#[get("/signed/{token}")]
+pub async fn route_get_signed(
+    req: HttpRequest,
+    storage: Data<AppStorage>,
+    token: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let signed = SignedUrl::verify(&token, &Config::get().signing_secret)?;
+
+    let user_agent = first_header_value(&req, USER_AGENT);
+    signed.check(ClientInfo::get(&req).ip, user_agent)?;
+
+    let file = FileModel::get(signed.file_id).await?;
+
+    let rotated = file
+        .link_rotated_at()
+        .is_some_and(|rotated_at| rotated_at.and_utc().timestamp_millis() > signed.issued_at);
+
+    if rotated {
+        return Err(AppError::SignatureRevoked);
+    }
+
+    // The URL proves the file existed, it may as well be told it's gone.
+    if file.expires_at().is_some_and(|expires_at| expires_at <= Utc::now().naive_utc()) {
+        return Err(AppError::FileExpired);
+    }
+
+    let served = file.into_served().await?;
+    check_tenant(&req, &served)?;
+
+    if let Some(max_uses) = signed.max_uses
+        && !SignedUrlUseModel::consume(&signed.jti, max_uses).await?
+    {
+        return Err(AppError::SignatureUsesExhausted);
+    }
+
+    let mut response = serve_file(&req, &storage, &served).await?;
+
+    // Bound to whoever redeemed it, no shared cache may answer someone else.
+    response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+
+    Ok(response)
+}
+
+/// Downloads a file as it was uploaded, before it was transcoded
+/// to UTF-8, only files that were normalized have an original.
+///
+/// Users the file is shared with can download it as well.
+#[get("/{id}/original")]
+pub async fn route_get_original(
+    req: HttpRequest,
+    user: UserModel,
+    storage: Data<AppStorage>,
+    id: FileId,
+) -> Result<impl Responder, AppError> {
+    let served = FileModel::get_accessible(*id, user.id(), FilePermission::Read)
+        .await?
+        .into_served()
+        .await?;
+    let file = &served.file;
+
+    let key = original_blob_key(file)?;
+
+    let name = file.path().rsplit('/').next().unwrap_or_default();
+
+    serve_blob(&req, &storage, &served, &key, None, name, file.text_encoding()).await
+}
+
+/// Holds the cursor resuming a listing when more files may follow.
+const X_NEXT_CURSOR: HeaderName = HeaderName::from_static("x-next-cursor");
+
+#[derive(serde::Deserialize)]
+pub struct MineQuery {
+    tag: Option<String>,
+    /// One of `created`, `name` or `size`, anything else is rejected.
+    #[serde(default)]
+    sort: FileSort,
+    #[serde(default)]
+    order: SortOrder,
+    /// How names compare when sorting by name, `natural` or `binary`.
+    #[serde(default)]
+    collation: Collation,
+    /// Only lists files under this SPDX identifier.
+    license: Option<String>,
+    /// Lists at most this many files, at most 1000, every one when unset.
+    limit: Option<i64>,
+    /// The `X-Next-Cursor` of the previous page.
+    after: Option<String>,
+}
+
+/// The opaque cursor handed to clients, which also records how the
+/// listing was sorted so it can't resume another one.
+#[derive(Serialize, Deserialize)]
+struct PageCursor {
+    order: SortOrder,
+    collation: Collation,
+    after: FileCursor,
+}
+
+impl PageCursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(token: &str) -> Result<Self, AppError> {
+        URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or(AppError::InvalidCursor)
+    }
+
+    /// The cursor of `query` when it was issued for the same sort.
+    fn from_query(query: &MineQuery) -> Result<Option<FileCursor>, AppError> {
+        let Some(token) = &query.after else {
+            return Ok(None);
+        };
+
+        let cursor = PageCursor::decode(token)?;
+
+        if cursor.after.sort() != query.sort
+            || cursor.order != query.order
+            || cursor.collation != query.collation
+        {
+            return Err(AppError::InvalidCursor);
+        }
+
+        Ok(Some(cursor.after))
+    }
+}
+
+/// Lists the files of the user, newest first unless `sort` and `order`
+/// say otherwise, only those carrying `tag` or under `license` when given.
+#[get("/mine")]
+pub async fn route_list_mine(
+    req: HttpRequest,
+    user: UserModel,
+    query: Query<MineQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (files, next) = list_mine(user.id(), &query).await?;
+
+    let mut response = HttpResponse::Ok();
+
+    if let Some(next) = next {
+        response.insert_header((X_NEXT_CURSOR, next));
+    }
+
+    Ok(response.json(files.iter().map(|file| file_result(&req, file)).collect::<Vec<_>>()))
+}
+
+/// A page of the files of `owner_id` and the cursor of the next one.
+///
+/// Pages resume past the sort key and id of the last file listed, so
+/// files added or removed meanwhile don't shift the following ones.
+async fn list_mine(
+    owner_id: i64,
+    query: &MineQuery,
+) -> Result<(Vec<FileModel>, Option<String>), AppError> {
+    let license = query.license.as_deref().map(canonical_license).transpose()?;
+    let after = PageCursor::from_query(query)?;
+    let limit = query.limit.map(|limit| limit.clamp(1, 1000));
+
+    let files = match &query.tag {
+        Some(tag) => {
+            FileModel::list_by_tag(
+                owner_id,
+                &normalize_tag(tag)?,
+                query.sort,
+                query.order,
+                query.collation,
+                license,
+                after.as_ref(),
+                limit,
+            )
+            .await?
+        },
+        None => {
+            FileModel::list_owned(
+                owner_id,
+                query.sort,
+                query.order,
+                query.collation,
+                license,
+                after.as_ref(),
+                limit,
+            )
+            .await?
+        },
+    };
+
+    // A short page is the last one.
+    let next = match files.last().filter(|_| Some(files.len() as i64) == limit) {
+        Some(last) => Some(
+            PageCursor {
+                order: query.order,
+                collation: query.collation,
+                after: FileCursor::after(last, query.sort, query.collation).await?,
+            }
+            .encode(),
+        ),
+        None => None,
+    };
+
+    Ok((files, next))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SignatureQuery {
+    /// Defaults to one picked from the file size.
+    block_size: Option<usize>,
+}
+
+/// Lists the block hashes of an owned file, so the client can send
+/// only what changed to `POST /file/{id}/delta`.
+#[get("/{id}/signature")]
+pub async fn route_get_signature(
+    user: UserModel,
+    storage: Data<AppStorage>,
+    id: FileId,
+    query: Query<SignatureQuery>,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let block_size = block_size(file.size() as u64, query.block_size);
+
+    Ok(Json(cached_signature(&storage, &file, block_size).await?))
+}
+
+/// Lists the users an owned file is shared with.
+#[get("/{id}/acl")]
+pub async fn route_list_acl(user: UserModel, id: FileId) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+
+    Ok(Json(FileAclModel::list_for_file(file.id()).await?))
+}
+
+/// The state of every processing stage run on a file owned by the user or shared with them.
+#[get("/{id}/processing")]
+pub async fn route_get_processing(user: UserModel, id: FileId) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_accessible(*id, user.id(), FilePermission::Read).await?;
+
+    Ok(Json(FileProcessingModel::list_for_file(file.id()).await?))
+}
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    /// Days counted back from today included, 30 by default and at most 366.
+    days: Option<i64>,
+}
+
+/// The views of an owned file over a window of days. Its unique viewers are
+/// estimated within `unique_viewers_error`, the relative standard error,
+/// and left out when `UNIQUE_VIEWERS` is disabled.
+///
+/// Views are written every `STATS_FLUSH_INTERVAL` seconds,
+/// the latest ones only show up after that.
+#[get("/{id}/stats")]
+pub async fn route_get_stats(
+    user: UserModel,
+    id: FileId,
+    query: Query<StatsQuery>,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let days = query.days.unwrap_or(30).clamp(1, 366);
+
+    Ok(Json(viewer_stats(file.id(), days).await?))
+}
+
+#[derive(Serialize)]
+struct FileDetail {
+    #[serde(flatten)]
+    file: FileResult,
+    processing_summary: ProcessingSummary,
+}
+
+/// Describes a file owned by the user or shared with them.
+#[get("/{id}")]
+pub async fn route_get_file(
+    req: HttpRequest,
+    user: UserModel,
+    id: FileId,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_accessible(*id, user.id(), FilePermission::Read).await?;
+
+    Ok(Json(FileDetail {
+        file: file_result(&req, &file),
+        processing_summary: FileProcessingModel::summary(file.id()).await?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeDelta, Utc};
+    use database::{DatabaseError, FileCreation, FileTagModel, Visibility};
+
+    use actix_web::http::StatusCode;
+    use actix_web::test::read_body;
+
+    use super::*;
+    use crate::testing::{
+        create_bucket, create_file, create_user, get, run, unique_name, upload_file,
+    };
+    use crate::utils::app_storage::open_storage;
+    use crate::utils::normalize::natural_sort_key;
+    use crate::utils::tags::normalize_tags;
+
+    /// Names sorting differently under each collation, with case,
+    /// accents, numbers and characters outside of Latin.
+    const NAMES: [&str; 8] = [
+        "file10.txt",
+        "Zebra.pdf",
+        "apple.txt",
+        "日本.txt",
+        "Ärger.pdf",
+        "file2.txt",
+        "Apple.txt",
+        "ärger.pdf",
+    ];
+
+    fn query(query: &str) -> MineQuery {
+        Query::<MineQuery>::from_query(query).unwrap().into_inner()
+    }
+
+    fn paths(files: &[FileModel]) -> Vec<&str> {
+        files.iter().map(FileModel::path).collect()
+    }
+
+    /// Every file of `owner_id` read a `page` at a time.
+    async fn traverse(owner_id: i64, listing: &str, page: usize) -> Vec<FileModel> {
+        let mut files = Vec::new();
+        let mut after = None::<String>;
+
+        loop {
+            let cursor = after.map(|after| format!("&after={after}")).unwrap_or_default();
+            let (page, next) =
+                list_mine(owner_id, &query(&format!("{listing}&limit={page}{cursor}")))
+                    .await
+                    .unwrap();
+            files.extend(page);
+
+            match next {
+                Some(next) => after = Some(next),
+                None => return files,
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn names_sort_by_collation_and_pages_resume_on_ties() {
+        run(async {
+            let user = create_user(&unique_name("collation")).await;
+            let bucket = create_bucket(&user).await;
+            // Two creation dates and three sizes, so every sort has ties.
+            let created_at = Utc::now().naive_utc() - TimeDelta::days(1);
+
+            for (index, name) in NAMES.iter().enumerate() {
+                FileModel::create_new(FileCreation {
+                    bucket_id: bucket.id(),
+                    path: name.to_string(),
+                    size: index as i64 % 3,
+                    hash: format!("{:064x}", rand::random::<u128>()),
+                    hash_algorithm: "sha256".into(),
+                    created_at: Some(created_at + TimeDelta::seconds(index as i64 % 2)),
+                    expires_at: None,
+                    text: None,
+                    name_key: natural_sort_key(name),
+                    content_type: None,
+                })
+                .await
+                .unwrap();
+            }
+
+            let (natural, _) = list_mine(user.id(), &query("sort=name&order=asc")).await.unwrap();
+            assert_eq!(
+                paths(&natural),
+                [
+                    "apple.txt",
+                    "Apple.txt",
+                    "Ärger.pdf",
+                    "ärger.pdf",
+                    "file2.txt",
+                    "file10.txt",
+                    "Zebra.pdf",
+                    "日本.txt",
+                ]
+            );
+
+            let (binary, _) =
+                list_mine(user.id(), &query("sort=name&order=asc&collation=binary")).await.unwrap();
+            assert_eq!(
+                paths(&binary),
+                [
+                    "Apple.txt",
+                    "Zebra.pdf",
+                    "apple.txt",
+                    "file10.txt",
+                    "file2.txt",
+                    "Ärger.pdf",
+                    "ärger.pdf",
+                    "日本.txt",
+                ]
+            );
+
+            for sort in ["created", "name", "size"] {
+                for order in ["asc", "desc"] {
+                    for collation in ["natural", "binary"] {
+                        let listing = format!("sort={sort}&order={order}&collation={collation}");
+                        let (all, _) = list_mine(user.id(), &query(&listing)).await.unwrap();
+
+                        for page in [1, 3] {
+                            let paged = traverse(user.id(), &listing, page).await;
+                            assert_eq!(paths(&paged), paths(&all), "{listing} by {page}");
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn cursors_only_resume_the_listing_they_came_from() {
+        run(async {
+            let user = create_user(&unique_name("cursor")).await;
+            let bucket = create_bucket(&user).await;
+            create_file(&bucket, "first.txt").await;
+            create_file(&bucket, "second.txt").await;
+
+            let (_, next) = list_mine(user.id(), &query("sort=name&limit=1")).await.unwrap();
+            let next = next.unwrap();
+
+            for listing in ["sort=size", "sort=name&order=asc", "sort=name&collation=binary"] {
+                let error = list_mine(user.id(), &query(&format!("{listing}&after={next}")))
+                    .await
+                    .err()
+                    .unwrap();
+                assert!(matches!(error, AppError::InvalidCursor), "{listing}");
+            }
+
+            let error = list_mine(user.id(), &query("after=garbage")).await.err().unwrap();
+            assert!(matches!(error, AppError::InvalidCursor));
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn files_are_tagged_filtered_by_tag_and_untagged() {
+        run(async {
+            let owner = create_user(&unique_name("tags")).await;
+            let bucket = create_bucket(&owner).await;
+            let (report, photo) =
+                (create_file(&bucket, "report.pdf").await, create_file(&bucket, "photo.png").await);
+            let other = create_user(&unique_name("tags")).await;
+            let theirs = create_file(&create_bucket(&other).await, "theirs.pdf").await;
+
+            let work = normalize_tags(&["Work".into(), " WORK ".into()]).unwrap();
+            assert_eq!(work, ["work"]);
+
+            FileTagModel::add(report.id(), owner.id(), &work).await.unwrap();
+            FileTagModel::add(report.id(), owner.id(), &["2024".into()]).await.unwrap();
+            FileTagModel::add(theirs.id(), other.id(), &work).await.unwrap();
+            assert_eq!(FileTagModel::list_for_file(report.id()).await.unwrap(), ["2024", "work"]);
+
+            let (tagged, _) = list_mine(owner.id(), &query("tag=Work")).await.unwrap();
+            assert_eq!(paths(&tagged), ["report.pdf"]);
+
+            let (all, _) = list_mine(owner.id(), &query("sort=name&order=asc")).await.unwrap();
+            assert_eq!(paths(&all), ["photo.png", "report.pdf"]);
+            assert!(FileTagModel::list_for_file(photo.id()).await.unwrap().is_empty());
+
+            assert!(FileTagModel::remove(report.id(), "work").await.unwrap());
+            assert!(!FileTagModel::remove(report.id(), "work").await.unwrap());
+
+            let (tagged, _) = list_mine(owner.id(), &query("tag=work")).await.unwrap();
+            assert!(tagged.is_empty());
+
+            // Tags are per owner, the other one keeps theirs.
+            let (tagged, _) = list_mine(other.id(), &query("tag=work")).await.unwrap();
+            assert_eq!(paths(&tagged), ["theirs.pdf"]);
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn signed_urls_serve_the_content_and_only_count_uses_served() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("signed")).await).await;
+            let file = upload_file(&storage, &bucket, "signed.txt", b"signed").await;
+            let expires_at = Utc::now().timestamp() + 60;
+            let secret = &Config::get().signing_secret;
+
+            let once = SignedUrl::new(file.id(), expires_at).bind_max_uses(1);
+            let path = format!("/file/signed/{}", once.sign(secret));
+
+            let response = get(&storage, &path).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "private, no-store");
+            // The content itself, never the slug leading to it unbound.
+            assert_eq!(read_body(response).await, "signed");
+
+            assert_eq!(get(&storage, &path).await.status(), StatusCode::FORBIDDEN);
+
+            let revoked = SignedUrl::new(file.id(), expires_at).bind_max_uses(1);
+            let path = format!("/file/signed/{}", revoked.sign(secret));
+            // Only URLs issued before the millisecond of the rotation are revoked.
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            file.rotate_slug(false).await.unwrap();
+
+            assert_eq!(get(&storage, &path).await.status(), StatusCode::FORBIDDEN);
+            // Refused before its use was counted.
+            assert!(SignedUrlUseModel::consume(&revoked.jti, 1).await.unwrap());
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn granted_users_read_private_files_until_revoked() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let owner = create_user(&unique_name("owner")).await;
+            let reader = create_user(&unique_name("reader")).await;
+            let deleter = create_user(&unique_name("deleter")).await;
+            let stranger = create_user(&unique_name("stranger")).await;
+            let bucket = create_bucket(&owner).await;
+            let file = upload_file(&storage, &bucket, "private.txt", b"private").await;
+            assert_eq!(bucket.visibility(), Visibility::Private);
+
+            FileAclModel::grant(file.id(), reader.id(), FilePermission::Read).await.unwrap();
+            FileAclModel::grant(file.id(), deleter.id(), FilePermission::Delete).await.unwrap();
+
+            // What `GET /file/{id}` and its original and processing look the file up with.
+            let readable = |user: &UserModel| {
+                FileModel::get_accessible(file.id(), user.id(), FilePermission::Read)
+            };
+
+            for user in [&owner, &reader, &deleter] {
+                assert_eq!(readable(user).await.unwrap().id(), file.id(), "{}", user.username());
+            }
+
+            let refused = readable(&stranger).await;
+            assert!(matches!(refused, Err(DatabaseError::ModelNotFound(_))));
+
+            // Only the owner lists or changes the grants.
+            assert!(FileModel::get_owned(file.id(), reader.id()).await.is_err());
+            let grantees = FileAclModel::list_for_file(file.id()).await.unwrap();
+            let permissions = grantees
+                .iter()
+                .map(|grant| (grant.grantee_id, grant.permission.as_str()))
+                .collect::<Vec<_>>();
+            assert_eq!(permissions.len(), 2);
+            assert!(permissions.contains(&(reader.id(), "read")));
+            assert!(permissions.contains(&(deleter.id(), "delete")));
+
+            assert!(FileAclModel::revoke(file.id(), reader.id()).await.unwrap());
+            assert!(readable(&reader).await.is_err());
+            assert!(readable(&deleter).await.is_ok());
+        })
+    }
+}
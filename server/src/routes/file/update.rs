@@ -0,0 +1,278 @@
+use actix_web::web::{Json, Query};
+use actix_web::{HttpRequest, HttpResponse, Responder, patch, post};
+use chrono::NaiveDateTime;
+use database::{FileModel, FileUpdate, UserModel};
+use log::info;
+use serde::Serialize;
+
+use crate::AppError;
+use crate::extractors::file_id::FileId;
+use crate::utils::concurrency::{expected_unmodified_since, expected_version};
+use crate::utils::file_cache::FileCache;
+use crate::utils::license::check_license;
+use crate::utils::links::file_result;
+use crate::utils::normalize::natural_sort_key;
+use crate::utils::not_found::NotFoundCache;
+use crate::utils::purge::{file_key, purge};
+
+macros_utils::routes! {
+    route route_rotate_slug,
+    route route_rotate_link,
+    route route_edit,
+}
+
+#[derive(Serialize)]
+struct RotatedSlug {
+    slug: String,
+}
+
+/// Generates a new slug for an owned file so previously
+/// shared links stop resolving.
+#[post("/{id}/rotate-slug")]
+pub async fn route_rotate_slug(user: UserModel, id: FileId) -> Result<impl Responder, AppError> {
+    let file = rotate(&FileModel::get_owned(*id, user.id()).await?, false).await?;
+
+    Ok(Json(RotatedSlug { slug: file.slug().to_owned() }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RotateQuery {
+    /// Answers the old slug with 410 rather than 404.
+    #[serde(default)]
+    gone: bool,
+}
+
+/// Replaces the slug of an owned file after its link leaked, signed
+/// URLs issued before are revoked along with the old slug.
+#[post("/{id}/rotate-link")]
+pub async fn route_rotate_link(
+    req: HttpRequest,
+    user: UserModel,
+    id: FileId,
+    query: Query<RotateQuery>,
+) -> Result<impl Responder, AppError> {
+    let file = FileModel::get_owned(*id, user.id()).await?;
+    let rotated = rotate(&file, query.gone).await?;
+
+    info!("Rotated the link of file {}, {} is now revoked", file.id(), file.slug());
+
+    Ok(Json(file_result(&req, &rotated)))
+}
+
+/// Gives `file` a new slug, dropping what the caches hold for either one
+/// so the old slug stops serving and the new one resolves right away.
+async fn rotate(file: &FileModel, keep_revoked: bool) -> Result<FileModel, AppError> {
+    let rotated = file.rotate_slug(keep_revoked).await?;
+
+    NotFoundCache::get().forget(rotated.slug());
+    FileCache::get().forget(file.id());
+    purge(vec![file_key(file.id())]);
+
+    Ok(rotated)
+}
+
+/// Edits an owned file, when `If-Match` or `If-Unmodified-Since` is
+/// stale the current state is returned with a 412 so the client can merge.
+#[patch("/{id}")]
+pub async fn route_edit(
+    req: HttpRequest,
+    user: UserModel,
+    id: FileId,
+    update: Json<FileUpdate>,
+) -> Result<HttpResponse, AppError> {
+    let expected = expected_version(&req)?;
+    let unmodified_since = expected_unmodified_since(&req);
+    let file = FileModel::get_owned(*id, user.id()).await?;
+
+    let mut update = update.into_inner();
+
+    if let Some(path) = update.path() {
+        let name_key = natural_sort_key(path);
+        update = update.with_name_key(name_key);
+    }
+
+    let license = check_license(update.license().clone())?;
+    update = update.with_license(license);
+
+    edit_response(&req, &file, update, expected, unmodified_since).await
+}
+
+/// Answers an edit with the edited file, or with the current one
+/// and a 412 when another edit got there first.
+async fn edit_response(
+    req: &HttpRequest,
+    file: &FileModel,
+    update: FileUpdate,
+    expected: Option<i64>,
+    unmodified_since: Option<NaiveDateTime>,
+) -> Result<HttpResponse, AppError> {
+    Ok(match edit(file, update, expected, unmodified_since).await? {
+        Some(file) => HttpResponse::Ok().json(file_result(req, &file)),
+        None => HttpResponse::PreconditionFailed()
+            .json(file_result(req, &FileModel::get(file.id()).await?)),
+    })
+}
+
+/// Applies `update` unless `file` changed since the client read it, in which
+/// case it's `None`, dropping the cached copy so the edit is served right away.
+async fn edit(
+    file: &FileModel,
+    update: FileUpdate,
+    expected: Option<i64>,
+    unmodified_since: Option<NaiveDateTime>,
+) -> Result<Option<FileModel>, AppError> {
+    let edited = file.edit(update, expected, unmodified_since).await?;
+
+    if let Some(edited) = &edited {
+        FileCache::get().forget(file.id());
+
+        // Both are part of what's served, the download name and `X-Robots-Tag`.
+        if edited.path() != file.path() || edited.indexable() != file.indexable() {
+            purge(vec![file_key(file.id())]);
+        }
+    }
+
+    Ok(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use actix_web::http::header::{HttpDate, IF_UNMODIFIED_SINCE};
+    use actix_web::test::{TestRequest, read_body};
+    use chrono::{TimeDelta, Utc};
+    use database::FileCreation;
+    use futures::join;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::testing::{
+        create_bucket, create_file, create_user, get, run, unique_name, upload_file,
+    };
+    use crate::utils::app_storage::open_storage;
+
+    fn renamed(path: &str) -> FileUpdate {
+        serde_json::from_value(serde_json::json!({ "path": path })).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn rotated_slugs_stop_serving_and_the_new_one_serves() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("rotate")).await).await;
+            let file = upload_file(&storage, &bucket, "shared.txt", b"shared").await;
+            let old = format!("/f/{}", file.slug());
+
+            // Served once first, so the file is cached under the old slug.
+            assert_eq!(get(&storage, &old).await.status(), StatusCode::OK);
+
+            let rotated = rotate(&file, false).await.unwrap();
+
+            assert_eq!(get(&storage, &old).await.status(), StatusCode::NOT_FOUND);
+
+            let response = get(&storage, &format!("/f/{}", rotated.slug())).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(read_body(response).await, "shared");
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn edits_are_served_right_away() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("edit")).await).await;
+            let file = upload_file(&storage, &bucket, "before.txt", b"edited").await;
+
+            // Served once first, so the file is cached as it was.
+            assert_eq!(
+                get(&storage, &format!("/f/{}", file.slug())).await.status(),
+                StatusCode::OK
+            );
+            assert_eq!(FileCache::get().lookup(file.slug()).unwrap().file.path(), "before.txt");
+
+            edit(&file, renamed("after.txt"), None, None).await.unwrap().unwrap();
+            assert!(FileCache::get().lookup(file.slug()).is_none());
+
+            assert_eq!(
+                get(&storage, &format!("/f/{}", file.slug())).await.status(),
+                StatusCode::OK
+            );
+            assert_eq!(FileCache::get().lookup(file.slug()).unwrap().file.path(), "after.txt");
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn concurrent_edits_of_the_same_version_let_one_through() {
+        run(async {
+            let bucket = create_bucket(&create_user(&unique_name("edit")).await).await;
+            let file = create_file(&bucket, "draft.txt").await;
+            let req = TestRequest::default().to_http_request();
+            let version = Some(file.version());
+
+            let (first, second) = join!(
+                edit_response(&req, &file, renamed("first.txt"), version, None),
+                edit_response(&req, &file, renamed("second.txt"), version, None),
+            );
+            let (first, second) = (first.unwrap(), second.unwrap());
+
+            let mut statuses = [first.status(), second.status()];
+            statuses.sort();
+            assert_eq!(statuses, [StatusCode::OK, StatusCode::PRECONDITION_FAILED]);
+
+            let (winner, loser) =
+                if first.status() == StatusCode::OK { (first, second) } else { (second, first) };
+            let winner: Value =
+                serde_json::from_slice(&to_bytes(winner.into_body()).await.unwrap()).unwrap();
+            let loser: Value =
+                serde_json::from_slice(&to_bytes(loser.into_body()).await.unwrap()).unwrap();
+
+            // The refused client gets what the other one wrote to merge with.
+            assert_eq!(loser["path"], winner["path"]);
+            assert_eq!(loser["version_etag"], winner["version_etag"]);
+            assert_ne!(loser["version_etag"], file.version_etag());
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn edits_based_on_a_stale_date_are_refused() {
+        run(async {
+            let bucket = create_bucket(&create_user(&unique_name("edit")).await).await;
+            // Older than a second, edits are told apart from it at HTTP date resolution.
+            let file = FileModel::create_new(FileCreation {
+                bucket_id: bucket.id(),
+                path: "dated.txt".into(),
+                size: 0,
+                hash: format!("{:064x}", rand::random::<u128>()),
+                hash_algorithm: "sha256".into(),
+                created_at: Some(Utc::now().naive_utc() - TimeDelta::minutes(1)),
+                expires_at: None,
+                text: None,
+                name_key: b"dated.txt".to_vec(),
+                content_type: None,
+            })
+            .await
+            .unwrap();
+
+            let read_at = HttpDate::from(SystemTime::from(file.last_modified_at().and_utc()));
+            let req = TestRequest::default()
+                .insert_header((IF_UNMODIFIED_SINCE, read_at.to_string()))
+                .to_http_request();
+            let since = expected_unmodified_since(&req);
+            assert!(since.is_some());
+
+            let response = edit_response(&req, &file, renamed("fresh.txt"), None, since).await;
+            assert_eq!(response.unwrap().status(), StatusCode::OK);
+
+            let response = edit_response(&req, &file, renamed("stale.txt"), None, since).await;
+            assert_eq!(response.unwrap().status(), StatusCode::PRECONDITION_FAILED);
+            assert_eq!(FileModel::get(file.id()).await.unwrap().path(), "fresh.txt");
+        })
+    }
+}
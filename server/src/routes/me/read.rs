@@ -0,0 +1,126 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::web::{Bytes, Json, Query};
+use actix_web::{Error, HttpRequest, HttpResponse, Responder, get};
+use database::{FileModel, FileResult, StorageBreakdown, StorageModel, UserModel};
+use futures::stream::{Stream, StreamExt, TryStreamExt, iter, try_unfold};
+use serde::Serialize;
+
+use crate::AppError;
+use crate::utils::links::{content_base_url, file_result, file_result_at};
+use crate::utils::usage::usage;
+
+macros_utils::routes! {
+    route route_export,
+    route route_storage_breakdown,
+    route route_usage,
+}
+
+/// Files read per query of an export. Every page is a short query of its
+/// own, so a slow download neither runs into the statement timeout nor
+/// holds a pooled connection while the client reads.
+const EXPORT_PAGE: i64 = 500;
+
+/// Streams every file of the caller as newline delimited JSON.
+#[get("/export")]
+pub async fn route_export(req: HttpRequest, user: UserModel) -> Result<HttpResponse, AppError> {
+    let base_url = content_base_url(&req);
+
+    let files = owned_files(user.id(), EXPORT_PAGE).map(move |file| {
+        let file = file_result_at(&file?, &base_url);
+        let mut line = serde_json::to_vec(&file).map_err(ErrorInternalServerError)?;
+        line.push(b'\n');
+
+        Ok::<_, Error>(Bytes::from(line))
+    });
+
+    Ok(HttpResponse::Ok().content_type("application/x-ndjson").streaming(files))
+}
+
+/// Every file owned by `owner_id` by id, read `page` files at a time
+/// as the stream is consumed.
+fn owned_files(owner_id: i64, page: i64) -> impl Stream<Item = Result<FileModel, AppError>> {
+    try_unfold(Some(0), move |after_id| async move {
+        let Some(after_id) = after_id else {
+            return Ok::<_, AppError>(None);
+        };
+
+        let files = FileModel::list_owned_after(owner_id, after_id, page).await?;
+
+        // A short page is the last one.
+        let next = files.last().filter(|_| files.len() as i64 == page).map(FileModel::id);
+
+        Ok(Some((iter(files.into_iter().map(Ok::<_, AppError>)), next)))
+    })
+    .try_flatten()
+}
+
+#[derive(serde::Deserialize)]
+pub struct BreakdownQuery {
+    /// Amount of largest files listed, 10 by default and at most 100.
+    largest: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct StorageReport {
+    #[serde(flatten)]
+    breakdown: StorageBreakdown,
+    largest: Vec<FileResult>,
+}
+
+/// Breaks the storage used by the caller down by category,
+/// bucket and size, along with their largest files.
+#[get("/storage/breakdown")]
+pub async fn route_storage_breakdown(
+    req: HttpRequest,
+    user: UserModel,
+    query: Query<BreakdownQuery>,
+) -> Result<impl Responder, AppError> {
+    let limit = query.largest.unwrap_or(10).clamp(0, 100);
+
+    Ok(Json(StorageReport {
+        breakdown: StorageModel::breakdown(user.id()).await?,
+        largest: FileModel::largest_owned(user.id(), limit)
+            .await?
+            .iter()
+            .map(|file| file_result(&req, file))
+            .collect(),
+    }))
+}
+
+/// Total bytes and files of the caller, recomputed every few seconds at most.
+#[get("/usage")]
+pub async fn route_usage(user: UserModel) -> Result<impl Responder, AppError> {
+    Ok(Json(usage(user.id()).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{create_bucket, create_file, create_user, run, unique_name};
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn exports_read_every_file_a_page_at_a_time() {
+        run(async {
+            let user = create_user(&unique_name("export")).await;
+            let (first, second) = (create_bucket(&user).await, create_bucket(&user).await);
+
+            let mut created = Vec::new();
+
+            for index in 0..5 {
+                let bucket = if index % 2 == 0 { &first } else { &second };
+                created.push(create_file(bucket, &format!("file-{index}")).await.id());
+            }
+
+            // Pages cut the files unevenly, evenly and not at all.
+            for page in [2, 5, 10] {
+                let exported = owned_files(user.id(), page)
+                    .map(|file| file.unwrap().id())
+                    .collect::<Vec<_>>()
+                    .await;
+
+                assert_eq!(exported, created);
+            }
+        })
+    }
+}
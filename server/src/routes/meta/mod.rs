@@ -0,0 +1,7 @@
+mod read;
+
+macros_utils::routes! {
+    load read,
+
+    on "/meta"
+}
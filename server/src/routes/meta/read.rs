@@ -0,0 +1,44 @@
+use actix_web::web::Json;
+use actix_web::{HttpResponse, Responder, get};
+use database::BlobModel;
+
+use crate::AppError;
+use crate::error_code::ErrorCode;
+use crate::extractors::admin::AdminUser;
+use crate::lifecycle::is_ready;
+use crate::tasks::supervisor::Supervisor;
+
+macros_utils::routes! {
+    route route_error_codes,
+    route route_blob_stats,
+    route route_ready,
+}
+
+/// Lists every error code with its status and description,
+/// so client SDKs can generate their error types from it.
+#[get("/error-codes")]
+pub async fn route_error_codes() -> impl Responder {
+    Json(ErrorCode::ALL.iter().map(ErrorCode::entry).collect::<Vec<_>>())
+}
+
+/// Reports the stored blobs and the drift the last reference
+/// count check found to admins, run it with `cdnctl check-refs`.
+#[get("/blobs")]
+pub async fn route_blob_stats(_: AdminUser) -> Result<impl Responder, AppError> {
+    Ok(Json(BlobModel::stats().await?))
+}
+
+/// Answers 503 until the schema is migrated and once a supervised background
+/// task was given up on, so orchestrators stop routing to a node that's silently broken.
+#[get("/ready")]
+pub async fn route_ready() -> HttpResponse {
+    let supervisor = Supervisor::get();
+
+    if !is_ready() {
+        HttpResponse::ServiceUnavailable().finish()
+    } else if supervisor.healthy() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::ServiceUnavailable().json(supervisor.tasks())
+    }
+}
@@ -1,6 +1,33 @@
+mod admin;
 mod auth;
+mod bucket;
+mod dav;
+mod domain;
+mod embed;
+mod feed;
+mod file;
+mod me;
+mod meta;
+mod seo;
+mod serve;
+mod tags;
 mod test;
+mod webhook;
 
 macros_utils::routes! {
+    load admin,
+    load auth,
+    load bucket,
+    load dav,
+    load domain,
+    load embed,
+    load feed,
+    load file,
+    load me,
+    load meta,
+    load seo,
+    load serve,
+    load tags,
     load test,
+    load webhook,
 }
@@ -0,0 +1,5 @@
+mod read;
+
+macros_utils::routes! {
+    load read,
+}
@@ -0,0 +1,63 @@
+use actix_web::web::Path;
+use actix_web::{HttpRequest, HttpResponse, get};
+use database::DatabaseError;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::links::public_url;
+use crate::utils::sitemap::sitemap;
+
+macros_utils::routes! {
+    route route_robots,
+    route route_sitemap,
+    route route_sitemap_page,
+}
+
+/// Serves `ROBOTS_TXT`, or keeps crawlers off content
+/// unless `SEO_ALLOW_INDEXING` is set.
+#[get("/robots.txt")]
+pub async fn route_robots(req: HttpRequest) -> HttpResponse {
+    let config = Config::get();
+
+    let robots = if !config.robots_txt.is_empty() {
+        config.robots_txt.clone()
+    } else if config.seo_allow_indexing {
+        format!("User-agent: *\nAllow: /\n\nSitemap: {}\n", public_url(&req, "/sitemap.xml"))
+    } else {
+        "User-agent: *\nDisallow: /f/\nDisallow: /s/\nDisallow: /c/\n".to_owned()
+    };
+
+    HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(robots)
+}
+
+/// Lists indexable files, or the numbered sitemaps once they
+/// don't fit a single one, only when `SEO_ALLOW_INDEXING` is set.
+#[get("/sitemap.xml")]
+pub async fn route_sitemap(req: HttpRequest) -> Result<HttpResponse, AppError> {
+    sitemap_response(&req, 0).await
+}
+
+#[get("/sitemap-{page}.xml")]
+pub async fn route_sitemap_page(
+    req: HttpRequest,
+    page: Path<i64>,
+) -> Result<HttpResponse, AppError> {
+    // Page 0 would alias the root sitemap.
+    if *page < 1 {
+        return Err(DatabaseError::ModelNotFound("sitemap").into());
+    }
+
+    sitemap_response(&req, *page).await
+}
+
+async fn sitemap_response(req: &HttpRequest, page: i64) -> Result<HttpResponse, AppError> {
+    if !Config::get().seo_allow_indexing {
+        return Err(DatabaseError::ModelNotFound("sitemap").into());
+    }
+
+    let xml = sitemap(&public_url(req, ""), page)
+        .await?
+        .ok_or(DatabaseError::ModelNotFound("sitemap"))?;
+
+    Ok(HttpResponse::Ok().content_type("application/xml; charset=utf-8").body(xml))
+}
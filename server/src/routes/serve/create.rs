@@ -0,0 +1,52 @@
+use actix_web::web::{Data, Path, Payload};
+use actix_web::{HttpRequest, HttpResponse, Responder, put};
+use database::{BucketModel, UserModel};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::middleware::forwarded::ClientInfo;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::links::file_result;
+use crate::utils::upload::{check_upload, read_body, store_upload};
+use crate::utils::upload_limit::{UploadLimiter, upload_key};
+use crate::utils::upload_slots::{UploadSlots, preferred_wait};
+
+macros_utils::routes! {
+    route route_put,
+}
+
+/// Stores the raw request body as `name` in the bucket named after the
+/// user, for clients that can't build an upload URL such as `curl -T`.
+///
+/// Held to the same checks and limits as `/file/upload`.
+#[put("/{name}")]
+pub async fn route_put(
+    req: HttpRequest,
+    user: UserModel,
+    storage: Data<AppStorage>,
+    name: Path<String>,
+    mut payload: Payload,
+) -> Result<impl Responder, AppError> {
+    let config = Config::get();
+
+    let bucket = BucketModel::get_or_create(user.username(), user.id()).await?;
+
+    let limit_key = upload_key(Some(user.id()), ClientInfo::get(&req).ip);
+    let _slot = UploadSlots::get().acquire(&limit_key, preferred_wait(&req)).await?;
+    let limit = UploadLimiter::get().acquire(&limit_key)?;
+
+    let mut response = HttpResponse::Created();
+
+    if let Some(limit) = limit {
+        limit.insert_headers(&mut response);
+    }
+
+    let body = read_body(&mut payload, config.max_upload_size).await?;
+    check_upload(&body).await?;
+
+    let normalize = config.normalize_text_encoding;
+    let file =
+        store_upload(&storage, bucket.id(), name.into_inner(), None, normalize, true, body).await?;
+
+    Ok(response.json(file_result(&req, &file)))
+}
@@ -0,0 +1,9 @@
+mod create;
+mod read;
+
+macros_utils::routes! {
+    load create,
+    load read,
+
+    on "/f"
+}
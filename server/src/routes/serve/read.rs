@@ -0,0 +1,274 @@
+use actix_web::http::header::{CACHE_CONTROL, ETAG, HeaderValue};
+use actix_web::web::{Data, Path, Query};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, get};
+use database::{DatabaseError, FileModel};
+use storage::{Storage, StorageError};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::middleware::tenant::Tenant;
+use crate::processing::poster::{POSTER_READY, poster_key, poster_thumbnail_key};
+use crate::processing::thumbnail::{
+    STALE_WHILE_REVALIDATE, latest_thumbnail_key, regenerate, thumbnail_key,
+};
+use crate::utils::app_storage::AppStorage;
+use crate::utils::links::verify_content_tag;
+use crate::utils::not_found::file_by_slug;
+use crate::utils::placeholder::placeholder_svg;
+use crate::utils::serving::{check_tenant, serve_file, serve_file_named};
+
+macros_utils::routes! {
+    route route_get_by_hash,
+    route route_get_placeholder,
+    route route_get_thumbnail,
+    route route_get_poster,
+    route route_get_file,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PlaceholderQuery {
+    v: Option<i64>,
+}
+
+/// Serves a file by the hash of its content, so the response at a
+/// given URL never changes and can be cached forever.
+///
+/// The tag following the hash ties the URL to one file and its current
+/// slug, so rotating the link retires it. It's handed out as
+/// `immutable_url` only when `CONTENT_ADDRESSED_URLS` is set.
+/// The filename is only used to name downloads.
+#[get("/{hash:[0-9a-f]{32,128}}-{tag:[0-9a-f]{16}}/{filename}")]
+pub async fn route_get_by_hash(
+    req: HttpRequest,
+    storage: Data<AppStorage>,
+    path: Path<(String, String, String)>,
+) -> Result<HttpResponse, AppError> {
+    if !Config::get().content_addressed_urls {
+        return Err(DatabaseError::ModelNotFound("file").into());
+    }
+
+    let (hash, tag, filename) = path.into_inner();
+    let owner_id = req.extensions().get::<Tenant>().map(|tenant| tenant.owner_id);
+
+    let file = FileModel::list_by_content_hash(&hash, owner_id)
+        .await?
+        .into_iter()
+        .find(|file| verify_content_tag(file, &tag))
+        .ok_or(DatabaseError::ModelNotFound("file"))?
+        .into_served()
+        .await?;
+    let mut response = serve_file_named(&req, &storage, &file, &filename).await?;
+
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("public, max-age=31536000, immutable"));
+
+    Ok(response)
+}
+
+/// Serves the content of a file, honoring a single `Range`.
+#[get("/{slug}")]
+pub async fn route_get_file(
+    req: HttpRequest,
+    storage: Data<AppStorage>,
+    slug: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let file = file_by_slug(&slug).await?;
+
+    serve_file(&req, &storage, &file).await
+}
+
+/// Answers with an SVG placeholder for a file, from metadata recorded
+/// at upload so nothing is decoded here.
+///
+/// Only URLs keyed on the current version are cached as immutable.
+#[get("/{slug}/placeholder.svg")]
+pub async fn route_get_placeholder(
+    req: HttpRequest,
+    slug: Path<String>,
+    query: Query<PlaceholderQuery>,
+) -> Result<HttpResponse, AppError> {
+    let served = file_by_slug(&slug).await?;
+    check_tenant(&req, &served)?;
+    let file = served.file;
+
+    let cache_control = if query.v == Some(file.version()) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .insert_header((CACHE_CONTROL, cache_control))
+        .insert_header((ETAG, file.version_etag()))
+        .body(placeholder_svg(&file)))
+}
+
+/// Serves the thumbnail stored by the `thumbnail` post processor,
+/// files it didn't run on have none.
+///
+/// When the content changed since, the previous thumbnail is served right
+/// away while the new one is rendered in the background.
+///
+/// Videos with a poster are answered with the scaled down poster.
+#[get("/{slug}/thumbnail.png")]
+pub async fn route_get_thumbnail(
+    req: HttpRequest,
+    storage: Data<AppStorage>,
+    slug: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let served = file_by_slug(&slug).await?;
+    check_tenant(&req, &served)?;
+    let file = served.file;
+
+    let (key, content_type) = if file.poster_state() == Some(POSTER_READY) {
+        (poster_thumbnail_key(&file), "image/jpeg")
+    } else {
+        (thumbnail_key(&file)?, "image/png")
+    };
+
+    let (thumbnail, stale) = match storage.get(&key).await {
+        Err(StorageError::NotFound(_)) if content_type == "image/png" => {
+            let stale = match storage.get(&latest_thumbnail_key(&file)).await {
+                Err(StorageError::NotFound(_)) => {
+                    return Err(DatabaseError::ModelNotFound("thumbnail").into());
+                },
+                result => result?,
+            };
+
+            regenerate(file.clone(), storage.clone());
+
+            (stale, true)
+        },
+        Err(StorageError::NotFound(_)) => {
+            return Err(DatabaseError::ModelNotFound("thumbnail").into());
+        },
+        result => (result?, false),
+    };
+
+    let mut response = HttpResponse::Ok();
+    response.content_type(content_type);
+
+    // A stale thumbnail doesn't match the current version, so it gets no ETag.
+    if stale {
+        response.insert_header((
+            CACHE_CONTROL,
+            format!("public, max-age=0, stale-while-revalidate={STALE_WHILE_REVALIDATE}"),
+        ));
+    } else {
+        response
+            .insert_header((
+                CACHE_CONTROL,
+                format!("public, max-age=300, stale-while-revalidate={STALE_WHILE_REVALIDATE}"),
+            ))
+            .insert_header((ETAG, file.version_etag()));
+    }
+
+    Ok(response.body(thumbnail))
+}
+
+/// Serves the poster frame extracted from a video.
+#[get("/{slug}/poster.jpg")]
+pub async fn route_get_poster(
+    req: HttpRequest,
+    storage: Data<AppStorage>,
+    slug: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let served = file_by_slug(&slug).await?;
+    check_tenant(&req, &served)?;
+    let file = served.file;
+
+    if file.poster_state() != Some(POSTER_READY) {
+        return Err(DatabaseError::ModelNotFound("poster").into());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/jpeg")
+        .insert_header((ETAG, file.version_etag()))
+        .body(storage.get(&poster_key(&file)).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::test::read_body;
+
+    use super::*;
+    use crate::testing::{create_bucket, create_user, get, run, unique_name, upload_file};
+    use crate::utils::app_storage::open_storage;
+    use crate::utils::links::immutable_path;
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET, STORAGE_PATH and CONTENT_ADDRESSED_URLS"]
+    fn hash_urls_serve_the_content_whatever_the_filename() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("hash")).await).await;
+            let content = unique_name("content");
+            let file = upload_file(&storage, &bucket, "notes.txt", content.as_bytes()).await;
+
+            let path = immutable_path(&file).unwrap();
+            assert!(path.ends_with("/notes.txt"));
+
+            let response = get(&storage, &path).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get(CACHE_CONTROL).unwrap(),
+                "public, max-age=31536000, immutable"
+            );
+            assert_eq!(read_body(response).await, content.as_bytes());
+
+            let renamed = format!("{}/renamed.bin", path.rsplit_once('/').unwrap().0);
+            let response = get(&storage, &renamed).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(read_body(response).await, content.as_bytes());
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET, STORAGE_PATH and CONTENT_ADDRESSED_URLS"]
+    fn a_hash_alone_reaches_no_file() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let content = unique_name("content");
+
+            let mine = create_bucket(&create_user(&unique_name("hash")).await).await;
+            let theirs = create_bucket(&create_user(&unique_name("hash")).await).await;
+            let mine = upload_file(&storage, &mine, "a.txt", content.as_bytes()).await;
+            let theirs = upload_file(&storage, &theirs, "a.txt", content.as_bytes()).await;
+
+            // Same content, still a link of its own for each file.
+            assert_eq!(mine.hash(), theirs.hash());
+            assert_ne!(immutable_path(&mine), immutable_path(&theirs));
+
+            let hash = mine.hash().unwrap();
+            let forged = format!("/f/{hash}-{}/a.txt", "0".repeat(16));
+
+            for path in [format!("/f/{hash}/a.txt"), forged] {
+                assert_eq!(get(&storage, &path).await.status(), StatusCode::NOT_FOUND);
+            }
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET, STORAGE_PATH and CONTENT_ADDRESSED_URLS"]
+    fn rotating_the_link_retires_the_hash_url() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("hash")).await).await;
+            let file = upload_file(&storage, &bucket, "leaked.txt", b"leaked").await;
+
+            let old = immutable_path(&file).unwrap();
+            let rotated = file.rotate_slug(false).await.unwrap();
+            let new = immutable_path(&rotated).unwrap();
+
+            assert_ne!(old, new);
+            assert_eq!(get(&storage, &old).await.status(), StatusCode::NOT_FOUND);
+
+            let response = get(&storage, &new).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(read_body(response).await, "leaked");
+        })
+    }
+}
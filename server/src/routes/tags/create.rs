@@ -0,0 +1,48 @@
+use actix_web::web::{Json, Path};
+use actix_web::{Responder, post};
+use database::{DatabaseError, FileTagModel, UserModel};
+use log::info;
+use serde::Serialize;
+
+use crate::AppError;
+use crate::utils::tags::normalize_tag;
+
+macros_utils::routes! {
+    route route_rename,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RenameRequest {
+    to: String,
+}
+
+#[derive(Serialize)]
+pub struct TagChange {
+    pub files: u64,
+}
+
+/// Renames a tag on every file of the user, when the new name is
+/// already in use both tags are merged into one.
+#[post("/{name}/rename")]
+pub async fn route_rename(
+    user: UserModel,
+    name: Path<String>,
+    request: Json<RenameRequest>,
+) -> Result<impl Responder, AppError> {
+    let from = normalize_tag(&name)?;
+    let to = normalize_tag(&request.to)?;
+
+    if from == to {
+        return Ok(Json(TagChange { files: 0 }));
+    }
+
+    let files = FileTagModel::rename(user.id(), &from, &to).await?;
+
+    if files == 0 {
+        return Err(DatabaseError::ModelNotFound("tag").into());
+    }
+
+    info!("User {} renamed tag {from} to {to} on {files} files", user.id());
+
+    Ok(Json(TagChange { files }))
+}
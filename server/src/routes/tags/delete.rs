@@ -0,0 +1,27 @@
+use actix_web::web::{Json, Path};
+use actix_web::{Responder, delete};
+use database::{DatabaseError, FileTagModel, UserModel};
+use log::info;
+
+use super::create::TagChange;
+use crate::AppError;
+use crate::utils::tags::normalize_tag;
+
+macros_utils::routes! {
+    route route_delete,
+}
+
+/// Removes a tag from every file of the user.
+#[delete("/{name}")]
+pub async fn route_delete(user: UserModel, name: Path<String>) -> Result<impl Responder, AppError> {
+    let tag = normalize_tag(&name)?;
+    let files = FileTagModel::delete_for_owner(user.id(), &tag).await?;
+
+    if files == 0 {
+        return Err(DatabaseError::ModelNotFound("tag").into());
+    }
+
+    info!("User {} deleted tag {tag} from {files} files", user.id());
+
+    Ok(Json(TagChange { files }))
+}
@@ -0,0 +1,11 @@
+mod create;
+mod delete;
+mod read;
+
+macros_utils::routes! {
+    load create,
+    load delete,
+    load read,
+
+    on "/tags"
+}
@@ -0,0 +1,15 @@
+use actix_web::web::Json;
+use actix_web::{Responder, get};
+use database::{FileTagModel, UserModel};
+
+use crate::AppError;
+
+macros_utils::routes! {
+    route route_list,
+}
+
+/// Lists the tags of the user along with how many files carry each.
+#[get("")]
+pub async fn route_list(user: UserModel) -> Result<impl Responder, AppError> {
+    Ok(Json(FileTagModel::list_for_owner(user.id()).await?))
+}
@@ -0,0 +1,57 @@
+use actix_web::web::Json;
+use actix_web::{Responder, post};
+use database::{BucketModel, UserModel, UserWebhookCreation, UserWebhookModel};
+
+use crate::AppError;
+use crate::utils::tags::normalize_tag;
+use crate::utils::webhooks::{MAX_WEBHOOKS, WebhookEvent, resolve_target};
+
+macros_utils::routes! {
+    route route_create,
+}
+
+/// Adds a webhook the events of the caller's files are delivered to, at
+/// most `MAX_WEBHOOKS` each. Targets resolving to anything but public
+/// addresses are refused, they're checked again on every delivery.
+#[post("")]
+pub async fn route_create(
+    user: UserModel,
+    creation: Json<UserWebhookCreation>,
+) -> Result<impl Responder, AppError> {
+    let mut creation = creation.into_inner();
+
+    resolve_target(&creation.url).await?;
+    creation.events = check_events(&creation.events)?;
+    creation.tag = creation.tag.as_deref().map(normalize_tag).transpose()?;
+    creation.secret = creation.secret.filter(|secret| !secret.is_empty());
+
+    if let Some(bucket_id) = creation.bucket_id {
+        BucketModel::get_owned(bucket_id, user.id()).await?;
+    }
+
+    let webhook = UserWebhookModel::create(user.id(), &creation, MAX_WEBHOOKS)
+        .await?
+        .ok_or(AppError::TooManyWebhooks(MAX_WEBHOOKS))?;
+
+    Ok(Json(webhook.into_result()))
+}
+
+/// The events subscribed to without duplicates, at least one known event.
+pub fn check_events(events: &[String]) -> Result<Vec<String>, AppError> {
+    let mut checked = Vec::new();
+
+    for event in events {
+        let event = WebhookEvent::parse(event)
+            .ok_or_else(|| AppError::InvalidWebhookEvent(event.clone()))?;
+
+        if !checked.contains(&event.as_str().to_owned()) {
+            checked.push(event.as_str().to_owned());
+        }
+    }
+
+    if checked.is_empty() {
+        return Err(AppError::InvalidWebhookEvent(String::new()));
+    }
+
+    Ok(checked)
+}
@@ -0,0 +1,17 @@
+use actix_web::web::Path;
+use actix_web::{HttpResponse, delete};
+use database::{UserModel, UserWebhookModel};
+
+use crate::AppError;
+
+macros_utils::routes! {
+    route route_delete,
+}
+
+/// Deletes an owned webhook along with its delivery history.
+#[delete("/{id}")]
+pub async fn route_delete(user: UserModel, id: Path<i64>) -> Result<HttpResponse, AppError> {
+    UserWebhookModel::get_owned(*id, user.id()).await?.delete().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
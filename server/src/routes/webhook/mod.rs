@@ -0,0 +1,13 @@
+mod create;
+mod delete;
+mod read;
+mod update;
+
+macros_utils::routes! {
+    load create,
+    load delete,
+    load read,
+    load update,
+
+    on "/users/me/webhooks"
+}
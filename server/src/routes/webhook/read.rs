@@ -0,0 +1,26 @@
+use actix_web::web::{Json, Path};
+use actix_web::{Responder, get};
+use database::{UserModel, UserWebhookModel};
+
+use crate::AppError;
+
+macros_utils::routes! {
+    route route_list,
+    route route_deliveries,
+}
+
+/// Lists the webhooks of the caller, disabled ones with the reason why.
+#[get("")]
+pub async fn route_list(user: UserModel) -> Result<impl Responder, AppError> {
+    let webhooks = UserWebhookModel::list_owned(user.id()).await?;
+
+    Ok(Json(webhooks.iter().map(UserWebhookModel::into_result).collect::<Vec<_>>()))
+}
+
+/// The latest delivery attempts of an owned webhook, newest first.
+#[get("/{id}/deliveries")]
+pub async fn route_deliveries(user: UserModel, id: Path<i64>) -> Result<impl Responder, AppError> {
+    let webhook = UserWebhookModel::get_owned(*id, user.id()).await?;
+
+    Ok(Json(UserWebhookModel::list_deliveries(webhook.id()).await?))
+}
@@ -0,0 +1,33 @@
+use actix_web::web::{Json, Path};
+use actix_web::{Responder, patch};
+use database::{UserModel, UserWebhookModel, UserWebhookUpdate};
+
+use super::create::check_events;
+use crate::AppError;
+use crate::utils::webhooks::resolve_target;
+
+macros_utils::routes! {
+    route route_edit,
+}
+
+/// Changes the target, secret, events or format of an owned webhook,
+/// enabling a disabled one gives it a fresh start.
+#[patch("/{id}")]
+pub async fn route_edit(
+    user: UserModel,
+    id: Path<i64>,
+    update: Json<UserWebhookUpdate>,
+) -> Result<impl Responder, AppError> {
+    let webhook = UserWebhookModel::get_owned(*id, user.id()).await?;
+    let mut update = update.into_inner();
+
+    if let Some(url) = &update.url {
+        resolve_target(url).await?;
+    }
+
+    if let Some(events) = &update.events {
+        update.events = Some(check_events(events)?);
+    }
+
+    Ok(Json(webhook.edit(&update).await?.into_result()))
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::rt::time::interval;
+use database::TrafficTotals;
+use log::warn;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::traffic::Traffic;
+use crate::utils::upload_limit::UploadLimiter;
+
+/// What makes the traffic of a client abusive between two checks.
+struct Thresholds {
+    wasted_percent: u32,
+    min_wasted_bytes: u64,
+    aborted_uploads: i64,
+}
+
+impl Thresholds {
+    fn configured() -> Self {
+        let config = Config::get();
+
+        Thresholds {
+            wasted_percent: config.abuse_wasted_percent,
+            min_wasted_bytes: config.abuse_min_wasted_bytes,
+            aborted_uploads: config.abuse_aborted_uploads,
+        }
+    }
+
+    /// Why `totals` are abusive, `None` when they aren't.
+    fn reason(&self, totals: &TrafficTotals) -> Option<String> {
+        if self.aborted_uploads > 0 && totals.aborted_uploads >= self.aborted_uploads {
+            return Some(format!("{} uploads aborted", totals.aborted_uploads));
+        }
+
+        let exchanged = totals.bytes_in + totals.bytes_out;
+
+        let wasteful = self.wasted_percent > 0
+            && totals.wasted_bytes as u64 >= self.min_wasted_bytes
+            && totals.wasted_bytes * 100 >= exchanged * i64::from(self.wasted_percent);
+
+        wasteful.then(|| format!("{} of {exchanged} bytes wasted", totals.wasted_bytes))
+    }
+}
+
+/// Checks the traffic of every client forever, every `ABUSE_CHECK_INTERVAL`
+/// seconds, over what they exchanged since the previous check.
+pub async fn run_abuse_detection() -> Result<(), AppError> {
+    let config = Config::get();
+    let mut interval = interval(Duration::from_secs(config.abuse_check_interval.max(1)));
+
+    // The first tick is immediate, it only starts the first window.
+    interval.tick().await;
+    Traffic::get().take_windows();
+
+    loop {
+        interval.tick().await;
+
+        check(
+            Traffic::get().take_windows(),
+            &Thresholds::configured(),
+            config.abuse_rate_limit,
+            Duration::from_secs(config.abuse_cooldown),
+        );
+    }
+}
+
+/// Logs every client of `windows` found abusive, their uploads are held to
+/// `rate_limit` for `cooldown` unless it's `0`. Returns the clients found.
+fn check(
+    windows: HashMap<String, TrafficTotals>,
+    thresholds: &Thresholds,
+    rate_limit: u32,
+    cooldown: Duration,
+) -> Vec<String> {
+    let mut found = Vec::new();
+
+    for (client, totals) in windows {
+        let Some(reason) = thresholds.reason(&totals) else {
+            continue;
+        };
+
+        if rate_limit > 0 {
+            UploadLimiter::get().tighten(&client, cooldown);
+            warn!("Client {client} looks abusive, {reason}, its uploads are limited for a while");
+        } else {
+            warn!("Client {client} looks abusive, {reason}");
+        }
+
+        found.push(client);
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::thread::sleep;
+
+    use super::*;
+    use crate::utils::upload_limit::upload_key;
+
+    const THRESHOLDS: Thresholds = Thresholds {
+        wasted_percent: 50,
+        min_wasted_bytes: 1000,
+        aborted_uploads: 3,
+    };
+
+    fn client() -> String {
+        upload_key(None, Some(IpAddr::V4(Ipv4Addr::from(rand::random::<u32>()))))
+    }
+
+    fn totals(bytes_in: i64, wasted_bytes: i64, aborted_uploads: i64) -> TrafficTotals {
+        TrafficTotals {
+            requests: 10,
+            bytes_in,
+            bytes_out: 0,
+            wasted_bytes,
+            aborted_uploads,
+        }
+    }
+
+    #[test]
+    fn clients_past_a_threshold_are_abusive() {
+        assert!(THRESHOLDS.reason(&totals(4000, 1000, 0)).is_none());
+        assert!(THRESHOLDS.reason(&totals(2000, 1000, 0)).is_some());
+        // Too few bytes to tell, whatever their share.
+        assert!(THRESHOLDS.reason(&totals(900, 900, 0)).is_none());
+        assert!(THRESHOLDS.reason(&totals(100, 0, 2)).is_none());
+        assert_eq!(THRESHOLDS.reason(&totals(100, 0, 3)).unwrap(), "3 uploads aborted");
+    }
+
+    #[test]
+    #[ignore = "needs SIGNING_SECRET"]
+    fn abusive_clients_are_limited_until_their_cooldown_is_over() {
+        let (abusive, calm) = (client(), client());
+        let windows = HashMap::from([
+            (abusive.clone(), totals(5000, 4000, 0)),
+            (calm.clone(), totals(5000, 900, 2)),
+        ]);
+
+        let found = check(windows, &THRESHOLDS, 2, Duration::from_millis(200));
+        assert_eq!(found, vec![abusive.clone()]);
+
+        let limiter = UploadLimiter::get();
+        assert!(limiter.tightened_until(&calm).is_none());
+        assert!(limiter.tightened_until(&abusive).is_some());
+
+        // One upload at a time, the next one has to wait.
+        assert!(limiter.acquire(&abusive).is_ok());
+        assert!(limiter.acquire(&abusive).is_err());
+        assert!(limiter.acquire(&calm).is_ok());
+
+        sleep(Duration::from_millis(250));
+        assert!(limiter.tightened_until(&abusive).is_none());
+        assert!(limiter.acquire(&abusive).is_ok());
+    }
+}
@@ -0,0 +1,167 @@
+use std::future::Future;
+use std::io::Error as IoError;
+use std::time::Duration;
+
+use actix_web::rt::time::interval;
+use database::{DatabaseError, TenantDomainModel};
+use hickory_resolver::TokioAsyncResolver;
+use log::{info, warn};
+
+use crate::AppError;
+use crate::config::Config;
+
+/// Looks up the TXT records challenges are answered with.
+pub trait TxtResolver {
+    /// The TXT records at `name`, empty when it has none or can't be resolved.
+    fn txt_records(&self, name: &str) -> impl Future<Output = Vec<String>> + Send;
+}
+
+impl TxtResolver for TokioAsyncResolver {
+    async fn txt_records(&self, name: &str) -> Vec<String> {
+        match self.txt_lookup(name).await {
+            Ok(records) => records.iter().map(ToString::to_string).collect(),
+            // Missing records are the usual case while the owner sets them up.
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Checks pending custom domains forever, every
+/// `DOMAIN_VERIFICATION_INTERVAL` seconds.
+///
+/// A domain is verified once `_cdn-challenge.<hostname>` has
+/// a TXT record holding its verification token.
+pub async fn run_domain_verification() -> Result<(), AppError> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(IoError::other)?;
+
+    let mut interval =
+        interval(Duration::from_secs(Config::get().domain_verification_interval.max(1)));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = verify_pending(&resolver).await {
+            warn!("Custom domain verification failed: {error}");
+        }
+    }
+}
+
+/// Verifies the pending claims whose challenge passed, the first claim
+/// of a hostname to pass wins and the claims of others are dropped.
+async fn verify_pending(resolver: &impl TxtResolver) -> Result<(), AppError> {
+    for domain in TenantDomainModel::list_unverified().await? {
+        let challenge = format!("_cdn-challenge.{}.", domain.hostname());
+        let records = resolver.txt_records(&challenge).await;
+
+        if !records.iter().any(|record| record == domain.verification_token()) {
+            continue;
+        }
+
+        match domain.verify().await {
+            Ok(_) => info!("Verified custom domain {}", domain.hostname()),
+            // Dropped along with the other claims when an earlier one won.
+            Err(DatabaseError::HostnameTaken | DatabaseError::ModelNotFound(_)) => {},
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::testing::{create_user, run, unique_name};
+
+    /// Answers with the records set by the test.
+    #[derive(Default)]
+    struct MockResolver(Mutex<HashMap<String, Vec<String>>>);
+
+    impl MockResolver {
+        fn publish(&self, hostname: &str, token: &str) {
+            let mut records = self.0.lock().unwrap();
+            records.entry(format!("_cdn-challenge.{hostname}.")).or_default().push(token.into());
+        }
+    }
+
+    impl TxtResolver for MockResolver {
+        async fn txt_records(&self, name: &str) -> Vec<String> {
+            self.0.lock().unwrap().get(name).cloned().unwrap_or_default()
+        }
+    }
+
+    async fn claim(owner_id: i64, hostname: &str) -> Result<TenantDomainModel, DatabaseError> {
+        TenantDomainModel::create(owner_id, hostname, None).await
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn the_first_claim_to_pass_its_challenge_wins() {
+        run(async {
+            let squatter = create_user(&unique_name("squatter")).await;
+            let owner = create_user(&unique_name("owner")).await;
+            let hostname = format!("{}.example.com", unique_name("files"));
+            let resolver = MockResolver::default();
+
+            // Claiming first doesn't keep the real owner from claiming it.
+            let squatted = claim(squatter.id(), &hostname).await.unwrap();
+            let claimed = claim(owner.id(), &hostname).await.unwrap();
+
+            verify_pending(&resolver).await.unwrap();
+            assert!(TenantDomainModel::get_verified(&hostname).await.is_err());
+
+            // A record holding another token verifies nothing.
+            resolver.publish(&hostname, "not-a-token");
+            verify_pending(&resolver).await.unwrap();
+            assert!(TenantDomainModel::get_verified(&hostname).await.is_err());
+
+            resolver.publish(&hostname, claimed.verification_token());
+            verify_pending(&resolver).await.unwrap();
+
+            let verified = TenantDomainModel::get_verified(&hostname).await.unwrap();
+            assert_eq!(verified.owner_id(), owner.id());
+
+            // The competing claim is dropped and can't be made again.
+            let dropped = TenantDomainModel::get_claim(&hostname, squatter.id()).await;
+            assert!(matches!(dropped, Err(DatabaseError::ModelNotFound(_))));
+            let refused = claim(squatter.id(), &hostname).await;
+            assert!(matches!(refused, Err(DatabaseError::HostnameTaken)));
+
+            // Even with its token published the old claim can't win anymore.
+            resolver.publish(&hostname, squatted.verification_token());
+            verify_pending(&resolver).await.unwrap();
+            assert_eq!(
+                TenantDomainModel::get_verified(&hostname).await.unwrap().owner_id(),
+                owner.id()
+            );
+
+            // Claiming it again is harmless for the owner.
+            let reclaimed = claim(owner.id(), &hostname).await.unwrap();
+            assert_eq!(reclaimed.verification_token(), claimed.verification_token());
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn claims_verified_together_have_a_single_winner() {
+        run(async {
+            let first = create_user(&unique_name("first")).await;
+            let second = create_user(&unique_name("second")).await;
+            let hostname = format!("{}.example.com", unique_name("race"));
+
+            let a = claim(first.id(), &hostname).await.unwrap();
+            let b = claim(second.id(), &hostname).await.unwrap();
+
+            let (a, b) = futures::join!(a.verify(), b.verify());
+            assert!(a.is_ok() != b.is_ok());
+
+            let winner = TenantDomainModel::get_verified(&hostname).await.unwrap();
+            let loser = if a.is_ok() { second.id() } else { first.id() };
+            assert_ne!(winner.owner_id(), loser);
+            assert!(TenantDomainModel::get_claim(&hostname, loser).await.is_err());
+        })
+    }
+}
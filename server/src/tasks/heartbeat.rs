@@ -0,0 +1,49 @@
+use std::process;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use actix_web::rt::time::interval;
+use database::InstanceHeartbeatModel;
+use database::schema_compat::{load_phases, schema_version};
+use log::warn;
+
+use super::jobs::interrupt_abandoned_jobs;
+use crate::AppError;
+
+/// Well under `HEARTBEAT_TIMEOUT`, a missed beat or two doesn't drop the instance.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Identifies this process among the instances sharing the database.
+pub fn instance_id() -> &'static str {
+    static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+    INSTANCE_ID.get_or_init(|| {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_owned());
+        format!("{host}-{}-{:08x}", process::id(), rand::random::<u32>())
+    })
+}
+
+/// Reports the schema version this instance was built with, picks
+/// up migration phase advances and gives up on the jobs of instances
+/// that stopped reporting forever, every `HEARTBEAT_INTERVAL`.
+pub async fn run_heartbeat() -> Result<(), AppError> {
+    let mut interval = interval(HEARTBEAT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Some(version) = schema_version()
+            && let Err(error) = InstanceHeartbeatModel::beat(instance_id(), version).await
+        {
+            warn!("Instance heartbeat failed: {error}");
+        }
+
+        if let Err(error) = load_phases().await {
+            warn!("Reloading migration phases failed: {error}");
+        }
+
+        if let Err(error) = interrupt_abandoned_jobs().await {
+            warn!("Checking for abandoned jobs failed: {error}");
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::future::Future;
+
+use actix_web::rt::spawn;
+use database::{JOB_CANCELLED, JOB_FAILED, JOB_SUCCEEDED, JobModel};
+use log::{error, info, warn};
+use serde_json::Value;
+
+use super::heartbeat::instance_id;
+use crate::AppError;
+
+/// Handed to a tracked job to report how far it got.
+pub struct JobProgress {
+    id: i64,
+}
+
+impl JobProgress {
+    /// Records the progress of the job, fails with `JobCancelled` once it
+    /// was asked to stop so the job can bail out with `?`.
+    pub async fn update(&self, done: i64, total: Option<i64>) -> Result<(), AppError> {
+        if JobModel::set_progress(self.id, done, total).await? {
+            return Err(AppError::JobCancelled);
+        }
+
+        Ok(())
+    }
+}
+
+/// Records a job of `kind` and runs it in the background, its outcome
+/// is stored as its result or its error once it's done.
+pub async fn spawn_tracked<F, Fut>(
+    kind: &str,
+    parameters: Value,
+    job: F,
+) -> Result<JobModel, AppError>
+where
+    F: FnOnce(JobProgress) -> Fut + 'static,
+    Fut: Future<Output = Result<Value, AppError>> + 'static,
+{
+    let record = JobModel::start(kind, parameters, instance_id()).await?;
+    let id = record.id();
+    let kind = kind.to_owned();
+
+    spawn(async move {
+        let (state, result, error) = match job(JobProgress { id }).await {
+            Ok(result) => (JOB_SUCCEEDED, Some(result), None),
+            Err(AppError::JobCancelled) => (JOB_CANCELLED, None, None),
+            Err(error) => (JOB_FAILED, None, Some(error.to_string())),
+        };
+
+        info!("Job {id} ({kind}) finished as {state}");
+
+        if let Err(error) = JobModel::finish(id, state, result, error).await {
+            error!("Couldn't record the outcome of job {id}: {error}");
+        }
+    });
+
+    Ok(record)
+}
+
+/// Marks the jobs of instances that stopped heartbeating as interrupted.
+pub async fn interrupt_abandoned_jobs() -> Result<(), AppError> {
+    for job in JobModel::mark_interrupted().await? {
+        warn!("Job {} ({}) was interrupted, its instance stopped", job.id(), job.kind());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,9 @@
+pub mod abuse;
+pub mod domain_verification;
+pub mod heartbeat;
+pub mod jobs;
+pub mod multipart;
+pub mod orphans;
+pub mod stats;
+pub mod storage_summary;
+pub mod supervisor;
@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+
+use chrono::{TimeDelta, Utc};
+use database::MultipartUploadModel;
+use log::{info, warn};
+use serde::Serialize;
+use storage::MultipartStorage;
+
+use crate::AppError;
+use crate::config::Config;
+
+/// What a reconciliation found and did.
+#[derive(Default, Serialize)]
+pub struct MultipartReport {
+    /// Tracked uploads older than `MULTIPART_UPLOAD_TTL` that were aborted.
+    pub aborted: Vec<String>,
+    /// Uploads the backend holds that we never tracked, aborted as well
+    /// since nothing could ever resume them.
+    pub untracked: Vec<String>,
+    /// Tracked uploads the backend doesn't know about, forgotten.
+    pub missing: Vec<String>,
+    /// Uploads whose abort failed, retried on the next run.
+    pub failed: Vec<String>,
+}
+
+/// Compares the tracked multipart uploads with the ones the backend holds,
+/// aborting the stale and untracked ones so their parts stop being billed.
+///
+/// Recent tracked uploads are left alone, a retried write resumes them.
+pub async fn reconcile_multipart<S: MultipartStorage>(
+    storage: &S,
+) -> Result<MultipartReport, AppError> {
+    let since = Utc::now().naive_utc() - TimeDelta::seconds(Config::get().multipart_upload_ttl);
+
+    let tracked = MultipartUploadModel::list().await?;
+    let remote = storage.list_multipart_uploads().await?;
+
+    let tracked_ids = tracked.iter().map(|upload| upload.upload_id()).collect::<HashSet<_>>();
+    let remote_ids = remote.iter().map(|upload| upload.upload_id.as_str()).collect::<HashSet<_>>();
+
+    let mut report = MultipartReport::default();
+
+    for upload in &tracked {
+        if !remote_ids.contains(upload.upload_id()) {
+            upload.finish().await?;
+            report.missing.push(upload.upload_id().to_owned());
+            continue;
+        }
+
+        if upload.started_at() > since {
+            continue;
+        }
+
+        match storage.abort_multipart(upload.blob_key(), upload.upload_id()).await {
+            Ok(()) => {
+                upload.finish().await?;
+                report.aborted.push(upload.upload_id().to_owned());
+            },
+            Err(error) => {
+                warn!(
+                    "Couldn't abort multipart upload {} after {} attempts: {error}",
+                    upload.upload_id(),
+                    upload.abort_attempts() + 1
+                );
+                upload.record_abort_failure(&error.to_string()).await?;
+                report.failed.push(upload.upload_id().to_owned());
+            },
+        }
+    }
+
+    for upload in remote.iter().filter(|upload| !tracked_ids.contains(upload.upload_id.as_str())) {
+        match storage.abort_multipart(&upload.key, &upload.upload_id).await {
+            Ok(()) => report.untracked.push(upload.upload_id.clone()),
+            Err(error) => {
+                warn!("Couldn't abort untracked multipart upload {}: {error}", upload.upload_id);
+                report.failed.push(upload.upload_id.clone());
+            },
+        }
+    }
+
+    info!(
+        "Multipart reconciliation aborted {} stale and {} untracked uploads, forgot {} missing",
+        report.aborted.len(),
+        report.untracked.len(),
+        report.missing.len()
+    );
+
+    Ok(report)
+}
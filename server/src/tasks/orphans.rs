@@ -0,0 +1,151 @@
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+
+use database::{BlobModel, DatabaseError};
+use log::info;
+use serde::Serialize;
+use storage::{LocalStorage, Storage, StorageError};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::hashing::HashAlgorithm;
+use crate::utils::stat_cache::StatCache;
+
+/// Hashes looked up at once when checking for references.
+const BATCH_SIZE: usize = 1000;
+
+/// What a purge found and did.
+#[derive(Default, Serialize)]
+pub struct OrphanReport {
+    /// Blobs looked at, recent ones included.
+    pub scanned: usize,
+    /// Unreferenced blobs that were, or with `dry_run` would be, removed.
+    pub removed: usize,
+    pub reclaimed_bytes: u64,
+}
+
+/// Removes the blobs no file references anymore, as left behind by
+/// uploads that crashed between storing the blob and recording the file.
+///
+/// Blobs younger than `ORPHAN_GRACE_PERIOD` are kept, they may belong
+/// to an upload still in flight.
+pub async fn purge_orphaned_blobs(
+    storage: &LocalStorage,
+    dry_run: bool,
+) -> Result<OrphanReport, AppError> {
+    let grace = Duration::from_secs(Config::get().orphan_grace_period);
+
+    let report = purge(storage, dry_run, grace, |algorithm, hashes| async move {
+        BlobModel::referenced(algorithm, &hashes).await
+    })
+    .await?;
+
+    info!(
+        "Orphaned blobs: {} of {} {}, {} bytes",
+        report.removed,
+        report.scanned,
+        if dry_run { "would be removed" } else { "removed" },
+        report.reclaimed_bytes
+    );
+
+    Ok(report)
+}
+
+/// The purge itself, `referenced` picks which of the hashes
+/// of an algorithm some file still points at.
+async fn purge<F, R>(
+    storage: &LocalStorage,
+    dry_run: bool,
+    grace: Duration,
+    referenced: F,
+) -> Result<OrphanReport, AppError>
+where
+    F: Fn(&'static str, Vec<String>) -> R,
+    R: Future<Output = Result<Vec<String>, DatabaseError>>,
+{
+    let mut report = OrphanReport::default();
+    let is_old = |modified_at: SystemTime| {
+        SystemTime::now().duration_since(modified_at).is_ok_and(|age| age > grace)
+    };
+
+    for algorithm in HashAlgorithm::ALL {
+        let blobs = storage.list(algorithm.name()).await?;
+        report.scanned += blobs.len();
+
+        let candidates =
+            blobs.into_iter().filter(|blob| is_old(blob.modified_at)).collect::<Vec<_>>();
+
+        for batch in candidates.chunks(BATCH_SIZE) {
+            let hashes = batch
+                .iter()
+                .filter_map(|blob| blob.key.rsplit('/').next().map(str::to_owned))
+                .collect::<Vec<_>>();
+
+            let referenced = referenced(algorithm.name(), hashes).await?;
+
+            for blob in batch {
+                let hash = blob.key.rsplit('/').next().unwrap_or_default();
+
+                if referenced.iter().any(|referenced| referenced == hash) {
+                    continue;
+                }
+
+                // An upload deduplicated against the blob since it was listed
+                // touched it, its file may be recorded by now.
+                match storage.modified_at(&blob.key).await {
+                    Ok(modified_at) if is_old(modified_at) => {},
+                    Ok(_) | Err(StorageError::NotFound(_)) => continue,
+                    Err(error) => return Err(error.into()),
+                }
+
+                if !dry_run {
+                    storage.delete(&blob.key).await?;
+                    StatCache::get().forget(&blob.key);
+                }
+
+                report.removed += 1;
+                report.reclaimed_bytes += blob.size;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs::File;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn only_unreferenced_blobs_are_reclaimed() {
+        let root = temp_dir().join(format!("orphans-{:016x}", rand::random::<u64>()));
+        let storage = LocalStorage::new(&root).await.unwrap();
+        let old = SystemTime::now() - Duration::from_secs(3600);
+
+        for hash in ["orphan", "kept", "touched"] {
+            let key = format!("sha256/{hash}");
+            storage.put(&key, hash.as_bytes()).await.unwrap();
+            File::options().write(true).open(root.join(&key)).unwrap().set_modified(old).unwrap();
+        }
+
+        // Deduplicating against a blob restarts its grace period.
+        assert!(!storage.put_if_absent("sha256/touched", b"touched").await.unwrap());
+
+        let report = purge(&storage, false, Duration::from_secs(60), |_, hashes| async move {
+            Ok(hashes.into_iter().filter(|hash| hash == "kept").collect())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.reclaimed_bytes, 6);
+        assert!(!storage.exists("sha256/orphan").await.unwrap());
+        assert!(storage.exists("sha256/kept").await.unwrap());
+        assert!(storage.exists("sha256/touched").await.unwrap());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
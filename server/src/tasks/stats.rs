@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use actix_web::rt::time::interval;
+use log::warn;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::traffic::Traffic;
+use crate::utils::view_stats::ViewStats;
+
+/// Writes the buffered views and traffic forever, every `STATS_FLUSH_INTERVAL` seconds.
+pub async fn run_stats_flush() -> Result<(), AppError> {
+    let mut interval = interval(Duration::from_secs(Config::get().stats_flush_interval.max(1)));
+
+    loop {
+        interval.tick().await;
+        flush_stats().await;
+    }
+}
+
+/// Writes what's buffered so far, what can't be written is logged and dropped.
+pub async fn flush_stats() {
+    if let Err(error) = ViewStats::get().flush().await {
+        warn!("Couldn't write the buffered views: {error}");
+    }
+
+    if let Err(error) = Traffic::get().flush().await {
+        warn!("Couldn't write the buffered traffic: {error}");
+    }
+}
@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use actix_web::rt::time::interval;
+use database::StorageModel;
+use log::warn;
+
+use crate::AppError;
+use crate::config::Config;
+
+/// Refreshes the per user storage summary forever,
+/// every `STORAGE_SUMMARY_INTERVAL` seconds.
+pub async fn run_storage_summary_refresh() -> Result<(), AppError> {
+    let mut interval = interval(Duration::from_secs(Config::get().storage_summary_interval.max(1)));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = StorageModel::refresh_summary().await {
+            warn!("Storage summary refresh failed: {error}");
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, set_hook, take_hook};
+use std::pin::pin;
+use std::sync::{Arc, Mutex, Once, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::rt::task::JoinHandle;
+use actix_web::rt::time::sleep;
+use futures::FutureExt;
+use futures::future::{Either, select};
+use log::{error, warn};
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::AppError;
+use crate::config::Config;
+
+static SUPERVISOR: OnceLock<Supervisor> = OnceLock::new();
+static PANIC_HOOK: Once = Once::new();
+
+thread_local! {
+    /// Backtrace of the last panic on this thread, captured by the panic hook
+    /// since `catch_unwind` only hands back the payload.
+    static LAST_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Delay before the first restart, doubled on every successive failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A task that ran at least this long before failing
+/// starts over from the initial backoff.
+const RAPID_FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Running,
+    BackingOff,
+    /// Gave up after too many rapid failures, waits for a manual restart.
+    Failed,
+    /// Returned without an error, which loops never do on their own.
+    Finished,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TaskState {
+    status: TaskStatus,
+    restarts: u64,
+    last_error: Option<String>,
+    #[serde(skip)]
+    restart: Arc<Notify>,
+}
+
+/// Every supervised task and its state, shared with the admin routes.
+#[derive(Default)]
+pub struct Supervisor {
+    tasks: Mutex<BTreeMap<&'static str, TaskState>>,
+}
+
+impl Supervisor {
+    pub fn get() -> &'static Self {
+        SUPERVISOR.get_or_init(Supervisor::default)
+    }
+
+    pub fn tasks(&self) -> BTreeMap<&'static str, TaskState> {
+        self.tasks.lock().unwrap_or_else(|error| error.into_inner()).clone()
+    }
+
+    /// Whether every task is still being kept alive.
+    pub fn healthy(&self) -> bool {
+        self.tasks().values().all(|task| task.status != TaskStatus::Failed)
+    }
+
+    /// Restarts a failed or backing off task right away, returns
+    /// `false` when no task has that name.
+    pub fn restart(&self, name: &str) -> bool {
+        let tasks = self.tasks.lock().unwrap_or_else(|error| error.into_inner());
+
+        match tasks.get(name) {
+            Some(task) => {
+                task.restart.notify_one();
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn update(&self, name: &'static str, update: impl FnOnce(&mut TaskState)) {
+        let mut tasks = self.tasks.lock().unwrap_or_else(|error| error.into_inner());
+
+        if let Some(task) = tasks.get_mut(name) {
+            update(task);
+        }
+    }
+}
+
+/// Spawns the task built by `factory` and spawns it again whenever
+/// it panics or returns an error, waiting longer after every failure.
+///
+/// After `TASK_MAX_FAILURES` rapid failures in a row the task is marked
+/// failed, which the readiness route reports, until restarted manually.
+pub fn supervised_task<F, Fut>(name: &'static str, factory: F) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = Result<(), AppError>> + 'static,
+{
+    install_panic_hook();
+
+    let restart = Arc::new(Notify::new());
+    let supervisor = Supervisor::get();
+
+    supervisor.tasks.lock().unwrap_or_else(|error| error.into_inner()).insert(
+        name,
+        TaskState {
+            status: TaskStatus::Running,
+            restarts: 0,
+            last_error: None,
+            restart: restart.clone(),
+        },
+    );
+
+    actix_web::rt::spawn(async move {
+        let config = Config::get();
+        let mut failures = 0;
+
+        loop {
+            supervisor.update(name, |task| task.status = TaskStatus::Running);
+
+            let started = Instant::now();
+
+            let reason = match AssertUnwindSafe(factory()).catch_unwind().await {
+                Ok(Ok(())) => {
+                    supervisor.update(name, |task| task.status = TaskStatus::Finished);
+                    return;
+                },
+                Ok(Err(error)) => {
+                    error!("Task {name} failed: {error}");
+                    error.to_string()
+                },
+                Err(payload) => {
+                    let message = payload
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".into());
+
+                    let backtrace = LAST_BACKTRACE.with(|backtrace| backtrace.borrow_mut().take());
+                    error!(
+                        "Task {name} panicked: {message}\n{}",
+                        backtrace.map(|backtrace| backtrace.to_string()).unwrap_or_default()
+                    );
+
+                    format!("panicked: {message}")
+                },
+            };
+
+            if started.elapsed() >= RAPID_FAILURE_WINDOW {
+                failures = 0;
+            }
+
+            failures += 1;
+
+            supervisor.update(name, |task| {
+                task.restarts += 1;
+                task.last_error = Some(reason);
+            });
+
+            if failures >= config.task_max_failures {
+                warn!("Task {name} failed {failures} times in a row, waiting for a manual restart");
+                supervisor.update(name, |task| task.status = TaskStatus::Failed);
+                restart.notified().await;
+                failures = 0;
+                continue;
+            }
+
+            let backoff = backoff(failures, Duration::from_secs(config.task_backoff_max));
+            warn!("Restarting task {name} in {backoff:?}");
+            supervisor.update(name, |task| task.status = TaskStatus::BackingOff);
+
+            let sleeping = pin!(sleep(backoff));
+            let notified = pin!(restart.notified());
+            let woken = select(sleeping, notified).await;
+
+            if let Either::Right(_) = woken {
+                failures = 0;
+            }
+        }
+    })
+}
+
+/// Exponential backoff after `failures` successive failures, capped at `max`.
+fn backoff(failures: u32, max: Duration) -> Duration {
+    INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1))).min(max)
+}
+
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous = take_hook();
+
+        set_hook(Box::new(move |info| {
+            LAST_BACKTRACE
+                .with(|backtrace| *backtrace.borrow_mut() = Some(Backtrace::force_capture()));
+            previous(info);
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use actix_web::rt::time::timeout;
+
+    use super::*;
+
+    /// Waits until the task `name` reaches `status` after `restarts` restarts.
+    async fn wait_for(name: &str, status: TaskStatus, restarts: u64) -> TaskState {
+        let reached = |task: &TaskState| task.status == status && task.restarts == restarts;
+
+        timeout(Duration::from_secs(5), async {
+            loop {
+                match Supervisor::get().tasks().remove(name) {
+                    Some(task) if reached(&task) => return task,
+                    _ => sleep(Duration::from_millis(10)).await,
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("{name} never reached {status:?}"))
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let max = Duration::from_secs(10);
+
+        assert_eq!(backoff(1, max), Duration::from_secs(1));
+        assert_eq!(backoff(2, max), Duration::from_secs(2));
+        assert_eq!(backoff(4, max), Duration::from_secs(8));
+        assert_eq!(backoff(5, max), max);
+        assert_eq!(backoff(u32::MAX, max), max);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs SIGNING_SECRET"]
+    async fn panics_are_recorded_and_restarts_skip_the_backoff() {
+        let runs = Rc::new(Cell::new(0));
+        let counted = runs.clone();
+
+        supervised_task("test-panics", move || {
+            let runs = counted.clone();
+
+            async move {
+                runs.set(runs.get() + 1);
+
+                match runs.get() {
+                    1 => panic!("boom"),
+                    2 => Err(AppError::JobCancelled),
+                    _ => Ok(()),
+                }
+            }
+        });
+
+        let task = wait_for("test-panics", TaskStatus::BackingOff, 1).await;
+        assert_eq!(task.last_error.unwrap(), "panicked: boom");
+
+        assert!(Supervisor::get().restart("test-panics"));
+        let task = wait_for("test-panics", TaskStatus::BackingOff, 2).await;
+        assert_eq!(task.last_error.unwrap(), AppError::JobCancelled.to_string());
+
+        assert!(Supervisor::get().restart("test-panics"));
+        wait_for("test-panics", TaskStatus::Finished, 2).await;
+        assert_eq!(runs.get(), 3);
+
+        assert!(!Supervisor::get().restart("test-unknown"));
+    }
+}
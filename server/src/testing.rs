@@ -0,0 +1,184 @@
+//! Fixtures for the tests that need a database. Those are ignored by
+//! default with a reason naming the variables they need, and run with
+//! `cargo test -- --ignored` once `DATABASE_URL` points to a migrated
+//! database.
+//!
+//! They run through `run` rather than `#[actix_web::test]`, the pool
+//! `db!()` hands out is shared by every test and its connections only
+//! work as long as the runtime they were opened on.
+
+use std::future::Future;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::LazyLock;
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+
+use actix_web::App;
+use actix_web::dev::ServiceResponse;
+use actix_web::test::{TestRequest, call_service, init_service};
+use actix_web::web::{Bytes, Data};
+use database::{BucketModel, BucketUpdate, FileCreation, FileModel, UserModel};
+use serde_json::json;
+use sqlx::PgPool;
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::LocalSet;
+
+use crate::routes::routes;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::upload::store_upload;
+
+/// Runs a database test on the runtime every one of them shares.
+pub fn run<F: Future>(test: F) -> F::Output {
+    static RUNTIME: LazyLock<Runtime> =
+        LazyLock::new(|| Builder::new_multi_thread().enable_all().build().unwrap());
+
+    // Handlers spawn on the local set like they do under actix's runtime.
+    RUNTIME.block_on(LocalSet::new().run_until(test))
+}
+
+/// `prefix` followed by random digits, names are unique across users.
+pub fn unique_name(prefix: &str) -> String {
+    format!("{prefix}-{:016x}", rand::random::<u64>())
+}
+
+/// A pool of the fixtures' own for the rows the models can't write.
+static POOL: LazyLock<PgPool> =
+    LazyLock::new(|| PgPool::connect_lazy(&std::env::var("DATABASE_URL").unwrap()).unwrap());
+
+/// Inserted directly, tests only need someone owning their files.
+pub async fn create_user(username: &str) -> UserModel {
+    sqlx::query("INSERT INTO users (username, email, password) VALUES ($1, $1, '')")
+        .bind(username)
+        .execute(&*POOL)
+        .await
+        .unwrap();
+
+    UserModel::get_by_username(username).await.unwrap()
+}
+
+/// An API key of `owner`, returned as the secret to authenticate with.
+pub async fn create_api_key(owner: &UserModel) -> String {
+    let secret = unique_name("key");
+
+    sqlx::query("INSERT INTO api_keys (user_id, secret) VALUES ($1, $2)")
+        .bind(owner.id())
+        .bind(&secret)
+        .execute(&*POOL)
+        .await
+        .unwrap();
+
+    secret
+}
+
+pub async fn create_bucket(owner: &UserModel) -> BucketModel {
+    BucketModel::get_or_create(&unique_name("bucket"), owner.id()).await.unwrap()
+}
+
+/// `bucket` made `public`, `unlisted` or `private`.
+pub async fn set_visibility(bucket: BucketModel, visibility: &str) -> BucketModel {
+    let update: BucketUpdate = serde_json::from_value(json!({ "visibility": visibility })).unwrap();
+
+    bucket.edit(update, None).await.unwrap().unwrap()
+}
+
+/// A file whose blob was never stored, for tests of the rows alone.
+pub async fn create_file(bucket: &BucketModel, path: &str) -> FileModel {
+    FileModel::create_new(FileCreation {
+        bucket_id: bucket.id(),
+        path: path.to_owned(),
+        size: 0,
+        hash: format!("{:064x}", rand::random::<u128>()),
+        hash_algorithm: "sha256".into(),
+        created_at: None,
+        expires_at: None,
+        text: None,
+        name_key: path.as_bytes().to_vec(),
+        content_type: None,
+    })
+    .await
+    .unwrap()
+}
+
+/// Gives `file` the slug `slug` behind the caches' back.
+pub async fn set_slug(file: &FileModel, slug: &str) {
+    sqlx::query("UPDATE objects SET slug = $1 WHERE id = $2")
+        .bind(slug)
+        .bind(file.id())
+        .execute(&*POOL)
+        .await
+        .unwrap();
+}
+
+/// Makes `file` look stored before sizes were checked.
+pub async fn unverify_size(file: &FileModel) {
+    sqlx::query("UPDATE objects SET size_verified_at = NULL WHERE id = $1")
+        .bind(file.id())
+        .execute(&*POOL)
+        .await
+        .unwrap();
+}
+
+/// A file holding `content`, stored through the upload pipeline.
+pub async fn upload_file(
+    storage: &AppStorage,
+    bucket: &BucketModel,
+    path: &str,
+    content: &[u8],
+) -> FileModel {
+    let body = Bytes::copy_from_slice(content);
+
+    store_upload(storage, bucket.id(), path.to_owned(), None, false, true, body).await.unwrap()
+}
+
+/// Answers a GET of `path` through the routes of the server.
+pub async fn get(storage: &AppStorage, path: &str) -> ServiceResponse {
+    let app = init_service(App::new().app_data(Data::new(storage.clone())).configure(routes)).await;
+
+    call_service(&app, TestRequest::get().uri(path).to_request()).await
+}
+
+/// A receiver answering every request with `status`,
+/// handing the requests it got to the test.
+pub fn receiver(status: u16) -> (SocketAddr, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let (sender, requests) = channel();
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+
+            // Read the headers, then as much body as they announce.
+            while let Ok(read @ 1..) = stream.read(&mut buffer) {
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request);
+
+                if let Some((headers, body)) = text.split_once("\r\n\r\n") {
+                    let length = headers
+                        .lines()
+                        .find_map(|line| {
+                            line.to_ascii_lowercase()
+                                .strip_prefix("content-length: ")
+                                .map(str::to_owned)
+                        })
+                        .and_then(|length| length.parse::<usize>().ok())
+                        .unwrap_or(0);
+
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+
+            let _ = sender.send(String::from_utf8_lossy(&request).into_owned());
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+        }
+    });
+
+    (address, requests)
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use futures::future::try_join_all;
+use storage::{
+    BreakerPolicy, Encrypted, LocalStorage, MasterKey, Resilient, RetryPolicy, StorageResult,
+};
+
+use crate::config::Config;
+
+/// The storage the server runs on, local blobs encrypted at rest
+/// behind retries and a circuit breaker per operation.
+///
+/// Appendable files are written in place and stay in plaintext,
+/// reads tell them apart from sealed blobs by their header.
+pub type AppStorage = Resilient<Encrypted<LocalStorage>>;
+
+/// Opens `STORAGE_PATH` with the keys and retry settings from the config.
+pub async fn open_storage() -> StorageResult<AppStorage> {
+    let config = Config::get();
+    let local = LocalStorage::new(&config.storage_path).await?;
+
+    let previous =
+        try_join_all(config.previous_master_key_files.iter().map(MasterKey::from_file)).await?;
+    let encrypted = match &config.master_key_file {
+        Some(path) => Encrypted::new(local, MasterKey::from_file(path).await?, previous),
+        None => Encrypted::disabled(local, previous),
+    };
+
+    let retry = RetryPolicy {
+        max_attempts: config.storage_max_attempts,
+        total_budget: Duration::from_millis(config.storage_retry_budget_ms),
+        ..RetryPolicy::default()
+    };
+
+    Ok(Resilient::new(encrypted, retry, BreakerPolicy::default()))
+}
+
+/// The local backend under the wrappers, for what only it can do
+/// such as appending in place or listing blobs.
+pub fn local(storage: &AppStorage) -> &LocalStorage {
+    storage.inner().inner()
+}
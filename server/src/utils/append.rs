@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crc32fast::Hasher;
+use database::FileModel;
+use storage::{SEALED_HEAD_LENGTH, Storage, looks_sealed};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::app_storage::{AppStorage, local};
+use crate::utils::file_cache::FileCache;
+use crate::utils::serving::file_blob_key;
+use crate::utils::stat_cache::StatCache;
+
+static APPEND_LOCKS: OnceLock<Mutex<HashMap<i64, Arc<AsyncMutex<()>>>>> = OnceLock::new();
+
+/// Appends `data` to an appendable file, appends to the same file are
+/// serialized so each one lands whole after the previous one. The row of
+/// the file is locked for the length of the append so other nodes wait
+/// too, the lock per file here only keeps appends waiting on this node
+/// from each holding a connection.
+///
+/// Appendable files are written in place and never encrypted at rest,
+/// whatever `MASTER_KEY_FILE` is. Reads tell sealed blobs by their first
+/// bytes, so appends that would make a file start like one are refused.
+///
+/// The running checksum is a CRC32 carried over from the previous
+/// append, so the content already stored is never read again. The
+/// digest of `HASH_ALGORITHM` is left unset on appendable files: its
+/// hasher state can't be persisted between appends, and computing it
+/// would mean reading the whole file back every time.
+pub async fn append(storage: &AppStorage, id: i64, data: &[u8]) -> Result<FileModel, AppError> {
+    serialized(id, append_locked(storage, id, data)).await
+}
+
+/// Runs `task` once every earlier task of the same file is done.
+async fn serialized<T>(id: i64, task: impl Future<Output = T>) -> T {
+    let lock = file_lock(id);
+    let guard = lock.lock().await;
+
+    let result = task.await;
+
+    drop(guard);
+    release_lock(id, lock);
+
+    result
+}
+
+/// The CRC32 of the content once `data` is appended to
+/// content whose CRC32 was `previous`.
+fn extend_crc32(previous: u32, data: &[u8]) -> u32 {
+    let mut hasher = Hasher::new_with_initial(previous);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+async fn append_locked(storage: &AppStorage, id: i64, data: &[u8]) -> Result<FileModel, AppError> {
+    let lock = FileModel::lock_for_append(id).await?;
+    let file = lock.file();
+
+    if !file.appendable() {
+        return Err(AppError::NotAppendable);
+    }
+
+    let offset = file.size() as u64;
+    let size = offset + data.len() as u64;
+    let max_size = Config::get().max_appendable_size;
+
+    if size > max_size {
+        return Err(AppError::AppendTooLarge(max_size));
+    }
+
+    let key = file_blob_key(file)?;
+    let local = local(storage);
+
+    // Once the head is stored whole it was already checked.
+    if offset < SEALED_HEAD_LENGTH as u64 {
+        let mut head = match offset {
+            0 => Vec::new(),
+            _ => local.get_range(&key, 0..offset).await?,
+        };
+        head.extend_from_slice(data);
+
+        if looks_sealed(&head) {
+            return Err(AppError::AppendLooksSealed);
+        }
+    }
+
+    local.append_at(&key, offset, data).await?;
+
+    let crc32 = extend_crc32(file.append_crc32().unwrap_or_default(), data);
+    let file = lock.record(size as i64, crc32).await?;
+    StatCache::get().forget_file(&file);
+    FileCache::get().forget(file.id());
+
+    Ok(file)
+}
+
+fn file_lock(id: i64) -> Arc<AsyncMutex<()>> {
+    let mut locks = APPEND_LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+
+    locks.entry(id).or_default().clone()
+}
+
+/// Drops the lock of a file once nobody else waits on it.
+fn release_lock(id: i64, lock: Arc<AsyncMutex<()>>) {
+    let mut locks = APPEND_LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+
+    // One reference is ours and one is the map's.
+    if Arc::strong_count(&lock) <= 2 {
+        locks.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{create_bucket, create_user, run, unique_name};
+    use crate::utils::app_storage::open_storage;
+
+    async fn create_appendable(name: &str) -> FileModel {
+        let bucket = create_bucket(&create_user(&unique_name("append")).await).await;
+
+        FileModel::create_appendable(bucket.id(), name, name.as_bytes(), "sha256", None)
+            .await
+            .unwrap()
+    }
+
+    /// Follows the file like `tail -f`, asking for what's past the last recorded size.
+    async fn tail(storage: &AppStorage, id: i64, seen: &mut Vec<u8>) {
+        let file = FileModel::get(id).await.unwrap();
+        let size = file.size() as u64;
+
+        if size > seen.len() as u64 {
+            let key = file_blob_key(&file).unwrap();
+            seen.extend(storage.get_range(&key, seen.len() as u64..size).await.unwrap());
+        }
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn interleaved_appends_land_whole_and_in_order() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let id = create_appendable("log").await.id();
+
+            let client = |name: &'static str| {
+                let storage = &storage;
+
+                async move {
+                    let mut seen = Vec::new();
+
+                    for line in 0..50 {
+                        let data = format!("{name} {line}\n");
+
+                        // The other client skips the lock of this node like
+                        // another node would, only the row lock orders them.
+                        match name {
+                            "a" => append(storage, id, data.as_bytes()).await.unwrap(),
+                            _ => append_locked(storage, id, data.as_bytes()).await.unwrap(),
+                        };
+                        tail(storage, id, &mut seen).await;
+                    }
+
+                    seen
+                }
+            };
+
+            let (seen_a, seen_b) = futures::join!(client("a"), client("b"));
+            let file = FileModel::get(id).await.unwrap();
+            let content = storage.get(&file_blob_key(&file).unwrap()).await.unwrap();
+
+            // Each tail read only ever saw a prefix of the final content.
+            assert!(content.starts_with(&seen_a));
+            assert!(content.starts_with(&seen_b));
+
+            let lines: Vec<&str> = std::str::from_utf8(&content).unwrap().lines().collect();
+            assert_eq!(lines.len(), 100);
+
+            for name in ["a", "b"] {
+                let own: Vec<String> = lines
+                    .iter()
+                    .filter(|line| line.starts_with(name))
+                    .map(|line| line.to_string())
+                    .collect();
+                let expected: Vec<String> = (0..50).map(|line| format!("{name} {line}")).collect();
+
+                assert_eq!(own, expected);
+            }
+
+            assert_eq!(file.size() as usize, content.len());
+            assert_eq!(file.append_crc32(), Some(crc32fast::hash(&content)));
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn unrecorded_bytes_are_cut_and_sealed_heads_refused() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let file = create_appendable("log").await;
+            let key = file_blob_key(&file).unwrap();
+
+            let file = append(&storage, file.id(), b"hello").await.unwrap();
+
+            // Written by an append whose row was never updated.
+            local(&storage).append_at(&key, 5, b" lost").await.unwrap();
+
+            let file = append(&storage, file.id(), b" world").await.unwrap();
+            assert_eq!(storage.get(&key).await.unwrap(), b"hello world");
+            assert_eq!(file.size(), 11);
+
+            let file = create_appendable("sealed").await;
+            append(&storage, file.id(), b"CDNENC").await.unwrap();
+
+            let refused = append(&storage, file.id(), b"01 and more").await;
+            assert!(matches!(refused, Err(AppError::AppendLooksSealed)));
+            assert_eq!(FileModel::get(file.id()).await.unwrap().size(), 6);
+        })
+    }
+}
@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::{Error as IoError, Result as IoResult, Write};
+use std::rc::Rc;
+use std::vec::IntoIter;
+
+use actix_web::web::{Bytes, Data};
+use database::FileModel;
+use futures::{Stream, stream};
+use storage::Storage;
+use zip::write::{SimpleFileOptions, StreamWriter};
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::AppError;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::serving::file_blob_key;
+
+/// Where the zip writer puts its output until it's sent as the next chunk.
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, data: &[u8]) -> IoResult<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+struct Archive {
+    storage: Data<AppStorage>,
+    files: IntoIter<FileModel>,
+    names: HashSet<String>,
+    buffer: Buffer,
+    writer: Option<ZipWriter<StreamWriter<Buffer>>>,
+}
+
+/// Streams `files` as a zip archive, a chunk per entry, so only
+/// one blob is held in memory at a time.
+///
+/// Entries are stored uncompressed, most uploads already are compressed.
+pub fn zip_stream(
+    storage: Data<AppStorage>,
+    files: Vec<FileModel>,
+) -> impl Stream<Item = Result<Bytes, AppError>> {
+    let buffer = Buffer::default();
+
+    let archive = Archive {
+        storage,
+        files: files.into_iter(),
+        names: HashSet::new(),
+        writer: Some(ZipWriter::new_stream(buffer.clone())),
+        buffer,
+    };
+
+    stream::unfold(Some(archive), |archive| async move {
+        let mut archive = archive?;
+
+        match archive.next_chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(archive))),
+            Ok(None) => None,
+            // The archive can't be resumed after a failed entry.
+            Err(error) => Some((Err(error), None)),
+        }
+    })
+}
+
+impl Archive {
+    /// Writes the next entry, or the central directory once
+    /// every file was written, `None` when the archive is done.
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, AppError> {
+        if self.writer.is_none() {
+            return Ok(None);
+        }
+
+        match self.files.next() {
+            Some(file) => {
+                let mut blob = self.storage.get(&file_blob_key(&file)?).await?;
+
+                // Bytes past the recorded size belong to an append still in flight.
+                if file.appendable() {
+                    blob.truncate(file.size() as usize);
+                }
+
+                let name = self.entry_name(file.path());
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Stored)
+                    .large_file(blob.len() as u64 >= u32::MAX as u64);
+
+                if let Some(writer) = &mut self.writer {
+                    writer.start_file(name, options).map_err(IoError::other)?;
+                    writer.write_all(&blob)?;
+                }
+            },
+            None => {
+                if let Some(writer) = self.writer.take() {
+                    writer.finish().map_err(IoError::other)?;
+                }
+            },
+        }
+
+        Ok(Some(Bytes::from(self.buffer.0.take())))
+    }
+
+    /// Files with the same path get a counter before their extension.
+    fn entry_name(&mut self, path: &str) -> String {
+        let mut name = path.trim_start_matches('/').to_owned();
+        let mut counter = 1;
+
+        while !self.names.insert(name.clone()) {
+            counter += 1;
+
+            name = match path.rsplit_once('.') {
+                Some((stem, extension)) if !stem.is_empty() => {
+                    format!("{stem} ({counter}).{extension}")
+                },
+                _ => format!("{path} ({counter})"),
+            }
+            .trim_start_matches('/')
+            .to_owned();
+        }
+
+        name
+    }
+}
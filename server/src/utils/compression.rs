@@ -0,0 +1,106 @@
+use std::io::{self, Write};
+
+use brotli::CompressorWriter;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::config::Config;
+
+/// Set in the extensions of a response to always send it as is, for
+/// downloads and for responses holding secrets an attacker could guess
+/// byte by byte from the compressed size.
+pub struct NoCompression;
+
+#[derive(Clone, Copy)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Appended to the storage key of precompressed variants.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let config = Config::get();
+
+        match self {
+            Encoding::Brotli => {
+                let mut writer = CompressorWriter::new(
+                    Vec::with_capacity(data.len() / 2),
+                    4096,
+                    config.compress_brotli_level,
+                    22,
+                );
+
+                writer.write_all(data)?;
+                Ok(writer.into_inner())
+            },
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(
+                    Vec::with_capacity(data.len() / 2),
+                    Compression::new(config.compress_gzip_level),
+                );
+
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+        }
+    }
+}
+
+/// The encodings `Accept-Encoding` allows, brotli first,
+/// encodings given a `q=0` are refused.
+pub fn accepted(accept_encoding: &str) -> impl Iterator<Item = Encoding> + '_ {
+    let accepts = |name: &str| {
+        accept_encoding.split(',').any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+
+            params.next().is_some_and(|coding| coding.eq_ignore_ascii_case(name))
+                && params
+                    .filter_map(|param| param.strip_prefix("q="))
+                    .all(|q| q.parse::<f32>().is_ok_and(|q| q > 0.0))
+        })
+    };
+
+    [Encoding::Brotli, Encoding::Gzip]
+        .into_iter()
+        .filter(move |encoding| accepts(encoding.as_str()))
+}
+
+/// Picks brotli over gzip when `Accept-Encoding` allows both.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    accepted(accept_encoding).next()
+}
+
+/// Whether compressing a content type is worth it, formats
+/// such as images and archives are already compressed.
+pub fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/wasm"
+                | "application/x-ndjson"
+                | "image/svg+xml"
+                | "image/bmp"
+        )
+}
@@ -0,0 +1,86 @@
+use std::time::SystemTime;
+
+use actix_web::http::header::{IF_MATCH, IfUnmodifiedSince};
+use actix_web::{HttpMessage, HttpRequest};
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::headers::single_header_value;
+
+/// Reads the row version a mutation expects from `If-Match`.
+///
+/// `None` means last write wins, which is only allowed
+/// while `REQUIRE_IF_MATCH` is disabled.
+pub fn expected_version(req: &HttpRequest) -> Result<Option<i64>, AppError> {
+    let Some(value) = single_header_value(req, IF_MATCH)? else {
+        if Config::get().require_if_match {
+            return Err(AppError::PreconditionRequired);
+        }
+
+        return Ok(None);
+    };
+
+    let value = value.trim();
+
+    if value == "*" {
+        return Ok(None);
+    }
+
+    value
+        .strip_prefix("W/")
+        .unwrap_or(value)
+        .trim_matches('"')
+        .strip_prefix('v')
+        .and_then(|version| version.parse().ok())
+        .map(Some)
+        .ok_or(AppError::InvalidIfMatch)
+}
+
+/// Reads the last modification a mutation expects from `If-Unmodified-Since`,
+/// ignored alongside `If-Match` since the version is the stronger check.
+///
+/// Unparseable dates are ignored as well, as HTTP mandates.
+pub fn expected_unmodified_since(req: &HttpRequest) -> Option<NaiveDateTime> {
+    if req.headers().contains_key(IF_MATCH) {
+        return None;
+    }
+
+    let IfUnmodifiedSince(date) = req.get_header::<IfUnmodifiedSince>()?;
+
+    Some(DateTime::<Utc>::from(SystemTime::from(date)).naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn http_dates_are_read_as_utc() {
+        let req = TestRequest::default()
+            .insert_header(("If-Unmodified-Since", "Tue, 15 Nov 1994 08:12:31 GMT"))
+            .to_http_request();
+
+        let expected = NaiveDate::from_ymd_opt(1994, 11, 15).unwrap().and_hms_opt(8, 12, 31);
+        assert_eq!(expected_unmodified_since(&req), expected);
+    }
+
+    #[test]
+    fn dates_are_ignored_alongside_if_match_or_when_malformed() {
+        let date = ("If-Unmodified-Since", "Tue, 15 Nov 1994 08:12:31 GMT");
+
+        let req = TestRequest::default()
+            .insert_header(date)
+            .insert_header((IF_MATCH, "\"v3\""))
+            .to_http_request();
+        assert_eq!(expected_unmodified_since(&req), None);
+
+        let req = TestRequest::default()
+            .insert_header(("If-Unmodified-Since", "last tuesday"))
+            .to_http_request();
+        assert_eq!(expected_unmodified_since(&req), None);
+    }
+}
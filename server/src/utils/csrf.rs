@@ -0,0 +1,31 @@
+use actix_web::http::header::HeaderName;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::Config;
+
+/// Readable by the UI, which echoes it in `X-CSRF-Token`.
+pub const CSRF_COOKIE: &str = "csrf_token";
+
+pub const X_CSRF_TOKEN: HeaderName = HeaderName::from_static("x-csrf-token");
+
+fn mac(session: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(Config::get().signing_secret.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(b"csrf:");
+    mac.update(session.as_bytes());
+    mac
+}
+
+/// The CSRF token of a session, derived from its cookie so
+/// a token taken from one session is useless in another.
+pub fn csrf_token(session: &str) -> String {
+    URL_SAFE_NO_PAD.encode(mac(session).finalize().into_bytes())
+}
+
+/// Checks `token` against the session in constant time.
+pub fn verify_csrf_token(session: &str, token: &str) -> bool {
+    URL_SAFE_NO_PAD.decode(token).is_ok_and(|token| mac(session).verify_slice(&token).is_ok())
+}
@@ -0,0 +1,121 @@
+use std::fmt::Write;
+use std::future::Future;
+use std::time::Duration;
+
+use actix_web::rt::time::timeout;
+use log::warn;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::AppError;
+use crate::utils::dav::escape;
+
+/// How long a single panel may load before it's given up on,
+/// a slow source never holds the rest of the page.
+pub const PANEL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Loads and renders a panel of the admin dashboard, sources that
+/// fail or time out render an error chip in place of their data.
+pub async fn panel<T, F>(title: &str, source: F) -> String
+where
+    T: Serialize,
+    F: Future<Output = Result<T, AppError>>,
+{
+    let body = match timeout(PANEL_TIMEOUT, source).await {
+        Ok(Ok(data)) => match serde_json::to_value(data) {
+            Ok(value) => render_value(&value),
+            Err(error) => error_chip(&error.to_string()),
+        },
+        Ok(Err(error)) => {
+            warn!("Dashboard panel {title} failed: {error}");
+            error_chip(&error.to_string())
+        },
+        Err(_) => error_chip(&format!("timed out after {}s", PANEL_TIMEOUT.as_secs())),
+    };
+
+    format!("<section><h2>{}</h2>{body}</section>", escape(title))
+}
+
+fn error_chip(message: &str) -> String {
+    format!("<p class=\"error\">Unavailable: {}</p>", escape(message))
+}
+
+/// Lists of objects become tables, lone objects become key value lists.
+fn render_value(value: &Value) -> String {
+    match value {
+        Value::Array(rows) if rows.is_empty() => "<p>None.</p>".to_owned(),
+        Value::Array(rows) => {
+            let columns = match &rows[0] {
+                Value::Object(fields) => fields.keys().cloned().collect::<Vec<_>>(),
+                _ => Vec::new(),
+            };
+
+            let mut html = String::from("<table><tr>");
+
+            for column in &columns {
+                let _ = write!(html, "<th>{}</th>", escape(column));
+            }
+
+            html.push_str("</tr>");
+
+            for row in rows {
+                html.push_str("<tr>");
+
+                match row {
+                    Value::Object(fields) => {
+                        for column in &columns {
+                            let cell = fields.get(column).map(render_scalar).unwrap_or_default();
+                            let _ = write!(html, "<td>{cell}</td>");
+                        }
+                    },
+                    other => {
+                        let _ = write!(html, "<td>{}</td>", render_scalar(other));
+                    },
+                }
+
+                html.push_str("</tr>");
+            }
+
+            html.push_str("</table>");
+            html
+        },
+        Value::Object(fields) => {
+            let mut html = String::from("<dl>");
+
+            for (key, value) in fields {
+                let rendered = match value {
+                    Value::Object(_) | Value::Array(_) => render_value(value),
+                    scalar => render_scalar(scalar),
+                };
+                let _ = write!(html, "<dt>{}</dt><dd>{rendered}</dd>", escape(key));
+            }
+
+            html.push_str("</dl>");
+            html
+        },
+        scalar => format!("<p>{}</p>", render_scalar(scalar)),
+    }
+}
+
+fn render_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_owned(),
+        Value::String(text) => escape(text),
+        other => escape(&other.to_string()),
+    }
+}
+
+/// Wraps the rendered panels in a page that reloads itself every `refresh` seconds.
+pub fn page(panels: &[String], refresh: u64) -> String {
+    format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"{refresh}\"><title>Dashboard</title>\
+         <style>body{{font-family:sans-serif;margin:2em}}\
+         section{{margin-bottom:2em}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #ccc;padding:.2em .5em;text-align:left}}\
+         dt{{font-weight:bold}}.error{{display:inline-block;background:#fdd;color:#900;\
+         padding:.2em .6em;border-radius:1em}}</style></head>\
+         <body><h1>Dashboard</h1>{}</body></html>",
+        panels.concat(),
+    )
+}
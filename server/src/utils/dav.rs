@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+use chrono::NaiveDateTime;
+
+/// A resource listed in a `PROPFIND` response.
+pub struct DavEntry {
+    /// Unencoded path segments below `/dav/`.
+    pub segments: Vec<String>,
+    pub collection: bool,
+    pub length: u64,
+    pub last_modified: NaiveDateTime,
+    pub etag: Option<String>,
+}
+
+/// Renders a `207 Multi-Status` body for `entries`.
+///
+/// Every element is prefixed with the `D:` namespace, which is what
+/// Finder and Explorer expect, and hrefs are percent encoded per segment.
+pub fn multistatus(entries: &[DavEntry]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+    );
+
+    for entry in entries {
+        let segments: Vec<_> = entry.segments.iter().map(|segment| encode(segment)).collect();
+        let mut href = format!("/dav/{}", segments.join("/"));
+
+        if entry.collection && !href.ends_with('/') {
+            href.push('/');
+        }
+
+        let name = entry.segments.last().map(String::as_str).unwrap_or_default();
+        let modified = entry.last_modified.format("%a, %d %b %Y %H:%M:%S GMT");
+
+        let _ = write!(xml, "<D:response><D:href>{}</D:href>", escape(&href));
+        let _ = write!(xml, "<D:propstat><D:prop>");
+        let _ = write!(xml, "<D:displayname>{}</D:displayname>", escape(name));
+        let _ = write!(xml, "<D:getlastmodified>{modified}</D:getlastmodified>");
+
+        if entry.collection {
+            let _ = write!(xml, "<D:resourcetype><D:collection/></D:resourcetype>");
+        } else {
+            let _ = write!(xml, "<D:resourcetype/>");
+            let _ = write!(xml, "<D:getcontentlength>{}</D:getcontentlength>", entry.length);
+            let _ = write!(xml, "<D:getcontenttype>application/octet-stream</D:getcontenttype>");
+        }
+
+        if let Some(etag) = &entry.etag {
+            let _ = write!(xml, "<D:getetag>\"{}\"</D:getetag>", escape(etag));
+        }
+
+        let _ = write!(xml, "</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>");
+        let _ = writeln!(xml, "</D:response>");
+    }
+
+    xml.push_str("</D:multistatus>\n");
+    xml
+}
+
+/// Percent encodes everything but RFC 3986 unreserved characters.
+pub fn encode(segment: &str) -> String {
+    segment.bytes().fold(String::with_capacity(segment.len()), |mut encoded, byte| {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            },
+            byte => {
+                let _ = write!(encoded, "%{byte:02X}");
+            },
+        }
+
+        encoded
+    })
+}
+
+/// Escapes text for XML and HTML, attribute values included.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 4, 6).unwrap().and_hms_opt(hour, 30, 5).unwrap()
+    }
+
+    fn collection(segments: &[&str], hour: u32) -> DavEntry {
+        DavEntry {
+            segments: segments.iter().map(|segment| segment.to_string()).collect(),
+            collection: true,
+            length: 0,
+            last_modified: at(hour),
+            etag: None,
+        }
+    }
+
+    #[test]
+    fn root_listings_match_the_golden_file() {
+        let entries =
+            [collection(&[], 8), collection(&["photos"], 9), collection(&["Café & <Bar>"], 10)];
+
+        assert_eq!(multistatus(&entries), include_str!("../../testdata/dav/propfind_root.xml"));
+    }
+
+    #[test]
+    fn bucket_listings_match_the_golden_file() {
+        let file = |name: &str, length, etag: Option<&str>| DavEntry {
+            segments: vec!["photos".into(), name.into()],
+            collection: false,
+            length,
+            last_modified: at(11),
+            etag: etag.map(str::to_owned),
+        };
+
+        let entries = [
+            collection(&["photos"], 9),
+            file("holiday 2024.jpg", 48213, Some("9f86d081884c7d65")),
+            file("notes/draft #1?.txt", 0, None),
+            file("日本語.png", 7, Some("a\"b")),
+        ];
+
+        assert_eq!(multistatus(&entries), include_str!("../../testdata/dav/propfind_bucket.xml"));
+    }
+
+    #[test]
+    fn segments_keep_only_unreserved_characters() {
+        assert_eq!(encode("a b/c?d#e%f"), "a%20b%2Fc%3Fd%23e%25f");
+        assert_eq!(encode("é"), "%C3%A9");
+        assert_eq!(encode("Az09-._~"), "Az09-._~");
+    }
+}
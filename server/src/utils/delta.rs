@@ -0,0 +1,421 @@
+use std::io::Error as IoError;
+
+use actix_web::web::block;
+use database::{DatabaseError, FileModel};
+use log::info;
+use serde::{Deserialize, Serialize};
+use storage::{Storage, StorageError};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::processing::Pipeline;
+use crate::processing::clamav::scan;
+use crate::tasks::jobs::JobProgress;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::file_cache::FileCache;
+use crate::utils::hashing::{HashAlgorithm, blob_key};
+use crate::utils::inline::sniff;
+use crate::utils::purge::{file_key, purge};
+use crate::utils::serving::file_blob_key;
+use crate::utils::stat_cache::StatCache;
+
+/// Smallest block a signature is computed over, smaller blocks
+/// find more matches but make the signature grow.
+pub const MIN_BLOCK_SIZE: usize = 1024;
+
+/// Largest block a signature is computed over.
+pub const MAX_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Hex digits kept of the strong hash of a block, the weak hash
+/// already ruled out nearly every other block.
+const STRONG_HASH_LENGTH: usize = 32;
+
+/// Copies `count` blocks of the stored content starting at `block`,
+/// encoded as the tag, a big endian `u64` and a big endian `u32`.
+const OP_COPY: u8 = 0;
+
+/// Inserts bytes sent along, encoded as the tag,
+/// a big endian `u32` length and the bytes.
+const OP_LITERAL: u8 = 1;
+
+/// The blocks of a stored file, for a client to find which parts
+/// of its new version it doesn't have to send.
+#[derive(Serialize, Deserialize)]
+pub struct Signature {
+    pub block_size: usize,
+    /// Size of the content the signature was computed over.
+    pub size: u64,
+    /// Hash of that content, a delta only applies to this exact version.
+    pub hash: String,
+    pub blocks: Vec<BlockSignature>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BlockSignature {
+    /// The rsync rolling checksum, which the client slides over its content.
+    pub weak: u32,
+    /// A prefix of the BLAKE3 hash as hex, confirming a weak match.
+    pub strong: String,
+}
+
+/// The block size used for a file of `size` bytes, around its square
+/// root so neither the signature nor the literals of a delta get large.
+///
+/// A size `requested` by the client is honored within the bounds.
+pub fn block_size(size: u64, requested: Option<usize>) -> usize {
+    let size = requested.unwrap_or_else(|| (size as f64).sqrt() as usize);
+
+    size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE).next_power_of_two()
+}
+
+/// Computes the signature of `data` cut in blocks of `block_size`,
+/// the last block is shorter unless the size is a multiple.
+pub fn signature(data: &[u8], block_size: usize, hash: String) -> Signature {
+    let blocks = data
+        .chunks(block_size)
+        .map(|block| BlockSignature {
+            weak: weak_checksum(block),
+            strong: strong_hash(block),
+        })
+        .collect();
+
+    Signature {
+        block_size,
+        size: data.len() as u64,
+        hash,
+        blocks,
+    }
+}
+
+/// The rsync checksum of `block`, its two halves can be rolled one byte
+/// forward without reading the block again.
+pub fn weak_checksum(block: &[u8]) -> u32 {
+    let (mut a, mut b) = (0u32, 0u32);
+
+    for (index, byte) in block.iter().enumerate() {
+        a = a.wrapping_add(u32::from(*byte));
+        b = b.wrapping_add((block.len() - index) as u32 * u32::from(*byte));
+    }
+
+    (a & 0xffff) | (b << 16)
+}
+
+fn strong_hash(block: &[u8]) -> String {
+    let mut hash = blake3::hash(block).to_hex().to_string();
+    hash.truncate(STRONG_HASH_LENGTH);
+    hash
+}
+
+/// The signature of the content of `file`, cached next to the blobs
+/// so repeated calls only read it, the key changes with the content.
+pub async fn cached_signature(
+    storage: &AppStorage,
+    file: &FileModel,
+    block_size: usize,
+) -> Result<Signature, AppError> {
+    let hash = content_hash(file)?;
+    let key = format!("signatures/{}/{hash}/{block_size}.json", file.hash_algorithm());
+
+    match storage.get(&key).await {
+        Ok(cached) => return Ok(serde_json::from_slice(&cached).map_err(IoError::other)?),
+        Err(StorageError::NotFound(_)) => {},
+        Err(error) => return Err(error.into()),
+    }
+
+    let data = storage.get(&file_blob_key(file)?).await?;
+
+    // Hashing every block is CPU bound, so it's kept off the async workers.
+    let signature =
+        block(move || signature(&data, block_size, hash)).await.map_err(IoError::other)?;
+
+    let encoded = serde_json::to_vec(&signature).map_err(IoError::other)?;
+    storage.put_if_absent(&key, &encoded).await?;
+
+    Ok(signature)
+}
+
+/// Replaces the content of `file` with the stored content patched by
+/// `delta`, committed only when the result hashes to `checksum`.
+///
+/// The candidate is simply dropped when it doesn't match, the
+/// client is then expected to upload the whole file instead.
+pub async fn patch(
+    storage: &AppStorage,
+    file: FileModel,
+    block_size: usize,
+    checksum: &str,
+    delta: Vec<u8>,
+    progress: Option<&JobProgress>,
+) -> Result<FileModel, AppError> {
+    const STEPS: i64 = 3;
+
+    content_hash(&file)?;
+
+    let algorithm =
+        HashAlgorithm::parse(file.hash_algorithm()).ok_or(DatabaseError::ModelNotFound("file"))?;
+    let base = storage.get(&file_blob_key(&file)?).await?;
+    let max_size = base.len() as u64 + Config::get().max_upload_size as u64;
+
+    if let Some(progress) = progress {
+        progress.update(1, Some(STEPS)).await?;
+    }
+
+    // Rebuilding and hashing large files is CPU bound, so it's kept off the async workers.
+    let (data, literal, hash) = block(move || {
+        let (data, literal) = apply(&base, block_size, &delta, max_size)?;
+        let hash = algorithm.digest(&data);
+
+        Ok::<_, AppError>((data, literal, hash))
+    })
+    .await
+    .map_err(IoError::other)??;
+
+    if !hash.eq_ignore_ascii_case(checksum) {
+        return Err(AppError::DeltaMismatch);
+    }
+
+    if let Some(progress) = progress {
+        progress.update(2, Some(STEPS)).await?;
+    }
+
+    scan(&data).await?;
+    storage.put_if_absent(&blob_key(algorithm, &hash), &data).await?;
+
+    let id = file.id();
+    let replaced = file
+        .replace_content(data.len() as i64, &hash, Some(sniff(&data, file.path())))
+        .await?
+        .ok_or(AppError::DeltaConflict)?;
+    StatCache::get().forget_file(&file);
+    let file = Pipeline::get().run(replaced, &data, storage).await?;
+    FileCache::get().forget(id);
+    purge(vec![file_key(id)]);
+
+    if let Some(progress) = progress {
+        progress.update(STEPS, Some(STEPS)).await?;
+    }
+
+    info!("Patched file {id} to {} bytes, {literal} of them sent", data.len());
+
+    Ok(file)
+}
+
+/// Appendable files aren't addressed by their content, so they can't be patched.
+fn content_hash(file: &FileModel) -> Result<String, AppError> {
+    file.hash()
+        .map(str::to_owned)
+        .ok_or(AppError::InvalidDelta("appendable files are appended to instead"))
+}
+
+/// Rebuilds a file from the stored `base` and the instructions of `delta`,
+/// refusing to produce more than `max_size` bytes.
+///
+/// Returns the patched content and how many literal bytes were sent.
+pub fn apply(
+    base: &[u8],
+    block_size: usize,
+    delta: &[u8],
+    max_size: u64,
+) -> Result<(Vec<u8>, u64), AppError> {
+    let mut output = Vec::with_capacity(base.len());
+    let mut literal = 0;
+    let mut delta = delta;
+
+    while let Some((&op, rest)) = delta.split_first() {
+        delta = rest;
+
+        let bytes = match op {
+            OP_COPY => {
+                let block = u64::from_be_bytes(take(&mut delta)?);
+                let count = u32::from_be_bytes(take(&mut delta)?);
+
+                let start = usize::try_from(block)
+                    .ok()
+                    .and_then(|block| block.checked_mul(block_size))
+                    .filter(|start| *start < base.len())
+                    .ok_or(AppError::InvalidDelta("a copy starts past the stored content"))?;
+                let end = (count as usize)
+                    .checked_mul(block_size)
+                    .and_then(|length| start.checked_add(length))
+                    .ok_or(AppError::InvalidDelta("a copy ends past the stored content"))?;
+
+                &base[start..end.min(base.len())]
+            },
+            OP_LITERAL => {
+                let length = u32::from_be_bytes(take(&mut delta)?) as usize;
+
+                if delta.len() < length {
+                    return Err(AppError::InvalidDelta("a literal is cut short"));
+                }
+
+                let (bytes, rest) = delta.split_at(length);
+                delta = rest;
+                literal += length as u64;
+
+                bytes
+            },
+            _ => return Err(AppError::InvalidDelta("unknown instruction")),
+        };
+
+        if (output.len() + bytes.len()) as u64 > max_size {
+            return Err(AppError::DeltaTooLarge(max_size));
+        }
+
+        output.extend_from_slice(bytes);
+    }
+
+    Ok((output, literal))
+}
+
+fn take<const N: usize>(delta: &mut &[u8]) -> Result<[u8; N], AppError> {
+    let Some((bytes, rest)) = delta.split_first_chunk::<N>() else {
+        return Err(AppError::InvalidDelta("an instruction is cut short"));
+    };
+
+    *delta = rest;
+
+    Ok(*bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{create_bucket, create_user, run, unique_name, upload_file};
+    use crate::utils::app_storage::open_storage;
+
+    const BLOCK_SIZE: usize = MIN_BLOCK_SIZE;
+
+    /// Deterministic content that doesn't repeat within a block.
+    fn content(length: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+
+        (0..length)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn copy(block: u64, count: u32) -> Vec<u8> {
+        [&[OP_COPY][..], &block.to_be_bytes(), &count.to_be_bytes()].concat()
+    }
+
+    fn literal(bytes: &[u8]) -> Vec<u8> {
+        [&[OP_LITERAL][..], &(bytes.len() as u32).to_be_bytes(), bytes].concat()
+    }
+
+    /// What a client computes against a signature, checking every offset.
+    fn delta(signature: &Signature, data: &[u8]) -> Vec<u8> {
+        let (mut delta, mut pending, mut offset) = (Vec::new(), Vec::new(), 0);
+
+        while offset < data.len() {
+            let block = &data[offset..(offset + signature.block_size).min(data.len())];
+            let weak = weak_checksum(block);
+
+            let found = signature
+                .blocks
+                .iter()
+                .position(|known| known.weak == weak && known.strong == strong_hash(block));
+
+            let Some(index) = found else {
+                pending.push(data[offset]);
+                offset += 1;
+                continue;
+            };
+
+            if !pending.is_empty() {
+                delta.extend(literal(&std::mem::take(&mut pending)));
+            }
+
+            delta.extend(copy(index as u64, 1));
+            offset += block.len();
+        }
+
+        if !pending.is_empty() {
+            delta.extend(literal(&pending));
+        }
+
+        delta
+    }
+
+    #[test]
+    fn deltas_rebuild_the_new_version() {
+        let base = content(BLOCK_SIZE * 5 + 100, 1);
+
+        let mut new = base.clone();
+        new.splice(BLOCK_SIZE * 2 + 10..BLOCK_SIZE * 2 + 10, *b"inserted");
+        new.truncate(new.len() - 50);
+        new.extend(content(300, 2));
+
+        let signature = signature(&base, BLOCK_SIZE, String::new());
+        let delta = delta(&signature, &new);
+        let (patched, literal) = apply(&base, BLOCK_SIZE, &delta, u64::MAX).unwrap();
+
+        assert_eq!(patched, new);
+        assert!(literal < (BLOCK_SIZE * 2) as u64);
+    }
+
+    #[test]
+    fn weak_checksum_rolls_forward() {
+        let data = content(BLOCK_SIZE + 64, 3);
+
+        for offset in 0..64 {
+            let weak = weak_checksum(&data[offset..offset + BLOCK_SIZE]);
+            let (removed, added) = (u32::from(data[offset]), u32::from(data[offset + BLOCK_SIZE]));
+
+            let a = (weak & 0xffff).wrapping_sub(removed).wrapping_add(added) & 0xffff;
+            let b = (weak >> 16).wrapping_sub(BLOCK_SIZE as u32 * removed).wrapping_add(a) & 0xffff;
+
+            assert_eq!(a | (b << 16), weak_checksum(&data[offset + 1..offset + 1 + BLOCK_SIZE]));
+        }
+    }
+
+    #[test]
+    fn malformed_deltas_are_refused() {
+        let base = content(BLOCK_SIZE * 2, 4);
+
+        for delta in [
+            copy(2, 1),
+            copy(u64::MAX, 1),
+            literal(b"cut short")[..8].to_vec(),
+            vec![OP_COPY, 0, 0],
+            vec![7],
+        ] {
+            assert!(matches!(
+                apply(&base, BLOCK_SIZE, &delta, u64::MAX),
+                Err(AppError::InvalidDelta(_))
+            ));
+        }
+
+        assert!(matches!(
+            apply(&base, BLOCK_SIZE, &copy(0, 2), BLOCK_SIZE as u64),
+            Err(AppError::DeltaTooLarge(_))
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn patching_forgets_the_size_of_the_replaced_content() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("delta")).await).await;
+            let base = content(BLOCK_SIZE * 3, rand::random());
+            let file = upload_file(&storage, &bucket, "patched.bin", &base).await;
+            let key = file_blob_key(&file).unwrap();
+
+            assert_eq!(StatCache::get().size(&storage, &key).await.unwrap(), base.len() as u64);
+
+            let mut new = base.clone();
+            new.extend(b"appended");
+            let delta = delta(&signature(&base, BLOCK_SIZE, String::new()), &new);
+            let checksum = HashAlgorithm::parse(file.hash_algorithm()).unwrap().digest(&new);
+
+            let patched = patch(&storage, file, BLOCK_SIZE, &checksum, delta, None).await.unwrap();
+
+            assert!(StatCache::get().lookup(&key).is_none());
+            assert_eq!(patched.size(), new.len() as i64);
+            assert!(patched.size_verified());
+        })
+    }
+}
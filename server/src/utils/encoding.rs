@@ -0,0 +1,66 @@
+use std::borrow::Cow;
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+
+/// Only the head of an upload is looked at, that's
+/// enough for detection and keeps large uploads cheap.
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Recorded when a text upload didn't match any encoding confidently.
+pub const UNKNOWN_ENCODING: &str = "unknown";
+
+/// Whether `data` looks like text, files with a byte order mark always
+/// do, otherwise anything with a known magic number or a NUL byte doesn't.
+pub fn is_text_like(data: &[u8]) -> bool {
+    if data.is_empty() {
+        return false;
+    }
+
+    if Encoding::for_bom(data).is_some() {
+        return true;
+    }
+
+    let sample = &data[..data.len().min(SAMPLE_SIZE)];
+
+    infer::get(sample).is_none() && !sample.contains(&0)
+}
+
+/// Detects the encoding of a text upload, `None` when the
+/// detector wasn't confident enough to pick one.
+pub fn detect_encoding(data: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(data) {
+        return Some(encoding);
+    }
+
+    let sample = &data[..data.len().min(SAMPLE_SIZE)];
+
+    // A sample cut mid character is still UTF-8 as long as
+    // the only error is the truncated sequence at its end.
+    let valid_utf8 = match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(error) => error.error_len().is_none() && sample.len() < data.len(),
+    };
+
+    if valid_utf8 {
+        return Some(UTF_8);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, sample.len() == data.len());
+
+    let (encoding, confident) = detector.guess_assess(None, true);
+
+    confident.then_some(encoding)
+}
+
+/// Transcodes `data` from `encoding` to UTF-8 dropping any byte order mark,
+/// malformed sequences are replaced rather than failing the upload.
+pub fn to_utf8<'a>(data: &'a [u8], encoding: &'static Encoding) -> Cow<'a, [u8]> {
+    let (text, _) = encoding.decode_with_bom_removal(data);
+
+    match text {
+        Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+        Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+    }
+}
@@ -0,0 +1,302 @@
+use std::fmt::Write;
+
+use chrono::NaiveDateTime;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::utils::dav::escape;
+use crate::utils::hashing::hex;
+
+/// Bytes of the HMAC kept in feed tokens.
+const TOKEN_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+impl FeedFormat {
+    /// The format served under `feed.<extension>`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "atom" => Some(FeedFormat::Atom),
+            "rss" => Some(FeedFormat::Rss),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "atom",
+            FeedFormat::Rss => "rss",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+}
+
+/// The newest files of a bucket as a feed readers can follow.
+pub struct Feed {
+    pub title: String,
+    /// Where the feed itself is served, token included.
+    pub self_url: String,
+    /// The most recent change to any entry, the bucket creation when empty.
+    pub updated: NaiveDateTime,
+    pub entries: Vec<FeedEntry>,
+}
+
+pub struct FeedEntry {
+    pub title: String,
+    /// The page of the file, also its id since it's stable.
+    pub link: String,
+    /// The content itself, with its type and size in bytes.
+    pub enclosure_url: String,
+    pub content_type: String,
+    pub length: u64,
+    pub published: NaiveDateTime,
+    pub updated: NaiveDateTime,
+}
+
+impl Feed {
+    pub fn render(&self, format: FeedFormat) -> String {
+        match format {
+            FeedFormat::Atom => self.atom(),
+            FeedFormat::Rss => self.rss(),
+        }
+    }
+
+    /// An Atom 1.0 document, RFC 4287.
+    fn atom(&self) -> String {
+        let mut xml = String::from(concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            "\n",
+            r#"<feed xmlns="http://www.w3.org/2005/Atom">"#,
+            "\n",
+        ));
+
+        let _ = writeln!(xml, "<id>{}</id>", escape(&self.self_url));
+        let _ = writeln!(xml, "<title>{}</title>", escape(&self.title));
+        let _ = writeln!(xml, "<updated>{}</updated>", rfc3339(self.updated));
+        let _ = writeln!(xml, "<link rel=\"self\" href=\"{}\"/>", escape(&self.self_url));
+        let _ = writeln!(xml, "<author><name>{}</name></author>", escape(&self.title));
+
+        for entry in &self.entries {
+            let _ = write!(xml, "<entry><id>{}</id>", escape(&entry.link));
+            let _ = write!(xml, "<title>{}</title>", escape(&entry.title));
+            let _ = write!(xml, "<link rel=\"alternate\" href=\"{}\"/>", escape(&entry.link));
+            let _ = write!(
+                xml,
+                "<link rel=\"enclosure\" type=\"{}\" length=\"{}\" href=\"{}\"/>",
+                escape(&entry.content_type),
+                entry.length,
+                escape(&entry.enclosure_url)
+            );
+            let _ = write!(xml, "<published>{}</published>", rfc3339(entry.published));
+            let _ = writeln!(xml, "<updated>{}</updated></entry>", rfc3339(entry.updated));
+        }
+
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    /// An RSS 2.0 document, linking to itself through Atom as validators expect.
+    fn rss(&self) -> String {
+        let mut xml = String::from(concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            "\n",
+            r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>"#,
+            "\n",
+        ));
+
+        let _ = writeln!(xml, "<title>{}</title>", escape(&self.title));
+        let _ = writeln!(xml, "<link>{}</link>", escape(&self.self_url));
+        let _ = writeln!(xml, "<description>{}</description>", escape(&self.title));
+        let _ = writeln!(
+            xml,
+            "<atom:link rel=\"self\" type=\"application/rss+xml\" href=\"{}\"/>",
+            escape(&self.self_url)
+        );
+        let _ = writeln!(xml, "<lastBuildDate>{}</lastBuildDate>", rfc822(self.updated));
+
+        for entry in &self.entries {
+            let _ = write!(xml, "<item><title>{}</title>", escape(&entry.title));
+            let _ = write!(xml, "<link>{}</link>", escape(&entry.link));
+            let _ = write!(xml, "<guid isPermaLink=\"true\">{}</guid>", escape(&entry.link));
+            let _ = write!(xml, "<pubDate>{}</pubDate>", rfc822(entry.published));
+            let _ = writeln!(
+                xml,
+                "<enclosure url=\"{}\" length=\"{}\" type=\"{}\"/></item>",
+                escape(&entry.enclosure_url),
+                entry.length,
+                escape(&entry.content_type)
+            );
+        }
+
+        xml.push_str("</channel></rss>\n");
+        xml
+    }
+}
+
+/// The token unlisted feeds are read with, it's only
+/// bound to the bucket so it lasts as long as the secret.
+pub fn feed_token(bucket_id: i64) -> String {
+    hex(&feed_mac(bucket_id).finalize().into_bytes()[..TOKEN_LENGTH])
+}
+
+/// Whether `token` was minted for the bucket, compared in constant time.
+pub fn verify_feed_token(bucket_id: i64, token: &str) -> bool {
+    let token = (0..token.len())
+        .step_by(2)
+        .map(|start| token.get(start..start + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect::<Option<Vec<_>>>();
+
+    match token {
+        Some(token) if token.len() == TOKEN_LENGTH => {
+            feed_mac(bucket_id).verify_truncated_left(&token).is_ok()
+        },
+        _ => false,
+    }
+}
+
+fn feed_mac(bucket_id: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(Config::get().signing_secret.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("feed:{bucket_id}").as_bytes());
+
+    mac
+}
+
+fn rfc3339(date: NaiveDateTime) -> String {
+    date.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn rfc822(date: NaiveDateTime) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 5, day).unwrap().and_hms_opt(8, 15, 0).unwrap()
+    }
+
+    fn feed() -> Feed {
+        Feed {
+            title: "wallpapers & more".into(),
+            self_url: "https://cdn.example.com/c/wallpapers/feed.atom?token=ab".into(),
+            updated: at(3),
+            entries: vec![
+                FeedEntry {
+                    title: "<dawn>.png".into(),
+                    link: "https://cdn.example.com/f/dawn".into(),
+                    enclosure_url: "https://usercontent.example.com/f/dawn".into(),
+                    content_type: "image/png".into(),
+                    length: 48213,
+                    published: at(2),
+                    updated: at(3),
+                },
+                FeedEntry {
+                    title: "dusk.jpg".into(),
+                    link: "https://cdn.example.com/f/dusk".into(),
+                    enclosure_url: "https://usercontent.example.com/f/dusk".into(),
+                    content_type: "image/jpeg".into(),
+                    length: 0,
+                    published: at(1),
+                    updated: at(1),
+                },
+            ],
+        }
+    }
+
+    /// The elements between every `<tag>` and its closing tag.
+    fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+        xml.split(&format!("<{tag}>"))
+            .skip(1)
+            .map(|rest| rest.split(&format!("</{tag}>")).next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn atom_feeds_carry_what_rfc_4287_requires() {
+        let atom = feed().render(FeedFormat::Atom);
+        let (head, _) = atom.split_once("<entry>").unwrap();
+
+        assert!(head.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns="));
+        // A feed has an id, a title, an update date and an author.
+        for tag in ["id", "title", "updated", "author"] {
+            assert_eq!(elements(head, tag).len(), 1, "{tag}");
+        }
+        assert!(head.contains("<updated>2025-05-03T08:15:00Z</updated>"));
+        assert!(head.contains("href=\"https://cdn.example.com/c/wallpapers/feed.atom?token=ab\""));
+
+        let entries = elements(&atom, "entry");
+        assert_eq!(entries.len(), 2);
+
+        for entry in &entries {
+            for tag in ["id", "title", "updated", "published"] {
+                assert_eq!(elements(entry, tag).len(), 1, "{tag} in {entry}");
+            }
+        }
+
+        assert_eq!(elements(entries[0], "title"), ["&lt;dawn&gt;.png"]);
+        assert!(entries[0].contains(
+            "<link rel=\"enclosure\" type=\"image/png\" length=\"48213\" \
+             href=\"https://usercontent.example.com/f/dawn\"/>"
+        ));
+        assert_eq!(elements(entries[1], "published"), ["2025-05-01T08:15:00Z"]);
+        assert!(atom.ends_with("</entry>\n</feed>\n"));
+    }
+
+    #[test]
+    fn rss_feeds_enclose_every_file() {
+        let rss = feed().render(FeedFormat::Rss);
+
+        assert_eq!(elements(&rss, "title")[0], "wallpapers &amp; more");
+        assert_eq!(elements(&rss, "lastBuildDate"), ["Sat, 03 May 2025 08:15:00 GMT"]);
+
+        let items = elements(&rss, "item");
+        assert_eq!(items.len(), 2);
+        assert_eq!(elements(items[1], "pubDate"), ["Thu, 01 May 2025 08:15:00 GMT"]);
+        assert!(items[1].contains(
+            "<link>https://cdn.example.com/f/dusk</link>\
+             <guid isPermaLink=\"true\">https://cdn.example.com/f/dusk</guid>"
+        ));
+        assert!(items[1].contains(
+            "<enclosure url=\"https://usercontent.example.com/f/dusk\" length=\"0\" \
+             type=\"image/jpeg\"/>"
+        ));
+        assert!(rss.ends_with("</channel></rss>\n"));
+    }
+
+    #[test]
+    fn formats_are_named_by_their_extension() {
+        for format in [FeedFormat::Atom, FeedFormat::Rss] {
+            assert_eq!(FeedFormat::from_extension(format.extension()), Some(format));
+        }
+
+        assert_eq!(FeedFormat::from_extension("json"), None);
+    }
+
+    #[test]
+    #[ignore = "needs SIGNING_SECRET"]
+    fn tokens_only_open_the_feed_they_were_minted_for() {
+        let token = feed_token(7);
+
+        assert_eq!(token.len(), TOKEN_LENGTH * 2);
+        assert!(verify_feed_token(7, &token));
+        assert!(!verify_feed_token(8, &token));
+        assert!(!verify_feed_token(7, &token[2..]));
+        assert!(!verify_feed_token(7, "not a token"));
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use database::ServedFile;
+use hashlink::LinkedHashMap;
+
+use crate::config::Config;
+
+static FILE_CACHE: OnceLock<FileCache> = OnceLock::new();
+
+struct Entry {
+    served: ServedFile,
+    expires_at: Instant,
+}
+
+/// Files recently looked up by slug, so serving a hot file doesn't query
+/// the database every time, bounded by `FILE_CACHE_CAPACITY` and expiring
+/// after `FILE_CACHE_TTL` seconds.
+///
+/// The owner and indexability cached along are only refreshed on expiry,
+/// a change to the preference of the owner takes up to the TTL to apply.
+///
+/// Entries are kept from the least to the most recently used, so the
+/// one to evict when full is always the first.
+///
+/// Every change to a file must `forget` it.
+pub struct FileCache {
+    entries: Mutex<LinkedHashMap<String, Entry>>,
+}
+
+impl FileCache {
+    pub fn get() -> &'static Self {
+        FILE_CACHE.get_or_init(|| FileCache {
+            entries: Mutex::new(LinkedHashMap::new()),
+        })
+    }
+
+    pub fn lookup(&self, slug: &str) -> Option<ServedFile> {
+        let mut entries = self.entries.lock().unwrap_or_else(|error| error.into_inner());
+
+        match entries.to_back(slug) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.served.clone()),
+            Some(_) => {
+                entries.remove(slug);
+                None
+            },
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, served: &ServedFile) {
+        let config = Config::get();
+
+        if config.file_cache_ttl == 0 {
+            return;
+        }
+
+        let mut ttl = Duration::from_secs(config.file_cache_ttl);
+
+        // Lookups stop finding an expiring file once it expired, so must the cache.
+        if let Some(expires_at) = served.file.expires_at() {
+            match (expires_at - Utc::now().naive_utc()).to_std() {
+                Ok(left) => ttl = ttl.min(left),
+                Err(_) => return,
+            }
+        }
+
+        self.insert_for(served, ttl, config.file_cache_capacity);
+    }
+
+    fn insert_for(&self, served: &ServedFile, ttl: Duration, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap_or_else(|error| error.into_inner());
+        let slug = served.file.slug();
+        entries.remove(slug);
+
+        while entries.len() >= capacity {
+            entries.pop_front();
+        }
+
+        entries.insert(
+            slug.to_owned(),
+            Entry {
+                served: served.clone(),
+                expires_at: Instant::now() + ttl,
+            },
+        );
+    }
+
+    /// Drops the file `id` under any slug it was cached by, called
+    /// whenever it's edited, rotated, processed or deleted.
+    pub fn forget(&self, id: i64) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .retain(|_, entry| entry.served.file.id() != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use database::{FileCreation, FileModel};
+
+    use super::*;
+    use crate::testing::{create_bucket, create_file, create_user, run, unique_name};
+
+    async fn served(file: FileModel) -> ServedFile {
+        file.into_served().await.unwrap()
+    }
+
+    fn cache() -> FileCache {
+        FileCache {
+            entries: Mutex::new(LinkedHashMap::new()),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn entries_expire_after_their_ttl() {
+        run(async {
+            let bucket = create_bucket(&create_user(&unique_name("cache")).await).await;
+            let file = served(create_file(&bucket, "brief.txt").await).await;
+            let cache = cache();
+
+            cache.insert_for(&file, Duration::from_millis(50), 8);
+            assert!(cache.lookup(file.file.slug()).is_some());
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(cache.lookup(file.file.slug()).is_none());
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn the_least_recently_used_file_is_evicted() {
+        run(async {
+            let bucket = create_bucket(&create_user(&unique_name("cache")).await).await;
+            let [first, second, third] = [
+                served(create_file(&bucket, "first.txt").await).await,
+                served(create_file(&bucket, "second.txt").await).await,
+                served(create_file(&bucket, "third.txt").await).await,
+            ];
+            let cache = cache();
+            let ttl = Duration::from_secs(60);
+
+            cache.insert_for(&first, ttl, 2);
+            cache.insert_for(&second, ttl, 2);
+            assert!(cache.lookup(first.file.slug()).is_some());
+            cache.insert_for(&third, ttl, 2);
+
+            assert!(cache.lookup(second.file.slug()).is_none());
+            assert!(cache.lookup(first.file.slug()).is_some());
+            assert!(cache.lookup(third.file.slug()).is_some());
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn files_are_not_cached_past_their_expiry() {
+        run(async {
+            let bucket = create_bucket(&create_user(&unique_name("cache")).await).await;
+            let file = FileModel::create_new(FileCreation {
+                bucket_id: bucket.id(),
+                path: "expired.txt".into(),
+                size: 0,
+                hash: format!("{:064x}", rand::random::<u128>()),
+                hash_algorithm: "sha256".into(),
+                created_at: None,
+                expires_at: Some(Utc::now().naive_utc() - TimeDelta::seconds(1)),
+                text: None,
+                name_key: b"expired.txt".to_vec(),
+                content_type: None,
+            })
+            .await
+            .unwrap();
+            let file = served(file).await;
+            let cache = cache();
+
+            cache.insert(&file);
+            assert!(cache.lookup(file.file.slug()).is_none());
+        })
+    }
+}
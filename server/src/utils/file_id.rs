@@ -0,0 +1,171 @@
+use std::sync::OnceLock;
+
+use crate::config::Config;
+
+const ALPHABET: &[u8; 62] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Base62 digits needed for any `u64`, ids are always this long so
+/// consecutive ones don't give themselves away by their length.
+const DIGITS: usize = 11;
+
+/// Trailing characters checking the digits, a tampered id
+/// decodes by chance once in 3844 tries.
+const CHECK_LENGTH: usize = 2;
+
+/// Feistel rounds of the permutation, enough for ids to look unrelated.
+const ROUNDS: u8 = 4;
+
+static KEY: OnceLock<Option<[u8; 32]>> = OnceLock::new();
+
+/// The key derived from `FILE_ID_SALT`, unset when obfuscation is off.
+fn key() -> Option<&'static [u8; 32]> {
+    KEY.get_or_init(|| {
+        let salt = &Config::get().file_id_salt;
+        (!salt.is_empty())
+            .then(|| blake3::derive_key("cdn file id obfuscation v1", salt.as_bytes()))
+    })
+    .as_ref()
+}
+
+pub fn enabled() -> bool {
+    key().is_some()
+}
+
+fn round(key: &[u8; 32], round: u8, half: u32) -> u32 {
+    let mut input = [0; 5];
+    input[0] = round;
+    input[1..].copy_from_slice(&half.to_le_bytes());
+
+    let hash = blake3::keyed_hash(key, &input);
+    u32::from_le_bytes(hash.as_bytes()[..4].try_into().expect("A hash has 32 bytes"))
+}
+
+/// A keyed permutation of every `u64`, `unpermute` reverses it.
+fn permute(key: &[u8; 32], value: u64) -> u64 {
+    let (mut left, mut right) = ((value >> 32) as u32, value as u32);
+
+    for index in 0..ROUNDS {
+        (left, right) = (right, left ^ round(key, index, right));
+    }
+
+    ((left as u64) << 32) | right as u64
+}
+
+fn unpermute(key: &[u8; 32], value: u64) -> u64 {
+    let (mut left, mut right) = ((value >> 32) as u32, value as u32);
+
+    for index in (0..ROUNDS).rev() {
+        (left, right) = (right ^ round(key, index, left), left);
+    }
+
+    ((left as u64) << 32) | right as u64
+}
+
+fn check(key: &[u8; 32], digits: &[u8]) -> [u8; CHECK_LENGTH] {
+    let hash = blake3::keyed_hash(key, digits);
+    let value = u16::from_le_bytes([hash.as_bytes()[0], hash.as_bytes()[1]]) as usize;
+
+    [ALPHABET[value % 62], ALPHABET[value / 62 % 62]]
+}
+
+/// The id shown to clients, obfuscated when `FILE_ID_SALT` is set.
+pub fn encode(id: i64) -> String {
+    encode_with(key(), id)
+}
+
+fn encode_with(key: Option<&[u8; 32]>, id: i64) -> String {
+    let Some(key) = key else {
+        return id.to_string();
+    };
+
+    let mut value = permute(key, id as u64);
+    let mut digits = [0; DIGITS];
+
+    for digit in digits.iter_mut().rev() {
+        *digit = ALPHABET[(value % 62) as usize];
+        value /= 62;
+    }
+
+    let mut encoded = digits.to_vec();
+    encoded.extend_from_slice(&check(key, &digits));
+
+    String::from_utf8(encoded).expect("The alphabet is ASCII")
+}
+
+/// The id `encoded` by `encode`, `None` when it was tampered with, isn't
+/// obfuscated while it should be or obfuscated while it shouldn't.
+pub fn decode(encoded: &str) -> Option<i64> {
+    decode_with(key(), encoded)
+}
+
+fn decode_with(key: Option<&[u8; 32]>, encoded: &str) -> Option<i64> {
+    let Some(key) = key else {
+        return encoded.parse().ok().filter(|id| *id >= 0);
+    };
+
+    let bytes = encoded.as_bytes();
+
+    if bytes.len() != DIGITS + CHECK_LENGTH {
+        return None;
+    }
+
+    let (digits, checked) = bytes.split_at(DIGITS);
+
+    if check(key, digits) != checked {
+        return None;
+    }
+
+    let mut value = 0u64;
+
+    for digit in digits {
+        let digit = ALPHABET.iter().position(|character| character == digit)? as u64;
+        value = value.checked_mul(62)?.checked_add(digit)?;
+    }
+
+    i64::try_from(unpermute(key, value)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn ids_round_trip() {
+        for id in [0, 1, 2, 61, 62, 1_000_000, i64::MAX] {
+            let encoded = encode_with(Some(&KEY), id);
+
+            assert_eq!(encoded.len(), DIGITS + CHECK_LENGTH);
+            assert_eq!(decode_with(Some(&KEY), &encoded), Some(id));
+        }
+    }
+
+    #[test]
+    fn consecutive_ids_look_unrelated() {
+        let (first, second) = (encode_with(Some(&KEY), 41), encode_with(Some(&KEY), 42));
+
+        assert_ne!(first[..DIGITS - 1], second[..DIGITS - 1]);
+        assert_ne!(encode_with(Some(&[8; 32]), 42), second);
+    }
+
+    #[test]
+    fn tampered_ids_are_refused() {
+        let encoded = encode_with(Some(&KEY), 42);
+        let mut tampered = encoded.clone().into_bytes();
+        tampered[0] = if tampered[0] == b'0' { b'1' } else { b'0' };
+
+        assert_eq!(decode_with(Some(&KEY), std::str::from_utf8(&tampered).unwrap()), None);
+        assert_eq!(decode_with(Some(&KEY), &encoded[1..]), None);
+        assert_eq!(decode_with(Some(&KEY), "42"), None);
+        assert_eq!(decode_with(Some(&[8; 32]), &encoded), None);
+    }
+
+    #[test]
+    fn plain_ids_without_a_key() {
+        assert_eq!(encode_with(None, 42), "42");
+        assert_eq!(decode_with(None, "42"), Some(42));
+        assert_eq!(decode_with(None, "-1"), None);
+        assert_eq!(decode_with(None, &encode_with(Some(&KEY), 42)), None);
+    }
+}
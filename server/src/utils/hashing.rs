@@ -0,0 +1,141 @@
+use std::fmt::Write;
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+/// The digest used for deduplication and ETags, the name is
+/// stored next to every hash so hashes of different algorithms never match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+    Md5,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 3] =
+        [HashAlgorithm::Sha256, HashAlgorithm::Blake3, HashAlgorithm::Md5];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            "md5" => Some(HashAlgorithm::Md5),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Md5 => "md5",
+        }
+    }
+
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+        }
+    }
+
+    /// Hashes `data` at once, returning the lowercase hex digest.
+    pub fn digest(&self, data: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// The storage key for a blob, blobs are spread over two
+/// directory levels and kept apart per algorithm.
+pub fn blob_key(algorithm: HashAlgorithm, hash: &str) -> String {
+    format!("{}/{}/{}/{hash}", algorithm.name(), &hash[..2], &hash[2..4])
+}
+
+/// An incremental hasher for bodies received in chunks.
+pub enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Md5(Md5),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            },
+            Hasher::Md5(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => hex(&hasher.finalize()),
+            Hasher::Blake3(hasher) => hex(hasher.finalize().as_bytes()),
+            Hasher::Md5(hasher) => hex(&hasher.finalize()),
+        }
+    }
+}
+
+/// Lowercase hex digits of `bytes`.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_match_the_reference_vectors() {
+        assert_eq!(
+            HashAlgorithm::Sha256.digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            HashAlgorithm::Blake3.digest(b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(HashAlgorithm::Md5.digest(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn chunked_bodies_hash_like_whole_ones() {
+        let data = b"the quick brown fox jumps over the lazy dog";
+
+        for algorithm in HashAlgorithm::ALL {
+            let mut hasher = algorithm.hasher();
+            data.chunks(7).for_each(|chunk| hasher.update(chunk));
+
+            assert_eq!(hasher.finalize(), algorithm.digest(data), "{}", algorithm.name());
+        }
+    }
+
+    #[test]
+    fn the_same_content_is_stored_apart_per_algorithm() {
+        let data = b"shared content";
+        let keys = HashAlgorithm::ALL.map(|algorithm| blob_key(algorithm, &algorithm.digest(data)));
+
+        assert!(keys[0].starts_with("sha256/"));
+        assert!(keys[1].starts_with("blake3/"));
+        assert!(keys[2].starts_with("md5/"));
+    }
+
+    #[test]
+    fn names_round_trip_case_insensitively() {
+        for algorithm in HashAlgorithm::ALL {
+            assert_eq!(HashAlgorithm::parse(algorithm.name()), Some(algorithm));
+            assert_eq!(HashAlgorithm::parse(&algorithm.name().to_uppercase()), Some(algorithm));
+        }
+
+        assert_eq!(HashAlgorithm::parse("sha1"), None);
+    }
+}
@@ -0,0 +1,149 @@
+use actix_web::HttpMessage;
+use actix_web::http::header::HeaderName;
+use thiserror::Error as ThisError;
+
+/// Longest header value read, longer ones are refused rather than parsed.
+pub const MAX_HEADER_VALUE_LENGTH: usize = 8 * 1024;
+
+#[derive(Debug, ThisError)]
+pub enum HeaderError {
+    #[error("The {0} header was sent more than once with different values")]
+    Duplicate(HeaderName),
+
+    #[error("The {0} header is longer than {MAX_HEADER_VALUE_LENGTH} bytes")]
+    TooLong(HeaderName),
+
+    #[error("The {0} header isn't valid text")]
+    NotText(HeaderName),
+}
+
+/// Reads a header that must hold a single value, such as `Range` or
+/// `If-Match`, repeating it with the same value is tolerated.
+pub fn single_header_value(
+    req: &impl HttpMessage,
+    name: HeaderName,
+) -> Result<Option<&str>, HeaderError> {
+    let mut values = req.headers().get_all(&name);
+
+    let Some(first) = values.next() else {
+        return Ok(None);
+    };
+
+    if values.any(|value| value != first) {
+        return Err(HeaderError::Duplicate(name));
+    }
+
+    if first.len() > MAX_HEADER_VALUE_LENGTH {
+        return Err(HeaderError::TooLong(name));
+    }
+
+    first.to_str().map(Some).map_err(|_| HeaderError::NotText(name))
+}
+
+/// Reads the first usable value of an informative header such as
+/// `User-Agent`, which is never worth failing a request over.
+pub fn first_header_value(req: &impl HttpMessage, name: HeaderName) -> Option<&str> {
+    req.headers()
+        .get_all(name)
+        .filter(|value| value.len() <= MAX_HEADER_VALUE_LENGTH)
+        .find_map(|value| value.to_str().ok())
+}
+
+/// Reads a list header such as `X-Forwarded-For`, values sent on
+/// several lines are joined with commas in the order received.
+pub fn joined_header_values(
+    req: &impl HttpMessage,
+    name: HeaderName,
+) -> Result<Option<String>, HeaderError> {
+    let mut joined: Option<String> = None;
+
+    for value in req.headers().get_all(&name) {
+        let value = value.to_str().map_err(|_| HeaderError::NotText(name.clone()))?;
+
+        match &mut joined {
+            Some(joined) => {
+                joined.push_str(", ");
+                joined.push_str(value);
+            },
+            None => joined = Some(value.to_owned()),
+        }
+
+        if joined.as_ref().is_some_and(|joined| joined.len() > MAX_HEADER_VALUE_LENGTH) {
+            return Err(HeaderError::TooLong(name));
+        }
+    }
+
+    Ok(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderValue, RANGE, USER_AGENT};
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn single_values_tolerate_identical_repeats() {
+        let req = TestRequest::default()
+            .append_header((RANGE, "bytes=0-1"))
+            .append_header((RANGE, "bytes=0-1"))
+            .to_http_request();
+
+        assert_eq!(single_header_value(&req, RANGE).unwrap(), Some("bytes=0-1"));
+        assert_eq!(
+            single_header_value(&TestRequest::default().to_http_request(), RANGE).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn single_values_refuse_conflicts_and_oversized_values() {
+        let conflicting = TestRequest::default()
+            .append_header((RANGE, "bytes=0-1"))
+            .append_header((RANGE, "bytes=2-3"))
+            .to_http_request();
+        let long = TestRequest::default()
+            .insert_header((RANGE, "a".repeat(MAX_HEADER_VALUE_LENGTH + 1)))
+            .to_http_request();
+        let binary = TestRequest::default()
+            .insert_header((RANGE, HeaderValue::from_bytes(b"\xff").unwrap()))
+            .to_http_request();
+
+        assert!(matches!(single_header_value(&conflicting, RANGE), Err(HeaderError::Duplicate(_))));
+        assert!(matches!(single_header_value(&long, RANGE), Err(HeaderError::TooLong(_))));
+        assert!(matches!(single_header_value(&binary, RANGE), Err(HeaderError::NotText(_))));
+    }
+
+    #[test]
+    fn first_value_skips_unusable_ones() {
+        let req = TestRequest::default()
+            .append_header((USER_AGENT, HeaderValue::from_bytes(b"\xff").unwrap()))
+            .append_header((USER_AGENT, "curl/8.0"))
+            .to_http_request();
+
+        assert_eq!(first_header_value(&req, USER_AGENT), Some("curl/8.0"));
+    }
+
+    #[test]
+    fn list_values_are_joined_in_order() {
+        let name = HeaderName::from_static("x-forwarded-for");
+        let req = TestRequest::default()
+            .append_header((name.clone(), "192.0.2.1"))
+            .append_header((name.clone(), "10.0.0.1, 10.0.0.2"))
+            .to_http_request();
+
+        assert_eq!(
+            joined_header_values(&req, name.clone()).unwrap().as_deref(),
+            Some("192.0.2.1, 10.0.0.1, 10.0.0.2")
+        );
+
+        let long = "a".repeat(MAX_HEADER_VALUE_LENGTH / 2 + 1);
+        let req = TestRequest::default()
+            .append_header((name.clone(), long.as_str()))
+            .append_header((name.clone(), long.as_str()))
+            .to_http_request();
+
+        assert!(matches!(joined_header_values(&req, name), Err(HeaderError::TooLong(_))));
+    }
+}
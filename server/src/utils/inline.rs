@@ -0,0 +1,132 @@
+use actix_web::HttpRequest;
+
+use crate::config::Config;
+use crate::middleware::forwarded::ClientInfo;
+
+/// Types safe to render inline on our origin, anything else
+/// is downloaded so it can't run scripts against our cookies.
+const INLINE_ALLOWED: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/bmp",
+    "video/mp4",
+    "video/webm",
+    "video/ogg",
+    "audio/mpeg",
+    "audio/ogg",
+    "audio/wav",
+    "audio/flac",
+    "audio/aac",
+    "audio/mp4",
+    "application/pdf",
+    "text/plain",
+];
+
+/// How a file is handed to the browser.
+pub struct InlinePolicy {
+    pub content_type: &'static str,
+    pub inline: bool,
+}
+
+/// Decides the type and disposition of a file, HTML is served as
+/// `text/plain` unless `ALLOW_HTML_INLINE` is set and the request
+/// came through `SANDBOX_BASE_URL`.
+pub fn inline_policy(req: &HttpRequest, data: &[u8], path: &str) -> InlinePolicy {
+    let content_type = sniff(data, path);
+
+    if is_markup(content_type) {
+        let config = Config::get();
+
+        if config.allow_html_inline && is_sandbox(req, &config.sandbox_base_url) {
+            return InlinePolicy { content_type, inline: true };
+        }
+
+        return InlinePolicy {
+            content_type: "text/plain",
+            inline: false,
+        };
+    }
+
+    InlinePolicy {
+        content_type,
+        inline: INLINE_ALLOWED.contains(&content_type),
+    }
+}
+
+/// The type of a file from its content, falling back to its extension,
+/// markup is looked for first since magic numbers don't cover it.
+pub fn sniff(data: &[u8], path: &str) -> &'static str {
+    let head =
+        String::from_utf8_lossy(&data[..data.len().min(512)]).trim_start().to_ascii_lowercase();
+
+    let markup = ["<!doctype html", "<html", "<head", "<body", "<script", "<iframe", "<svg"]
+        .iter()
+        .any(|tag| head.starts_with(tag));
+
+    if markup {
+        return "text/html";
+    }
+
+    if let Some(kind) = infer::get(data) {
+        return kind.mime_type();
+    }
+
+    mime_guess::from_path(path).first_raw().unwrap_or("application/octet-stream")
+}
+
+/// The usual extension of `content_type`, for naming files stored without a
+/// name, the reverse of guessing a type from a name.
+pub fn mime_to_extension(content_type: &str) -> Option<&'static str> {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    // The registry lists rarer spellings first for these.
+    let preferred = match essence.as_str() {
+        "image/jpeg" => Some("jpg"),
+        "text/plain" => Some("txt"),
+        "audio/mpeg" => Some("mp3"),
+        "video/quicktime" => Some("mov"),
+        "application/octet-stream" => Some("bin"),
+        _ => None,
+    };
+
+    preferred.or_else(|| mime_guess::get_mime_extensions_str(&essence)?.first().copied())
+}
+
+fn is_markup(content_type: &str) -> bool {
+    matches!(
+        content_type,
+        "text/html" | "application/xhtml+xml" | "text/xml" | "application/xml" | "image/svg+xml"
+    )
+}
+
+fn is_sandbox(req: &HttpRequest, sandbox_base_url: &str) -> bool {
+    let sandbox_host =
+        sandbox_base_url.split_once("://").map_or(sandbox_base_url, |(_, rest)| rest);
+    let sandbox_host = sandbox_host.split('/').next().unwrap_or_default();
+
+    !sandbox_host.is_empty() && ClientInfo::get(req).host.eq_ignore_ascii_case(sandbox_host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mime_to_extension_maps_common_types() {
+        let cases = [
+            ("image/jpeg", Some("jpg")),
+            ("image/png", Some("png")),
+            ("text/plain; charset=utf-8", Some("txt")),
+            ("IMAGE/JPEG", Some("jpg")),
+            ("application/pdf", Some("pdf")),
+            ("application/x-not-a-type", None),
+        ];
+
+        for (content_type, expected) in cases {
+            assert_eq!(mime_to_extension(content_type), expected, "{content_type}");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use database::FileLicense;
+use serde_json::{Map, Value, json};
+
+use crate::AppError;
+
+/// The SPDX identifiers files can be licensed under, `LicenseRef-Proprietary`
+/// stands for all rights reserved.
+pub const LICENSES: [&str; 12] = [
+    "CC0-1.0",
+    "CC-BY-4.0",
+    "CC-BY-SA-4.0",
+    "CC-BY-ND-4.0",
+    "CC-BY-NC-4.0",
+    "CC-BY-NC-SA-4.0",
+    "CC-BY-NC-ND-4.0",
+    "MIT",
+    "Apache-2.0",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "LicenseRef-Proprietary",
+];
+
+/// Longest attribution accepted, in characters.
+pub const MAX_ATTRIBUTION_LENGTH: usize = 1024;
+
+/// Longest source URL accepted, in bytes.
+pub const MAX_SOURCE_URL_LENGTH: usize = 2048;
+
+/// The accepted spelling of a license identifier, matched case insensitively.
+pub fn canonical_license(id: &str) -> Result<&'static str, AppError> {
+    LICENSES
+        .into_iter()
+        .find(|license| license.eq_ignore_ascii_case(id.trim()))
+        .ok_or_else(|| AppError::InvalidLicense(id.into()))
+}
+
+/// Checks every part of `license` and spells its identifier the accepted
+/// way, empty parts are kept as they clear what was set before.
+pub fn check_license(mut license: FileLicense) -> Result<FileLicense, AppError> {
+    if let Some(id) = license.license.as_deref().filter(|id| !id.is_empty()) {
+        license.license = Some(canonical_license(id)?.to_owned());
+    }
+
+    if let Some(attribution) = &license.attribution {
+        let valid = attribution.chars().count() <= MAX_ATTRIBUTION_LENGTH
+            && !attribution.chars().any(|char| char.is_control() && char != '\n');
+
+        if !valid {
+            return Err(AppError::InvalidAttribution);
+        }
+    }
+
+    if license.source_url.as_deref().is_some_and(|url| !url.is_empty() && !is_source_url(url)) {
+        return Err(AppError::InvalidSourceUrl);
+    }
+
+    Ok(license)
+}
+
+/// Only absolute http and https URLs are linked to from attributions.
+fn is_source_url(url: &str) -> bool {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"));
+
+    url.len() <= MAX_SOURCE_URL_LENGTH
+        && rest.is_some_and(|rest| {
+            !rest.is_empty()
+                && !rest.starts_with('/')
+                && !rest.chars().any(|char| char.is_whitespace() || char.is_control())
+        })
+}
+
+/// Where the text of a license is published, proprietary ones have none.
+pub fn license_url(id: &str) -> Option<String> {
+    (!id.starts_with("LicenseRef-")).then(|| format!("https://spdx.org/licenses/{id}.html"))
+}
+
+/// schema.org markup of a video and its reuse terms, `None` when it has none,
+/// escaped so it can't close the script element it's embedded in.
+pub fn video_json_ld(name: &str, content_url: &str, license: &FileLicense) -> Option<String> {
+    if license.is_empty() {
+        return None;
+    }
+
+    let mut object = Map::new();
+    object.insert("@context".into(), json!("https://schema.org"));
+    object.insert("@type".into(), json!("VideoObject"));
+    object.insert("name".into(), json!(name));
+    object.insert("contentUrl".into(), json!(content_url));
+
+    if let Some(url) = license.license.as_deref().and_then(license_url) {
+        object.insert("license".into(), json!(url));
+    }
+
+    if let Some(attribution) = &license.attribution {
+        object.insert("creditText".into(), json!(attribution));
+    }
+
+    if let Some(source_url) = &license.source_url {
+        object.insert("isBasedOn".into(), json!(source_url));
+    }
+
+    Some(Value::Object(object).to_string().replace("</", "<\\/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn license_ids_ignore_case() {
+        assert_eq!(canonical_license("cc-by-4.0").ok(), Some("CC-BY-4.0"));
+        assert_eq!(canonical_license(" MIT ").ok(), Some("MIT"));
+        assert!(canonical_license("WTFPL").is_err());
+    }
+
+    #[test]
+    fn source_urls_must_be_absolute() {
+        assert!(is_source_url("https://example.com/art"));
+        assert!(!is_source_url("javascript:alert(1)"));
+        assert!(!is_source_url("https:///path"));
+        assert!(!is_source_url("http://exa mple.com"));
+    }
+
+    #[test]
+    fn json_ld_describes_the_terms() {
+        let license = FileLicense {
+            license: Some("CC-BY-4.0".into()),
+            attribution: Some("</script>Jane".into()),
+            source_url: None,
+        };
+
+        let markup = video_json_ld("clip.mp4", "https://cdn.test/f/abc", &license).unwrap();
+        let value: Value = serde_json::from_str(&markup).unwrap();
+
+        assert!(!markup.contains("</"));
+        assert_eq!(value["license"], "https://spdx.org/licenses/CC-BY-4.0.html");
+        assert_eq!(value["creditText"], "</script>Jane");
+        assert!(video_json_ld("clip.mp4", "", &FileLicense::default()).is_none());
+    }
+}
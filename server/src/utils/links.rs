@@ -0,0 +1,105 @@
+use actix_web::{HttpMessage, HttpRequest};
+use database::{FileModel, FileResult};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::middleware::forwarded::ClientInfo;
+use crate::middleware::tenant::Tenant;
+use crate::utils::dav::encode;
+use crate::utils::file_id;
+use crate::utils::hashing::hex;
+
+/// Bytes of the content tag kept in immutable URLs.
+const CONTENT_TAG_LENGTH: usize = 8;
+
+/// An absolute link to `path`, on the custom domain the request came
+/// through, on `PUBLIC_BASE_URL` or on the host the client asked for.
+pub fn public_url(req: &HttpRequest, path: &str) -> String {
+    format!("{}{path}", public_base_url(req).trim_end_matches('/'))
+}
+
+fn public_base_url(req: &HttpRequest) -> String {
+    if let Some(tenant) = req.extensions().get::<Tenant>() {
+        return tenant.base_url.clone();
+    }
+
+    let public_base_url = &Config::get().public_base_url;
+
+    if public_base_url.is_empty() {
+        let client = ClientInfo::get(req);
+        format!("{}://{}", client.scheme, client.host)
+    } else {
+        public_base_url.clone()
+    }
+}
+
+/// The base file content is linked under, the sandbox when HTML
+/// may render inline so it never runs on the primary origin.
+pub fn content_base_url(req: &HttpRequest) -> String {
+    let config = Config::get();
+
+    if config.allow_html_inline && !config.sandbox_base_url.is_empty() {
+        config.sandbox_base_url.clone()
+    } else {
+        public_base_url(req)
+    }
+}
+
+/// The API representation of a file, linking to its content.
+pub fn file_result(req: &HttpRequest, file: &FileModel) -> FileResult {
+    file_result_at(file, &content_base_url(req))
+}
+
+/// Like `file_result`, its content linked under `base_url`
+/// and its id obfuscated when `FILE_ID_SALT` is set.
+pub fn file_result_at(file: &FileModel, base_url: &str) -> FileResult {
+    let mut result = file.into_result().with_base_url(base_url);
+
+    if let Some(path) = immutable_path(file) {
+        result = result.with_immutable_url(format!("{}{path}", base_url.trim_end_matches('/')));
+    }
+
+    if file_id::enabled() { result.with_obfuscated_id(file_id::encode(file.id())) } else { result }
+}
+
+/// The path the current content of `file` is served at for good, when
+/// content addressed URLs are enabled and its hash is known.
+pub fn immutable_path(file: &FileModel) -> Option<String> {
+    if !Config::get().content_addressed_urls {
+        return None;
+    }
+
+    let tag = content_mac(file)?.finalize().into_bytes();
+    let name = file.path().rsplit('/').next().unwrap_or_default();
+
+    Some(format!("/f/{}-{}/{}", file.hash()?, hex(&tag[..CONTENT_TAG_LENGTH]), encode(name)))
+}
+
+/// Whether `tag` is the one the immutable URL of `file` carries,
+/// compared in constant time.
+pub fn verify_content_tag(file: &FileModel, tag: &str) -> bool {
+    let tag = (0..tag.len())
+        .step_by(2)
+        .map(|start| tag.get(start..start + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect::<Option<Vec<_>>>();
+
+    match (tag, content_mac(file)) {
+        (Some(tag), Some(mac)) if tag.len() == CONTENT_TAG_LENGTH => {
+            mac.verify_truncated_left(&tag).is_ok()
+        },
+        _ => false,
+    }
+}
+
+/// Keyed on the file as well as its content, so knowing a hash
+/// doesn't reach the files of other owners holding the same content.
+///
+/// The slug is keyed too, rotating the link retires the URL with it.
+fn content_mac(file: &FileModel) -> Option<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(Config::get().signing_secret.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("content:{}:{}:{}", file.id(), file.slug(), file.hash()?).as_bytes());
+
+    Some(mac)
+}
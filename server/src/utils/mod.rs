@@ -0,0 +1,38 @@
+pub mod app_storage;
+pub mod append;
+pub mod archive;
+pub mod compression;
+pub mod concurrency;
+pub mod csrf;
+pub mod dashboard;
+pub mod dav;
+pub mod delta;
+pub mod encoding;
+pub mod feed;
+pub mod file_cache;
+pub mod file_id;
+pub mod hashing;
+pub mod headers;
+pub mod inline;
+pub mod license;
+pub mod links;
+pub mod multipart;
+pub mod normalize;
+pub mod not_found;
+pub mod oembed;
+pub mod placeholder;
+pub mod post_policy;
+pub mod purge;
+pub mod range;
+pub mod serving;
+pub mod signing;
+pub mod sitemap;
+pub mod stat_cache;
+pub mod tags;
+pub mod traffic;
+pub mod upload;
+pub mod upload_limit;
+pub mod upload_slots;
+pub mod usage;
+pub mod view_stats;
+pub mod webhooks;
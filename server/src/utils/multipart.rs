@@ -0,0 +1,53 @@
+use chrono::{TimeDelta, Utc};
+use database::MultipartUploadModel;
+use storage::{CompletedPart, MultipartStorage};
+
+use crate::AppError;
+use crate::config::Config;
+
+/// Size of every part but the last, S3 refuses parts under 5 MiB.
+pub const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Stores `data` under `key` as a multipart upload, every acknowledged
+/// part is checkpointed so a retry after a crash continues the same
+/// upload and only sends the parts that are missing.
+pub async fn put_multipart<S: MultipartStorage>(
+    storage: &S,
+    key: &str,
+    data: &[u8],
+) -> Result<(), AppError> {
+    let since = Utc::now().naive_utc() - TimeDelta::seconds(Config::get().multipart_upload_ttl);
+
+    let upload = match MultipartUploadModel::get_resumable(key, since).await? {
+        Some(upload) => upload,
+        None => MultipartUploadModel::create(key, &storage.create_multipart(key).await?).await?,
+    };
+
+    let mut parts = upload
+        .parts()
+        .await?
+        .into_iter()
+        .map(|part| CompletedPart {
+            part_number: part.part_number,
+            etag: part.etag,
+        })
+        .collect::<Vec<_>>();
+
+    for (index, chunk) in data.chunks(PART_SIZE).enumerate() {
+        let part_number = index as i32 + 1;
+
+        if parts.iter().any(|part| part.part_number == part_number) {
+            continue;
+        }
+
+        let part = storage.upload_part(key, upload.upload_id(), part_number, chunk).await?;
+        upload.record_part(part.part_number, &part.etag).await?;
+        parts.push(part);
+    }
+
+    parts.sort_by_key(|part| part.part_number);
+    storage.complete_multipart(key, upload.upload_id(), &parts).await?;
+    upload.finish().await?;
+
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Version of the algorithms below, stored values depend on their output
+/// so any change to it must bump this and ship a migration re-normalizing them.
+pub const NORMALIZATION_VERSION: u32 = 1;
+
+/// Digits of a number compared by value in a sort key, longer runs are cut.
+const MAX_NUMBER_DIGITS: usize = u8::MAX as usize;
+
+/// Starts a number in a sort key, then come its digit count and its
+/// digits. Only digits encode to this byte otherwise, so it's unambiguous.
+const NUMBER_MARKER: u8 = b'0';
+
+/// Combining marks kept on a single character, enough for any
+/// script while stacked "zalgo" marks are dropped.
+const MAX_COMBINING_MARKS: usize = 2;
+
+/// How `slugify` shapes its output.
+#[derive(Debug, Clone, Copy)]
+pub struct SlugOptions {
+    /// Replaces every run of characters that aren't allowed.
+    pub separator: char,
+    /// Longest slug produced, in graphemes.
+    pub max_length: usize,
+    /// Only ASCII letters and digits are kept when set, accented latin
+    /// letters are transliterated first, other letters are kept otherwise.
+    pub ascii: bool,
+    /// Characters allowed besides letters and digits.
+    pub extra: &'static [char],
+}
+
+impl Default for SlugOptions {
+    fn default() -> Self {
+        SlugOptions {
+            separator: '-',
+            max_length: 64,
+            ascii: true,
+            extra: &[],
+        }
+    }
+}
+
+/// Case folds `text`, lowercasing isn't enough for
+/// German `ß` or the Turkish dotted capital `İ`.
+///
+/// The dotless `ı` is a letter of its own and is kept.
+fn fold_case(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+
+    for character in text.chars() {
+        match character {
+            // Lowercasing it gives `i` and a combining dot instead.
+            'İ' => folded.push('i'),
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            'ſ' => folded.push('s'),
+            _ => folded.extend(character.to_lowercase()),
+        }
+    }
+
+    folded
+}
+
+/// NFKC then case folding, so full width and compatibility
+/// forms compare equal to their plain counterparts.
+fn fold(text: &str) -> String {
+    fold_case(&text.nfkc().collect::<String>()).nfkc().collect()
+}
+
+/// Drops combining marks stacked past `MAX_COMBINING_MARKS`.
+fn limit_marks(text: &str) -> String {
+    let mut marks = 0;
+
+    text.chars()
+        .filter(|character| {
+            if is_combining_mark(*character) {
+                marks += 1;
+                marks <= MAX_COMBINING_MARKS
+            } else {
+                marks = 0;
+                true
+            }
+        })
+        .collect()
+}
+
+/// Latin letters that don't decompose into a base and a mark.
+fn transliterate(character: char) -> Option<&'static str> {
+    Some(match character {
+        'æ' => "ae",
+        'œ' => "oe",
+        'ø' => "o",
+        'ł' => "l",
+        'đ' | 'ð' => "d",
+        'þ' => "th",
+        'ı' => "i",
+        'ħ' => "h",
+        'ŋ' => "n",
+        _ => return None,
+    })
+}
+
+/// Strips accents from an already folded `text`, `é` becomes `e`.
+fn strip_accents(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+
+    for character in text.nfd().filter(|character| !is_combining_mark(*character)) {
+        match transliterate(character) {
+            Some(replacement) => stripped.push_str(replacement),
+            None => stripped.push(character),
+        }
+    }
+
+    stripped
+}
+
+/// Keeps at most `max_length` graphemes, never splitting one.
+fn truncate_graphemes(text: &str, max_length: usize) -> &str {
+    match text.grapheme_indices(true).nth(max_length) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+/// Turns `input` into a slug for paths and identifiers, characters that
+/// aren't allowed collapse into a single separator, never leading or trailing.
+///
+/// Stable for a given `NORMALIZATION_VERSION` and `options`.
+pub fn slugify(input: &str, options: SlugOptions) -> String {
+    let mut folded = limit_marks(&fold(input));
+
+    if options.ascii {
+        folded = strip_accents(&folded);
+    }
+
+    let mut slug = String::with_capacity(folded.len());
+    let mut pending_separator = false;
+
+    for character in folded.chars() {
+        let allowed = if options.ascii {
+            character.is_ascii_alphanumeric()
+        } else {
+            character.is_alphanumeric() || is_combining_mark(character)
+        } || options.extra.contains(&character);
+
+        if !allowed {
+            pending_separator = true;
+            continue;
+        }
+
+        if pending_separator && !slug.is_empty() {
+            slug.push(options.separator);
+        }
+
+        pending_separator = false;
+        slug.push(character);
+    }
+
+    truncate_graphemes(&slug, options.max_length).trim_end_matches(options.separator).to_owned()
+}
+
+/// Collapses whitespace runs into a single space and trims the ends.
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Tags as stored and looked up, folded and with their whitespace collapsed,
+/// letters of every script and their accents are kept.
+///
+/// Stable for a given `NORMALIZATION_VERSION`, stored tags depend on it.
+pub fn normalize_tag(tag: &str) -> String {
+    collapse_whitespace(&limit_marks(&fold(tag)))
+}
+
+/// Search terms are folded like tags and their accents stripped,
+/// so searches match regardless of how terms were typed.
+pub fn normalize_search_term(term: &str) -> String {
+    collapse_whitespace(&strip_accents(&normalize_tag(term)))
+}
+
+/// The form two file names are compared in to detect a conflict, names
+/// differing only in case or Unicode form would clash on most file systems.
+///
+/// Stable for a given `NORMALIZATION_VERSION`.
+pub fn canonical_filename(name: &str) -> String {
+    limit_marks(&fold(name.trim()))
+}
+
+/// The key names sort by under the natural collation, case and accents
+/// are ignored and numbers compare by value so `file2` sorts before
+/// `file10`, the keys compare bytewise.
+///
+/// Stable for a given `NORMALIZATION_VERSION`, stored keys depend on it.
+pub fn natural_sort_key(name: &str) -> Vec<u8> {
+    let folded = strip_accents(&limit_marks(&fold(name)));
+    let mut key = Vec::with_capacity(folded.len());
+    let mut chars = folded.chars().peekable();
+
+    while let Some(character) = chars.next() {
+        if !character.is_ascii_digit() {
+            key.extend_from_slice(character.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+
+        let mut number = String::from(character);
+
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            number.push(digit);
+        }
+
+        // Leading zeros don't change the value, a lone zero is kept.
+        let digits = match number.trim_start_matches('0') {
+            "" => "0",
+            digits => &digits[..digits.len().min(MAX_NUMBER_DIGITS)],
+        };
+
+        key.push(NUMBER_MARKER);
+        key.push(digits.len() as u8);
+        key.extend_from_slice(digits.as_bytes());
+    }
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_are_folded_ascii() {
+        let options = SlugOptions::default();
+
+        assert_eq!(slugify("  Crème Brûlée!! ", options), "creme-brulee");
+        assert_eq!(slugify("Straße İstanbul", options), "strasse-istanbul");
+        assert_eq!(slugify("Ｆｕｌｌ　Ｗｉｄｔｈ", options), "full-width");
+        assert_eq!(slugify("---", options), "");
+    }
+
+    #[test]
+    fn slugs_keep_letters_when_not_ascii() {
+        let options = SlugOptions {
+            ascii: false,
+            max_length: 4,
+            separator: '_',
+            extra: &['.'],
+        };
+
+        assert_eq!(slugify("東京 タワー", options), "東京_タ");
+        assert_eq!(slugify("a.b c", options), "a.b");
+        assert_eq!(slugify("ab c", options), "ab_c");
+    }
+
+    #[test]
+    fn stacked_marks_are_dropped() {
+        let zalgo = "z\u{301}\u{302}\u{303}\u{304}a";
+
+        assert_eq!(normalize_tag(zalgo).chars().count(), 4);
+    }
+
+    #[test]
+    fn clashing_names_share_a_canonical_form() {
+        assert_eq!(canonical_filename("README.md"), canonical_filename(" readme.MD"));
+        assert_eq!(canonical_filename("Cafe\u{301}.txt"), canonical_filename("café.txt"));
+        assert_eq!(canonical_filename("ﬁle"), canonical_filename("file"));
+        assert_ne!(canonical_filename("a.txt"), canonical_filename("b.txt"));
+    }
+
+    #[test]
+    fn tags_and_terms_collapse_whitespace() {
+        assert_eq!(normalize_tag("  Ünder \t Water "), "ünder water");
+        assert_eq!(normalize_search_term("Ünder  Water"), "under water");
+    }
+
+    #[test]
+    fn numbers_sort_by_value() {
+        let mut names = ["file10", "File2", "file02b", "file1", "éclair", "eclair0"];
+        names.sort_by_key(|name| natural_sort_key(name));
+
+        assert_eq!(names, ["éclair", "eclair0", "file1", "File2", "file02b", "file10"]);
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use database::{DatabaseError, FileModel, ServedFile};
+use hashlink::LinkedHashMap;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::file_cache::FileCache;
+
+static NOT_FOUND: OnceLock<NotFoundCache> = OnceLock::new();
+
+/// Entries kept, the oldest are dropped first past it.
+const MAX_ENTRIES: usize = 16 * 1024;
+
+/// Slugs recently looked up without a match, so hammering
+/// a missing file doesn't query the database every time.
+///
+/// Entries are kept in the order they were added, which is also the order
+/// they expire in since they all live `NOT_FOUND_CACHE_TTL` seconds.
+pub struct NotFoundCache {
+    entries: Mutex<LinkedHashMap<String, Instant>>,
+}
+
+impl NotFoundCache {
+    pub fn get() -> &'static Self {
+        NOT_FOUND.get_or_init(|| NotFoundCache {
+            entries: Mutex::new(LinkedHashMap::new()),
+        })
+    }
+
+    fn contains(&self, slug: &str) -> bool {
+        let mut entries = self.entries.lock().unwrap_or_else(|error| error.into_inner());
+
+        match entries.get(slug) {
+            Some(expires_at) if *expires_at > Instant::now() => true,
+            Some(_) => {
+                entries.remove(slug);
+                false
+            },
+            None => false,
+        }
+    }
+
+    fn insert(&self, slug: &str, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap_or_else(|error| error.into_inner());
+        let now = Instant::now();
+
+        // Only the front ever needs looking at, expired or not.
+        while let Some((_, expires_at)) = entries.front()
+            && (*expires_at <= now || entries.len() >= MAX_ENTRIES)
+        {
+            entries.pop_front();
+        }
+
+        entries.insert(slug.to_owned(), now + ttl);
+    }
+
+    /// Drops `slug` from the cache, called whenever a file starts using it.
+    pub fn forget(&self, slug: &str) {
+        self.entries.lock().unwrap_or_else(|error| error.into_inner()).remove(slug);
+    }
+}
+
+/// Obtains a file by slug, answering from the caches for files recently
+/// found and slugs that were missing less than `NOT_FOUND_CACHE_TTL` seconds ago.
+///
+/// Slugs retired by a rotation are never cached, they're answered as revoked.
+pub async fn file_by_slug(slug: &str) -> Result<ServedFile, AppError> {
+    let ttl = Config::get().not_found_cache_ttl;
+    let cache = NotFoundCache::get();
+
+    if ttl > 0 && cache.contains(slug) {
+        return Err(AppError::FileNotFound(ttl));
+    }
+
+    if let Some(file) = FileCache::get().lookup(slug) {
+        return Ok(file);
+    }
+
+    match FileModel::get_served_by_slug(slug).await {
+        Err(DatabaseError::ModelNotFound(_)) => {
+            if FileModel::slug_revoked(slug).await? {
+                return Err(AppError::LinkRevoked);
+            }
+
+            if ttl > 0 {
+                cache.insert(slug, Duration::from_secs(ttl));
+            }
+
+            Err(AppError::FileNotFound(ttl))
+        },
+        result => {
+            let served = result?;
+            FileCache::get().insert(&served);
+
+            Ok(served)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{create_bucket, create_file, create_user, run, set_slug, unique_name};
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn a_second_miss_is_answered_without_the_database() {
+        run(async {
+            let slug = unique_name("missing");
+            let bucket = create_bucket(&create_user(&unique_name("missing")).await).await;
+            let file = create_file(&bucket, "late.txt").await;
+
+            assert!(matches!(file_by_slug(&slug).await, Err(AppError::FileNotFound(_))));
+
+            // Had the database been asked again, it would find the file now.
+            set_slug(&file, &slug).await;
+            assert!(matches!(file_by_slug(&slug).await, Err(AppError::FileNotFound(_))));
+
+            NotFoundCache::get().forget(&slug);
+            assert_eq!(file_by_slug(&slug).await.unwrap().file.id(), file.id());
+        })
+    }
+
+    #[test]
+    fn the_oldest_entries_make_room() {
+        let cache = NotFoundCache {
+            entries: Mutex::new(LinkedHashMap::new()),
+        };
+
+        for index in 0..=MAX_ENTRIES {
+            cache.insert(&index.to_string(), Duration::from_secs(60));
+        }
+
+        assert!(!cache.contains("0"));
+        assert!(cache.contains("1"));
+        assert!(cache.contains(&MAX_ENTRIES.to_string()));
+    }
+}
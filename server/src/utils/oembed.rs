@@ -0,0 +1,172 @@
+use actix_web::HttpRequest;
+use database::{DatabaseError, FileModel, TenantDomainModel};
+use serde::Serialize;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::middleware::forwarded::ClientInfo;
+use crate::middleware::tenant::{hostname, public_host};
+use crate::processing::poster::POSTER_READY;
+use crate::processing::thumbnail::THUMBNAIL_SIZE;
+use crate::utils::dav::escape;
+use crate::utils::links::{content_base_url, public_url};
+use crate::utils::not_found::file_by_slug;
+
+/// Size videos are embedded at when their dimensions aren't known.
+const DEFAULT_VIDEO_SIZE: (u32, u32) = (640, 360);
+
+/// An oEmbed response, fields that don't apply to its type are left out.
+#[derive(Serialize)]
+pub struct OEmbed {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    version: &'static str,
+    title: String,
+    provider_name: &'static str,
+    provider_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail_width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail_height: Option<u32>,
+}
+
+/// Fits `width` by `height` within the given bounds keeping
+/// its aspect ratio, sizes are never scaled up.
+pub fn scale(
+    width: u32,
+    height: u32,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+) -> (u32, u32) {
+    let ratio = [
+        max_width.map(|max| max as f64 / width.max(1) as f64),
+        max_height.map(|max| max as f64 / height.max(1) as f64),
+    ]
+    .into_iter()
+    .flatten()
+    .fold(1.0, f64::min);
+
+    (
+        ((width as f64 * ratio).round() as u32).max(1),
+        ((height as f64 * ratio).round() as u32).max(1),
+    )
+}
+
+/// Resolves a file from one of its links, either on the public host,
+/// the content host or a verified custom domain, which only holds the
+/// files of its tenant.
+pub async fn file_from_url(req: &HttpRequest, url: &str) -> Result<FileModel, AppError> {
+    let not_found = || AppError::from(DatabaseError::ModelNotFound("file"));
+
+    let rest = url.split_once("://").map(|(_, rest)| rest).ok_or_else(not_found)?;
+    let (host, path) = rest.split_once('/').ok_or_else(not_found)?;
+    let host = hostname(host);
+
+    let slug = path
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.strip_prefix("f/"))
+        .and_then(|path| path.split('/').next())
+        .filter(|slug| !slug.is_empty())
+        .ok_or_else(not_found)?;
+
+    let config = Config::get();
+    let client = ClientInfo::get(req);
+    let known = [
+        public_host(&config.public_base_url),
+        public_host(&config.sandbox_base_url),
+        client.host.as_str(),
+    ];
+
+    let served = file_by_slug(slug).await?;
+
+    if !known.iter().any(|known| !known.is_empty() && hostname(known) == host) {
+        let domain = match TenantDomainModel::get_verified(&host).await {
+            Err(DatabaseError::ModelNotFound(_)) => return Err(not_found()),
+            domain => domain?,
+        };
+
+        if served.owner_id != Some(domain.owner_id()) {
+            return Err(DatabaseError::ModelNotFound("file").into());
+        }
+    }
+
+    Ok(served.file)
+}
+
+/// Describes a file as a `photo` when it's an image with known dimensions,
+/// as a `video` playing in the embed page and as a `link` otherwise.
+pub fn describe(
+    req: &HttpRequest,
+    file: &FileModel,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+) -> OEmbed {
+    let mime = mime_guess::from_path(file.path()).first_or_octet_stream();
+    let content_url = format!("{}/f/{}", content_base_url(req).trim_end_matches('/'), file.slug());
+    let dimensions = file.dimensions().map(|(width, height)| (width as u32, height as u32));
+
+    let mut oembed = OEmbed {
+        kind: "link",
+        version: "1.0",
+        title: file.path().rsplit('/').next().unwrap_or_default().to_owned(),
+        provider_name: "cdn",
+        provider_url: public_url(req, "/"),
+        url: None,
+        html: None,
+        width: None,
+        height: None,
+        thumbnail_url: None,
+        thumbnail_width: None,
+        thumbnail_height: None,
+    };
+
+    let thumbnail = match (mime.type_().as_str(), dimensions) {
+        ("image", Some((width, height))) => {
+            let (width, height) = scale(width, height, max_width, max_height);
+
+            oembed.kind = "photo";
+            oembed.url = Some(content_url.clone());
+            oembed.width = Some(width);
+            oembed.height = Some(height);
+
+            dimensions
+        },
+        ("video", _) => {
+            let (width, height) = dimensions.unwrap_or(DEFAULT_VIDEO_SIZE);
+            let (width, height) = scale(width, height, max_width, max_height);
+
+            oembed.kind = "video";
+            oembed.html = Some(format!(
+                "<iframe src=\"{}\" width=\"{width}\" height=\"{height}\" frameborder=\"0\" \
+                 allowfullscreen></iframe>",
+                escape(&public_url(req, &format!("/embed/{}", file.slug())))
+            ));
+            oembed.width = Some(width);
+            oembed.height = Some(height);
+
+            dimensions.filter(|_| file.poster_state() == Some(POSTER_READY))
+        },
+        _ => None,
+    };
+
+    if let Some((width, height)) = thumbnail {
+        let (width, height) = scale(width, height, Some(THUMBNAIL_SIZE), Some(THUMBNAIL_SIZE));
+
+        oembed.thumbnail_url = Some(format!("{content_url}/thumbnail.png"));
+        oembed.thumbnail_width = Some(width);
+        oembed.thumbnail_height = Some(height);
+    }
+
+    oembed
+}
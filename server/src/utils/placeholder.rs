@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use database::{FileModel, ImageMetadata};
+use image::ImageReader;
+use image::imageops::FilterType;
+
+/// The size of an image read from its header alone, `None`
+/// when `data` isn't an image in a supported format.
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(data)).with_guessed_format().ok()?.into_dimensions().ok()
+}
+
+/// Decodes `data` as an image to record its size and dominant color,
+/// this is the only place a placeholder ever decodes anything.
+pub fn image_metadata(data: &[u8]) -> Option<ImageMetadata> {
+    let image = image::load_from_memory(data).ok()?;
+    let width = i32::try_from(image.width()).ok()?;
+    let height = i32::try_from(image.height()).ok()?;
+
+    // Colors are bucketed to 4 bits per channel, the most common
+    // bucket wins and its pixels are averaged to get the final color.
+    let mut buckets: HashMap<(u8, u8, u8), (u32, [u32; 3])> = HashMap::new();
+
+    for pixel in image.resize(32, 32, FilterType::Triangle).to_rgba8().pixels() {
+        let [red, green, blue, alpha] = pixel.0;
+
+        if alpha < 128 {
+            continue;
+        }
+
+        let (count, sum) = buckets.entry((red >> 4, green >> 4, blue >> 4)).or_default();
+        *count += 1;
+        sum[0] += red as u32;
+        sum[1] += green as u32;
+        sum[2] += blue as u32;
+    }
+
+    let (count, [red, green, blue]) =
+        buckets.into_values().max_by_key(|(count, _)| *count).unwrap_or((1, [0; 3]));
+
+    Some(ImageMetadata {
+        width,
+        height,
+        dominant_color: format!("#{:02x}{:02x}{:02x}", red / count, green / count, blue / count),
+    })
+}
+
+/// Renders the placeholder of a file, a rectangle of its dominant color
+/// at its intrinsic size for images or an icon of its kind otherwise.
+pub fn placeholder_svg(file: &FileModel) -> String {
+    let color = file.dominant_color().filter(|color| is_hex_color(color));
+
+    match file.dimensions().zip(color) {
+        Some(((width, height), color)) => format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}"><rect width="100%" height="100%" fill="{color}"/></svg>"#
+        ),
+        None => icon(file.path()).to_owned(),
+    }
+}
+
+fn icon(path: &str) -> &'static str {
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+
+    match extension.as_deref().unwrap_or_default() {
+        "pdf" | "txt" | "md" | "rtf" | "doc" | "docx" | "odt" | "xls" | "xlsx" | "ods" | "ppt"
+        | "pptx" | "odp" | "csv" | "epub" => include_str!("../../assets/placeholders/document.svg"),
+        "mp4" | "webm" | "mkv" | "mov" | "avi" | "m4v" => {
+            include_str!("../../assets/placeholders/video.svg")
+        },
+        "mp3" | "flac" | "ogg" | "opus" | "wav" | "m4a" | "aac" => {
+            include_str!("../../assets/placeholders/audio.svg")
+        },
+        "zip" | "tar" | "gz" | "tgz" | "xz" | "zst" | "bz2" | "7z" | "rar" => {
+            include_str!("../../assets/placeholders/archive.svg")
+        },
+        // Images uploaded before their metadata was recorded.
+        "png" | "jpg" | "jpeg" | "gif" | "webp" | "avif" | "bmp" | "tiff" => {
+            include_str!("../../assets/placeholders/image.svg")
+        },
+        _ => include_str!("../../assets/placeholders/file.svg"),
+    }
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|character| character.is_ascii_hexdigit())
+}
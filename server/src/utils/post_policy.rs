@@ -0,0 +1,222 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::AppError;
+use crate::config::Config;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Keeps policy signatures from being valid for anything else signed with the secret.
+const DOMAIN: &[u8] = b"post-policy:";
+
+/// The conditions a form upload is accepted under, signed when
+/// issued so the form fields can't be altered by the browser.
+#[derive(Serialize, Deserialize)]
+pub struct PostPolicy {
+    /// The user uploads are attributed to.
+    pub user_id: i64,
+    pub bucket_id: i64,
+    /// Seconds since the epoch.
+    pub expires_at: i64,
+    pub min_size: u64,
+    pub max_size: u64,
+    /// Uploaded names must start with it, empty for any name.
+    pub key_prefix: String,
+    /// The content type of the file part must start with it, unset for any type.
+    pub content_type_prefix: Option<String>,
+}
+
+/// The hidden fields a form posting to `POST /file/upload/form` carries.
+#[derive(Serialize)]
+pub struct PolicyFields {
+    pub policy: String,
+    pub signature: String,
+}
+
+fn mac(secret: &str, policy: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(DOMAIN);
+    mac.update(policy.as_bytes());
+    mac
+}
+
+impl PostPolicy {
+    pub fn sign(&self, secret: &str) -> PolicyFields {
+        let policy = STANDARD.encode(serde_json::to_vec(self).expect("A policy always serializes"));
+        let signature = STANDARD.encode(mac(secret, &policy).finalize().into_bytes());
+
+        PolicyFields { policy, signature }
+    }
+
+    /// Decodes a policy once its signature is checked, expired ones are refused.
+    pub fn verify(policy: &str, signature: &str, secret: &str) -> Result<Self, AppError> {
+        let signature = STANDARD.decode(signature).map_err(|_| AppError::PolicyInvalid)?;
+
+        mac(secret, policy).verify_slice(&signature).map_err(|_| AppError::PolicyInvalid)?;
+
+        let policy = STANDARD.decode(policy).map_err(|_| AppError::PolicyInvalid)?;
+        let policy: Self = serde_json::from_slice(&policy).map_err(|_| AppError::PolicyInvalid)?;
+
+        if policy.expires_at <= Utc::now().timestamp() {
+            return Err(AppError::PolicyExpired);
+        }
+
+        Ok(policy)
+    }
+
+    pub fn check_name(&self, name: &str) -> Result<(), AppError> {
+        if name.is_empty() || !name.starts_with(&self.key_prefix) {
+            return Err(AppError::PolicyViolation(format!(
+                "the name must start with \"{}\"",
+                self.key_prefix
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_content_type(&self, content_type: Option<&str>) -> Result<(), AppError> {
+        let Some(prefix) = &self.content_type_prefix else {
+            return Ok(());
+        };
+
+        if !content_type.is_some_and(|content_type| content_type.starts_with(prefix.as_str())) {
+            return Err(AppError::PolicyViolation(format!(
+                "the content type must start with \"{prefix}\""
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_size(&self, size: u64) -> Result<(), AppError> {
+        if !(self.min_size..=self.max_size).contains(&size) {
+            return Err(AppError::PolicyViolation(format!(
+                "the file must be between {} and {} bytes",
+                self.min_size, self.max_size
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The origin of an absolute http(s) URL, lowercased, `None` for anything
+/// else or when it carries credentials that could disguise its host.
+fn origin(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+
+    if authority.is_empty() || authority.contains(['@', '\\']) {
+        return None;
+    }
+
+    Some(format!("{scheme}://{authority}").to_ascii_lowercase())
+}
+
+/// Checks that a `success_action_redirect` goes to one of
+/// `POLICY_REDIRECT_ORIGINS`, so forms can't be used as open redirects.
+pub fn check_redirect(url: &str) -> Result<(), AppError> {
+    let allowed = origin(url).is_some_and(|origin| {
+        Config::get()
+            .policy_redirect_origins
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(&origin))
+    });
+
+    if allowed { Ok(()) } else { Err(AppError::RedirectNotAllowed) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn policy(expires_at: i64) -> PostPolicy {
+        PostPolicy {
+            user_id: 1,
+            bucket_id: 2,
+            expires_at,
+            min_size: 1,
+            max_size: 1024,
+            key_prefix: "uploads/".into(),
+            content_type_prefix: Some("image/".into()),
+        }
+    }
+
+    #[test]
+    fn signed_policies_verify() {
+        let fields = policy(Utc::now().timestamp() + 60).sign(SECRET);
+        let verified = PostPolicy::verify(&fields.policy, &fields.signature, SECRET).unwrap();
+
+        assert_eq!(verified.user_id, 1);
+        assert_eq!(verified.bucket_id, 2);
+        assert_eq!(verified.key_prefix, "uploads/");
+    }
+
+    #[test]
+    fn tampered_or_foreign_policies_are_refused() {
+        let fields = policy(Utc::now().timestamp() + 60).sign(SECRET);
+        let mut altered = policy(Utc::now().timestamp() + 60);
+        altered.max_size = u64::MAX;
+        let altered = altered.sign("another-secret");
+
+        assert!(matches!(
+            PostPolicy::verify(&altered.policy, &fields.signature, SECRET),
+            Err(AppError::PolicyInvalid)
+        ));
+        assert!(matches!(
+            PostPolicy::verify(&fields.policy, &fields.signature, "another-secret"),
+            Err(AppError::PolicyInvalid)
+        ));
+        assert!(matches!(
+            PostPolicy::verify(&fields.policy, "not base64!", SECRET),
+            Err(AppError::PolicyInvalid)
+        ));
+    }
+
+    #[test]
+    fn expired_policies_are_refused() {
+        let fields = policy(Utc::now().timestamp() - 1).sign(SECRET);
+
+        assert!(matches!(
+            PostPolicy::verify(&fields.policy, &fields.signature, SECRET),
+            Err(AppError::PolicyExpired)
+        ));
+    }
+
+    #[test]
+    fn conditions_are_enforced() {
+        let policy = policy(0);
+
+        assert!(policy.check_name("uploads/cat.png").is_ok());
+        assert!(policy.check_name("private/cat.png").is_err());
+        assert!(policy.check_content_type(Some("image/png")).is_ok());
+        assert!(policy.check_content_type(Some("text/html")).is_err());
+        assert!(policy.check_content_type(None).is_err());
+        assert!(policy.check_size(1024).is_ok());
+        assert!(policy.check_size(0).is_err());
+        assert!(policy.check_size(1025).is_err());
+    }
+
+    #[test]
+    fn only_web_origins_are_extracted() {
+        assert_eq!(origin("https://Example.com/done?x=1").as_deref(), Some("https://example.com"));
+        assert_eq!(origin("http://example.com:8080").as_deref(), Some("http://example.com:8080"));
+        assert_eq!(origin("javascript://example.com"), None);
+        assert_eq!(origin("https://user@evil.com"), None);
+        assert_eq!(origin("https://evil.com\\@example.com"), None);
+        assert_eq!(origin("/relative"), None);
+    }
+}
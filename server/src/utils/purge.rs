@@ -0,0 +1,384 @@
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use actix_web::rt::spawn;
+use actix_web::rt::time::sleep;
+use database::JobModel;
+use log::warn;
+use reqwest::{Client, RequestBuilder};
+use serde_json::{Value, json};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::tasks::jobs::spawn_tracked;
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Attempts at purging a batch of keys before the purge counts as failed.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Waited before the second attempt, doubled for every one after.
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+/// How long the API has to answer a purge.
+const PURGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keys purged by a single request, Cloudflare takes at most 30 tags at once.
+const KEYS_PER_REQUEST: usize = 30;
+
+/// Longest key accepted from operators, ours are far shorter.
+const MAX_KEY_LENGTH: usize = 128;
+
+const CLOUDFLARE_API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+const FASTLY_API_URL: &str = "https://api.fastly.com";
+
+/// The key of everything served for a file.
+pub fn file_key(file_id: i64) -> String {
+    format!("file-{file_id}")
+}
+
+/// The key of every file in a bucket.
+pub fn bucket_key(bucket_id: i64) -> String {
+    format!("bucket-{bucket_id}")
+}
+
+/// The key of every file of a user.
+pub fn owner_key(owner_id: i64) -> String {
+    format!("owner-{owner_id}")
+}
+
+/// Whether `key` can be purged, both APIs take letters, digits,
+/// dashes and underscores, commas and spaces separate keys in headers.
+pub fn valid_key(key: &str) -> bool {
+    (1..=MAX_KEY_LENGTH).contains(&key.len())
+        && key.bytes().all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_'))
+}
+
+/// Which cache sits in front of the origin, if any.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PurgeProvider {
+    None,
+    Cloudflare,
+    Fastly,
+}
+
+impl PurgeProvider {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(PurgeProvider::None),
+            "cloudflare" => Some(PurgeProvider::Cloudflare),
+            "fastly" => Some(PurgeProvider::Fastly),
+            _ => None,
+        }
+    }
+}
+
+/// Drops what a cache holds under surrogate keys.
+pub trait PurgeBackend {
+    /// Purges every response tagged with one of `keys`.
+    fn purge(&self, keys: &[String]) -> impl Future<Output = Result<(), AppError>>;
+}
+
+/// Purges by cache tag through the API of a Cloudflare zone.
+pub struct Cloudflare {
+    api_url: String,
+    zone_id: String,
+    api_token: String,
+}
+
+impl Cloudflare {
+    pub fn new(zone_id: &str, api_token: &str) -> Self {
+        Cloudflare::at(CLOUDFLARE_API_URL, zone_id, api_token)
+    }
+
+    fn at(api_url: &str, zone_id: &str, api_token: &str) -> Self {
+        Cloudflare {
+            api_url: api_url.to_owned(),
+            zone_id: zone_id.to_owned(),
+            api_token: api_token.to_owned(),
+        }
+    }
+}
+
+impl PurgeBackend for Cloudflare {
+    async fn purge(&self, keys: &[String]) -> Result<(), AppError> {
+        let request = client()?
+            .post(format!("{}/zones/{}/purge_cache", self.api_url, self.zone_id))
+            .bearer_auth(&self.api_token)
+            .header("Content-Type", "application/json")
+            .body(json!({ "tags": keys }).to_string());
+
+        send(request).await
+    }
+}
+
+/// Purges by surrogate key through the API of a Fastly service.
+pub struct Fastly {
+    api_url: String,
+    service_id: String,
+    api_token: String,
+}
+
+impl Fastly {
+    pub fn new(service_id: &str, api_token: &str) -> Self {
+        Fastly::at(FASTLY_API_URL, service_id, api_token)
+    }
+
+    fn at(api_url: &str, service_id: &str, api_token: &str) -> Self {
+        Fastly {
+            api_url: api_url.to_owned(),
+            service_id: service_id.to_owned(),
+            api_token: api_token.to_owned(),
+        }
+    }
+}
+
+impl PurgeBackend for Fastly {
+    async fn purge(&self, keys: &[String]) -> Result<(), AppError> {
+        let request = client()?
+            .post(format!("{}/service/{}/purge", self.api_url, self.service_id))
+            .header("Fastly-Key", &self.api_token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .body(json!({ "surrogate_keys": keys }).to_string());
+
+        send(request).await
+    }
+}
+
+fn client() -> Result<Client, AppError> {
+    Client::builder()
+        .timeout(PURGE_TIMEOUT)
+        .build()
+        .map_err(|error| AppError::PurgeFailed(error.to_string()))
+}
+
+async fn send(request: RequestBuilder) -> Result<(), AppError> {
+    match request.send().await {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => {
+            Err(AppError::PurgeFailed(format!("The API answered {}", response.status())))
+        },
+        Err(error) => Err(AppError::PurgeFailed(error.without_url().to_string())),
+    }
+}
+
+/// The backend configured with `PURGE_BACKEND`, purging nothing when it's `none`.
+pub enum Backend {
+    None,
+    Cloudflare(Cloudflare),
+    Fastly(Fastly),
+}
+
+impl Backend {
+    pub fn get() -> &'static Self {
+        BACKEND.get_or_init(|| {
+            let config = Config::get();
+
+            match config.purge_backend {
+                PurgeProvider::None => Backend::None,
+                PurgeProvider::Cloudflare => Backend::Cloudflare(Cloudflare::new(
+                    &config.purge_zone,
+                    &config.purge_api_token,
+                )),
+                PurgeProvider::Fastly => {
+                    Backend::Fastly(Fastly::new(&config.purge_zone, &config.purge_api_token))
+                },
+            }
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        !matches!(self, Backend::None)
+    }
+}
+
+impl PurgeBackend for Backend {
+    async fn purge(&self, keys: &[String]) -> Result<(), AppError> {
+        match self {
+            Backend::None => Ok(()),
+            Backend::Cloudflare(cloudflare) => cloudflare.purge(keys).await,
+            Backend::Fastly(fastly) => fastly.purge(keys).await,
+        }
+    }
+}
+
+/// Purges `keys` in the background, the change they follow is already done
+/// so a purge that fails is only logged, the cache expires it eventually.
+pub fn purge(keys: Vec<String>) {
+    let backend = Backend::get();
+
+    if !backend.enabled() {
+        return;
+    }
+
+    spawn(async move {
+        if let Err(error) = enqueue(backend, keys).await {
+            warn!("Couldn't queue a cache purge: {error}");
+        }
+    });
+}
+
+/// Purges `keys` as a tracked job retried up to `MAX_ATTEMPTS` times.
+pub async fn enqueue(backend: &'static Backend, keys: Vec<String>) -> Result<JobModel, AppError> {
+    let parameters = json!({ "keys": keys });
+
+    spawn_tracked("cache-purge", parameters, move |_| purge_all(backend, keys, RETRY_BACKOFF)).await
+}
+
+/// Purges `keys` a batch at a time, waiting `backoff` before
+/// retrying a batch and twice as long every attempt after.
+async fn purge_all(
+    backend: &impl PurgeBackend,
+    keys: Vec<String>,
+    backoff: Duration,
+) -> Result<Value, AppError> {
+    let mut attempts = 0;
+
+    for batch in keys.chunks(KEYS_PER_REQUEST) {
+        let mut number = 1;
+
+        loop {
+            attempts += 1;
+
+            match backend.purge(batch).await {
+                Ok(()) => break,
+                Err(error) if number == MAX_ATTEMPTS => {
+                    warn!("Gave up purging {} from the cache: {error}", batch.join(" "));
+
+                    return Err(error);
+                },
+                Err(error) => {
+                    warn!("Purging the cache failed, attempt {number} of {MAX_ATTEMPTS}: {error}");
+                    sleep(backoff * 2u32.pow(number - 1)).await;
+                    number += 1;
+                },
+            }
+        }
+    }
+
+    Ok(json!({ "keys": keys.len(), "attempts": attempts }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::testing::receiver;
+
+    /// Fails the first `failures` purges, recording every batch it's given.
+    struct Flaky {
+        failures: RefCell<u32>,
+        batches: RefCell<Vec<Vec<String>>>,
+    }
+
+    impl Flaky {
+        fn new(failures: u32) -> Self {
+            Flaky {
+                failures: RefCell::new(failures),
+                batches: RefCell::default(),
+            }
+        }
+    }
+
+    impl PurgeBackend for Flaky {
+        async fn purge(&self, keys: &[String]) -> Result<(), AppError> {
+            self.batches.borrow_mut().push(keys.to_vec());
+
+            let mut failures = self.failures.borrow_mut();
+
+            if *failures == 0 {
+                return Ok(());
+            }
+
+            *failures -= 1;
+
+            Err(AppError::PurgeFailed("The API answered 503".into()))
+        }
+    }
+
+    fn keys(count: i64) -> Vec<String> {
+        (1..=count).map(file_key).collect()
+    }
+
+    #[test]
+    fn keys_are_stable_ids_any_api_takes() {
+        assert_eq!(file_key(42), "file-42");
+        assert_eq!(bucket_key(7), "bucket-7");
+        assert_eq!(owner_key(3), "owner-3");
+
+        for key in [file_key(42), bucket_key(7), owner_key(3), "custom_key-1".into()] {
+            assert!(valid_key(&key), "{key}");
+        }
+
+        for key in ["", "two keys", "a,b", "file-42\r\n", &"k".repeat(MAX_KEY_LENGTH + 1)] {
+            assert!(!valid_key(key), "{key:?}");
+        }
+    }
+
+    #[test]
+    fn providers_are_named_like_they_are_configured() {
+        assert_eq!(PurgeProvider::parse("Cloudflare"), Some(PurgeProvider::Cloudflare));
+        assert_eq!(PurgeProvider::parse("fastly"), Some(PurgeProvider::Fastly));
+        assert_eq!(PurgeProvider::parse("none"), Some(PurgeProvider::None));
+        assert_eq!(PurgeProvider::parse("akamai"), None);
+    }
+
+    #[actix_web::test]
+    async fn cloudflare_purges_tags_of_the_zone() {
+        let (address, requests) = receiver(200);
+        let cloudflare = Cloudflare::at(&format!("http://{address}/client/v4"), "zone", "token");
+
+        cloudflare.purge(&keys(2)).await.unwrap();
+
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("POST /client/v4/zones/zone/purge_cache HTTP/1.1\r\n"));
+        assert!(request.contains("\r\nauthorization: Bearer token\r\n"));
+        assert!(request.contains("\r\ncontent-type: application/json\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"tags\":[\"file-1\",\"file-2\"]}"));
+    }
+
+    #[actix_web::test]
+    async fn fastly_purges_surrogate_keys_of_the_service() {
+        let (address, requests) = receiver(200);
+        let fastly = Fastly::at(&format!("http://{address}"), "service", "token");
+
+        fastly.purge(&keys(2)).await.unwrap();
+
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("POST /service/service/purge HTTP/1.1\r\n"));
+        assert!(request.contains("\r\nfastly-key: token\r\n"));
+        assert!(!request.contains("authorization"));
+        assert!(request.ends_with("\r\n\r\n{\"surrogate_keys\":[\"file-1\",\"file-2\"]}"));
+    }
+
+    #[actix_web::test]
+    async fn refused_purges_fail() {
+        let (address, _requests) = receiver(403);
+        let fastly = Fastly::at(&format!("http://{address}"), "service", "token");
+
+        let error = fastly.purge(&keys(1)).await.unwrap_err();
+        assert!(matches!(error, AppError::PurgeFailed(error) if error.contains("403")));
+    }
+
+    #[actix_web::test]
+    async fn failed_purges_are_retried_a_batch_at_a_time() {
+        let flaky = Flaky::new(2);
+
+        let result = purge_all(&flaky, keys(31), Duration::ZERO).await.unwrap();
+        assert_eq!(result, json!({ "keys": 31, "attempts": 4 }));
+
+        let batches = flaky.batches.take();
+        let sizes = batches.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, [30, 30, 30, 1]);
+        assert_eq!(batches[3], ["file-31"]);
+
+        let flaky = Flaky::new(MAX_ATTEMPTS);
+        let error = purge_all(&flaky, keys(1), Duration::ZERO).await.unwrap_err();
+        assert!(matches!(error, AppError::PurgeFailed(_)));
+        assert_eq!(flaky.batches.take().len(), MAX_ATTEMPTS as usize);
+    }
+}
@@ -0,0 +1,102 @@
+use std::ops::RangeInclusive;
+
+/// The outcome of validating a `Range` header against the stored size.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range was requested, the whole body is served.
+    Full,
+    /// An inclusive range, always within the stored size.
+    Partial(RangeInclusive<u64>),
+    /// Answered with 416 and `Content-Range: bytes */<size>`.
+    Unsatisfiable,
+}
+
+/// Parses a single `bytes=` range and clamps it to `size`.
+///
+/// Offsets are only ever compared against `size`, nothing is
+/// allocated from them. Multiple ranges and other units are ignored
+/// and the whole body is served instead, as the RFC allows.
+pub fn parse_range(header: Option<&str>, size: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Unsatisfiable;
+    };
+
+    let (start, end) = (start.trim(), end.trim());
+
+    let valid = match (start.is_empty(), end.is_empty()) {
+        (true, true) => false,
+        (true, false) => is_number(end),
+        (false, true) => is_number(start),
+        (false, false) => is_number(start) && is_number(end),
+    };
+
+    if !valid || size == 0 {
+        return ByteRange::Unsatisfiable;
+    }
+
+    // Numbers too big for an u64 are necessarily past the end.
+    let parse = |value: &str| value.parse::<u64>().unwrap_or(u64::MAX);
+
+    let range = match (start.is_empty(), end.is_empty()) {
+        // `bytes=-500`, the last 500 bytes.
+        (true, _) => match parse(end) {
+            0 => return ByteRange::Unsatisfiable,
+            suffix => size.saturating_sub(suffix)..=size - 1,
+        },
+        // `bytes=500-`, from 500 to the end.
+        (false, true) => parse(start)..=size - 1,
+        (false, false) => parse(start)..=parse(end).min(size - 1),
+    };
+
+    if *range.start() >= size || range.start() > range.end() {
+        return ByteRange::Unsatisfiable;
+    }
+
+    ByteRange::Partial(range)
+}
+
+fn is_number(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_clamped_to_the_size() {
+        assert_eq!(parse_range(Some("bytes=0-99"), 1000), ByteRange::Partial(0..=99));
+        assert_eq!(parse_range(Some("bytes=900-"), 1000), ByteRange::Partial(900..=999));
+        assert_eq!(parse_range(Some("bytes=-100"), 1000), ByteRange::Partial(900..=999));
+        assert_eq!(parse_range(Some("bytes=-5000"), 1000), ByteRange::Partial(0..=999));
+        assert_eq!(
+            parse_range(Some("bytes=10-99999999999999999999"), 20),
+            ByteRange::Partial(10..=19)
+        );
+    }
+
+    #[test]
+    fn unusable_ranges_serve_everything() {
+        assert_eq!(parse_range(None, 1000), ByteRange::Full);
+        assert_eq!(parse_range(Some("items=0-1"), 1000), ByteRange::Full);
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 1000), ByteRange::Full);
+    }
+
+    #[test]
+    fn invalid_ranges_are_unsatisfiable() {
+        for header in ["bytes=-", "bytes=5", "bytes=a-b", "bytes=-0", "bytes=1000-", "bytes=20-10"]
+        {
+            assert_eq!(parse_range(Some(header), 1000), ByteRange::Unsatisfiable, "{header}");
+        }
+
+        assert_eq!(parse_range(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+    }
+}
@@ -0,0 +1,659 @@
+use std::io::{Error as IoError, ErrorKind};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use actix_web::body::SizedStream;
+use actix_web::http::header::{
+    ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_RANGE, CONTENT_TYPE,
+    ContentDisposition, DispositionParam, DispositionType, HeaderName, HeaderValue, RANGE,
+    USER_AGENT, VARY, X_CONTENT_TYPE_OPTIONS,
+};
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::{Bytes, Query};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder};
+use database::{DatabaseError, FileModel, ServedFile};
+use futures::Stream;
+use futures::stream::try_unfold;
+use log::warn;
+use storage::{Storage, StorageError};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::extractors::admin::require_admin;
+use crate::extractors::auth::OptionalUser;
+use crate::middleware::forwarded::ClientInfo;
+use crate::middleware::tenant::Tenant;
+use crate::processing::precompress::{self, precompressed_key};
+use crate::utils::app_storage::AppStorage;
+use crate::utils::compression::{Encoding, NoCompression, accepted};
+use crate::utils::encoding::UNKNOWN_ENCODING;
+use crate::utils::file_cache::FileCache;
+use crate::utils::hashing::{HashAlgorithm, blob_key};
+use crate::utils::headers::{first_header_value, joined_header_values, single_header_value};
+use crate::utils::inline::{inline_policy, mime_to_extension};
+use crate::utils::purge::{bucket_key, file_key, owner_key};
+use crate::utils::range::{ByteRange, parse_range};
+use crate::utils::stat_cache::StatCache;
+use crate::utils::view_stats::ViewStats;
+
+/// Bytes read from storage at once when a blob is streamed.
+const STREAM_CHUNK: u64 = 256 * 1024;
+
+/// Bytes of a blob its type is sniffed from, magic numbers are well within it.
+const SNIFF_LENGTH: u64 = 8 * 1024;
+
+/// Files found with another size than recorded since startup.
+static SIZE_MISMATCHES: AtomicU64 = AtomicU64::new(0);
+
+const X_ROBOTS_TAG: HeaderName = HeaderName::from_static("x-robots-tag");
+
+/// Read by Fastly, space separated.
+const SURROGATE_KEY: HeaderName = HeaderName::from_static("surrogate-key");
+
+/// Read by Cloudflare, comma separated.
+const CACHE_TAG: HeaderName = HeaderName::from_static("cache-tag");
+
+/// The storage key of the blob holding the file content,
+/// appendable files change so they're keyed by id instead.
+pub fn file_blob_key(file: &FileModel) -> Result<String, AppError> {
+    if file.appendable() {
+        return Ok(format!("append/{}", file.id()));
+    }
+
+    let key = file
+        .hash()
+        .zip(HashAlgorithm::parse(file.hash_algorithm()))
+        .map(|(hash, algorithm)| blob_key(algorithm, hash))
+        .ok_or(DatabaseError::ModelNotFound("file"))?;
+
+    Ok(key)
+}
+
+/// The storage key of the upload as sent, only
+/// files transcoded to UTF-8 have one.
+pub fn original_blob_key(file: &FileModel) -> Result<String, AppError> {
+    let key = file
+        .original_hash()
+        .zip(HashAlgorithm::parse(file.hash_algorithm()))
+        .map(|(hash, algorithm)| blob_key(algorithm, hash))
+        .ok_or(DatabaseError::ModelNotFound("file"))?;
+
+    Ok(key)
+}
+
+/// On a custom domain only the files of its tenant exist,
+/// anything else is reported as missing.
+pub fn check_tenant(req: &HttpRequest, served: &ServedFile) -> Result<(), AppError> {
+    let tenant = req.extensions().get::<Tenant>().map(|tenant| tenant.owner_id);
+
+    if tenant.is_some_and(|owner_id| served.owner_id != Some(owner_id)) {
+        return Err(DatabaseError::ModelNotFound("file").into());
+    }
+
+    Ok(())
+}
+
+/// Answers with the content of a file, honoring a single `Range`,
+/// only types on the inline allow list are rendered by the browser.
+pub async fn serve_file(
+    req: &HttpRequest,
+    storage: &AppStorage,
+    served: &ServedFile,
+) -> Result<HttpResponse, AppError> {
+    let name = served.file.path().rsplit('/').next().unwrap_or_default();
+
+    serve_file_named(req, storage, served, name).await
+}
+
+/// Like `serve_file`, but downloads are named `name`.
+pub async fn serve_file_named(
+    req: &HttpRequest,
+    storage: &AppStorage,
+    served: &ServedFile,
+    name: &str,
+) -> Result<HttpResponse, AppError> {
+    let file = &served.file;
+    // A normalized copy is always UTF-8 whatever was detected.
+    let charset = if file.normalized() { Some("UTF-8") } else { file.text_encoding() };
+
+    let key = file_blob_key(file)?;
+    let response = serve_blob(req, storage, served, &key, Some(file.size()), name, charset).await?;
+
+    if is_view(req, &response) {
+        let user_agent = first_header_value(req, USER_AGENT).unwrap_or_default();
+        ViewStats::get().record(file.id(), ClientInfo::get(req).ip, user_agent);
+    }
+
+    Ok(response)
+}
+
+/// Whether a response shows the file, players fetching the rest
+/// of a video by ranges only count for their first one.
+fn is_view(req: &HttpRequest, response: &HttpResponse) -> bool {
+    let first_range = || {
+        response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .is_some_and(|range| range.starts_with("bytes 0-"))
+    };
+
+    req.method() == Method::GET
+        && (response.status() == StatusCode::OK
+            || response.status() == StatusCode::PARTIAL_CONTENT && first_range())
+}
+
+/// Answers with the blob at `key` on behalf of `file`, text
+/// is labeled with `charset` when it's known.
+///
+/// `recorded_size` is the size of the blob as recorded with the file, when
+/// the blob holds its content, see `blob_size`.
+pub async fn serve_blob(
+    req: &HttpRequest,
+    storage: &AppStorage,
+    served: &ServedFile,
+    key: &str,
+    recorded_size: Option<i64>,
+    name: &str,
+    charset: Option<&str>,
+) -> Result<HttpResponse, AppError> {
+    check_tenant(req, served)?;
+
+    let file = &served.file;
+
+    let verify = verify_requested(req, served.owner_id).await?;
+    let size = blob_size(storage, file, key, recorded_size, verify).await?;
+
+    let head = storage.get_range(key, 0..size.min(SNIFF_LENGTH)).await?;
+
+    let header = single_header_value(req, RANGE)?;
+
+    let range = match parse_range(header, size) {
+        ByteRange::Full => None,
+        ByteRange::Partial(range) => Some(range),
+        ByteRange::Unsatisfiable => return Err(AppError::RangeNotSatisfiable(size)),
+    };
+
+    let indexable = Config::get().seo_allow_indexing && served.indexable;
+
+    let Some(range) = range else {
+        let mut response = content_headers(HttpResponse::Ok(), req, served, name, charset, &head);
+        robots_header(&mut response, indexable);
+        vary_header(&mut response, file);
+
+        if let Some((encoding, variant, size)) = precompressed(req, storage, file, key).await {
+            return Ok(response
+                .insert_header((CONTENT_ENCODING, encoding.as_str()))
+                .body(blob_stream(storage, &variant, 0..size)));
+        }
+
+        return Ok(response.body(blob_stream(storage, key, 0..size)));
+    };
+
+    let mut response =
+        content_headers(HttpResponse::PartialContent(), req, served, name, charset, &head);
+    robots_header(&mut response, indexable);
+    vary_header(&mut response, file);
+
+    Ok(response
+        .insert_header((CONTENT_RANGE, format!("bytes {}-{}/{size}", range.start(), range.end())))
+        .body(blob_stream(storage, key, *range.start()..range.end() + 1)))
+}
+
+#[derive(serde::Deserialize)]
+struct VerifyQuery {
+    #[serde(default)]
+    verify: bool,
+}
+
+/// Whether the request asked with `?verify=true` for the size of the file to
+/// be checked against storage, honored only for its owner and admins since
+/// skipping the `StatCache` costs a round trip to storage and a database write.
+async fn verify_requested(req: &HttpRequest, owner_id: Option<i64>) -> Result<bool, AppError> {
+    let requested =
+        Query::<VerifyQuery>::from_query(req.query_string()).is_ok_and(|query| query.verify);
+
+    if !requested {
+        return Ok(false);
+    }
+
+    let OptionalUser(Some(user)) = OptionalUser::extract(req).await? else {
+        return Ok(false);
+    };
+
+    if owner_id == Some(user.id()) {
+        return Ok(true);
+    }
+
+    match require_admin(user).await {
+        Ok(_) => Ok(true),
+        Err(AppError::AdminRequired) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// The size of the blob at `key`, the recorded size of the content of `file`
+/// is trusted so serving it costs no round trip to storage.
+///
+/// Storage is asked instead, through the `StatCache`, for blobs without a
+/// recorded size, files stored before sizes were checked and flagged ones.
+/// `verify` always asks it, bypassing the cache. A recorded size found to
+/// match is marked as checked, one that doesn't flags the file and what's
+/// stored is served.
+async fn blob_size(
+    storage: &AppStorage,
+    file: &FileModel,
+    key: &str,
+    recorded_size: Option<i64>,
+    verify: bool,
+) -> Result<u64, AppError> {
+    let recorded = recorded_size.map(|size| size as u64);
+
+    if let Some(recorded) = recorded.filter(|_| file.size_verified() && !verify) {
+        return Ok(recorded);
+    }
+
+    let stored = if verify {
+        StatCache::get().refresh(storage, key).await?
+    } else {
+        StatCache::get().size(storage, key).await?
+    };
+
+    let Some(recorded) = recorded else {
+        return Ok(stored);
+    };
+
+    // Bytes past the recorded size belong to an append still in flight.
+    if stored == recorded || file.appendable() && stored > recorded {
+        if !file.size_verified() {
+            file.verify_size().await?;
+            FileCache::get().forget(file.id());
+        }
+
+        return Ok(recorded);
+    }
+
+    flag_size_mismatch(file, stored).await?;
+
+    Ok(stored)
+}
+
+/// Counts and flags a file whose content was found with `stored` bytes
+/// instead of the recorded size, for the consistency checks to look at.
+async fn flag_size_mismatch(file: &FileModel, stored: u64) -> Result<(), AppError> {
+    if file.mismatched_size() == Some(stored as i64) {
+        return Ok(());
+    }
+
+    if file.flag_size_mismatch(stored as i64).await? {
+        SIZE_MISMATCHES.fetch_add(1, Ordering::Relaxed);
+        FileCache::get().forget(file.id());
+
+        warn!("File {} records {} bytes but {stored} are stored", file.id(), file.size());
+    }
+
+    Ok(())
+}
+
+/// Files found with another size than recorded since startup, each
+/// counted once until its size changes.
+pub fn size_mismatches() -> u64 {
+    SIZE_MISMATCHES.load(Ordering::Relaxed)
+}
+
+/// Streams `range` of the blob at `key`, reading `STREAM_CHUNK` at a time
+/// so serving a file never holds more than that of it in memory.
+fn blob_stream(
+    storage: &AppStorage,
+    key: &str,
+    range: Range<u64>,
+) -> SizedStream<impl Stream<Item = Result<Bytes, StorageError>> + use<>> {
+    let storage = storage.clone();
+    let key = key.to_owned();
+    let size = range.end - range.start;
+
+    let chunks = try_unfold(range.start, move |offset| {
+        let (storage, key) = (storage.clone(), key.clone());
+
+        async move {
+            if offset >= range.end {
+                return Ok(None);
+            }
+
+            let chunk =
+                storage.get_range(&key, offset..(offset + STREAM_CHUNK).min(range.end)).await?;
+
+            // The blob shrank since its size was read, the response can't be completed.
+            if chunk.is_empty() {
+                return Err(IoError::from(ErrorKind::UnexpectedEof).into());
+            }
+
+            let next = offset + chunk.len() as u64;
+            Ok(Some((Bytes::from(chunk), next)))
+        }
+    });
+
+    SizedStream::new(size, chunks)
+}
+
+/// The best stored variant of the blob at `key` the client accepts, ranges
+/// and appendable files are always served from the content as stored.
+async fn precompressed(
+    req: &HttpRequest,
+    storage: &AppStorage,
+    file: &FileModel,
+    key: &str,
+) -> Option<(Encoding, String, u64)> {
+    if !precompress::enabled() || file.appendable() {
+        return None;
+    }
+
+    let accept_encoding = joined_header_values(req, ACCEPT_ENCODING).ok().flatten()?;
+
+    best_variant(storage, key, &accept_encoding).await
+}
+
+/// The key and size of the first variant of the blob at `key` stored for
+/// an encoding `accept_encoding` allows, brotli first.
+async fn best_variant(
+    storage: &AppStorage,
+    key: &str,
+    accept_encoding: &str,
+) -> Option<(Encoding, String, u64)> {
+    for encoding in accepted(accept_encoding) {
+        let variant = precompressed_key(key, encoding);
+
+        if let Ok(size) = StatCache::get().size(storage, &variant).await {
+            return Some((encoding, variant, size));
+        }
+    }
+
+    None
+}
+
+/// Tells caches the content depends on `Accept-Encoding` whenever a
+/// precompressed variant may be served, including when this client got none.
+fn vary_header(response: &mut HttpResponseBuilder, file: &FileModel) {
+    if precompress::enabled() && !file.appendable() {
+        response.insert_header((VARY, HeaderValue::from_static("accept-encoding")));
+    }
+}
+
+/// Keeps search engines from indexing files that aren't indexable.
+fn robots_header(response: &mut HttpResponseBuilder, indexable: bool) {
+    if !indexable {
+        response.insert_header((X_ROBOTS_TAG, "noindex"));
+    }
+}
+
+/// The name content is offered under, files stored without a name, or
+/// named after their hash, become `download` with an extension for their type.
+fn download_name(name: &str, hash: Option<&str>, content_type: &str) -> String {
+    let unnamed = name.is_empty() || hash.is_some_and(|hash| name.eq_ignore_ascii_case(hash));
+
+    if !unnamed {
+        return name.to_owned();
+    }
+
+    match mime_to_extension(content_type) {
+        Some(extension) => format!("download.{extension}"),
+        None => "download".to_owned(),
+    }
+}
+
+/// Sets the headers every served content gets, the type is sniffed
+/// from the start of the blob even when a later range is served.
+///
+/// Content is always sent as stored, so ranges keep matching its bytes.
+fn content_headers(
+    mut response: HttpResponseBuilder,
+    req: &HttpRequest,
+    served: &ServedFile,
+    name: &str,
+    charset: Option<&str>,
+    head: &[u8],
+) -> HttpResponseBuilder {
+    let file = &served.file;
+    let policy = inline_policy(req, head, file.path());
+
+    let content_type = match charset {
+        Some(charset)
+            if policy.content_type.starts_with("text/") && charset != UNKNOWN_ENCODING =>
+        {
+            format!("{}; charset={charset}", policy.content_type)
+        },
+        _ => policy.content_type.to_owned(),
+    };
+
+    let disposition = ContentDisposition {
+        disposition: if policy.inline {
+            DispositionType::Inline
+        } else {
+            DispositionType::Attachment
+        },
+        parameters: vec![DispositionParam::Filename(download_name(
+            name,
+            file.hash(),
+            policy.content_type,
+        ))],
+    };
+
+    response
+        .insert_header((ACCEPT_RANGES, "bytes"))
+        .insert_header((CONTENT_TYPE, content_type))
+        .insert_header(disposition)
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"));
+
+    if Config::get().surrogate_keys {
+        let keys = surrogate_keys(file.id(), file.bucket_id(), served.owner_id);
+
+        for header in surrogate_key_headers(&keys) {
+            response.insert_header(header);
+        }
+    }
+
+    response.extensions_mut().insert(NoCompression);
+
+    response
+}
+
+/// The keys a cache in front of the origin can purge the content of a
+/// file by, they only depend on ids so they never change. Anonymous
+/// uploads have no owner key.
+pub fn surrogate_keys(file_id: i64, bucket_id: i64, owner_id: Option<i64>) -> Vec<String> {
+    [Some(file_key(file_id)), Some(bucket_key(bucket_id)), owner_id.map(owner_key)]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// `keys` the way Fastly and Cloudflare each read them.
+fn surrogate_key_headers(keys: &[String]) -> [(HeaderName, String); 2] {
+    [(SURROGATE_KEY, keys.join(" ")), (CACHE_TAG, keys.join(","))]
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::ResponseError;
+    use actix_web::body::{BodySize, MessageBody, to_bytes};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+    use crate::testing::{
+        create_bucket, create_file, create_user, get, run, unique_name, unverify_size, upload_file,
+    };
+    use crate::utils::app_storage::open_storage;
+
+    fn body_size(response: &ServiceResponse) -> BodySize {
+        response.response().body().size()
+    }
+
+    #[test]
+    fn download_name_falls_back_for_unnamed_files() {
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+        assert_eq!(download_name("report.pdf", Some(hash), "application/pdf"), "report.pdf");
+        assert_eq!(download_name("", Some(hash), "image/png"), "download.png");
+        assert_eq!(download_name(hash, Some(hash), "image/jpeg"), "download.jpg");
+        assert_eq!(download_name("", None, "application/x-not-a-type"), "download");
+    }
+
+    #[test]
+    fn surrogate_keys_are_stable_ids() {
+        assert_eq!(surrogate_keys(42, 7, Some(3)), ["file-42", "bucket-7", "owner-3"]);
+        assert_eq!(surrogate_keys(42, 7, None), ["file-42", "bucket-7"]);
+    }
+
+    #[test]
+    fn surrogate_keys_are_listed_for_each_cache() {
+        let keys = surrogate_keys(42, 7, Some(3));
+        let [(fastly, fastly_keys), (cloudflare, cloudflare_keys)] = surrogate_key_headers(&keys);
+
+        assert_eq!(
+            (fastly.as_str(), fastly_keys.as_str()),
+            ("surrogate-key", "file-42 bucket-7 owner-3")
+        );
+        assert_eq!(
+            (cloudflare.as_str(), cloudflare_keys.as_str()),
+            ("cache-tag", "file-42,bucket-7,owner-3")
+        );
+    }
+
+    #[test]
+    #[ignore = "needs SIGNING_SECRET and STORAGE_PATH"]
+    fn the_best_stored_variant_is_streamed() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let key = format!("test/{}", unique_name("variant"));
+            let (gzip, brotli) = (
+                precompressed_key(&key, Encoding::Gzip),
+                precompressed_key(&key, Encoding::Brotli),
+            );
+
+            assert!(best_variant(&storage, &key, "gzip, br").await.is_none());
+
+            storage.put(&gzip, b"gzipped").await.unwrap();
+            let (encoding, variant, size) = best_variant(&storage, &key, "gzip, br").await.unwrap();
+            assert_eq!((encoding.as_str(), variant.as_str(), size), ("gzip", gzip.as_str(), 7));
+
+            storage.put(&brotli, b"brotli").await.unwrap();
+            let (encoding, variant, size) = best_variant(&storage, &key, "gzip, br").await.unwrap();
+            assert_eq!((encoding.as_str(), variant.as_str(), size), ("br", brotli.as_str(), 6));
+
+            let body = to_bytes(blob_stream(&storage, &variant, 0..size)).await.unwrap();
+            assert_eq!(body, "brotli");
+
+            let (encoding, ..) = best_variant(&storage, &key, "br;q=0, gzip").await.unwrap();
+            assert_eq!(encoding.as_str(), "gzip");
+            assert!(best_variant(&storage, &key, "identity").await.is_none());
+
+            storage.delete(&gzip).await.unwrap();
+            storage.delete(&brotli).await.unwrap();
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn the_tenant_is_checked_against_the_owner_read_with_the_file() {
+        run(async {
+            let owner = create_user(&unique_name("tenant")).await;
+            let file = create_file(&create_bucket(&owner).await, "tenant.txt").await;
+            let served = file.into_served().await.unwrap();
+            assert_eq!(served.owner_id, Some(owner.id()));
+            assert!(served.indexable);
+
+            let on = |owner_id| {
+                let req = TestRequest::default().to_http_request();
+                req.extensions_mut().insert(Tenant { owner_id, base_url: String::new() });
+                req
+            };
+
+            assert!(check_tenant(&TestRequest::default().to_http_request(), &served).is_ok());
+            assert!(check_tenant(&on(owner.id()), &served).is_ok());
+
+            let error = check_tenant(&on(owner.id() + 1), &served).err().unwrap();
+            assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+
+            let update = serde_json::from_value(serde_json::json!({ "indexable": false })).unwrap();
+            let file = served.file.edit(update, None, None).await.unwrap().unwrap();
+            assert!(!file.into_served().await.unwrap().indexable);
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn legacy_files_are_checked_until_their_size_matches() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("legacy")).await).await;
+            let content = unique_name("legacy");
+            let file = upload_file(&storage, &bucket, "legacy.txt", content.as_bytes()).await;
+            assert!(file.size_verified());
+
+            unverify_size(&file).await;
+            let file = FileModel::get(file.id()).await.unwrap();
+            assert!(!file.size_verified());
+
+            let response = get(&storage, &format!("/f/{}", file.slug())).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(body_size(&response), BodySize::Sized(content.len() as u64));
+
+            let file = FileModel::get(file.id()).await.unwrap();
+            assert!(file.size_verified());
+            assert_eq!(file.mismatched_size(), None);
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL, SIGNING_SECRET and STORAGE_PATH"]
+    fn mismatched_sizes_are_counted_and_flagged() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let bucket = create_bucket(&create_user(&unique_name("mismatch")).await).await;
+            let content = unique_name("mismatch");
+            let file = upload_file(&storage, &bucket, "mismatch.txt", content.as_bytes()).await;
+            let key = file_blob_key(&file).unwrap();
+            let path = format!("/f/{}", file.slug());
+
+            // The blob is truncated behind the server's back.
+            storage.put(&key, b"lost").await.unwrap();
+
+            // The recorded size is trusted, storage isn't asked.
+            let response = get(&storage, &path).await;
+            assert_eq!(body_size(&response), BodySize::Sized(content.len() as u64));
+
+            // Anonymous clients can't make it ask storage either.
+            let response = get(&storage, &format!("{path}?verify=true")).await;
+            assert_eq!(body_size(&response), BodySize::Sized(content.len() as u64));
+            assert!(FileModel::get(file.id()).await.unwrap().size_verified());
+
+            // Once it's to be checked again the truncation is found.
+            unverify_size(&file).await;
+            FileCache::get().forget(file.id());
+            let before = size_mismatches();
+            let response = get(&storage, &path).await;
+            assert_eq!(body_size(&response), BodySize::Sized(4));
+            assert!(size_mismatches() > before);
+
+            let file = FileModel::get(file.id()).await.unwrap();
+            assert!(!file.size_verified());
+            assert_eq!(file.mismatched_size(), Some(4));
+
+            // Flagged files are served with what's stored until it matches again.
+            let response = get(&storage, &path).await;
+            assert_eq!(body_size(&response), BodySize::Sized(4));
+
+            storage.put(&key, content.as_bytes()).await.unwrap();
+            StatCache::get().forget(&key);
+
+            let response = get(&storage, &path).await;
+            assert_eq!(body_size(&response), BodySize::Sized(content.len() as u64));
+
+            let file = FileModel::get(file.id()).await.unwrap();
+            assert!(file.size_verified());
+            assert_eq!(file.mismatched_size(), None);
+        })
+    }
+}
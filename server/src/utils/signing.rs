@@ -0,0 +1,220 @@
+use std::net::IpAddr;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::AppError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The claims carried by a signed URL, every binding
+/// is optional and only checked when it was encoded at signing time.
+pub struct SignedUrl {
+    pub file_id: i64,
+    pub expires_at: i64,
+    pub jti: String,
+    pub ip_prefix: Option<String>,
+    pub max_uses: Option<i32>,
+    pub ua_hash: Option<String>,
+    /// Milliseconds since the epoch, tokens from before a link
+    /// rotation are revoked, `0` for tokens predating this claim.
+    pub issued_at: i64,
+}
+
+impl SignedUrl {
+    pub fn new(file_id: i64, expires_at: i64) -> Self {
+        Self {
+            file_id,
+            expires_at,
+            jti: format!("{:032x}", rand::random::<u128>()),
+            ip_prefix: None,
+            max_uses: None,
+            ua_hash: None,
+            issued_at: Utc::now().timestamp_millis(),
+        }
+    }
+
+    pub fn bind_ip(mut self, ip: IpAddr) -> Self {
+        self.ip_prefix = Some(ip_prefix(ip));
+        self
+    }
+
+    pub fn bind_max_uses(mut self, max_uses: i32) -> Self {
+        self.max_uses = Some(max_uses);
+        self
+    }
+
+    pub fn bind_user_agent(mut self, user_agent: &str) -> Self {
+        self.ua_hash = Some(ua_hash(user_agent));
+        self
+    }
+
+    /// Serializes and signs the claims into an URL safe token.
+    pub fn sign(&self, secret: &str) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(self.payload());
+        let signature = URL_SAFE_NO_PAD.encode(mac(secret, &payload).finalize().into_bytes());
+
+        format!("{payload}.{signature}")
+    }
+
+    /// Verifies the token signature and decodes its claims,
+    /// the bindings themselves are checked by `check`.
+    pub fn verify(token: &str, secret: &str) -> Result<Self, AppError> {
+        let (payload, signature) = token.split_once('.').ok_or(AppError::SignatureInvalid)?;
+        let signature =
+            URL_SAFE_NO_PAD.decode(signature).map_err(|_| AppError::SignatureInvalid)?;
+
+        mac(secret, payload).verify_slice(&signature).map_err(|_| AppError::SignatureInvalid)?;
+
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| AppError::SignatureInvalid)?;
+        let payload = String::from_utf8(payload).map_err(|_| AppError::SignatureInvalid)?;
+
+        Self::parse(&payload).ok_or(AppError::SignatureInvalid)
+    }
+
+    /// Checks the expiry and the request bound claims,
+    /// `max_uses` needs the database so it's left to the caller.
+    pub fn check(&self, peer: Option<IpAddr>, user_agent: Option<&str>) -> Result<(), AppError> {
+        if self.expires_at < Utc::now().timestamp() {
+            return Err(AppError::SignatureExpired);
+        }
+
+        if let Some(prefix) = &self.ip_prefix
+            && peer.map(ip_prefix).as_ref() != Some(prefix)
+        {
+            return Err(AppError::SignatureWrongIp);
+        }
+
+        if let Some(hash) = &self.ua_hash
+            && user_agent.map(ua_hash).as_ref() != Some(hash)
+        {
+            return Err(AppError::SignatureWrongClient);
+        }
+
+        Ok(())
+    }
+
+    fn payload(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}|{}|{}",
+            self.file_id,
+            self.expires_at,
+            self.jti,
+            self.ip_prefix.as_deref().unwrap_or_default(),
+            self.max_uses.map(|uses| uses.to_string()).unwrap_or_default(),
+            self.ua_hash.as_deref().unwrap_or_default(),
+            self.issued_at
+        )
+    }
+
+    fn parse(payload: &str) -> Option<Self> {
+        let mut parts = payload.split('|');
+        let optional = |part: &str| (!part.is_empty()).then(|| part.to_owned());
+
+        let signed = Self {
+            file_id: parts.next()?.parse().ok()?,
+            expires_at: parts.next()?.parse().ok()?,
+            jti: parts.next()?.to_owned(),
+            ip_prefix: optional(parts.next()?),
+            max_uses: optional(parts.next()?).map(|uses| uses.parse()).transpose().ok()?,
+            ua_hash: optional(parts.next()?),
+            issued_at: parts.next().map(str::parse).transpose().ok()?.unwrap_or(0),
+        };
+
+        parts.next().is_none().then_some(signed)
+    }
+}
+
+fn mac(secret: &str, payload: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Reduces an address to its /24 (IPv4) or /64 (IPv6) network,
+/// so clients renewing their lease in the same network still match.
+fn ip_prefix(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{a}.{b}.{c}.0/24")
+        },
+        IpAddr::V6(ip) => {
+            let [a, b, c, d, ..] = ip.segments();
+            format!("{a:x}:{b:x}:{c:x}:{d:x}::/64")
+        },
+    }
+}
+
+fn ua_hash(user_agent: &str) -> String {
+    URL_SAFE_NO_PAD.encode(&Sha256::digest(user_agent.as_bytes())[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    fn signed() -> SignedUrl {
+        SignedUrl::new(42, Utc::now().timestamp() + 60)
+            .bind_ip("192.0.2.10".parse().unwrap())
+            .bind_max_uses(3)
+            .bind_user_agent("curl/8.0")
+    }
+
+    #[test]
+    fn claims_survive_signing() {
+        let signed = signed();
+        let verified = SignedUrl::verify(&signed.sign(SECRET), SECRET).unwrap();
+
+        assert_eq!(verified.file_id, 42);
+        assert_eq!(verified.expires_at, signed.expires_at);
+        assert_eq!(verified.jti, signed.jti);
+        assert_eq!(verified.ip_prefix.as_deref(), Some("192.0.2.0/24"));
+        assert_eq!(verified.max_uses, Some(3));
+        assert_eq!(verified.issued_at, signed.issued_at);
+        assert!(verified.check(Some("192.0.2.99".parse().unwrap()), Some("curl/8.0")).is_ok());
+    }
+
+    #[test]
+    fn tampered_tokens_are_refused() {
+        let token = signed().sign(SECRET);
+        let (payload, signature) = token.split_once('.').unwrap();
+
+        let forged = SignedUrl::new(43, i64::MAX).sign("other");
+        let (forged, _) = forged.split_once('.').unwrap();
+
+        for token in [
+            format!("{forged}.{signature}"),
+            format!("{payload}.{}", &signature[1..]),
+            payload.to_owned(),
+        ] {
+            assert!(matches!(SignedUrl::verify(&token, SECRET), Err(AppError::SignatureInvalid)));
+        }
+
+        assert!(SignedUrl::verify(&token, "other").is_err());
+    }
+
+    #[test]
+    fn bindings_are_checked() {
+        let signed = signed();
+
+        assert!(matches!(
+            signed.check(Some("198.51.100.1".parse().unwrap()), Some("curl/8.0")),
+            Err(AppError::SignatureWrongIp)
+        ));
+        assert!(matches!(
+            signed.check(Some("192.0.2.1".parse().unwrap()), None),
+            Err(AppError::SignatureWrongClient)
+        ));
+        assert!(matches!(
+            SignedUrl::new(1, Utc::now().timestamp() - 1).check(None, None),
+            Err(AppError::SignatureExpired)
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use database::FileModel;
+
+use crate::AppError;
+
+/// Most URLs a single sitemap may list, per the sitemaps protocol.
+pub const SITEMAP_PAGE_SIZE: i64 = 50_000;
+
+/// Sitemaps are rebuilt at most once an hour per base URL and page.
+const CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Cached sitemaps kept before the cache is emptied.
+const MAX_ENTRIES: usize = 256;
+
+/// Built sitemaps and when they were built, by base URL and page.
+type SitemapCache = HashMap<(String, i64), (Instant, Option<String>)>;
+
+static CACHE: OnceLock<Mutex<SitemapCache>> = OnceLock::new();
+
+/// The sitemaps needed to list `total` URLs, there's always at least one.
+pub fn sitemap_pages(total: i64) -> i64 {
+    (total.max(0) as u64).div_ceil(SITEMAP_PAGE_SIZE as u64).max(1) as i64
+}
+
+/// Renders `/sitemap.xml` for `page` 0 and `/sitemap-<page>.xml` otherwise,
+/// `None` when there's no such page.
+///
+/// Past one page the root becomes an index of the numbered pages.
+pub async fn sitemap(base_url: &str, page: i64) -> Result<Option<String>, AppError> {
+    let cache = CACHE.get_or_init(Default::default);
+    let key = (base_url.to_owned(), page);
+
+    let cached = cache
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .get(&key)
+        .filter(|(built_at, _)| built_at.elapsed() < CACHE_TTL)
+        .map(|(_, xml)| xml.clone());
+
+    if let Some(xml) = cached {
+        return Ok(xml);
+    }
+
+    let pages = sitemap_pages(FileModel::count_indexable().await?);
+
+    let xml = match page {
+        0 if pages > 1 => Some(sitemap_index(base_url, pages)),
+        0 => Some(url_set(base_url, 0).await?),
+        page if pages > 1 && (1..=pages).contains(&page) => {
+            Some(url_set(base_url, page - 1).await?)
+        },
+        _ => None,
+    };
+
+    let mut entries = cache.lock().unwrap_or_else(|error| error.into_inner());
+
+    // The base URL may come from the Host header, so the cache is bounded.
+    if entries.len() >= MAX_ENTRIES {
+        entries.clear();
+    }
+
+    entries.insert(key, (Instant::now(), xml.clone()));
+
+    Ok(xml)
+}
+
+fn sitemap_index(base_url: &str, pages: i64) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#,
+        "\n",
+    ));
+
+    for page in 1..=pages {
+        xml += &format!("<sitemap><loc>{}/sitemap-{page}.xml</loc></sitemap>\n", escape(base_url));
+    }
+
+    xml + "</sitemapindex>\n"
+}
+
+async fn url_set(base_url: &str, page: i64) -> Result<String, AppError> {
+    let entries = FileModel::list_indexable(page * SITEMAP_PAGE_SIZE, SITEMAP_PAGE_SIZE).await?;
+
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#,
+        "\n",
+    ));
+
+    for entry in entries {
+        xml += &format!(
+            "<url><loc>{}/f/{}</loc><lastmod>{}</lastmod></url>\n",
+            escape(base_url),
+            escape(&entry.slug),
+            entry.last_modified_at.format("%Y-%m-%dT%H:%M:%SZ")
+        );
+    }
+
+    Ok(xml + "</urlset>\n")
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        create_bucket, create_file, create_user, run, set_visibility, unique_name,
+    };
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn only_files_of_public_buckets_are_listed() {
+        run(async {
+            let owner = create_user(&unique_name("sitemap")).await;
+            let public = set_visibility(create_bucket(&owner).await, "public").await;
+            let listed = create_file(&public, "listed.txt").await;
+            let private = create_file(&create_bucket(&owner).await, "private.txt").await;
+            let unlisted = set_visibility(create_bucket(&owner).await, "unlisted").await;
+            let unlisted = create_file(&unlisted, "unlisted.txt").await;
+
+            let entries = FileModel::list_indexable(0, i64::MAX).await.unwrap();
+            let listed_slug =
+                |file: &FileModel| entries.iter().any(|entry| entry.slug == file.slug());
+
+            assert!(listed_slug(&listed));
+            assert!(!listed_slug(&private));
+            assert!(!listed_slug(&unlisted));
+
+            let sitemap = url_set("https://cdn.example", 0).await.unwrap();
+            assert!(!sitemap.contains(private.slug()));
+        })
+    }
+}
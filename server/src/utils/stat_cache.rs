@@ -0,0 +1,137 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use database::FileModel;
+use hashlink::LinkedHashMap;
+use storage::Storage;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::serving::{file_blob_key, original_blob_key};
+
+static STAT_CACHE: OnceLock<StatCache> = OnceLock::new();
+
+struct Entry {
+    size: u64,
+    expires_at: Instant,
+}
+
+/// Sizes of blobs recently read from storage, for the stats serving can't
+/// avoid such as those of files stored before their size was checked,
+/// bounded by `STAT_CACHE_CAPACITY` and expiring after `STAT_CACHE_TTL`.
+///
+/// Only sizes found are kept, a blob stored meanwhile is seen right away.
+///
+/// Every change to the content of a file must `forget_file` it.
+pub struct StatCache {
+    entries: Mutex<LinkedHashMap<String, Entry>>,
+}
+
+impl StatCache {
+    pub fn get() -> &'static Self {
+        STAT_CACHE.get_or_init(StatCache::new)
+    }
+
+    fn new() -> Self {
+        StatCache {
+            entries: Mutex::new(LinkedHashMap::new()),
+        }
+    }
+
+    /// The size of the blob at `key`, read from storage unless it's cached.
+    pub async fn size(&self, storage: &AppStorage, key: &str) -> Result<u64, AppError> {
+        match self.lookup(key) {
+            Some(size) => Ok(size),
+            None => self.refresh(storage, key).await,
+        }
+    }
+
+    /// The size of the blob at `key` read from storage, whatever was cached.
+    pub async fn refresh(&self, storage: &AppStorage, key: &str) -> Result<u64, AppError> {
+        let size = storage.size(key).await?;
+
+        let config = Config::get();
+        let ttl = Duration::from_secs(config.stat_cache_ttl);
+        self.insert_for(key, size, ttl, config.stat_cache_capacity);
+
+        Ok(size)
+    }
+
+    pub fn lookup(&self, key: &str) -> Option<u64> {
+        let mut entries = self.entries.lock().unwrap_or_else(|error| error.into_inner());
+
+        match entries.to_back(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.size),
+            Some(_) => {
+                entries.remove(key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    fn insert_for(&self, key: &str, size: u64, ttl: Duration, capacity: usize) {
+        if capacity == 0 || ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap_or_else(|error| error.into_inner());
+        entries.remove(key);
+
+        while entries.len() >= capacity {
+            entries.pop_front();
+        }
+
+        entries.insert(key.to_owned(), Entry { size, expires_at: Instant::now() + ttl });
+    }
+
+    /// Drops the blob at `key`, called whenever it's deleted.
+    pub fn forget(&self, key: &str) {
+        self.entries.lock().unwrap_or_else(|error| error.into_inner()).remove(key);
+    }
+
+    /// Drops the blobs holding the content of `file`, called whenever
+    /// it's replaced, appended to or deleted.
+    pub fn forget_file(&self, file: &FileModel) {
+        for key in [file_blob_key(file), original_blob_key(file)].into_iter().flatten() {
+            self.forget(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{run, unique_name};
+    use crate::utils::app_storage::open_storage;
+
+    #[test]
+    #[ignore = "needs SIGNING_SECRET and STORAGE_PATH"]
+    fn sizes_are_kept_until_forgotten_or_expired() {
+        run(async {
+            let storage = open_storage().await.unwrap();
+            let key = format!("test/{}", unique_name("stat"));
+            let cache = StatCache::new();
+
+            assert!(cache.size(&storage, &key).await.is_err());
+
+            storage.put(&key, b"first").await.unwrap();
+            assert_eq!(cache.size(&storage, &key).await.unwrap(), 5);
+
+            storage.put(&key, b"second").await.unwrap();
+            assert_eq!(cache.size(&storage, &key).await.unwrap(), 5);
+            assert_eq!(cache.refresh(&storage, &key).await.unwrap(), 6);
+
+            cache.forget(&key);
+            assert!(cache.lookup(&key).is_none());
+
+            cache.insert_for(&key, 6, Duration::from_millis(50), 8);
+            assert_eq!(cache.lookup(&key), Some(6));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(cache.lookup(&key).is_none());
+
+            storage.delete(&key).await.unwrap();
+        })
+    }
+}
@@ -0,0 +1,30 @@
+use crate::AppError;
+use crate::utils::normalize;
+
+/// Longest tag accepted, in characters.
+pub const MAX_TAG_LENGTH: usize = 64;
+
+/// Most tags a single file can carry.
+pub const MAX_TAGS_PER_FILE: usize = 100;
+
+/// Tags are compared case and Unicode form insensitively,
+/// so they're stored in their normalized form.
+pub fn normalize_tag(tag: &str) -> Result<String, AppError> {
+    let tag = normalize::normalize_tag(tag);
+
+    let valid = !tag.is_empty()
+        && tag.chars().count() <= MAX_TAG_LENGTH
+        && !tag.chars().any(char::is_control);
+
+    if valid { Ok(tag) } else { Err(AppError::InvalidTag) }
+}
+
+/// Normalizes every tag, duplicates are dropped.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, AppError> {
+    let mut tags = tags.iter().map(|tag| normalize_tag(tag)).collect::<Result<Vec<_>, _>>()?;
+
+    tags.sort();
+    tags.dedup();
+
+    Ok(tags)
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::mem::take;
+use std::sync::{Mutex, OnceLock};
+
+use actix_web::rt::spawn;
+use chrono::{NaiveDate, Utc};
+use database::{TrafficStatsModel, TrafficTotals};
+use log::warn;
+
+use crate::AppError;
+
+static TRAFFIC: OnceLock<Traffic> = OnceLock::new();
+
+/// Days of a client on a route buffered before they're all written at once.
+const MAX_PENDING: usize = 4096;
+
+/// Clients watched between two abuse checks, the table is emptied when full.
+const MAX_WINDOWS: usize = 64 * 1024;
+
+/// How a request went, as seen on the connection.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Transfer {
+    /// Bytes of the request body read.
+    pub received: u64,
+    /// Bytes of the response body handed to the connection.
+    pub sent: u64,
+    /// Answered with a 4xx.
+    pub rejected: bool,
+    /// The request body broke off before it was whole.
+    pub aborted_upload: bool,
+    /// The response body was dropped before it was whole, the client left.
+    pub disconnected: bool,
+}
+
+impl Transfer {
+    /// Every byte of a request that didn't go through is wasted, both ways.
+    fn totals(&self) -> TrafficTotals {
+        let (bytes_in, bytes_out) = (self.received as i64, self.sent as i64);
+        let wasted = self.rejected || self.aborted_upload || self.disconnected;
+
+        TrafficTotals {
+            requests: 1,
+            bytes_in,
+            bytes_out,
+            wasted_bytes: if wasted { bytes_in + bytes_out } else { 0 },
+            aborted_uploads: i64::from(self.aborted_upload),
+        }
+    }
+}
+
+/// Traffic buffered in memory per day, client and route, written every
+/// `STATS_FLUSH_INTERVAL` seconds. The totals of every client are kept
+/// apart as well, until the abuse detection takes them.
+pub struct Traffic {
+    pending: Mutex<HashMap<(NaiveDate, String, String), TrafficTotals>>,
+    windows: Mutex<HashMap<String, TrafficTotals>>,
+}
+
+impl Traffic {
+    pub fn get() -> &'static Self {
+        TRAFFIC.get_or_init(Traffic::new)
+    }
+
+    fn new() -> Self {
+        Traffic {
+            pending: Mutex::new(HashMap::new()),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request of `client` on `route`, the buffer is written
+    /// in the background when it holds too many of them.
+    pub fn record(&self, client: &str, route: &str, transfer: &Transfer) {
+        let totals = transfer.totals();
+
+        let mut windows = self.windows.lock().unwrap_or_else(|error| error.into_inner());
+
+        if windows.len() >= MAX_WINDOWS && !windows.contains_key(client) {
+            windows.clear();
+        }
+
+        windows.entry(client.to_owned()).or_default().add(&totals);
+        drop(windows);
+
+        let key = (Utc::now().date_naive(), client.to_owned(), route.to_owned());
+        let mut pending = self.pending.lock().unwrap_or_else(|error| error.into_inner());
+
+        let full = (pending.len() >= MAX_PENDING && !pending.contains_key(&key))
+            .then(|| take(&mut *pending));
+
+        pending.entry(key).or_default().add(&totals);
+        drop(pending);
+
+        if let Some(full) = full {
+            spawn(async move {
+                if let Err(error) = write(full).await {
+                    warn!("Couldn't write the buffered traffic: {error}");
+                }
+            });
+        }
+    }
+
+    /// Writes the traffic buffered so far, adding it to what's stored.
+    pub async fn flush(&self) -> Result<(), AppError> {
+        let pending = take(&mut *self.pending.lock().unwrap_or_else(|error| error.into_inner()));
+
+        write(pending).await
+    }
+
+    /// The totals of every client since the last time they were taken.
+    pub fn take_windows(&self) -> HashMap<String, TrafficTotals> {
+        take(&mut *self.windows.lock().unwrap_or_else(|error| error.into_inner()))
+    }
+}
+
+async fn write(
+    pending: HashMap<(NaiveDate, String, String), TrafficTotals>,
+) -> Result<(), AppError> {
+    for ((day, client, route), totals) in pending {
+        TrafficStatsModel::add(day, &client, &route, &totals).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{run, unique_name};
+
+    #[test]
+    fn only_requests_that_failed_waste_bytes() {
+        let served = Transfer {
+            received: 10,
+            sent: 90,
+            ..Transfer::default()
+        };
+        assert_eq!(served.totals().wasted_bytes, 0);
+
+        let rejected = Transfer { rejected: true, ..served };
+        assert_eq!(rejected.totals().wasted_bytes, 100);
+
+        let aborted = Transfer {
+            received: 40,
+            sent: 0,
+            aborted_upload: true,
+            ..served
+        };
+        assert_eq!(aborted.totals().wasted_bytes, 40);
+        assert_eq!(aborted.totals().aborted_uploads, 1);
+
+        let disconnected = Transfer { disconnected: true, ..served };
+        assert_eq!(disconnected.totals().wasted_bytes, 100);
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL"]
+    fn buffered_traffic_is_added_to_the_stored_day() {
+        run(async {
+            let traffic = Traffic::new();
+            let client = unique_name("ip");
+            let transfer = Transfer {
+                received: 100,
+                sent: 20,
+                ..Transfer::default()
+            };
+
+            traffic.record(&client, "/upload", &transfer);
+            traffic.record(&client, "/upload", &Transfer { rejected: true, ..transfer });
+            traffic.flush().await.unwrap();
+            traffic.record(&client, "/upload", &transfer);
+            traffic.flush().await.unwrap();
+
+            let day = Utc::now().date_naive();
+            let entries = TrafficStatsModel::list_day(day, i64::MAX).await.unwrap();
+            let entry = entries.iter().find(|entry| entry.client == client).unwrap();
+
+            assert_eq!(entry.route, "/upload");
+            assert_eq!((entry.requests, entry.bytes_in, entry.bytes_out), (3, 300, 60));
+            assert_eq!(entry.wasted_bytes, 120);
+
+            let windows = traffic.take_windows();
+            assert_eq!(windows[&client].requests, 3);
+            assert!(traffic.take_windows().is_empty());
+        })
+    }
+}
@@ -0,0 +1,405 @@
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+
+use actix_web::web::{Bytes, BytesMut, Payload};
+use chrono::NaiveDateTime;
+use database::{DatabaseError, FileCreation, FileModel, TextMetadata};
+use futures::{Stream, TryStreamExt};
+use storage::Storage;
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::processing::Pipeline;
+use crate::processing::clamav::scan;
+use crate::utils::app_storage::AppStorage;
+use crate::utils::encoding::{UNKNOWN_ENCODING, detect_encoding, is_text_like, to_utf8};
+use crate::utils::file_cache::FileCache;
+use crate::utils::hashing::blob_key;
+use crate::utils::inline::sniff;
+use crate::utils::normalize::natural_sort_key;
+use crate::utils::not_found::NotFoundCache;
+use crate::utils::placeholder::image_dimensions;
+use crate::utils::purge::{file_key, purge};
+use crate::utils::stat_cache::StatCache;
+
+/// Suffixes tried by the `rename` policy before giving up with a conflict.
+const MAX_RENAME_ATTEMPTS: u32 = 100;
+
+/// What an upload named like a file already in its bucket does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadCollision {
+    /// Stores it as `name-1.ext`, `name-2.ext` and so on.
+    Rename,
+    /// Replaces the content of the existing file, only in buckets
+    /// the uploader owns, elsewhere the upload is rejected.
+    Overwrite,
+    /// Answers with a 409.
+    Reject,
+}
+
+impl UploadCollision {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "rename" => Some(UploadCollision::Rename),
+            "overwrite" => Some(UploadCollision::Overwrite),
+            "reject" => Some(UploadCollision::Reject),
+            _ => None,
+        }
+    }
+}
+
+/// `path` with `-<attempt>` before the extension of its last segment.
+fn suffixed(path: &str, attempt: u32) -> String {
+    let segment_start = path.rfind('/').map_or(0, |index| index + 1);
+
+    match path[segment_start..].rfind('.').filter(|index| *index > 0) {
+        Some(index) => {
+            let (stem, extension) = path.split_at(segment_start + index);
+            format!("{stem}-{attempt}{extension}")
+        },
+        None => format!("{path}-{attempt}"),
+    }
+}
+
+/// Creates the file, a name already taken in the bucket is
+/// handled according to `UPLOAD_COLLISION`.
+async fn create_file(creation: FileCreation, owned: bool) -> Result<FileModel, AppError> {
+    let error = match FileModel::create_new(creation.clone()).await {
+        Err(DatabaseError::PathTaken(error)) => DatabaseError::PathTaken(error),
+        result => return Ok(result?),
+    };
+
+    match Config::get().upload_collision {
+        UploadCollision::Rename => {
+            for attempt in 1..=MAX_RENAME_ATTEMPTS {
+                let path = suffixed(&creation.path, attempt);
+
+                let name_key = natural_sort_key(&path);
+                let creation = FileCreation { path, name_key, ..creation.clone() };
+
+                match FileModel::create_new(creation).await {
+                    Err(DatabaseError::PathTaken(_)) => continue,
+                    result => return Ok(result?),
+                }
+            }
+
+            Err(error.into())
+        },
+        UploadCollision::Overwrite if owned => {
+            let existing = FileModel::get_at(creation.bucket_id, &creation.path).await?;
+
+            // Appendable files aren't addressed by a hash, they're appended to instead.
+            if existing.hash().is_none() {
+                return Err(error.into());
+            }
+
+            let file = existing
+                .replace_content(creation.size, &creation.hash, creation.content_type.as_deref())
+                .await?
+                .ok_or(AppError::Database(error))?;
+            StatCache::get().forget_file(&existing);
+            FileCache::get().forget(file.id());
+            purge(vec![file_key(file.id())]);
+
+            Ok(file)
+        },
+        UploadCollision::Overwrite | UploadCollision::Reject => Err(error.into()),
+    }
+}
+
+/// An upload body as received, held in memory up to
+/// `UPLOAD_MEMORY_THRESHOLD` and spooled to disk past it.
+pub enum UploadBody {
+    Memory(Bytes),
+    Spooled(SpooledBody),
+}
+
+/// A body spooled to a file under `UPLOAD_SPOOL_PATH`, removed when dropped.
+pub struct SpooledBody {
+    path: PathBuf,
+    size: usize,
+}
+
+impl Drop for SpooledBody {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl UploadBody {
+    pub fn size(&self) -> usize {
+        match self {
+            UploadBody::Memory(body) => body.len(),
+            UploadBody::Spooled(spooled) => spooled.size,
+        }
+    }
+
+    /// The whole body, read back from disk when it was spooled.
+    ///
+    /// Bodies are hashed, scanned and sealed whole, so one sent
+    /// slowly holds no more than the threshold until it's complete.
+    pub async fn into_bytes(self) -> Result<Bytes, AppError> {
+        match self {
+            UploadBody::Memory(body) => Ok(body),
+            UploadBody::Spooled(spooled) => Ok(fs::read(&spooled.path).await?.into()),
+        }
+    }
+}
+
+/// Reads an upload body of at most `limit` bytes, only once the upload
+/// holds its slot so queued uploads don't buffer their bodies meanwhile.
+pub async fn read_body(payload: &mut Payload, limit: usize) -> Result<Bytes, AppError> {
+    let chunks = payload.map_err(|error| AppError::Io(IoError::other(error)));
+
+    read_upload(chunks, limit).await?.ok_or(AppError::UploadTooLarge(limit))?.into_bytes().await
+}
+
+/// Reads an upload body of at most `limit` bytes as it's received,
+/// `None` once it grows past it.
+pub async fn read_upload(
+    chunks: impl Stream<Item = Result<Bytes, AppError>> + Unpin,
+    limit: usize,
+) -> Result<Option<UploadBody>, AppError> {
+    let config = Config::get();
+
+    spool(chunks, limit, config.upload_memory_threshold, Path::new(&config.upload_spool_path)).await
+}
+
+async fn spool(
+    mut chunks: impl Stream<Item = Result<Bytes, AppError>> + Unpin,
+    limit: usize,
+    threshold: usize,
+    directory: &Path,
+) -> Result<Option<UploadBody>, AppError> {
+    let mut body = BytesMut::new();
+    let mut spooled: Option<(File, SpooledBody)> = None;
+
+    while let Some(chunk) = chunks.try_next().await? {
+        let size = spooled.as_ref().map_or(body.len(), |(_, spooled)| spooled.size);
+
+        if size + chunk.len() > limit {
+            return Ok(None);
+        }
+
+        if let Some((file, spooled)) = &mut spooled {
+            file.write_all(&chunk).await?;
+            spooled.size += chunk.len();
+            continue;
+        }
+
+        body.extend_from_slice(&chunk);
+
+        if body.len() > threshold {
+            let path = directory.join(format!("upload-{:016x}.partial", rand::random::<u64>()));
+            let spooled_body = SpooledBody { path, size: body.len() };
+
+            let mut file = File::create(&spooled_body.path).await?;
+            file.write_all(&body).await?;
+
+            body = BytesMut::new();
+            spooled = Some((file, spooled_body));
+        }
+    }
+
+    Ok(Some(match spooled {
+        Some((mut file, spooled)) => {
+            file.flush().await?;
+            UploadBody::Spooled(spooled)
+        },
+        None => UploadBody::Memory(body.freeze()),
+    }))
+}
+
+/// Refuses infected content and images too large to be processed,
+/// run on every upload before anything is stored.
+pub async fn check_upload(body: &[u8]) -> Result<(), AppError> {
+    let config = Config::get();
+
+    scan(body).await?;
+
+    // Only the header is read, so oversized images are refused before
+    // any post processor decodes them.
+    let too_large = image_dimensions(body)
+        .is_some_and(|(width, height)| width.max(height) > config.max_image_dimension);
+
+    if too_large {
+        return Err(AppError::ImageTooLarge(config.max_image_dimension));
+    }
+
+    Ok(())
+}
+
+/// Stores an uploaded body as `path` in a bucket and runs the post
+/// processors on it, text is transcoded to UTF-8 when `normalize` is set.
+///
+/// `owned` tells whether the uploader owns the bucket, only then
+/// may an existing file be overwritten.
+pub async fn store_upload(
+    storage: &AppStorage,
+    bucket_id: i64,
+    path: String,
+    expires_at: Option<NaiveDateTime>,
+    normalize: bool,
+    owned: bool,
+    body: Bytes,
+) -> Result<FileModel, AppError> {
+    let (creation, body) =
+        store_content(storage, bucket_id, path, expires_at, normalize, body).await?;
+    let file = create_file(creation, owned).await?;
+
+    process_upload(storage, file, &body).await
+}
+
+/// Like `store_upload` for a file imported from disk, which keeps
+/// `created_at` and is skipped when its name is taken in the bucket
+/// instead of following `UPLOAD_COLLISION`.
+///
+/// Returns `None` when it was skipped.
+pub async fn import_upload(
+    storage: &AppStorage,
+    bucket_id: i64,
+    path: String,
+    created_at: Option<NaiveDateTime>,
+    body: Bytes,
+) -> Result<Option<FileModel>, AppError> {
+    let normalize = Config::get().normalize_text_encoding;
+    let (creation, body) = store_content(storage, bucket_id, path, None, normalize, body).await?;
+
+    let file = match FileModel::create_new(FileCreation { created_at, ..creation }).await {
+        Err(DatabaseError::PathTaken(_)) => return Ok(None),
+        result => result?,
+    };
+
+    Ok(Some(process_upload(storage, file, &body).await?))
+}
+
+/// Stores the blob of an upload, along with its original when text
+/// is transcoded, and describes the file to create for it.
+async fn store_content(
+    storage: &AppStorage,
+    bucket_id: i64,
+    path: String,
+    expires_at: Option<NaiveDateTime>,
+    normalize: bool,
+    body: Bytes,
+) -> Result<(FileCreation, Bytes), AppError> {
+    let algorithm = Config::get().hash_algorithm;
+
+    let mut text = None;
+    let mut body = body;
+
+    if is_text_like(&body) {
+        let encoding = detect_encoding(&body);
+
+        let original = match encoding {
+            Some(encoding) if normalize => {
+                let normalized = to_utf8(&body, encoding);
+
+                if *normalized == *body {
+                    None
+                } else {
+                    let original_hash = algorithm.digest(&body);
+                    storage.put_if_absent(&blob_key(algorithm, &original_hash), &body).await?;
+
+                    let original_size = body.len() as i64;
+                    body = Bytes::from(normalized.into_owned());
+
+                    Some((original_hash, original_size))
+                }
+            },
+            _ => None,
+        };
+
+        text = Some(TextMetadata {
+            encoding: encoding.map_or(UNKNOWN_ENCODING, |encoding| encoding.name()).to_owned(),
+            original,
+        });
+    }
+
+    let hash = algorithm.digest(&body);
+
+    storage.put_if_absent(&blob_key(algorithm, &hash), &body).await?;
+
+    let creation = FileCreation {
+        bucket_id,
+        name_key: natural_sort_key(&path),
+        content_type: Some(sniff(&body, &path).to_owned()),
+        path,
+        size: body.len() as i64,
+        hash,
+        hash_algorithm: algorithm.name().to_owned(),
+        created_at: None,
+        expires_at,
+        text,
+    };
+
+    Ok((creation, body))
+}
+
+/// Runs the post processors on a created file.
+async fn process_upload(
+    storage: &AppStorage,
+    file: FileModel,
+    body: &[u8],
+) -> Result<FileModel, AppError> {
+    let file = Pipeline::get().run(file, body, storage).await?;
+    NotFoundCache::get().forget(file.slug());
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    fn chunks(sizes: &[usize]) -> impl Stream<Item = Result<Bytes, AppError>> + Unpin {
+        stream::iter(sizes.iter().map(|size| Ok(Bytes::from(vec![7; *size]))).collect::<Vec<_>>())
+    }
+
+    fn spooled_files(directory: &Path) -> usize {
+        std::fs::read_dir(directory).unwrap().count()
+    }
+
+    #[actix_web::test]
+    async fn only_bodies_past_the_threshold_are_spooled() {
+        let directory = std::env::temp_dir().join(format!("spool-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir(&directory).unwrap();
+
+        let small = spool(chunks(&[100, 100]), 1000, 256, &directory).await.unwrap().unwrap();
+        assert!(matches!(small, UploadBody::Memory(_)));
+        assert_eq!(spooled_files(&directory), 0);
+        assert_eq!(small.into_bytes().await.unwrap(), vec![7; 200]);
+
+        let large = spool(chunks(&[200, 200, 300]), 1000, 256, &directory).await.unwrap().unwrap();
+        assert!(matches!(large, UploadBody::Spooled(_)));
+        assert_eq!(large.size(), 700);
+        assert_eq!(spooled_files(&directory), 1);
+        assert_eq!(large.into_bytes().await.unwrap(), vec![7; 700]);
+        assert_eq!(spooled_files(&directory), 0);
+
+        // Bodies growing past the limit leave nothing behind.
+        let too_large = spool(chunks(&[300, 800]), 1000, 256, &directory).await.unwrap();
+        assert!(too_large.is_none());
+        assert_eq!(spooled_files(&directory), 0);
+
+        std::fs::remove_dir(directory).unwrap();
+    }
+
+    #[test]
+    fn suffix_goes_before_the_extension() {
+        assert_eq!(suffixed("cat.png", 1), "cat-1.png");
+        assert_eq!(suffixed("archive.tar.gz", 2), "archive.tar-2.gz");
+        assert_eq!(suffixed("photos/2024/cat.png", 3), "photos/2024/cat-3.png");
+    }
+
+    #[test]
+    fn names_without_an_extension_get_a_trailing_suffix() {
+        assert_eq!(suffixed("README", 1), "README-1");
+        assert_eq!(suffixed(".env", 1), ".env-1");
+        assert_eq!(suffixed("v1.2/notes", 4), "v1.2/notes-4");
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::HttpResponseBuilder;
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::AppError;
+use crate::config::Config;
+
+static UPLOAD_LIMITER: OnceLock<UploadLimiter> = OnceLock::new();
+
+/// Buckets kept before full ones are swept, the
+/// table is emptied when sweeping doesn't free anything.
+const MAX_ENTRIES: usize = 64 * 1024;
+
+/// Where a client stands against its upload budget, as told
+/// by the `RateLimit-*` headers and the throttled error body.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the budget is whole again.
+    pub reset: Duration,
+    /// Until the next upload is allowed, zero when one is.
+    pub retry_after: Duration,
+}
+
+impl RateLimit {
+    pub fn reset_at(&self) -> DateTime<Utc> {
+        Utc::now() + TimeDelta::from_std(self.reset).unwrap_or_default()
+    }
+
+    /// Adds the fields of the RateLimit header draft, so clients
+    /// can pace themselves before they are ever throttled.
+    pub fn insert_headers(&self, response: &mut HttpResponseBuilder) {
+        response.insert_header(("RateLimit-Limit", self.limit));
+        response.insert_header(("RateLimit-Remaining", self.remaining));
+        response.insert_header(("RateLimit-Reset", ceil_secs(self.reset)));
+    }
+}
+
+/// Whole seconds, rounded up so a client waiting that long is never early.
+pub fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// A token bucket per uploader, refilled at `UPLOAD_RATE_LIMIT` uploads
+/// a minute up to `UPLOAD_RATE_BURST`, kept in memory so every instance
+/// counts on its own.
+///
+/// Uploaders found abusive are held to `ABUSE_RATE_LIMIT` instead,
+/// until their cooldown is over.
+pub struct UploadLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    /// Uploaders held to the stricter budget, until when.
+    tightened: Mutex<HashMap<String, Instant>>,
+}
+
+impl UploadLimiter {
+    pub fn get() -> &'static Self {
+        UPLOAD_LIMITER.get_or_init(|| UploadLimiter {
+            buckets: Mutex::new(HashMap::new()),
+            tightened: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Takes an upload from the client's budget, failing with how long to
+    /// wait when it is spent, `None` when uploads aren't limited.
+    pub fn acquire(&self, key: &str) -> Result<Option<RateLimit>, AppError> {
+        let config = Config::get();
+
+        // The stricter budget gets a bucket of its own, keys keep their budget.
+        if self.tightened_until(key).is_some() {
+            return self.acquire_at(&format!("tightened:{key}"), config.abuse_rate_limit, 1);
+        }
+
+        self.acquire_at(key, config.upload_rate_limit, config.upload_rate_burst)
+    }
+
+    /// Holds `key` to `ABUSE_RATE_LIMIT` for `cooldown`, a client
+    /// found abusive again has its cooldown start over.
+    pub fn tighten(&self, key: &str, cooldown: Duration) {
+        let mut tightened = self.tightened.lock().unwrap_or_else(|error| error.into_inner());
+        let now = Instant::now();
+
+        if tightened.len() >= MAX_ENTRIES {
+            tightened.retain(|_, until| *until > now);
+        }
+
+        tightened.insert(key.to_owned(), now + cooldown);
+    }
+
+    /// Until when `key` is held to the stricter budget, `None` when it isn't.
+    pub fn tightened_until(&self, key: &str) -> Option<Instant> {
+        let mut tightened = self.tightened.lock().unwrap_or_else(|error| error.into_inner());
+
+        match tightened.get(key) {
+            Some(until) if *until > Instant::now() => Some(*until),
+            Some(_) => {
+                tightened.remove(key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    /// Like `acquire` with a budget of `per_minute` refilling up to `burst`,
+    /// for uploads limited apart from the others, `0` doesn't limit.
+    ///
+    /// A key must always be used with the same budget.
+    pub fn acquire_at(
+        &self,
+        key: &str,
+        per_minute: u32,
+        burst: u32,
+    ) -> Result<Option<RateLimit>, AppError> {
+        if per_minute == 0 {
+            return Ok(None);
+        }
+
+        let rate = f64::from(per_minute) / 60.0;
+        let burst = f64::from(burst.max(1));
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|error| error.into_inner());
+
+        if buckets.len() >= MAX_ENTRIES {
+            buckets.retain(|_, bucket| refill(bucket, now, rate, burst) < burst);
+
+            if buckets.len() >= MAX_ENTRIES {
+                buckets.clear();
+            }
+        }
+
+        let bucket =
+            buckets.entry(key.to_owned()).or_insert(Bucket { tokens: burst, updated_at: now });
+
+        let tokens = refill(bucket, now, rate, burst);
+        let allowed = tokens >= 1.0;
+
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let limit = RateLimit {
+            limit: burst as u32,
+            remaining: bucket.tokens as u32,
+            reset: Duration::from_secs_f64((burst - bucket.tokens) / rate),
+            retry_after: Duration::from_secs_f64((1.0 - bucket.tokens).max(0.0) / rate),
+        };
+
+        if allowed { Ok(Some(limit)) } else { Err(AppError::RateLimited(limit)) }
+    }
+}
+
+/// Credits what was earned since the last look, returning the new balance.
+fn refill(bucket: &mut Bucket, now: Instant, rate: f64, burst: f64) -> f64 {
+    let earned = now.duration_since(bucket.updated_at).as_secs_f64() * rate;
+
+    bucket.tokens = (bucket.tokens + earned).min(burst);
+    bucket.updated_at = now;
+    bucket.tokens
+}
+
+/// Users are limited across addresses, anonymous uploads per address.
+pub fn upload_key(user_id: Option<i64>, ip: Option<IpAddr>) -> String {
+    match (user_id, ip) {
+        (Some(id), _) => format!("user:{id}"),
+        (None, Some(ip)) => format!("ip:{ip}"),
+        (None, None) => "ip:-".to_owned(),
+    }
+}
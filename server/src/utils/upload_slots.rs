@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use actix_web::HttpRequest;
+use actix_web::http::header::HeaderName;
+use actix_web::rt::time::timeout;
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::AppError;
+use crate::config::Config;
+use crate::utils::headers::joined_header_values;
+use crate::utils::upload_limit::RateLimit;
+
+static UPLOAD_SLOTS: OnceLock<UploadSlots> = OnceLock::new();
+
+/// Longest an upload waits for a slot, whatever `Prefer: wait` asks for.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// How soon a rejected client is told to retry, uploads
+/// in flight usually end within seconds.
+const RETRY_AFTER: Duration = Duration::from_secs(1);
+
+const PREFER: HeaderName = HeaderName::from_static("prefer");
+
+/// Uploads running at once per uploader, keyed like `upload_key`, so
+/// a single client can't hold every worker and the spool disk.
+///
+/// Keys are dropped once their last upload ends.
+pub struct UploadSlots {
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+/// A slot held for the length of an upload, released when dropped so
+/// failures, client disconnects and panics all give it back.
+pub struct UploadSlot {
+    key: String,
+    semaphore: Arc<Semaphore>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+/// An uploader with uploads running, as listed on the admin dashboard.
+#[derive(Serialize)]
+pub struct UploadsInFlight {
+    pub key: String,
+    pub uploads: u32,
+}
+
+impl UploadSlots {
+    pub fn get() -> &'static Self {
+        UPLOAD_SLOTS.get_or_init(|| UploadSlots { semaphores: Mutex::new(HashMap::new()) })
+    }
+
+    /// Takes a slot for `key`, waiting up to `wait` for one to free up,
+    /// `None` when `UPLOAD_CONCURRENCY` is `0`.
+    pub async fn acquire(&self, key: &str, wait: Duration) -> Result<Option<UploadSlot>, AppError> {
+        let limit = Config::get().upload_concurrency;
+
+        if limit == 0 {
+            return Ok(None);
+        }
+
+        let semaphore = self
+            .lock()
+            .entry(key.to_owned())
+            .or_insert_with(|| Arc::new(Semaphore::new(limit as usize)))
+            .clone();
+
+        let permit = match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) if wait.is_zero() => None,
+            Err(_) => {
+                timeout(wait, semaphore.clone().acquire_owned()).await.ok().and_then(Result::ok)
+            },
+        };
+
+        // Built before the check so a rejection still evicts an idle key.
+        let slot = UploadSlot { key: key.to_owned(), semaphore, permit };
+
+        if slot.permit.is_none() {
+            return Err(AppError::RateLimited(RateLimit {
+                limit,
+                remaining: 0,
+                reset: RETRY_AFTER,
+                retry_after: RETRY_AFTER,
+            }));
+        }
+
+        Ok(Some(slot))
+    }
+
+    /// Uploads `key` is running right now.
+    pub fn in_flight(&self, key: &str) -> u32 {
+        self.lock().get(key).map_or(0, |semaphore| self.used(semaphore))
+    }
+
+    /// Every uploader with uploads running, busiest first.
+    pub fn all_in_flight(&self) -> Vec<UploadsInFlight> {
+        let mut uploads = self
+            .lock()
+            .iter()
+            .map(|(key, semaphore)| UploadsInFlight {
+                key: key.clone(),
+                uploads: self.used(semaphore),
+            })
+            .filter(|entry| entry.uploads > 0)
+            .collect::<Vec<_>>();
+
+        uploads.sort_unstable_by(|a, b| b.uploads.cmp(&a.uploads).then_with(|| a.key.cmp(&b.key)));
+        uploads
+    }
+
+    fn used(&self, semaphore: &Semaphore) -> u32 {
+        Config::get().upload_concurrency.saturating_sub(semaphore.available_permits() as u32)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<Semaphore>>> {
+        self.semaphores.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl Drop for UploadSlot {
+    fn drop(&mut self) {
+        self.permit.take();
+
+        let slots = UploadSlots::get();
+        let mut semaphores = slots.lock();
+
+        // Clones are only taken under the lock, so with none but the table's
+        // and this one left nobody holds or awaits a slot of this key.
+        let idle = semaphores.get(&self.key).is_some_and(|semaphore| {
+            Arc::ptr_eq(semaphore, &self.semaphore) && Arc::strong_count(&self.semaphore) == 2
+        });
+
+        if idle {
+            semaphores.remove(&self.key);
+        }
+    }
+}
+
+/// How long the client is willing to wait for a slot, from
+/// `Prefer: wait=<seconds>`, zero when it didn't say.
+pub fn preferred_wait(req: &HttpRequest) -> Duration {
+    let Ok(Some(prefer)) = joined_header_values(req, PREFER) else {
+        return Duration::ZERO;
+    };
+
+    prefer
+        .split([',', ';'])
+        .filter_map(|preference| preference.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("wait"))
+        .and_then(|(_, seconds)| seconds.trim().trim_matches('"').parse::<u64>().ok())
+        .map_or(Duration::ZERO, |seconds| Duration::from_secs(seconds).min(MAX_WAIT))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn wait(prefer: &str) -> Duration {
+        preferred_wait(&TestRequest::default().insert_header((PREFER, prefer)).to_http_request())
+    }
+
+    #[test]
+    fn wait_is_read_among_preferences() {
+        assert_eq!(wait("respond-async, wait=5"), Duration::from_secs(5));
+        assert_eq!(wait("handling=lenient; WAIT = \"3\""), Duration::from_secs(3));
+        assert_eq!(wait("wait=86400"), MAX_WAIT);
+    }
+
+    #[test]
+    fn missing_or_invalid_wait_is_zero() {
+        assert_eq!(preferred_wait(&TestRequest::default().to_http_request()), Duration::ZERO);
+        assert_eq!(wait("wait=-1"), Duration::ZERO);
+        assert_eq!(wait("respond-async"), Duration::ZERO);
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use database::{FileModel, FileUsage};
+
+use crate::AppError;
+
+/// Usage is recomputed at most this often per user, uploads
+/// show up in it after a few seconds at worst.
+const CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// Cached usages kept before the cache is emptied.
+const MAX_ENTRIES: usize = 4096;
+
+static CACHE: OnceLock<Mutex<HashMap<i64, (Instant, FileUsage)>>> = OnceLock::new();
+
+/// The storage used by `user_id`, from the cache when it's recent enough.
+pub async fn usage(user_id: i64) -> Result<FileUsage, AppError> {
+    let cache = CACHE.get_or_init(Default::default);
+
+    let cached = cache
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .get(&user_id)
+        .filter(|(computed_at, _)| computed_at.elapsed() < CACHE_TTL)
+        .map(|(_, usage)| *usage);
+
+    if let Some(usage) = cached {
+        return Ok(usage);
+    }
+
+    let usage = FileModel::usage_for_user(user_id).await?;
+
+    let mut entries = cache.lock().unwrap_or_else(|error| error.into_inner());
+
+    if entries.len() >= MAX_ENTRIES {
+        entries.clear();
+    }
+
+    entries.insert(user_id, (Instant::now(), usage));
+
+    Ok(usage)
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::mem::take;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+
+use actix_web::rt::spawn;
+use chrono::{NaiveDate, TimeDelta, Utc};
+use database::FileViewStatsModel;
+use log::warn;
+use serde::Serialize;
+
+use crate::AppError;
+use crate::config::Config;
+
+static VIEW_STATS: OnceLock<ViewStats> = OnceLock::new();
+
+/// Bits of a viewer hash picking its register, sketches hold `2^PRECISION`.
+const PRECISION: u32 = 10;
+
+const REGISTERS: usize = 1 << PRECISION;
+
+/// Relative standard error of the unique viewer estimates, `1.04 / √m`
+/// for `m` registers: two thirds of them are within 3.25% of the truth
+/// and nearly all within three times that.
+pub const STANDARD_ERROR: f64 = 1.04 / 32.0;
+
+/// Days of a file buffered before they're all written at once, bounding
+/// the buffer to about a kilobyte of sketch each.
+const MAX_PENDING: usize = 1024;
+
+/// A HyperLogLog sketch, it estimates how many distinct hashes
+/// were inserted in a fixed kilobyte however many there were.
+#[derive(Clone)]
+pub struct Sketch {
+    registers: Vec<u8>,
+}
+
+impl Default for Sketch {
+    fn default() -> Self {
+        Sketch { registers: vec![0; REGISTERS] }
+    }
+}
+
+impl Sketch {
+    /// `None` for sketches of another size, or the empty ones
+    /// recorded while unique viewers weren't counted.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        (bytes.len() == REGISTERS).then(|| Sketch { registers: bytes.to_vec() })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// The first bits of `hash` pick a register, which keeps the longest
+    /// run of leading zeros seen in the others.
+    pub fn insert(&mut self, hash: u64) {
+        let register = (hash >> (64 - PRECISION)) as usize;
+        // The guard bit caps the run for hashes whose other bits are all zeros.
+        let rank = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    /// Makes this sketch the union of both, as if it had seen every hash.
+    pub fn merge(&mut self, other: &Sketch) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    /// The estimated number of distinct hashes inserted, counted through
+    /// the empty registers while few were since it's more accurate then.
+    pub fn estimate(&self) -> u64 {
+        let registers = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / registers);
+
+        let sum = self.registers.iter().map(|rank| 2f64.powi(-i32::from(*rank))).sum::<f64>();
+        let raw = alpha * registers * registers / sum;
+        let empty = self.registers.iter().filter(|rank| **rank == 0).count();
+
+        let estimate = if raw <= 2.5 * registers && empty > 0 {
+            registers * (registers / empty as f64).ln()
+        } else {
+            raw
+        };
+
+        estimate.round() as u64
+    }
+}
+
+/// The key viewers are hashed with, drawn at random for every day and only
+/// kept in memory so a day's hashes can't be linked to anyone afterwards.
+struct DailySalt {
+    day: NaiveDate,
+    key: [u8; 32],
+}
+
+#[derive(Default)]
+struct PendingViews {
+    views: i64,
+    /// `None` while unique viewers aren't counted.
+    viewers: Option<Sketch>,
+}
+
+/// Views buffered in memory per file and day, written
+/// every `STATS_FLUSH_INTERVAL` seconds.
+///
+/// Every instance salts on its own, so a viewer served by
+/// several of them on the same day is counted once by each.
+pub struct ViewStats {
+    pending: Mutex<HashMap<(i64, NaiveDate), PendingViews>>,
+    salt: Mutex<Option<DailySalt>>,
+}
+
+impl ViewStats {
+    pub fn get() -> &'static Self {
+        VIEW_STATS.get_or_init(ViewStats::new)
+    }
+
+    fn new() -> Self {
+        ViewStats {
+            pending: Mutex::new(HashMap::new()),
+            salt: Mutex::new(None),
+        }
+    }
+
+    /// Counts a view of `file_id` by the client at `ip` with `user_agent`, the
+    /// buffer is written in the background when it holds too many files.
+    pub fn record(&self, file_id: i64, ip: Option<IpAddr>, user_agent: &str) {
+        let day = Utc::now().date_naive();
+        let viewer = Config::get().unique_viewers.then(|| self.viewer_hash(day, ip, user_agent));
+
+        let mut pending = self.pending.lock().unwrap_or_else(|error| error.into_inner());
+
+        let full = (pending.len() >= MAX_PENDING && !pending.contains_key(&(file_id, day)))
+            .then(|| take(&mut *pending));
+
+        let views = pending.entry((file_id, day)).or_default();
+        views.views += 1;
+
+        if let Some(viewer) = viewer {
+            views.viewers.get_or_insert_with(Sketch::default).insert(viewer);
+        }
+
+        drop(pending);
+
+        if let Some(full) = full {
+            spawn(async move {
+                if let Err(error) = write(full).await {
+                    warn!("Couldn't write the buffered views: {error}");
+                }
+            });
+        }
+    }
+
+    /// Writes the views buffered so far, merging them into what's stored.
+    pub async fn flush(&self) -> Result<(), AppError> {
+        let pending = take(&mut *self.pending.lock().unwrap_or_else(|error| error.into_inner()));
+
+        write(pending).await
+    }
+
+    /// The hash of a viewer on `day`, keyed with the salt of that day,
+    /// the salt of the previous day is forgotten once it's drawn.
+    fn viewer_hash(&self, day: NaiveDate, ip: Option<IpAddr>, user_agent: &str) -> u64 {
+        let mut salt = self.salt.lock().unwrap_or_else(|error| error.into_inner());
+
+        let salt = match &mut *salt {
+            Some(salt) if salt.day == day => salt,
+            salt => salt.insert(DailySalt { day, key: rand::random() }),
+        };
+
+        let ip = ip.map_or_else(|| "-".to_owned(), |ip| ip.to_string());
+        let hash = blake3::keyed_hash(&salt.key, format!("{ip}\n{user_agent}").as_bytes());
+
+        u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("hashes are 32 bytes"))
+    }
+}
+
+async fn write(pending: HashMap<(i64, NaiveDate), PendingViews>) -> Result<(), AppError> {
+    for ((file_id, day), views) in pending {
+        let viewers = views.viewers.as_ref().map(Sketch::as_bytes).unwrap_or_default();
+
+        FileViewStatsModel::add(file_id, day, views.views, viewers).await?;
+    }
+
+    Ok(())
+}
+
+/// How often a file was viewed over a window of days, as answered by
+/// `GET /file/{id}/stats`.
+#[derive(Serialize)]
+pub struct ViewerStats {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub views: i64,
+    /// `None` when unique viewers aren't counted.
+    pub estimated_unique_viewers: Option<u64>,
+    /// The relative standard error of the estimate.
+    pub unique_viewers_error: Option<f64>,
+}
+
+/// The views of a file over the last `days` days, today included, its
+/// unique viewers estimated from the union of the sketches of those days.
+///
+/// Views still buffered aren't part of it yet.
+pub async fn viewer_stats(file_id: i64, days: i64) -> Result<ViewerStats, AppError> {
+    let to = Utc::now().date_naive();
+    let from = to - TimeDelta::days(days - 1);
+
+    let mut views = 0;
+    let mut viewers = None::<Sketch>;
+
+    for day in FileViewStatsModel::list_range(file_id, from, to).await? {
+        views += day.views;
+
+        if let Some(sketch) = Sketch::from_bytes(&day.viewers) {
+            viewers.get_or_insert_with(Sketch::default).merge(&sketch);
+        }
+    }
+
+    let counted = Config::get().unique_viewers;
+    let estimated_unique_viewers = counted.then(|| viewers.as_ref().map_or(0, Sketch::estimate));
+
+    Ok(ViewerStats {
+        from,
+        to,
+        views,
+        estimated_unique_viewers,
+        unique_viewers_error: counted.then_some(STANDARD_ERROR),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{create_bucket, create_file, create_user, run, unique_name};
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 5, day).unwrap()
+    }
+
+    fn viewer(number: u32) -> (Option<IpAddr>, String) {
+        (Some(IpAddr::from(number.to_be_bytes())), format!("agent/{}", number % 7))
+    }
+
+    /// A sketch of `count` distinct viewers starting at `first`, on `day`.
+    fn sketch_of(stats: &ViewStats, day: NaiveDate, first: u32, count: u32) -> Sketch {
+        let mut sketch = Sketch::default();
+
+        for number in first..first + count {
+            let (ip, user_agent) = viewer(number);
+            sketch.insert(stats.viewer_hash(day, ip, &user_agent));
+        }
+
+        sketch
+    }
+
+    #[test]
+    fn estimates_stay_within_the_error_bound() {
+        let stats = ViewStats::new();
+
+        for count in [10, 500, 5_000, 50_000] {
+            let sketch = sketch_of(&stats, day(1), 0, count);
+            let error = (sketch.estimate() as f64 - f64::from(count)).abs() / f64::from(count);
+
+            assert!(error < 3.0 * STANDARD_ERROR, "{} for {count}", sketch.estimate());
+        }
+
+        // Viewers coming back don't count twice.
+        let mut sketch = sketch_of(&stats, day(1), 0, 1_000);
+        sketch.merge(&sketch_of(&stats, day(1), 0, 1_000));
+        let once = sketch_of(&stats, day(1), 0, 1_000).estimate();
+        assert_eq!(sketch.estimate(), once);
+
+        assert_eq!(Sketch::default().estimate(), 0);
+        assert_eq!(Sketch::from_bytes(sketch.as_bytes()).unwrap().estimate(), once);
+        assert!(Sketch::from_bytes(&[]).is_none());
+    }
+
+    #[test]
+    fn unions_estimate_the_viewers_of_every_day() {
+        let stats = ViewStats::new();
+
+        // The second day has 2000 viewers, half of them new.
+        let mut union = sketch_of(&stats, day(1), 0, 2_000);
+        union.merge(&sketch_of(&stats, day(1), 1_000, 2_000));
+
+        let error = (union.estimate() as f64 - 3_000.0).abs() / 3_000.0;
+        assert!(error < 3.0 * STANDARD_ERROR, "{}", union.estimate());
+    }
+
+    #[test]
+    fn salts_rotate_with_the_day() {
+        let stats = ViewStats::new();
+        let (ip, user_agent) = viewer(42);
+
+        let first = stats.viewer_hash(day(1), ip, &user_agent);
+        assert_eq!(stats.viewer_hash(day(1), ip, &user_agent), first);
+        assert_ne!(stats.viewer_hash(day(1), ip, "another agent"), first);
+
+        let second = stats.viewer_hash(day(2), ip, &user_agent);
+        assert_ne!(second, first);
+        assert_eq!(stats.viewer_hash(day(2), ip, &user_agent), second);
+
+        // The salt of a past day is gone for good.
+        assert_ne!(stats.viewer_hash(day(1), ip, &user_agent), first);
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn buffered_views_are_merged_into_the_stored_days() {
+        run(async {
+            let owner = create_user(&unique_name("views")).await;
+            let file = create_file(&create_bucket(&owner).await, "clip.mp4").await;
+            let stats = ViewStats::new();
+
+            for number in 0..300 {
+                let (ip, user_agent) = viewer(number % 100);
+                stats.record(file.id(), ip, &user_agent);
+            }
+            stats.flush().await.unwrap();
+
+            // A second flush merges into the stored day rather than replacing it.
+            for number in 50..150 {
+                let (ip, user_agent) = viewer(number);
+                stats.record(file.id(), ip, &user_agent);
+            }
+            stats.flush().await.unwrap();
+
+            // An older day within the window, with viewers of its own.
+            let yesterday = Utc::now().date_naive() - TimeDelta::days(1);
+            let older = sketch_of(&stats, yesterday, 1_000, 50);
+            FileViewStatsModel::add(file.id(), yesterday, 50, older.as_bytes()).await.unwrap();
+
+            let window = viewer_stats(file.id(), 7).await.unwrap();
+            assert_eq!(window.views, 450);
+            assert_eq!(window.unique_viewers_error, Some(STANDARD_ERROR));
+
+            let estimate = window.estimated_unique_viewers.unwrap() as f64;
+            assert!((estimate - 200.0).abs() / 200.0 < 3.0 * STANDARD_ERROR, "{estimate}");
+
+            let today = viewer_stats(file.id(), 1).await.unwrap();
+            assert_eq!(today.views, 400);
+            assert_eq!(today.from, today.to);
+        })
+    }
+}
@@ -0,0 +1,595 @@
+use std::io::Error as IoError;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use actix_web::rt::spawn;
+use actix_web::rt::time::sleep;
+use chrono::Utc;
+use database::{FileModel, FileResult, FileTagModel, UserWebhookModel, WebhookFormat};
+use hickory_resolver::TokioAsyncResolver;
+use hmac::{Hmac, Mac};
+use log::warn;
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use serde_json::{Value, json};
+use sha2::Sha256;
+
+use crate::AppError;
+use crate::config::Config;
+use crate::tasks::jobs::spawn_tracked;
+use crate::utils::hashing::hex;
+use crate::utils::upload_limit::UploadLimiter;
+
+/// Webhooks each user may have.
+pub const MAX_WEBHOOKS: i64 = 5;
+
+/// Attempts at delivering an event before it counts as failed.
+const MAX_ATTEMPTS: i32 = 3;
+
+/// Waited before the second attempt, doubled for every one after.
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+/// How long a receiver has to answer an attempt.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookEvent {
+    FileUploaded,
+    FileTagged,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 2] = [WebhookEvent::FileUploaded, WebhookEvent::FileTagged];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::FileUploaded => "file.uploaded",
+            WebhookEvent::FileTagged => "file.tagged",
+        }
+    }
+
+    pub fn parse(event: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|known| known.as_str() == event)
+    }
+}
+
+/// Whether an event on a file of `bucket_id` carrying `tags`
+/// falls within the bucket and tag the webhook is limited to.
+pub fn in_scope(webhook: &UserWebhookModel, bucket_id: i64, tags: &[String]) -> bool {
+    webhook.bucket_id().is_none_or(|scope| scope == bucket_id)
+        && webhook.tag().is_none_or(|scope| tags.iter().any(|tag| tag == scope))
+}
+
+/// The body delivered for `event`, Discord only takes a message
+/// so the file is summed up in one line linking to it.
+pub fn payload(
+    format: WebhookFormat,
+    event: WebhookEvent,
+    file: &FileResult,
+    tags: &[String],
+) -> Value {
+    match format {
+        WebhookFormat::Json => json!({
+            "event": event.as_str(),
+            "sent_at": Utc::now(),
+            "file": file,
+            "tags": tags,
+        }),
+        WebhookFormat::Discord => {
+            let name = file.path().rsplit('/').next().unwrap_or_default();
+            let content = match event {
+                WebhookEvent::FileUploaded => format!("Uploaded **{name}**\n{}", file.url()),
+                WebhookEvent::FileTagged => {
+                    format!("Tagged **{name}** with {}\n{}", tags.join(", "), file.url())
+                },
+            };
+
+            // Names are the uploader's, they must not ping anyone.
+            json!({ "content": content, "allowed_mentions": { "parse": [] } })
+        },
+    }
+}
+
+/// `sha256=` and the hex HMAC of `<timestamp>.<body>`, the timestamp
+/// is signed along so a captured delivery can't be replayed later.
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+
+    format!("sha256={}", hex(&mac.finalize().into_bytes()))
+}
+
+/// Whether webhooks may reach `ip`, private networks, loopback, link local
+/// ranges such as the cloud metadata endpoints and reserved ones can't be.
+fn is_public(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            let shared = first == 100 && second & 0xc0 == 64;
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || shared
+                || first == 0
+                || first >= 240)
+        },
+        IpAddr::V6(ip) => {
+            let documentation = ip.segments()[..2] == [0x2001, 0xdb8];
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()
+                || documentation)
+        },
+    }
+}
+
+/// Resolves the target of a webhook, refusing it unless it's an http or
+/// https URL whose every address is public. Deliveries are sent to the
+/// addresses checked here, so the name can't be rebound in between.
+pub async fn resolve_target(url: &str) -> Result<Vec<SocketAddr>, AppError> {
+    let url = Url::parse(url).map_err(|_| AppError::InvalidWebhookUrl)?;
+    let host = url.host_str().ok_or(AppError::InvalidWebhookUrl)?;
+    let port = url.port_or_known_default().ok_or(AppError::InvalidWebhookUrl)?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::InvalidWebhookUrl);
+    }
+
+    let ips = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => vec![ip],
+        Err(_) => {
+            let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(IoError::other)?;
+
+            resolver
+                .lookup_ip(host)
+                .await
+                .map_err(|_| AppError::InvalidWebhookUrl)?
+                .iter()
+                .collect()
+        },
+    };
+
+    if let Some(ip) = ips.iter().find(|ip| !is_public(**ip)) {
+        return Err(AppError::WebhookTargetForbidden(ip.to_string()));
+    }
+
+    if ips.is_empty() {
+        return Err(AppError::InvalidWebhookUrl);
+    }
+
+    Ok(ips.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+}
+
+/// Delivers `event` to the webhooks of the owner of `file` it's in the
+/// scope of, in the background so the request isn't held up by them.
+pub fn dispatch(event: WebhookEvent, file: &FileModel, result: FileResult) {
+    let (file_id, bucket_id) = (file.id(), file.bucket_id());
+
+    spawn(async move {
+        if let Err(error) = enqueue(event, file_id, bucket_id, result).await {
+            warn!("Couldn't dispatch {} of file {file_id} to webhooks: {error}", event.as_str());
+        }
+    });
+}
+
+async fn enqueue(
+    event: WebhookEvent,
+    file_id: i64,
+    bucket_id: i64,
+    file: FileResult,
+) -> Result<(), AppError> {
+    let webhooks = UserWebhookModel::list_subscribed(bucket_id, event.as_str()).await?;
+
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+
+    let tags = FileTagModel::list_for_file(file_id).await?;
+    let rate_limit = Config::get().webhook_rate_limit;
+
+    for webhook in webhooks.iter().filter(|webhook| in_scope(webhook, bucket_id, &tags)) {
+        let limit_key = format!("webhook:{}", webhook.owner_id());
+
+        if UploadLimiter::get().acquire_at(&limit_key, rate_limit, rate_limit).is_err() {
+            let error = Some("Dropped, too many deliveries a minute");
+            UserWebhookModel::record_delivery(webhook.id(), event.as_str(), 0, None, error).await?;
+            continue;
+        }
+
+        let delivery = Delivery {
+            webhook_id: webhook.id(),
+            url: webhook.url().to_owned(),
+            secret: webhook.secret().map(str::to_owned),
+            event,
+            body: payload(webhook.format(), event, &file, &tags).to_string(),
+        };
+        let parameters = json!({ "webhook_id": webhook.id(), "event": event.as_str() });
+
+        spawn_tracked("webhook-delivery", parameters, move |_| delivery.run()).await?;
+    }
+
+    Ok(())
+}
+
+/// An event on its way to a webhook.
+struct Delivery {
+    webhook_id: i64,
+    url: String,
+    secret: Option<String>,
+    event: WebhookEvent,
+    body: String,
+}
+
+/// How an attempt went, the status is kept for failures too.
+struct Attempt {
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+impl Delivery {
+    /// Attempts the delivery up to `MAX_ATTEMPTS` times, the target is
+    /// checked again every time since its addresses may have changed.
+    async fn run(self) -> Result<Value, AppError> {
+        let mut number = 1;
+
+        loop {
+            let attempt = match resolve_target(&self.url).await {
+                Ok(addresses) => self.post(&addresses).await,
+                Err(error) => Attempt {
+                    status: None,
+                    error: Some(error.to_string()),
+                },
+            };
+
+            self.record(number, &attempt).await?;
+
+            match attempt.error {
+                None => {
+                    UserWebhookModel::delivered(self.webhook_id).await?;
+
+                    return Ok(json!({ "status": attempt.status, "attempts": number }));
+                },
+                Some(error) if number == MAX_ATTEMPTS => {
+                    self.fail(&error).await?;
+
+                    return Err(AppError::WebhookDeliveryFailed(error));
+                },
+                Some(_) => {
+                    sleep(RETRY_BACKOFF * 2u32.pow(number as u32 - 1)).await;
+                    number += 1;
+                },
+            }
+        }
+    }
+
+    /// Posts the event to `addresses`, whatever the name of the URL resolves to
+    /// by then. Redirects aren't followed, they could lead anywhere.
+    async fn post(&self, addresses: &[SocketAddr]) -> Attempt {
+        let failed = |error: String| Attempt { status: None, error: Some(error) };
+
+        let Some(host) =
+            Url::parse(&self.url).ok().and_then(|url| url.host_str().map(str::to_owned))
+        else {
+            return failed("The URL is malformed".into());
+        };
+
+        let client = Client::builder()
+            .redirect(Policy::none())
+            .timeout(DELIVERY_TIMEOUT)
+            .resolve_to_addrs(&host, addresses)
+            .build();
+
+        let client = match client {
+            Ok(client) => client,
+            Err(error) => return failed(error.to_string()),
+        };
+
+        let timestamp = Utc::now().timestamp();
+        let mut request = client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Event", self.event.as_str())
+            .header("X-Webhook-Timestamp", timestamp);
+
+        if let Some(secret) = &self.secret {
+            request =
+                request.header("X-Webhook-Signature", signature(secret, timestamp, &self.body));
+        }
+
+        match request.body(self.body.clone()).send().await {
+            Ok(response) if response.status().is_success() => Attempt {
+                status: Some(response.status().as_u16()),
+                error: None,
+            },
+            Ok(response) => Attempt {
+                status: Some(response.status().as_u16()),
+                error: Some(format!("The receiver answered {}", response.status())),
+            },
+            Err(error) => failed(error.without_url().to_string()),
+        }
+    }
+
+    async fn record(&self, number: i32, attempt: &Attempt) -> Result<(), AppError> {
+        UserWebhookModel::record_delivery(
+            self.webhook_id,
+            self.event.as_str(),
+            number,
+            attempt.status.map(i32::from),
+            attempt.error.as_deref(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Counts a delivery that failed every attempt, the webhook is
+    /// disabled after `WEBHOOK_DISABLE_AFTER` of them in a row and the
+    /// reason shown to its owner along with it.
+    async fn fail(&self, error: &str) -> Result<(), AppError> {
+        let disable_after = Config::get().webhook_disable_after;
+        let reason = format!("Disabled after {disable_after} failed deliveries, the last: {error}");
+        let webhook = UserWebhookModel::failed(self.webhook_id, disable_after, &reason).await?;
+
+        if !webhook.enabled() && webhook.disabled_reason() == Some(reason.as_str()) {
+            warn!("Disabled webhook {} of user {}: {error}", webhook.id(), webhook.owner_id());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use database::{UserWebhookCreation, UserWebhookUpdate};
+
+    use super::*;
+    use crate::testing::{create_bucket, create_file, create_user, receiver, run, unique_name};
+
+    /// A webhook of `owner_id` on uploads signed with `hunter2`,
+    /// `None` when they already have as many as allowed.
+    async fn create_webhook(
+        owner_id: i64,
+        url: &str,
+        bucket_id: Option<i64>,
+        tag: Option<&str>,
+    ) -> Option<UserWebhookModel> {
+        let creation = UserWebhookCreation {
+            url: url.to_owned(),
+            secret: Some("hunter2".into()),
+            events: vec!["file.uploaded".into()],
+            bucket_id,
+            tag: tag.map(str::to_owned),
+            format: WebhookFormat::Json,
+        };
+
+        UserWebhookModel::create(owner_id, &creation, MAX_WEBHOOKS).await.unwrap()
+    }
+
+    #[test]
+    fn events_are_named_like_they_are_subscribed_to() {
+        for event in WebhookEvent::ALL {
+            assert_eq!(WebhookEvent::parse(event.as_str()), Some(event));
+        }
+
+        assert_eq!(WebhookEvent::parse("file.deleted"), None);
+    }
+
+    #[test]
+    fn signatures_cover_the_timestamp_and_the_body() {
+        let signed = signature("hunter2", 1_700_000_000, "{}");
+
+        assert!(signed.starts_with("sha256="));
+        assert_eq!(signed.len(), "sha256=".len() + 64);
+        assert_ne!(signed, signature("hunter2", 1_700_000_001, "{}"));
+        assert_ne!(signed, signature("hunter3", 1_700_000_000, "{}"));
+    }
+
+    #[test]
+    fn only_public_addresses_are_reachable() {
+        for ip in [
+            "169.254.169.254",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+
+        for ip in ["93.184.215.14", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn link_local_targets_are_refused() {
+        let error = resolve_target("http://169.254.169.254/latest/meta-data/").await.unwrap_err();
+        assert!(matches!(error, AppError::WebhookTargetForbidden(ip) if ip == "169.254.169.254"));
+
+        let error = resolve_target("http://[fe80::1]:8080/hook").await.unwrap_err();
+        assert!(matches!(error, AppError::WebhookTargetForbidden(_)));
+
+        for url in ["ftp://example.com/hook", "not a url", "file:///etc/passwd"] {
+            assert!(matches!(resolve_target(url).await, Err(AppError::InvalidWebhookUrl)), "{url}");
+        }
+
+        let addresses = resolve_target("https://93.184.215.14/hook").await.unwrap();
+        assert_eq!(addresses, ["93.184.215.14:443".parse().unwrap()]);
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn discord_payloads_are_a_single_message_linking_the_file() {
+        run(async {
+            let owner = create_user(&unique_name("hook")).await;
+            let bucket = create_bucket(&owner).await;
+            let file = create_file(&bucket, "clips/@everyone.mp4").await;
+            let result = file.into_result().with_base_url("https://cdn.example.com");
+
+            let discord = payload(WebhookFormat::Discord, WebhookEvent::FileUploaded, &result, &[]);
+            assert_eq!(discord.as_object().unwrap().len(), 2);
+            assert_eq!(discord["content"], format!("Uploaded **@everyone.mp4**\n{}", result.url()));
+            assert!(result.url().starts_with("https://cdn.example.com/"));
+            assert_eq!(discord["allowed_mentions"], json!({ "parse": [] }));
+
+            let tags = ["gaming".to_owned(), "funny".to_owned()];
+            let tagged = payload(WebhookFormat::Discord, WebhookEvent::FileTagged, &result, &tags);
+            assert!(
+                tagged["content"]
+                    .as_str()
+                    .unwrap()
+                    .starts_with("Tagged **@everyone.mp4** with gaming, funny\n")
+            );
+
+            let full = payload(WebhookFormat::Json, WebhookEvent::FileTagged, &result, &tags);
+            assert_eq!(full["event"], "file.tagged");
+            assert_eq!(full["file"]["path"], "clips/@everyone.mp4");
+            assert_eq!(full["tags"], json!(tags));
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn events_outside_the_scope_of_a_webhook_dont_fire() {
+        run(async {
+            let owner = create_user(&unique_name("hook")).await;
+            let clips = create_bucket(&owner).await;
+            let other = create_bucket(&owner).await;
+            let url = "https://93.184.215.14/hook";
+
+            let anywhere = create_webhook(owner.id(), url, None, None).await.unwrap();
+            let in_clips = create_webhook(owner.id(), url, Some(clips.id()), None).await.unwrap();
+            let tagged =
+                create_webhook(owner.id(), url, Some(clips.id()), Some("gaming")).await.unwrap();
+
+            let fired = |bucket_id: i64, tags: &[&str]| {
+                let tags = tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+                [&anywhere, &in_clips, &tagged]
+                    .into_iter()
+                    .map(|webhook| in_scope(webhook, bucket_id, &tags))
+                    .collect::<Vec<_>>()
+            };
+
+            assert_eq!(fired(clips.id(), &[]), [true, true, false]);
+            assert_eq!(fired(clips.id(), &["gaming"]), [true, true, true]);
+            assert_eq!(fired(other.id(), &["gaming"]), [true, false, false]);
+
+            // Only the owner's webhooks subscribed to the event are candidates.
+            let subscribed = UserWebhookModel::list_subscribed(other.id(), "file.uploaded").await;
+            assert_eq!(subscribed.unwrap().len(), 3);
+            let subscribed = UserWebhookModel::list_subscribed(other.id(), "file.tagged").await;
+            assert!(subscribed.unwrap().is_empty());
+
+            let stranger = create_bucket(&create_user(&unique_name("hook")).await).await;
+            let subscribed =
+                UserWebhookModel::list_subscribed(stranger.id(), "file.uploaded").await;
+            assert!(subscribed.unwrap().is_empty());
+
+            // The sixth webhook is refused.
+            for _ in 0..2 {
+                assert!(create_webhook(owner.id(), url, None, None).await.is_some());
+            }
+            assert!(create_webhook(owner.id(), url, None, None).await.is_none());
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn sustained_failures_disable_the_webhook() {
+        run(async {
+            let (address, requests) = receiver(500);
+            let owner = create_user(&unique_name("hook")).await;
+            let url = format!("http://{address}/hook");
+            let webhook = create_webhook(owner.id(), &url, None, None).await.unwrap();
+
+            let delivery = Delivery {
+                webhook_id: webhook.id(),
+                url,
+                secret: webhook.secret().map(str::to_owned),
+                event: WebhookEvent::FileUploaded,
+                body: "{\"event\":\"file.uploaded\"}".into(),
+            };
+
+            let disable_after = Config::get().webhook_disable_after;
+
+            for number in 1..=disable_after {
+                let attempt = delivery.post(&[address]).await;
+                assert_eq!(attempt.status, Some(500));
+                delivery.record(1, &attempt).await.unwrap();
+                delivery.fail(attempt.error.as_deref().unwrap()).await.unwrap();
+
+                let webhook = UserWebhookModel::get(webhook.id()).await.unwrap();
+                assert_eq!(webhook.enabled(), number < disable_after, "after {number} failures");
+            }
+
+            let webhook = UserWebhookModel::get(webhook.id()).await.unwrap();
+            assert!(webhook.disabled_reason().unwrap().contains("500"));
+
+            let request = requests.recv().unwrap();
+            let timestamp = request
+                .lines()
+                .find_map(|line| line.strip_prefix("x-webhook-timestamp: "))
+                .unwrap();
+            let expected = signature("hunter2", timestamp.parse().unwrap(), &delivery.body);
+            assert!(request.contains(&format!("x-webhook-signature: {expected}")));
+            assert!(request.ends_with(&delivery.body));
+
+            let deliveries = UserWebhookModel::list_deliveries(webhook.id()).await.unwrap();
+            assert_eq!(deliveries.len(), disable_after as usize);
+            assert!(deliveries.iter().all(|delivery| delivery.status_code == Some(500)));
+
+            // Enabling it again forgets the failures.
+            let update = UserWebhookUpdate {
+                url: None,
+                secret: None,
+                events: None,
+                format: None,
+                enabled: Some(true),
+            };
+            let webhook = webhook.edit(&update).await.unwrap();
+            assert!(webhook.enabled() && webhook.disabled_reason().is_none());
+        })
+    }
+
+    #[test]
+    #[ignore = "needs DATABASE_URL and SIGNING_SECRET"]
+    fn only_the_latest_deliveries_are_kept() {
+        run(async {
+            let owner = create_user(&unique_name("hook")).await;
+            let url = "https://93.184.215.14/hook";
+            let webhook = create_webhook(owner.id(), url, None, None).await.unwrap();
+
+            for attempt in 1..=25 {
+                UserWebhookModel::record_delivery(
+                    webhook.id(),
+                    "file.uploaded",
+                    attempt,
+                    Some(204),
+                    None,
+                )
+                .await
+                .unwrap();
+            }
+
+            let deliveries = UserWebhookModel::list_deliveries(webhook.id()).await.unwrap();
+            let attempts = deliveries.iter().map(|delivery| delivery.attempt).collect::<Vec<_>>();
+            assert_eq!(attempts, (6..=25).rev().collect::<Vec<_>>());
+        })
+    }
+}